@@ -0,0 +1,93 @@
+// Acknowledgements (--ack-interval): every so often the client tells the
+// server how far it has got with each file, so a server that understands can
+// stop resending what's already here and see where the holes are. Where a
+// NACK lists what's missing once the link goes quiet, an ACK goes out on a
+// timer whatever's happening. The course server takes any datagram as a
+// request to start over, so --ack-interval is off unless asked for.
+//
+// Layout, big-endian, not padded:
+//
+//   "SFSA"  version  session ID (8)  file count (2)
+//   per file: file ID (1), packets in a row from packet 0 (4), bitmap (4)
+//
+// Bit i of the bitmap, lowest first, is set if packet `in_a_row + 1 + i` is
+// here: packet `in_a_row` is the first one missing, and the bitmap says what
+// has come past it. A long list is split over several datagrams, each a
+// complete ACK.
+
+use crate::{file_manager::FileManager, wire};
+
+pub const MAGIC: &[u8; 4] = b"SFSA";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 4 + 1 + 8 + 2;
+pub const ENTRY_LEN: usize = 9;
+// As many files as fit in the largest packet
+pub const MAX_ENTRIES: usize = (wire::MAX_PACKET - HEADER_LEN) / ENTRY_LEN;
+pub const BITMAP_LEN: u32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub file_id: u8,
+    pub in_a_row: u32,
+    pub bitmap: u32,
+}
+
+// Where every file we've heard of has got to
+pub fn summarize(files: &FileManager) -> Vec<Entry> {
+    (files.file_ids().into_iter())
+        .map(|file_id| {
+            let has = |n: u32| files.has_packet(file_id, n);
+            let in_a_row = (0..u32::MAX).find(|&n| !has(n)).unwrap_or(u32::MAX);
+            let bitmap = (0..BITMAP_LEN)
+                .filter(|&i| in_a_row.checked_add(1 + i).is_some_and(has))
+                .fold(0, |bitmap, i| bitmap | 1 << i);
+            Entry {
+                file_id,
+                in_a_row,
+                bitmap,
+            }
+        })
+        .collect()
+}
+
+// The ACKs for `entries`, in the order given, at most MAX_ENTRIES apiece
+pub fn encode(session_id: u64, entries: &[Entry]) -> Vec<Vec<u8>> {
+    entries
+        .chunks(MAX_ENTRIES)
+        .map(|entries| {
+            let mut out = Vec::with_capacity(HEADER_LEN + entries.len() * ENTRY_LEN);
+            out.extend_from_slice(MAGIC);
+            out.push(VERSION);
+            out.extend_from_slice(&session_id.to_be_bytes());
+            out.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+            for entry in entries {
+                out.push(entry.file_id);
+                out.extend_from_slice(&entry.in_a_row.to_be_bytes());
+                out.extend_from_slice(&entry.bitmap.to_be_bytes());
+            }
+            out
+        })
+        .collect()
+}
+
+// The session ID and entries in an ACK, for servers and tests
+pub fn decode(bytes: &[u8]) -> Option<(u64, Vec<Entry>)> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+        return None;
+    }
+    let session_id = u64::from_be_bytes(bytes[5..13].try_into().ok()?);
+    let count = u16::from_be_bytes([bytes[13], bytes[14]]) as usize;
+    let body = &bytes[HEADER_LEN..];
+    if body.len() != count * ENTRY_LEN {
+        return None;
+    }
+    let entries = body
+        .chunks(ENTRY_LEN)
+        .map(|entry| Entry {
+            file_id: entry[0],
+            in_a_row: u32::from_be_bytes(entry[1..5].try_into().unwrap()),
+            bitmap: u32::from_be_bytes(entry[5..9].try_into().unwrap()),
+        })
+        .collect();
+    Some((session_id, entries))
+}
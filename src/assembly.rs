@@ -0,0 +1,254 @@
+// Where a file's payloads are kept until it's written (--assembly-backend).
+// Which is quickest depends on the workload, so `bench assembly` times each
+// against the same packets:
+//
+//   hashmap  a map from packet number to payload; nothing is reserved for
+//            packets that haven't arrived, so it suits sparse or tiny files
+//   vec      a slot per packet number up to the highest seen, so lookups and
+//            the final in-order walk are plain indexing
+//   mmap     one anonymous mapping with a 1 KiB slot per packet number, so
+//            full payloads are copied in with no allocation of their own
+//   spill    payloads go to files under --spill-dir (see spill.rs); what's
+//            kept in memory is a map of empty payloads
+//
+// A payload too long for a 1 KiB slot moves an mmap file over to a map, and
+// a packet numbered past what a 16-bit number holds (wide packets, see
+// wire.rs) moves a vec or mmap file over, so one wild packet number can't
+// have either reserve gigabytes.
+
+use crate::{packet::Payload, wire};
+use clap::ValueEnum;
+use memmap2::MmapMut;
+use std::{collections::HashMap, io};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    Hashmap,
+    Vec,
+    Mmap,
+    Spill,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Hashmap => "hashmap",
+            Backend::Vec => "vec",
+            Backend::Mmap => "mmap",
+            Backend::Spill => "spill",
+        }
+    }
+}
+
+const SLOT: usize = wire::MAX_PAYLOAD;
+
+// Slots an mmap store starts with; it doubles from there
+const MIN_SLOTS: usize = 64;
+
+// Packet numbers past these go in a map
+const MAX_SLOTS: u32 = 1 << 16;
+
+// A slot with nothing in it yet
+const EMPTY: u16 = u16::MAX;
+
+pub struct Slots {
+    map: MmapMut,
+    // Payload length per slot, or EMPTY
+    lens: Vec<u16>,
+    count: usize,
+}
+
+impl Slots {
+    fn new() -> io::Result<Self> {
+        Ok(Slots {
+            map: MmapMut::map_anon(MIN_SLOTS * SLOT)?,
+            lens: Vec::new(),
+            count: 0,
+        })
+    }
+
+    fn get(&self, packet_number: u32) -> Option<&[u8]> {
+        let len = *self.lens.get(packet_number as usize)?;
+        let offset = packet_number as usize * SLOT;
+        (len != EMPTY).then(|| &self.map[offset..offset + len as usize])
+    }
+
+    // Returns false if the packet was already here
+    fn insert(&mut self, packet_number: u32, data: &[u8]) -> io::Result<bool> {
+        let index = packet_number as usize;
+        if self.lens.len() <= index {
+            self.lens.resize(index + 1, EMPTY);
+        }
+        let slots = self.map.len() / SLOT;
+        if index >= slots {
+            let mut grown = MmapMut::map_anon((index + 1).next_power_of_two() * SLOT)?;
+            grown[..self.map.len()].copy_from_slice(&self.map);
+            self.map = grown;
+        }
+        let earlier = self.lens[index] != EMPTY;
+        self.map[index * SLOT..index * SLOT + data.len()].copy_from_slice(data);
+        self.lens[index] = data.len() as u16;
+        if !earlier {
+            self.count += 1;
+        }
+        Ok(!earlier)
+    }
+
+    fn numbers(&self) -> impl Iterator<Item = u32> + '_ {
+        self.lens
+            .iter()
+            .enumerate()
+            .filter(|(_, &len)| len != EMPTY)
+            .map(|(n, _)| n as u32)
+    }
+}
+
+// One file's received payloads, by packet number
+pub enum Packets {
+    Map(HashMap<u32, Payload>),
+    Vec {
+        slots: Vec<Option<Payload>>,
+        count: usize,
+    },
+    Mmap(Slots),
+}
+
+impl Packets {
+    pub fn new(backend: Backend) -> Self {
+        match backend {
+            Backend::Vec => Packets::Vec {
+                slots: Vec::new(),
+                count: 0,
+            },
+            // An mmap store is only made for its first packet, so it can
+            // fall back to a map if the mapping fails
+            Backend::Hashmap | Backend::Mmap | Backend::Spill => Packets::Map(HashMap::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Packets::Map(map) => map.len(),
+            Packets::Vec { count, .. } => *count,
+            Packets::Mmap(slots) => slots.count,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, packet_number: u32) -> Option<&[u8]> {
+        match self {
+            Packets::Map(map) => map.get(&packet_number).map(|data| &data[..]),
+            Packets::Vec { slots, .. } => {
+                slots.get(packet_number as usize).and_then(Option::as_deref)
+            }
+            Packets::Mmap(slots) => slots.get(packet_number),
+        }
+    }
+
+    // Room for `count` packets up front, when a header says how big the
+    // file is
+    pub fn reserve(&mut self, count: usize) {
+        match self {
+            Packets::Map(map) => map.reserve(count),
+            Packets::Vec { slots, .. } => slots.reserve(count.saturating_sub(slots.len())),
+            Packets::Mmap(_) => {}
+        }
+    }
+
+    pub fn contains(&self, packet_number: u32) -> bool {
+        self.get(packet_number).is_some()
+    }
+
+    // Returns false if the packet was already here (the new payload replaces
+    // it, as it always has)
+    pub fn insert(&mut self, backend: Backend, packet_number: u32, data: Payload) -> bool {
+        if backend == Backend::Mmap && data.len() <= SLOT {
+            if let Packets::Map(map) = self {
+                if map.is_empty() {
+                    if let Ok(slots) = Slots::new() {
+                        *self = Packets::Mmap(slots);
+                    }
+                }
+            }
+        }
+        match self {
+            Packets::Map(map) => map.insert(packet_number, data).is_none(),
+            _ if packet_number >= MAX_SLOTS => {
+                self.make_map().insert(packet_number, data).is_none()
+            }
+            Packets::Vec { slots, count } => {
+                let index = packet_number as usize;
+                if slots.len() <= index {
+                    slots.resize(index + 1, None);
+                }
+                let earlier = slots[index].replace(data).is_some();
+                if !earlier {
+                    *count += 1;
+                }
+                !earlier
+            }
+            Packets::Mmap(slots) if data.len() <= SLOT => {
+                match slots.insert(packet_number, &data) {
+                    Ok(new) => new,
+                    Err(_) => self.make_map().insert(packet_number, data).is_none(),
+                }
+            }
+            Packets::Mmap(_) => self.make_map().insert(packet_number, data).is_none(),
+        }
+    }
+
+    // Move everything into a map, for payloads the slots can't hold
+    fn make_map(&mut self) -> &mut HashMap<u32, Payload> {
+        if !matches!(self, Packets::Map(_)) {
+            let map = self
+                .numbers()
+                .map(|n| (n, Payload::from_slice(self.get(n).unwrap_or_default())))
+                .collect();
+            *self = Packets::Map(map);
+        }
+        match self {
+            Packets::Map(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn extend(&mut self, backend: Backend, packets: Vec<(u32, Payload)>) {
+        for (packet_number, data) in packets {
+            self.insert(backend, packet_number, data);
+        }
+    }
+
+    // Stored packet numbers, in no particular order
+    pub fn numbers(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+        match self {
+            Packets::Map(map) => Box::new(map.keys().copied()),
+            Packets::Vec { slots, .. } => Box::new(
+                slots
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, data)| data.is_some())
+                    .map(|(n, _)| n as u32),
+            ),
+            Packets::Mmap(slots) => Box::new(slots.numbers()),
+        }
+    }
+
+    // Stored packet numbers, lowest first
+    pub fn sorted(&self) -> Vec<u32> {
+        let mut numbers: Vec<u32> = self.numbers().collect();
+        if let Packets::Map(_) = self {
+            numbers.sort_unstable();
+        }
+        numbers
+    }
+
+    pub fn highest(&self) -> Option<u32> {
+        match self {
+            Packets::Map(map) => map.keys().max().copied(),
+            _ => self.numbers().last(),
+        }
+    }
+}
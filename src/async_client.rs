@@ -0,0 +1,136 @@
+// --async, with the `tokio` feature: every --server at once, each over a
+// socket of its own. A task per session moves datagrams between its socket
+// and the session, which runs as it always does (timers, NACKs and all) on
+// tokio's blocking pool over a `MemoryTransport`, so reading the socket never
+// waits on parsing, storing, or writing files, and one slow server holds up
+// nobody else. With several servers each one's files go in a directory of
+// their own under --output-dir, named the way `grade` names them.
+
+use crate::{
+    cli::Cli,
+    client,
+    error::ClientError,
+    events::{Progress, Terminal},
+    fsio,
+    i18n::tr,
+    log::log,
+    manifest::Manifest,
+    session,
+    transport::{MemoryServer, MemoryTransport, SystemClock},
+};
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tokio::{net::UdpSocket, runtime, task};
+
+// Reports and --expect results come out one session at a time
+type Finishing = Arc<Mutex<()>>;
+
+pub fn run(cli: &Cli, manifest: Option<Manifest>) -> Result<(), ClientError> {
+    let runtime = runtime::Builder::new_current_thread().enable_io().build()?;
+    let manifest = Arc::new(manifest);
+    let finishing = Finishing::default();
+    let several = cli.server.len() > 1;
+    runtime.block_on(async {
+        let sessions: Vec<_> = (cli.server.iter())
+            .map(|server| {
+                let cli = client::for_server(cli, server, several);
+                tokio::spawn(transfer(cli, manifest.clone(), finishing.clone()))
+            })
+            .collect();
+
+        let mut first_error = None;
+        for (server, session) in cli.server.iter().zip(sessions) {
+            let result = session.await.map_err(io::Error::other)?;
+            if let Err(e) = result {
+                if several {
+                    log!(
+                        Warn,
+                        "{}",
+                        tr!("server-session-failed", server = server, error = e)
+                    );
+                }
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    })
+}
+
+// One server's session, start to finish
+async fn transfer(
+    cli: Cli,
+    manifest: Arc<Option<Manifest>>,
+    finishing: Finishing,
+) -> Result<(), ClientError> {
+    let deadline = cli.deadline.map(|timeout| Instant::now() + timeout);
+    fsio::create_dir_all(cli.output_dir())?;
+    let sock = client::bind(cli.bind, client::ip_version(&cli, [&*cli.server[0]]))?;
+    client::set_recv_buffer(&sock, &cli);
+    client::connect(&sock, &cli.server[0])?;
+    sock.set_nonblocking(true)?;
+    let sock = Arc::new(UdpSocket::from_std(sock)?);
+    sock.send(&session::hello(&cli)).await?;
+
+    let (
+        mut transport,
+        MemoryServer {
+            requests,
+            to_client,
+        },
+    ) = MemoryTransport::pair();
+    // What the session sends (repeat requests, NACKs, capability replies)
+    // comes off a plain channel, so it waits on a blocking thread
+    let outgoing = {
+        let sock = sock.clone();
+        let handle = runtime::Handle::current();
+        task::spawn_blocking(move || {
+            for datagram in requests {
+                let _ = handle.block_on(sock.send(&datagram));
+            }
+        })
+    };
+    let incoming = tokio::spawn({
+        let sock = sock.clone();
+        let mut buf = vec![0; cli.receive_buffer_len()];
+        async move {
+            loop {
+                let len = sock.recv(&mut buf).await?;
+                if to_client.send(buf[..len].to_vec()).is_err() {
+                    return Ok::<_, io::Error>(()); // The session is over
+                }
+            }
+        }
+    });
+
+    let session = task::spawn_blocking(move || {
+        let mut progress = Progress::default();
+        if !cli.quiet {
+            progress.add(Terminal::default());
+        }
+        let received = session::receive_files_over(
+            &mut transport,
+            &SystemClock,
+            &cli,
+            &mut progress,
+            deadline,
+        );
+        drop(transport); // Lets `outgoing` finish
+        let (file_manager, report) = received?;
+        let _turn = finishing
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        client::finish_session(&cli, (*manifest).as_ref(), file_manager, report, deadline)
+    });
+    let result = session.await.map_err(io::Error::other)?;
+    incoming.abort();
+    let _ = outgoing.await;
+    // A socket error (the port closed, say) ends the datagrams early, and
+    // says more than the session running out of them does
+    match incoming.await {
+        Ok(Err(e)) if result.is_err() => Err(e.into()),
+        _ => result,
+    }
+}
@@ -0,0 +1,134 @@
+// --audit: an append-only log of session events in which every entry carries
+// the SHA-256 of the one before it, back to a first entry chained to all
+// zeros. Editing, inserting, dropping, or reordering entries breaks the chain
+// from there on, and `verify-audit` says where. Cutting entries off the end
+// doesn't; that's what the last hash `verify-audit` prints is for, to keep
+// somewhere whoever writes the log can't reach.
+//
+// One entry a line:
+//
+//   <sequence> <wall-clock ms> <event, as in --progress-file> prev=<hex> hash=<hex>
+//
+// where `hash` is the SHA-256 of everything before " hash=". A run appending
+// to an existing log carries on its chain.
+
+use crate::hash::HashAlgorithm;
+
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+fn digest(body: &str) -> String {
+    HashAlgorithm::Sha256.digest(body.as_bytes())
+}
+
+// Where the next entry goes
+#[derive(Debug, PartialEq, Eq)]
+pub struct Chain {
+    next: u64,
+    prev: String,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Chain {
+            next: 0,
+            prev: GENESIS.to_string(),
+        }
+    }
+}
+
+impl Chain {
+    // Carry on after the last line of an existing log, or None if it isn't
+    // an entry
+    pub fn after(last: &str) -> Option<Self> {
+        let entry = parse(last)?;
+        Some(Chain {
+            next: entry.sequence + 1,
+            prev: entry.hash.to_string(),
+        })
+    }
+
+    // The line for an event, without a newline
+    pub fn entry(&mut self, millis: u128, event: &str) -> String {
+        let body = format!("{} {millis} {event} prev={}", self.next, self.prev);
+        let hash = digest(&body);
+        let line = format!("{body} hash={hash}");
+        self.next += 1;
+        self.prev = hash;
+        line
+    }
+}
+
+struct Entry<'a> {
+    sequence: u64,
+    // What the hash covers
+    body: &'a str,
+    prev: &'a str,
+    hash: &'a str,
+}
+
+// From the right, since a file name in the event could say anything
+fn parse(line: &str) -> Option<Entry<'_>> {
+    let (body, hash) = line.rsplit_once(" hash=")?;
+    let (rest, prev) = body.rsplit_once(" prev=")?;
+    let sequence = rest.split(' ').next()?.parse().ok()?;
+    Some(Entry {
+        sequence,
+        body,
+        prev,
+        hash,
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    Malformed,
+    OutOfSequence { expected: u64, found: u64 },
+    // The entry doesn't point at the one before it
+    WrongPrev,
+    // The entry isn't what its hash says
+    WrongHash,
+}
+
+// The first entry that doesn't check out, counting lines from 1
+#[derive(Debug, PartialEq, Eq)]
+pub struct Broken {
+    pub line: usize,
+    pub problem: Problem,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Intact {
+    pub entries: u64,
+    pub last_hash: String,
+}
+
+pub fn verify(log: &str) -> Result<Intact, Broken> {
+    let mut chain = Chain::default();
+    for (i, line) in log.lines().enumerate() {
+        let broken = |problem| Broken {
+            line: i + 1,
+            problem,
+        };
+        let entry = parse(line).ok_or_else(|| broken(Problem::Malformed))?;
+        if entry.sequence != chain.next {
+            return Err(broken(Problem::OutOfSequence {
+                expected: chain.next,
+                found: entry.sequence,
+            }));
+        }
+        if entry.prev != chain.prev {
+            return Err(broken(Problem::WrongPrev));
+        }
+        if digest(entry.body) != entry.hash {
+            return Err(broken(Problem::WrongHash));
+        }
+        chain = Chain {
+            next: entry.sequence + 1,
+            prev: entry.hash.to_string(),
+        };
+    }
+    Ok(Intact {
+        entries: chain.next,
+        last_hash: chain.prev,
+    })
+}
@@ -0,0 +1,653 @@
+// Built-in micro-benchmarks for the receive path, run with
+// `segmented-file-system-client bench <scenario>`
+
+use crate::{
+    assembly::Backend,
+    cli::{Cli, OnConflict, WriteOrder},
+    error::ClientError,
+    events::Progress,
+    file_manager::FileManager,
+    fsio,
+    packet::{Packet, PacketView},
+    session,
+    transport::{Handler, ManualClock, Transport, FAKE_SERVER},
+    wire,
+};
+use clap::{Parser, ValueEnum};
+use std::{convert::TryFrom, io, time::Instant};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Scenario {
+    /// Plain recv_from against UDP GRO for a burst of full-size datagrams
+    /// over loopback. Linux only.
+    Gro,
+    /// Heap allocations per packet when parsing and storing headers, short
+    /// data packets, and full data packets. Counts need the `count-allocs`
+    /// feature.
+    Alloc,
+    /// FileManager::process_packets against one process_packet call per
+    /// packet, and (on Linux) recvmmsg against recvmsg over loopback.
+    Batch,
+    /// Each --assembly-backend storing three files' packets (in order,
+    /// shuffled, and with duplicates) and writing them out.
+    Assembly,
+    /// PacketView::parse, Packet::try_from (which copies), then
+    /// FileManager::process_packet as well, then a whole session, over
+    /// packets generated in-process rather than read from a socket.
+    Pipeline,
+}
+
+pub fn run(scenario: Scenario, packets: usize) -> io::Result<()> {
+    match scenario {
+        Scenario::Gro => gro(packets),
+        Scenario::Alloc => alloc(packets),
+        Scenario::Batch => batch(packets),
+        Scenario::Assembly => assembly(packets),
+        Scenario::Pipeline => pipeline(packets),
+    }
+}
+
+// Counts every allocation so `bench alloc` can report them. Off by default
+// since it puts an atomic add on every allocation in the program.
+#[cfg(feature = "count-allocs")]
+mod counting {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    pub static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+    struct Counting;
+
+    // SAFETY: defers to the system allocator and only adds bookkeeping
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Counting = Counting;
+}
+
+fn allocations() -> Option<u64> {
+    #[cfg(feature = "count-allocs")]
+    return Some(counting::ALLOCATIONS.load(std::sync::atomic::Ordering::Relaxed));
+    #[cfg(not(feature = "count-allocs"))]
+    None
+}
+
+// Synthetic packets for one workload, spread over three files like a real
+// session
+fn alloc_workload(kind: &str, packets: usize) -> Vec<Vec<u8>> {
+    (0..packets)
+        .map(|i| {
+            let file_id = (i % 3) as u8;
+            match kind {
+                "headers" => {
+                    let mut packet = vec![wire::HEADER_STATUS, file_id];
+                    packet.extend_from_slice(format!("file-{file_id}.txt").as_bytes());
+                    packet
+                }
+                _ => {
+                    let len = if kind == "short data" {
+                        40
+                    } else {
+                        wire::MAX_PAYLOAD
+                    };
+                    let mut packet = wire::data_prefix(file_id, (i / 3) as u16, false).to_vec();
+                    packet.resize(wire::PAYLOAD + len, b'x');
+                    packet
+                }
+            }
+        })
+        .collect()
+}
+
+fn alloc(packets: usize) -> io::Result<()> {
+    if allocations().is_none() {
+        println!("(allocation counts need `--features count-allocs`; showing times only)");
+    }
+    // Data packets are keyed by a u16 packet number per file
+    let packets = packets.min(3 * usize::from(u16::MAX));
+    println!("{packets} packets per workload");
+    println!(
+        "{:<12} {:>10} {:>12} {:>10}",
+        "workload", "ms", "allocations", "per packet"
+    );
+    for kind in ["headers", "short data", "full data"] {
+        let workload = alloc_workload(kind, packets);
+        let mut file_manager = FileManager::default();
+        let before = allocations();
+        let started = Instant::now();
+        for bytes in &workload {
+            if let Ok(packet) = Packet::try_from(bytes.as_slice()) {
+                file_manager.process_packet(packet);
+            }
+        }
+        let elapsed = started.elapsed();
+        let counts = before
+            .zip(allocations())
+            .map(|(before, after)| after - before);
+        println!(
+            "{:<12} {:>10.1} {:>12} {:>10}",
+            kind,
+            elapsed.as_secs_f64() * 1000.0,
+            counts.map_or("-".to_string(), |n| n.to_string()),
+            counts.map_or("-".to_string(), |n| format!(
+                "{:.2}",
+                n as f64 / packets.max(1) as f64
+            ))
+        );
+    }
+    Ok(())
+}
+
+// Full data packets that switch file every `run` packets
+fn data_packets(packets: usize, run: usize) -> Vec<Vec<u8>> {
+    let mut next_number = [0u16; 3];
+    (0..packets)
+        .map(|i| {
+            let file_id = (i / run % 3) as u8;
+            let number = &mut next_number[file_id as usize];
+            let mut packet = wire::data_prefix(file_id, *number, false).to_vec();
+            packet.resize(wire::PAYLOAD + wire::MAX_PAYLOAD, b'x');
+            *number = number.wrapping_add(1);
+            packet
+        })
+        .collect()
+}
+
+fn batch(packets: usize) -> io::Result<()> {
+    let packets = packets.min(3 * usize::from(u16::MAX));
+    println!("{packets} full data packets, three files");
+    println!(
+        "{:<22} {:>12} {:>12}",
+        "file switches", "per packet", "batched"
+    );
+    for (label, run) in [("every packet", 1), ("every 32 packets", 32)] {
+        let workload = data_packets(packets, run);
+        let parse = || {
+            workload
+                .iter()
+                .filter_map(|bytes| Packet::try_from(bytes.as_slice()).ok())
+                .collect::<Vec<_>>()
+        };
+
+        let parsed = parse();
+        let mut file_manager = FileManager::default();
+        let started = Instant::now();
+        for packet in parsed {
+            file_manager.process_packet(packet);
+        }
+        let single = started.elapsed();
+
+        let parsed = parse();
+        let mut file_manager = FileManager::default();
+        let started = Instant::now();
+        file_manager.process_packets(parsed.into_iter());
+        let batched = started.elapsed();
+
+        println!(
+            "{:<22} {:>10.1}ms {:>10.1}ms",
+            label,
+            single.as_secs_f64() * 1000.0,
+            batched.as_secs_f64() * 1000.0
+        );
+    }
+    println!();
+    recv_batch(packets)
+}
+
+// Three whole files: a header each, then full data packets and a short last
+// one, in the order `arrange` puts them
+fn file_packets(packets: usize, arrange: &str) -> Vec<Vec<u8>> {
+    let per_file = (packets / 3).clamp(1, usize::from(u16::MAX));
+    let mut all = Vec::new();
+    for file_id in 0..3u8 {
+        let mut header = vec![wire::HEADER_STATUS, file_id];
+        header.extend_from_slice(format!("bench-{file_id}.bin").as_bytes());
+        all.push(header);
+        for number in 0..per_file {
+            let last = number + 1 == per_file;
+            let mut packet = wire::data_prefix(file_id, number as u16, last).to_vec();
+            let len = if last { 100 } else { wire::MAX_PAYLOAD };
+            packet.resize(wire::PAYLOAD + len, b'x');
+            all.push(packet);
+        }
+    }
+    // A fixed xorshift so every run sees the same order
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut random = move |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+    match arrange {
+        "shuffled" => {
+            for i in (1..all.len()).rev() {
+                all.swap(i, random(i + 1));
+            }
+        }
+        "duplicates" => {
+            // One in ten packets again at some later point
+            for _ in 0..all.len() / 10 {
+                let packet = all[random(all.len())].clone();
+                let at = random(all.len() + 1);
+                all.insert(at, packet);
+            }
+        }
+        _ => {}
+    }
+    all
+}
+
+fn assembly(packets: usize) -> io::Result<()> {
+    let scratch = std::env::temp_dir().join(format!("sfs-bench-{}", std::process::id()));
+    fsio::create_dir_all(&scratch)?;
+    let result = assembly_in(&scratch, packets);
+    fsio::remove_dir_all(&scratch)?;
+    result
+}
+
+fn assembly_in(scratch: &std::path::Path, packets: usize) -> io::Result<()> {
+    println!("{packets} packets over three files, written to a temporary directory");
+    println!(
+        "{:<12} {:<8} {:>10} {:>10} {:>10}",
+        "workload", "backend", "store ms", "write ms", "total ms"
+    );
+    for arrange in ["in order", "shuffled", "duplicates"] {
+        let workload = file_packets(packets, arrange);
+        let mut fastest: Option<(Backend, f64)> = None;
+        for &backend in Backend::value_variants() {
+            let parsed: Vec<Packet> = workload
+                .iter()
+                .filter_map(|bytes| Packet::try_from(bytes.as_slice()).ok())
+                .collect();
+            let mut file_manager = FileManager::with_backend(backend, scratch);
+            // Each backend writes the same files over the last one's
+            file_manager.on_conflict = OnConflict::Overwrite;
+            let started = Instant::now();
+            file_manager.process_packets(parsed.into_iter());
+            let stored = started.elapsed();
+            let finalized = file_manager.write_all_files(scratch, WriteOrder::Id, None);
+            let total = started.elapsed();
+            file_manager.clean_up()?;
+            if let Some((_, e)) = finalized.failed.into_iter().next() {
+                return Err(e);
+            }
+
+            let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+            println!(
+                "{:<12} {:<8} {:>10.1} {:>10.1} {:>10.1}",
+                arrange,
+                backend.name(),
+                ms(stored),
+                ms(total - stored),
+                ms(total)
+            );
+            if fastest.is_none_or(|(_, best)| ms(total) < best) {
+                fastest = Some((backend, ms(total)));
+            }
+        }
+        if let Some((backend, _)) = fastest {
+            println!("fastest for {arrange}: {}", backend.name());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn recv_batch(_packets: usize) -> io::Result<()> {
+    println!("recvmmsg is only available on Linux");
+    Ok(())
+}
+
+// recvmsg against recvmmsg for the same burst
+#[cfg(target_os = "linux")]
+fn recv_batch(packets: usize) -> io::Result<()> {
+    use crate::linux;
+    use std::{net::UdpSocket, thread, time::Duration};
+
+    const BATCH: usize = 32;
+    const IDLE: Duration = Duration::from_millis(200);
+
+    println!(
+        "{:<10} {:>10} {:>10} {:>10} {:>12}",
+        "path", "received", "recv calls", "ms", "datagrams/s"
+    );
+    for batched in [false, true] {
+        let sock = UdpSocket::bind("127.0.0.1:0")?;
+        sock.set_read_timeout(Some(IDLE))?;
+        let target = sock.local_addr()?;
+        let sender = thread::spawn(move || send_burst(target, packets));
+
+        let mut buf = vec![0; PACKET_SIZE];
+        let mut batch = linux::BatchReceiver::new(BATCH, PACKET_SIZE);
+        let (mut datagrams, mut calls) = (0, 0);
+        let mut started = None;
+        loop {
+            let received = if batched {
+                batch.recv(&sock)
+            } else {
+                linux::recv(&sock, &mut buf).map(|_| 1)
+            };
+            let Ok(count) = received else {
+                break;
+            };
+            started.get_or_insert_with(Instant::now);
+            datagrams += count;
+            calls += 1;
+        }
+        sender.join().expect("sender panicked")?;
+
+        // The final read only ended by timing out
+        let elapsed = started.map_or(Duration::ZERO, |s| s.elapsed().saturating_sub(IDLE));
+        println!(
+            "{:<10} {:>10} {:>10} {:>10.1} {:>12.0}",
+            if batched { "recvmmsg" } else { "recvmsg" },
+            datagrams,
+            calls,
+            elapsed.as_secs_f64() * 1000.0,
+            datagrams as f64 / elapsed.as_secs_f64().max(1e-9)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+const PACKET_SIZE: usize = wire::MAX_PACKET;
+
+// Blast `packets` full-size datagrams at `target` over loopback, several per
+// syscall
+#[cfg(target_os = "linux")]
+fn send_burst(target: std::net::SocketAddr, packets: usize) -> io::Result<()> {
+    const PACKETS_PER_SEND: usize = 32;
+
+    let sock = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    crate::linux::enable_gso(&sock, PACKET_SIZE as u16)?;
+    let burst = vec![1u8; PACKET_SIZE * PACKETS_PER_SEND];
+    let mut sent = 0;
+    while sent < packets {
+        let count = PACKETS_PER_SEND.min(packets - sent);
+        sock.send_to(&burst[..count * PACKET_SIZE], target)?;
+        sent += count;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn gro(_packets: usize) -> io::Result<()> {
+    println!("UDP GRO is only available on Linux");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn gro(packets: usize) -> io::Result<()> {
+    use crate::linux;
+    use std::{
+        net::UdpSocket,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    // Receive until the burst stops, returning (datagrams, recv calls, time)
+    fn receive(sock: &UdpSocket, gro: bool) -> io::Result<(usize, usize, Duration)> {
+        let mut buf = vec![0; linux::GRO_BUFFER_SIZE];
+        let (mut datagrams, mut calls) = (0, 0);
+        let mut started = None;
+        loop {
+            let received = if gro {
+                linux::recv(sock, &mut buf).map(|received| {
+                    let size = received.segment_size.unwrap_or(received.len).max(1);
+                    received.len.div_ceil(size)
+                })
+            } else {
+                sock.recv(&mut buf).map(|_| 1)
+            };
+            let Ok(count) = received else {
+                break;
+            };
+            started.get_or_insert_with(Instant::now);
+            datagrams += count;
+            calls += 1;
+        }
+        // The final read only ended by timing out
+        let elapsed = started.map_or(Duration::ZERO, |s| s.elapsed());
+        Ok((
+            datagrams,
+            calls,
+            elapsed.saturating_sub(Duration::from_millis(200)),
+        ))
+    }
+
+    println!("{packets} datagrams of {PACKET_SIZE} bytes over loopback");
+    println!(
+        "{:<6} {:>10} {:>10} {:>10} {:>12}",
+        "path", "received", "recv calls", "ms", "datagrams/s"
+    );
+    for gro in [false, true] {
+        let sock = UdpSocket::bind("127.0.0.1:0")?;
+        sock.set_read_timeout(Some(Duration::from_millis(200)))?;
+        if gro {
+            linux::enable_gro(&sock)?;
+        }
+        let target = sock.local_addr()?;
+        let sender = thread::spawn(move || send_burst(target, packets));
+        let (datagrams, calls, elapsed) = receive(&sock, gro)?;
+        sender.join().expect("sender panicked")?;
+
+        let rate = datagrams as f64 / elapsed.as_secs_f64().max(1e-9);
+        println!(
+            "{:<6} {:>10} {:>10} {:>10.1} {:>12.0}",
+            if gro { "gro" } else { "plain" },
+            datagrams,
+            calls,
+            elapsed.as_secs_f64() * 1000.0,
+            rate
+        );
+    }
+    Ok(())
+}
+
+// Data bytes in each of Synthetic's packets; kept small so millions of them
+// still fit in memory once stored
+const SYNTHETIC_PAYLOAD: usize = 128;
+// Packets handed over per receive, about what a batched read brings in
+const SYNTHETIC_BATCH: usize = 64;
+
+// A transport that makes its datagrams up as they're asked for: `packets`
+// data packets over as few files as their u16 packet numbers allow, each
+// file's header first and its packets in order. Nothing is sent anywhere;
+// once every file is out it's exhausted, so a session ends there.
+pub struct Synthetic {
+    // How many data packets each file has
+    files: Vec<u16>,
+    file: usize,
+    // None while the file's header is still to go
+    next: Option<u16>,
+    datagram: Vec<u8>,
+    bytes: u64,
+}
+
+impl Synthetic {
+    // A u16's worth of packets in each of 256 files
+    pub const MAX_PACKETS: usize = u16::MAX as usize * 256;
+
+    pub fn new(packets: usize) -> Self {
+        let per_file = usize::from(u16::MAX);
+        let packets = packets.clamp(1, Self::MAX_PACKETS);
+        let files = (0..packets.div_ceil(per_file))
+            .map(|n| (packets - n * per_file).min(per_file) as u16)
+            .collect();
+        Synthetic {
+            files,
+            file: 0,
+            next: None,
+            datagram: Vec::with_capacity(wire::PAYLOAD + SYNTHETIC_PAYLOAD),
+            bytes: 0,
+        }
+    }
+
+    pub fn files(&self) -> usize {
+        self.files.len()
+    }
+
+    // Every datagram's length, so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    // The next datagram into `self.datagram`, or false if there are no more
+    fn generate(&mut self) -> bool {
+        let Some(&count) = self.files.get(self.file) else {
+            return false;
+        };
+        let file_id = self.file as u8;
+        self.datagram.clear();
+        match self.next {
+            None => {
+                self.datagram.extend([wire::HEADER_STATUS, file_id]);
+                let name = format!("synthetic-{file_id}.bin");
+                self.datagram.extend_from_slice(name.as_bytes());
+                self.next = Some(0);
+            }
+            Some(number) => {
+                let last = number + 1 == count;
+                (self.datagram).extend_from_slice(&wire::data_prefix(file_id, number, last));
+                (self.datagram).resize(wire::PAYLOAD + SYNTHETIC_PAYLOAD, b'x');
+                self.next = Some(number + 1);
+                if last {
+                    self.file += 1;
+                    self.next = None;
+                }
+            }
+        }
+        self.bytes += self.datagram.len() as u64;
+        true
+    }
+}
+
+impl Transport for Synthetic {
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError> {
+        let mut received = false;
+        for _ in 0..SYNTHETIC_BATCH {
+            if !self.generate() {
+                break;
+            }
+            handle(&self.datagram, FAKE_SERVER)?;
+            received = true;
+        }
+        Ok(received)
+    }
+
+    fn send(&mut self, _datagram: &[u8]) {}
+
+    fn connect(&mut self, _server: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn exhausted(&self) -> bool {
+        self.file >= self.files.len()
+    }
+}
+
+// Every datagram Synthetic has, each through `handle`
+fn drain(transport: &mut Synthetic, handle: &mut Handler) -> Result<(), ClientError> {
+    while transport.receive(handle)? {}
+    Ok(())
+}
+
+fn pipeline(packets: usize) -> io::Result<()> {
+    let packets = packets.clamp(1, Synthetic::MAX_PACKETS);
+    let files = Synthetic::new(packets).files();
+    println!("{packets} data packets of {SYNTHETIC_PAYLOAD} bytes over {files} files, generated in-process");
+    println!(
+        "{:<16} {:>10} {:>14} {:>10}",
+        "stage", "ms", "packets/s", "MB/s"
+    );
+    let row = |stage: &str, started: Instant, transport: &Synthetic| {
+        let secs = started.elapsed().as_secs_f64();
+        println!(
+            "{:<16} {:>10.1} {:>14.0} {:>10.1}",
+            stage,
+            secs * 1000.0,
+            packets as f64 / secs,
+            transport.bytes() as f64 / secs / 1e6
+        );
+    };
+    let failed = |e: ClientError| io::Error::other(e.to_string());
+
+    // Picked apart in the buffer, nothing copied out
+    let mut transport = Synthetic::new(packets);
+    let started = Instant::now();
+    let mut viewed = 0usize;
+    drain(&mut transport, &mut |bytes, _| {
+        viewed += usize::from(PacketView::parse(bytes).is_ok());
+        Ok(())
+    })
+    .map_err(failed)?;
+    row("view", started, &transport);
+
+    let mut transport = Synthetic::new(packets);
+    let started = Instant::now();
+    let mut parsed = 0usize;
+    drain(&mut transport, &mut |bytes, _| {
+        parsed += usize::from(Packet::try_from(bytes).is_ok());
+        Ok(())
+    })
+    .map_err(failed)?;
+    row("parse", started, &transport);
+
+    let mut transport = Synthetic::new(packets);
+    let mut file_manager = FileManager::default();
+    let started = Instant::now();
+    drain(&mut transport, &mut |bytes, _| {
+        if let Ok(packet) = Packet::try_from(bytes) {
+            file_manager.process_packet(packet);
+        }
+        Ok(())
+    })
+    .map_err(failed)?;
+    row("parse + store", started, &transport);
+    drop(file_manager);
+
+    let until = format!("files={files}");
+    let cli = Cli::try_parse_from(["client", "--until", &until])
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    let mut transport = Synthetic::new(packets);
+    let clock = ManualClock::default();
+    let started = Instant::now();
+    let (file_manager, _) = session::run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli,
+        None,
+    )
+    .map_err(failed)?;
+    row("session", started, &transport);
+    let complete = (0..files as u8)
+        .filter(|&file_id| file_manager.is_complete(file_id))
+        .count();
+    if viewed.min(parsed) != packets + files || complete != files {
+        return Err(io::Error::other(format!(
+            "only {} packets parsed and {complete} of {files} files complete",
+            viewed.min(parsed)
+        )));
+    }
+    Ok(())
+}
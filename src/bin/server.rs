@@ -0,0 +1,88 @@
+// The companion server: serves local files the way the course server does,
+// for trying out the client without it. See server.rs in the library.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    i18n::{self, tr, Lang},
+    log::log,
+    server, units,
+};
+use std::{
+    io,
+    net::{Ipv4Addr, TcpListener, UdpSocket},
+    path::PathBuf,
+    time::Duration,
+};
+
+#[derive(Parser)]
+#[command(about = "Serve files over UDP in the segmented file system's packets")]
+struct Args {
+    /// Files to send, each with the ID of its place in the list (from 0).
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// UDP port to wait for requests on.
+    #[arg(long, default_value_t = 6014)]
+    port: u16,
+
+    /// Stop after sending the files to this many clients.
+    #[arg(long, value_name = "N")]
+    clients: Option<usize>,
+
+    /// Follow each transfer with an end-of-session datagram, for clients
+    /// run with `--until end`. Clients that don't know it take it for a
+    /// broken data packet.
+    #[arg(long)]
+    end_marker: bool,
+
+    /// Send extended headers (protocol v2), with each file's size,
+    /// modification time and permissions, for the client to give the copy
+    /// it writes. The course client takes them for plain headers and gets
+    /// the names wrong.
+    #[arg(long)]
+    metadata: bool,
+
+    /// How long to wait between packets, so a client on the same machine can
+    /// keep up; 0 sends them back to back.
+    #[arg(long, value_name = "DURATION", default_value = "0.1ms",
+          value_parser = units::parse_duration)]
+    pace: Duration,
+
+    /// Serve over TCP on --port instead, each packet a frame with its
+    /// length in front, for clients run with `--transport tcp`.
+    #[arg(long)]
+    tcp: bool,
+
+    /// Language for messages.
+    #[arg(long, value_enum)]
+    lang: Option<Lang>,
+}
+
+fn run(args: &Args) -> io::Result<()> {
+    let packets = server::load(&args.files, args.metadata)?;
+    let options = server::Options {
+        clients: args.clients,
+        end_marker: args.end_marker,
+        pace: args.pace,
+    };
+    let serving = |addr| tr!("serve-files-serving", count = args.files.len(), addr = addr);
+    if args.tcp {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, args.port))?;
+        println!("{}", serving(listener.local_addr()?));
+        return server::serve_tcp(&listener, &packets, &options);
+    }
+    let sock = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, args.port))?;
+    println!("{}", serving(sock.local_addr()?));
+    server::serve(&sock, &packets, &options)
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Some(lang) = args.lang {
+        i18n::set(lang);
+    }
+    if let Err(e) = run(&args) {
+        log!(Error, "{}", tr!("error", error = e));
+        std::process::exit(1);
+    }
+}
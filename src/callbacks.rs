@@ -0,0 +1,119 @@
+// Progress for a program embedding the client, as calls rather than lines
+// of output: implement `ClientEvents` (every hook does nothing unless
+// overridden) and hand it to `client::transfer`, or add a `Callbacks` to a
+// session's `Progress` to get the hooks from anything that runs one.
+//
+// The hooks run on the thread receiving, between datagrams, so a slow one
+// holds up the transfer; a GUI's should pass things on (down a channel, say)
+// and return.
+
+use crate::{
+    error::ClientError,
+    events::{Event, ProgressSink},
+    wire,
+};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    ffi::OsStr,
+    io,
+    path::Path,
+    rc::Rc,
+};
+
+pub trait ClientEvents {
+    // A file's header, the first time it arrives
+    fn on_header(&mut self, _file_id: u8, _name: &OsStr) {}
+    // A packet new to the file; `expected` is known once its last packet has
+    // arrived, or sooner if its header said how big it is
+    fn on_progress(&mut self, _file_id: u8, _received: usize, _expected: Option<u32>) {}
+    // A file written out in full
+    fn on_file_complete(&mut self, _path: &Path) {}
+    // What ended the transfer early, or stopped files from being written
+    fn on_error(&mut self, _error: &ClientError) {}
+}
+
+// Nobody listening
+impl ClientEvents for () {}
+
+// So the hooks can be shared with whatever else needs them afterwards
+impl<C: ClientEvents> ClientEvents for Rc<RefCell<C>> {
+    fn on_header(&mut self, file_id: u8, name: &OsStr) {
+        self.borrow_mut().on_header(file_id, name)
+    }
+
+    fn on_progress(&mut self, file_id: u8, received: usize, expected: Option<u32>) {
+        self.borrow_mut().on_progress(file_id, received, expected)
+    }
+
+    fn on_file_complete(&mut self, path: &Path) {
+        self.borrow_mut().on_file_complete(path)
+    }
+
+    fn on_error(&mut self, error: &ClientError) {
+        self.borrow_mut().on_error(error)
+    }
+}
+
+#[derive(Default)]
+struct Counted {
+    named: bool,
+    // Packet numbers seen, so a duplicate isn't counted twice
+    received: HashSet<u32>,
+    expected: Option<u32>,
+}
+
+// The sink that turns a session's events into the hooks
+pub struct Callbacks<C> {
+    events: C,
+    files: BTreeMap<u8, Counted>,
+}
+
+impl<C: ClientEvents> Callbacks<C> {
+    pub fn new(events: C) -> Self {
+        Callbacks {
+            events,
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C: ClientEvents> ProgressSink for Callbacks<C> {
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        match *event {
+            // Each --daemon session starts from nothing
+            Event::SessionStarted { .. } => self.files.clear(),
+            Event::Header {
+                file_id,
+                file_name,
+                size,
+            } => {
+                let file = self.files.entry(file_id).or_default();
+                if let Some(size) = size.filter(|_| file.expected.is_none()) {
+                    file.expected = Some(size.div_ceil(wire::MAX_PAYLOAD as u64) as u32);
+                }
+                if !file.named {
+                    file.named = true;
+                    self.events.on_header(file_id, file_name);
+                    (self.events).on_progress(file_id, file.received.len(), file.expected);
+                }
+            }
+            Event::Data {
+                file_id,
+                packet_number,
+                is_last_packet,
+                ..
+            } => {
+                let file = self.files.entry(file_id).or_default();
+                if is_last_packet {
+                    file.expected = Some(packet_number + 1);
+                }
+                if file.received.insert(packet_number) {
+                    (self.events).on_progress(file_id, file.received.len(), file.expected);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
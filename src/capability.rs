@@ -0,0 +1,125 @@
+// Capability advertisements: a server with optional extensions can list them
+// in a small datagram before (or among) its packets, and the client answers
+// with the ones it will use. Whatever this build doesn't have (gzip, zstd
+// and encryption without the `gzip`, `zstd` and `crypto` features) is turned
+// down and named in the report, so the server doesn't turn it on and leave
+// the client choking on packets it can't read. Before this, "S" being odd,
+// an advertisement was taken for a data packet.
+//
+// Layout, big-endian, 8 bytes, the same both ways:
+//
+//   "SFSC"  version  protocol version  feature bits (2)
+//
+// The protocol version is the server's (see protocol.rs); one that leaves it
+// zero hasn't said.
+//
+// A server that never sends one gets nothing back; the course server doesn't
+// know about any of this.
+
+pub const MAGIC: &[u8; 4] = b"SFSC";
+pub const VERSION: u8 = 1;
+pub const LEN: usize = 8;
+
+// Feature bits
+pub const CRC: u16 = 1 << 0;
+pub const ECHO: u16 = 1 << 1;
+pub const RESUME: u16 = 1 << 2;
+pub const COMPRESSION: u16 = 1 << 3;
+pub const ENCRYPTION: u16 = 1 << 4;
+pub const ACK: u16 = 1 << 5;
+// COMPRESSION is gzip; this is the other kind
+pub const ZSTD: u16 = 1 << 6;
+// NACKs with 32-bit packet numbers (see nack.rs)
+pub const WIDE_NACK: u16 = 1 << 7;
+
+const GZIP: u16 = if cfg!(feature = "gzip") {
+    COMPRESSION
+} else {
+    0
+};
+
+const ZSTANDARD: u16 = if cfg!(feature = "zstd") { ZSTD } else { 0 };
+
+const CHACHA: u16 = if cfg!(feature = "crypto") {
+    ENCRYPTION
+} else {
+    0
+};
+
+// What this build can do at all, whether or not it's turned on
+pub const BUILT_IN: u16 = CRC | ECHO | RESUME | ACK | WIDE_NACK | GZIP | ZSTANDARD | CHACHA;
+
+const NAMES: [(u16, &str); 8] = [
+    (CRC, "crc"),
+    (ECHO, "echo"),
+    (RESUME, "resume"),
+    (COMPRESSION, "compression"),
+    (ENCRYPTION, "encryption"),
+    (ACK, "ack"),
+    (ZSTD, "zstd"),
+    (WIDE_NACK, "wide-nack"),
+];
+
+// The features in `bits` by name, lowest bit first; bits nobody has named
+// yet go by number
+pub fn names(bits: u16) -> Vec<String> {
+    (0..16)
+        .map(|bit| 1 << bit)
+        .filter(|flag| bits & flag != 0)
+        .map(
+            |flag| match NAMES.iter().find(|(named, _)| *named == flag) {
+                Some((_, name)) => name.to_string(),
+                None => format!("bit {}", flag.trailing_zeros()),
+            },
+        )
+        .collect()
+}
+
+pub fn encode(bits: u16) -> [u8; LEN] {
+    let mut bytes = [0; LEN];
+    bytes[..4].copy_from_slice(MAGIC);
+    bytes[4] = VERSION;
+    bytes[6..].copy_from_slice(&bits.to_be_bytes());
+    bytes
+}
+
+// The features a datagram advertises, if it's an advertisement
+pub fn parse(bytes: &[u8]) -> Option<u16> {
+    let ours = bytes.len() == LEN && &bytes[..4] == MAGIC && bytes[4] == VERSION;
+    ours.then(|| u16::from_be_bytes([bytes[6], bytes[7]]))
+}
+
+// The protocol version an advertisement says the server speaks, 0 if none
+pub fn protocol_version(bytes: &[u8]) -> u8 {
+    bytes[5]
+}
+
+// What the server offered against what this run wants
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Negotiation {
+    pub offered: u16,
+    // Turned on with options (--crc and so on)
+    pub wanted: u16,
+}
+
+impl Negotiation {
+    // The answer to send back
+    pub fn agreed(self) -> u16 {
+        self.offered & self.wanted & BUILT_IN
+    }
+
+    // Offered, but this build can't do them
+    pub fn missing(self) -> u16 {
+        self.offered & !BUILT_IN
+    }
+
+    // Offered and built in, but not turned on for this run
+    pub fn declined(self) -> u16 {
+        self.offered & BUILT_IN & !self.wanted
+    }
+
+    // Turned on for this run, but the server didn't offer them
+    pub fn unoffered(self) -> u16 {
+        self.wanted & !self.offered
+    }
+}
@@ -0,0 +1,176 @@
+// Captures (--record, --replay): every datagram the session was handed, byte
+// for byte and with when it came, so a parsing or reassembly bug seen once
+// can be played back through the same session offline, as many times as it
+// takes. Unlike --journal, nothing is left out, so they grow with the
+// transfer. Everything is big-endian, like the wire format.
+//
+//   header (16 bytes): "SFSP", version, 3 zero bytes, session start in
+//                      microseconds since the Unix epoch
+//   record:            microseconds since the session started (8), datagram
+//                      length (2), the datagram
+//
+// A capture cut off mid-record (the client was killed) keeps what's whole.
+
+use crate::{
+    error::ClientError,
+    fsio,
+    i18n::tr,
+    transport::{Clock, Handler, ManualClock, Transport, FAKE_SERVER},
+};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub const MAGIC: &[u8; 4] = b"SFSP";
+pub const VERSION: u8 = 1;
+const HEADER_LEN: usize = 16;
+// Before the datagram itself
+const PREFIX_LEN: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Datagram {
+    pub at: Duration,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+}
+
+impl Recorder {
+    // Start a new capture at `path`, replacing any old one
+    pub fn create(path: &Path, started: Instant) -> io::Result<Self> {
+        let mut out = BufWriter::new(fsio::create(path)?);
+        let wall = SystemTime::now() - started.elapsed();
+        let micros = wall.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_micros());
+        let mut header = [0; HEADER_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = VERSION;
+        header[8..].copy_from_slice(&(micros as u64).to_be_bytes());
+        fsio::write_all(&mut out, path, &header)?;
+        Ok(Recorder {
+            out,
+            path: path.to_path_buf(),
+            started,
+        })
+    }
+
+    pub fn record(&mut self, now: Instant, datagram: &[u8]) -> io::Result<()> {
+        let at = now.saturating_duration_since(self.started).as_micros() as u64;
+        // No UDP datagram is longer, so nothing's cut short
+        let len = datagram.len().min(u16::MAX as usize);
+        let mut prefix = [0; PREFIX_LEN];
+        prefix[..8].copy_from_slice(&at.to_be_bytes());
+        prefix[8..].copy_from_slice(&(len as u16).to_be_bytes());
+        fsio::write_all(&mut self.out, &self.path, &prefix)?;
+        fsio::write_all(&mut self.out, &self.path, &datagram[..len])
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        fsio::at(fsio::Action::Flush, &self.path, || {
+            io::Write::flush(&mut self.out)
+        })
+    }
+}
+
+// Every whole datagram in a capture, in the order they came
+pub fn read(path: &Path) -> io::Result<Vec<Datagram>> {
+    let bytes = fsio::read(path)?;
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            tr!("capture-not-a-capture", path = path.display()),
+        ));
+    }
+    if bytes[4] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            tr!(
+                "capture-bad-version",
+                path = path.display(),
+                version = bytes[4]
+            ),
+        ));
+    }
+    let mut datagrams = Vec::new();
+    let mut rest = &bytes[HEADER_LEN..];
+    while rest.len() >= PREFIX_LEN {
+        let micros = u64::from_be_bytes(rest[..8].try_into().unwrap());
+        let len = u16::from_be_bytes([rest[8], rest[9]]) as usize;
+        let Some(datagram) = rest.get(PREFIX_LEN..PREFIX_LEN + len) else {
+            break;
+        };
+        datagrams.push(Datagram {
+            at: Duration::from_micros(micros),
+            bytes: datagram.to_vec(),
+        });
+        rest = &rest[PREFIX_LEN + len..];
+    }
+    Ok(datagrams)
+}
+
+// Plays a capture back on a `ManualClock`, each datagram when it came
+// relative to the start, a poll interval at a time through the gaps so the
+// session's timers see them as they were. Whatever the client sends goes
+// nowhere: the capture already has the answer.
+pub struct ReplayTransport {
+    datagrams: VecDeque<Datagram>,
+    clock: ManualClock,
+    started: Instant,
+    poll_interval: Option<Duration>,
+}
+
+impl ReplayTransport {
+    pub fn new(datagrams: Vec<Datagram>, clock: &ManualClock) -> Self {
+        ReplayTransport {
+            datagrams: datagrams.into(),
+            clock: clock.clone(),
+            started: clock.now(),
+            poll_interval: None,
+        }
+    }
+
+    // Datagrams not played yet
+    pub fn remaining(&self) -> usize {
+        self.datagrams.len()
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError> {
+        let Some(next) = self.datagrams.front() else {
+            return Ok(false);
+        };
+        let wait = (self.started + next.at).saturating_duration_since(self.clock.now());
+        match self.poll_interval {
+            Some(poll) if poll < wait => {
+                self.clock.advance(poll);
+                return Ok(false);
+            }
+            _ => self.clock.advance(wait),
+        }
+        let datagram = self.datagrams.pop_front().expect("just looked at it");
+        handle(&datagram.bytes, FAKE_SERVER).map(|()| true)
+    }
+
+    fn send(&mut self, _: &[u8]) {}
+
+    fn connect(&mut self, _: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_poll_interval(&mut self, interval: Option<Duration>) -> io::Result<()> {
+        self.poll_interval = interval;
+        Ok(())
+    }
+
+    fn exhausted(&self) -> bool {
+        self.datagrams.is_empty()
+    }
+}
@@ -0,0 +1,97 @@
+// --daemon's manifest.json: every file received so far, kept in the output
+// directory and brought up to date after each batch (one pushed transfer) is
+// written, so whatever picks the files up can poll one file rather than scan
+// the directory. Like progress.json it's written beside the old one and
+// renamed over it, so a reader never sees half of one. It's the same shape
+// --expect reads, with each file's batch time added:
+//
+//   {"files": [{"name": "small.txt", "size": 47, "hash": "blake3:...",
+//               "batch_unix_ms": 1700000000000}]}
+//
+// A file written again under the same name replaces its entry. A run
+// picks up where the last one's manifest.json left off.
+
+use crate::{fsio, hash::HashAlgorithm, i18n::tr};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+pub const FILE_NAME: &str = "manifest.json";
+const PARTIAL_NAME: &str = "manifest.json.partial";
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Catalog {
+    pub files: Vec<Entry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    // Relative to the output directory
+    pub name: String,
+    pub size: u64,
+    pub hash: String,
+    // Wall-clock time the batch was written, in ms since the Unix epoch
+    pub batch_unix_ms: u128,
+}
+
+impl Catalog {
+    // `dir`'s manifest.json, or an empty one if there isn't one yet
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(FILE_NAME);
+        let text = match fsio::read_to_string(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Catalog::default()),
+            result => result?,
+        };
+        serde_json::from_str(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                tr!("catalog-bad", path = path.display(), error = e),
+            )
+        })
+    }
+
+    // Add (or replace) the entries for a batch of files just written
+    pub fn add(
+        &mut self,
+        dir: &Path,
+        written: &[(u8, PathBuf)],
+        algorithm: HashAlgorithm,
+        batch_unix_ms: u128,
+    ) -> io::Result<()> {
+        for (_, path) in written {
+            let data = fsio::read(path)?;
+            let name = path.strip_prefix(dir).unwrap_or(path);
+            let entry = Entry {
+                name: name.to_string_lossy().into_owned(),
+                size: data.len() as u64,
+                hash: algorithm.tagged(&data),
+                batch_unix_ms,
+            };
+            self.files.retain(|earlier| earlier.name != entry.name);
+            self.files.push(entry);
+        }
+        Ok(())
+    }
+
+    // Replace `dir`'s manifest.json with this one
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        let partial = dir.join(PARTIAL_NAME);
+        fsio::write(&partial, format!("{json}\n").as_bytes())?;
+        fsio::rename(&partial, &dir.join(FILE_NAME))
+    }
+}
+
+// Bring `dir`'s manifest.json up to date with a batch
+pub fn record(
+    dir: &Path,
+    written: &[(u8, PathBuf)],
+    algorithm: HashAlgorithm,
+    batch_unix_ms: u128,
+) -> io::Result<()> {
+    let mut catalog = Catalog::load(dir)?;
+    catalog.add(dir, written, algorithm, batch_unix_ms)?;
+    catalog.write(dir)
+}
@@ -0,0 +1,1044 @@
+use crate::{
+    assembly::Backend,
+    bench,
+    completion::{self, Completion, Until},
+    hash::HashAlgorithm,
+    i18n::{self, tr},
+    idle::OnIdle,
+    limits,
+    owner::{self, Owner},
+    selection::Selection,
+    simulate,
+    stream::{self, Target},
+    text::Newlines,
+    units, wire,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Client for the OutOfMoney.com segmented file system server.
+#[derive(Clone, Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Read defaults for any of these options from this TOML file instead of
+    /// `segmented-client/config.toml` in the user's config directory. Keys
+    /// are the long option names; options given here win.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Print a man page for the client to standard output and exit.
+    #[arg(long)]
+    pub generate_man: bool,
+
+    /// Server to request the files from, as `HOST:PORT`. Give it more than
+    /// once with --failover to have servers to fall back on.
+    #[arg(long, value_name = "ADDR", default_value = crate::client::SERVER_ADDR,
+          value_parser = parse_server)]
+    pub server: Vec<String>,
+
+    /// Broadcast for servers on the local subnet, list the ones that answer
+    /// with their files, and take the one picked as --server. Broadcasts to
+    /// ADDR if given, to port 6014 on every host if not.
+    #[arg(long, value_name = "ADDR", num_args = 0..=1,
+          default_missing_value = crate::discover::BROADCAST,
+          conflicts_with_all = ["listen_only", "multicast", "replay"])]
+    pub discover: Option<String>,
+
+    /// With --discover, take the first server to answer rather than asking.
+    #[arg(long, requires = "discover")]
+    pub first: bool,
+
+    /// How long --discover waits for answers.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration,
+          default_value = "1s")]
+    pub discover_wait: Duration,
+
+    /// Local UDP port to receive on, on every interface; 0 picks a free one.
+    /// With --listen-only this is the port servers push to.
+    #[arg(long, value_name = "PORT", default_value_t = crate::client::BIND_PORT)]
+    pub bind: u16,
+
+    /// Use IPv4 only. Without -4, -6 or --dual-stack the socket is IPv4
+    /// unless a --server has only IPv6 addresses (IPv6), or there are both
+    /// kinds of --server (dual-stack). --listen-only is IPv4 unless told.
+    #[arg(short = '4', long, conflicts_with_all = ["ipv6", "dual_stack"])]
+    pub ipv4: bool,
+
+    /// Use IPv6 only.
+    #[arg(short = '6', long, conflicts_with = "dual_stack")]
+    pub ipv6: bool,
+
+    /// Bind `[::]` for IPv4 and IPv6 both, reaching IPv4 servers at their
+    /// IPv4-mapped addresses.
+    #[arg(long)]
+    pub dual_stack: bool,
+
+    /// Receive on tokio, from every --server at once, each over its own
+    /// socket and into its own directory under --output-dir when there are
+    /// several. Needs a build with `--features tokio`.
+    #[arg(long = "async")]
+    pub run_async: bool,
+
+    /// Ask every --server at once over the one socket, without tokio: it
+    /// isn't connected to any of them, and each datagram goes to the session
+    /// for the server it came from, so each server's files are kept apart
+    /// and, when there are several, written into a directory of their own
+    /// under --output-dir.
+    #[arg(long)]
+    pub concurrent: bool,
+
+    /// When a server stops answering partway through (a connect,
+    /// first-packet, or stall timeout), ask the next --server instead and
+    /// keep what has arrived so far. The servers must be serving the same
+    /// files.
+    #[arg(long)]
+    pub failover: bool,
+
+    /// Send repeat requests (from the watchdog or --failover) as plain
+    /// requests, without a resume token listing the packets already
+    /// received. For servers that choke on anything but the usual request.
+    #[arg(long)]
+    pub no_resume_token: bool,
+
+    /// Talk to the server the way the course client did, with every
+    /// extension off: the plain v1 request, no resume tokens, capability
+    /// offers left unanswered, and status bytes read as v1 throughout
+    /// (16-bit packet numbers; no extended headers or checksum packets).
+    /// For making sure nothing added since has broken the original server.
+    #[arg(long, conflicts_with_all = ["crc", "compression", "psk", "echo_interval",
+                                      "ack_interval", "nack_after"])]
+    pub legacy: bool,
+
+    /// Don't send a request; wait for a server to push a transfer to us.
+    /// The first header packet to arrive starts the session, and only its
+    /// sender's packets are accepted after that.
+    #[arg(long)]
+    pub listen_only: bool,
+
+    /// Keep accepting pushed transfers after the first one is written,
+    /// turning the client into a drop-box style receiver. A manifest.json in
+    /// the output directory lists every file received so far.
+    #[arg(long, requires = "listen_only")]
+    pub daemon: bool,
+
+    /// With --listen-only, join this multicast group (`GROUP:PORT`, IPv4)
+    /// and take the transfer sent to it, instead of waiting on --bind. Each
+    /// sender gets a session of its own.
+    #[arg(long, value_name = "GROUP:PORT", value_parser = parse_multicast,
+          requires = "listen_only",
+          conflicts_with_all = ["ipv6", "dual_stack", "stdout", "json"])]
+    pub multicast: Option<SocketAddrV4>,
+
+    /// The local interface to join the --multicast group on, by its IPv4
+    /// address. Without it the system picks.
+    #[arg(long, value_name = "ADDR", requires = "multicast")]
+    pub interface: Option<Ipv4Addr>,
+
+    /// Only take --multicast packets sent from this address; give it more
+    /// than once for several. With just one, its files go in --output-dir
+    /// as usual; otherwise each sender's go in a directory named for it.
+    #[arg(long, value_name = "ADDR", requires = "multicast")]
+    pub multicast_source: Vec<Ipv4Addr>,
+
+    /// Re-send the request when less than this arrives per second over a
+    /// whole watchdog window, e.g. `64KB/s` or `1Mbps`. The watchdog is off
+    /// unless set.
+    #[arg(long, value_name = "RATE", value_parser = units::parse_rate)]
+    pub watchdog_min_rate: Option<u64>,
+
+    /// How long a window the watchdog measures the rate over, e.g. `5s` or
+    /// `1m` [default: 5s].
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub watchdog_window: Option<Duration>,
+
+    /// Once nothing has arrived for this long, e.g. `300ms`, send the
+    /// server a retransmission request (NACK) listing the packets known to
+    /// be missing, and again each time it stays quiet that long. Only
+    /// servers that support NACKs resend anything; the course server
+    /// doesn't. Packets past 65535 are only listed to a server that says
+    /// it takes wide NACKs.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub nack_after: Option<Duration>,
+
+    /// Tell the server this often, e.g. `500ms`, how far each file has got:
+    /// how many packets have arrived in a row from the first, and which of
+    /// the next 32 after that are here too. Only for servers that support
+    /// ACKs: the course server takes any datagram as a new request and
+    /// starts over.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub ack_interval: Option<Duration>,
+
+    /// Keep the transfer to this rate, e.g. `500k` or `4Mbps`, so it leaves
+    /// room on a shared link: the hello asks the server for it (the
+    /// companion server paces itself to it; the course server ignores it),
+    /// ACKs and NACKs going the other way keep to it too, and the report
+    /// says what rate the transfer came to.
+    #[arg(long, value_name = "RATE", value_parser = units::parse_rate)]
+    pub limit_rate: Option<u64>,
+
+    /// When the session is over: `files=N` once N files are complete,
+    /// `names=A,B,...` once those files are, `quiet=DURATION` once one file
+    /// is complete and nothing has arrived for that long (dropping files
+    /// still missing packets), `idle=DURATION` once every file heard of is
+    /// complete and nothing has arrived for that long, or `end` once the
+    /// server has sent its end-of-session datagram and every file heard of
+    /// is complete.
+    #[arg(long, value_name = "CRITERION", default_value = "files=3",
+          value_parser = completion::parse_until)]
+    pub until: Until,
+
+    /// Don't let --until end the session before it has run this long, e.g.
+    /// `10s`, so a server that pauses between files isn't cut off by
+    /// `quiet=...`.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub min_runtime: Option<Duration>,
+
+    /// Give up if the server hasn't sent anything this long after the
+    /// hello, e.g. `5s`. Exits with status 3.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub connect_timeout: Option<Duration>,
+
+    /// Send the hello again if the server hasn't answered this long after
+    /// it, then after twice as long, and so on, never more than 8s apart,
+    /// until something arrives. For a hello lost on the way.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration,
+          default_value = "250ms")]
+    pub hello_interval: Duration,
+
+    /// Send the hello again at most this many times; 0 sends it just once
+    /// [default: until the server answers or --connect-timeout].
+    #[arg(long, value_name = "N")]
+    pub connect_retries: Option<u32>,
+
+    /// Give up if no usable packet has arrived this long after the session
+    /// began (the request went out, or we started listening). Exits with
+    /// status 4.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub first_packet_timeout: Option<Duration>,
+
+    /// Give up if a file that has started arriving gets no more packets for
+    /// this long while still incomplete, e.g. `10s`. Exits with status 5.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub stall_timeout: Option<Duration>,
+
+    /// Once nothing at all has arrived for this long partway through, e.g.
+    /// `5s`, with a file still incomplete, print which files those are and
+    /// the packets each is missing, then do what --on-idle says. Again each
+    /// time it stays quiet that long.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub idle_after: Option<Duration>,
+
+    /// What --idle-after does once it's printed the diagnostics: `resend`
+    /// the request, send a `nack` listing the missing packets (for servers
+    /// that take them), only `report` it, or `abort` with status 12. A
+    /// pushed transfer has nobody to ask, so there it only reports.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        requires = "idle_after"
+    )]
+    pub on_idle: OnIdle,
+
+    /// Give up if the session hasn't finished after this long, e.g.
+    /// `2m30s`. Exits with status 6.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub session_timeout: Option<Duration>,
+
+    /// Give up if the run, writing the files included, isn't over this long
+    /// after it started, e.g. `5m`; the receive loop wakes up for it on time.
+    /// Files not yet written by then aren't. Exits with status 10.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub deadline: Option<Duration>,
+
+    /// Give up if the server sends more than this much in one session, e.g.
+    /// `1.5GiB`.
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    pub max_total_bytes: Option<u64>,
+
+    /// Give up if the server sends packets for more than this many files.
+    #[arg(long, value_name = "N")]
+    pub max_files: Option<usize>,
+
+    /// Give up if any one file is bigger than this, e.g. `100MiB`: as soon
+    /// as its header's size or the packets that came for it say so. A file
+    /// that only grows past it once it's unpacked isn't written.
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    pub max_file_size: Option<u64>,
+
+    /// Keep only files whose names match this pattern (`*` any run of
+    /// characters, `?` any one). Give it more than once for several. With
+    /// nothing but plain names the session ends once those are in.
+    #[arg(long, value_name = "GLOB")]
+    pub only: Vec<String>,
+
+    /// Leave out files whose names match this pattern, as for --only.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Keep only the file with this ID; give it more than once for several.
+    /// The session ends once those are in (or left out by --only or
+    /// --exclude).
+    #[arg(long, value_name = "N")]
+    pub file_id: Vec<u8>,
+
+    /// Give up after more than this many datagrams that don't parse as
+    /// packets. Otherwise each one is skipped and counted in the report, and
+    /// the first of each kind gets a warning. 0 gives up at the first.
+    /// Exits with status 13.
+    #[arg(long, value_name = "N")]
+    pub max_parse_errors: Option<u64>,
+
+    /// Give up on headers whose file name is longer than this many bytes.
+    #[arg(long, value_name = "BYTES", default_value_t = limits::DEFAULT_MAX_NAME_LEN)]
+    pub max_name_len: usize,
+
+    /// What to do when a second header for a file gives it a different
+    /// name: keep the first, take the last, or give up on the session.
+    /// Either way a rename is reported with both names.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t)]
+    pub renamed_headers: limits::Renames,
+
+    /// Keep the directories in header names like `src/lib/util.rs`, making
+    /// them under --output-dir as needed, instead of writing each file under
+    /// the last part of its name. Only `/` separates directories, and a name
+    /// that's absolute or goes up with `..` ends the session.
+    #[arg(long)]
+    pub tree: bool,
+
+    /// How packets get here: as UDP datagrams, or as length-prefixed frames
+    /// over a TCP connection to --server, for networks where UDP is blocked
+    /// or loses too much. The server has to speak it too (the companion
+    /// server's `--tcp`).
+    #[arg(long, value_enum, value_name = "KIND", default_value_t)]
+    pub transport: TransportKind,
+
+    /// Let the kernel coalesce incoming datagrams (UDP GRO) and split them
+    /// back up here, cutting per-packet syscalls at very high rates. Linux
+    /// only.
+    #[arg(long)]
+    pub gro: bool,
+
+    /// Read up to this many datagrams per system call with recvmmsg(2) and
+    /// store them as one batch; headers and echo replies in a batch are
+    /// handled before its data. Linux only.
+    #[arg(long, value_name = "N", default_value_t = 1,
+          value_parser = clap::value_parser!(u16).range(1..=256))]
+    pub recv_batch: u16,
+
+    /// Read datagrams into a buffer this long, for a path whose MTU lets
+    /// through more than a packet should be. A datagram that doesn't fit is
+    /// reported as cut short rather than read as whatever the first bytes
+    /// happen to look like; one that fits but is longer than the protocol
+    /// allows is still too long. At least the longest packet there is (1030
+    /// bytes, or 1034 with --crc).
+    #[arg(long, value_name = "BYTES",
+          value_parser = clap::value_parser!(u16).range(1..=65507))]
+    pub max_packet_size: Option<u16>,
+
+    /// Ask the kernel for a socket receive buffer (SO_RCVBUF) this big, so
+    /// a burst waits there instead of being dropped. The kernel can give
+    /// less (on Linux, no more than net.core.rmem_max); the client warns if
+    /// it does.
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    pub recv_buffer: Option<u64>,
+
+    /// Read the socket on a thread of its own that does nothing else,
+    /// handing datagrams to the session through a queue this many deep, so
+    /// a burst waits in the queue rather than overflowing the kernel's
+    /// buffer while packets are parsed and stored. The report says how full
+    /// the queue got.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub pipeline: Option<u32>,
+
+    /// Write payloads straight into spill files in this directory as they
+    /// arrive instead of holding whole files in memory. While every packet is
+    /// full-size the spill file becomes the output by a rename, so keep it on
+    /// the same filesystem as the output.
+    #[arg(long, value_name = "DIR")]
+    pub spill_dir: Option<PathBuf>,
+
+    /// Write files in this directory until they're whole, then move them
+    /// into the output directory, instead of writing them next to where
+    /// they're going. Spill files go here too unless --spill-dir says
+    /// otherwise. It can be on another filesystem; finished files are copied
+    /// across then.
+    #[arg(long, value_name = "DIR")]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Don't hold the sizes extended headers give up against the free space
+    /// in the output directory (and --temp-dir and --spill-dir) as they
+    /// come. Without this, a transfer that can't fit ends as soon as that's
+    /// known, rather than halfway through writing. Only checked on Linux.
+    #[arg(long)]
+    pub no_space_check: bool,
+
+    /// Keep no more than this much of the payloads received in memory. Past
+    /// it, the file holding the most goes onto disk in --spill-dir (or the
+    /// current directory) and the rest of its packets follow it there, to be
+    /// read back when it's written. A file with a short packet before its
+    /// last can't go, and stays in memory.
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    pub max_memory: Option<u64>,
+
+    /// Where payloads are kept until their file is written: a `hashmap` per
+    /// file, a `vec` indexed by packet number, an `mmap` of 1 KiB slots, or
+    /// `spill` files on disk (in --spill-dir, or the output directory).
+    /// Defaults to `spill` with --spill-dir and `hashmap` otherwise; `bench
+    /// assembly` shows which is fastest here.
+    #[arg(long, value_enum, value_name = "BACKEND")]
+    pub assembly_backend: Option<Backend>,
+
+    /// Expect a CRC-32 of the payload in the last 4 bytes of every data
+    /// packet, and drop packets whose CRC doesn't match. Needs a server that
+    /// sends them.
+    #[arg(long)]
+    pub crc: bool,
+
+    /// Take up a server's offer to send files compressed, unpacking each
+    /// one as it's written: gzip with the `gzip` feature, on by default,
+    /// and zstd with the `zstd` one. A file compressed a way this build
+    /// can't unpack isn't written.
+    #[arg(long, conflicts_with = "stdout")]
+    pub compression: bool,
+
+    /// Open every data packet's payload with this pre-shared key (64 hex
+    /// digits) before using it, dropping packets that don't authenticate.
+    /// Header names are sealed too; one that doesn't open is written under
+    /// --headerless-name. Needs a server that seals them with the same key, and the `crypto`
+    /// feature. Each session sends a random salt in its hello and is sealed
+    /// under a key made from the two, so one key does for any number of
+    /// transfers; a pushed or replayed one has no hello to send it in.
+    #[arg(long, value_name = "HEX", value_parser = crate::crypto::parse_key,
+          conflicts_with_all = ["crc", "listen_only", "replay"])]
+    pub psk: Option<crate::crypto::Key>,
+
+    // Not an option: the salt --psk's session key is made with, drawn
+    // afresh for each session (see resalted)
+    #[arg(skip = crate::crypto::Salt::random())]
+    pub salt: crate::crypto::Salt,
+
+    /// Check CRCs on this many worker threads instead of the receive thread.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub crc_workers: usize,
+
+    /// How the receive loop waits for packets: `blocking` sleeps in the
+    /// kernel, `busy` spins on non-blocking reads, and `adaptive` spins while
+    /// packets are flowing and blocks once they stop.
+    #[arg(long, value_name = "STRATEGY", value_enum, default_value_t = PollStrategy::Blocking)]
+    pub poll_strategy: PollStrategy,
+
+    /// How long adaptive polling keeps spinning after the last packet before
+    /// falling back to blocking reads.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration, default_value = "1ms")]
+    pub spin_budget: Duration,
+
+    /// Pin the receiving thread to this CPU. Linux only; ignored with a
+    /// warning elsewhere or if the OS refuses.
+    #[arg(long, value_name = "CPU")]
+    pub cpu: Option<usize>,
+
+    /// Nice value for the receiving thread, from -20 (highest priority) to
+    /// 19. Negative values usually need root or CAP_SYS_NICE. Linux only.
+    #[arg(long, value_name = "NICE", allow_negative_numbers = true,
+          value_parser = clap::value_parser!(i32).range(-20..=19))]
+    pub priority: Option<i32>,
+
+    /// Sleep this long after each datagram arrives, like a client that can't
+    /// keep up, for testing a server's pacing. The socket's receive buffer
+    /// fills and the kernel drops what doesn't fit.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub artificial_delay: Option<Duration>,
+
+    /// Throw away every Nth datagram before it's looked at, for testing a
+    /// server's retransmission.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub drop_every: Option<u64>,
+
+    /// Drop this fraction of datagrams at random (`0.05` or `5%`) between
+    /// the socket and the session, like a lossy network, for testing how
+    /// the client and the server's retransmission cope.
+    #[arg(long, value_name = "FRACTION", value_parser = units::parse_fraction)]
+    pub simulate_loss: Option<f64>,
+
+    /// Deliver this fraction of datagrams twice, at random.
+    #[arg(long, value_name = "FRACTION", value_parser = units::parse_fraction)]
+    pub simulate_duplicate: Option<f64>,
+
+    /// Hold some datagrams back at random and deliver them a few datagrams
+    /// late.
+    #[arg(long)]
+    pub simulate_reorder: bool,
+
+    /// Make the simulated network's choices from this seed, to repeat a
+    /// run; the report says which seed a run used.
+    #[arg(long, value_name = "N")]
+    pub simulate_seed: Option<u64>,
+
+    /// Don't print how far along each file is while receiving (or which
+    /// server the transfer is coming from). The report at the end is still
+    /// printed.
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Print progress and the report as JSON instead, one object per line on
+    /// standard output, for another program to read. Each has an `event`:
+    /// `listening`, `session-started`, `file-started`, `file-completed` (with
+    /// the path, size and hash), `file-incomplete`, `failover`, `finished`
+    /// (with `errors`, everything that went wrong and the file it was), or
+    /// `error`.
+    #[arg(long, conflicts_with_all = ["stdout", "run_async", "concurrent"])]
+    pub json: bool,
+
+    /// Say more on standard error as the session runs: -v for each file as
+    /// it completes, -vv for every packet as it's parsed as well. Warnings
+    /// (each duplicate packet among them) and errors are printed either way.
+    #[arg(long, short, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Instead of progress lines, keep a table of the files on screen,
+    /// redrawn as packets come: each one's packets so far, rate, duplicates
+    /// and the packets still missing. Space pauses it and the arrow keys
+    /// scroll it. Needs a build with `--features tui`.
+    #[arg(long, conflicts_with_all = ["json", "quiet", "stdout"])]
+    pub tui: bool,
+
+    /// Also append every progress event to this file, so a transfer watched
+    /// live is archived for later inspection.
+    #[arg(long, value_name = "PATH")]
+    pub progress_file: Option<PathBuf>,
+
+    /// Keep a progress.json in the output directory up to date this often,
+    /// e.g. `2s`, with how many packets of which files are in so far. It's
+    /// replaced whole each time and removed when the session ends.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub snapshot_interval: Option<Duration>,
+
+    /// Append every progress event to this audit log, each entry carrying a
+    /// hash of the one before, so the log can't be edited afterwards without
+    /// `verify-audit` noticing. Later runs carry on the same chain.
+    #[arg(long, value_name = "PATH")]
+    pub audit: Option<PathBuf>,
+
+    /// Write a CSV with a row per file (name, ID, bytes, packets,
+    /// duplicates, --hash digest, status, and how long it took) once the
+    /// session ends. Each session replaces the last one's.
+    #[arg(long, value_name = "PATH")]
+    pub report_csv: Option<PathBuf>,
+
+    /// Append this session's figures to a history file kept from run to run:
+    /// rate, loss, each second's throughput, and when each file's packets
+    /// came. `report` draws it as a page of charts.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stdout", "plan"])]
+    pub history: Option<PathBuf>,
+
+    /// Once every file is written, list them in a JSON manifest: each one's
+    /// name, size, packet count, SHA-256, and when its first and latest
+    /// packets came. It's the shape --expect reads, so a later run can be
+    /// checked against it. PATH is in --output-dir [default: manifest.json].
+    #[arg(long, value_name = "PATH", num_args = 0..=1,
+          default_missing_value = "manifest.json", conflicts_with = "stdout")]
+    pub write_manifest: Option<PathBuf>,
+
+    /// How long to wait for a file's header once all its data is in. The
+    /// request goes out again halfway through, in case the server resends;
+    /// if the header still hasn't come when it's over, the file is written
+    /// under --headerless-name.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration,
+          default_value = "2s")]
+    pub header_grace: Duration,
+
+    /// The name a file whose header never came is written under, with
+    /// `{id}` for its file ID.
+    #[arg(long, value_name = "NAME", default_value = "unnamed-{id}.bin")]
+    pub headerless_name: String,
+
+    /// Look at the first bytes of each complete file, and when they say it's
+    /// a PNG, PDF, ZIP, ELF binary or text, add or swap its extension to
+    /// match if its name doesn't. The names the files came with go in the
+    /// report.
+    #[arg(long, conflicts_with = "stdout")]
+    pub fix_extensions: bool,
+
+    /// What to do with empty data packets not marked last, which some
+    /// servers send as keepalives: `ignore` counts and drops them, `store`
+    /// keeps them as (empty) packets of their file.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t)]
+    pub keepalives: Keepalives,
+
+    /// Write this one file (by name, or by ID if it's a number) to standard
+    /// output as it arrives, in order, instead of to disk. Nothing else is
+    /// kept, and the report and progress go to standard error.
+    #[arg(long, value_name = "FILE", value_parser = stream::parse_target,
+          conflicts_with_all = ["run_async", "concurrent", "daemon"])]
+    pub stdout: Option<Target>,
+
+    /// Give the --stdout file to this command's standard input instead, run
+    /// by the shell, e.g. `--stdout data.tar --pipe-to 'tar x'`, and fail if
+    /// the command does. --stdout-buffer paces it the same way.
+    #[arg(long, value_name = "COMMAND", requires = "stdout")]
+    pub pipe_to: Option<String>,
+
+    /// With --stdout, keep no more than this much of the file past what has
+    /// gone out, e.g. `4MiB`. Packets further on are dropped as they come,
+    /// and --nack-after asks for them again once the reader has taken what
+    /// comes before, so a slow reader slows the transfer instead of filling
+    /// memory. Needs a server that answers NACKs.
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size,
+          requires_all = ["stdout", "nack_after"])]
+    pub stdout_buffer: Option<u64>,
+
+    /// Write the files here instead of the current directory, creating it if
+    /// need be. A name from the server never reaches outside it: anything up
+    /// to the last `/` or `\` is dropped, with a warning.
+    #[arg(long, short, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+
+    /// What to do when a file being written is already there: `rename`
+    /// writes the new one as `name.1.ext` (or `.2`, and so on), `skip`
+    /// leaves the old one be, `skip-identical` skips it only if the new one
+    /// is the same and renames it otherwise, `version-suffix` writes
+    /// `name.ext.v2` (or `.v3`, ...), `timestamp-subdir` puts it in a
+    /// directory named for the session's first collision, e.g.
+    /// `2025-03-01T14-05-09.250Z/`, `error` counts the file as failed, and
+    /// `overwrite` replaces it.
+    #[arg(
+        long,
+        visible_alias = "collision",
+        value_enum,
+        value_name = "POLICY",
+        default_value_t
+    )]
+    pub on_conflict: OnConflict,
+
+    /// Write files, and list them in reports, by file ID or by name. Either
+    /// way the order is the same on every run.
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t)]
+    pub write_order: WriteOrder,
+
+    /// Write each file as soon as its last packet is in, on this many
+    /// threads of their own, rather than all of them once the session is
+    /// over. These don't wait for --write-order, and the receive loop doesn't
+    /// wait for them.
+    #[arg(long, value_name = "N", conflicts_with = "stdout",
+          value_parser = clap::value_parser!(u8).range(1..=64))]
+    pub write_threads: Option<u8>,
+
+    /// Receive as usual, but instead of writing the files, print what
+    /// writing them would do: the directories it would make, and where each
+    /// file would go under --on-conflict and what it would do to a file
+    /// already there. Nothing in --output-dir is touched.
+    #[arg(long, conflicts_with_all = ["write_threads", "stdout", "run_async", "concurrent",
+                                      "daemon", "json"])]
+    pub plan: bool,
+
+    /// Give the files written (and the directories `grade` makes) to this
+    /// owner, as `user:group`, `user`, or `:group`, by name or number. For
+    /// running as root on someone else's behalf. Linux only.
+    #[arg(long, value_name = "USER:GROUP", value_parser = owner::parse)]
+    pub chown: Option<Owner>,
+
+    /// File mode creation mask for everything the client creates, in octal
+    /// as for umask(1), e.g. `027`. Linux only.
+    #[arg(long, value_name = "MASK", value_parser = owner::parse_umask)]
+    pub umask: Option<u32>,
+
+    /// After the transfer, check the files written against this JSON
+    /// manifest (`{"files": [{"name": ..., "size": ..., "hash": ...}]}`,
+    /// size and hash optional) and exit with status 7 if any are missing,
+    /// extra, or different. Files that are different (like ones a checksum
+    /// packet disagrees with) are moved into `quarantine/`, each with a
+    /// `.failure.json` saying what was wrong.
+    #[arg(long, value_name = "PATH")]
+    pub expect: Option<PathBuf>,
+
+    /// Give text files these line endings, `lf` or `crlf`, and drop any
+    /// UTF-8 byte order mark. Files that aren't valid UTF-8, or that have NUL
+    /// bytes, are taken for binary and written as they came.
+    #[arg(long, value_name = "NEWLINES")]
+    pub normalize_text: Option<Newlines>,
+
+    /// Keep every packet received in a log in this directory, so a run that
+    /// crashes or is killed can be started again with the same --resume and
+    /// carry on from where it stopped rather than from nothing. The log is
+    /// removed once the files are written.
+    #[arg(long, value_name = "DIR")]
+    pub resume: Option<PathBuf>,
+
+    /// Start --resume's log over when it's from a transfer with another
+    /// server, or isn't a log at all, rather than stopping.
+    #[arg(long, requires = "resume")]
+    pub force: bool,
+
+    /// Record every packet's arrival (time, file, packet number, size, and
+    /// flags) in a compact binary file, for `analyze` to look at afterwards.
+    /// Each session replaces the last one's journal.
+    #[arg(long, value_name = "PATH")]
+    pub journal: Option<PathBuf>,
+
+    /// Save every datagram as it arrives, whole and with when it came, for
+    /// `--replay` to play back later. Each session replaces the last one's
+    /// capture.
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+
+    /// Write every datagram as it arrives to a text file, as an annotated hex
+    /// dump: when it came and from where, what its status byte means, and
+    /// what the parser made of it. Each session replaces the last one's
+    /// trace.
+    #[arg(long, value_name = "PATH")]
+    pub trace_packets: Option<PathBuf>,
+
+    /// Play a `--record` capture back through the session instead of
+    /// talking to a server, at its own pace on a clock that only moves with
+    /// it, and write the files as usual. The session ends when the capture
+    /// does, if it hasn't already.
+    #[arg(long, value_name = "PATH",
+          conflicts_with_all = ["run_async", "concurrent", "daemon"])]
+    pub replay: Option<PathBuf>,
+
+    /// Send a small echo probe this often and time the server's reply, for
+    /// the round-trip time and jitter in the report; a file isn't called
+    /// stalled sooner than a round trip could answer. Only for servers that
+    /// reflect probes: the course server takes any datagram as a new
+    /// request and starts over.
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub echo_interval: Option<Duration>,
+
+    /// Once the transfer ends, also report on how the server paced it:
+    /// burst sizes, the gaps between bursts, how quickly it resent after a
+    /// repeat request, and what sizes its payloads were.
+    #[arg(long)]
+    pub diagnose: bool,
+
+    /// Digest recorded for each received file in grade summaries.
+    #[arg(long, value_enum, value_name = "ALGORITHM", default_value_t)]
+    pub hash: HashAlgorithm,
+
+    /// Language for progress, warnings, and errors. Defaults to the one
+    /// named by LC_ALL, LC_MESSAGES, or LANG. The events file, JSON, and CSV
+    /// output stay in English whatever this is.
+    #[arg(long, value_enum, value_name = "LANG")]
+    pub lang: Option<i18n::Lang>,
+}
+
+// What to do with empty data packets that aren't a file's last
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Keepalives {
+    // Count them and drop them
+    #[default]
+    Ignore,
+    // Keep them as packets of the file, as older clients did
+    Store,
+}
+
+// What becomes of a file already where a new one is going
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    Overwrite,
+    Skip,
+    // Give the new one a free name next to it
+    #[default]
+    Rename,
+    // Rename, unless the new one is the same as what's there
+    SkipIdentical,
+    // name.ext.v2, .v3, ...
+    VersionSuffix,
+    // The same name, in a directory of the session's own
+    TimestampSubdir,
+    Error,
+}
+
+// The order files are written and listed in at the end of a session
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum WriteOrder {
+    #[default]
+    Id,
+    Name,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TransportKind {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PollStrategy {
+    Blocking,
+    Busy,
+    Adaptive,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Print a shell completion script to standard output.
+    Completions {
+        /// The shell to generate completions for.
+        shell: Shell,
+    },
+
+    /// Drive a session interactively, one command at a time.
+    Repl,
+
+    /// Run one of the built-in receive-path benchmarks.
+    Bench {
+        scenario: bench::Scenario,
+
+        /// How many packets to push through the benchmark.
+        #[arg(long, default_value_t = 200_000)]
+        packets: usize,
+    },
+
+    /// Summarise a --journal file: where packets went missing, how far out
+    /// of order they came, and the receive rate over time.
+    Analyze {
+        /// The journal to read.
+        journal: PathBuf,
+
+        /// How much of the session each throughput row covers.
+        #[arg(long, value_name = "DURATION", value_parser = units::parse_duration,
+              default_value = "1s")]
+        interval: Duration,
+
+        /// Print the tables as CSV.
+        #[arg(long)]
+        csv: bool,
+    },
+
+    /// Ask again for just these files, e.g. the one a transfer came away
+    /// without, into the same --output-dir. The hello names them, so a
+    /// server that understands sends only those; from one that doesn't the
+    /// rest are dropped as they come, as with --file-id.
+    Fetch {
+        /// A file to ask for, by ID; give it more than once for several.
+        #[arg(long = "file-id", value_name = "N", required = true)]
+        file_ids: Vec<u8>,
+    },
+
+    /// Cut files into the packets a server would send them as, each
+    /// datagram in a file of its own, with no network involved. File IDs
+    /// count up from 0 in the order given.
+    Pack {
+        /// The files to cut up.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Directory for the packets.
+        #[arg(long, value_name = "DIR", default_value = "packets")]
+        into: PathBuf,
+
+        /// Send extended headers, with each file's size, modification time
+        /// and permissions.
+        #[arg(long)]
+        metadata: bool,
+    },
+
+    /// Put files back together from a directory of packets, as `pack`
+    /// writes them, into --output-dir.
+    Unpack {
+        /// The directory of packets.
+        dir: PathBuf,
+    },
+
+    /// Draw the sessions --history recorded as report.html in --output-dir:
+    /// throughput and loss from run to run, and when each file's packets
+    /// came in the latest session. The page is one file with no links out,
+    /// so viewing it fetches nothing.
+    Report {
+        /// Show the page in a browser once it's written.
+        #[arg(long)]
+        open: bool,
+    },
+
+    /// Check an --audit log's hash chain, and print the last hash to keep
+    /// somewhere safe: cutting entries off the end can only be caught by
+    /// comparing against it.
+    VerifyAudit {
+        /// The audit log to check.
+        log: PathBuf,
+    },
+
+    /// Play a scenario file (see scenario.rs for the format) to the first
+    /// client that sends a request, as a test server that sends exactly the
+    /// packets it's told to, in the order it's told to.
+    Serve {
+        /// The scenario to play.
+        scenario: PathBuf,
+
+        /// UDP port to wait for the request on.
+        #[arg(long, default_value_t = 6014)]
+        port: u16,
+    },
+
+    /// Run the transfer against a list of servers (one per student), keeping
+    /// each server's files in its own directory and writing a pass/fail
+    /// summary as JSON and CSV.
+    Grade {
+        /// File listing one server per line, as `ADDR` or `NAME ADDR`.
+        #[arg(long, value_name = "PATH")]
+        servers: PathBuf,
+
+        /// Directory for the per-server results and the summaries.
+        #[arg(long, value_name = "DIR", default_value = "grades")]
+        out_dir: PathBuf,
+
+        /// Directory of reference files; a server only passes if it delivers
+        /// exactly these.
+        #[arg(long, value_name = "DIR")]
+        expected: Option<PathBuf>,
+    },
+}
+
+// `HOST:PORT`, with IPv6 addresses in brackets. Only the shape is checked
+// here; names are looked up when the socket connects.
+pub fn parse_server(s: &str) -> Result<String, String> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return match addr.port() {
+            0 => Err(tr!("server-bad-port", port = 0)),
+            _ => Ok(s.to_string()),
+        };
+    }
+    let Some((host, port)) = s.rsplit_once(':') else {
+        return Err(tr!("server-no-port", addr = s));
+    };
+    if !port.parse::<u16>().is_ok_and(|port| port != 0) {
+        return Err(tr!("server-bad-port", port = port));
+    }
+    if host.is_empty() {
+        return Err(tr!("server-no-host", addr = s));
+    }
+    if host.contains(':') || host.contains(['[', ']']) {
+        return Err(tr!("server-ipv6-brackets", addr = s));
+    }
+    Ok(s.to_string())
+}
+
+pub fn parse_multicast(s: &str) -> Result<SocketAddrV4, String> {
+    let addr: SocketAddrV4 = s.parse().map_err(|_| tr!("multicast-bad-addr", addr = s))?;
+    if !addr.ip().is_multicast() {
+        return Err(tr!("multicast-not-a-group", addr = s));
+    }
+    match addr.port() {
+        0 => Err(tr!("server-bad-port", port = 0)),
+        _ => Ok(addr),
+    }
+}
+
+impl Cli {
+    pub fn watchdog_window(&self) -> Duration {
+        self.watchdog_window.unwrap_or(Duration::from_secs(5))
+    }
+
+    pub fn assembly_backend(&self) -> Backend {
+        match (self.assembly_backend, &self.spill_dir) {
+            (Some(backend), _) => backend,
+            (None, Some(_)) => Backend::Spill,
+            (None, None) => Backend::Hashmap,
+        }
+    }
+
+    // The same options for another session, with a salt of its own
+    pub fn resalted(&self) -> Cli {
+        Cli {
+            salt: crate::crypto::Salt::random(),
+            ..self.clone()
+        }
+    }
+
+    // Whether the session may ask for packets again by number: with
+    // --nack-after, or --idle-after's nack policy
+    pub fn sends_nacks(&self) -> bool {
+        self.nack_after.is_some() || (self.idle_after.is_some() && self.on_idle == OnIdle::Nack)
+    }
+
+    // What --psk adds to a data packet
+    pub fn tag_len(&self) -> usize {
+        if self.psk.is_some() {
+            crate::crypto::TAG_LEN
+        } else {
+            0
+        }
+    }
+
+    // The longest datagram read whole
+    pub fn max_packet_size(&self) -> usize {
+        (self.max_packet_size).map_or(wire::max_datagram(self.crc) + self.tag_len(), usize::from)
+    }
+
+    // A byte longer, so a datagram that fills it is known to be cut short
+    pub fn receive_buffer_len(&self) -> usize {
+        self.max_packet_size() + 1
+    }
+
+    // --headerless-name for this file
+    pub fn headerless_name(&self, file_id: u8) -> String {
+        self.headerless_name.replace("{id}", &file_id.to_string())
+    }
+
+    // Where spill files go, if anywhere was said
+    pub fn spill_dir(&self) -> Option<&Path> {
+        (self.spill_dir.as_deref()).or(self.temp_dir.as_deref())
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        self.output_dir.as_deref().unwrap_or(Path::new("."))
+    }
+
+    // Every guard against running out of something, in one place
+    pub fn limits(&self) -> limits::Limits {
+        limits::Limits {
+            max_files: self.max_files,
+            max_file_size: self.max_file_size,
+            max_name_len: self.max_name_len,
+            max_buffered: self.max_memory,
+            max_total_bytes: self.max_total_bytes,
+            max_session: self.session_timeout,
+            renamed_headers: self.renamed_headers,
+            tree: self.tree,
+        }
+    }
+
+    pub fn selection(&self) -> Selection {
+        let mut selection = Selection {
+            only: self.only.clone(),
+            exclude: self.exclude.clone(),
+            file_ids: self.file_id.clone(),
+        };
+        if let Some(Command::Fetch { file_ids }) = &self.command {
+            selection.file_ids.extend(file_ids);
+        }
+        match &self.stdout {
+            Some(Target::Id(id)) => selection.file_ids = vec![*id],
+            Some(Target::Name(name)) => selection.only = vec![name.clone()],
+            None => {}
+        }
+        selection
+    }
+
+    // The --until rule, unless the selection names exactly what it wants
+    pub fn completion(&self) -> Completion {
+        (self.selection().completion()).unwrap_or_else(|| self.until.completion())
+    }
+
+    // The simulated bad network, if any of it is asked for
+    pub fn simulation(&self) -> Option<simulate::Options> {
+        let options = simulate::Options {
+            loss: self.simulate_loss.unwrap_or(0.0),
+            duplicate: self.simulate_duplicate.unwrap_or(0.0),
+            reorder: self.simulate_reorder,
+            seed: self.simulate_seed,
+        };
+        (options.loss > 0.0 || options.duplicate > 0.0 || options.reorder).then_some(options)
+    }
+}
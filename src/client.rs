@@ -0,0 +1,635 @@
+// Running a transfer: the local socket, the request, receiving, and writing
+// out what came in.
+
+use crate::{
+    callbacks::{Callbacks, ClientEvents},
+    capture::{self, ReplayTransport},
+    catalog,
+    cli::{parse_server, Cli},
+    error::ClientError,
+    events::{json_line, unix_millis, Progress},
+    file_manager::{FileManager, Finalized, ReceivedFile},
+    fsio, grade, history,
+    hooks::{self, HandshakeSink, Hook, Hooks},
+    i18n::{self, tr},
+    log::log,
+    manifest::{self, Manifest},
+    plan, quarantine,
+    report::SessionReport,
+    results, session, sniff, state,
+    tcp::TcpTransport,
+    transport::{Clock, ManualClock, SystemClock, Transport},
+    units, validate,
+};
+use clap::Parser;
+use serde_json::json;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    io, iter,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub use crate::session::{receive_files, send_hello, send_request};
+
+pub const BIND_PORT: u16 = 7077;
+pub const SERVER_ADDR: &str = "127.0.0.1:6014";
+
+// What the local socket speaks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6,
+    // IPv6, with IPv4 peers at their IPv4-mapped addresses
+    Dual,
+}
+
+impl IpVersion {
+    // What it takes to reach `servers`: IPv6 for one with only IPv6
+    // addresses, IPv4 for anything else (one that doesn't resolve yet
+    // included), and both for some of each
+    pub fn for_servers<'a>(servers: impl IntoIterator<Item = &'a str>) -> Self {
+        let (mut v4, mut v6) = (false, false);
+        for server in servers {
+            let addrs: Vec<SocketAddr> = server
+                .to_socket_addrs()
+                .map(Iterator::collect)
+                .unwrap_or_default();
+            if !addrs.is_empty() && addrs.iter().all(SocketAddr::is_ipv6) {
+                v6 = true;
+            } else {
+                v4 = true;
+            }
+        }
+        match (v4, v6) {
+            (true, true) => IpVersion::Dual,
+            (false, true) => IpVersion::V6,
+            _ => IpVersion::V4,
+        }
+    }
+}
+
+// -4, -6 or --dual-stack, or else whatever `servers` need
+pub fn ip_version<'a>(cli: &Cli, servers: impl IntoIterator<Item = &'a str>) -> IpVersion {
+    if cli.ipv4 {
+        IpVersion::V4
+    } else if cli.ipv6 {
+        IpVersion::V6
+    } else if cli.dual_stack {
+        IpVersion::Dual
+    } else {
+        IpVersion::for_servers(servers)
+    }
+}
+
+// The local socket, on every interface. A port that's taken is the likely
+// failure, so the error says which one and how to pick another.
+pub fn bind(port: u16, ip: IpVersion) -> io::Result<UdpSocket> {
+    let bound = match ip {
+        IpVersion::V4 => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)),
+        IpVersion::V6 => bind_v6(port, true),
+        IpVersion::Dual => bind_v6(port, false),
+    };
+    bound.map_err(|e| {
+        let message = if e.kind() == io::ErrorKind::AddrInUse {
+            tr!("bind-in-use", port = port)
+        } else {
+            tr!("bind-failed", port = port, error = e)
+        };
+        io::Error::new(e.kind(), message)
+    })
+}
+
+// The OS picks whether an IPv6 socket takes IPv4 too if we don't, and they
+// don't all pick the same
+fn bind_v6(port: u16, only_v6: bool) -> io::Result<UdpSocket> {
+    let sock = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    sock.set_only_v6(only_v6)?;
+    sock.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+    Ok(sock.into())
+}
+
+// --recv-buffer, or as much of it as the kernel will give. Linux reports
+// back double what it was asked for, to cover its bookkeeping, so only
+// getting less than that is worth a warning.
+pub fn set_recv_buffer(sock: &UdpSocket, cli: &Cli) {
+    let Some(asked) = cli.recv_buffer else {
+        return;
+    };
+    let sock = SockRef::from(sock);
+    let size = usize::try_from(asked).unwrap_or(usize::MAX);
+    let asked_for = units::format_size(asked);
+    match (sock.set_recv_buffer_size(size)).and_then(|()| sock.recv_buffer_size()) {
+        Ok(got) if (got as u64) < asked => {
+            let got = units::format_size(got as u64);
+            log!(
+                Warn,
+                "{}",
+                tr!("recv-buffer-capped", asked = asked_for, got = got)
+            );
+        }
+        Ok(_) => {}
+        Err(e) => log!(
+            Warn,
+            "{}",
+            tr!("recv-buffer-failed", asked = asked_for, error = e)
+        ),
+    }
+}
+
+// Where `server` is, as a socket bound to `local` reaches it. An IPv6
+// socket reaches IPv4 servers at their IPv4-mapped addresses, which only
+// works if it's dual-stack; an IPv4 one can't reach IPv6 servers at all.
+pub fn server_addrs(local: SocketAddr, server: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs = server.to_socket_addrs()?;
+    Ok(addrs
+        .filter_map(|addr| match (local, addr.ip()) {
+            (SocketAddr::V6(_), IpAddr::V4(ip)) => {
+                Some(SocketAddr::new(ip.to_ipv6_mapped().into(), addr.port()))
+            }
+            (SocketAddr::V4(_), IpAddr::V6(_)) => None,
+            _ => Some(addr),
+        })
+        .collect())
+}
+
+// Point the socket at `server`, trying each of its addresses that the
+// socket can reach
+pub fn connect(sock: &UdpSocket, server: &str) -> io::Result<()> {
+    let addrs = server_addrs(sock.local_addr()?, server)?;
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            tr!("server-wrong-family", server = server),
+        ));
+    }
+    sock.connect(&addrs[..])
+}
+
+// The options for one of several sessions at once (--async, --concurrent):
+// just `server`, and with others alongside, its own directory under
+// --output-dir, named the way `grade` names them
+pub fn for_server(cli: &Cli, server: &str, several: bool) -> Cli {
+    let mut cli = cli.resalted();
+    cli.server = vec![server.to_string()];
+    if several {
+        let dir = cli.output_dir().join(grade::directory_name(server));
+        cli.output_dir = Some(dir);
+        // They can't all have the one port
+        cli.bind = 0;
+        // Nor all draw on the one terminal
+        cli.quiet = true;
+    }
+    cli
+}
+
+// The whole transfer for `cli`, kept in memory: bind, ask, receive, and
+// hand back every complete file, with nothing written to disk
+pub fn receive_all(cli: &Cli) -> Result<Vec<ReceivedFile>, ClientError> {
+    let cli = &cli.resalted();
+    let server = &cli.server[0];
+    let sock = bind(cli.bind, ip_version(cli, [server.as_str()]))?;
+    set_recv_buffer(&sock, cli);
+    connect(&sock, server)?;
+    send_hello(&sock, cli);
+    let deadline = cli.deadline.map(|timeout| Instant::now() + timeout);
+    let (file_manager, _) = receive_files(&sock, cli, &mut Progress::default(), deadline)?;
+    Ok(file_manager.into_received(cli.write_order)?)
+}
+
+// The whole transfer for `cli`, written to its output directory, with
+// `events` told of each header, packet and file written as they happen, and
+// of the error if there is one. Hands back the files written.
+pub fn transfer(
+    cli: &Cli,
+    events: impl ClientEvents + 'static,
+) -> Result<Vec<(u8, PathBuf)>, ClientError> {
+    transfer_hooked(cli, events, &no_hooks())
+}
+
+// transfer, stopping at each of `hooks` on the way (see hooks.rs)
+fn transfer_hooked(
+    cli: &Cli,
+    events: impl ClientEvents + 'static,
+    hooks: &hooks::Shared,
+) -> Result<Vec<(u8, PathBuf)>, ClientError> {
+    let events = Rc::new(RefCell::new(events));
+    // A client run twice is two sessions
+    let result = transfer_to(&cli.resalted(), &events, hooks);
+    if let Err(e) = &result {
+        events.borrow_mut().on_error(e);
+    }
+    result
+}
+
+fn no_hooks() -> hooks::Shared {
+    Arc::new(Mutex::new(()))
+}
+
+fn transfer_to(
+    cli: &Cli,
+    events: &Rc<RefCell<impl ClientEvents + 'static>>,
+    hooks: &hooks::Shared,
+) -> Result<Vec<(u8, PathBuf)>, ClientError> {
+    let server = &cli.server[0];
+    let sock = bind(cli.bind, ip_version(cli, [server.as_str()]))?;
+    let local = sock.local_addr()?;
+    hooks::call(hooks, Hook::AfterBind, |hooks| hooks.after_bind(local))?;
+    set_recv_buffer(&sock, cli);
+    connect(&sock, server)?;
+    fsio::create_dir_all(cli.output_dir())?;
+    send_hello(&sock, cli);
+    let deadline = cli.deadline.map(|timeout| Instant::now() + timeout);
+    let mut progress = Progress::default();
+    progress.add(Callbacks::new(Rc::clone(events)));
+    let failed = Rc::new(RefCell::new(None));
+    progress.add(HandshakeSink::new(Arc::clone(hooks), Rc::clone(&failed)));
+    let (mut file_manager, _) = receive_files(&sock, cli, &mut progress, deadline)
+        .map_err(|e| failed.borrow_mut().take().unwrap_or(e))?;
+    // Once a hook turns one file down, the rest aren't written either
+    let mut refused = None;
+    let finalized = file_manager.write_all_files_redirected(
+        cli.output_dir(),
+        cli.write_order,
+        deadline,
+        |file_id, path| {
+            if refused.is_some() {
+                return Err(io::Error::other(i18n::text("hook-refused-earlier")));
+            }
+            let call = |hooks: &mut dyn Hooks| hooks.before_write(file_id, &path);
+            match hooks::call(hooks, Hook::BeforeWrite, call) {
+                Ok(redirected) => Ok(redirected.unwrap_or(path)),
+                Err(e) => {
+                    let message = e.to_string();
+                    refused = Some(e);
+                    Err(io::Error::other(message))
+                }
+            }
+        },
+    );
+    file_manager.clean_up()?;
+    for (_, path) in &finalized.written {
+        events.borrow_mut().on_file_complete(path);
+    }
+    match refused {
+        Some(e) => Err(e),
+        None => finalized.into_result(),
+    }
+}
+
+// A client set up in code rather than from the command line. Anything the
+// builder has no method for can be given with `args`, as it would be on the
+// command line.
+pub struct Client {
+    cli: Cli,
+    hooks: hooks::Shared,
+}
+
+#[derive(Default)]
+pub struct ClientBuilder {
+    servers: Vec<String>,
+    bind: Option<u16>,
+    deadline: Option<Duration>,
+    output_dir: Option<PathBuf>,
+    args: Vec<OsString>,
+    hooks: Option<hooks::Shared>,
+}
+
+impl Client {
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    pub fn cli(&self) -> &Cli {
+        &self.cli
+    }
+
+    // The transfer, with its files written to the output directory
+    pub fn run(&self) -> Result<Vec<(u8, PathBuf)>, ClientError> {
+        transfer_hooked(&self.cli, (), &self.hooks)
+    }
+
+    // The same, with `events` told how it's going (see callbacks.rs)
+    pub fn run_with(
+        &self,
+        events: impl ClientEvents + 'static,
+    ) -> Result<Vec<(u8, PathBuf)>, ClientError> {
+        transfer_hooked(&self.cli, events, &self.hooks)
+    }
+}
+
+impl From<Cli> for Client {
+    fn from(cli: Cli) -> Self {
+        Client {
+            cli,
+            hooks: no_hooks(),
+        }
+    }
+}
+
+impl ClientBuilder {
+    // Where the request goes; again for servers --failover can fall back on
+    pub fn server(mut self, server: impl Into<String>) -> Self {
+        self.servers.push(server.into());
+        self
+    }
+
+    // 0 picks a free one
+    pub fn bind_port(mut self, port: u16) -> Self {
+        self.bind = Some(port);
+        self
+    }
+
+    // How long the whole run can take, writing the files included
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(timeout);
+        self
+    }
+
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    // Where the transfer stops for the embedding program (see hooks.rs)
+    pub fn hooks(mut self, hooks: impl Hooks + Send + 'static) -> Self {
+        self.hooks = Some(Arc::new(Mutex::new(hooks)));
+        self
+    }
+
+    // Command-line options, e.g. `["--until", "files=2"]`
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    // The client, once its options are checked the way the command line's are
+    pub fn build(self) -> Result<Client, ClientError> {
+        let bad = |problems| ClientError::BadOptions { problems };
+        let args = iter::once(OsString::from(env!("CARGO_PKG_NAME"))).chain(self.args);
+        let mut cli = Cli::try_parse_from(args).map_err(|e| bad(vec![e.to_string()]))?;
+        if !self.servers.is_empty() {
+            let servers: Result<Vec<_>, _> = self
+                .servers
+                .iter()
+                .map(|server| parse_server(server))
+                .collect();
+            cli.server = servers.map_err(|e| bad(vec![e]))?;
+        }
+        cli.bind = self.bind.unwrap_or(cli.bind);
+        cli.deadline = self.deadline.or(cli.deadline);
+        cli.output_dir = self.output_dir.or(cli.output_dir);
+        let problems: Vec<_> = (validate::validate(&cli).iter())
+            .filter(|diagnostic| diagnostic.is_error())
+            .map(ToString::to_string)
+            .collect();
+        match problems.is_empty() {
+            true => Ok(Client {
+                cli,
+                hooks: self.hooks.unwrap_or_else(no_hooks),
+            }),
+            false => Err(bad(problems)),
+        }
+    }
+}
+
+// --replay: the session run again from a capture, with no server and no
+// waiting, and its files written as if they'd just come in
+pub fn replay(
+    cli: &Cli,
+    path: &Path,
+    manifest: Option<&Manifest>,
+    progress: &mut Progress,
+) -> Result<(), ClientError> {
+    let clock = ManualClock::default();
+    let mut transport = ReplayTransport::new(capture::read(path)?, &clock);
+    // On the capture's clock, which has nothing to do with the real one
+    let deadline = cli.deadline.map(|timeout| clock.now() + timeout);
+    let (file_manager, report) =
+        session::receive_files_over(&mut transport, &clock, cli, progress, deadline)?;
+    finish_session(cli, manifest, file_manager, report, None)
+}
+
+// --transport tcp: the usual session, over a connection to the server
+pub fn over_tcp(
+    cli: &Cli,
+    manifest: Option<&Manifest>,
+    progress: &mut Progress,
+) -> Result<(), ClientError> {
+    let deadline = cli.deadline.map(|timeout| Instant::now() + timeout);
+    let mut transport = TcpTransport::new(cli.connect_timeout);
+    transport.connect(&cli.server[0])?;
+    transport.send(&session::hello(cli));
+    let (file_manager, report) =
+        session::receive_files_over(&mut transport, &SystemClock, cli, progress, deadline)?;
+    finish_session(cli, manifest, file_manager, report, deadline)
+}
+
+// With --stdout the file has already gone out as it came, so there's
+// nothing to write, and the report goes where it can't get mixed into it
+fn finish_stream(
+    cli: &Cli,
+    file_manager: FileManager,
+    report: SessionReport,
+) -> Result<(), ClientError> {
+    let interrupted = report.interrupted();
+    if let Some(dir) = cli.resume.as_deref().filter(|_| !interrupted) {
+        state::clear(dir)?;
+    }
+    file_manager.clean_up()?;
+    report.write(&mut io::stderr().lock())?;
+    if interrupted {
+        return Err(ClientError::Interrupted {
+            written: 0,
+            partial: 0,
+        });
+    }
+    Ok(())
+}
+
+// --json's account of each file the session heard about, then of the
+// session as a whole
+fn print_json(
+    cli: &Cli,
+    file_manager: &FileManager,
+    report: &SessionReport,
+    finalized: &Finalized,
+) -> io::Result<()> {
+    for row in results::file_rows(file_manager, report, finalized, cli)? {
+        let path = (finalized.written.iter())
+            .find(|(file_id, _)| *file_id == row.file_id)
+            .map(|(_, path)| path.display().to_string());
+        json_line(json!({
+            "event": if path.is_some() { "file-completed" } else { "file-incomplete" },
+            "file_id": row.file_id,
+            "name": row.name,
+            "path": path,
+            "bytes": row.bytes,
+            "packets": row.packets,
+            "hash": row.hash,
+            "status": row.status,
+        }));
+    }
+    let mut finished = report.summary();
+    finished["event"] = "finished".into();
+    finished["written"] = finalized.written.len().into();
+    finished["failed"] = finalized.failed.len().into();
+    json_line(finished);
+    Ok(())
+}
+
+// Write out a finished session and say how it went
+pub fn finish_session(
+    cli: &Cli,
+    manifest: Option<&Manifest>,
+    mut file_manager: FileManager,
+    mut report: SessionReport,
+    deadline: Option<Instant>,
+) -> Result<(), ClientError> {
+    if cli.stdout.is_some() {
+        return finish_stream(cli, file_manager, report);
+    }
+    if cli.fix_extensions {
+        for renamed in sniff::fix_extensions(&mut file_manager) {
+            report.record_renamed(renamed.file_id, &renamed.from, &renamed.to);
+        }
+    }
+    if cli.plan {
+        for line in plan::plan(&mut file_manager, cli.output_dir(), cli.write_order) {
+            println!("{line}");
+        }
+        report.print();
+        return Ok(file_manager.clean_up()?);
+    }
+    let mut finalized = file_manager.write_all_files(cli.output_dir(), cli.write_order, deadline);
+    // Checked now, so the files that fail go to quarantine/ before anything
+    // lists where they are
+    let divergences = match manifest {
+        Some(manifest) => manifest::compare(manifest, &finalized.written, cli.hash)?,
+        None => Vec::new(),
+    };
+    let mut failures: Vec<_> = (finalized.mismatched.iter())
+        .map(|(file_id, path)| quarantine::Failure {
+            file_id: *file_id,
+            name: (path.file_name())
+                .map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+            reason: "checksum",
+            expected: file_manager.checksum(*file_id),
+            actual: None,
+        })
+        .collect();
+    failures
+        .extend((divergences.iter()).filter_map(|d| quarantine::failure(d, &finalized.written)));
+    quarantine::apply(cli.output_dir(), &mut finalized, &failures)?;
+    for (file_id, e) in &finalized.failed {
+        let message = tr!("write-failed", file_id = file_id, error = e);
+        report.record_error(Some(*file_id), "write-failed", message);
+    }
+    for (file_id, path) in &finalized.mismatched {
+        let path = path.display();
+        let message = tr!("checksum-mismatch", file_id = file_id, path = path);
+        report.record_error(Some(*file_id), "checksum-mismatch", message);
+    }
+    for divergence in &divergences {
+        let file_id = quarantine::failure(divergence, &finalized.written).map(|f| f.file_id);
+        report.record_error(file_id, "manifest-mismatch", divergence.describe());
+    }
+    let interrupted = report.interrupted();
+    let partial = match interrupted {
+        true => file_manager.write_partial_files(cli.output_dir())?,
+        false => Vec::new(),
+    };
+    if cli.daemon {
+        let batch = unix_millis();
+        catalog::record(cli.output_dir(), &finalized.written, cli.hash, batch)?;
+    }
+    // Nothing left to resume once every file is written
+    if let Some(dir) = cli
+        .resume
+        .as_deref()
+        .filter(|_| finalized.failed.is_empty() && !interrupted)
+    {
+        state::clear(dir)?;
+    }
+    if let Some(path) = &cli.history {
+        let entry = history::Entry::new(&report, &file_manager, &finalized, cli.write_order);
+        history::append(path, &entry)?;
+    }
+    if let Some(path) = &cli.report_csv {
+        let rows = results::file_rows(&file_manager, &report, &finalized, cli)?;
+        results::write_csv(path, &rows)?;
+    }
+    if let Some(path) =
+        (cli.write_manifest.as_deref()).filter(|_| finalized.failed.is_empty() && !interrupted)
+    {
+        let dir = cli.output_dir();
+        manifest::write(
+            &dir.join(path),
+            dir,
+            &finalized.written,
+            &file_manager,
+            &report,
+        )?;
+    }
+    if cli.json {
+        print_json(cli, &file_manager, &report, &finalized)?;
+    } else {
+        report.print();
+    }
+    file_manager.clean_up()?;
+    for (file_id, path) in &finalized.skipped {
+        let path = path.display();
+        log!(
+            Warn,
+            "{}",
+            tr!("write-skipped", file_id = file_id, path = path)
+        );
+    }
+    for (file_id, e) in &finalized.failed {
+        log!(
+            Error,
+            "{}",
+            tr!("write-failed", file_id = file_id, error = e)
+        );
+    }
+    for (file_id, path) in &finalized.mismatched {
+        let path = path.display();
+        log!(
+            Error,
+            "{}",
+            tr!("checksum-mismatch", file_id = file_id, path = path)
+        );
+    }
+    if interrupted {
+        for file in &partial {
+            let path = cli.output_dir().join(&file.partial);
+            log!(
+                Warn,
+                "{}",
+                tr!(
+                    "partial-kept",
+                    file_id = file.id,
+                    path = path.display(),
+                    received = file.received,
+                    missing = units::count(&file.missing)
+                )
+            );
+        }
+        return Err(ClientError::Interrupted {
+            written: finalized.written.len(),
+            partial: partial.len(),
+        });
+    }
+    if let Some(manifest) = manifest {
+        let problems = manifest::report(&divergences, manifest.files.len());
+        if problems > 0 && finalized.failed.is_empty() {
+            return Err(ClientError::ManifestMismatch { problems });
+        }
+    }
+    finalized.into_result().map(|_| ())
+}
@@ -0,0 +1,264 @@
+// Deciding when a session is over. The protocol never says how many files
+// are coming, so the client needs a rule: a fixed number of files (3 for the
+// course server), a list of names we expect, "something is complete and the
+// server has gone quiet" for batches of unknown size, or a server that says
+// it's finished. Each is a `CompletionPolicy` over the same `State`, and so
+// is any closure taking one.
+//
+// A server that knows it can mark the end of a session with a small datagram
+// after its last packet:
+//
+//   "SFSF"  version
+//
+// The course server doesn't, and takes any datagram from us as a request to
+// start over, so there's nothing to send back.
+
+use crate::{file_manager::FileManager, units};
+use std::{collections::HashSet, ffi::OsStr, fmt, time::Duration};
+
+pub const END_MAGIC: &[u8; 4] = b"SFSF";
+pub const END_VERSION: u8 = 1;
+pub const END_LEN: usize = 5;
+
+pub fn end_of_session() -> [u8; END_LEN] {
+    let mut bytes = [0; END_LEN];
+    bytes[..4].copy_from_slice(END_MAGIC);
+    bytes[4] = END_VERSION;
+    bytes
+}
+
+pub fn is_end_of_session(bytes: &[u8]) -> bool {
+    bytes.len() == END_LEN && &bytes[..4] == END_MAGIC && bytes[4] == END_VERSION
+}
+
+// What a completion rule gets to look at
+pub struct State<'a> {
+    pub files: &'a FileManager,
+    // Since the last datagram arrived
+    pub quiet_for: Duration,
+    // Whether the server has said the session is over
+    pub ended: bool,
+    // Files left out by --only, --exclude, or --file-id
+    pub skipped: &'a HashSet<u8>,
+}
+
+impl State<'_> {
+    fn complete_files(&self) -> impl Iterator<Item = u8> + '_ {
+        self.files
+            .file_ids()
+            .into_iter()
+            .filter(|&id| self.files.is_complete(id))
+    }
+
+    fn complete_names(&self) -> impl Iterator<Item = &OsStr> + '_ {
+        self.complete_files()
+            .filter_map(|id| self.files.file_status(id).and_then(|(name, _, _)| name))
+    }
+
+    // At least one file heard of, and every one of them complete
+    fn all_complete(&self) -> bool {
+        let ids = self.files.file_ids();
+        !ids.is_empty() && ids.iter().all(|&id| self.files.is_complete(id))
+    }
+}
+
+pub trait CompletionPolicy {
+    fn is_done(&self, state: &State) -> bool;
+
+    // Whether the rule can become true while nothing arrives, so the
+    // receive loop has to wake up to check it
+    fn needs_clock(&self) -> bool {
+        false
+    }
+}
+
+impl<F: Fn(&State) -> bool> CompletionPolicy for F {
+    fn is_done(&self, state: &State) -> bool {
+        self(state)
+    }
+}
+
+// Exactly `count` files, every one complete
+pub struct FileCount(pub usize);
+
+impl CompletionPolicy for FileCount {
+    fn is_done(&self, state: &State) -> bool {
+        let ids = state.files.file_ids();
+        ids.len() == self.0 && state.complete_files().count() == self.0
+    }
+}
+
+// Every named file complete; anything else the server sends is only kept if
+// it happens to be complete by then
+pub struct Catalog(pub Vec<String>);
+
+impl CompletionPolicy for Catalog {
+    fn is_done(&self, state: &State) -> bool {
+        (self.0.iter()).all(|name| state.complete_names().any(|done| done == name.as_str()))
+    }
+}
+
+// At least one file complete and nothing at all for the settle time. Files
+// still missing packets by then aren't coming, so they're dropped.
+pub struct Quiescent(pub Duration);
+
+impl CompletionPolicy for Quiescent {
+    fn is_done(&self, state: &State) -> bool {
+        state.quiet_for >= self.0 && state.complete_files().next().is_some()
+    }
+
+    fn needs_clock(&self) -> bool {
+        true
+    }
+}
+
+// Every file heard of complete and nothing new for the idle time, so a file
+// whose header came late still gets waited for
+pub struct Idle(pub Duration);
+
+impl CompletionPolicy for Idle {
+    fn is_done(&self, state: &State) -> bool {
+        state.quiet_for >= self.0 && state.all_complete()
+    }
+
+    fn needs_clock(&self) -> bool {
+        true
+    }
+}
+
+// The server has sent its end-of-session datagram and every file heard of
+// is complete. The marker can overtake the last few packets, so it doesn't
+// end the session on its own.
+pub struct EndOfSession;
+
+impl CompletionPolicy for EndOfSession {
+    fn is_done(&self, state: &State) -> bool {
+        state.ended && state.all_complete()
+    }
+}
+
+pub struct Completion {
+    // How the report refers to the rule
+    name: String,
+    policy: Box<dyn CompletionPolicy>,
+}
+
+impl Completion {
+    pub fn new(name: impl Into<String>, policy: impl CompletionPolicy + 'static) -> Self {
+        Completion {
+            name: name.into(),
+            policy: Box::new(policy),
+        }
+    }
+
+    pub fn custom(name: impl Into<String>, done: impl Fn(&State) -> bool + 'static) -> Self {
+        Completion::new(name, done)
+    }
+
+    pub fn file_count(count: usize) -> Self {
+        Completion::new(until(Until::Files(count)), FileCount(count))
+    }
+
+    pub fn catalog(names: Vec<String>) -> Self {
+        Completion::new(until(Until::Names(names.clone())), Catalog(names))
+    }
+
+    pub fn quiescent(settle: Duration) -> Self {
+        Completion::new(until(Until::Quiet(settle)), Quiescent(settle))
+    }
+
+    pub fn idle(idle: Duration) -> Self {
+        Completion::new(until(Until::Idle(idle)), Idle(idle))
+    }
+
+    pub fn end_of_session() -> Self {
+        Completion::new(until(Until::End), EndOfSession)
+    }
+
+    pub fn is_done(&self, state: &State) -> bool {
+        self.policy.is_done(state)
+    }
+
+    pub fn needs_clock(&self) -> bool {
+        self.policy.needs_clock()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// How the report names a rule from --until
+fn until(until: Until) -> String {
+    format!("--until {until}")
+}
+
+// `--until` as typed; `Cli` has to stay `Clone`, so the closure is only built
+// when a session starts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Until {
+    Files(usize),
+    Names(Vec<String>),
+    Quiet(Duration),
+    Idle(Duration),
+    End,
+}
+
+// The same form `parse_until` reads
+impl fmt::Display for Until {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Until::Files(count) => write!(f, "files={count}"),
+            Until::Names(names) => write!(f, "names={}", names.join(",")),
+            Until::Quiet(settle) => write!(f, "quiet={}", units::format_duration(*settle)),
+            Until::Idle(idle) => write!(f, "idle={}", units::format_duration(*idle)),
+            Until::End => f.write_str("end"),
+        }
+    }
+}
+
+impl Until {
+    pub fn completion(&self) -> Completion {
+        match self {
+            Until::Files(count) => Completion::file_count(*count),
+            Until::Names(names) => Completion::catalog(names.clone()),
+            Until::Quiet(settle) => Completion::quiescent(*settle),
+            Until::Idle(idle) => Completion::idle(*idle),
+            Until::End => Completion::end_of_session(),
+        }
+    }
+}
+
+// `files=N`, `names=A,B,...`, `quiet=DURATION`, `idle=DURATION`, or `end`
+pub fn parse_until(s: &str) -> Result<Until, String> {
+    if s == "end" {
+        return Ok(Until::End);
+    }
+    let (kind, value) = s.split_once('=').ok_or_else(|| {
+        format!("expected files=N, names=A,B, quiet=DURATION, idle=DURATION, or end, not {s:?}")
+    })?;
+    match kind {
+        "files" => value
+            .parse()
+            .ok()
+            .filter(|&count| count > 0)
+            .map(Until::Files)
+            .ok_or_else(|| format!("expected a positive file count, not {value:?}")),
+        "names" => {
+            let names: Vec<String> = value
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+            if names.is_empty() {
+                return Err("expected at least one file name".to_string());
+            }
+            Ok(Until::Names(names))
+        }
+        "quiet" => units::parse_duration(value).map(Until::Quiet),
+        "idle" => units::parse_duration(value).map(Until::Idle),
+        _ => Err(format!(
+            "unknown criterion {kind:?}; use files, names, quiet, idle, or end"
+        )),
+    }
+}
@@ -0,0 +1,140 @@
+// Compressed files (--compression): a server that offers it can send a
+// file's data as one gzip stream cut into the usual packets, saying so in
+// the header (see wire.rs), and the stream is unpacked on the way to disk.
+// Only the written file is whole again; --stdout and read_range see the
+// bytes as they came. Checksum packets, and an extended header's size, are of
+// the file once it's unpacked.
+//
+// gzip is unpacked by `flate2`, behind the `gzip` feature (on by default),
+// and zstd by `zstd`, behind the `zstd` feature (off by default, since it
+// builds the C library). A file sent a way this build can't unpack fails to
+// write with a message saying why.
+
+use crate::i18n::tr;
+use std::io::{self, Read};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub fn is_built_in(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+}
+
+// Payloads one after another, read as one stream
+pub struct Chunks<'a, I: Iterator<Item = &'a [u8]>> {
+    chunks: I,
+    current: &'a [u8],
+}
+
+impl<'a, I: Iterator<Item = &'a [u8]>> Chunks<'a, I> {
+    pub fn new(chunks: I) -> Self {
+        Chunks {
+            chunks,
+            current: &[],
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = &'a [u8]>> Read for Chunks<'a, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current.is_empty() {
+            match self.chunks.next() {
+                Some(chunk) => self.current = chunk,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.current.len());
+        buf[..n].copy_from_slice(&self.current[..n]);
+        self.current = &self.current[n..];
+        Ok(n)
+    }
+}
+
+// Unpack `input`, handing the file's bytes to `out` a piece at a time
+pub fn decode(
+    compression: Compression,
+    input: impl Read,
+    out: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    match compression {
+        Compression::None => pump(input, out, |e| e),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => gzip(input, out),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => zstd(input, out),
+        #[allow(unreachable_patterns)]
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            tr!("compression-not-built-in", method = compression.name()),
+        )),
+    }
+}
+
+// `input` to `out`, with what goes wrong reading it put as `failed` says
+fn pump(
+    mut input: impl Read,
+    out: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    failed: impl Fn(io::Error) -> io::Error,
+) -> io::Result<()> {
+    let mut buf = [0; 8192];
+    loop {
+        match input.read(&mut buf).map_err(&failed)? {
+            0 => return Ok(()),
+            n => out(&buf[..n])?,
+        }
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// One or more gzip members, one after another. What's wrong with a stream
+// that doesn't check out is only told apart when it isn't gzip at all.
+#[cfg(feature = "gzip")]
+fn gzip(mut input: impl Read, out: &mut dyn FnMut(&[u8]) -> io::Result<()>) -> io::Result<()> {
+    let mut magic = Vec::with_capacity(3);
+    input.by_ref().take(3).read_to_end(&mut magic)?;
+    if magic.is_empty() {
+        return Ok(());
+    }
+    if magic != [0x1f, 0x8b, 8] {
+        return Err(invalid(tr!("gzip-not-gzip")));
+    }
+    let input = flate2::read::MultiGzDecoder::new(io::Cursor::new(magic).chain(input));
+    pump(input, out, |_| invalid(tr!("gzip-corrupt")))
+}
+
+// One or more zstd frames, one after another
+#[cfg(feature = "zstd")]
+fn zstd(mut input: impl Read, out: &mut dyn FnMut(&[u8]) -> io::Result<()>) -> io::Result<()> {
+    // An empty file may come as no frames at all, as with gzip
+    let mut first = Vec::with_capacity(1);
+    input.by_ref().take(1).read_to_end(&mut first)?;
+    if first.is_empty() {
+        return Ok(());
+    }
+    let corrupt = |_| invalid(tr!("zstd-corrupt"));
+    let input = zstd::Decoder::new(io::Cursor::new(first).chain(input)).map_err(corrupt)?;
+    pump(input, out, corrupt)
+}
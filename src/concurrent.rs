@@ -0,0 +1,153 @@
+// --concurrent: every --server at once over the one socket, without tokio.
+// The socket isn't connected to any of them. It's read with recv_from here,
+// and each datagram goes to the session for the server it came from, so each
+// server's files are kept in a FileManager of their own: a file is known by
+// where it came from as well as its ID. Sessions run as they always do, each
+// on a thread of its own over a `MemoryTransport` like --async's, and what
+// they send goes back out to their server with send_to.
+
+use crate::{
+    cli::Cli,
+    client,
+    error::ClientError,
+    events::{Progress, Terminal},
+    fsio,
+    i18n::tr,
+    log::log,
+    manifest::Manifest,
+    session,
+    transport::{self, MemoryServer, MemoryTransport, SystemClock},
+};
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// How long a read waits before looking for sessions that are done
+const POLL: Duration = Duration::from_millis(100);
+
+struct Running {
+    server: String,
+    to_session: Sender<Vec<u8>>,
+    session: JoinHandle<Result<(), ClientError>>,
+}
+
+// The address requests go to and replies come from, as the socket sees it
+fn resolve(sock: &UdpSocket, server: &str) -> io::Result<SocketAddr> {
+    let addrs = client::server_addrs(sock.local_addr()?, server)?;
+    addrs.first().copied().ok_or_else(|| {
+        let message = tr!("server-wrong-family", server = server);
+        io::Error::new(io::ErrorKind::AddrNotAvailable, message)
+    })
+}
+
+pub fn run(sock: &UdpSocket, cli: &Cli, manifest: Option<Manifest>) -> Result<(), ClientError> {
+    let manifest = Arc::new(manifest);
+    // Reports and --expect results come out one session at a time
+    let finishing = Arc::new(Mutex::new(()));
+    let several = cli.server.len() > 1;
+    let mut running: HashMap<SocketAddr, Running> = HashMap::new();
+    for server in &cli.server {
+        let addr = resolve(sock, server)?;
+        if let Some(earlier) = running.get(&addr) {
+            let message = tr!(
+                "concurrent-same-server",
+                server = server,
+                earlier = earlier.server
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message).into());
+        }
+        let cli = client::for_server(cli, server, several);
+        fsio::create_dir_all(cli.output_dir())?;
+        let (
+            mut transport,
+            MemoryServer {
+                requests,
+                to_client,
+            },
+        ) = MemoryTransport::pair();
+        let out = sock.try_clone()?;
+        thread::spawn(move || {
+            for datagram in requests {
+                let _ = out.send_to(&datagram, addr);
+            }
+        });
+        let deadline = cli.deadline.map(|timeout| Instant::now() + timeout);
+        let (manifest, finishing) = (manifest.clone(), finishing.clone());
+        let hello = session::hello(&cli);
+        let session = thread::spawn(move || {
+            let mut progress = Progress::default();
+            if !cli.quiet {
+                progress.add(Terminal::default());
+            }
+            let received = session::receive_files_over(
+                &mut transport,
+                &SystemClock,
+                &cli,
+                &mut progress,
+                deadline,
+            );
+            drop(transport); // Lets its sender finish
+            let (file_manager, report) = received?;
+            let _turn = finishing
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            client::finish_session(&cli, (*manifest).as_ref(), file_manager, report, deadline)
+        });
+        sock.send_to(&hello, addr)?;
+        running.insert(
+            addr,
+            Running {
+                server: server.clone(),
+                to_session: to_client,
+                session,
+            },
+        );
+    }
+
+    sock.set_read_timeout(Some(POLL))?;
+    let mut buf = vec![0; cli.receive_buffer_len()];
+    let mut first_error = None;
+    while !running.is_empty() {
+        match sock.recv_from(&mut buf) {
+            // Anyone else's datagrams aren't part of any session
+            Ok((len, from)) => {
+                if let Some(running) = running.get(&from) {
+                    let _ = running.to_session.send(buf[..len].to_vec());
+                }
+            }
+            Err(e) if transport::is_timeout(&e) => {}
+            // Windows says a server wasn't there on the next read, whichever
+            // server it was; its session gives up on it by itself
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => {}
+            Err(e) => return Err(e.into()),
+        }
+        let done: Vec<SocketAddr> = (running.iter())
+            .filter(|(_, running)| running.session.is_finished())
+            .map(|(&addr, _)| addr)
+            .collect();
+        for addr in done {
+            let Running {
+                server, session, ..
+            } = running.remove(&addr).expect("listed as done");
+            let result = session
+                .join()
+                .map_err(|_| io::Error::other(tr!("concurrent-panicked", server = server)))?;
+            if let Err(e) = result {
+                if several {
+                    log!(
+                        Warn,
+                        "{}",
+                        tr!("server-session-failed", server = server, error = e)
+                    );
+                }
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
@@ -0,0 +1,249 @@
+// The configuration file: defaults for any of the client's options, in
+// `--config PATH` or else `config.toml` under the user's config directory
+// (`$XDG_CONFIG_HOME/segmented-client`, `~/.config/segmented-client`, or
+// `%APPDATA%\segmented-client` on Windows). Each key is an option's long
+// name, with `-` or `_`, and the flags on the command line win:
+//
+//   server = "127.0.0.1:6014"
+//   output-dir = "downloads"
+//   connect_timeout = "5s"     # durations and sizes as the flags take them
+//   nack-after = "300ms"
+//   crc = true                 # a flag, on (false leaves it off)
+//   only = ["*.txt", "*.md"]   # an option given more than once
+//
+// This is the part of TOML a list of options needs: `key = value` lines,
+// comments, strings (basic and literal), numbers, booleans, and arrays on one
+// line. Tables aren't, since there's nothing to group. Values go to the
+// options as if typed, so they're checked the same way.
+
+use crate::{
+    cli::Cli,
+    fsio,
+    i18n::{self, tr},
+};
+use clap::{parser::ValueSource, ArgAction, ArgMatches, CommandFactory};
+use std::{
+    env,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    // Numbers as written, since they go to the options as text anyway
+    Number(String),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub line: usize,
+    pub key: String,
+    pub value: Value,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub path: PathBuf,
+    pub entries: Vec<Entry>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// The default file, whether or not it's there
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(env::var_os("APPDATA")?),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("segmented-client").join("config.toml"))
+}
+
+// A string from just after its opening quote; the rest of the line after
+// the closing one comes back with it
+fn string(quote: char, s: &str) -> Result<(String, &str), &'static str> {
+    let mut out = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Ok((out, &s[i + 1..])),
+            '\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                _ => return Err("config-bad-escape"),
+            },
+            c => out.push(c),
+        }
+    }
+    Err("config-unterminated-string")
+}
+
+// One value from the start of `s`, and what's after it
+fn value(s: &str) -> Result<(Value, &str), &'static str> {
+    let s = s.trim_start();
+    if let Some(rest) = s.strip_prefix('"') {
+        return string('"', rest).map(|(s, rest)| (Value::String(s), rest));
+    }
+    if let Some(rest) = s.strip_prefix('\'') {
+        return string('\'', rest).map(|(s, rest)| (Value::String(s), rest));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            let (item, after) = value(rest)?;
+            if matches!(item, Value::Array(_)) {
+                return Err("config-nested-array");
+            }
+            items.push(item);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => {}
+                None => return Err("config-bad-array"),
+            }
+        }
+    }
+    let end = s.find([',', ']', ' ', '\t', '#']).unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ if word.replace('_', "").parse::<f64>().is_ok() => Value::Number(word.replace('_', "")),
+        _ => return Err("config-bad-value"),
+    };
+    Ok((value, rest))
+}
+
+fn entry(line: &str) -> Result<Option<(String, Value)>, &'static str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    if line.starts_with('[') {
+        return Err("config-no-tables");
+    }
+    let (key, rest) = line.split_once('=').ok_or("config-no-equals")?;
+    let key = key.trim().trim_matches('"');
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if key.is_empty() || !key.chars().all(valid) {
+        return Err("config-bad-key");
+    }
+    let (value, rest) = value(rest)?;
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err("config-trailing");
+    }
+    Ok(Some((key.to_string(), value)))
+}
+
+impl Config {
+    pub fn parse(path: &Path, text: &str) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let parsed = entry(line).map_err(|problem| {
+                invalid(tr!(
+                    "config-syntax",
+                    path = path.display(),
+                    line = i + 1,
+                    problem = i18n::text(problem)
+                ))
+            })?;
+            if let Some((key, value)) = parsed {
+                entries.push(Entry {
+                    line: i + 1,
+                    key,
+                    value,
+                });
+            }
+        }
+        Ok(Config {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Config::parse(path, &fsio::read_to_string(path)?)
+    }
+
+    // The command-line arguments the file stands for, leaving out any option
+    // that `matches` (the command line by itself) already has
+    pub fn to_args(&self, matches: &ArgMatches) -> io::Result<Vec<OsString>> {
+        let command = Cli::command();
+        let mut args = Vec::new();
+        for entry in &self.entries {
+            let long = entry.key.replace('_', "-");
+            let arg = (command.get_arguments())
+                .find(|arg| arg.get_long() == Some(&long) && long != "config");
+            let problem = |key: &str| {
+                let path = self.path.display();
+                invalid(i18n::format(
+                    key,
+                    &[("path", &path), ("line", &entry.line), ("option", &long)],
+                ))
+            };
+            let Some(arg) = arg else {
+                return Err(problem("config-unknown-option"));
+            };
+            if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            let flag = format!("--{long}");
+            let is_switch = matches!(arg.get_action(), ArgAction::SetTrue);
+            let repeats = matches!(arg.get_action(), ArgAction::Append);
+            let values = match &entry.value {
+                Value::Bool(on) if is_switch => {
+                    if *on {
+                        args.push(OsString::from(flag));
+                    }
+                    continue;
+                }
+                _ if is_switch => return Err(problem("config-not-a-switch")),
+                Value::Array(items) if repeats => items.clone(),
+                Value::Array(_) => return Err(problem("config-not-a-list")),
+                value => vec![value.clone()],
+            };
+            for value in values {
+                let text = match value {
+                    Value::String(s) | Value::Number(s) => s,
+                    Value::Bool(on) => on.to_string(),
+                    Value::Array(_) => unreachable!("arrays don't nest"),
+                };
+                args.push(OsString::from(&flag));
+                args.push(OsString::from(text));
+            }
+        }
+        Ok(args)
+    }
+}
+
+// The arguments to parse: the program name, what the configuration file
+// says, then the command line, which wins. A missing default file is no
+// configuration; a missing --config is an error.
+pub fn args(raw: Vec<OsString>) -> io::Result<Vec<OsString>> {
+    let matches = Cli::command().get_matches_from(&raw);
+    let config = match matches.get_one::<PathBuf>("config") {
+        Some(path) => Config::load(path)?,
+        None => match default_path().filter(|path| path.is_file()) {
+            Some(path) => Config::load(&path)?,
+            None => return Ok(raw),
+        },
+    };
+    let mut args = raw;
+    let rest = args.split_off(args.len().min(1));
+    args.extend(config.to_args(&matches)?);
+    args.extend(rest);
+    Ok(args)
+}
@@ -0,0 +1,171 @@
+// Encrypted payloads (--psk): a server with the same pre-shared key seals
+// each data packet's payload with ChaCha20-Poly1305 (RFC 8439), and the
+// client opens it before the packet goes any further. A packet that doesn't
+// open was corrupted or forged, and is dropped and counted like any other
+// malformed one; it's asked for again like any other missing one.
+//
+//   nonce:           file ID, packet number (4, big-endian), seven zeros
+//   additional data: the packet before its payload (status, file ID, number)
+//   payload:         the ciphertext, then the 16-byte tag
+//
+// The nonce comes from the packet alone, so a packet sent again is the same
+// bytes whichever copy arrives. What keeps the next transfer from using it
+// again on different data is the key: each session draws a random salt and
+// sends it in its hello (see protocol.rs), and both ends seal under a key
+// derived from the pre-shared one and the salt rather than under the
+// pre-shared key itself.
+//
+// A header's file name is sealed the same way, so nobody watching can tell
+// what's being sent, and goes in the name field as lowercase hex:
+//
+//   nonce:           file ID, ten zeros, then 1
+//   additional data: the header before its name (status, file ID, metadata)
+//
+// A name that doesn't open gets --headerless-name instead. The rest of the
+// header, and checksum packets, go in the clear.
+//
+// The cipher is the `chacha20poly1305` crate's, behind the `crypto` feature
+// (off by default); without it --psk is turned down when the options are
+// checked.
+
+use std::fmt;
+
+pub const KEY_LEN: usize = 32;
+pub const TAG_LEN: usize = 16;
+pub const NONCE_LEN: usize = 12;
+pub const SALT_LEN: usize = 16;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; KEY_LEN]);
+
+impl From<[u8; KEY_LEN]> for Key {
+    fn from(bytes: [u8; KEY_LEN]) -> Self {
+        Key(bytes)
+    }
+}
+
+// Never in a log or an error
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+// --psk's value, 64 hex digits
+pub fn parse_key(s: &str) -> Result<Key, String> {
+    let s = s.trim();
+    if s.len() != KEY_LEN * 2 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("a key is {} hex digits", KEY_LEN * 2));
+    }
+    let mut key = [0; KEY_LEN];
+    for (n, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[n * 2..n * 2 + 2], 16).map_err(|e| e.to_string())?;
+    }
+    Ok(Key(key))
+}
+
+// Drawn for each session, so no two seal under the same key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Salt(pub [u8; SALT_LEN]);
+
+impl Salt {
+    #[cfg(feature = "crypto")]
+    pub fn random() -> Self {
+        use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Salt(salt)
+    }
+
+    // Nothing is sealed without the feature, so there's nothing to salt
+    #[cfg(not(feature = "crypto"))]
+    pub fn random() -> Self {
+        Salt([0; SALT_LEN])
+    }
+}
+
+// What a session's packets are sealed with: the salt hashed under the
+// pre-shared key
+pub fn session_key(psk: &Key, salt: &Salt) -> Key {
+    let mut hasher = blake3::Hasher::new_keyed(&psk.0);
+    hasher.update(b"segmented-file-system session key");
+    hasher.update(&salt.0);
+    Key(*hasher.finalize().as_bytes())
+}
+
+pub fn packet_nonce(file_id: u8, packet_number: u32) -> [u8; NONCE_LEN] {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[0] = file_id;
+    nonce[1..5].copy_from_slice(&packet_number.to_be_bytes());
+    nonce
+}
+
+// The last byte set keeps it clear of every packet's nonce
+pub fn name_nonce(file_id: u8) -> [u8; NONCE_LEN] {
+    let mut nonce = packet_nonce(file_id, 0);
+    nonce[NONCE_LEN - 1] = 1;
+    nonce
+}
+
+#[cfg(feature = "crypto")]
+pub use cipher::{open, open_name, seal, seal_name, seal_packet};
+
+#[cfg(feature = "crypto")]
+mod cipher {
+    use super::{name_nonce, packet_nonce, Key, NONCE_LEN};
+    use crate::hash;
+    use chacha20poly1305::{
+        aead::{Aead, KeyInit, Payload},
+        ChaCha20Poly1305,
+    };
+
+    fn cipher(key: &Key) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(&key.0.into())
+    }
+
+    // The ciphertext, then the tag
+    pub fn seal(key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        // Only fails on more than 256GB
+        (cipher(key).encrypt(nonce.into(), payload)).expect("a payload fits in one nonce")
+    }
+
+    // The plaintext, or None if the tag isn't right
+    pub fn open(key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        let payload = Payload { msg: sealed, aad };
+        cipher(key).decrypt(nonce.into(), payload).ok()
+    }
+
+    // A data packet as a server with the key sends it: `prefix` (status, file
+    // ID and number) and the sealed payload
+    pub fn seal_packet(
+        key: &Key,
+        prefix: &[u8],
+        file_id: u8,
+        packet_number: u32,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let nonce = packet_nonce(file_id, packet_number);
+        let mut packet = prefix.to_vec();
+        packet.extend(seal(key, &nonce, prefix, payload));
+        packet
+    }
+
+    // A header's name field as a server with the key sends it, `prefix`
+    // being the header before it
+    pub fn seal_name(key: &Key, file_id: u8, prefix: &[u8], name: &str) -> String {
+        hash::hex(&seal(key, &name_nonce(file_id), prefix, name.as_bytes()))
+    }
+
+    // The name, or None if it isn't hex, doesn't open, or isn't UTF-8
+    pub fn open_name(key: &Key, file_id: u8, prefix: &[u8], field: &[u8]) -> Option<String> {
+        let sealed: Option<Vec<u8>> = (field.chunks(2))
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+            .collect();
+        let opened = open(key, &name_nonce(file_id), prefix, &sealed?)?;
+        String::from_utf8(opened).ok()
+    }
+}
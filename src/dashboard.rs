@@ -0,0 +1,406 @@
+// --tui: a table of the files that redraws itself in place as packets come,
+// for transfers too long for progress lines to be any use. A row a file:
+//
+//   file  name                        packets         rate   dups  missing
+//      1  lecture.mp4              1200/48000    1205KiB/s      3  17-40, 96, ...
+//
+// with a line under it for the whole session. It's drawn with plain ANSI
+// escapes, at most every REDRAW_EVERY, and once more when the session ends;
+// when standard output isn't a terminal only that last frame is written.
+// Everything comes from the session's events, like the other sinks. Behind
+// the `tui` feature (off by default); without it --tui is turned down when
+// the options are checked.
+//
+// On a terminal it takes keys too, read by crossterm on a thread of their
+// own: space (or p) freezes the frame while the counts go on, and the arrow
+// keys, j/k, Page Up/Down, Home and End scroll through more files than the
+// screen has rows for. The terminal is in raw mode meanwhile, so Ctrl-C
+// comes as a key and is passed on to `interrupt` by hand. Keys are looked at
+// as events come, so a stalled transfer's table only moves once it resumes.
+
+use crate::{
+    events::{Event, ProgressSink},
+    i18n::{self, tr},
+    interrupt, units, wire,
+};
+use crossterm::{
+    event::{self as terminal_event, Event as TerminalEvent, KeyCode, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    env,
+    io::{self, IsTerminal, Write},
+    net::SocketAddr,
+    process,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+const REDRAW_EVERY: Duration = Duration::from_millis(200);
+// Missing ranges listed before "..."
+const MOST_RANGES: usize = 4;
+const NAME_WIDTH: usize = 20;
+// When $COLUMNS and the terminal don't say
+const DEFAULT_WIDTH: usize = 80;
+const DEFAULT_HEIGHT: usize = 24;
+// The heading, the session's line and the one saying what's scrolled off
+const FIXED_LINES: usize = 3;
+
+#[derive(Default)]
+pub struct Dashboard {
+    peer: Option<SocketAddr>,
+    started: Option<Instant>,
+    files: BTreeMap<u8, Row>,
+    // Datagrams, duplicates and all
+    packets: u64,
+    bytes: u64,
+    last_drawn: Option<Instant>,
+    // Lines the last frame took, to go back up over
+    drawn: usize,
+    // The first file shown, and how many the last frame had room for
+    scroll: usize,
+    page: usize,
+    // The frame stays as it is until space is pressed again
+    paused: bool,
+    keyboard: Keyboard,
+}
+
+// Raw mode and the keys read in it, the terminal put back when it's dropped
+#[derive(Default)]
+struct Keyboard {
+    // Set once the thread reading them is going
+    keys: Option<Receiver<KeyCode>>,
+    raw: bool,
+}
+
+#[derive(Default)]
+struct Row {
+    name: Option<String>,
+    received: HashSet<u32>,
+    // Every packet below this is in, so the gaps are looked for after it
+    in_order: u32,
+    highest: Option<u32>,
+    // Known once the last packet is in, or from an extended header
+    expected: Option<u32>,
+    duplicates: u64,
+    bytes: u64,
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl Row {
+    fn record(&mut self, packet_number: u32, len: usize, is_last_packet: bool, now: Instant) {
+        if !self.received.insert(packet_number) {
+            self.duplicates += 1;
+            return;
+        }
+        self.bytes += len as u64;
+        self.first.get_or_insert(now);
+        self.last = Some(now);
+        self.highest = self.highest.max(Some(packet_number));
+        if is_last_packet {
+            self.expected = Some(packet_number + 1);
+        }
+        while self.received.contains(&self.in_order) {
+            self.in_order += 1;
+        }
+    }
+
+    // "0-4, 7, 9-12", of the first MOST_RANGES gaps
+    fn missing(&self) -> String {
+        let end = self.expected.or(self.highest.map(|n| n + 1)).unwrap_or(0);
+        let mut ranges = Vec::new();
+        let mut n = self.in_order;
+        while n < end {
+            if self.received.contains(&n) {
+                n += 1;
+                continue;
+            }
+            if ranges.len() == MOST_RANGES {
+                ranges.push("...".to_string());
+                break;
+            }
+            let start = n;
+            while n < end && !self.received.contains(&n) {
+                n += 1;
+            }
+            ranges.push(match n - 1 {
+                last if last == start => start.to_string(),
+                last => format!("{start}-{last}"),
+            });
+        }
+        ranges.join(", ")
+    }
+
+    // From its first packet to its latest, so a finished file's stays put
+    fn rate(&self) -> String {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) if last > first => rounded_rate(self.bytes, last - first),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+// To the KiB, since a rate that's exact to the byte doesn't read
+fn rounded_rate(bytes: u64, over: Duration) -> String {
+    let rate = (bytes as f64 / over.as_secs_f64()) as u64;
+    units::format_rate(if rate >= 1024 {
+        rate / 1024 * 1024
+    } else {
+        rate
+    })
+}
+
+// At most `width` characters of `text`, ending in "…" if some were cut
+fn clip(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut clipped: String = text.chars().take(width.saturating_sub(1)).collect();
+    clipped.push('…');
+    clipped
+}
+
+impl Dashboard {
+    // Bring the table up to date with `event`, which happened at `now`
+    pub fn record(&mut self, event: &Event, now: Instant) {
+        match *event {
+            Event::SessionStarted { peer } => {
+                // Each --daemon session starts from nothing, and its table
+                // goes under the last one's
+                *self = Dashboard {
+                    peer: Some(peer),
+                    started: Some(now),
+                    keyboard: std::mem::take(&mut self.keyboard),
+                    ..Dashboard::default()
+                };
+            }
+            Event::Header {
+                file_id,
+                file_name,
+                size,
+            } => {
+                let row = self.files.entry(file_id).or_default();
+                row.name = Some(file_name.to_string_lossy().into_owned());
+                if let Some(size) = size.filter(|_| row.expected.is_none()) {
+                    row.expected = Some(size.div_ceil(wire::MAX_PAYLOAD as u64) as u32);
+                }
+            }
+            Event::Data {
+                file_id,
+                packet_number,
+                len,
+                is_last_packet,
+            } => {
+                self.packets += 1;
+                self.bytes += len as u64;
+                let row = self.files.entry(file_id).or_default();
+                row.record(packet_number, len, is_last_packet, now);
+            }
+            _ => {}
+        }
+    }
+
+    // Pause, or scroll by a row, a page or all the way
+    pub fn key(&mut self, key: KeyCode) {
+        let page = self.page.max(1);
+        match key {
+            KeyCode::Char(' ' | 'p') => self.paused = !self.paused,
+            KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll += 1,
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(page),
+            KeyCode::PageDown => self.scroll += page,
+            KeyCode::Home => self.scroll = 0,
+            KeyCode::End => self.scroll = usize::MAX,
+            _ => {}
+        }
+        // No further than where the last page is full
+        self.scroll = self.scroll.min(self.files.len().saturating_sub(self.page));
+    }
+
+    // Rows for files in a frame `height` lines tall
+    fn rows(height: usize) -> usize {
+        height.saturating_sub(FIXED_LINES).max(1)
+    }
+
+    // The frame as it stands at `now`, `width` characters wide and `height`
+    // lines tall at most
+    pub fn render(&self, now: Instant, width: usize, height: usize) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{:>4}  {:<NAME_WIDTH$}  {:>13}  {:>11}  {:>5}  {}",
+            i18n::text("dashboard-file"),
+            i18n::text("dashboard-name"),
+            i18n::text("dashboard-packets"),
+            i18n::text("dashboard-rate"),
+            i18n::text("dashboard-duplicates"),
+            i18n::text("dashboard-missing"),
+        )];
+        let rows = Self::rows(height);
+        let first = self.scroll.min(self.files.len().saturating_sub(rows));
+        for (&file_id, row) in self.files.iter().skip(first).take(rows) {
+            let name = match &row.name {
+                Some(name) => clip(name, NAME_WIDTH),
+                None => tr!("progress-unnamed", file_id = file_id),
+            };
+            let expected = row.expected.map_or("?".to_string(), |n| n.to_string());
+            let packets = format!("{}/{expected}", row.received.len());
+            lines.push(format!(
+                "{file_id:>4}  {name:<NAME_WIDTH$}  {packets:>13}  {:>11}  {:>5}  {}",
+                row.rate(),
+                row.duplicates,
+                row.missing(),
+            ));
+        }
+        let elapsed = self
+            .started
+            .map_or(Duration::ZERO, |at| now.saturating_duration_since(at));
+        let duplicates: u64 = self.files.values().map(|row| row.duplicates).sum();
+        lines.push(match self.peer {
+            None => i18n::text("dashboard-waiting").to_string(),
+            Some(peer) => tr!(
+                "dashboard-summary",
+                peer = peer,
+                elapsed = units::format_duration(elapsed),
+                packets = self.packets,
+                duplicates = duplicates,
+                rate = match elapsed.is_zero() {
+                    true => "-".to_string(),
+                    false => rounded_rate(self.bytes, elapsed),
+                }
+            ),
+        });
+        let shown = self.files.len().min(rows);
+        let mut status = Vec::new();
+        if shown < self.files.len() {
+            status.push(tr!(
+                "dashboard-rows",
+                first = first + 1,
+                last = first + shown,
+                files = self.files.len()
+            ));
+        }
+        if self.paused {
+            status.push(i18n::text("dashboard-paused").to_string());
+        }
+        if !status.is_empty() {
+            lines.push(status.join("; "));
+        }
+        lines.iter().map(|line| clip(line, width)).collect()
+    }
+
+    // All of the table once the session's over, since it's left on screen
+    fn draw(&mut self, out: &mut impl Write, now: Instant, last: bool) -> io::Result<()> {
+        let (columns, lines) = terminal::size()
+            .map(|(columns, lines)| (columns as usize, lines as usize))
+            .unwrap_or((DEFAULT_WIDTH, DEFAULT_HEIGHT));
+        let width = (env::var("COLUMNS").ok())
+            .and_then(|columns| columns.parse().ok())
+            .unwrap_or(columns);
+        let height = if last { usize::MAX } else { lines };
+        self.page = Self::rows(lines);
+        let frame = self.render(now, width, height);
+        // Back up over the last frame and clear to the end of the screen
+        if self.drawn > 0 {
+            write!(out, "\x1b[{}F\x1b[J", self.drawn)?;
+        }
+        // Raw mode doesn't go back to the start of the line by itself
+        let end = if self.keyboard.raw { "\r\n" } else { "\n" };
+        for line in &frame {
+            write!(out, "{line}{end}")?;
+        }
+        self.drawn = frame.len();
+        self.last_drawn = Some(now);
+        out.flush()
+    }
+}
+
+impl Keyboard {
+    // Raw mode, and the thread reading keys the first time round
+    fn listen(&mut self) {
+        if self.raw || !io::stdin().is_terminal() || terminal::enable_raw_mode().is_err() {
+            return;
+        }
+        self.raw = true;
+        if self.keys.is_none() {
+            let (send, keys) = mpsc::channel();
+            thread::spawn(move || read_keys(send));
+            self.keys = Some(keys);
+        }
+    }
+
+    fn restore(&mut self) {
+        if std::mem::take(&mut self.raw) {
+            let _ = terminal::disable_raw_mode();
+        }
+    }
+
+    fn pressed(&self) -> Vec<KeyCode> {
+        self.keys.iter().flat_map(Receiver::try_iter).collect()
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+// Until the dashboard's gone, each key pressed but Ctrl-C, which is handled
+// here so it works however long the next event is in coming
+fn read_keys(keys: Sender<KeyCode>) {
+    while let Ok(event) = terminal_event::read() {
+        let TerminalEvent::Key(key) = event else {
+            continue;
+        };
+        // Windows says when a key comes back up as well
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            // Either way the next thing may be exiting, raw mode and all
+            if interrupt::requested() || !interrupt::installed() {
+                let _ = terminal::disable_raw_mode();
+            }
+            if !interrupt::installed() {
+                process::exit(interrupt::EXIT_CODE);
+            }
+            interrupt::request();
+            continue;
+        }
+        if keys.send(key.code).is_err() {
+            break;
+        }
+    }
+}
+
+impl ProgressSink for Dashboard {
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        let now = Instant::now();
+        self.record(event, now);
+        let mut stdout = io::stdout().lock();
+        if stdout.is_terminal() {
+            self.keyboard.listen();
+        }
+        let pressed = self.keyboard.pressed();
+        let moved = !pressed.is_empty();
+        for key in pressed {
+            self.key(key);
+        }
+        match event {
+            Event::SessionFinished { .. } => {
+                self.paused = false;
+                let drawn = self.draw(&mut stdout, now, true);
+                self.keyboard.restore();
+                drawn
+            }
+            _ if !stdout.is_terminal() => Ok(()),
+            // A key's answered straight away, even while paused
+            _ if moved => self.draw(&mut stdout, now, false),
+            _ if self.paused => Ok(()),
+            _ if self.last_drawn.is_some_and(|at| now - at < REDRAW_EVERY) => Ok(()),
+            _ => self.draw(&mut stdout, now, false),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+// --diagnose: what the transfer says about the server's pacing, for students
+// tuning their own. Datagrams closer together than BURST_GAP count as one
+// burst. A repeat request is "answered" by the first duplicate data packet
+// after it, since a duplicate is the server resending rather than just
+// carrying on.
+
+use crate::{
+    i18n::{self, tr},
+    wire,
+};
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+const BURST_GAP: Duration = Duration::from_millis(1);
+
+// Payload sizes, grouped; the last group is anything longer than a full one
+const SIZE_GROUPS: [(usize, usize); 6] = [
+    (0, 0),
+    (1, 255),
+    (256, 511),
+    (512, wire::MAX_PAYLOAD - 1),
+    (wire::MAX_PAYLOAD, wire::MAX_PAYLOAD),
+    (wire::MAX_PAYLOAD + 1, usize::MAX),
+];
+
+#[derive(Default)]
+pub struct Diagnosis {
+    arrivals: Vec<Instant>,
+    // Payload length of every data packet
+    payloads: Vec<usize>,
+    // A repeat request still waiting for a resend
+    waiting: Option<Instant>,
+    requests: u32,
+    responses: Vec<Duration>,
+}
+
+fn median(sorted: &[Duration]) -> Duration {
+    sorted.get(sorted.len() / 2).copied().unwrap_or_default()
+}
+
+// To the microsecond, which is as fine as timestamps here are worth reading
+fn show(d: Duration) -> String {
+    format!("{:?}", Duration::from_micros(d.as_micros() as u64))
+}
+
+impl Diagnosis {
+    pub fn record_datagram(&mut self, now: Instant, payload: Option<usize>) {
+        self.arrivals.push(now);
+        self.payloads.extend(payload);
+    }
+
+    pub fn record_request(&mut self, now: Instant) {
+        self.requests += 1;
+        self.waiting.get_or_insert(now);
+    }
+
+    pub fn record_duplicate(&mut self, now: Instant) {
+        if let Some(sent) = self.waiting.take() {
+            self.responses.push(now - sent);
+        }
+    }
+
+    // Packets per burst and the gaps between bursts
+    fn bursts(&self) -> (Vec<u64>, Vec<Duration>) {
+        let mut sizes = Vec::new();
+        let mut gaps = Vec::new();
+        for (i, &at) in self.arrivals.iter().enumerate() {
+            match i.checked_sub(1).map(|before| at - self.arrivals[before]) {
+                Some(gap) if gap <= BURST_GAP => *sizes.last_mut().unwrap() += 1,
+                Some(gap) => {
+                    gaps.push(gap);
+                    sizes.push(1);
+                }
+                None => sizes.push(1),
+            }
+        }
+        (sizes, gaps)
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{}", i18n::text("diagnose-title"))?;
+        let (mut sizes, mut gaps) = self.bursts();
+        sizes.sort_unstable();
+        gaps.sort_unstable();
+        writeln!(
+            out,
+            "{}",
+            tr!(
+                "diagnose-bursts",
+                bursts = sizes.len(),
+                median = sizes.get(sizes.len() / 2).copied().unwrap_or(0),
+                max = sizes.last().copied().unwrap_or(0),
+                gap = show(BURST_GAP)
+            )
+        )?;
+        if !gaps.is_empty() {
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "diagnose-gaps",
+                    median = show(median(&gaps)),
+                    max = show(gaps[gaps.len() - 1])
+                )
+            )?;
+        }
+
+        let mut responses = self.responses.clone();
+        responses.sort_unstable();
+        if self.requests == 0 {
+            writeln!(out, "{}", i18n::text("diagnose-no-requests"))?;
+        } else if responses.is_empty() {
+            writeln!(
+                out,
+                "{}",
+                tr!("diagnose-no-resends", requests = self.requests)
+            )?;
+        } else {
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "diagnose-resends",
+                    answered = responses.len(),
+                    requests = self.requests,
+                    median = show(median(&responses)),
+                    max = show(responses.last().copied().unwrap_or_default())
+                )
+            )?;
+        }
+
+        writeln!(out, "{}", i18n::text("diagnose-sizes"))?;
+        let total = self.payloads.len().max(1);
+        for (low, high) in SIZE_GROUPS {
+            let count = (self.payloads.iter())
+                .filter(|&&len| (low..=high).contains(&len))
+                .count();
+            let range = match (low, high) {
+                _ if low == high => low.to_string(),
+                (_, usize::MAX) => format!("{low}+"),
+                _ => format!("{low}-{high}"),
+            };
+            writeln!(
+                out,
+                "    {range:>9} {count:>8} {:>5.1}%",
+                count as f64 * 100.0 / total as f64
+            )?;
+        }
+        Ok(())
+    }
+}
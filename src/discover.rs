@@ -0,0 +1,183 @@
+// --discover: find servers on the local subnet instead of being told one.
+// The client broadcasts a request, every server that hears it says how many
+// files it has and what they're called, and the one picked (by number, or
+// the first with --first) is the --server for the transfer. The companion
+// server answers; the course server doesn't know about any of this and
+// says nothing.
+//
+// Layout, big-endian:
+//
+//   request: "SFSD"  version  0
+//   reply:   "SFSD"  version  1  file count (u16)  names
+//
+// where each name is its length (u8) and then its UTF-8 bytes, as many as
+// fit in one datagram; the count is of every file, so a reply can list
+// fewer names than it counts.
+
+use crate::{
+    cli::Cli,
+    error::ClientError,
+    i18n::{self, tr},
+    log::log,
+    wire,
+};
+use std::{
+    io::{self, BufRead, Write},
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+pub const MAGIC: &[u8; 4] = b"SFSD";
+pub const VERSION: u8 = 1;
+// Where --discover broadcasts without an address, the server's usual port
+pub const BROADCAST: &str = "255.255.255.255:6014";
+
+const REQUEST: u8 = 0;
+const REPLY: u8 = 1;
+// The magic, version, kind and count
+const REPLY_HEADER: usize = 8;
+
+// A server that answered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Found {
+    pub addr: SocketAddr,
+    pub files: u16,
+    pub names: Vec<String>,
+}
+
+pub fn request() -> [u8; 6] {
+    let [m0, m1, m2, m3] = *MAGIC;
+    [m0, m1, m2, m3, VERSION, REQUEST]
+}
+
+pub fn is_request(bytes: &[u8]) -> bool {
+    bytes.len() >= 6 && bytes[..4] == MAGIC[..] && bytes[5] == REQUEST
+}
+
+// A server's answer, naming its files until the datagram is full
+pub fn reply(names: &[&str]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend([VERSION, REPLY]);
+    out.extend_from_slice(&(names.len().min(u16::MAX.into()) as u16).to_be_bytes());
+    for name in names {
+        let name = &name.as_bytes()[..name.len().min(u8::MAX.into())];
+        if out.len() + 1 + name.len() > wire::MAX_PACKET {
+            break;
+        }
+        out.push(name.len() as u8);
+        out.extend_from_slice(name);
+    }
+    out
+}
+
+// None for anything that isn't a reply; a name cut off partway ends the list
+pub fn parse_reply(bytes: &[u8], addr: SocketAddr) -> Option<Found> {
+    if bytes.len() < REPLY_HEADER || bytes[..4] != MAGIC[..] || bytes[5] != REPLY {
+        return None;
+    }
+    let files = u16::from_be_bytes([bytes[6], bytes[7]]);
+    let mut names = Vec::new();
+    let mut rest = &bytes[REPLY_HEADER..];
+    while let Some((&len, after)) = rest.split_first() {
+        let Some(name) = after.get(..len as usize) else {
+            break;
+        };
+        names.push(String::from_utf8_lossy(name).into_owned());
+        rest = &after[len as usize..];
+    }
+    Some(Found { addr, files, names })
+}
+
+// Broadcast to `target` and collect every answer that comes within `wait`,
+// each server once, in the order they answered
+pub fn broadcast(target: &str, wait: Duration) -> io::Result<Vec<Found>> {
+    let sock = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    sock.set_broadcast(true)?;
+    sock.send_to(&request(), target)?;
+    let until = Instant::now() + wait;
+    let mut found: Vec<Found> = Vec::new();
+    let mut buf = [0; wire::MAX_PACKET];
+    loop {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(found);
+        }
+        sock.set_read_timeout(Some(left))?;
+        match sock.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                let server = parse_reply(&buf[..len], from);
+                if let Some(server) = server.filter(|s| found.iter().all(|f| f.addr != s.addr)) {
+                    found.push(server);
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(found)
+            }
+            // Windows's word that something along the way turned one away
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// List what answered --discover and return the server to use
+pub fn choose(cli: &Cli, target: &str) -> Result<String, ClientError> {
+    let found = broadcast(target, cli.discover_wait)?;
+    if found.is_empty() {
+        return Err(ClientError::NothingDiscovered {
+            target: target.to_string(),
+            wait: cli.discover_wait,
+        });
+    }
+    // Listed whatever the level, so there's something to choose from
+    for (n, server) in found.iter().enumerate() {
+        log!(Warn, "{}", describe(n + 1, server));
+    }
+    if cli.first || found.len() == 1 {
+        return Ok(found[0].addr.to_string());
+    }
+    Ok(found[pick(&mut io::stdin().lock(), found.len())? - 1]
+        .addr
+        .to_string())
+}
+
+fn describe(n: usize, server: &Found) -> String {
+    let mut names = server.names.join(", ");
+    let more = usize::from(server.files).saturating_sub(server.names.len());
+    if more > 0 {
+        names.push_str(&tr!("discover-more", count = more));
+    }
+    tr!(
+        "discover-found",
+        n = n,
+        addr = server.addr,
+        files = server.files,
+        names = names
+    )
+}
+
+// Ask for a number from 1 to `count` until one comes
+fn pick(input: &mut impl BufRead, count: usize) -> io::Result<usize> {
+    loop {
+        eprint!("{}", tr!("discover-pick", count = count));
+        io::stderr().flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            let message = i18n::text("discover-no-choice");
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message));
+        }
+        match line.trim().parse() {
+            Ok(n) if (1..=count).contains(&n) => return Ok(n),
+            _ => log!(
+                Warn,
+                "{}",
+                tr!("discover-not-a-choice", choice = line.trim())
+            ),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// Echo probes (--echo-interval): a small datagram a compatible server sends
+// straight back, so the client can measure round trips on a transfer that
+// otherwise only flows one way. The course server doesn't know them and takes
+// any datagram as a request to start over, so they're off unless asked for.
+//
+// Layout, big-endian, 20 bytes, reflected unchanged:
+//
+//   "SFSE"  version  3 zero bytes  session ID (8)  sequence number (4)
+//
+// A reply is only taken as one if it has our session ID, so a data packet
+// that happens to start with the magic isn't swallowed.
+//
+// Round trips feed the smoothed RTT and variance of RFC 6298, and the
+// retransmission timeout it gives sets a floor under --stall-timeout: a
+// file isn't called stalled faster than the path can answer.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+pub const MAGIC: &[u8; 4] = b"SFSE";
+pub const VERSION: u8 = 1;
+pub const LEN: usize = 20;
+
+// Probes not answered after this many more have gone out are taken as lost
+const MAX_OUTSTANDING: u32 = 64;
+
+pub fn probe(session_id: u64, sequence: u32) -> [u8; LEN] {
+    let mut bytes = [0; LEN];
+    bytes[..4].copy_from_slice(MAGIC);
+    bytes[4] = VERSION;
+    bytes[8..16].copy_from_slice(&session_id.to_be_bytes());
+    bytes[16..].copy_from_slice(&sequence.to_be_bytes());
+    bytes
+}
+
+// The sequence number of a reflected probe from this session
+pub fn reply(bytes: &[u8], session_id: u64) -> Option<u32> {
+    let ours = bytes.len() == LEN
+        && &bytes[..4] == MAGIC
+        && bytes[4] == VERSION
+        && bytes[8..16] == session_id.to_be_bytes();
+    ours.then(|| u32::from_be_bytes(bytes[16..].try_into().unwrap()))
+}
+
+// RFC 6298's smoothed round-trip time and its variation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Estimator {
+    pub srtt: Option<Duration>,
+    pub rttvar: Duration,
+}
+
+impl Estimator {
+    pub fn sample(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+    }
+
+    // How long an answer may reasonably take, once there's a sample
+    pub fn rto(&self) -> Option<Duration> {
+        // The clock granularity term; what we read is this precise
+        const G: Duration = Duration::from_millis(1);
+        self.srtt.map(|srtt| srtt + G.max(self.rttvar * 4))
+    }
+}
+
+// What the probes found, for the report
+#[derive(Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub probes: u32,
+    pub answered: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub max: Duration,
+    // Mean difference between consecutive round trips
+    pub jitter: Duration,
+}
+
+pub struct Echo {
+    session_id: u64,
+    interval: Duration,
+    next: Instant,
+    sequence: u32,
+    outstanding: HashMap<u32, Instant>,
+    samples: Vec<Duration>,
+    estimator: Estimator,
+}
+
+impl Echo {
+    pub fn new(session_id: u64, interval: Duration, now: Instant) -> Self {
+        Echo {
+            session_id,
+            interval,
+            next: now,
+            sequence: 0,
+            outstanding: HashMap::new(),
+            samples: Vec::new(),
+            estimator: Estimator::default(),
+        }
+    }
+
+    // The next probe, if one is due
+    pub fn due(&mut self, now: Instant) -> Option<[u8; LEN]> {
+        if now < self.next {
+            return None;
+        }
+        self.next = now + self.interval;
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.outstanding
+            .retain(|&old, _| sequence.wrapping_sub(old) < MAX_OUTSTANDING);
+        self.outstanding.insert(sequence, now);
+        Some(probe(self.session_id, sequence))
+    }
+
+    // Whether `bytes` was a reply to one of our probes (a late or repeated
+    // reply still counts as one, but isn't timed)
+    pub fn answer(&mut self, bytes: &[u8], now: Instant) -> bool {
+        let Some(sequence) = reply(bytes, self.session_id) else {
+            return false;
+        };
+        if let Some(sent) = self.outstanding.remove(&sequence) {
+            let rtt = now - sent;
+            self.samples.push(rtt);
+            self.estimator.sample(rtt);
+        }
+        true
+    }
+
+    pub fn rto(&self) -> Option<Duration> {
+        self.estimator.rto()
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let jitter = self
+            .samples
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]))
+            .sum::<Duration>()
+            .checked_div(self.samples.len().saturating_sub(1) as u32)
+            .unwrap_or_default();
+        Stats {
+            probes: self.sequence,
+            answered: self.samples.len(),
+            min: sorted.first().copied().unwrap_or_default(),
+            median: sorted.get(sorted.len() / 2).copied().unwrap_or_default(),
+            max: sorted.last().copied().unwrap_or_default(),
+            jitter,
+        }
+    }
+}
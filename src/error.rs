@@ -0,0 +1,310 @@
+// Everything that can end a run early, each with a stable code and exit status.
+
+use crate::{audit, hooks, i18n, i18n::tr, interrupt, limits, packet::PacketParseError, units};
+use std::{error::Error, fmt, path::PathBuf, time::Duration};
+
+#[derive(Debug)]
+pub enum ClientError {
+    IoError(std::io::Error),
+    PacketParseError(PacketParseError),
+    // The server never answered the hello, however many times it went out
+    ServerUnreachable {
+        timeout: Duration,
+        requests: u32,
+    },
+    // No server answered --discover
+    NothingDiscovered {
+        target: String,
+        wait: Duration,
+    },
+    // Datagrams arrived, but not one packet the session could use
+    FirstPacketTimeout {
+        timeout: Duration,
+        datagrams: u64,
+    },
+    // A file stopped getting packets partway through
+    FileStalled {
+        file_id: u8,
+        stalled_for: Duration,
+        received: usize,
+        expected: Option<u32>,
+    },
+    // Nothing arrived for --idle-after with files incomplete, and --on-idle
+    // said to give up
+    TransferIdle {
+        idle_for: Duration,
+        incomplete: usize,
+    },
+    // --session-timeout ran out
+    SessionTimeout {
+        timeout: Duration,
+        packets: u64,
+    },
+    // The run wasn't over, files written and all, by the caller's deadline
+    DeadlinePassed,
+    TooMuchData {
+        limit: u64,
+    },
+    TooManyFiles {
+        limit: usize,
+    },
+    // A file was bigger than --max-file-size
+    FileTooLarge {
+        file_id: u8,
+        limit: u64,
+    },
+    BadFileName {
+        file_id: u8,
+        problem: limits::NameProblem,
+    },
+    // Two headers named a file differently, with --renamed-headers error
+    RenamedFile {
+        file_id: u8,
+        first: String,
+        second: String,
+    },
+    // Some files were complete but couldn't be written
+    PartialWrite {
+        written: usize,
+        failed: usize,
+    },
+    // Files were written, but not with the SHA-256s their checksum packets
+    // gave
+    ChecksumMismatch {
+        files: usize,
+    },
+    // Ctrl-C ended the session; the files not complete were kept as .partial
+    Interrupted {
+        written: usize,
+        partial: usize,
+    },
+    // The files written aren't the ones --expect listed
+    ManifestMismatch {
+        problems: usize,
+    },
+    // The files' headers said more than there's room for in `dir`
+    InsufficientSpace {
+        dir: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+    // verify-audit found the chain broken
+    AuditBroken(audit::Broken),
+    // A `ClientBuilder` was given options that can't work together
+    BadOptions {
+        problems: Vec<String>,
+    },
+    // One of the embedding program's hooks turned the transfer down
+    Hook {
+        hook: hooks::Hook,
+        error: hooks::HookError,
+    },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ClientError::IoError(e) => e.to_string(),
+            ClientError::PacketParseError(e) => tr!("error-parse", message = e),
+            ClientError::ServerUnreachable { timeout, requests } => tr!(
+                "error-server-unreachable",
+                timeout = units::format_duration(*timeout),
+                requests = requests
+            ),
+            ClientError::NothingDiscovered { target, wait } => tr!(
+                "error-nothing-discovered",
+                target = target,
+                wait = units::format_duration(*wait)
+            ),
+            ClientError::FirstPacketTimeout { timeout, datagrams } => tr!(
+                "error-first-packet-timeout",
+                timeout = units::format_duration(*timeout),
+                datagrams = datagrams
+            ),
+            ClientError::FileStalled {
+                file_id,
+                stalled_for,
+                received,
+                expected,
+            } => tr!(
+                "error-file-stalled",
+                file_id = file_id,
+                stalled = units::format_duration(*stalled_for),
+                received = received,
+                expected = expected.map_or("?".to_string(), |count| count.to_string())
+            ),
+            ClientError::TransferIdle {
+                idle_for,
+                incomplete,
+            } => tr!(
+                "error-transfer-idle",
+                idle = units::format_duration(*idle_for),
+                files = incomplete
+            ),
+            ClientError::SessionTimeout { timeout, packets } => tr!(
+                "error-session-timeout",
+                timeout = units::format_duration(*timeout),
+                packets = packets
+            ),
+            ClientError::DeadlinePassed => i18n::text("error-deadline").to_string(),
+            ClientError::TooMuchData { limit } => {
+                tr!("error-too-much-data", limit = units::format_size(*limit))
+            }
+            ClientError::TooManyFiles { limit } => tr!("error-too-many-files", limit = limit),
+            ClientError::FileTooLarge { file_id, limit } => tr!(
+                "error-file-too-large",
+                file_id = file_id,
+                limit = units::format_size(*limit)
+            ),
+            ClientError::PartialWrite { written, failed } => {
+                tr!("error-partial-write", written = written, failed = failed)
+            }
+            ClientError::Interrupted { written, partial } => {
+                tr!("error-interrupted", written = written, partial = partial)
+            }
+            ClientError::ChecksumMismatch { files } => {
+                tr!("error-checksum-mismatch", files = files)
+            }
+            ClientError::ManifestMismatch { problems } => {
+                tr!("error-manifest-mismatch", problems = problems)
+            }
+            ClientError::InsufficientSpace {
+                dir,
+                needed,
+                available,
+            } => tr!(
+                "error-insufficient-space",
+                dir = dir.display(),
+                needed = units::format_size(*needed),
+                available = units::format_size(*available)
+            ),
+            ClientError::AuditBroken(broken) => tr!(
+                "error-audit-broken",
+                line = broken.line,
+                problem = i18n::audit_problem(&broken.problem)
+            ),
+            ClientError::BadOptions { problems } => {
+                tr!("error-bad-options", problems = problems.join("\n"))
+            }
+            ClientError::Hook { hook, error } => tr!("error-hook", hook = hook, error = error),
+            ClientError::BadFileName { file_id, problem } => tr!(
+                "error-bad-file-name",
+                file_id = file_id,
+                problem = i18n::name_problem(problem)
+            ),
+            ClientError::RenamedFile {
+                file_id,
+                first,
+                second,
+            } => tr!(
+                "error-renamed-file",
+                file_id = file_id,
+                first = first,
+                second = second
+            ),
+        };
+        f.write_str(&message)
+    }
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClientError::IoError(e) => Some(e),
+            ClientError::PacketParseError(e) => Some(e),
+            ClientError::Hook { error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+impl ClientError {
+    // Stable name for scripts and the grade summary
+    pub fn code(&self) -> &'static str {
+        match self {
+            ClientError::IoError(_) => "io",
+            ClientError::PacketParseError(_) => "malformed-packet",
+            ClientError::ServerUnreachable { .. } => "server-unreachable",
+            ClientError::NothingDiscovered { .. } => "nothing-discovered",
+            ClientError::FirstPacketTimeout { .. } => "first-packet-timeout",
+            ClientError::FileStalled { .. } => "file-stalled",
+            ClientError::TransferIdle { .. } => "transfer-idle",
+            ClientError::SessionTimeout { .. } => "session-timeout",
+            ClientError::DeadlinePassed => "deadline",
+            ClientError::TooMuchData { .. } => "too-much-data",
+            ClientError::TooManyFiles { .. } => "too-many-files",
+            ClientError::FileTooLarge { .. } => "file-too-large",
+            ClientError::BadFileName { .. } => "bad-file-name",
+            ClientError::RenamedFile { .. } => "renamed-file",
+            ClientError::PartialWrite { .. } => "partial-write",
+            ClientError::ChecksumMismatch { .. } => "checksum-mismatch",
+            ClientError::Interrupted { .. } => "interrupted",
+            ClientError::ManifestMismatch { .. } => "manifest-mismatch",
+            ClientError::InsufficientSpace { .. } => "insufficient-space",
+            ClientError::AuditBroken(_) => "audit-broken",
+            ClientError::BadOptions { .. } => "bad-options",
+            ClientError::Hook { .. } => "hook",
+        }
+    }
+
+    // Each timer gets its own exit status, as do a partial write, --expect,
+    // a broken audit log, a checksum mismatch, too many datagrams that don't
+    // parse, and trouble reading or writing (the disk, too little of it, or
+    // the network); 2 is bad options, and no server answering --discover is
+    // as good as one not answering the hello. Ctrl-C exits the way a shell
+    // reports SIGINT.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ClientError::ServerUnreachable { .. } | ClientError::NothingDiscovered { .. } => 3,
+            ClientError::FirstPacketTimeout { .. } => 4,
+            ClientError::FileStalled { .. } => 5,
+            ClientError::SessionTimeout { .. } => 6,
+            ClientError::DeadlinePassed => 10,
+            ClientError::ManifestMismatch { .. } => 7,
+            ClientError::PartialWrite { .. } => 8,
+            ClientError::AuditBroken(_) => 9,
+            ClientError::ChecksumMismatch { .. } => 11,
+            ClientError::TransferIdle { .. } => 12,
+            ClientError::BadOptions { .. } => 2,
+            ClientError::PacketParseError(_) => 13,
+            ClientError::IoError(_) | ClientError::InsufficientSpace { .. } => 14,
+            ClientError::Interrupted { .. } => interrupt::EXIT_CODE,
+            _ => 1,
+        }
+    }
+}
+
+impl From<limits::Rejected> for ClientError {
+    fn from(rejected: limits::Rejected) -> Self {
+        match rejected {
+            limits::Rejected::TooManyFiles { limit } => ClientError::TooManyFiles { limit },
+            limits::Rejected::BadName { file_id, problem } => {
+                ClientError::BadFileName { file_id, problem }
+            }
+            limits::Rejected::Renamed {
+                file_id,
+                first,
+                second,
+            } => ClientError::RenamedFile {
+                file_id,
+                first,
+                second,
+            },
+            limits::Rejected::FileTooLarge { file_id, limit } => {
+                ClientError::FileTooLarge { file_id, limit }
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::IoError(e)
+    }
+}
+
+impl From<PacketParseError> for ClientError {
+    fn from(e: PacketParseError) -> Self {
+        Self::PacketParseError(e)
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{audit::Chain, fsio, i18n::tr, idle::Incomplete, units, wire};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, HashSet},
+    ffi::OsStr,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// Something worth telling the user (or a log) about while a session runs
+pub enum Event<'a> {
+    SessionStarted {
+        peer: SocketAddr,
+    },
+    Header {
+        file_id: u8,
+        file_name: &'a OsStr,
+        // From an extended header
+        size: Option<u64>,
+    },
+    Data {
+        file_id: u8,
+        packet_number: u32,
+        len: usize,
+        is_last_packet: bool,
+    },
+    // What a file's SHA-256 should come to
+    Checksum {
+        file_id: u8,
+    },
+    Watchdog {
+        rate: u64,
+        re_requested: bool,
+    },
+    // Nothing arrived for --idle-after; `action` is what was done about it
+    Idle {
+        idle_for: Duration,
+        files: &'a [Incomplete],
+        action: &'static str,
+    },
+    // Retransmission requests went out for this many missing packets
+    Nack {
+        packets: usize,
+    },
+    // The current server timed out and the request went to the next one
+    Failover {
+        server: &'a str,
+        reason: &'a str,
+    },
+    SessionFinished {
+        packets: u64,
+        bytes: u64,
+        // On the monotonic clock, so the rate survives clock jumps
+        elapsed: Duration,
+    },
+}
+
+// Somewhere progress events go
+pub trait ProgressSink {
+    fn event(&mut self, event: &Event) -> io::Result<()>;
+}
+
+// How often the interactive reporter lists the files still arriving
+const PROGRESS_EVERY: Duration = Duration::from_secs(1);
+
+// The interactive reporter: every PROGRESS_EVERY, a line for each file still
+// arriving, and a line for each file as it's done. --quiet leaves it out.
+#[derive(Default)]
+pub struct Terminal {
+    files: BTreeMap<u8, FileProgress>,
+    last_listed: Option<Instant>,
+    // Standard output is taken, by --stdout
+    to_stderr: bool,
+}
+
+#[derive(Default)]
+struct FileProgress {
+    name: Option<String>,
+    // Packet numbers seen, so a duplicate isn't counted twice
+    received: HashSet<u32>,
+    // Datagram bytes, as in the session report
+    bytes: u64,
+    // Known once the last packet is in
+    expected: Option<u32>,
+    done: bool,
+}
+
+impl FileProgress {
+    fn is_complete(&self) -> bool {
+        self.name.is_some() && self.expected == Some(self.received.len() as u32)
+    }
+
+    fn line(&self, file_id: u8) -> String {
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => tr!("progress-unnamed", file_id = file_id),
+        };
+        let received = self.received.len();
+        let bytes = units::format_size_rounded(self.bytes);
+        match self.expected {
+            _ if self.done => tr!(
+                "progress-file-done",
+                name = name,
+                received = received,
+                bytes = bytes
+            ),
+            Some(expected) => tr!(
+                "progress-file",
+                name = name,
+                received = received,
+                expected = expected,
+                percent = received as u64 * 100 / expected.max(1) as u64,
+                bytes = bytes
+            ),
+            None => tr!(
+                "progress-file-unknown",
+                name = name,
+                received = received,
+                bytes = bytes
+            ),
+        }
+    }
+}
+
+impl Terminal {
+    pub fn on_stderr() -> Self {
+        Terminal {
+            to_stderr: true,
+            ..Terminal::default()
+        }
+    }
+
+    // Bring a file's progress up to date, saying so if that finished it
+    fn record(&mut self, event: &Event, out: &mut impl Write) -> io::Result<()> {
+        let (file_id, file) = match *event {
+            Event::Header {
+                file_id,
+                file_name,
+                size,
+            } => {
+                let file = self.files.entry(file_id).or_default();
+                file.name = Some(file_name.to_string_lossy().into_owned());
+                // Known before the last packet, if the header said how big
+                // (none at all for an empty file)
+                if let Some(size) = size.filter(|_| file.expected.is_none()) {
+                    file.expected = Some(size.div_ceil(wire::MAX_PAYLOAD as u64) as u32);
+                }
+                (file_id, file)
+            }
+            Event::Data {
+                file_id,
+                packet_number,
+                len,
+                is_last_packet,
+            } => {
+                let file = self.files.entry(file_id).or_default();
+                if file.received.insert(packet_number) {
+                    file.bytes += len as u64;
+                }
+                if is_last_packet {
+                    file.expected = Some(packet_number + 1);
+                }
+                (file_id, file)
+            }
+            _ => return Ok(()),
+        };
+        if !file.done && file.is_complete() {
+            file.done = true;
+            writeln!(out, "{}", file.line(file_id))?;
+        }
+        Ok(())
+    }
+
+    // The files still arriving, if it's time
+    fn list(&mut self, out: &mut impl Write, now: Instant) -> io::Result<()> {
+        if self.last_listed.is_some_and(|at| now - at < PROGRESS_EVERY) {
+            return Ok(());
+        }
+        self.last_listed = Some(now);
+        for (&file_id, file) in self.files.iter().filter(|(_, file)| !file.done) {
+            writeln!(out, "{}", file.line(file_id))?;
+        }
+        Ok(())
+    }
+}
+
+impl ProgressSink for Terminal {
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        let mut stdout: Box<dyn Write> = match self.to_stderr {
+            true => Box::new(io::stderr()),
+            false => Box::new(io::stdout()),
+        };
+        match event {
+            Event::SessionStarted { peer } => {
+                // Each --daemon session starts from nothing
+                *self = Terminal {
+                    last_listed: Some(Instant::now()),
+                    to_stderr: self.to_stderr,
+                    ..Terminal::default()
+                };
+                writeln!(stdout, "{}", tr!("receiving-from", peer = peer))?
+            }
+            Event::Header { .. } | Event::Data { .. } => {
+                self.record(event, &mut stdout)?;
+                self.list(&mut stdout, Instant::now())?;
+            }
+            Event::Checksum { .. }
+            | Event::Watchdog { .. }
+            | Event::Idle { .. }
+            | Event::Nack { .. }
+            | Event::SessionFinished { .. } => {}
+            Event::Failover { server, .. } => {
+                writeln!(stdout, "{}", tr!("failing-over", server = server))?
+            }
+        }
+        stdout.flush()
+    }
+}
+
+// --json: events as JSON objects on standard output, one a line. Packets
+// aren't listed one by one; the files and the report at the end are, by the
+// session's finish (see `file-completed` and `finished` in client.rs).
+#[derive(Default)]
+pub struct JsonLines {
+    // Files whose `file-started` has gone out, as headers can repeat
+    started: HashSet<u8>,
+}
+
+// One line of --json output
+pub fn json_line(mut value: serde_json::Value) {
+    if let Some(object) = value.as_object_mut() {
+        object.insert("time_ms".into(), (unix_millis() as u64).into());
+    }
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{value}").and_then(|_| stdout.flush());
+}
+
+impl ProgressSink for JsonLines {
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        let value = match *event {
+            Event::SessionStarted { peer } => {
+                self.started.clear();
+                json!({ "event": "session-started", "peer": peer.to_string() })
+            }
+            Event::Header {
+                file_id,
+                file_name,
+                size,
+            } if self.started.insert(file_id) => json!({
+                "event": "file-started",
+                "file_id": file_id,
+                "name": file_name.to_string_lossy(),
+                "size": size,
+            }),
+            Event::Failover { server, reason } => {
+                json!({ "event": "failover", "server": server, "reason": reason })
+            }
+            Event::Idle {
+                idle_for,
+                files,
+                action,
+            } => {
+                let files: Vec<_> = (files.iter())
+                    .map(|file| {
+                        json!({
+                            "file_id": file.file_id,
+                            "name": file.name,
+                            "received": file.received,
+                            "expected": file.expected,
+                            "missing": units::count(&file.missing),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "event": "idle",
+                    "idle_ms": idle_for.as_millis() as u64,
+                    "files": files,
+                    "action": action,
+                })
+            }
+            _ => return Ok(()),
+        };
+        json_line(value);
+        Ok(())
+    }
+}
+
+// Append-only archive of events, one line each prefixed with the wall-clock
+// time in milliseconds since the Unix epoch and ending with `mono_ms=`, the
+// milliseconds since the file was opened by the monotonic clock. Wall-clock
+// times can jump (NTP, a laptop waking up); `mono_ms` always moves forward,
+// so intervals and rates should come from it.
+pub struct EventsFile {
+    file: File,
+    opened: Instant,
+}
+
+impl EventsFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = fsio::open(path, OpenOptions::new().create(true).append(true))?;
+        Ok(EventsFile {
+            file,
+            opened: Instant::now(),
+        })
+    }
+}
+
+// An event as the events file and audit log put it
+fn describe(event: &Event) -> String {
+    match event {
+        Event::SessionStarted { peer } => format!("session-started peer={peer}"),
+        Event::Header {
+            file_id,
+            file_name,
+            size: None,
+        } => format!("header file_id={file_id} name={:?}", file_name),
+        Event::Header {
+            file_id,
+            file_name,
+            size: Some(size),
+        } => format!("header file_id={file_id} name={:?} size={size}", file_name),
+        Event::Data {
+            file_id,
+            packet_number,
+            len,
+            is_last_packet,
+        } => {
+            format!("data file_id={file_id} packet={packet_number} len={len} last={is_last_packet}")
+        }
+        Event::Checksum { file_id } => format!("checksum file_id={file_id}"),
+        Event::Watchdog { rate, re_requested } => {
+            format!("watchdog rate={rate} re_requested={re_requested}")
+        }
+        Event::Idle {
+            idle_for,
+            files,
+            action,
+        } => format!(
+            "idle idle_ms={} incomplete={} action={action}",
+            idle_for.as_millis(),
+            files.len()
+        ),
+        Event::Nack { packets } => format!("nack packets={packets}"),
+        Event::Failover { server, reason } => {
+            format!("failover server={server} reason={reason}")
+        }
+        Event::SessionFinished {
+            packets,
+            bytes,
+            elapsed,
+        } => format!(
+            "session-finished packets={packets} bytes={bytes} elapsed_ms={} rate={}",
+            elapsed.as_millis(),
+            // Not 18446744073709551615 for a session over in no time at all
+            match elapsed.is_zero() {
+                true => 0,
+                false => (*bytes as f64 / elapsed.as_secs_f64()) as u64,
+            }
+        ),
+    }
+}
+
+pub fn unix_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis())
+}
+
+impl ProgressSink for EventsFile {
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        let mono_ms = self.opened.elapsed().as_millis();
+        writeln!(
+            self.file,
+            "{} {} mono_ms={mono_ms}",
+            unix_millis(),
+            describe(event)
+        )
+    }
+}
+
+// The --audit log: events as hash-chained entries (see audit.rs)
+// Longer than any one entry
+const AUDIT_TAIL: u64 = 8192;
+
+pub struct AuditFile {
+    file: File,
+    chain: Chain,
+}
+
+impl AuditFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = fsio::open(
+            path,
+            OpenOptions::new().read(true).create(true).append(true),
+        )?;
+        // Only the last entry matters, so a long log isn't read from the start
+        let len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(len.saturating_sub(AUDIT_TAIL)))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        let tail = String::from_utf8_lossy(&tail);
+        let chain = match tail.lines().last() {
+            Some(last) => Chain::after(last).ok_or_else(|| {
+                let message = tr!("audit-bad-tail", path = path.display());
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            })?,
+            None => Chain::default(),
+        };
+        Ok(AuditFile { file, chain })
+    }
+}
+
+impl ProgressSink for AuditFile {
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        let entry = self.chain.entry(unix_millis(), &describe(event));
+        writeln!(self.file, "{entry}")
+    }
+}
+
+// Fans every event out to all configured sinks
+#[derive(Default)]
+pub struct Progress {
+    sinks: Vec<Box<dyn ProgressSink>>,
+}
+
+impl Progress {
+    pub fn add(&mut self, sink: impl ProgressSink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    pub fn emit(&mut self, event: Event) -> io::Result<()> {
+        for sink in &mut self.sinks {
+            sink.event(&event)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1198 @@
+// Where packets go until their files are written: each file's name, its
+// packet count once the last packet says, and the payloads so far. A file
+// with a checksum packet is checked against it as it's written.
+
+use crate::{
+    assembly::{Backend, Packets},
+    cli::{OnConflict, WriteOrder},
+    compression::{self, Chunks, Compression},
+    error::ClientError,
+    fsio,
+    hash::{self, HashAlgorithm},
+    i18n::{self, tr},
+    limits::Limits,
+    log::log,
+    owner::Owner,
+    packet::{Checksum, Data, Header, Metadata, Packet, Payload},
+    resume,
+    sink::FileSink,
+    spill::{Spill, Stored},
+    tempdir::{self, TempDirs},
+    text::{self, Newlines},
+    units, wire,
+    writers::Writers,
+};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet}, // HashMap for storing file packets
+    ffi::{OsStr, OsString},          // Storing OS-compatible filenames
+    fs::{File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+// File name, expected packet count, and received packets for one file
+type PacketGroup = (Option<Arc<OsStr>>, Option<u32>, Packets);
+
+type Digest = [u8; wire::DIGEST_LEN];
+
+// How many times a file is written before giving up on it, if the errors
+// look like they might clear
+pub const WRITE_ATTEMPTS: u32 = 3;
+
+// What became of the complete files at the end of a session
+#[derive(Default)]
+pub struct Finalized {
+    pub written: Vec<(u8, PathBuf)>,
+    // Already there, and left alone (--on-conflict skip)
+    pub skipped: Vec<(u8, PathBuf)>,
+    pub failed: Vec<(u8, io::Error)>,
+    // Written, but not what their checksum packets said (also in `written`)
+    pub mismatched: Vec<(u8, PathBuf)>,
+}
+
+impl Finalized {
+    // Put down what write_file did with a file
+    fn record(&mut self, file_id: u8, result: io::Result<Option<Written>>) {
+        match result {
+            Ok(Some(Written::To(path))) => self.written.push((file_id, path)),
+            Ok(Some(Written::Skipped(path))) => self.skipped.push((file_id, path)),
+            Ok(Some(Written::Mismatched(path))) => {
+                self.mismatched.push((file_id, path.clone()));
+                self.written.push((file_id, path));
+            }
+            Ok(None) => {}
+            Err(e) => self.failed.push((file_id, e)),
+        }
+    }
+
+    // The files written, or an error if any couldn't be or came out wrong
+    pub fn into_result(self) -> Result<Vec<(u8, PathBuf)>, ClientError> {
+        if !self.failed.is_empty() {
+            Err(ClientError::PartialWrite {
+                written: self.written.len(),
+                failed: self.failed.len(),
+            })
+        } else if !self.mismatched.is_empty() {
+            Err(ClientError::ChecksumMismatch {
+                files: self.mismatched.len(),
+            })
+        } else {
+            Ok(self.written)
+        }
+    }
+}
+
+// Where write_file would put a file, by --on-conflict
+#[derive(Debug, PartialEq, Eq)]
+pub enum Placement {
+    // Nothing there yet
+    New(PathBuf),
+    Overwrite(PathBuf),
+    // What's there stays, and the new one isn't written (skip)
+    Kept(PathBuf),
+    // Written at `path`, leaving `existing` be; with `compare` (skip-
+    // identical), not kept if it's the same as `existing`
+    Beside {
+        path: PathBuf,
+        existing: PathBuf,
+        compare: bool,
+    },
+    // Something's there and --on-conflict error won't have it
+    Refused(PathBuf),
+}
+
+// What's in one of the files write_partial_files kept, listed in
+// partial.json
+#[derive(Debug, Serialize)]
+pub struct PartialFile {
+    pub id: u8,
+    pub name: String,
+    // Where its packets went, beside partial.json
+    pub partial: String,
+    pub received: usize,
+    // Unknown until the last packet arrives
+    pub expected: Option<u32>,
+    // Without the last packet, only the gaps below the highest one seen. In
+    // partial.json as "0-4", "7", the way the reports give them.
+    #[serde(serialize_with = "spans")]
+    pub missing: Vec<Range<u32>>,
+}
+
+fn spans<S: serde::Serializer>(ranges: &[Range<u32>], out: S) -> Result<S::Ok, S::Error> {
+    out.collect_seq(
+        (ranges.iter()).map(|range| units::format_spans(std::slice::from_ref(range), 1)),
+    )
+}
+
+pub const PARTIAL_MANIFEST: &str = "partial.json";
+
+// What read_range found
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRead {
+    // The bytes asked for, cut short where the file ends
+    Bytes(Vec<u8>),
+    // These packets have to arrive first
+    NotYetAvailable { missing: Vec<u32> },
+    // The range starts at or after the end of the file, which is `size`
+    // bytes long (or, before the last packet is here, the most a file can be)
+    PastEnd { size: u64 },
+}
+
+// Packet n starts n of these into its file
+const GRID: u64 = wire::MAX_PAYLOAD as u64;
+
+// The most packets set aside ahead of time for a file an extended header
+// says is big, so a header alone can't take much memory
+const MAX_RESERVE: u64 = 1 << 14;
+
+// What write_file did with a file
+#[derive(Debug, PartialEq, Eq)]
+pub enum Written {
+    To(PathBuf),
+    // Something was already there and --on-conflict skip kept it
+    Skipped(PathBuf),
+    // Written, but its SHA-256 isn't the one its checksum packet gave
+    Mismatched(PathBuf),
+}
+
+// A complete file, put together in memory rather than written out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceivedFile {
+    pub id: u8,
+    pub name: OsString,
+    // As sent, without --normalize-text
+    pub data: Vec<u8>,
+}
+
+// `path` with the first free numeric suffix before its extension:
+// a.txt, then a.1.txt, a.2.txt, ...
+pub fn free_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    (1u64..)
+        .map(|n| {
+            let mut name = OsString::from(stem);
+            name.push(format!(".{n}"));
+            if let Some(extension) = path.extension() {
+                name.push(".");
+                name.push(extension);
+            }
+            path.with_file_name(name)
+        })
+        .find(|candidate| !fsio::exists(candidate))
+        .expect("ran out of suffixes")
+}
+
+fn too_large(file_id: u8, limit: u64) -> io::Error {
+    let limit = units::format_size(limit);
+    let message = tr!("file-too-large", file_id = file_id, limit = limit);
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// `path` with the first free version after it: a.txt.v2, a.txt.v3, ...
+pub fn version_name(path: &Path) -> PathBuf {
+    (2u64..)
+        .map(|n| {
+            let mut name = OsString::from(path.as_os_str());
+            name.push(format!(".v{n}"));
+            PathBuf::from(name)
+        })
+        .find(|candidate| !fsio::exists(candidate))
+        .expect("ran out of versions")
+}
+
+// What an extended header said about a file, given to the copy at `path`.
+// Set-user-ID and the like are left off; only the permission bits count.
+fn give_metadata(metadata: &Metadata, file: &File, path: &Path) -> io::Result<()> {
+    if let Some(time) = metadata.modified_time() {
+        fsio::set_modified(file, path, time)?;
+    }
+    if let Some(mode) = metadata.mode {
+        fsio::set_mode(path, mode & 0o777)?;
+    }
+    Ok(())
+}
+
+// Payload bytes a file has in memory
+fn bytes_held(packets: &Packets) -> u64 {
+    (packets.numbers())
+        .map(|number| packets.get(number).map_or(0, <[u8]>::len) as u64)
+        .sum()
+}
+
+fn identical(a: &Path, b: &Path) -> io::Result<bool> {
+    Ok(fsio::read(a)? == fsio::read(b)?)
+}
+
+// Manage and store files into disk
+pub struct FileManager {
+    files: HashMap<u8, PacketGroup>, // Mpas file ID to PacketGroup
+    backend: Backend,                // How payloads are kept
+    spill: Option<Spill>,            // Keep payloads on disk instead
+    max_memory: Option<u64>,         // Most payload bytes kept in memory (--max-memory)
+    max_file_size: Option<u64>,      // Most bytes a file is written with (--max-file-size)
+    in_memory: u64,                  // Payload bytes kept in memory now
+    over_memory: bool,               // Warned that nothing more can go to disk
+    temp: TempDirs,                  // Where files live until they're done
+    conflict_dir: Option<String>,    // With timestamp-subdir, once it's named
+    duplicates: HashMap<u8, u64>,    // Data packets received more than once
+    checksums: HashMap<u8, Digest>,  // SHA-256s from checksum packets
+    metadata: HashMap<u8, Metadata>, // From extended headers, to give the files
+    pub owner: Option<Owner>,        // Who written files go to (--chown)
+    pub newlines: Option<Newlines>,  // Line endings for text files (--normalize-text)
+    pub on_conflict: OnConflict,     // Files already there (--on-conflict)
+    writers: Option<Writers>,        // Writing files as they're done (--write-threads)
+    handed_off: HashSet<u8>,         // Files given to `writers`
+    staging: Option<PathBuf>,        // Where files are written first (--temp-dir)
+}
+
+impl Default for FileManager {
+    fn default() -> Self {
+        FileManager {
+            files: HashMap::new(),
+            backend: Backend::Hashmap,
+            spill: None,
+            max_memory: None,
+            max_file_size: None,
+            in_memory: 0,
+            over_memory: false,
+            temp: TempDirs::default(),
+            conflict_dir: None,
+            duplicates: HashMap::new(),
+            checksums: HashMap::new(),
+            metadata: HashMap::new(),
+            owner: None,
+            newlines: None,
+            on_conflict: OnConflict::default(),
+            writers: None,
+            handed_off: HashSet::new(),
+            staging: None,
+        }
+    }
+}
+
+impl FileManager {
+    // Keep payloads the way `backend` does. Spill files go in `spill_dir`.
+    pub fn with_backend(backend: Backend, spill_dir: &Path) -> Self {
+        FileManager {
+            backend,
+            spill: (backend == Backend::Spill).then(|| Spill::new(spill_dir.to_path_buf())),
+            ..FileManager::default()
+        }
+    }
+
+    // Hold no more than `max` bytes of payloads in memory, moving files onto
+    // disk in `spill_dir` to stay under it (--max-memory)
+    pub fn with_max_memory(mut self, max: u64, spill_dir: &Path) -> Self {
+        self.max_memory = Some(max);
+        (self.spill).get_or_insert_with(|| Spill::on_demand(spill_dir.to_path_buf()));
+        self
+    }
+
+    // Keep to the limits on what's held and what's written. Spill files go
+    // in `spill_dir`.
+    pub fn with_limits(mut self, limits: &Limits, spill_dir: &Path) -> Self {
+        if let Some(max) = limits.max_buffered {
+            self = self.with_max_memory(max, spill_dir);
+        }
+        self.max_file_size = limits.max_file_size;
+        self
+    }
+
+    // Write each file into `dir` on one of `threads` threads as soon as it's
+    // complete (--write-threads), rather than in write_all_files
+    pub fn with_writers(mut self, threads: usize, dir: &Path) -> Self {
+        self.writers = Some(Writers::new(threads, dir));
+        self
+    }
+
+    // Write files in a scratch directory in `dir` before moving them into
+    // place, instead of in one next to where they're going (--temp-dir).
+    // On another filesystem, moving them means copying.
+    pub fn with_temp_dir(mut self, dir: &Path) -> Self {
+        self.staging = Some(dir.to_path_buf());
+        self
+    }
+
+    // What the file's extended header said, if it had one
+    pub fn metadata(&self, file_id: u8) -> Option<Metadata> {
+        self.metadata.get(&file_id).copied()
+    }
+
+    // How a file's data is compressed, as its header said
+    fn compression(&self, file_id: u8) -> Compression {
+        self.metadata
+            .get(&file_id)
+            .map_or(Compression::None, |m| m.compression)
+    }
+
+    // Whether the file has gone to --write-threads to be written
+    pub fn is_handed_off(&self, file_id: u8) -> bool {
+        self.handed_off.contains(&file_id)
+    }
+
+    // Check file have received all packets
+    pub fn is_complete(&self, file_id: u8) -> bool {
+        match self.files.get(&file_id) {
+            Some((name, Some(count), packets)) => {
+                packets.len() == *count as usize && name.is_some()
+            }
+            _ => false,
+        }
+    }
+
+    // Files with every data packet but no header
+    pub fn headerless(&self) -> Vec<u8> {
+        self.files
+            .iter()
+            .filter(|(_, (name, expected, packets))| {
+                name.is_none() && *expected == Some(packets.len() as u32)
+            })
+            .map(|(&file_id, _)| file_id)
+            .collect()
+    }
+
+    // Whether this data packet is already stored
+    pub fn has_packet(&self, file_id: u8, packet_number: u32) -> bool {
+        self.files
+            .get(&file_id)
+            .is_some_and(|(_, _, packets)| packets.contains(packet_number))
+    }
+
+    // The payload kept for a packet, if we have it and it's still at hand
+    // (not in a spilled file that's been finished)
+    pub fn payload(&self, file_id: u8, packet_number: u32) -> Option<&[u8]> {
+        let (_, _, packets) = self.files.get(&file_id)?;
+        if !packets.contains(packet_number) || self.handed_off.contains(&file_id) {
+            return None;
+        }
+        match &self.spill {
+            Some(spill) if spill.holds(file_id) => spill.read(file_id, packet_number),
+            _ => packets.get(packet_number),
+        }
+    }
+
+    // Count a duplicate that was dropped before it reached `store`
+    pub fn record_duplicate(&mut self, file_id: u8) {
+        *self.duplicates.entry(file_id).or_default() += 1;
+    }
+
+    // Throw away everything kept for a file
+    pub fn forget(&mut self, file_id: u8) {
+        if let Some((_, _, packets)) = self.files.remove(&file_id) {
+            self.in_memory = self.in_memory.saturating_sub(bytes_held(&packets));
+        }
+        self.duplicates.remove(&file_id);
+        self.checksums.remove(&file_id);
+        self.metadata.remove(&file_id);
+        if let Some(spill) = self.spill.as_mut() {
+            spill.discard(file_id);
+        }
+    }
+
+    // Give a file a name of our own when its header went missing
+    pub fn name_file(&mut self, file_id: u8, name: &str) {
+        if let Some(entry) = self.files.get_mut(&file_id) {
+            entry.0 = Some(Arc::from(OsStr::new(name)));
+        }
+    }
+
+    // Handle incoming packets and process them
+    pub fn process_packet(&mut self, packet: Packet) {
+        let file_id = packet.file_id();
+        let backend = self.backend;
+        let entry =
+            (self.files.entry(file_id)).or_insert_with(|| (None, None, Packets::new(backend)));
+        match Self::store(
+            entry,
+            backend,
+            self.spill.as_mut(),
+            &mut self.temp,
+            &mut self.checksums,
+            &mut self.metadata,
+            packet,
+        ) {
+            Some(held) => self.in_memory += held,
+            None => *self.duplicates.entry(file_id).or_default() += 1,
+        }
+        self.hand_off(file_id);
+        self.keep_within_memory();
+    }
+
+    // Like process_packet for many packets, looking a file up once per run
+    // of consecutive packets for it rather than once per packet
+    pub fn process_packets(&mut self, packets: impl Iterator<Item = Packet>) {
+        let mut packets = packets.peekable();
+        while let Some(packet) = packets.next() {
+            let file_id = packet.file_id();
+            let backend = self.backend;
+            let entry =
+                (self.files.entry(file_id)).or_insert_with(|| (None, None, Packets::new(backend)));
+            let mut duplicates = 0;
+            match Self::store(
+                entry,
+                backend,
+                self.spill.as_mut(),
+                &mut self.temp,
+                &mut self.checksums,
+                &mut self.metadata,
+                packet,
+            ) {
+                Some(held) => self.in_memory += held,
+                None => duplicates += 1,
+            }
+            while let Some(packet) = packets.next_if(|packet| packet.file_id() == file_id) {
+                match Self::store(
+                    entry,
+                    backend,
+                    self.spill.as_mut(),
+                    &mut self.temp,
+                    &mut self.checksums,
+                    &mut self.metadata,
+                    packet,
+                ) {
+                    Some(held) => self.in_memory += held,
+                    None => duplicates += 1,
+                }
+            }
+            if duplicates > 0 {
+                *self.duplicates.entry(file_id).or_default() += duplicates;
+            }
+            self.hand_off(file_id);
+            self.keep_within_memory();
+        }
+    }
+
+    // Give a file that's just been completed to `writers`, with everything
+    // that goes into writing it. Only its packet numbers stay, so it still
+    // counts as complete.
+    fn hand_off(&mut self, file_id: u8) {
+        let Some(writers) = &self.writers else {
+            return;
+        };
+        if self.handed_off.contains(&file_id) || !self.is_complete(file_id) {
+            return;
+        }
+        if self.on_conflict == OnConflict::TimestampSubdir {
+            // The same directory for every file, whichever thread writes it
+            (self.conflict_dir).get_or_insert_with(|| {
+                units::format_timestamp(SystemTime::now()).replace(':', "-")
+            });
+        }
+        let (name, expected, packets) = self.files.get_mut(&file_id).expect("complete file");
+        let numbers = packets.sorted().into_iter();
+        let taken = mem::replace(
+            packets,
+            Packets::Map(numbers.map(|n| (n, Payload::new())).collect()),
+        );
+        self.in_memory = self.in_memory.saturating_sub(bytes_held(&taken));
+        let mut alone = FileManager {
+            backend: self.backend,
+            spill: self.spill.as_mut().map(|spill| spill.detach(file_id)),
+            owner: self.owner,
+            newlines: self.newlines,
+            max_file_size: self.max_file_size,
+            on_conflict: self.on_conflict,
+            conflict_dir: self.conflict_dir.clone(),
+            staging: self.staging.clone(),
+            ..FileManager::default()
+        };
+        alone
+            .files
+            .insert(file_id, (name.clone(), *expected, taken));
+        if let Some(&digest) = self.checksums.get(&file_id) {
+            alone.checksums.insert(file_id, digest);
+        }
+        if let Some(&metadata) = self.metadata.get(&file_id) {
+            alone.metadata.insert(file_id, metadata);
+        }
+        writers.write(file_id, alone);
+        self.handed_off.insert(file_id);
+    }
+
+    // While payloads in memory are over --max-memory, move the file holding
+    // the most of them onto disk, where the rest of its packets follow it.
+    // A file that doesn't fit the spill grid stays in memory, and if that
+    // leaves nothing to move, the cap is passed with a warning.
+    fn keep_within_memory(&mut self) {
+        let (Some(max), Some(spill)) = (self.max_memory, self.spill.as_mut()) else {
+            return;
+        };
+        while self.in_memory > max {
+            let largest = (self.files.iter())
+                .filter(|(&file_id, _)| !spill.holds(file_id) && !spill.refused(file_id))
+                .map(|(&file_id, (_, _, packets))| (file_id, bytes_held(packets)))
+                .filter(|&(_, bytes)| bytes > 0)
+                .max_by_key(|&(_, bytes)| bytes);
+            let Some((file_id, bytes)) = largest else {
+                if !self.over_memory {
+                    let held = units::format_size(self.in_memory);
+                    log!(Warn, "{}", tr!("max-memory-passed", held = held));
+                    self.over_memory = true;
+                }
+                return;
+            };
+            let (_, expected, packets) = self.files.get_mut(&file_id).expect("listed file");
+            let sorted = packets.sorted();
+            let payloads =
+                (sorted.iter()).map(|&number| (number, packets.get(number).unwrap_or_default()));
+            let last = expected.map(|count| count - 1);
+            if spill.adopt(&mut self.temp, file_id, payloads, last) {
+                // Only the packet numbers matter from here on, as with
+                // --assembly-backend spill
+                let numbers = sorted.into_iter().map(|number| (number, Payload::new()));
+                *packets = Packets::Map(numbers.collect());
+                self.in_memory -= bytes;
+            }
+        }
+    }
+
+    // How many payload bytes it took to keep in memory, or None if it was a
+    // data packet we already had, which is left as it was
+    fn store(
+        entry: &mut PacketGroup,
+        backend: Backend,
+        spill: Option<&mut Spill>,
+        temp: &mut TempDirs,
+        checksums: &mut HashMap<u8, Digest>,
+        metadata: &mut HashMap<u8, Metadata>,
+        packet: Packet,
+    ) -> Option<u64> {
+        match packet {
+            Packet::Header(Header {
+                file_id,
+                file_name,
+                metadata: given,
+            }) => {
+                entry.0 = Some(file_name); // Store file name
+                if let Some(size) = given.size.filter(|_| entry.2.is_empty()) {
+                    if matches!(backend, Backend::Hashmap | Backend::Vec) {
+                        let count = size.div_ceil(GRID).min(MAX_RESERVE);
+                        entry.2.reserve(count as usize);
+                    }
+                    // Packed, the payloads aren't the file that size is of
+                    if let Some(spill) = spill.filter(|_| given.compression == Compression::None) {
+                        spill.reserve(temp, file_id, size);
+                    }
+                }
+                // An uncompressed file the header says is empty has no
+                // data packets to wait for, though an empty last one may
+                // still come
+                let empty = given.size == Some(0) && given.compression == Compression::None;
+                if empty && entry.1.is_none() && entry.2.is_empty() {
+                    entry.1 = Some(0);
+                }
+                if !given.is_empty() {
+                    metadata.insert(file_id, given);
+                }
+                Some(0)
+            }
+
+            Packet::Data(Data {
+                file_id,
+                packet_number,
+                is_last_packet,
+                mut data,
+            }) => {
+                if entry.2.contains(packet_number) {
+                    return None;
+                }
+                let mut held = 0;
+                if let Some(spill) = spill {
+                    let stored = entry.2.numbers();
+                    match spill.store(temp, file_id, packet_number, &data, is_last_packet, stored) {
+                        // Only the packet number matters from here on
+                        Stored::OnDisk => data = Payload::new(),
+                        Stored::InMemory => {}
+                        Stored::FellBack(earlier) => {
+                            held = earlier.iter().map(|(_, data)| data.len() as u64).sum();
+                            entry.2.extend(backend, earlier);
+                        }
+                    }
+                }
+                held += data.len() as u64;
+                let new = entry.2.insert(backend, packet_number, data); // store data packet
+
+                // The furthest "last" packet, or the furthest packet of any
+                // kind, is where the file ends: with two that disagree it
+                // takes the whole range up to it to be complete
+                if is_last_packet {
+                    let count = packet_number.checked_add(1);
+                    let stored = entry.2.highest().and_then(|highest| highest.checked_add(1));
+                    entry.1 = entry.1.max(count).max(stored);
+                }
+                new.then_some(held)
+            }
+
+            Packet::Checksum(Checksum { file_id, digest }) => {
+                checksums.insert(file_id, digest);
+                Some(0)
+            }
+        }
+    }
+
+    // IDs of every file we've heard about, in order
+    pub fn file_ids(&self) -> Vec<u8> {
+        let mut ids: Vec<u8> = self.files.keys().cloned().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    // IDs of every file, by ID or by name. Files with no name yet go last.
+    pub fn ordered_ids(&self, order: WriteOrder) -> Vec<u8> {
+        let mut ids = self.file_ids();
+        if order == WriteOrder::Name {
+            ids.sort_by_key(|id| {
+                let name = self.files[id].0.as_deref();
+                (name.is_none(), name.map(OsStr::as_encoded_bytes))
+            });
+        }
+        ids
+    }
+
+    // How long a complete file is, as received
+    pub fn received_len(&self, file_id: u8) -> Option<u64> {
+        if !self.is_complete(file_id) || self.handed_off.contains(&file_id) {
+            return None;
+        }
+        if let Some(len) = self.spill.as_ref().and_then(|spill| spill.len(file_id)) {
+            return Some(len);
+        }
+        let (_, _, packets) = self.files.get(&file_id)?;
+        (packets.numbers())
+            .map(|packet_number| Some(packets.get(packet_number)?.len() as u64))
+            .sum()
+    }
+
+    // The first `len` bytes of a complete file once it's unpacked, for
+    // --fix-extensions to tell what it is
+    pub fn head(&self, file_id: u8, len: usize) -> Option<Vec<u8>> {
+        if !self.is_complete(file_id) || self.handed_off.contains(&file_id) {
+            return None;
+        }
+        let (_, _, packets) = self.files.get(&file_id)?;
+        let spilled = self
+            .spill
+            .as_ref()
+            .filter(|spill| spill.len(file_id).is_some());
+        let mut head = Vec::new();
+        match self.compression(file_id) {
+            Compression::None => {
+                for packet_number in packets.sorted() {
+                    if head.len() >= len {
+                        break;
+                    }
+                    let data = match spilled {
+                        Some(spill) => spill.read(file_id, packet_number),
+                        None => packets.get(packet_number),
+                    };
+                    head.extend_from_slice(data?);
+                }
+            }
+            compression => {
+                let contents = self.contents(file_id)?;
+                compression::decode(compression, &contents[..], &mut |data| {
+                    head.extend_from_slice(data);
+                    Ok(())
+                })
+                .ok()?;
+            }
+        }
+        head.truncate(len);
+        Some(head)
+    }
+
+    // The SHA-256 a checksum packet gave for a file, in hex
+    pub fn checksum(&self, file_id: u8) -> Option<String> {
+        self.checksums.get(&file_id).map(|digest| hash::hex(digest))
+    }
+
+    // Name, number of packets received, and expected packet count for a file
+    pub fn file_status(&self, file_id: u8) -> Option<(Option<&OsStr>, usize, Option<u32>)> {
+        self.files
+            .get(&file_id)
+            .map(|(name, expected, packets)| (name.as_deref(), packets.len(), *expected))
+    }
+
+    // Packet numbers not received yet, as ranges: one packet numbered far
+    // past the rest is one range, not a number for every packet in between.
+    // Until the last packet shows up we only know about the gaps below the
+    // highest packet number seen.
+    pub fn missing_packets(&self, file_id: u8) -> Option<Vec<Range<u32>>> {
+        let (_, expected, packets) = self.files.get(&file_id)?;
+        let end = match expected {
+            Some(count) => *count,
+            None => packets.highest().map_or(0, |max| max.saturating_add(1)),
+        };
+        let mut missing = Vec::new();
+        let mut next = 0;
+        for n in packets.sorted().into_iter().take_while(|&n| n < end) {
+            if n > next {
+                missing.push(next..n);
+            }
+            next = n + 1;
+        }
+        if next < end {
+            missing.push(next..end);
+        }
+        Some(missing)
+    }
+
+    // Every packet known to be missing and numbered below `end`, file by
+    // file in ID order, for a retransmission request. One at a time, since
+    // a request only has room for so many.
+    pub fn gaps(&self, end: u32) -> impl Iterator<Item = (u8, u32)> + '_ {
+        self.file_ids().into_iter().flat_map(move |file_id| {
+            let missing = self.missing_packets(file_id).unwrap_or_default();
+            (missing.into_iter())
+                .flat_map(move |range| (range.start..range.end.min(end)).map(move |n| (file_id, n)))
+        })
+    }
+
+    // What we have of every file we've heard about, for a repeat request
+    pub fn resume_token(&self, session_id: u64) -> resume::Token {
+        let files = self
+            .file_ids()
+            .into_iter()
+            .map(|file_id| {
+                let (name, expected, packets) = &self.files[&file_id];
+                resume::FileProgress {
+                    file_id,
+                    have_header: name.is_some(),
+                    expected: *expected,
+                    received: packets.sorted(),
+                }
+            })
+            .collect();
+        resume::Token { session_id, files }
+    }
+
+    // Where file `file_id` would be written in `dir` under --on-conflict,
+    // and what that does to anything already there. Nothing is touched.
+    pub fn place(&mut self, dir: &Path, file_id: u8) -> Option<Placement> {
+        let Some((Some(name), _, _)) = self.files.get(&file_id) else {
+            return None;
+        };
+        let existing = dir.join(&**name);
+        if !fsio::exists(&existing) {
+            return Some(Placement::New(existing));
+        }
+        let beside = |path, compare| Placement::Beside {
+            path,
+            existing: existing.clone(),
+            compare,
+        };
+        Some(match self.on_conflict {
+            OnConflict::Overwrite => Placement::Overwrite(existing),
+            OnConflict::Skip => Placement::Kept(existing),
+            OnConflict::Rename => beside(free_name(&existing), false),
+            OnConflict::SkipIdentical => beside(free_name(&existing), true),
+            OnConflict::VersionSuffix => beside(version_name(&existing), false),
+            OnConflict::TimestampSubdir => {
+                // One directory for the session, named when it's needed
+                let stamp = self.conflict_dir.get_or_insert_with(|| {
+                    units::format_timestamp(SystemTime::now()).replace(':', "-")
+                });
+                let path = dir.join(&*stamp).join(&**name);
+                match fsio::exists(&path) {
+                    true => beside(free_name(&path), false),
+                    false => beside(path, false),
+                }
+            }
+            OnConflict::Error => Placement::Refused(existing),
+        })
+    }
+
+    // Write one file to disk in `dir`, saying where it went
+    pub fn write_file(&mut self, dir: &Path, file_id: u8) -> io::Result<Option<Written>> {
+        self.write_file_placed(dir, file_id, &mut |_, path| Ok(path))
+    }
+
+    // write_file, with `redirect` shown the path first and giving back the
+    // one to write to
+    fn write_file_placed(
+        &mut self,
+        dir: &Path,
+        file_id: u8,
+        redirect: &mut dyn FnMut(u8, PathBuf) -> io::Result<PathBuf>,
+    ) -> io::Result<Option<Written>> {
+        let Some(placement) = self.place(dir, file_id) else {
+            return Ok(None);
+        };
+        // With skip-identical, the file already there, to compare with
+        let (path, earlier) = match placement {
+            Placement::New(path) | Placement::Overwrite(path) => (path, None),
+            Placement::Kept(path) => return Ok(Some(Written::Skipped(path))),
+            Placement::Beside {
+                path,
+                existing,
+                compare,
+            } => (path, compare.then_some(existing)),
+            Placement::Refused(path) => {
+                let message = tr!("file-exists", path = path.display());
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, message));
+            }
+        };
+
+        let path = redirect(file_id, path)?;
+        fsio::create_parent_dirs(&path)?;
+        let written = self.write_to(dir, file_id, path)?;
+        // Written beside it to compare, since the new one can be on disk
+        // already (spilled) and normalizing changes it
+        match (earlier, written) {
+            (Some(earlier), Written::To(path)) if identical(&earlier, &path)? => {
+                fsio::remove_file(&path)?;
+                Ok(Some(Written::Skipped(earlier)))
+            }
+            (_, written) => Ok(Some(written)),
+        }
+    }
+
+    // Write one file to `path`, going through the scratch directory in `dir`
+    fn write_to(&mut self, dir: &Path, file_id: u8, path: PathBuf) -> io::Result<Written> {
+        let compression = self.compression(file_id);
+        let (file_name, expected, packets) = self.files.get_mut(&file_id).expect("no such file");
+        let name = file_name.as_ref().expect("Missing file name");
+
+        // The checksum is of the file as sent, once unpacked but before
+        // --normalize-text
+        let checksum = self.checksums.get(&file_id).map(|digest| hash::hex(digest));
+        let mut hasher = checksum.as_ref().map(|_| HashAlgorithm::Sha256.hasher());
+        let written = |path: PathBuf, actual: Option<String>| match actual {
+            Some(actual) if Some(&actual) != checksum.as_ref() => Written::Mismatched(path),
+            _ => Written::To(path),
+        };
+
+        if let Some(spill) = self.spill.as_mut() {
+            let complete = expected.is_some_and(|count| packets.len() == count as usize);
+            // Normalizing and unpacking need the bytes in hand, so they take
+            // the long way
+            let as_sent = self.newlines.is_none() && compression == Compression::None;
+            if complete && as_sent && spill.finish(file_id, &path)? {
+                let len = fsio::at(fsio::Action::Read, &path, || path.metadata())?.len();
+                if let Some(limit) = self.max_file_size.filter(|&limit| len > limit) {
+                    fsio::remove_file(&path)?;
+                    return Err(too_large(file_id, limit));
+                }
+                if let Some(owner) = self.owner {
+                    owner.apply(&path)?;
+                }
+                if let Some(metadata) = self.metadata.get(&file_id) {
+                    let file = fsio::open(&path, OpenOptions::new().write(true))?;
+                    give_metadata(metadata, &file, &path)?;
+                }
+                // The spill file was the file, so it's read back to check it
+                let actual = match checksum {
+                    Some(_) => Some(HashAlgorithm::Sha256.digest(&fsio::read(&path)?)),
+                    None => None,
+                };
+                return Ok(written(path, actual));
+            }
+            // Not done yet, so write out what we have the usual way
+            let spilled = spill.unspill(file_id, packets.numbers());
+            packets.extend(self.backend, spilled);
+        }
+        // Written to the scratch directory first so a half-written file
+        // never shows up under its real name
+        let staging = self.staging.as_deref().unwrap_or(dir);
+        let partial = self.temp.get(staging)?.join(&**name);
+        fsio::create_parent_dirs(&partial)?;
+        let mut file = fsio::create(&partial)?;
+
+        // Normalizing needs the whole file, so it's gathered up first
+        let mut contents = Vec::new();
+        // Unpacking can make a file any size, whatever came for it
+        let mut len = 0;
+        let max_file_size = self.max_file_size;
+        let mut emit = |data: &[u8]| {
+            len += data.len() as u64;
+            if let Some(limit) = max_file_size.filter(|&limit| len > limit) {
+                return Err(too_large(file_id, limit));
+            }
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(data);
+            }
+            if self.newlines.is_none() {
+                return fsio::write_all(&mut file, &partial, data); // Write data to file
+            }
+            contents.extend_from_slice(data);
+            Ok(())
+        };
+        let sorted = packets.sorted();
+        let mut payloads = sorted.iter().filter_map(|&key| packets.get(key));
+        match compression {
+            Compression::None => payloads.try_for_each(&mut emit)?,
+            _ => compression::decode(compression, Chunks::new(payloads), &mut emit)?,
+        }
+        if let Some(newlines) = self.newlines {
+            let contents = text::normalize(&contents, newlines).unwrap_or(contents);
+            fsio::write_all(&mut file, &partial, &contents)?;
+        }
+        if let Some(metadata) = self.metadata.get(&file_id) {
+            give_metadata(metadata, &file, &partial)?;
+        }
+        fsio::sync(&file, &partial)?;
+        drop(file);
+        // Handed over before it has its real name, so it never shows up
+        // under that name belonging to anyone else
+        if let Some(owner) = self.owner {
+            owner.apply(&partial)?;
+        }
+        tempdir::move_into_place(&partial, &path)?;
+
+        Ok(written(path, hasher.map(|hasher| hasher.finish())))
+    }
+
+    // Keep every file that isn't complete as `name.partial` in `dir`, each
+    // packet where it would be in the whole file and the gaps zeroed, and
+    // list what's missing from them in partial.json. For a session cut
+    // short, where the rest isn't coming.
+    pub fn write_partial_files(&mut self, dir: &Path) -> io::Result<Vec<PartialFile>> {
+        let mut kept = Vec::new();
+        for file_id in self.file_ids() {
+            if self.is_complete(file_id) {
+                continue;
+            }
+            let missing = self.missing_packets(file_id).unwrap_or_default();
+            let (name, expected, packets) = self.files.get_mut(&file_id).expect("listed file");
+            if let Some(spill) = self.spill.as_mut() {
+                let spilled = spill.unspill(file_id, packets.numbers());
+                packets.extend(self.backend, spilled);
+            }
+            let name = match name {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => format!("unnamed-{file_id}.bin"),
+            };
+            let partial = format!("{name}.partial");
+            let path = dir.join(&partial);
+            fsio::create_parent_dirs(&path)?;
+            let mut file = fsio::create(&path)?;
+            // Each packet where it goes, seeking past the gaps so they're
+            // holes rather than zeros held in memory
+            for number in packets.sorted() {
+                if let Some(data) = packets.get(number) {
+                    let at = u64::from(number) * wire::MAX_PAYLOAD as u64;
+                    fsio::at(fsio::Action::Write, &path, || {
+                        file.seek(SeekFrom::Start(at))
+                    })?;
+                    fsio::write_all(&mut file, &path, data)?;
+                }
+            }
+            kept.push(PartialFile {
+                id: file_id,
+                name,
+                partial,
+                received: packets.len(),
+                expected: *expected,
+                missing,
+            });
+        }
+        if !kept.is_empty() {
+            #[derive(Serialize)]
+            struct Manifest<'a> {
+                files: &'a [PartialFile],
+            }
+            let json = serde_json::to_string_pretty(&Manifest { files: &kept })
+                .map_err(io::Error::other)?;
+            fsio::write(&dir.join(PARTIAL_MANIFEST), format!("{json}\n").as_bytes())?;
+        }
+        Ok(kept)
+    }
+
+    // Bytes from a file that may still be arriving, so whoever embeds us can
+    // start on the head of a big file before its tail is here. Every packet
+    // but the last is full, so the bytes of packet n start at n KiB whether
+    // or not the packets before it are here. Files spilled to disk can only
+    // be read up until they're written out.
+    pub fn read_range(&self, file_id: u8, range: Range<u64>) -> RangeRead {
+        let expected = self
+            .files
+            .get(&file_id)
+            .and_then(|(_, expected, _)| *expected);
+        let size = expected.and_then(|expected| {
+            let last = expected - 1;
+            Some(u64::from(last) * GRID + self.payload(file_id, last)?.len() as u64)
+        });
+        let size = size.unwrap_or((u64::from(u32::MAX) + 1) * GRID);
+        if range.start >= size && !range.is_empty() {
+            return RangeRead::PastEnd { size };
+        }
+        let end = range.end.min(size);
+        if range.start >= end {
+            return RangeRead::Bytes(Vec::new());
+        }
+
+        // Both fit, as neither is past the end
+        let numbers = (range.start / GRID) as u32..=((end - 1) / GRID) as u32;
+        let missing: Vec<u32> = (numbers.clone())
+            .filter(|&number| self.payload(file_id, number).is_none())
+            .collect();
+        if !missing.is_empty() {
+            return RangeRead::NotYetAvailable { missing };
+        }
+        let mut bytes = Vec::with_capacity((end - range.start) as usize);
+        for number in numbers {
+            let data = self.payload(file_id, number).expect("not missing");
+            let offset = u64::from(number) * GRID;
+            let from = range.start.saturating_sub(offset) as usize;
+            let to = ((end - offset) as usize).min(data.len());
+            bytes.extend_from_slice(data.get(from..to).unwrap_or_default());
+        }
+        RangeRead::Bytes(bytes)
+    }
+
+    // Data packets for a file that arrived more than once
+    pub fn duplicates(&self, file_id: u8) -> u64 {
+        self.duplicates.get(&file_id).copied().unwrap_or(0)
+    }
+
+    // Write every complete file to disk in `dir`. Each file is on its own:
+    // one that can't be written (after a few tries, if the error looks like
+    // it might clear) doesn't stop the rest, and one already on disk goes by
+    // --on-conflict. Depending on --until the session
+    // can end with stragglers, which are dropped. Files not started by
+    // `deadline` aren't written.
+    pub fn write_all_files(
+        &mut self,
+        dir: &Path,
+        order: WriteOrder,
+        deadline: Option<Instant>,
+    ) -> Finalized {
+        self.write_all_with(order, deadline, |files, file_id| {
+            files.write_file(dir, file_id)
+        })
+    }
+
+    // Like write_all_files, with `redirect` deciding at the last moment where
+    // each file goes: it's given the path --on-conflict settled on
+    pub fn write_all_files_redirected(
+        &mut self,
+        dir: &Path,
+        order: WriteOrder,
+        deadline: Option<Instant>,
+        mut redirect: impl FnMut(u8, PathBuf) -> io::Result<PathBuf>,
+    ) -> Finalized {
+        self.write_all_with(order, deadline, |files, file_id| {
+            files.write_file_placed(dir, file_id, &mut redirect)
+        })
+    }
+
+    // Like write_all_files, but through `sink` rather than into a directory.
+    // Checksums and --normalize-text apply; --on-conflict and --chown are the
+    // sink's business.
+    pub fn write_all_to(
+        &mut self,
+        sink: &mut dyn FileSink,
+        order: WriteOrder,
+        deadline: Option<Instant>,
+    ) -> Finalized {
+        self.write_all_with(order, deadline, |files, file_id| {
+            files.write_to_sink(sink, file_id)
+        })
+    }
+
+    fn write_all_with(
+        &mut self,
+        order: WriteOrder,
+        deadline: Option<Instant>,
+        mut write: impl FnMut(&mut Self, u8) -> io::Result<Option<Written>>,
+    ) -> Finalized {
+        let mut finalized = Finalized::default();
+        // The ones already handed over have been written, or are being
+        if let Some(writers) = self.writers.take() {
+            for (file_id, result) in writers.finish() {
+                finalized.record(file_id, result);
+            }
+        }
+        for file_id in self.ordered_ids(order) {
+            if !self.is_complete(file_id) || self.handed_off.contains(&file_id) {
+                continue;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                let e = io::Error::new(io::ErrorKind::TimedOut, i18n::text("deadline-unwritten"));
+                finalized.failed.push((file_id, e));
+                continue;
+            }
+            let result = fsio::retry_transient(WRITE_ATTEMPTS, || write(self, file_id));
+            finalized.record(file_id, result);
+        }
+        finalized
+    }
+
+    fn write_to_sink(&self, sink: &mut dyn FileSink, file_id: u8) -> io::Result<Option<Written>> {
+        let (Some(contents), Some((Some(name), ..))) =
+            (self.contents(file_id), self.file_status(file_id))
+        else {
+            return Ok(None);
+        };
+        let contents = match self.compression(file_id) {
+            Compression::None => contents,
+            compression => {
+                let mut unpacked = Vec::new();
+                compression::decode(compression, &contents[..], &mut |data| {
+                    unpacked.extend_from_slice(data);
+                    Ok(())
+                })?;
+                unpacked
+            }
+        };
+        let mismatched = (self.checksums.get(&file_id))
+            .is_some_and(|digest| HashAlgorithm::Sha256.digest(&contents) != hash::hex(digest));
+        let contents = match self.newlines {
+            Some(newlines) => text::normalize(&contents, newlines).unwrap_or(contents),
+            None => contents,
+        };
+        {
+            let mut out = sink.create(name)?;
+            out.write_all(&contents)?;
+            out.flush()?;
+        }
+        let path = sink.finalize(name)?;
+        Ok(Some(match mismatched {
+            true => Written::Mismatched(path),
+            false => Written::To(path),
+        }))
+    }
+
+    // A complete file's bytes, in order
+    pub fn contents(&self, file_id: u8) -> Option<Vec<u8>> {
+        if !self.is_complete(file_id) || self.handed_off.contains(&file_id) {
+            return None;
+        }
+        let (_, count, _) = self.file_status(file_id)?;
+        let mut data = Vec::new();
+        for packet_number in 0..count as u32 {
+            data.extend_from_slice(self.payload(file_id, packet_number)?);
+        }
+        Some(data)
+    }
+
+    // Every complete file in memory, in `order`, for a caller that wants the
+    // bytes rather than files on disk. Incomplete ones are dropped.
+    pub fn into_received(self, order: WriteOrder) -> io::Result<Vec<ReceivedFile>> {
+        let received = (self.ordered_ids(order).into_iter())
+            .filter_map(|id| {
+                let data = self.contents(id)?;
+                let (name, ..) = self.file_status(id)?;
+                Some(ReceivedFile {
+                    id,
+                    name: name?.to_os_string(),
+                    data,
+                })
+            })
+            .collect();
+        self.clean_up()?;
+        Ok(received)
+    }
+
+    // Remove the session's scratch directories once its files are written
+    pub fn clean_up(mut self) -> io::Result<()> {
+        // A spill file still being written out needs its directory a while
+        drop(self.writers.take());
+        self.temp.clean_up()
+    }
+}
@@ -0,0 +1,234 @@
+// Filesystem calls that shrug off signals and say what failed. A signal
+// landing mid-call (a terminal resize, a profiler, Ctrl-Z then `fg`) can
+// surface as `Interrupted`, which only means "try again"; every other error
+// gets the operation and path added, so "No such file or directory" says
+// which file. Paths go through `paths::long` here, and errors show them the
+// way the caller wrote them.
+
+use crate::{
+    i18n::{self, tr},
+    paths,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Clone, Copy)]
+pub enum Action {
+    Create,
+    Open,
+    Read,
+    Write,
+    Resize,
+    Map,
+    Flush,
+    Sync,
+    Move,
+    Copy,
+    Remove,
+    CreateDir,
+    Chown,
+    SetTimes,
+    Chmod,
+}
+
+impl Action {
+    fn describe(self) -> &'static str {
+        i18n::text(match self {
+            Action::Create => "io-create",
+            Action::Open => "io-open",
+            Action::Read => "io-read",
+            Action::Write => "io-write",
+            Action::Resize => "io-resize",
+            Action::Map => "io-map",
+            Action::Flush => "io-flush",
+            Action::Sync => "io-sync",
+            Action::Move => "io-move",
+            Action::Copy => "io-copy",
+            Action::Remove => "io-remove",
+            Action::CreateDir => "io-create-dir",
+            Action::Chown => "io-chown",
+            Action::SetTimes => "io-set-times",
+            Action::Chmod => "io-chmod",
+        })
+    }
+}
+
+// Run `op` until a signal doesn't interrupt it
+pub fn retry<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match op() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+// Errors that might go away if we wait a moment: a file another process
+// (a virus scanner, a backup tool) has open, or a network filesystem that
+// didn't answer in time
+pub fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::ResourceBusy
+    )
+}
+
+// Try `op` up to `attempts` times while it fails with transient errors,
+// waiting a little longer each time
+pub fn retry_transient<T>(attempts: u32, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut delay = Duration::from_millis(50);
+    for _ in 1..attempts {
+        match op() {
+            Err(e) if is_transient(&e) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    op()
+}
+
+// `retry`, then say what we were doing to which path if it still failed.
+// The error keeps its kind so callers can still match on it.
+pub fn at<T>(action: Action, path: &Path, op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    retry(op).map_err(|e| {
+        let message = tr!(
+            "io-failed",
+            action = action.describe(),
+            path = path.display(),
+            error = e
+        );
+        io::Error::new(e.kind(), message)
+    })
+}
+
+// Keep calling `write` until all of `bytes` is out. A write that takes
+// nothing would loop forever, so that's an error.
+pub fn write_all(out: &mut impl Write, path: &Path, mut bytes: &[u8]) -> io::Result<()> {
+    at(Action::Write, path, || {
+        while !bytes.is_empty() {
+            match out.write(bytes)? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                written => bytes = &bytes[written..],
+            }
+        }
+        Ok(())
+    })
+}
+
+// Everything written to `file` on the disk itself, before it's renamed into
+// place, so a crash can't leave the real name on a file with holes in it
+pub fn sync(file: &File, path: &Path) -> io::Result<()> {
+    at(Action::Sync, path, || file.sync_all())
+}
+
+pub fn create(path: &Path) -> io::Result<File> {
+    at(Action::Create, path, || File::create(paths::long(path)))
+}
+
+pub fn open(path: &Path, options: &OpenOptions) -> io::Result<File> {
+    at(Action::Open, path, || options.open(paths::long(path)))
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    at(Action::Read, path, || fs::read(paths::long(path)))
+}
+
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    at(Action::Read, path, || fs::read_to_string(paths::long(path)))
+}
+
+pub fn write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = create(path)?;
+    write_all(&mut file, path, bytes)
+}
+
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    at(Action::CreateDir, path, || {
+        fs::create_dir_all(paths::long(path))
+    })
+}
+
+// The directories `path` goes in, for a --tree name
+pub fn create_parent_dirs(path: &Path) -> io::Result<()> {
+    match path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        Some(parent) => create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+pub fn remove_dir_all(path: &Path) -> io::Result<()> {
+    at(Action::Remove, path, || {
+        fs::remove_dir_all(paths::long(path))
+    })
+}
+
+// Whether anything at all is at `path`, a dangling symlink included
+pub fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(paths::long(path)).is_ok()
+}
+
+pub fn metadata(path: &Path) -> io::Result<fs::Metadata> {
+    at(Action::Read, path, || fs::metadata(paths::long(path)))
+}
+
+pub fn remove_file(path: &Path) -> io::Result<()> {
+    at(Action::Remove, path, || fs::remove_file(paths::long(path)))
+}
+
+pub fn chown(path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    at(Action::Chown, path, || {
+        #[cfg(unix)]
+        return std::os::unix::fs::chown(paths::long(path), uid, gid);
+        #[cfg(not(unix))]
+        {
+            let _ = (uid, gid);
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    })
+}
+
+pub fn set_modified(file: &File, path: &Path, time: SystemTime) -> io::Result<()> {
+    at(Action::SetTimes, path, || file.set_modified(time))
+}
+
+// Unix permission bits. Elsewhere only the owner's write bit means anything,
+// as read-only or not.
+pub fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    at(Action::Chmod, path, || {
+        #[cfg(unix)]
+        let permissions = std::os::unix::fs::PermissionsExt::from_mode(mode);
+        #[cfg(not(unix))]
+        let permissions = {
+            let mut permissions = fs::metadata(paths::long(path))?.permissions();
+            permissions.set_readonly(mode & 0o200 == 0);
+            permissions
+        };
+        fs::set_permissions(paths::long(path), permissions)
+    })
+}
+
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    at(Action::Move, from, || {
+        fs::rename(paths::long(from), paths::long(to))
+    })
+}
+
+pub fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    at(Action::Copy, from, || {
+        fs::copy(paths::long(from), paths::long(to)).map(|_| ())
+    })
+}
+
+pub fn set_len(file: &File, path: &Path, len: u64) -> io::Result<()> {
+    at(Action::Resize, path, || file.set_len(len))
+}
@@ -0,0 +1,259 @@
+// Batch mode for course staff: run the transfer against every student's
+// server in turn, keep each result set in its own directory, and summarize
+// who passed as JSON and CSV.
+
+use crate::{
+    cli::Cli,
+    client,
+    error::ClientError,
+    events::{AuditFile, Progress, Terminal},
+    fsio,
+    i18n::{self, tr},
+    log::log,
+    results::csv_field,
+    session::{receive_files, send_hello},
+};
+use serde::Serialize;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+// A dead server would otherwise stall the whole batch
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Serialize)]
+struct ServerResult {
+    name: String,
+    server: String,
+    passed: bool,
+    error: Option<String>,
+    // Which check failed, e.g. `server-unreachable` or `file-stalled`
+    error_code: Option<&'static str>,
+    bytes: u64,
+    files: Vec<FileResult>,
+}
+
+#[derive(Serialize)]
+struct FileResult {
+    name: String,
+    bytes: u64,
+    // Tagged with the algorithm, e.g. `blake3:...`
+    hash: String,
+    // Only known when grading against reference files
+    matches_expected: Option<bool>,
+}
+
+// Servers are listed one per line as `ADDR` or `NAME ADDR`; blank lines and
+// `#` comments are skipped
+fn read_servers(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let servers = fsio::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once(char::is_whitespace) {
+            Some((name, addr)) => (name.to_string(), addr.trim().to_string()),
+            None => (line.to_string(), line.to_string()),
+        })
+        .collect();
+    Ok(servers)
+}
+
+// Directory names can't contain the `:` in `host:port` on every platform
+pub fn directory_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Receive one server's files into `dir`, returning where they were written
+fn transfer(cli: &Cli, addr: &str, dir: &Path) -> Result<Vec<PathBuf>, ClientError> {
+    let cli = &cli.resalted();
+    let deadline = cli.deadline.map(|timeout| Instant::now() + timeout);
+    let sock = client::bind(0, client::ip_version(cli, [addr]))?;
+    client::connect(&sock, addr)?;
+    send_hello(&sock, cli);
+
+    let mut progress = Progress::default();
+    if !cli.quiet {
+        progress.add(Terminal::default());
+    }
+    if let Some(path) = &cli.audit {
+        progress.add(AuditFile::open(path)?);
+    }
+    let (mut file_manager, report) = receive_files(&sock, cli, &mut progress, deadline)?;
+    report.print();
+
+    fsio::create_dir_all(dir)?;
+    if let Some(owner) = cli.chown {
+        owner.apply(dir)?;
+    }
+    let finalized = file_manager.write_all_files(dir, cli.write_order, deadline);
+    file_manager.clean_up()?;
+    for (file_id, e) in &finalized.failed {
+        log!(
+            Error,
+            "{}",
+            tr!("write-failed", file_id = file_id, error = e)
+        );
+    }
+    for (file_id, path) in &finalized.mismatched {
+        let path = path.display();
+        log!(
+            Error,
+            "{}",
+            tr!("checksum-mismatch", file_id = file_id, path = path)
+        );
+    }
+    let written = finalized.into_result()?;
+    Ok(written.into_iter().map(|(_, path)| path).collect())
+}
+
+fn grade_server(
+    cli: &Cli,
+    name: &str,
+    addr: &str,
+    dir: &Path,
+    expected: Option<&Path>,
+) -> ServerResult {
+    let mut result = ServerResult {
+        name: name.to_string(),
+        server: addr.to_string(),
+        passed: false,
+        error: None,
+        error_code: None,
+        bytes: 0,
+        files: Vec::new(),
+    };
+
+    let written = match transfer(cli, addr, dir) {
+        Ok(written) => written,
+        Err(e) => {
+            result.error = Some(format!("{e:?}"));
+            result.error_code = Some(e.code());
+            return result;
+        }
+    };
+
+    let mut all_match = true;
+    for path in &written {
+        let file_name = path.file_name().unwrap_or_default();
+        let data = match fsio::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                result.error = Some(e.to_string());
+                result.error_code = Some("io");
+                return result;
+            }
+        };
+        let matches_expected = expected
+            .map(|dir| fsio::read(&dir.join(file_name)).is_ok_and(|reference| reference == data));
+        all_match &= matches_expected.unwrap_or(true);
+        result.bytes += data.len() as u64;
+        result.files.push(FileResult {
+            name: file_name.to_string_lossy().into_owned(),
+            bytes: data.len() as u64,
+            hash: cli.hash.tagged(&data),
+            matches_expected,
+        });
+    }
+    result.files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Every reference file has to have shown up, not just the ones we got
+    if let Some(expected) = expected {
+        if let Ok(entries) = fs::read_dir(expected) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if !result.files.iter().any(|file| file.name == name) {
+                    all_match = false;
+                }
+            }
+        }
+    }
+
+    result.passed = all_match;
+    result
+}
+
+fn write_csv(path: &Path, results: &[ServerResult]) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fsio::create(path)?);
+    writeln!(
+        out,
+        "name,server,passed,error,file,bytes,hash,matches_expected"
+    )?;
+    for result in results {
+        let prefix = format!(
+            "{},{},{},{}",
+            csv_field(&result.name),
+            csv_field(&result.server),
+            result.passed,
+            csv_field(result.error.as_deref().unwrap_or(""))
+        );
+        if result.files.is_empty() {
+            writeln!(out, "{prefix},,0,,")?;
+        }
+        for file in &result.files {
+            let matches = file
+                .matches_expected
+                .map_or(String::new(), |m| m.to_string());
+            writeln!(
+                out,
+                "{prefix},{},{},{},{matches}",
+                csv_field(&file.name),
+                file.bytes,
+                file.hash
+            )?;
+        }
+    }
+    out.flush()
+}
+
+pub fn run(cli: &Cli, servers: &Path, out_dir: &Path, expected: Option<&Path>) -> io::Result<()> {
+    let mut cli = cli.clone();
+    cli.session_timeout.get_or_insert(DEFAULT_TIMEOUT);
+    // Each server is graded on its own
+    cli.failover = false;
+
+    let servers = read_servers(servers)?;
+    fsio::create_dir_all(out_dir)?;
+    if let Some(owner) = cli.chown {
+        owner.apply(out_dir)?;
+    }
+
+    let mut results = Vec::new();
+    for (name, addr) in &servers {
+        println!("{}", tr!("grade-server", name = name, addr = addr));
+        let dir = out_dir.join(directory_name(name));
+        let result = grade_server(&cli, name, addr, &dir, expected);
+        match (&result.error, result.passed) {
+            (Some(error), _) => println!("{}", tr!("grade-fail", error = error)),
+            (None, true) => println!("{}", i18n::text("grade-pass")),
+            (None, false) => println!("{}", i18n::text("grade-mismatch")),
+        }
+        results.push(result);
+    }
+
+    let json = serde_json::to_string_pretty(&results).map_err(io::Error::other)?;
+    fsio::write(&out_dir.join("summary.json"), (json + "\n").as_bytes())?;
+    write_csv(&out_dir.join("summary.csv"), &results)?;
+
+    let passed = results.iter().filter(|result| result.passed).count();
+    println!(
+        "{}",
+        tr!(
+            "grade-summary",
+            passed = passed,
+            total = results.len(),
+            dir = out_dir.display()
+        )
+    );
+    Ok(())
+}
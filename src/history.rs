@@ -0,0 +1,388 @@
+// --history: a line of JSON per session, appended to a file kept from run to
+// run, and `report`, which draws it as report.html in --output-dir, beside
+// the files. The page is a single file with everything in it, charts as
+// inline SVG, so opening it fetches nothing and nothing about the transfers
+// leaves the machine. An entry:
+//
+//   {"started_unix_ms": 1700000000000, "elapsed_ms": 5120, "packets": 480,
+//    "bytes": 480000, "duplicates": 3, "nacked": 12, "missing": 0,
+//    "throughput": [98304, 102400, ...],
+//    "files": [{"file_id": 1, "name": "a.txt", "packets": 12,
+//               "status": "complete", "first_ms": 10, "latest_ms": 900}]}
+//
+// `throughput` is the payload bytes that came in each second of the session.
+// Loss is an estimate: the packets asked for again (--nack-after) and those
+// that never came, out of everything the session should have had.
+
+use crate::{
+    cli::WriteOrder,
+    file_manager::{FileManager, Finalized},
+    fsio,
+    i18n::{self, tr},
+    report::SessionReport,
+    results, units,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write as _,
+    fs::OpenOptions,
+    io,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, UNIX_EPOCH},
+};
+
+pub const PAGE_NAME: &str = "report.html";
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 160.0;
+const ROW_HEIGHT: f64 = 18.0;
+// Room on the left of the timeline for file names
+const LABEL_WIDTH: f64 = 160.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub started_unix_ms: u64,
+    pub elapsed_ms: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    pub duplicates: u64,
+    pub nacked: u64,
+    pub missing: u64,
+    pub throughput: Vec<u64>,
+    pub files: Vec<FileSpan>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSpan {
+    pub file_id: u8,
+    pub name: String,
+    pub packets: usize,
+    // As in --report-csv
+    pub status: String,
+    // Since the session started; none if no packet of it was kept
+    pub first_ms: Option<u64>,
+    pub latest_ms: Option<u64>,
+}
+
+impl Entry {
+    pub fn new(
+        report: &SessionReport,
+        files: &FileManager,
+        finalized: &Finalized,
+        order: WriteOrder,
+    ) -> Self {
+        let started = report.started_wall().duration_since(UNIX_EPOCH);
+        let mut entry = Entry {
+            started_unix_ms: started.map_or(0, |d| d.as_millis() as u64),
+            elapsed_ms: report.elapsed().as_millis() as u64,
+            packets: report.packets(),
+            bytes: report.bytes(),
+            duplicates: report.duplicates(),
+            nacked: report.nacked_packets(),
+            missing: 0,
+            throughput: report.throughput().to_vec(),
+            files: Vec::new(),
+        };
+        for file_id in files.ordered_ids(order) {
+            let Some((name, packets, _)) = files.file_status(file_id) else {
+                continue;
+            };
+            let missing = files.missing_packets(file_id).unwrap_or_default();
+            entry.missing += units::count(&missing);
+            let span = report.file_span(file_id);
+            entry.files.push(FileSpan {
+                file_id,
+                name: name.map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+                packets,
+                status: results::status(finalized, file_id).to_string(),
+                first_ms: span.map(|(first, _)| first.as_millis() as u64),
+                latest_ms: span.map(|(_, latest)| latest.as_millis() as u64),
+            });
+        }
+        entry
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.bytes * 1000 / self.elapsed_ms.max(1)
+    }
+
+    // The share of packets lost on the way, from 0 to 1
+    pub fn loss(&self) -> f64 {
+        match self.packets + self.missing {
+            0 => 0.0,
+            all => ((self.nacked + self.missing) as f64 / all as f64).min(1.0),
+        }
+    }
+}
+
+pub fn append(path: &Path, entry: &Entry) -> io::Result<()> {
+    let mut line = serde_json::to_string(entry).map_err(io::Error::other)?;
+    line.push('\n');
+    let mut file = fsio::open(path, OpenOptions::new().create(true).append(true))?;
+    fsio::write_all(&mut file, path, line.as_bytes())
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<Entry>> {
+    let text = fsio::read_to_string(path)?;
+    (text.lines().enumerate())
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                let message = tr!(
+                    "history-bad",
+                    path = path.display(),
+                    line = index + 1,
+                    error = e
+                );
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            })
+        })
+        .collect()
+}
+
+// Draw `history` as report.html in `dir`, and say where it went
+pub fn write_page(history: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let page = render(&load(history)?);
+    fsio::create_dir_all(dir)?;
+    let path = dir.join(PAGE_NAME);
+    fsio::write(&path, page.as_bytes())?;
+    Ok(path)
+}
+
+// Have the desktop show `path` in a browser
+pub fn open(path: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = process::Command::new("open");
+    #[cfg(not(any(windows, target_os = "macos")))]
+    let mut command = process::Command::new("xdg-open");
+    let status = command.arg(path).status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(tr!(
+            "report-open-failed",
+            path = path.display(),
+            status = status
+        ))),
+    }
+}
+
+pub fn render(entries: &[Entry]) -> String {
+    let title = escape(i18n::text("report-page-title"));
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    let Some(latest) = entries.last() else {
+        let _ = writeln!(page, "<p>{}</p>", escape(i18n::text("report-page-empty")));
+        page.push_str("</body>\n</html>\n");
+        return page;
+    };
+
+    let started = UNIX_EPOCH + Duration::from_millis(latest.started_unix_ms);
+    let heading = tr!(
+        "report-page-latest",
+        started = units::format_timestamp(started)
+    );
+    let summary = tr!(
+        "report-page-summary",
+        files = latest.files.len(),
+        bytes = units::format_size(latest.bytes),
+        elapsed = units::format_duration(Duration::from_millis(latest.elapsed_ms)),
+        rate = units::format_rate(latest.rate()),
+        loss = format!("{:.1}", latest.loss() * 100.0)
+    );
+    let _ = writeln!(
+        page,
+        "<h2>{}</h2>\n<p>{}</p>",
+        escape(&heading),
+        escape(&summary)
+    );
+    let _ = writeln!(
+        page,
+        "<h3>{}</h3>\n{}",
+        escape(i18n::text("report-page-throughput")),
+        throughput_chart(&latest.throughput)
+    );
+    let _ = writeln!(
+        page,
+        "<h3>{}</h3>\n{}",
+        escape(i18n::text("report-page-files")),
+        timeline(&latest.files, latest.elapsed_ms)
+    );
+
+    let _ = writeln!(
+        page,
+        "<h2>{}</h2>",
+        escape(i18n::text("report-page-sessions"))
+    );
+    let rates: Vec<f64> = entries.iter().map(|entry| entry.rate() as f64).collect();
+    let rate_labels = entries.iter().map(|entry| units::format_rate(entry.rate()));
+    let _ = writeln!(
+        page,
+        "<h3>{}</h3>\n{}",
+        escape(i18n::text("report-page-rate")),
+        bar_chart(&rates, rate_labels, "rate")
+    );
+    let losses: Vec<f64> = entries.iter().map(Entry::loss).collect();
+    let loss_labels = losses.iter().map(|loss| format!("{:.1}%", loss * 100.0));
+    let _ = writeln!(
+        page,
+        "<h3>{}</h3>\n{}",
+        escape(i18n::text("report-page-loss")),
+        bar_chart(&losses, loss_labels, "loss")
+    );
+
+    page.push_str("<table>\n<tr>");
+    for key in [
+        "report-page-started",
+        "report-page-took",
+        "report-page-file-count",
+        "report-page-bytes",
+        "report-page-speed",
+        "report-page-lost",
+    ] {
+        let _ = write!(page, "<th>{}</th>", escape(i18n::text(key)));
+    }
+    page.push_str("</tr>\n");
+    for entry in entries.iter().rev() {
+        let started = UNIX_EPOCH + Duration::from_millis(entry.started_unix_ms);
+        let _ = writeln!(
+            page,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+            units::format_timestamp(started),
+            units::format_duration(Duration::from_millis(entry.elapsed_ms)),
+            entry.files.len(),
+            units::format_size(entry.bytes),
+            units::format_rate(entry.rate()),
+            entry.loss() * 100.0
+        );
+    }
+    page.push_str("</table>\n</body>\n</html>\n");
+    page
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+svg{display:block;margin-bottom:1em}\
+.axis{stroke:#999}.rate{fill:#4a7ab5}.loss{fill:#c0504d}\
+.complete{fill:#5b9d5b}.incomplete,.skipped{fill:#bbb}\
+.failed,.checksum-mismatch{fill:#c0504d}\
+text{font-size:11px;fill:#444}\
+table{border-collapse:collapse}td,th{padding:2px 10px;text-align:right}\
+tr:nth-child(even){background:#f2f2f2}";
+
+// Bytes a second across the session, as a line
+fn throughput_chart(seconds: &[u64]) -> String {
+    let most = seconds.iter().copied().max().unwrap_or(0).max(1);
+    let step = WIDTH / (seconds.len().max(2) - 1) as f64;
+    let points: Vec<String> = (seconds.iter().enumerate())
+        .map(|(second, &bytes)| {
+            let y = HEIGHT - bytes as f64 / most as f64 * HEIGHT;
+            format!("{:.1},{y:.1}", second as f64 * step)
+        })
+        .collect();
+    format!(
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"-60 -10 {w} {h}\">\
+         <line class=\"axis\" x1=\"0\" y1=\"{HEIGHT}\" x2=\"{WIDTH}\" y2=\"{HEIGHT}\"/>\
+         <text x=\"-6\" y=\"4\" text-anchor=\"end\">{top}</text>\
+         <text x=\"-6\" y=\"{HEIGHT}\" text-anchor=\"end\">0</text>\
+         <text x=\"{WIDTH}\" y=\"{bottom}\" text-anchor=\"end\">{seconds}s</text>\
+         <polyline fill=\"none\" stroke=\"#4a7ab5\" stroke-width=\"2\" points=\"{points}\"/>\
+         </svg>",
+        w = WIDTH + 70.0,
+        h = HEIGHT + 30.0,
+        top = units::format_rate(most),
+        bottom = HEIGHT + 14.0,
+        seconds = seconds.len(),
+        points = points.join(" "),
+    )
+}
+
+// A bar a session, oldest first, each labelled on hover
+fn bar_chart(values: &[f64], labels: impl Iterator<Item = String>, class: &str) -> String {
+    let most = values.iter().copied().fold(0.0, f64::max);
+    let width = WIDTH / values.len().max(1) as f64;
+    let mut svg = format!("<svg width=\"{}\" height=\"{}\">", WIDTH, HEIGHT + 1.0);
+    for ((index, value), label) in values.iter().enumerate().zip(labels) {
+        let height = match most > 0.0 {
+            true => value / most * HEIGHT,
+            false => 0.0,
+        };
+        let _ = write!(
+            svg,
+            "<rect class=\"{class}\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{height:.1}\">\
+             <title>{}</title></rect>",
+            index as f64 * width,
+            HEIGHT - height,
+            (width - 2.0).max(1.0),
+            escape(&label)
+        );
+    }
+    let _ = write!(
+        svg,
+        "<line class=\"axis\" x1=\"0\" y1=\"{HEIGHT}\" x2=\"{WIDTH}\" y2=\"{HEIGHT}\"/></svg>"
+    );
+    svg
+}
+
+// A row a file, with a bar from its first packet to its latest
+fn timeline(files: &[FileSpan], elapsed_ms: u64) -> String {
+    let scale = (WIDTH - LABEL_WIDTH) / elapsed_ms.max(1) as f64;
+    let mut svg = format!(
+        "<svg width=\"{WIDTH}\" height=\"{}\">",
+        ROW_HEIGHT * files.len() as f64
+    );
+    for (row, file) in files.iter().enumerate() {
+        let y = row as f64 * ROW_HEIGHT;
+        let name = match file.name.is_empty() {
+            true => file.file_id.to_string(),
+            false => file.name.clone(),
+        };
+        let _ = write!(
+            svg,
+            "<text x=\"0\" y=\"{:.1}\">{}</text>",
+            y + ROW_HEIGHT - 5.0,
+            escape(&name)
+        );
+        let (Some(first), Some(latest)) = (file.first_ms, file.latest_ms) else {
+            continue;
+        };
+        let _ = write!(
+            svg,
+            "<rect class=\"{}\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\">\
+             <title>{} ({} {}-{} ms)</title></rect>",
+            escape(&file.status),
+            LABEL_WIDTH + first as f64 * scale,
+            y + 2.0,
+            ((latest - first) as f64 * scale).max(2.0),
+            ROW_HEIGHT - 4.0,
+            escape(&name),
+            escape(&file.status),
+            first,
+            latest
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,102 @@
+// Points in a transfer where a program embedding the client can step in,
+// rather than only hear how it's going (that's callbacks.rs): once the socket
+// is bound, once the server has answered, and before each file goes to disk,
+// with the path it's going to and the chance to send it somewhere else.
+// Every hook does nothing unless overridden; one that returns an error ends
+// the transfer with `ClientError::Hook`. Hand them to `ClientBuilder::hooks`.
+
+use crate::{
+    error::ClientError,
+    events::{Event, ProgressSink},
+};
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+pub type HookError = Box<dyn Error + Send + Sync>;
+
+pub trait Hooks {
+    // The local socket is bound to `local`, and nothing has gone out yet
+    fn after_bind(&mut self, _local: SocketAddr) -> Result<(), HookError> {
+        Ok(())
+    }
+    // The first packet came from `peer`, so the session is under way
+    fn after_handshake(&mut self, _peer: SocketAddr) -> Result<(), HookError> {
+        Ok(())
+    }
+    // File `file_id` is about to be written to `path`, --on-conflict having
+    // had its say; where it should go instead, if anywhere
+    fn before_write(&mut self, _file_id: u8, _path: &Path) -> Result<Option<PathBuf>, HookError> {
+        Ok(None)
+    }
+}
+
+// Nothing to step in with
+impl Hooks for () {}
+
+// Held by the client, and by the sink below while a session runs
+pub type Shared = Arc<Mutex<dyn Hooks + Send>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hook {
+    AfterBind,
+    AfterHandshake,
+    BeforeWrite,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Hook::AfterBind => "after_bind",
+            Hook::AfterHandshake => "after_handshake",
+            Hook::BeforeWrite => "before_write",
+        })
+    }
+}
+
+// One hook called, its error saying which it was. One that panicked last
+// time is called all the same.
+pub fn call<T>(
+    hooks: &Shared,
+    hook: Hook,
+    call: impl FnOnce(&mut dyn Hooks) -> Result<T, HookError>,
+) -> Result<T, ClientError> {
+    let mut hooks = hooks.lock().unwrap_or_else(PoisonError::into_inner);
+    call(&mut *hooks).map_err(|error| ClientError::Hook { hook, error })
+}
+
+// Calls after_handshake as the session starts. The session only learns that
+// a sink failed, so the hook's own error is kept in `failed` for whoever
+// ran the session to give back instead.
+pub struct HandshakeSink {
+    hooks: Shared,
+    failed: Rc<RefCell<Option<ClientError>>>,
+}
+
+impl HandshakeSink {
+    pub fn new(hooks: Shared, failed: Rc<RefCell<Option<ClientError>>>) -> Self {
+        HandshakeSink { hooks, failed }
+    }
+}
+
+impl ProgressSink for HandshakeSink {
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        let &Event::SessionStarted { peer } = event else {
+            return Ok(());
+        };
+        call(&self.hooks, Hook::AfterHandshake, |hooks| {
+            hooks.after_handshake(peer)
+        })
+        .map_err(|e| {
+            let failed = io::Error::other(e.to_string());
+            *self.failed.borrow_mut() = Some(e);
+            failed
+        })
+    }
+}
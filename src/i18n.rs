@@ -0,0 +1,1691 @@
+// User-facing messages in the user's language, picked with --lang or from
+// LC_ALL / LC_MESSAGES / LANG. Only what people read goes through here:
+// the events file, JSON, and CSV keep their fixed English codes so scripts
+// reading them don't break when someone else runs the client.
+
+use crate::{
+    audit,
+    limits::{Ignored, NameProblem},
+    packet::Malformed,
+};
+use clap::ValueEnum;
+use std::{env, fmt, sync::OnceLock};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    /// English
+    En,
+    /// Español
+    Es,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+// The language from the usual locale variables, e.g. `es_MX.UTF-8`
+fn from_env() -> Lang {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map_or(Lang::En, |value| {
+            if value.starts_with("es") {
+                Lang::Es
+            } else {
+                Lang::En
+            }
+        })
+}
+
+// Only the first call has any effect
+pub fn set(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+fn lang() -> Lang {
+    *LANG.get_or_init(from_env)
+}
+
+// Key, English, Spanish. `{name}` marks where an argument goes.
+const MESSAGES: &[(&str, &str, &str)] = &[
+    // Progress and reports
+    (
+        "receiving-from",
+        "Receiving from {peer}",
+        "Recibiendo de {peer}",
+    ),
+    (
+        "listening",
+        "Listening on {addr} for pushed transfers",
+        "Escuchando en {addr} a la espera de transferencias",
+    ),
+    (
+        "multicast-joined",
+        "Listening to multicast group {group} for pushed transfers",
+        "Escuchando el grupo multicast {group} a la espera de transferencias",
+    ),
+    (
+        "failing-over",
+        "Switching to {server}",
+        "Cambiando a {server}",
+    ),
+    (
+        "report-failover",
+        "  failed over to {server} ({reason})",
+        "  se cambió a {server} ({reason})",
+    ),
+    (
+        "report-acks",
+        "  sent {acks} acknowledgements",
+        "  se enviaron {acks} acuses de recibo",
+    ),
+    (
+        "report-renamed",
+        "  file {file_id} came as {from} and was written as {to}, by what's in it",
+        "  el archivo {file_id} llegó como {from} y se escribió como {to}, por su contenido",
+    ),
+    (
+        "report-idle",
+        "  went quiet for --idle-after {count} times",
+        "  se quedó en silencio para --idle-after {count} veces",
+    ),
+    (
+        "report-nacks",
+        "  {nacks} rounds of retransmission requests asked for {packets} missing packets",
+        "  {nacks} rondas de solicitudes de retransmisión pidieron {packets} paquetes que faltaban",
+    ),
+    (
+        "report-resumed",
+        "  {packets} packets were picked up from an earlier run (--resume)",
+        "  {packets} paquetes se recuperaron de una ejecución anterior (--resume)",
+    ),
+    (
+        "report-resume-tokens",
+        "  {requests} repeat requests carried a resume token",
+        "  {requests} solicitudes repetidas llevaron un token de reanudación",
+    ),
+    (
+        "report-skipped",
+        "  {packets} packets for files not selected ({files} files left out by name)",
+        "  {packets} paquetes de archivos no seleccionados ({files} archivos descartados por nombre)",
+    ),
+    (
+        "report-keepalives",
+        "  {count} empty keepalive packets",
+        "  {count} paquetes vacíos de keepalive",
+    ),
+    (
+        "report-past-buffer",
+        "  {count} data packets past --stdout-buffer dropped, to be asked for again",
+        "  se descartaron {count} paquetes de datos más allá de --stdout-buffer, para pedirlos de nuevo",
+    ),
+    (
+        "report-duplicates",
+        "  {count} duplicate data packets dropped",
+        "  se descartaron {count} paquetes de datos duplicados",
+    ),
+    (
+        "report-out-of-order",
+        "  {count} data packets came after one numbered higher",
+        "  {count} paquetes de datos llegaron después de uno de número mayor",
+    ),
+    (
+        "report-conflicting-duplicates",
+        "  {count} of them for file {file_id} differed from the copy kept",
+        "  {count} de ellos del archivo {file_id} no coincidían con la copia guardada",
+    ),
+    (
+        "report-duplicate-headers",
+        "  {count} repeated headers",
+        "  {count} encabezados repetidos",
+    ),
+    (
+        "report-simulation",
+        "  simulated network dropped {dropped}, duplicated {duplicated} and reordered {reordered} datagrams (--simulate-seed {seed})",
+        "  la red simulada descartó {dropped}, duplicó {duplicated} y reordenó {reordered} datagramas (--simulate-seed {seed})",
+    ),
+    (
+        "report-simulated-drops",
+        "  threw away {count} datagrams for --drop-every",
+        "  se descartaron {count} datagramas por --drop-every",
+    ),
+    (
+        "report-received",
+        "Received {packets} packets ({bytes}) in {elapsed}, {rate}",
+        "Recibidos {packets} paquetes ({bytes}) en {elapsed}, {rate}",
+    ),
+    (
+        "report-rate-limit",
+        "  {percent}% of the --limit-rate of {limit}",
+        "  {percent}% del --limit-rate de {limit}",
+    ),
+    (
+        "report-held-back",
+        "  {count} ACK or NACK datagrams waited for room under --limit-rate",
+        "  {count} datagramas ACK o NACK esperaron hueco bajo --limit-rate",
+    ),
+    (
+        "report-started",
+        "  started at {time}",
+        "  comenzó a las {time}",
+    ),
+    ("report-interrupted", "  ended by Ctrl-C", "  terminada por Ctrl-C"),
+    (
+        "report-ended-by",
+        "  ended by {criterion}",
+        "  terminada por {criterion}",
+    ),
+    (
+        "log-packet",
+        "from {from}: {packet}",
+        "de {from}: {packet}",
+    ),
+    (
+        "log-duplicate",
+        "warning: packet {packet} of file {file_id} came again",
+        "aviso: el paquete {packet} del archivo {file_id} llegó otra vez",
+    ),
+    (
+        "log-file-complete",
+        "file {file_id} ({name}) is complete: {packets} packets",
+        "el archivo {file_id} ({name}) está completo: {packets} paquetes",
+    ),
+    (
+        "report-file",
+        "  file {file_id}: {bytes} in {packets} packets",
+        "  archivo {file_id}: {bytes} en {packets} paquetes",
+    ),
+    (
+        "report-straggler",
+        "  dropped incomplete file {file_id}: {received} of {expected} packets",
+        "  se descartó el archivo incompleto {file_id}: {received} de {expected} paquetes",
+    ),
+    (
+        "report-watchdog",
+        "  watchdog fired at {at}: {rate}",
+        "  el watchdog saltó a los {at}: {rate}",
+    ),
+    (
+        "report-watchdog-resent",
+        ", request re-sent",
+        ", solicitud reenviada",
+    ),
+    (
+        "report-no-drops",
+        "  no packets dropped by the kernel",
+        "  el kernel no descartó ningún paquete",
+    ),
+    (
+        "report-drops",
+        "  the kernel dropped {drops} packets because we fell behind; \
+         try --poll-strategy adaptive or a larger receive buffer",
+        "  el kernel descartó {drops} paquetes porque nos quedamos atrás; \
+         prueba --poll-strategy adaptive o un búfer de recepción más grande",
+    ),
+    (
+        "report-pipeline",
+        "  pipeline: {queued} datagrams queued, at most {high_water} of {capacity} waiting; \
+         the reader found the queue full {full} times",
+        "  cola: {queued} datagramas encolados, como mucho {high_water} de {capacity} en espera; \
+         el lector encontró la cola llena {full} veces",
+    ),
+    (
+        "report-ignored",
+        "  ignored {count} {what}",
+        "  se ignoraron {count} {what}",
+    ),
+    (
+        "report-malformed",
+        "  rejected {count} {what}",
+        "  se rechazaron {count} {what}",
+    ),
+    (
+        "report-capabilities",
+        "  the server offered {offered}; using {agreed}",
+        "  el servidor ofreció {offered}; en uso: {agreed}",
+    ),
+    ("capabilities-none", "nothing", "nada"),
+    (
+        "capabilities-missing",
+        "    turned down, not in this build: {features}",
+        "    rechazado, no está en esta compilación: {features}",
+    ),
+    (
+        "capabilities-declined",
+        "    turned down, not turned on for this run: {features}",
+        "    rechazado, no activado en esta ejecución: {features}",
+    ),
+    (
+        "capabilities-unoffered",
+        "    turned on here but not offered: {features}",
+        "    activado aquí pero no ofrecido: {features}",
+    ),
+    (
+        "report-protocol",
+        "  speaking protocol version {version}",
+        "  usando la versión {version} del protocolo",
+    ),
+    (
+        "report-echo",
+        "  {answered} of {probes} echo probes answered: round trip {min} at best, \
+         {median} median, {max} at worst, {jitter} jitter",
+        "  {answered} de {probes} sondas de eco respondidas: ida y vuelta {min} como mínimo, \
+         {median} de mediana, {max} como máximo, {jitter} de variación",
+    ),
+    (
+        "report-echo-unanswered",
+        "  none of {probes} echo probes were answered; the server may not reflect them",
+        "  ninguna de las {probes} sondas de eco tuvo respuesta; puede que el servidor no las refleje",
+    ),
+    ("diagnose-title", "Server pacing", "Ritmo del servidor"),
+    (
+        "diagnose-bursts",
+        "  {bursts} bursts (datagrams under {gap} apart), {median} datagrams in the median one, {max} in the largest",
+        "  {bursts} ráfagas (datagramas a menos de {gap}), {median} datagramas en la mediana, {max} en la mayor",
+    ),
+    (
+        "diagnose-gaps",
+        "  gaps between bursts: {median} median, {max} longest",
+        "  pausas entre ráfagas: {median} de mediana, {max} como máximo",
+    ),
+    (
+        "diagnose-no-requests",
+        "  no repeat requests were sent, so resending wasn't tested",
+        "  no se repitió la solicitud, así que no se probó el reenvío",
+    ),
+    (
+        "diagnose-no-resends",
+        "  nothing was resent after any of {requests} repeat requests",
+        "  no se reenvió nada tras ninguna de las {requests} solicitudes repetidas",
+    ),
+    (
+        "diagnose-resends",
+        "  resent after {answered} of {requests} repeat requests: {median} median, {max} slowest",
+        "  reenvió tras {answered} de {requests} solicitudes repetidas: {median} de mediana, {max} como máximo",
+    ),
+    (
+        "diagnose-sizes",
+        "  payload sizes in bytes:",
+        "  tamaños de carga útil en bytes:",
+    ),
+    (
+        "report-crc",
+        "  {corrupt} of {checked} packets failed their CRC; \
+         checks took {average} on average, {max} at most",
+        "  {corrupt} de {checked} paquetes fallaron el CRC; \
+         las comprobaciones tardaron {average} de media, {max} como máximo",
+    ),
+    ("grade-server", "== {name} ({addr})", "== {name} ({addr})"),
+    ("grade-pass", "PASS", "APROBADO"),
+    ("grade-fail", "FAIL: {error}", "SUSPENSO: {error}"),
+    (
+        "grade-mismatch",
+        "FAIL: files don't match the expected files",
+        "SUSPENSO: los archivos no coinciden con los esperados",
+    ),
+    (
+        "grade-summary",
+        "{passed}/{total} servers passed; summary in {dir}",
+        "{passed}/{total} servidores aprobados; resumen en {dir}",
+    ),
+    // Warnings
+    (
+        "cpu-pin-failed",
+        "warning: couldn't pin the receive thread to CPU {cpu}: {error}",
+        "aviso: no se pudo fijar el hilo de recepción a la CPU {cpu}: {error}",
+    ),
+    (
+        "priority-failed",
+        "warning: couldn't set the receive thread's priority to {nice}: {error}",
+        "aviso: no se pudo cambiar la prioridad del hilo de recepción a {nice}: {error}",
+    ),
+    (
+        "priority-needs-root",
+        " (raising priority needs root or CAP_SYS_NICE)",
+        " (subir la prioridad requiere root o CAP_SYS_NICE)",
+    ),
+    (
+        "no-drop-counter",
+        "warning: can't count kernel-level drops: {error}",
+        "aviso: no se pueden contar los descartes del kernel: {error}",
+    ),
+    (
+        "recv-buffer-failed",
+        "warning: can't set the socket receive buffer to {asked}: {error}",
+        "aviso: no se puede fijar el búfer de recepción del socket en {asked}: {error}",
+    ),
+    (
+        "recv-buffer-capped",
+        "warning: asked for a {asked} socket receive buffer but got {got}; raise net.core.rmem_max for more",
+        "aviso: se pidió un búfer de recepción de {asked} pero se obtuvo {got}; sube net.core.rmem_max para más",
+    ),
+    (
+        "ignoring-packet",
+        "Ignoring packet from {from}: {message}",
+        "Ignorando paquete de {from}: {message}",
+    ),
+    (
+        "last-packet-moved",
+        "file {file_id}: packet {packet} says it's the last, but so did packet {earlier}; the file runs to {packet}, and needs every packet up to it",
+        "archivo {file_id}: el paquete {packet} dice ser el último, pero también lo dijo el paquete {earlier}; el archivo llega hasta {packet}, y necesita todos los paquetes hasta él",
+    ),
+    (
+        "duplicate-conflicts",
+        "warning: packet {packet} of file {file_id} arrived again with different contents; keeping the first copy (and counting any more for this file)",
+        "aviso: el paquete {packet} del archivo {file_id} llegó de nuevo con otro contenido; se guarda la primera copia (y se cuentan los demás de este archivo)",
+    ),
+    (
+        "header-renamed",
+        "warning: file {file_id} was named {earlier}, now {name}; using {name}",
+        "aviso: el archivo {file_id} se llamaba {earlier}, ahora {name}; se usa {name}",
+    ),
+    (
+        "header-renamed-kept",
+        "warning: file {file_id} was named {earlier}, now {name}; keeping {earlier}",
+        "aviso: el archivo {file_id} se llamaba {earlier}, ahora {name}; se mantiene {earlier}",
+    ),
+    (
+        "skipping-malformed",
+        "warning: skipping a malformed packet from {from} (and counting any more like it): {message}",
+        "aviso: se omite un paquete mal formado de {from} (y se cuentan los demás así): {message}",
+    ),
+    (
+        "ignoring-first",
+        "warning: ignoring {what} (first from file {file_id})",
+        "aviso: ignorando {what} (el primero, del archivo {file_id})",
+    ),
+    (
+        "spill-fallback",
+        "warning: keeping file {file_id} in memory: {error}",
+        "aviso: el archivo {file_id} se queda en memoria: {error}",
+    ),
+    (
+        "max-memory-passed",
+        "warning: {held} of payloads in memory, past --max-memory, and none of it can go to disk",
+        "aviso: {held} de datos en memoria, más que --max-memory, y nada puede ir al disco",
+    ),
+    (
+        "kept-partial-files",
+        "note: kept this session's partial files in {dir}",
+        "nota: los archivos parciales de esta sesión se conservan en {dir}",
+    ),
+    (
+        "malformed-too-short",
+        "datagrams too short to be packets",
+        "datagramas demasiado cortos para ser paquetes",
+    ),
+    (
+        "malformed-bad-utf8",
+        "headers whose name isn't UTF-8",
+        "cabeceras cuyo nombre no es UTF-8",
+    ),
+    (
+        "malformed-data-too-short",
+        "data packets too short for a packet number",
+        "paquetes de datos demasiado cortos para un número de paquete",
+    ),
+    (
+        "malformed-too-long",
+        "datagrams longer than any packet",
+        "datagramas más largos que cualquier paquete",
+    ),
+    (
+        "malformed-forged",
+        "packets that didn't authenticate",
+        "paquetes que no se autenticaron",
+    ),
+    (
+        "psk-not-built",
+        "--psk needs a build with the `crypto` feature",
+        "--psk necesita una compilación con la característica `crypto`",
+    ),
+    (
+        "malformed-bad-checksum",
+        "packets that failed their CRC",
+        "paquetes que fallaron el CRC",
+    ),
+    (
+        "malformed-checksum-length",
+        "checksum packets of the wrong length",
+        "paquetes de suma de comprobación de longitud incorrecta",
+    ),
+    (
+        "malformed-unknown-compression",
+        "headers naming no compression there is",
+        "encabezados con una compresión que no existe",
+    ),
+    (
+        "ignored-past-end",
+        "data packets numbered past their file's last packet",
+        "paquetes de datos numerados más allá del último paquete de su archivo",
+    ),
+    (
+        "ignored-conflicting-last",
+        "last packets contradicting what came before",
+        "últimos paquetes que contradicen lo recibido antes",
+    ),
+    (
+        "ignored-too-high",
+        "packets numbered past a TiB into the file",
+        "paquetes numerados más allá de un TiB dentro del archivo",
+    ),
+    // Errors
+    ("error", "error: {error}", "error: {error}"),
+    (
+        "error-parse",
+        "malformed packet: {message}",
+        "paquete mal formado: {message}",
+    ),
+    (
+        "packet-too-short",
+        "packet too short: {len} bytes",
+        "paquete demasiado corto: {len} bytes",
+    ),
+    (
+        "data-packet-too-short",
+        "data packet for file {file_id} too short: {len} bytes",
+        "paquete de datos del archivo {file_id} demasiado corto: {len} bytes",
+    ),
+    (
+        "checksum-wrong-length",
+        "checksum packet for file {file_id} is {len} bytes, not {expected}",
+        "el paquete de suma de comprobación del archivo {file_id} tiene {len} bytes, no {expected}",
+    ),
+    (
+        "extended-header-too-short",
+        "extended header for file {file_id} is {len} bytes, less than {expected}",
+        "el encabezado extendido del archivo {file_id} tiene {len} bytes, menos de {expected}",
+    ),
+    (
+        "unknown-compression",
+        "header for file {file_id} sets both compression bits",
+        "el encabezado del archivo {file_id} activa los dos bits de compresión",
+    ),
+    (
+        "gzip-not-gzip",
+        "the compressed data isn't gzip",
+        "los datos comprimidos no son gzip",
+    ),
+    (
+        "gzip-corrupt",
+        "the compressed data is corrupt or cut short",
+        "los datos comprimidos están dañados o incompletos",
+    ),
+    (
+        "zstd-corrupt",
+        "the compressed data isn't zstd, or is corrupt or cut short",
+        "los datos comprimidos no son zstd, o están dañados o incompletos",
+    ),
+    (
+        "compression-not-built-in",
+        "this build can't unpack {method}",
+        "esta compilación no puede descomprimir {method}",
+    ),
+    (
+        "datagram-too-long",
+        "datagram of {len} bytes is longer than any packet ({limit})",
+        "datagrama de {len} bytes, más largo que cualquier paquete ({limit})",
+    ),
+    (
+        "datagram-cut-short",
+        "datagram didn't fit in the {buffer}-byte receive buffer and was cut short (--max-packet-size)",
+        "el datagrama no cabía en el búfer de recepción de {buffer} bytes y se cortó (--max-packet-size)",
+    ),
+    (
+        "invalid-utf8",
+        "file {file_id}'s name isn't valid UTF-8",
+        "el nombre del archivo {file_id} no es UTF-8 válido",
+    ),
+    (
+        "error-server-unreachable",
+        "the server didn't answer within {timeout} ({requests} hellos sent)",
+        "el servidor no respondió en {timeout} ({requests} saludos enviados)",
+    ),
+    (
+        "error-nothing-discovered",
+        "no server answered --discover at {target} within {wait}",
+        "ningún servidor respondió a --discover en {target} en {wait}",
+    ),
+    (
+        "discover-found",
+        "{n}. {addr}: {files} files ({names})",
+        "{n}. {addr}: {files} archivos ({names})",
+    ),
+    (
+        "discover-more",
+        " and {count} more",
+        " y {count} más",
+    ),
+    (
+        "discover-pick",
+        "which server? [1-{count}] ",
+        "¿qué servidor? [1-{count}] ",
+    ),
+    (
+        "discover-not-a-choice",
+        "\"{choice}\" isn't one of them",
+        "\"{choice}\" no es ninguno de ellos",
+    ),
+    (
+        "discover-no-choice",
+        "no server was picked",
+        "no se eligió ningún servidor",
+    ),
+    (
+        "error-first-packet-timeout",
+        "no usable packet arrived within {timeout} ({datagrams} datagrams discarded)",
+        "no llegó ningún paquete utilizable en {timeout} ({datagrams} datagramas descartados)",
+    ),
+    (
+        "error-transfer-idle",
+        "nothing arrived for {idle} with {files} files still incomplete",
+        "no llegó nada en {idle} con {files} archivos aún incompletos",
+    ),
+    (
+        "error-file-stalled",
+        "file {file_id} got no packets for {stalled} after {received} of {expected}",
+        "el archivo {file_id} no recibió paquetes en {stalled} tras {received} de {expected}",
+    ),
+    (
+        "error-session-timeout",
+        "the session didn't finish within {timeout} ({packets} packets received)",
+        "la sesión no terminó en {timeout} ({packets} paquetes recibidos)",
+    ),
+    (
+        "error-too-much-data",
+        "the server sent more than {limit}",
+        "el servidor envió más de {limit}",
+    ),
+    (
+        "error-partial-write",
+        "wrote {written} files, but {failed} couldn't be written",
+        "se escribieron {written} archivos, pero {failed} no se pudieron escribir",
+    ),
+    (
+        "error-interrupted",
+        "interrupted: wrote {written} complete files and kept {partial} partial ones",
+        "interrumpido: se escribieron {written} archivos completos y se guardaron {partial} parciales",
+    ),
+    (
+        "partial-kept",
+        "kept file {file_id} as {path}: {received} packets, {missing} known missing",
+        "se guardó el archivo {file_id} como {path}: {received} paquetes, faltan al menos {missing}",
+    ),
+    (
+        "error-checksum-mismatch",
+        "{files} files didn't match their checksum packets",
+        "{files} archivos no coincidieron con sus paquetes de suma de comprobación",
+    ),
+    ("progress-unnamed", "file {file_id}", "archivo {file_id}"),
+    (
+        "plan-heading",
+        "--plan: writing {count} files into {dir} would",
+        "--plan: escribir {count} archivos en {dir} haría esto:",
+    ),
+    ("plan-dir", "  make directory {path}", "  crearía el directorio {path}"),
+    ("plan-new", "  write {path} ({size})", "  escribiría {path} ({size})"),
+    (
+        "plan-overwrite",
+        "  overwrite {path} ({size})",
+        "  sobrescribiría {path} ({size})",
+    ),
+    (
+        "plan-kept",
+        "  leave {path} as it is, and not write file {file_id}",
+        "  dejaría {path} como está, sin escribir el archivo {file_id}",
+    ),
+    (
+        "plan-beside",
+        "  write {path} ({size}), leaving {existing} as it is",
+        "  escribiría {path} ({size}), dejando {existing} como está",
+    ),
+    (
+        "plan-beside-compare",
+        "  write {path} ({size}), unless it's the same as {existing}",
+        "  escribiría {path} ({size}), salvo que sea igual a {existing}",
+    ),
+    (
+        "plan-refused",
+        "  fail on file {file_id}: {path} is already there",
+        "  fallaría con el archivo {file_id}: {path} ya existe",
+    ),
+    ("plan-nothing", "  nothing: no file is complete", "  nada: ningún archivo está completo"),
+    ("dashboard-file", "file", "arch."),
+    ("dashboard-name", "name", "nombre"),
+    ("dashboard-packets", "packets", "paquetes"),
+    ("dashboard-rate", "rate", "velocidad"),
+    ("dashboard-duplicates", "dups", "dupl."),
+    ("dashboard-missing", "missing", "faltan"),
+    (
+        "dashboard-waiting",
+        "waiting for the first packet",
+        "esperando el primer paquete",
+    ),
+    (
+        "dashboard-summary",
+        "from {peer}, {elapsed}: {packets} packets, {duplicates} duplicates, {rate}",
+        "desde {peer}, {elapsed}: {packets} paquetes, {duplicates} duplicados, {rate}",
+    ),
+    (
+        "dashboard-rows",
+        "files {first}-{last} of {files}, arrow keys to scroll",
+        "archivos {first}-{last} de {files}, flechas para desplazarse",
+    ),
+    (
+        "dashboard-paused",
+        "paused, space to go on",
+        "en pausa, espacio para seguir",
+    ),
+    (
+        "idle-report",
+        "no packets for {idle}, the last at {last}; {files} files still incomplete:",
+        "sin paquetes en {idle}, el último a las {last}; {files} archivos aún incompletos:",
+    ),
+    (
+        "failure-report",
+        "when it failed, {complete} files were complete (none written) and {partial} partial:",
+        "al fallar, {complete} archivos estaban completos (sin escribir) y {partial} parciales:",
+    ),
+    (
+        "failure-complete",
+        "  file {file_id} ({name}): complete, {received} packets",
+        "  archivo {file_id} ({name}): completo, {received} paquetes",
+    ),
+    (
+        "idle-file",
+        "  file {file_id} ({name}): {received} of {expected} packets, {missing} missing: {ranges}",
+        "  archivo {file_id} ({name}): {received} de {expected} paquetes, faltan {missing}: {ranges}",
+    ),
+    (
+        "idle-file-no-gaps",
+        "  file {file_id} ({name}): {received} of {expected} packets, none missing before the last one seen",
+        "  archivo {file_id} ({name}): {received} de {expected} paquetes, ninguno falta antes del último visto",
+    ),
+    ("idle-no-header", "no header yet", "sin cabecera aún"),
+    (
+        "progress-file",
+        "  {name}: {received}/{expected} packets ({percent}%), {bytes}",
+        "  {name}: {received}/{expected} paquetes ({percent}%), {bytes}",
+    ),
+    (
+        "progress-file-unknown",
+        "  {name}: {received} packets so far, {bytes}",
+        "  {name}: {received} paquetes hasta ahora, {bytes}",
+    ),
+    (
+        "progress-file-done",
+        "  {name}: done, {received} packets, {bytes}",
+        "  {name}: listo, {received} paquetes, {bytes}",
+    ),
+    (
+        "snapshot-failed",
+        "warning: couldn't write progress.json, so it won't be kept up to date: {error}",
+        "aviso: no se pudo escribir progress.json, así que no se mantendrá al día: {error}",
+    ),
+    (
+        "header-never-came",
+        "warning: file {file_id} arrived without its header; writing it as {name}",
+        "aviso: el archivo {file_id} llegó sin su cabecera; se escribe como {name}",
+    ),
+    (
+        "checksum-mismatch",
+        "file {file_id} ({path}) doesn't match its checksum packet",
+        "el archivo {file_id} ({path}) no coincide con su paquete de suma de comprobación",
+    ),
+    (
+        "write-skipped",
+        "skipped file {file_id}: {path} is already there",
+        "se omitió el archivo {file_id}: {path} ya existe",
+    ),
+    (
+        "file-exists",
+        "{path} is already there (see --on-conflict)",
+        "{path} ya existe (véase --on-conflict)",
+    ),
+    (
+        "write-failed",
+        "couldn't write file {file_id}: {error}",
+        "no se pudo escribir el archivo {file_id}: {error}",
+    ),
+    (
+        "error-insufficient-space",
+        "not enough room in {dir}: the headers so far add up to {needed}, and there's \
+         {available} free",
+        "no hay sitio en {dir}: las cabeceras recibidas suman {needed} y quedan \
+         {available} libres",
+    ),
+    (
+        "error-manifest-mismatch",
+        "the output differs from --expect in {problems} ways",
+        "la salida difiere de --expect en {problems} puntos",
+    ),
+    (
+        "scenario-bad",
+        "not a valid scenario: {error}",
+        "no es un escenario válido: {error}",
+    ),
+    (
+        "scenario-packet-size",
+        "file {file} has packet_size {size}; it must be from 1 to {max}",
+        "el archivo {file} tiene packet_size {size}; debe estar entre 1 y {max}",
+    ),
+    (
+        "scenario-no-file",
+        "no file {file} in the scenario",
+        "no hay archivo {file} en el escenario",
+    ),
+    (
+        "scenario-no-packet",
+        "file {file} has no packet {packet} (it has {count})",
+        "el archivo {file} no tiene paquete {packet} (tiene {count})",
+    ),
+    (
+        "scenario-no-byte",
+        "packet {packet} of file {file} has no byte {byte} to corrupt",
+        "el paquete {packet} del archivo {file} no tiene byte {byte} que corromper",
+    ),
+    (
+        "scenario-bad-delay",
+        "bad delay `{delay}`: {error}",
+        "retardo `{delay}` no válido: {error}",
+    ),
+    (
+        "scenario-bad-raw",
+        "raw datagram `{raw}` isn't hex",
+        "el datagrama `{raw}` no está en hexadecimal",
+    ),
+    (
+        "scenario-serving",
+        "waiting for a request on {addr}",
+        "esperando una solicitud en {addr}",
+    ),
+    (
+        "serve-files-too-many",
+        "{count} files is more than the 256 file IDs there are",
+        "{count} archivos son más que los 256 ID de archivo que hay",
+    ),
+    (
+        "serve-files-bad-name",
+        "{path}: the file name isn't UTF-8, so it can't go in a header",
+        "{path}: el nombre del archivo no es UTF-8, así que no cabe en una cabecera",
+    ),
+    (
+        "serve-files-serving",
+        "serving {count} files on {addr}",
+        "sirviendo {count} archivos en {addr}",
+    ),
+    (
+        "catalog-bad",
+        "{path} isn't a manifest this client wrote: {error}",
+        "{path} no es un manifiesto escrito por este cliente: {error}",
+    ),
+    (
+        "manifest-bad",
+        "{path} isn't a valid --expect manifest: {error}",
+        "{path} no es un manifiesto de --expect válido: {error}",
+    ),
+    (
+        "manifest-unknown-hash",
+        "{path}: {name} has a hash from an unknown algorithm: {hash}",
+        "{path}: {name} tiene un hash de un algoritmo desconocido: {hash}",
+    ),
+    (
+        "manifest-ok",
+        "All {count} expected files arrived and match",
+        "Llegaron los {count} archivos esperados y coinciden",
+    ),
+    (
+        "manifest-missing",
+        "expected {name}, but it didn't arrive",
+        "se esperaba {name}, pero no llegó",
+    ),
+    (
+        "manifest-extra",
+        "{name} arrived but isn't in the manifest",
+        "llegó {name}, pero no está en el manifiesto",
+    ),
+    (
+        "manifest-wrong-size",
+        "{name} is {actual} bytes, expected {expected}",
+        "{name} tiene {actual} bytes; se esperaban {expected}",
+    ),
+    (
+        "manifest-wrong-hash",
+        "{name} has hash {actual}, expected {expected}",
+        "{name} tiene el hash {actual}; se esperaba {expected}",
+    ),
+    (
+        "error-file-too-large",
+        "file {file_id} is bigger than {limit}",
+        "el archivo {file_id} ocupa más de {limit}",
+    ),
+    (
+        "file-too-large",
+        "file {file_id} comes to more than {limit}, so it wasn't written",
+        "el archivo {file_id} ocupa más de {limit}, así que no se escribió",
+    ),
+    (
+        "error-too-many-files",
+        "the server sent more than {limit} files",
+        "el servidor envió más de {limit} archivos",
+    ),
+    (
+        "error-renamed-file",
+        "file {file_id} was named {first}, then {second}",
+        "el archivo {file_id} se llamó {first} y luego {second}",
+    ),
+    (
+        "error-bad-file-name",
+        "file {file_id} has an unusable name: {problem}",
+        "el archivo {file_id} tiene un nombre inutilizable: {problem}",
+    ),
+    (
+        "name-empty",
+        "the file name is empty",
+        "el nombre del archivo está vacío",
+    ),
+    (
+        "name-too-long",
+        "the file name is {len} bytes, over the {limit}-byte limit",
+        "el nombre del archivo ocupa {len} bytes, más del límite de {limit}",
+    ),
+    (
+        "name-separator",
+        "the file name has a path separator in it",
+        "el nombre del archivo tiene un separador de ruta",
+    ),
+    (
+        "name-absolute",
+        "the path starts at the root, not in the output directory",
+        "la ruta empieza en la raíz, no en el directorio de salida",
+    ),
+    (
+        "name-parent-dir",
+        "the path goes up out of a directory with ..",
+        "la ruta sube fuera de un directorio con ..",
+    ),
+    (
+        "name-not-a-file",
+        "the file name is . or .., which name a directory",
+        "el nombre del archivo es . o .., que nombran un directorio",
+    ),
+    (
+        "header-name-lossy",
+        "file {file_id}'s name isn't UTF-8, which names have to be here; writing it as {name}",
+        "el nombre del archivo {file_id} no es UTF-8, como deben ser aquí; se escribe como {name}",
+    ),
+    (
+        "sealed-name-unopened",
+        "warning: file {file_id}'s name didn't open with the --psk key; writing it as {name}",
+        "aviso: el nombre del archivo {file_id} no se abrió con la clave de --psk; se escribe como {name}",
+    ),
+    (
+        "header-path-stripped",
+        "warning: file {file_id}'s name {name} has directories in it; writing it as {base} (--tree keeps them)",
+        "aviso: el nombre {name} del archivo {file_id} incluye directorios; se escribe como {base} (--tree los conserva)",
+    ),
+    (
+        "output-dir-grade",
+        "grading writes each server's files under its own output directory",
+        "al calificar, los archivos de cada servidor van en su propio directorio de salida",
+    ),
+    (
+        "name-nul",
+        "the file name contains a NUL byte",
+        "el nombre del archivo contiene un byte NUL",
+    ),
+    (
+        "error-deadline",
+        "the deadline passed before the run was over",
+        "el plazo venció antes de que terminara la ejecución",
+    ),
+    (
+        "deadline-unwritten",
+        "the deadline passed before it could be written",
+        "el plazo venció antes de poder escribirlo",
+    ),
+    (
+        "error-audit-broken",
+        "the audit log is broken at line {line}: {problem}",
+        "el registro de auditoría está roto en la línea {line}: {problem}",
+    ),
+    (
+        "error-bad-options",
+        "the client can't be set up like that:\n{problems}",
+        "el cliente no se puede configurar así:\n{problems}",
+    ),
+    (
+        "hook-refused-earlier",
+        "not written, since a hook turned down an earlier file",
+        "no se escribió, porque un gancho rechazó un archivo anterior",
+    ),
+    (
+        "error-hook",
+        "the {hook} hook stopped the transfer: {error}",
+        "el gancho {hook} detuvo la transferencia: {error}",
+    ),
+    (
+        "audit-malformed",
+        "it isn't an audit entry",
+        "no es una entrada de auditoría",
+    ),
+    (
+        "audit-out-of-sequence",
+        "entry {expected} should be next, not {found}",
+        "tocaba la entrada {expected}, no la {found}",
+    ),
+    (
+        "audit-wrong-prev",
+        "it doesn't follow on from the entry before",
+        "no sigue a la entrada anterior",
+    ),
+    (
+        "audit-wrong-hash",
+        "it has been changed since it was written",
+        "se ha modificado desde que se escribió",
+    ),
+    (
+        "chown-empty",
+        "expected `user:group`, `user`, or `:group`",
+        "se esperaba `usuario:grupo`, `usuario` o `:grupo`",
+    ),
+    ("chown-no-user", "no user named {name}", "no hay ningún usuario llamado {name}"),
+    ("chown-no-group", "no group named {name}", "no hay ningún grupo llamado {name}"),
+    (
+        "chown-names-linux-only",
+        "users and groups can only be looked up by name on Linux; give their numbers",
+        "los usuarios y grupos solo se pueden buscar por nombre en Linux; indica sus números",
+    ),
+    (
+        "umask-not-octal",
+        "expected an octal mask from 000 to 777",
+        "se esperaba una máscara octal de 000 a 777",
+    ),
+    (
+        "pack-wrote",
+        "wrote {packets} packets into {dir}",
+        "se escribieron {packets} paquetes en {dir}",
+    ),
+    (
+        "unpack-wrote",
+        "file {file_id}: {path}",
+        "archivo {file_id}: {path}",
+    ),
+    (
+        "unpack-bad-packet",
+        "{path} isn't a packet: {error}",
+        "{path} no es un paquete: {error}",
+    ),
+    (
+        "history-bad",
+        "line {line} of history file {path} can't be read: {error}",
+        "no se puede leer la línea {line} del historial {path}: {error}",
+    ),
+    (
+        "report-no-history",
+        "report needs --history, the file sessions were recorded in",
+        "report necesita --history, el archivo en que se registraron las sesiones",
+    ),
+    (
+        "report-page-wrote",
+        "wrote {path}",
+        "se escribió {path}",
+    ),
+    (
+        "report-open-failed",
+        "couldn't show {path} in a browser ({status})",
+        "no se pudo mostrar {path} en un navegador ({status})",
+    ),
+    (
+        "pipe-to-failed",
+        "--pipe-to {command} failed: {status}",
+        "--pipe-to {command} falló: {status}",
+    ),
+    ("report-page-title", "Transfers", "Transferencias"),
+    (
+        "report-page-empty",
+        "No sessions in the history yet.",
+        "Todavía no hay sesiones en el historial.",
+    ),
+    (
+        "report-page-latest",
+        "Latest session, started {started}",
+        "Última sesión, empezada el {started}",
+    ),
+    (
+        "report-page-summary",
+        "{files} files, {bytes} in {elapsed} at {rate}, {loss}% lost",
+        "{files} archivos, {bytes} en {elapsed} a {rate}, {loss}% perdido",
+    ),
+    ("report-page-throughput", "Throughput", "Rendimiento"),
+    ("report-page-files", "Files", "Archivos"),
+    ("report-page-sessions", "Every session", "Todas las sesiones"),
+    ("report-page-rate", "Rate by session", "Velocidad por sesión"),
+    ("report-page-loss", "Loss by session", "Pérdida por sesión"),
+    ("report-page-started", "started", "empezada"),
+    ("report-page-took", "took", "duró"),
+    ("report-page-file-count", "files", "archivos"),
+    ("report-page-bytes", "bytes", "bytes"),
+    ("report-page-speed", "rate", "velocidad"),
+    ("report-page-lost", "lost", "perdido"),
+    (
+        "unpack-incomplete",
+        "file {file_id} is missing its header or packets ({missing} known missing), so it wasn't written",
+        "al archivo {file_id} le faltan su encabezado o paquetes ({missing} que se sepa), así que no se escribió",
+    ),
+    (
+        "audit-intact",
+        "{entries} entries, chain intact; last hash {hash}",
+        "{entries} entradas, cadena intacta; último hash {hash}",
+    ),
+    (
+        "audit-bad-tail",
+        "{path} doesn't end in an audit entry; run verify-audit on it",
+        "{path} no termina en una entrada de auditoría; compruébelo con verify-audit",
+    ),
+    ("io-failed", "couldn't {action} {path}: {error}", "no se pudo {action} {path}: {error}"),
+    ("io-create", "create", "crear"),
+    ("io-open", "open", "abrir"),
+    ("io-read", "read", "leer"),
+    ("io-write", "write to", "escribir en"),
+    ("io-resize", "resize", "cambiar el tamaño de"),
+    ("io-map", "map", "mapear"),
+    ("io-flush", "flush", "volcar"),
+    ("io-sync", "sync", "sincronizar"),
+    ("io-move", "move", "mover"),
+    ("io-copy", "copy", "copiar"),
+    ("io-remove", "remove", "borrar"),
+    ("io-create-dir", "create directory", "crear el directorio"),
+    ("io-chown", "change the owner of", "cambiar el propietario de"),
+    ("io-set-times", "set the times of", "cambiar las fechas de"),
+    ("io-chmod", "change the permissions of", "cambiar los permisos de"),
+    // Option checks
+    (
+        "failover-one-server",
+        "--failover needs more than one --server to fall back on",
+        "--failover necesita más de un --server al que recurrir",
+    ),
+    (
+        "servers-without-failover",
+        "only the first --server is used without --failover",
+        "sin --failover solo se usa el primer --server",
+    ),
+    (
+        "servers-without-failover-hint",
+        "add --failover to fall back on the others",
+        "añade --failover para recurrir a los demás",
+    ),
+    (
+        "servers-listen-only",
+        "nothing is requested when listening, so --server has no effect",
+        "al escuchar no se pide nada, así que --server no tiene efecto",
+    ),
+    ("label-error", "error", "error"),
+    ("label-warning", "warning", "aviso"),
+    ("label-help", "help", "ayuda"),
+    (
+        "watchdog-window-empty",
+        "the watchdog window can't be empty",
+        "la ventana del watchdog no puede estar vacía",
+    ),
+    (
+        "watchdog-window-hint",
+        "use a window such as 5s",
+        "usa una ventana como 5s",
+    ),
+    (
+        "deadline-zero",
+        "a deadline of 0 would end every run before it starts",
+        "un plazo de 0 terminaría cada ejecución antes de empezar",
+    ),
+    (
+        "session-timeout-zero",
+        "a session timeout of 0 would end every session before it starts",
+        "un tiempo límite de 0 terminaría cada sesión antes de empezar",
+    ),
+    (
+        "min-runtime-past-timeout",
+        "a minimum runtime of {min_runtime} means the {timeout} session timeout always fires first",
+        "con una duración mínima de {min_runtime} el tiempo límite de {timeout} salta siempre antes",
+    ),
+    (
+        "timeout-zero",
+        "a {option} of 0 would fire before anything could arrive",
+        "un {option} de 0 saltaría antes de que pudiera llegar nada",
+    ),
+    (
+        "nack-listen-only",
+        "nothing was requested when listening, so there's nobody to ask for resends",
+        "al escuchar no se pide nada, así que no hay a quién pedir reenvíos",
+    ),
+    (
+        "hello-interval-zero",
+        "a --hello-interval of 0 would send hellos nonstop until the server answered",
+        "un --hello-interval de 0 enviaría saludos sin parar hasta que respondiera el servidor",
+    ),
+    (
+        "ack-listen-only",
+        "nothing was requested when listening, so there's nobody to send acknowledgements to",
+        "al escuchar no se pide nada, así que no hay a quién enviar acuses de recibo",
+    ),
+    (
+        "ack-interval-zero",
+        "an --ack-interval of 0 would send acknowledgements nonstop",
+        "un --ack-interval de 0 enviaría acuses de recibo sin parar",
+    ),
+    (
+        "state-cut-short",
+        "a record cut short",
+        "un registro incompleto",
+    ),
+    (
+        "state-cut-crc",
+        "a record whose CRC doesn't match",
+        "un registro cuyo CRC no coincide",
+    ),
+    (
+        "state-cut-not-a-packet",
+        "a record that isn't a packet",
+        "un registro que no es un paquete",
+    ),
+    (
+        "state-log-cut",
+        "warning: dropping the last {bytes} bytes of {path}, from {why} on; carrying on from the {count} packets before it",
+        "aviso: se descartan los últimos {bytes} bytes de {path}, desde {why}; se sigue con los {count} paquetes anteriores",
+    ),
+    (
+        "state-not-a-log",
+        "{path} isn't a --resume log",
+        "{path} no es un registro de --resume",
+    ),
+    (
+        "state-other-server",
+        "{path} is the --resume log of a transfer from {server}",
+        "{path} es el registro de --resume de una transferencia desde {server}",
+    ),
+    (
+        "state-log-refused",
+        "{what}; --force starts it over",
+        "{what}; --force lo empieza de nuevo",
+    ),
+    (
+        "state-log-replaced",
+        "warning: {what}, and --force starts it over",
+        "aviso: {what}, y --force lo empieza de nuevo",
+    ),
+    (
+        "resume-grade",
+        "grading runs each server on its own, so there's no one transfer to resume",
+        "al calificar cada servidor va por separado, así que no hay una transferencia que reanudar",
+    ),
+    (
+        "headerless-name-bad",
+        "--headerless-name {name} isn't a file name that can be written in the output directory",
+        "--headerless-name {name} no es un nombre de archivo que se pueda escribir en el directorio de salida",
+    ),
+    (
+        "limit-rate-zero",
+        "a --limit-rate of 0 would never let anything through",
+        "un --limit-rate de 0 nunca dejaría pasar nada",
+    ),
+    (
+        "write-threads-grade",
+        "grading writes each server's files into a directory of its own once they're all in",
+        "al calificar, los archivos de cada servidor se escriben en su propio directorio cuando han llegado todos",
+    ),
+    (
+        "snapshot-interval-zero",
+        "a --snapshot-interval of 0 would rewrite progress.json nonstop",
+        "un --snapshot-interval de 0 reescribiría progress.json sin parar",
+    ),
+    (
+        "nack-after-zero",
+        "a --nack-after of 0 would send retransmission requests nonstop",
+        "un --nack-after de 0 enviaría solicitudes de retransmisión sin parar",
+    ),
+    (
+        "connect-timeout-listen-only",
+        "nothing is requested when listening, so there's no reply to wait for",
+        "al escuchar no se pide nada, así que no hay respuesta que esperar",
+    ),
+    (
+        "connect-timeout-listen-only-hint",
+        "use --first-packet-timeout to bound the wait for a pushed transfer",
+        "usa --first-packet-timeout para limitar la espera de una transferencia",
+    ),
+    (
+        "max-total-bytes-small",
+        "{size} is smaller than a single packet",
+        "{size} es menos que un solo paquete",
+    ),
+    (
+        "max-total-bytes-hint",
+        "allow at least {size}",
+        "permite al menos {size}",
+    ),
+    (
+        "max-packet-size-small",
+        "{size} bytes is shorter than the longest packet",
+        "{size} bytes es menos que el paquete más largo",
+    ),
+    (
+        "max-packet-size-hint",
+        "allow at least {size} bytes",
+        "permite al menos {size} bytes",
+    ),
+    (
+        "min-rate-zero",
+        "a minimum rate of 0 means the watchdog never fires",
+        "con una tasa mínima de 0 el watchdog nunca salta",
+    ),
+    (
+        "min-rate-zero-hint",
+        "drop the option to turn the watchdog off, or pick a positive rate",
+        "quita la opción para desactivar el watchdog, o elige una tasa positiva",
+    ),
+    (
+        "min-rate-listen-only",
+        "pushed transfers can't be re-requested, so the watchdog only records slow windows",
+        "las transferencias enviadas no se pueden volver a pedir, así que el watchdog \
+         solo registra las ventanas lentas",
+    ),
+    (
+        "window-without-rate",
+        "the watchdog window has no effect without a minimum rate",
+        "la ventana del watchdog no tiene efecto sin una tasa mínima",
+    ),
+    (
+        "window-without-rate-hint",
+        "add --watchdog-min-rate <RATE> to turn the watchdog on",
+        "añade --watchdog-min-rate <RATE> para activar el watchdog",
+    ),
+    (
+        "server-no-port",
+        "{addr} has no port; expected HOST:PORT, e.g. 127.0.0.1:6014",
+        "{addr} no tiene puerto; se esperaba HOST:PUERTO, p. ej. 127.0.0.1:6014",
+    ),
+    (
+        "server-bad-port",
+        "{port} isn't a port; expected a number from 1 to 65535",
+        "{port} no es un puerto; se esperaba un número del 1 al 65535",
+    ),
+    (
+        "server-no-host",
+        "{addr} has no host; expected HOST:PORT, e.g. 127.0.0.1:6014",
+        "{addr} no tiene host; se esperaba HOST:PUERTO, p. ej. 127.0.0.1:6014",
+    ),
+    (
+        "serve-client-failed",
+        "a client went away partway: {error}",
+        "un cliente se fue a medias: {error}",
+    ),
+    (
+        "tcp-closed",
+        "{server} closed the connection",
+        "{server} cerró la conexión",
+    ),
+    (
+        "tcp-frame-too-long",
+        "a {len}-byte packet is too long for a frame",
+        "un paquete de {len} bytes es demasiado largo para una trama",
+    ),
+    (
+        "multicast-bad-addr",
+        "{addr} isn't an IPv4 GROUP:PORT",
+        "{addr} no es un GRUPO:PUERTO IPv4",
+    ),
+    (
+        "multicast-not-a-group",
+        "{addr} isn't a multicast address (224.0.0.0 to 239.255.255.255)",
+        "{addr} no es una dirección multicast (224.0.0.0 a 239.255.255.255)",
+    ),
+    (
+        "multicast-join-failed",
+        "couldn't join multicast group {group} on {interface}: {error}",
+        "no se pudo unir al grupo multicast {group} en {interface}: {error}",
+    ),
+    (
+        "server-wrong-family",
+        "{server} has no address this socket can reach (see -4, -6 and --dual-stack)",
+        "{server} no tiene ninguna dirección a la que llegue este socket (véanse -4, -6 y --dual-stack)",
+    ),
+    (
+        "server-ipv6-brackets",
+        "{addr} isn't a valid address; put IPv6 addresses in brackets, e.g. [::1]:6014",
+        "{addr} no es una dirección válida; pon las direcciones IPv6 entre corchetes, p. ej. [::1]:6014",
+    ),
+    (
+        "bind-in-use",
+        "port {port} is already in use; pick another with --bind, or --bind 0 for any free one",
+        "el puerto {port} ya está en uso; elige otro con --bind, o --bind 0 para uno libre cualquiera",
+    ),
+    (
+        "bind-failed",
+        "couldn't receive on port {port}: {error}",
+        "no se pudo recibir en el puerto {port}: {error}",
+    ),
+    (
+        "ownership-linux-only",
+        "--chown and --umask are only supported on Linux",
+        "--chown y --umask solo funcionan en Linux",
+    ),
+    (
+        "chown-not-root",
+        "only root can give files to another user, so writing them will fail",
+        "solo root puede dar archivos a otro usuario, así que escribirlos fallará",
+    ),
+    (
+        "chown-not-root-hint",
+        "run as root, or give just `:group` for a group you're in",
+        "ejecútalo como root, o indica solo `:grupo` con un grupo al que pertenezcas",
+    ),
+    (
+        "tui-not-built",
+        "this build has no --tui dashboard",
+        "esta compilación no tiene el panel --tui",
+    ),
+    (
+        "tui-not-built-hint",
+        "rebuild with `cargo build --features tui`, or drop --tui",
+        "vuelve a compilar con `cargo build --features tui`, o quita --tui",
+    ),
+    (
+        "async-needs-tokio",
+        "this build has no --async client",
+        "esta compilación no tiene el cliente --async",
+    ),
+    (
+        "async-needs-tokio-hint",
+        "rebuild with `cargo build --features tokio`, or drop --async",
+        "vuelve a compilar con `cargo build --features tokio`, o quita --async",
+    ),
+    (
+        "tcp-conflicts",
+        "--transport tcp reads one connection, so it can't be used with {option}",
+        "--transport tcp lee una sola conexión, así que no admite {option}",
+    ),
+    (
+        "all-at-once-conflicts",
+        "{flag} runs every --server at once, so it can't be used with {option}",
+        "{flag} usa todos los --server a la vez, así que no admite {option}",
+    ),
+    (
+        "concurrent-same-server",
+        "--server {server} is the same server as --server {earlier}",
+        "--server {server} es el mismo servidor que --server {earlier}",
+    ),
+    (
+        "concurrent-panicked",
+        "the session for {server} crashed",
+        "la sesión de {server} falló de forma inesperada",
+    ),
+    (
+        "concurrent-pipeline",
+        "--concurrent reads the one socket itself, so --pipeline is ignored",
+        "--concurrent ya lee el socket por su cuenta, así que se ignora --pipeline",
+    ),
+    (
+        "async-pipeline",
+        "--async reads each socket on a task of its own already, so --pipeline is ignored",
+        "--async ya lee cada socket en su propia tarea, así que se ignora --pipeline",
+    ),
+    (
+        "server-session-failed",
+        "{server}: {error}",
+        "{server}: {error}",
+    ),
+    (
+        "gro-linux-only",
+        "UDP GRO is only available on Linux",
+        "UDP GRO solo está disponible en Linux",
+    ),
+    (
+        "gro-linux-only-hint",
+        "drop --gro; the plain receive path works everywhere",
+        "quita --gro; la recepción normal funciona en todas partes",
+    ),
+    (
+        "recv-batch-linux-only",
+        "batched receives are only available on Linux",
+        "la recepción por lotes solo está disponible en Linux",
+    ),
+    (
+        "recv-batch-linux-only-hint",
+        "drop --recv-batch to read one datagram at a time",
+        "quita --recv-batch para leer un datagrama cada vez",
+    ),
+    (
+        "tuning-linux-only",
+        "CPU pinning and thread priority are only supported on Linux and will be ignored",
+        "fijar la CPU y la prioridad del hilo solo funciona en Linux y se ignorará",
+    ),
+    (
+        "busy-polling",
+        "busy polling keeps a CPU core at 100% for the whole transfer",
+        "el sondeo activo mantiene un núcleo de CPU al 100% durante toda la transferencia",
+    ),
+    (
+        "busy-polling-hint",
+        "consider --poll-strategy adaptive, or --cpu to keep the spinning on one core",
+        "considera --poll-strategy adaptive, o --cpu para limitar el sondeo a un núcleo",
+    ),
+    ("config-syntax", "{path}:{line}: {problem}", "{path}:{line}: {problem}"),
+    (
+        "config-bad-escape",
+        "only \\\", \\\\, \\n and \\t can follow a backslash",
+        "tras una barra invertida solo pueden ir \\\", \\\\, \\n y \\t",
+    ),
+    (
+        "config-unterminated-string",
+        "the string is never closed",
+        "la cadena no se cierra",
+    ),
+    (
+        "config-nested-array",
+        "arrays can't hold arrays",
+        "un arreglo no puede contener arreglos",
+    ),
+    (
+        "config-bad-array",
+        "expected `,` or `]` in the array",
+        "se esperaba `,` o `]` en el arreglo",
+    ),
+    (
+        "config-bad-value",
+        "expected a string, number, true, false, or array (strings need quotes)",
+        "se esperaba una cadena, un número, true, false o un arreglo (las cadenas van entre comillas)",
+    ),
+    (
+        "config-no-tables",
+        "tables aren't needed: every key is an option, at the top level",
+        "no hacen falta tablas: cada clave es una opción, en el nivel superior",
+    ),
+    (
+        "config-no-equals",
+        "expected `key = value`",
+        "se esperaba `clave = valor`",
+    ),
+    (
+        "config-bad-key",
+        "a key is an option's long name, like output-dir",
+        "una clave es el nombre largo de una opción, como output-dir",
+    ),
+    (
+        "config-trailing",
+        "there's more on the line after the value",
+        "hay algo más en la línea después del valor",
+    ),
+    (
+        "config-unknown-option",
+        "{path}:{line}: there's no --{option} option",
+        "{path}:{line}: no existe la opción --{option}",
+    ),
+    (
+        "config-not-a-switch",
+        "{path}:{line}: --{option} is a switch, so it takes true or false",
+        "{path}:{line}: --{option} es un interruptor, así que lleva true o false",
+    ),
+    (
+        "config-not-a-list",
+        "{path}:{line}: --{option} takes one value, not an array",
+        "{path}:{line}: --{option} lleva un solo valor, no un arreglo",
+    ),
+    (
+        "capture-not-a-capture",
+        "{path} isn't a --record capture",
+        "{path} no es una captura de --record",
+    ),
+    (
+        "capture-bad-version",
+        "{path} is a version {version} capture, which this client can't replay",
+        "{path} es una captura de la versión {version}, que este cliente no sabe reproducir",
+    ),
+    (
+        "journal-not-a-journal",
+        "{path} isn't a --journal file",
+        "{path} no es un archivo de --journal",
+    ),
+    (
+        "journal-bad-version",
+        "{path} is a version {version} journal, which this client can't read",
+        "{path} es un diario de la versión {version}, que este cliente no sabe leer",
+    ),
+    (
+        "journal-damaged-header",
+        "{path} is a --journal file, but its header is damaged",
+        "{path} es un archivo de --journal, pero su cabecera está dañada",
+    ),
+    (
+        "journal-damaged-records",
+        "warning: skipped {count} damaged records in {path}",
+        "aviso: se omitieron {count} registros dañados de {path}",
+    ),
+    ("journal-files", "Files", "Archivos"),
+    (
+        "journal-bursts",
+        "Packets that never arrived",
+        "Paquetes que nunca llegaron",
+    ),
+    (
+        "journal-throughput",
+        "Received per {interval}",
+        "Recibido cada {interval}",
+    ),
+    (
+        "is-a-directory",
+        "{path} is a directory",
+        "{path} es un directorio",
+    ),
+    (
+        "does-not-exist",
+        "{path} does not exist",
+        "{path} no existe",
+    ),
+    (
+        "not-a-directory",
+        "{path} is not a directory",
+        "{path} no es un directorio",
+    ),
+    (
+        "create-directory-hint",
+        "create the directory first or choose another path",
+        "crea el directorio primero o elige otra ruta",
+    ),
+    (
+        "crc-workers-without-crc",
+        "CRC workers have nothing to do without --crc",
+        "los hilos de CRC no tienen nada que hacer sin --crc",
+    ),
+    (
+        "crc-workers-without-crc-hint",
+        "add --crc to check per-packet CRCs",
+        "añade --crc para comprobar el CRC de cada paquete",
+    ),
+    (
+        "spill-dir-unused",
+        "--spill-dir is only used by the spill assembly backend",
+        "--spill-dir solo lo usa el almacenamiento spill",
+    ),
+    (
+        "spill-dir-unused-hint",
+        "drop --assembly-backend, or use --assembly-backend spill",
+        "quita --assembly-backend, o usa --assembly-backend spill",
+    ),
+];
+
+// The message for `key` in the current language
+pub fn text(key: &str) -> &'static str {
+    let (_, en, es) = MESSAGES
+        .iter()
+        .find(|(k, _, _)| *k == key)
+        .unwrap_or_else(|| panic!("no message for {key:?}"));
+    match lang() {
+        Lang::En => en,
+        Lang::Es => es,
+    }
+}
+
+// The message for `key` with each `{name}` replaced by its argument
+pub fn format(key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut message = text(key).to_string();
+    for (name, value) in args {
+        message = message.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    message
+}
+
+// `tr!("key", name = value, ...)` is `format` with the arguments named
+// like in `format!`
+#[macro_export]
+macro_rules! tr {
+    ($key:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::format(
+            $key,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*],
+        )
+    };
+}
+pub use tr;
+
+pub fn ignored(reason: Ignored) -> &'static str {
+    text(match reason {
+        Ignored::PastEnd => "ignored-past-end",
+        Ignored::ConflictingLast => "ignored-conflicting-last",
+        Ignored::TooHigh => "ignored-too-high",
+    })
+}
+
+pub fn malformed(kind: Malformed) -> &'static str {
+    text(match kind {
+        Malformed::TooShort => "malformed-too-short",
+        Malformed::BadUtf8 => "malformed-bad-utf8",
+        Malformed::DataTooShort => "malformed-data-too-short",
+        Malformed::TooLong => "malformed-too-long",
+        Malformed::BadChecksum => "malformed-bad-checksum",
+        Malformed::ChecksumLength => "malformed-checksum-length",
+        Malformed::UnknownCompression => "malformed-unknown-compression",
+        Malformed::Forged => "malformed-forged",
+    })
+}
+
+pub fn audit_problem(problem: &audit::Problem) -> String {
+    match problem {
+        audit::Problem::Malformed => tr!("audit-malformed"),
+        audit::Problem::OutOfSequence { expected, found } => {
+            tr!("audit-out-of-sequence", expected = expected, found = found)
+        }
+        audit::Problem::WrongPrev => tr!("audit-wrong-prev"),
+        audit::Problem::WrongHash => tr!("audit-wrong-hash"),
+    }
+}
+
+pub fn name_problem(problem: &NameProblem) -> String {
+    match problem {
+        NameProblem::Empty => tr!("name-empty"),
+        NameProblem::TooLong { len, limit } => tr!("name-too-long", len = len, limit = limit),
+        NameProblem::ContainsNul => tr!("name-nul"),
+        NameProblem::HasSeparator => tr!("name-separator"),
+        NameProblem::NotAFile => tr!("name-not-a-file"),
+        NameProblem::Absolute => tr!("name-absolute"),
+        NameProblem::ParentDir => tr!("name-parent-dir"),
+    }
+}
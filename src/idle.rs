@@ -0,0 +1,118 @@
+// --idle-after: a transfer that's gone quiet partway through. Once nothing
+// at all has arrived for that long while a file is still incomplete, the
+// session says which files those are, what each is missing, and when the
+// last datagram came, then does what --on-idle says: ask again, ask for just
+// the missing packets (a NACK), only say so, or give up. It goes off again
+// each time it stays quiet that long.
+//
+// Unlike --stall-timeout, which gives up on one file that stops while
+// others carry on, this is about the whole transfer stopping.
+
+use crate::{
+    file_manager::FileManager,
+    i18n::{self, tr},
+    units,
+};
+use clap::ValueEnum;
+use std::{
+    ops::Range,
+    time::{Duration, SystemTime},
+};
+
+// How many runs of missing packets a file's line lists before "..."
+const MAX_RANGES: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnIdle {
+    // Send the request again, with a resume token unless --no-resume-token
+    #[default]
+    Resend,
+    // Send a NACK listing the missing packets, for servers that take them
+    Nack,
+    // Print the diagnostics and keep waiting
+    Report,
+    // End the session, exiting with status 12
+    Abort,
+}
+
+impl OnIdle {
+    pub fn name(self) -> &'static str {
+        match self {
+            OnIdle::Resend => "resend",
+            OnIdle::Nack => "nack",
+            OnIdle::Report => "report",
+            OnIdle::Abort => "abort",
+        }
+    }
+}
+
+// A file that isn't complete, as the diagnostics list it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Incomplete {
+    pub file_id: u8,
+    pub name: Option<String>,
+    pub received: usize,
+    // Unknown until the last packet arrives
+    pub expected: Option<u32>,
+    // Without the last packet, only the gaps below the highest one seen
+    pub missing: Vec<Range<u32>>,
+}
+
+// Every file heard of that isn't complete, in ID order
+pub fn incomplete(file_manager: &FileManager) -> Vec<Incomplete> {
+    (file_manager.file_ids().into_iter())
+        .filter(|&file_id| !file_manager.is_complete(file_id))
+        .filter_map(|file_id| {
+            let (name, received, expected) = file_manager.file_status(file_id)?;
+            Some(Incomplete {
+                file_id,
+                name: name.map(|name| name.to_string_lossy().into_owned()),
+                received,
+                expected,
+                missing: file_manager.missing_packets(file_id).unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+// The diagnostics: a line for the silence, then one a file
+pub fn report(files: &[Incomplete], idle_for: Duration, last: SystemTime) -> String {
+    let mut lines = vec![tr!(
+        "idle-report",
+        idle = units::format_duration(idle_for),
+        last = units::format_timestamp(last),
+        files = files.len()
+    )];
+    lines.extend(files.iter().map(file_line));
+    lines.join("\n")
+}
+
+// A file's line in the diagnostics: how far it got and what it's missing
+pub fn file_line(file: &Incomplete) -> String {
+    let name = match &file.name {
+        Some(name) => name.as_str(),
+        None => i18n::text("idle-no-header"),
+    };
+    let expected = file
+        .expected
+        .map_or("?".to_string(), |count| count.to_string());
+    match file.missing.is_empty() {
+        // Every packet so far is in order, and the last hasn't come
+        true => tr!(
+            "idle-file-no-gaps",
+            file_id = file.file_id,
+            name = name,
+            received = file.received,
+            expected = expected
+        ),
+        false => tr!(
+            "idle-file",
+            file_id = file.file_id,
+            name = name,
+            received = file.received,
+            expected = expected,
+            missing = units::count(&file.missing),
+            ranges = units::format_spans(&file.missing, MAX_RANGES)
+        ),
+    }
+}
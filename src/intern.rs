@@ -0,0 +1,27 @@
+// Header file names are short and servers repeat them (retransmissions,
+// several sessions in daemon mode), so each distinct name is allocated once
+// and shared after that.
+
+use std::{cell::RefCell, collections::HashMap, ffi::OsStr, sync::Arc};
+
+// Past this many distinct names we stop remembering new ones, so a server
+// inventing names can't grow the table forever
+const MAX_NAMES: usize = 1024;
+
+thread_local! {
+    static NAMES: RefCell<HashMap<Box<OsStr>, Arc<OsStr>>> = RefCell::new(HashMap::new());
+}
+
+pub fn file_name(name: &OsStr) -> Arc<OsStr> {
+    NAMES.with(|names| {
+        let mut names = names.borrow_mut();
+        if let Some(interned) = names.get(name) {
+            return Arc::clone(interned);
+        }
+        let interned: Arc<OsStr> = Arc::from(name);
+        if names.len() < MAX_NAMES {
+            names.insert(name.into(), Arc::clone(&interned));
+        }
+        interned
+    })
+}
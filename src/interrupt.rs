@@ -0,0 +1,42 @@
+// Ctrl-C: the first one ends the session where it stands, so the complete
+// files are written and the rest are kept as .partial files (see
+// `FileManager::write_partial_files`); a second one gives up on that and
+// exits straight away. Only the plain transfer and --listen-only install the
+// handler; the library never sets it off by itself.
+
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+// Status for a run ended by Ctrl-C, as shells report one killed by SIGINT
+pub const EXIT_CODE: i32 = 130;
+
+// How long a read may block while Ctrl-C can come
+pub const POLL: Duration = Duration::from_millis(250);
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn install() -> io::Result<()> {
+    ctrlc::set_handler(request).map_err(io::Error::other)?;
+    INSTALLED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn installed() -> bool {
+    INSTALLED.load(Ordering::SeqCst)
+}
+
+// Ctrl-C as the handler takes it, for where it comes as a key rather than a
+// signal (the --tui dashboard's raw terminal)
+pub fn request() {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        std::process::exit(EXIT_CODE);
+    }
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,455 @@
+// The --journal file: one fixed-size binary record per packet as it arrives,
+// small enough to leave on for a whole transfer, for `analyze` to pick apart
+// afterwards. Everything is big-endian, like the wire format.
+//
+//   header (20 bytes): "SFSJ", version, 3 zero bytes, session start in
+//                      microseconds since the Unix epoch, CRC-32
+//   record (20 bytes): microseconds since the session started (8), file ID,
+//                      flags, packet number's low half (2), datagram size
+//                      (2), packet number's high half (2), CRC-32
+//
+// Each CRC covers the 16 bytes before it, so a damaged record is skipped
+// (with a warning) rather than read as a packet that never came. The flags
+// are the low three bits of the packet's status byte: bit 0 set for data,
+// bit 1 for a last packet, bit 2 for a checksum. Headers and checksums record
+// packet number 0. The high half of the packet number is only ever set by
+// wide packets (see wire.rs); journals from before them have zeros there.
+//
+// Version 1 was the same without the CRCs. Older journals are still read;
+// each version's layout gets its own arm in `decode`.
+
+use crate::{
+    checksum, fsio,
+    i18n::{self, tr},
+    log::log,
+    units,
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub const MAGIC: &[u8; 4] = b"SFSJ";
+pub const VERSION: u8 = 2;
+// A header or record without its CRC
+const BODY_LEN: usize = 16;
+const RECORD_LEN: usize = BODY_LEN + 4;
+
+pub const DATA: u8 = 1;
+pub const LAST: u8 = 2;
+pub const CHECKSUM: u8 = 4;
+
+pub struct Record {
+    pub at: Duration,
+    pub file_id: u8,
+    pub flags: u8,
+    pub packet_number: u32,
+    pub size: u16,
+}
+
+impl Record {
+    fn to_bytes(&self) -> [u8; RECORD_LEN] {
+        let mut bytes = [0; RECORD_LEN];
+        bytes[..8].copy_from_slice(&(self.at.as_micros() as u64).to_be_bytes());
+        bytes[8] = self.file_id;
+        bytes[9] = self.flags;
+        let [high, high_low, low_high, low] = self.packet_number.to_be_bytes();
+        bytes[10..12].copy_from_slice(&[low_high, low]);
+        bytes[12..14].copy_from_slice(&self.size.to_be_bytes());
+        bytes[14..16].copy_from_slice(&[high, high_low]);
+        seal(&mut bytes);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let micros = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        Record {
+            at: Duration::from_micros(micros),
+            file_id: bytes[8],
+            flags: bytes[9],
+            packet_number: u32::from_be_bytes([bytes[14], bytes[15], bytes[10], bytes[11]]),
+            size: u16::from_be_bytes([bytes[12], bytes[13]]),
+        }
+    }
+
+    fn is_data(&self) -> bool {
+        self.flags & DATA != 0
+    }
+}
+
+// Fill in the CRC at the end of a header or record
+fn seal(bytes: &mut [u8; RECORD_LEN]) {
+    let crc = checksum::crc32(&bytes[..BODY_LEN]);
+    bytes[BODY_LEN..].copy_from_slice(&crc.to_be_bytes());
+}
+
+fn is_intact(bytes: &[u8]) -> bool {
+    bytes[BODY_LEN..] == checksum::crc32(&bytes[..BODY_LEN]).to_be_bytes()
+}
+
+pub struct Journal {
+    out: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+}
+
+impl Journal {
+    // Start a new journal at `path`, replacing any old one
+    pub fn create(path: &Path, started: Instant) -> io::Result<Self> {
+        let mut out = BufWriter::new(fsio::create(path)?);
+        let wall = SystemTime::now() - started.elapsed();
+        let micros = wall.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_micros());
+        let mut header = [0; RECORD_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = VERSION;
+        header[8..BODY_LEN].copy_from_slice(&(micros as u64).to_be_bytes());
+        seal(&mut header);
+        fsio::write_all(&mut out, path, &header)?;
+        Ok(Journal {
+            out,
+            path: path.to_path_buf(),
+            started,
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        now: Instant,
+        file_id: u8,
+        flags: u8,
+        packet_number: u32,
+        size: usize,
+    ) -> io::Result<()> {
+        let record = Record {
+            at: now.saturating_duration_since(self.started),
+            file_id,
+            flags,
+            packet_number,
+            size: size.min(u16::MAX as usize) as u16,
+        };
+        fsio::write_all(&mut self.out, &self.path, &record.to_bytes())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        fsio::at(fsio::Action::Flush, &self.path, || {
+            io::Write::flush(&mut self.out)
+        })
+    }
+}
+
+// The records of a journal of a version we know, and how many were damaged
+// and left out. None if the header itself is damaged.
+fn decode(version: u8, bytes: &[u8]) -> Option<(Vec<Record>, usize)> {
+    // A journal cut off mid-record (the client was killed) keeps what's whole
+    match version {
+        1 => Some((
+            bytes[BODY_LEN..]
+                .chunks_exact(BODY_LEN)
+                .map(Record::from_bytes)
+                .collect(),
+            0,
+        )),
+        _ => {
+            if bytes.len() < RECORD_LEN || !is_intact(&bytes[..RECORD_LEN]) {
+                return None;
+            }
+            let (intact, damaged): (Vec<&[u8]>, Vec<&[u8]>) = bytes[RECORD_LEN..]
+                .chunks_exact(RECORD_LEN)
+                .partition(|record| is_intact(record));
+            Some((
+                intact.into_iter().map(Record::from_bytes).collect(),
+                damaged.len(),
+            ))
+        }
+    }
+}
+
+// Every intact record in a journal, in arrival order
+pub fn read(path: &Path) -> io::Result<Vec<Record>> {
+    let bytes = fsio::read(path)?;
+    if bytes.len() < BODY_LEN || &bytes[..4] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            tr!("journal-not-a-journal", path = path.display()),
+        ));
+    }
+    let version = bytes[4];
+    if !(1..=VERSION).contains(&version) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            tr!(
+                "journal-bad-version",
+                path = path.display(),
+                version = version
+            ),
+        ));
+    }
+    let (records, damaged) = decode(version, &bytes).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            tr!("journal-damaged-header", path = path.display()),
+        )
+    })?;
+    if damaged > 0 {
+        log!(
+            Warn,
+            "{}",
+            tr!(
+                "journal-damaged-records",
+                path = path.display(),
+                count = damaged
+            )
+        );
+    }
+    Ok(records)
+}
+
+// What happened to one file's packets
+struct FileStats {
+    file_id: u8,
+    datagrams: u64,
+    distinct: usize,
+    expected: Option<u32>,
+    // Runs of packet numbers that never arrived: first and length
+    bursts: Vec<(u32, u32)>,
+    // How far behind the highest packet number seen so far a packet arrived,
+    // at worst
+    reorder_depth: u32,
+}
+
+impl FileStats {
+    fn missing(&self) -> u32 {
+        self.bursts.iter().map(|(_, len)| len).sum()
+    }
+
+    fn longest_burst(&self) -> u32 {
+        self.bursts.iter().map(|&(_, len)| len).max().unwrap_or(0)
+    }
+}
+
+// Packets and bytes that arrived in one slice of the session
+struct Bucket {
+    start: Duration,
+    packets: u64,
+    bytes: u64,
+}
+
+fn file_stats(file_id: u8, records: &[&Record]) -> FileStats {
+    let mut seen = HashSet::new();
+    let mut highest = None;
+    let mut reorder_depth = 0;
+    let mut expected = None;
+    for record in records.iter().filter(|record| record.is_data()) {
+        if record.flags & LAST != 0 {
+            expected = Some(record.packet_number.saturating_add(1));
+        }
+        if !seen.insert(record.packet_number) {
+            continue; // Duplicates don't say anything about ordering
+        }
+        match highest {
+            Some(top) if record.packet_number < top => {
+                reorder_depth = reorder_depth.max(top - record.packet_number)
+            }
+            _ => highest = Some(record.packet_number),
+        }
+    }
+
+    // Without a last packet we only know about gaps below the highest
+    let end = (expected.or(highest.map(|top: u32| top.saturating_add(1)))).unwrap_or(0);
+    let mut bursts = Vec::new();
+    for number in 0..end {
+        if seen.contains(&number) {
+            continue;
+        }
+        match bursts.last_mut() {
+            Some((first, len)) if *first + *len == number => *len += 1,
+            _ => bursts.push((number, 1)),
+        }
+    }
+    FileStats {
+        file_id,
+        datagrams: records.iter().filter(|record| record.is_data()).count() as u64,
+        distinct: seen.len(),
+        expected,
+        bursts,
+        reorder_depth,
+    }
+}
+
+fn buckets(records: &[Record], interval: Duration) -> Vec<Bucket> {
+    let Some(last) = records.iter().map(|record| record.at).max() else {
+        return Vec::new();
+    };
+    let count = (last.as_micros() / interval.as_micros()) as usize + 1;
+    let mut buckets: Vec<Bucket> = (0..count)
+        .map(|i| Bucket {
+            start: interval * i as u32,
+            packets: 0,
+            bytes: 0,
+        })
+        .collect();
+    for record in records {
+        let bucket = &mut buckets[(record.at.as_micros() / interval.as_micros()) as usize];
+        bucket.packets += 1;
+        bucket.bytes += record.size as u64;
+    }
+    buckets
+}
+
+// The `analyze` subcommand
+pub fn run(path: &Path, interval: Duration, csv: bool, out: &mut impl Write) -> io::Result<()> {
+    let records = read(path)?;
+    let interval = interval.max(Duration::from_millis(1));
+    let mut by_file: BTreeMap<u8, Vec<&Record>> = BTreeMap::new();
+    for record in &records {
+        by_file.entry(record.file_id).or_default().push(record);
+    }
+    let files: Vec<FileStats> = by_file
+        .iter()
+        .map(|(&file_id, records)| file_stats(file_id, records))
+        .collect();
+    let buckets = buckets(&records, interval);
+    if csv {
+        print_csv(&files, &buckets, interval, out)
+    } else {
+        print_tables(&files, &buckets, interval, out)
+    }
+}
+
+fn rate(bytes: u64, interval: Duration) -> u64 {
+    (bytes as f64 / interval.as_secs_f64()) as u64
+}
+
+fn print_tables(
+    files: &[FileStats],
+    buckets: &[Bucket],
+    interval: Duration,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(out, "{}", i18n::text("journal-files"))?;
+    writeln!(
+        out,
+        "{:>4} {:>9} {:>8} {:>8} {:>8} {:>7} {:>7} {:>7}",
+        "file", "datagrams", "distinct", "expected", "missing", "bursts", "longest", "reorder"
+    )?;
+    for file in files {
+        writeln!(
+            out,
+            "{:>4} {:>9} {:>8} {:>8} {:>8} {:>7} {:>7} {:>7}",
+            file.file_id,
+            file.datagrams,
+            file.distinct,
+            file.expected.map_or("?".to_string(), |n| n.to_string()),
+            file.missing(),
+            file.bursts.len(),
+            file.longest_burst(),
+            file.reorder_depth
+        )?;
+    }
+
+    if files.iter().any(|file| !file.bursts.is_empty()) {
+        writeln!(out)?;
+        writeln!(out, "{}", i18n::text("journal-bursts"))?;
+        writeln!(
+            out,
+            "{:>4} {:>6} {:>6} {:>6}",
+            "file", "first", "last", "length"
+        )?;
+        for file in files {
+            for &(first, len) in &file.bursts {
+                writeln!(
+                    out,
+                    "{:>4} {:>6} {:>6} {:>6}",
+                    file.file_id,
+                    first,
+                    first + len - 1,
+                    len
+                )?;
+            }
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{}",
+        tr!(
+            "journal-throughput",
+            interval = units::format_duration(interval)
+        )
+    )?;
+    writeln!(
+        out,
+        "{:>10} {:>8} {:>10} {:>12}",
+        "at", "packets", "bytes", "rate"
+    )?;
+    for bucket in buckets {
+        writeln!(
+            out,
+            "{:>10} {:>8} {:>10} {:>12}",
+            units::format_duration(bucket.start),
+            bucket.packets,
+            bucket.bytes,
+            units::format_rate(rate(bucket.bytes, interval))
+        )?;
+    }
+    Ok(())
+}
+
+// Three CSV tables one after the other, each with its own header row and a
+// blank line between them
+fn print_csv(
+    files: &[FileStats],
+    buckets: &[Bucket],
+    interval: Duration,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "file_id,datagrams,distinct,expected,missing,bursts,longest_burst,reorder_depth"
+    )?;
+    for file in files {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            file.file_id,
+            file.datagrams,
+            file.distinct,
+            file.expected.map_or(String::new(), |n| n.to_string()),
+            file.missing(),
+            file.bursts.len(),
+            file.longest_burst(),
+            file.reorder_depth
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(out, "file_id,first,last,length")?;
+    for file in files {
+        for &(first, len) in &file.bursts {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                file.file_id,
+                first,
+                first + len - 1,
+                len
+            )?;
+        }
+    }
+    writeln!(out)?;
+    writeln!(out, "start_ms,packets,bytes,bytes_per_second")?;
+    for bucket in buckets {
+        writeln!(
+            out,
+            "{},{},{},{}",
+            bucket.start.as_millis(),
+            bucket.packets,
+            bucket.bytes,
+            rate(bucket.bytes, interval)
+        )?;
+    }
+    Ok(())
+}
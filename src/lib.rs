@@ -0,0 +1,84 @@
+// The client as a library: packet parsing, reassembly, and receiving a whole
+// transfer, for other tools to build on. The `segmented-file-system-client`
+// binary is a thin layer over it.
+
+pub mod ack;
+pub mod assembly;
+#[cfg(feature = "tokio")]
+pub mod async_client;
+pub mod audit;
+pub mod bench;
+pub mod callbacks;
+pub mod capability;
+pub mod capture;
+pub mod catalog;
+pub mod checksum;
+pub mod cli;
+pub mod client;
+pub mod completion;
+pub mod compression;
+pub mod concurrent;
+pub mod config;
+pub mod crypto;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+pub mod diagnose;
+pub mod discover;
+pub mod echo;
+pub mod error;
+pub mod events;
+pub mod file_manager;
+pub mod fsio;
+pub mod grade;
+pub mod hash;
+pub mod history;
+pub mod hooks;
+pub mod i18n;
+pub mod idle;
+pub mod intern;
+pub mod interrupt;
+pub mod journal;
+pub mod limits;
+#[cfg(target_os = "linux")]
+pub mod linux;
+pub mod log;
+pub mod manifest;
+pub mod multicast;
+pub mod nack;
+pub mod owner;
+pub mod pack;
+pub mod packet;
+pub mod paths;
+pub mod peer;
+pub mod plan;
+pub mod priority;
+pub mod protocol;
+pub mod quarantine;
+pub mod ratelimit;
+pub mod repl;
+pub mod report;
+pub mod results;
+pub mod resume;
+pub mod scenario;
+pub mod selection;
+pub mod server;
+pub mod session;
+pub mod simulate;
+pub mod sink;
+pub mod snapshot;
+pub mod sniff;
+pub mod space;
+pub mod spill;
+pub mod state;
+pub mod stream;
+pub mod tcp;
+pub mod tempdir;
+pub mod text;
+pub mod trace;
+pub mod transport;
+pub mod units;
+pub mod validate;
+pub mod verify;
+pub mod watchdog;
+pub mod wire;
+pub mod writers;
@@ -0,0 +1,320 @@
+// Guards against servers that are broken or hostile: too many files, files
+// or sessions that go on too long, names we can't (or shouldn't) create, and
+// data packets that contradict what we already know about a file. The screen
+// only sees file IDs, names, packet numbers and sizes, so it can be
+// exercised without a socket.
+//
+// Every such limit lives in `Limits`, which `Cli::limits` builds from the
+// options (and so the config file). The screen, the session, the
+// FileManager holding payloads and the writer putting files on disk all read
+// the one they need from it.
+
+use crate::wire;
+use clap::ValueEnum;
+use std::{collections::HashMap, ffi::OsStr, time::Duration};
+
+// Longest file name most filesystems accept, in bytes
+pub const DEFAULT_MAX_NAME_LEN: usize = 255;
+
+// Packets a file can have whatever --max-file-size says: a TiB's worth, far
+// past any real transfer, and short of the 4 TiB one wide packet numbered
+// near the top could otherwise have the spill grid and partial files lay out
+pub const MAX_PACKETS: u32 = 1 << 30;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_files: Option<usize>,
+    // Payload bytes in one file, as received and as written
+    pub max_file_size: Option<u64>,
+    pub max_name_len: usize,
+    // Payload bytes held in memory before files go to disk (--max-memory)
+    pub max_buffered: Option<u64>,
+    // Bytes of datagrams in the whole session
+    pub max_total_bytes: Option<u64>,
+    // How long the session gets (--session-timeout)
+    pub max_session: Option<Duration>,
+    pub renamed_headers: Renames,
+    // Names can have directories in them (--tree)
+    pub tree: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_files: None,
+            max_file_size: None,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            max_buffered: None,
+            max_total_bytes: None,
+            max_session: None,
+            renamed_headers: Renames::default(),
+            tree: false,
+        }
+    }
+}
+
+// Which name a file keeps when a second header gives it a different one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Renames {
+    FirstWins,
+    // What the client always did, without saying so
+    #[default]
+    LastWins,
+    Error,
+}
+
+// A header that gave a file a different name from its first one
+#[derive(Debug, PartialEq, Eq)]
+pub struct Renamed {
+    pub earlier: String,
+    // Whether the earlier name stays (and the header is dropped)
+    pub kept_earlier: bool,
+}
+
+// Why a header's file name was refused
+#[derive(Debug, PartialEq, Eq)]
+pub enum NameProblem {
+    Empty,
+    TooLong { len: usize, limit: usize },
+    ContainsNul,
+    // Would reach outside the output directory
+    HasSeparator,
+    // `.` or `..`, which name a directory
+    NotAFile,
+    // With --tree: starting at the root instead of the output directory
+    Absolute,
+    // With --tree: going up out of a directory with `..`
+    ParentDir,
+}
+
+fn is_separator(c: char) -> bool {
+    // `C:name` is relative to a drive on Windows, not to the output directory
+    c == '/' || c == '\\' || (cfg!(windows) && c == ':')
+}
+
+// The part of a name after its last path separator, so a header for
+// `../../etc/cron.d/evil` or `/tmp/x` can only write `evil` or `x` in the
+// output directory
+pub fn base_name(name: &str) -> &str {
+    name.rsplit(is_separator).next().unwrap_or(name)
+}
+
+// The same for a name that needn't be UTF-8
+pub fn base_os_name(name: &OsStr) -> &OsStr {
+    match name.to_str() {
+        Some(name) => OsStr::new(base_name(name)),
+        #[cfg(unix)]
+        None => {
+            use std::os::unix::ffi::OsStrExt;
+            let bytes = name.as_bytes();
+            let start = (bytes.iter())
+                .rposition(|&b| is_separator(char::from(b)))
+                .map_or(0, |at| at + 1);
+            OsStr::from_bytes(&bytes[start..])
+        }
+        // Only Unix has names that aren't
+        #[cfg(not(unix))]
+        None => name,
+    }
+}
+
+pub fn check_name(name: &str, max_len: usize) -> Result<(), NameProblem> {
+    if name.is_empty() {
+        Err(NameProblem::Empty)
+    } else if name.len() > max_len {
+        Err(NameProblem::TooLong {
+            len: name.len(),
+            limit: max_len,
+        })
+    } else if name.contains('\0') {
+        Err(NameProblem::ContainsNul)
+    } else if name.contains(is_separator) {
+        Err(NameProblem::HasSeparator)
+    } else if name == "." || name == ".." {
+        Err(NameProblem::NotAFile)
+    } else {
+        Ok(())
+    }
+}
+
+// With --tree, a name is a relative path: directories and then the file,
+// between `/`s, each part held to what check_name holds a whole name to. A
+// backslash still isn't a separator, and `.`, `..`, and empty parts
+// (`a//b`, `dir/`) are all refused, so a path can only ever lead down.
+pub fn check_path(name: &str, max_len: usize) -> Result<(), NameProblem> {
+    if name.starts_with('/') {
+        Err(NameProblem::Absolute)
+    } else if name.split('/').any(|part| part == "..") {
+        Err(NameProblem::ParentDir)
+    } else {
+        (name.split('/')).try_for_each(|part| check_name(part, max_len))
+    }
+}
+
+// Packets that end the session
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rejected {
+    TooManyFiles {
+        limit: usize,
+    },
+    BadName {
+        file_id: u8,
+        problem: NameProblem,
+    },
+    // Two headers named the same file differently under `Renames::Error`
+    Renamed {
+        file_id: u8,
+        first: String,
+        second: String,
+    },
+    // From its header's size, or the payloads that came for it
+    FileTooLarge {
+        file_id: u8,
+        limit: u64,
+    },
+}
+
+// Data packets we drop (with a warning) rather than store
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ignored {
+    // Numbered at or past the end the file's last packet already set
+    PastEnd,
+    // A "last" packet before an earlier one, or before data already stored
+    // past it
+    ConflictingLast,
+    // Numbered MAX_PACKETS or more, which only a wide packet can be
+    TooHigh,
+}
+
+#[derive(Default)]
+struct FileState {
+    expected: Option<u32>, // packet count, once the last packet is in
+    highest: Option<u32>,  // highest packet number accepted
+    bytes: u64,            // payload bytes kept
+    name: Option<String>,  // from the header that's in effect
+}
+
+// What's been accepted this session, as far as the limits care
+#[derive(Default)]
+pub struct Screen {
+    limits: Limits,
+    files: HashMap<u8, FileState>,
+}
+
+impl Screen {
+    pub fn new(limits: Limits) -> Self {
+        Screen {
+            limits,
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    fn file(&mut self, file_id: u8) -> Result<&mut FileState, Rejected> {
+        let known = self.files.len();
+        if !self.files.contains_key(&file_id) {
+            if let Some(limit) = self.limits.max_files.filter(|&max| known >= max) {
+                return Err(Rejected::TooManyFiles { limit });
+            }
+        }
+        Ok(self.files.entry(file_id).or_default())
+    }
+
+    // Ok(Some(_)) means the header renames the file, which may or may not
+    // be allowed to stand
+    pub fn header(&mut self, file_id: u8, name: &str) -> Result<Option<Renamed>, Rejected> {
+        let check = match self.limits.tree {
+            true => check_path,
+            false => check_name,
+        };
+        check(name, self.limits.max_name_len)
+            .map_err(|problem| Rejected::BadName { file_id, problem })?;
+        let policy = self.limits.renamed_headers;
+        let file = self.file(file_id)?;
+        let Some(earlier) = file.name.as_deref().filter(|&earlier| earlier != name) else {
+            file.name = Some(name.to_string());
+            return Ok(None);
+        };
+        let earlier = earlier.to_string();
+        match policy {
+            Renames::FirstWins => Ok(Some(Renamed {
+                earlier,
+                kept_earlier: true,
+            })),
+            Renames::LastWins => {
+                file.name = Some(name.to_string());
+                Ok(Some(Renamed {
+                    earlier,
+                    kept_earlier: false,
+                }))
+            }
+            Renames::Error => Err(Rejected::Renamed {
+                file_id,
+                first: earlier,
+                second: name.to_string(),
+            }),
+        }
+    }
+
+    // Ok(Some(_)) means drop the packet but carry on
+    pub fn data(
+        &mut self,
+        file_id: u8,
+        packet_number: u32,
+        is_last: bool,
+    ) -> Result<Option<Ignored>, Rejected> {
+        // The file is at least this long, whatever's in the packet
+        let start = u64::from(packet_number) * wire::MAX_PAYLOAD as u64;
+        if let Some(limit) = self.limits.max_file_size.filter(|&limit| start > limit) {
+            return Err(Rejected::FileTooLarge { file_id, limit });
+        }
+        let file = self.file(file_id)?;
+        let ignored = if packet_number >= MAX_PACKETS {
+            Some(Ignored::TooHigh)
+        } else if is_last {
+            // Two "last" packets can't both be right. The one further on is
+            // taken, since the file can't end before data that's there, and
+            // a file only goes down as complete once it has every packet up
+            // to it.
+            if file.expected.is_some_and(|count| count > packet_number + 1)
+                || file.highest.is_some_and(|highest| highest > packet_number)
+            {
+                Some(Ignored::ConflictingLast)
+            } else {
+                file.expected = Some(packet_number + 1);
+                None
+            }
+        } else if file.expected.is_some_and(|count| packet_number >= count) {
+            Some(Ignored::PastEnd)
+        } else {
+            None
+        };
+        if ignored.is_none() {
+            file.highest = file.highest.max(Some(packet_number));
+        }
+        Ok(ignored)
+    }
+
+    // A header said the file is `size` bytes
+    pub fn size(&self, file_id: u8, size: u64) -> Result<(), Rejected> {
+        match self.limits.max_file_size.filter(|&limit| size > limit) {
+            Some(limit) => Err(Rejected::FileTooLarge { file_id, limit }),
+            None => Ok(()),
+        }
+    }
+
+    // A data packet with `len` bytes of payload is being kept. Duplicates
+    // aren't kept, so they don't count.
+    pub fn payload(&mut self, file_id: u8, len: usize) -> Result<(), Rejected> {
+        let limit = self.limits.max_file_size;
+        let file = self.file(file_id)?;
+        file.bytes += len as u64;
+        match limit.filter(|&limit| file.bytes > limit) {
+            Some(limit) => Err(Rejected::FileTooLarge { file_id, limit }),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,365 @@
+// Linux-only socket, scheduling, user-database and filesystem calls that std
+// doesn't expose
+
+use std::{
+    ffi::CString,
+    fs::File,
+    io, mem,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket},
+    os::{fd::AsRawFd, unix::ffi::OsStrExt},
+    path::Path,
+    ptr,
+};
+
+// Not exported by libc for glibc targets
+const SO_RXQ_OVFL: libc::c_int = 40;
+
+// Big enough for a full GRO batch (the kernel caps coalesced datagrams at 64 KiB)
+pub const GRO_BUFFER_SIZE: usize = 65536;
+
+// One recvmsg(2) worth of data
+pub struct Received {
+    pub len: usize,
+    pub from: SocketAddr,
+    // Set when GRO coalesced several datagrams: each one is this long except
+    // possibly the last
+    pub segment_size: Option<usize>,
+    // How many datagrams the kernel has dropped on this socket because its
+    // receive queue was full, when the drop counter is enabled
+    pub kernel_drops: Option<u32>,
+}
+
+fn set_int_option(
+    sock: &UdpSocket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    // SAFETY: the option value is a live c_int and its size is passed alongside
+    let result = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Ask the kernel to coalesce consecutive datagrams from the same flow
+pub fn enable_gro(sock: &UdpSocket) -> io::Result<()> {
+    set_int_option(sock, libc::SOL_UDP, libc::UDP_GRO, 1)
+}
+
+// Let one send(2) carry many datagrams of `size` bytes each (UDP GSO)
+pub fn enable_gso(sock: &UdpSocket, size: u16) -> io::Result<()> {
+    set_int_option(sock, libc::SOL_UDP, libc::UDP_SEGMENT, size as libc::c_int)
+}
+
+// Attach the socket's receive-queue overflow counter to every datagram
+pub fn enable_drop_counter(sock: &UdpSocket) -> io::Result<()> {
+    set_int_option(sock, libc::SOL_SOCKET, SO_RXQ_OVFL, 1)
+}
+
+fn socket_addr(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET => {
+            // SAFETY: the family says this is a sockaddr_in
+            let addr = unsafe { *(storage as *const _ as *const libc::sockaddr_in) };
+            Ok(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                u16::from_be(addr.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: the family says this is a sockaddr_in6
+            let addr = unsafe { *(storage as *const _ as *const libc::sockaddr_in6) };
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                u16::from_be(addr.sin6_port),
+                addr.sin6_flowinfo,
+                addr.sin6_scope_id,
+            )))
+        }
+        family => Err(io::Error::other(format!(
+            "unexpected address family {family}"
+        ))),
+    }
+}
+
+// Room for the UDP_GRO and SO_RXQ_OVFL control messages; u64s keep the cmsg
+// headers aligned
+type ControlBuffer = [u64; 16];
+
+// Pull the GRO segment size and drop counter out of a received message
+fn read_control(msg: &libc::msghdr, received: &mut Received) {
+    // SAFETY: the CMSG_* macros walk the control buffer the kernel filled in
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_UDP && (*cmsg).cmsg_type == libc::UDP_GRO {
+                let size = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                received.segment_size = Some(size as usize);
+            } else if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == SO_RXQ_OVFL {
+                let drops = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const u32);
+                received.kernel_drops = Some(drops);
+            }
+            cmsg = libc::CMSG_NXTHDR(msg, cmsg);
+        }
+    }
+}
+
+// Point `msg` at one buffer, address, and control buffer
+fn prepare_msghdr(
+    msg: &mut libc::msghdr,
+    iov: &mut libc::iovec,
+    storage: &mut libc::sockaddr_storage,
+    control: &mut ControlBuffer,
+) {
+    msg.msg_name = storage as *mut _ as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = mem::size_of_val(control) as _;
+}
+
+// recvmsg(2) with the ancillary data std throws away
+pub fn recv(sock: &UdpSocket, buf: &mut [u8]) -> io::Result<Received> {
+    // SAFETY: all-zero is a valid sockaddr_storage and msghdr
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut control: ControlBuffer = [0; 16];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    prepare_msghdr(&mut msg, &mut iov, &mut storage, &mut control);
+
+    // SAFETY: msg points at buffers that outlive the call
+    let len = unsafe { libc::recvmsg(sock.as_raw_fd(), &mut msg, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut received = Received {
+        len: len as usize,
+        from: socket_addr(&storage)?,
+        segment_size: None,
+        kernel_drops: None,
+    };
+    read_control(&msg, &mut received);
+    Ok(received)
+}
+
+// Buffers for recvmmsg(2), kept around so each call is just the syscall
+pub struct BatchReceiver {
+    bufs: Vec<Vec<u8>>,
+    storage: Vec<libc::sockaddr_storage>,
+    iovecs: Vec<libc::iovec>,
+    control: Vec<ControlBuffer>,
+    headers: Vec<libc::mmsghdr>,
+    received: Vec<Received>,
+}
+
+impl BatchReceiver {
+    pub fn new(count: usize, buf_size: usize) -> Self {
+        // SAFETY: all-zero is a valid sockaddr_storage, iovec, and mmsghdr
+        BatchReceiver {
+            bufs: vec![vec![0; buf_size]; count],
+            storage: (0..count).map(|_| unsafe { mem::zeroed() }).collect(),
+            iovecs: (0..count).map(|_| unsafe { mem::zeroed() }).collect(),
+            control: vec![[0; 16]; count],
+            headers: (0..count).map(|_| unsafe { mem::zeroed() }).collect(),
+            received: Vec::with_capacity(count),
+        }
+    }
+
+    // Wait for at least one datagram, then take whatever else is already
+    // queued, up to one per buffer. Returns how many arrived.
+    pub fn recv(&mut self, sock: &UdpSocket) -> io::Result<usize> {
+        for i in 0..self.bufs.len() {
+            self.iovecs[i] = libc::iovec {
+                iov_base: self.bufs[i].as_mut_ptr() as *mut libc::c_void,
+                iov_len: self.bufs[i].len(),
+            };
+            prepare_msghdr(
+                &mut self.headers[i].msg_hdr,
+                &mut self.iovecs[i],
+                &mut self.storage[i],
+                &mut self.control[i],
+            );
+        }
+
+        // SAFETY: every header points at buffers owned by self, which outlive
+        // the call
+        let count = unsafe {
+            libc::recvmmsg(
+                sock.as_raw_fd(),
+                self.headers.as_mut_ptr(),
+                self.headers.len() as libc::c_uint,
+                libc::MSG_WAITFORONE,
+                ptr::null_mut(),
+            )
+        };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.received.clear();
+        for i in 0..count as usize {
+            let mut message = Received {
+                len: self.headers[i].msg_len as usize,
+                from: socket_addr(&self.storage[i])?,
+                segment_size: None,
+                kernel_drops: None,
+            };
+            read_control(&self.headers[i].msg_hdr, &mut message);
+            self.received.push(message);
+        }
+        Ok(count as usize)
+    }
+
+    // The `i`th datagram from the last recv
+    pub fn get(&self, i: usize) -> (&Received, &[u8]) {
+        let received = &self.received[i];
+        (received, &self.bufs[i][..received.len])
+    }
+}
+
+// Keep the calling thread on one CPU
+pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "CPU number out of range",
+        ));
+    }
+    // SAFETY: an all-zero cpu_set_t is the empty set, and CPU_SET stays inside it
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Set the calling thread's nice value; going below 0 usually needs
+// CAP_SYS_NICE
+pub fn set_nice(nice: i32) -> io::Result<()> {
+    // SAFETY: gettid and setpriority only read their arguments
+    let result = unsafe {
+        let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS as _, tid, nice)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+pub fn euid() -> u32 {
+    // SAFETY: geteuid can't fail
+    unsafe { libc::geteuid() }
+}
+
+// Set the process's file mode creation mask, for everything created after
+pub fn set_umask(mask: u32) {
+    // SAFETY: umask can't fail and only reads its argument
+    unsafe {
+        libc::umask(mask as libc::mode_t);
+    }
+}
+
+// getpwnam_r(3) and getgrnam_r(3) want a buffer for the strings in the entry
+// and say ERANGE if it's too small
+fn with_lookup_buffer(
+    mut lookup: impl FnMut(&mut [libc::c_char]) -> libc::c_int,
+) -> io::Result<()> {
+    let mut buf = vec![0; 1024];
+    loop {
+        match lookup(&mut buf) {
+            0 => return Ok(()),
+            libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            error => return Err(io::Error::from_raw_os_error(error)),
+        }
+    }
+}
+
+// A user's ID, or None if there's no such user
+pub fn lookup_user(name: &str) -> io::Result<Option<u32>> {
+    let name = CString::new(name)?;
+    // SAFETY: all-zero is a valid passwd
+    let mut entry: libc::passwd = unsafe { mem::zeroed() };
+    let mut found = ptr::null_mut();
+    with_lookup_buffer(|buf| {
+        // SAFETY: every pointer is to something that outlives the call, and
+        // the buffer's length goes with it
+        unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut entry,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            )
+        }
+    })?;
+    Ok((!found.is_null()).then_some(entry.pw_uid))
+}
+
+// A group's ID, or None if there's no such group
+pub fn lookup_group(name: &str) -> io::Result<Option<u32>> {
+    let name = CString::new(name)?;
+    // SAFETY: all-zero is a valid group
+    let mut entry: libc::group = unsafe { mem::zeroed() };
+    let mut found = ptr::null_mut();
+    with_lookup_buffer(|buf| {
+        // SAFETY: as for getpwnam_r
+        unsafe {
+            libc::getgrnam_r(
+                name.as_ptr(),
+                &mut entry,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            )
+        }
+    })?;
+    Ok((!found.is_null()).then_some(entry.gr_gid))
+}
+
+// Bytes left for an unprivileged user on the filesystem holding `path`
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: all-zero is a valid statvfs
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    // SAFETY: the path is NUL-terminated and both outlive the call
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+// Set aside disk blocks for the first `len` bytes of `file`, so writing
+// them can't run out of room. fallocate(2) rather than posix_fallocate(3),
+// which writes zeros through a filesystem that can't do it.
+pub fn allocate(file: &File, len: u64) -> io::Result<()> {
+    let len = libc::off_t::try_from(len).map_err(|_| io::ErrorKind::InvalidInput)?;
+    // SAFETY: the descriptor stays open for as long as `file` is borrowed
+    if unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
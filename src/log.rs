@@ -0,0 +1,55 @@
+// What goes to standard error as the session runs, besides progress lines:
+// errors and warnings (each duplicate among them) always, each file as it
+// completes with -v, and every packet as it's parsed with -vv. The level is set once,
+// from the options, and is the same for every thread.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+impl Level {
+    // One step up from warnings for each -v
+    pub fn from_verbosity(verbose: u8) -> Self {
+        match verbose {
+            0 => Level::Warn,
+            1 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+}
+
+pub fn set(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Error,
+        1 => Level::Warn,
+        2 => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= self::level()
+}
+
+// `log!(Warn, "{}", tr!(...))` is `eprintln!`, if the level lets it through
+#[macro_export]
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::$level) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+pub use log;
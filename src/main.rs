@@ -1,193 +1,284 @@
-// Below is a version of the `main` function and some error types. This assumes
-// the existence of types like `FileManager`, `Packet`, and `PacketParseError`.
-// You can use this code as a starting point for the exercise, or you can
-// delete it and write your own code with the same function signature.
-
-use std::{
-    collections::HashMap, // HashMap for storing file packets
-    convert::TryFrom,     // Implement TryFrom trait for Packet
-    ffi::OsString,        // Storing OS-compatible filenames
-    fs::File,
-    io::{self, Write},
-    net::UdpSocket,
-    path::Path,
+// The command-line client: options, and wiring the library's pieces together
+// for each subcommand. The packet types, `FileManager`, and the errors live in
+// the library (lib.rs).
+
+use clap::{CommandFactory, Parser};
+#[cfg(target_os = "linux")]
+use segmented_file_system_client::linux;
+use segmented_file_system_client::{
+    audit, bench,
+    cli::{Cli, Command, TransportKind},
+    client::{self, receive_files, send_hello},
+    concurrent, config, discover,
+    error::ClientError,
+    events::{json_line, AuditFile, EventsFile, JsonLines, Progress, Terminal},
+    fsio, grade, history,
+    i18n::{self, tr},
+    interrupt, journal, log, manifest, multicast, pack, repl, scenario, validate,
+    validate::Diagnostic,
 };
-
-enum Packet {
-    // Define the packet structure here
-    Header(Header), // header packet with file name
-    Data(Data),     // data packet with file content
-}
-
-#[derive(Debug, PartialEq, Eq)]
-struct Header {
-    file_id: u8,
-    file_name: OsString,
-}
-
-#[derive(Debug, PartialEq, Eq)]
-struct Data {
-    file_id: u8,
-    packet_number: u16,
-    is_last_packet: bool,
-    data: Vec<u8>, // file content
-}
-
-#[derive(Debug)]
-pub struct PacketParseError {
-    message: String,
+use std::{env, io, net::Ipv4Addr, path::Path, time::Instant};
+
+fn verify_audit(path: &Path) -> Result<(), ClientError> {
+    let log = fsio::read_to_string(path)?;
+    let intact = audit::verify(&log).map_err(ClientError::AuditBroken)?;
+    println!(
+        "{}",
+        tr!(
+            "audit-intact",
+            entries = intact.entries,
+            hash = intact.last_hash
+        )
+    );
+    Ok(())
 }
 
-impl TryFrom<&[u8]> for Packet {
-    type Error = PacketParseError;
-
-    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        if bytes.len() < 2 {
-            return Err(PacketParseError {
-                message: "Packet too short".to_string(),
-            });
+// Apply --cpu and --priority to the current (receiving) thread. These are
+// best-effort: if the OS says no we say so and carry on at normal priority.
+fn tune_receive_thread(cli: &Cli) {
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(cpu) = cli.cpu {
+            if let Err(e) = linux::pin_to_cpu(cpu) {
+                log!(Warn, "{}", tr!("cpu-pin-failed", cpu = cpu, error = e));
+            }
         }
-
-        let status = bytes[0]; // First byte is status byte
-        let file_id = bytes[1]; // Second byte is file ID
-
-        if status % 2 == 0 {
-            // Header packet case
-            let file_name =
-                String::from_utf8(bytes[2..].to_vec()).map_err(|_| PacketParseError {
-                    message: "Invalid UTF-8 sequence".to_string(),
-                })?;
-
-            Ok(Packet::Header(Header {
-                file_id,
-                file_name: OsString::from(file_name),
-            }))
-        } else {
-            // Data packet case
-            if bytes.len() < 4 {
-                return Err(PacketParseError {
-                    message: "Data packet too short".to_string(),
-                });
+        if let Some(nice) = cli.priority {
+            if let Err(e) = linux::set_nice(nice) {
+                let hint = if e.kind() == io::ErrorKind::PermissionDenied {
+                    i18n::text("priority-needs-root")
+                } else {
+                    ""
+                };
+                log!(
+                    Warn,
+                    "{}{hint}",
+                    tr!("priority-failed", nice = nice, error = e)
+                );
             }
-
-            let packet_number = u16::from_be_bytes([bytes[2], bytes[3]]); // Parse 2 byte big endian packet num
-            let is_last_packet = status % 4 == 3; // check last packet if status % 4 = = 3
-            let data = bytes[4..].to_vec(); // data content
-            Ok(Packet::Data(Data {
-                file_id,
-                packet_number,
-                is_last_packet,
-                data,
-            }))
         }
     }
+    #[cfg(not(target_os = "linux"))]
+    let _ = cli;
 }
 
-// Manage and store files into disk
-#[derive(Default)]
-struct FileManager {
-    files: HashMap<u8, (Option<OsString>, Option<u16>, HashMap<u16, Vec<u8>>)>, // Mpas file ID to PacketGroup
+fn fail(e: ClientError, json: bool) -> ! {
+    if json {
+        json_line(serde_json::json!({
+            "event": "error",
+            "code": e.code(),
+            "message": e.to_string(),
+            "exit_code": e.exit_code(),
+        }));
+    } else {
+        log!(Error, "{}", tr!("error", error = e));
+    }
+    std::process::exit(e.exit_code());
 }
 
-impl FileManager {
-    // Check file have received all packets
-    fn received_all_packets(&self) -> bool {
-        self.files.len() == 3
-            && self
-                .files
-                .values()
-                .all(|(name, expected, packets)| match expected {
-                    Some(count) => packets.len() == *count as usize && name.is_some(),
-                    None => false,
-                })
-    }
-
-    // Handle incoming packets and process them
-    fn process_packet(&mut self, packet: Packet) {
-        match packet {
-            Packet::Header(Header { file_id, file_name }) => {
-                let entry = self.files.entry(file_id).or_default();
-                entry.0 = Some(file_name); // Store file name
-            }
-
-            Packet::Data(Data {
-                file_id,
-                packet_number,
-                is_last_packet,
-                data,
-            }) => {
-                let entry = self.files.entry(file_id).or_default();
-                entry.2.insert(packet_number, data); // store data packet
-                if is_last_packet {
-                    entry.1 = Some(packet_number + 1); // store expected packet count
-                }
-            }
-        }
+fn main() {
+    let cli = match config::args(env::args_os().collect()) {
+        Ok(args) => Cli::parse_from(args),
+        // A config file that can't be used is a bad option like any other
+        Err(e) => fail(
+            ClientError::BadOptions {
+                problems: vec![e.to_string()],
+            },
+            false,
+        ),
+    };
+    let json = cli.json;
+    if let Err(e) = run(cli) {
+        fail(e, json);
     }
+}
 
-    // Write all files to disk
-    fn write_all_files(&self) -> io::Result<()> {
-        for (file_name, _, packets) in self.files.values() {
-            let name = file_name.as_ref().expect("Missing file name");
-            let mut file = File::create(Path::new(name))?;
-
-            let mut keys: Vec<u16> = packets.keys().cloned().collect();
-            keys.sort_unstable(); // Sort packet numbers
+fn run(mut cli: Cli) -> Result<(), ClientError> {
+    if let Some(lang) = cli.lang {
+        i18n::set(lang);
+    }
+    log::set(log::Level::from_verbosity(cli.verbose));
 
-            for key in keys {
-                if let Some(data) = packets.get(&key) {
-                    file.write_all(data)?; // Write data to file
-                }
+    if cli.generate_man {
+        clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?;
+        return Ok(());
+    }
+    match cli.command {
+        Some(Command::Completions { shell }) => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+            return Ok(());
+        }
+        Some(Command::Repl) => return Ok(repl::run(cli.bind)?),
+        Some(Command::Bench { scenario, packets }) => return Ok(bench::run(scenario, packets)?),
+        Some(Command::Analyze {
+            journal,
+            interval,
+            csv,
+        }) => return Ok(journal::run(&journal, interval, csv, &mut io::stdout())?),
+        Some(Command::VerifyAudit { log }) => return verify_audit(&log),
+        Some(Command::Pack {
+            files,
+            into,
+            metadata,
+        }) => {
+            let packets = pack::pack(&files, &into, metadata)?;
+            let dir = into.display();
+            println!("{}", tr!("pack-wrote", packets = packets, dir = dir));
+            return Ok(());
+        }
+        Some(Command::Unpack { ref dir }) => {
+            for (file_id, path) in pack::unpack(dir, cli.output_dir())? {
+                let path = path.display();
+                println!("{}", tr!("unpack-wrote", file_id = file_id, path = path));
+            }
+            return Ok(());
+        }
+        Some(Command::Report { open }) => {
+            let Some(history) = &cli.history else {
+                let e = io::Error::new(io::ErrorKind::InvalidInput, tr!("report-no-history"));
+                return Err(e.into());
+            };
+            let path = history::write_page(history, cli.output_dir())?;
+            println!("{}", tr!("report-page-wrote", path = path.display()));
+            if open {
+                history::open(&path)?;
             }
+            return Ok(());
         }
+        Some(Command::Serve { scenario, port }) => return Ok(scenario::serve(&scenario, port)?),
+        Some(Command::Grade { .. } | Command::Fetch { .. }) | None => {}
+    }
 
-        Ok(())
+    // The errors go out the way any other failure does, --json and all
+    let (errors, warnings): (Vec<_>, Vec<_>) =
+        (validate::validate(&cli).into_iter()).partition(Diagnostic::is_error);
+    for warning in &warnings {
+        log!(Warn, "{warning}");
+    }
+    if !errors.is_empty() {
+        let problems = errors.iter().map(ToString::to_string).collect();
+        return Err(ClientError::BadOptions { problems });
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(mask) = cli.umask {
+        linux::set_umask(mask);
+    }
+    if let Some(target) = cli.discover.clone() {
+        cli.server = vec![discover::choose(&cli, &target)?];
+    }
+    if let Some(Command::Grade {
+        servers,
+        out_dir,
+        expected,
+    }) = &cli.command
+    {
+        return Ok(grade::run(&cli, servers, out_dir, expected.as_deref())?);
     }
-}
 
-#[derive(Debug)]
-pub enum ClientError {
-    IoError(std::io::Error),
-    PacketParseError(PacketParseError),
-}
+    // A bad manifest should stop us before the transfer, not after
+    let manifest = cli
+        .expect
+        .as_deref()
+        .map(|path| manifest::load(path, cli.hash))
+        .transpose()?;
 
-impl From<std::io::Error> for ClientError {
-    fn from(e: std::io::Error) -> Self {
-        ClientError::IoError(e)
+    #[cfg(feature = "tokio")]
+    if cli.run_async {
+        return segmented_file_system_client::async_client::run(&cli, manifest);
     }
-}
-
-impl From<PacketParseError> for ClientError {
-    fn from(e: PacketParseError) -> Self {
-        Self::PacketParseError(e)
+    if let Some(dir) = cli.output_dir.as_ref().filter(|_| !cli.plan) {
+        fsio::create_dir_all(dir)?;
+    }
+    tune_receive_thread(&cli);
+    interrupt::install()?;
+
+    let mut progress = Progress::default();
+    if cli.json {
+        progress.add(JsonLines::default());
+    } else if cli.tui {
+        #[cfg(feature = "tui")]
+        progress.add(segmented_file_system_client::dashboard::Dashboard::default());
+    } else if !cli.quiet {
+        progress.add(match cli.stdout {
+            Some(_) => Terminal::on_stderr(),
+            None => Terminal::default(),
+        });
+    }
+    if let Some(path) = &cli.progress_file {
+        progress.add(EventsFile::open(path)?);
+    }
+    if let Some(path) = &cli.audit {
+        progress.add(AuditFile::open(path)?);
+    }
+    if let Some(path) = &cli.replay {
+        return client::replay(&cli, path, manifest.as_ref(), &mut progress);
     }
-}
-
-fn main() -> Result<(), ClientError> {
-    let sock = UdpSocket::bind("0.0.0.0:7077")?;
 
-    let remote_addr = "127.0.0.1:6014";
-    sock.connect(remote_addr)?;
-    let mut buf = [0; 1028];
+    if let Some(group) = cli.multicast {
+        let interface = cli.interface.unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let sock = multicast::join(group, interface)?;
+        client::set_recv_buffer(&sock, &cli);
+        let joined = tr!("multicast-joined", group = group);
+        match cli.stdout {
+            _ if cli.json => json_line(serde_json::json!({
+                "event": "multicast-joined",
+                "group": group.to_string(),
+            })),
+            Some(_) => log!(Warn, "{joined}"),
+            None => println!("{joined}"),
+        }
+        return multicast::run(&sock, &cli, manifest);
+    }
 
-    let _ = sock.send(&buf[..1028]);
+    if cli.transport == TransportKind::Tcp {
+        return client::over_tcp(&cli, manifest.as_ref(), &mut progress);
+    }
 
-    let mut file_manager = FileManager::default();
+    // A pushed transfer could come from anywhere, so it's IPv4 unless told
+    let servers = cli.server.iter().filter(|_| !cli.listen_only);
+    let sock = client::bind(
+        cli.bind,
+        client::ip_version(&cli, servers.map(String::as_str)),
+    )?;
+    client::set_recv_buffer(&sock, &cli);
+    if cli.concurrent {
+        return concurrent::run(&sock, &cli, manifest);
+    }
 
-    while !file_manager.received_all_packets() {
-        let len = sock.recv(&mut buf)?;
-        let packet: Packet = buf[..len].try_into()?;
-        print!(".");
-        io::stdout().flush()?;
-        file_manager.process_packet(packet);
+    if cli.listen_only {
+        let addr = sock.local_addr()?;
+        let listening = tr!("listening", addr = addr);
+        match cli.stdout {
+            _ if cli.json => json_line(serde_json::json!({
+                "event": "listening",
+                "addr": addr.to_string(),
+            })),
+            // Said whatever the level, like a warning
+            Some(_) => log!(Warn, "{listening}"),
+            None => println!("{listening}"),
+        }
+        loop {
+            let deadline = cli.deadline.map(|timeout| Instant::now() + timeout);
+            let (file_manager, report) = receive_files(&sock, &cli, &mut progress, deadline)?;
+            client::finish_session(&cli, manifest.as_ref(), file_manager, report, deadline)?;
+            if !cli.daemon {
+                break;
+            }
+        }
+        return Ok(());
     }
 
-    file_manager.write_all_files()?;
+    let deadline = cli.deadline.map(|timeout| Instant::now() + timeout);
+    client::connect(&sock, &cli.server[0])?;
+    send_hello(&sock, &cli);
 
-    Ok(())
+    let (file_manager, report) = receive_files(&sock, &cli, &mut progress, deadline)?;
+    client::finish_session(&cli, manifest.as_ref(), file_manager, report, deadline)
 }
 
-// Set up 
+// Set up
 // mkdir ../testFiles
 // copy tests\target-files\*.txt ..\testFiles\
 // copy tests\target-files\*.jpg ..\testFiles\
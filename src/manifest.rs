@@ -0,0 +1,216 @@
+// --expect: a JSON list of the files a transfer should produce, for using the
+// client as a check in a pipeline:
+//
+//   {"files": [{"name": "small.txt", "size": 47, "hash": "sha256:..."}]}
+//
+// Sizes and hashes are optional. A hash without an `algorithm:` prefix is
+// taken to be one from --hash.
+//
+// --write-manifest writes one of these for a transfer that's done, with each
+// file's SHA-256, so the next run (or anything downstream) can check
+// against it. It says more than --expect reads: where in the output
+// directory each file went, its packet count, and when its first and latest
+// packets came.
+
+use crate::{
+    file_manager::FileManager, fsio, hash::HashAlgorithm, i18n::tr, log::log,
+    report::SessionReport, units,
+};
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub files: Vec<Expected>,
+}
+
+#[derive(Deserialize)]
+pub struct Expected {
+    pub name: String,
+    pub size: Option<u64>,
+    pub hash: Option<String>,
+}
+
+// How the output differs from the manifest
+pub enum Divergence {
+    Missing(String),
+    Extra(String),
+    WrongSize {
+        name: String,
+        expected: u64,
+        actual: u64,
+    },
+    WrongHash {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Divergence {
+    pub fn describe(&self) -> String {
+        match self {
+            Divergence::Missing(name) => tr!("manifest-missing", name = name),
+            Divergence::Extra(name) => tr!("manifest-extra", name = name),
+            Divergence::WrongSize {
+                name,
+                expected,
+                actual,
+            } => tr!(
+                "manifest-wrong-size",
+                name = name,
+                expected = expected,
+                actual = actual
+            ),
+            Divergence::WrongHash {
+                name,
+                expected,
+                actual,
+            } => tr!(
+                "manifest-wrong-hash",
+                name = name,
+                expected = expected,
+                actual = actual
+            ),
+        }
+    }
+}
+
+// Split `sha256:abc...` into its algorithm and digest
+fn parse_hash(hash: &str, default: HashAlgorithm) -> Option<(HashAlgorithm, String)> {
+    match hash.split_once(':') {
+        Some((name, digest)) => {
+            let algorithm = HashAlgorithm::from_str(name, true).ok()?;
+            Some((algorithm, digest.to_ascii_lowercase()))
+        }
+        None => Some((default, hash.to_ascii_lowercase())),
+    }
+}
+
+// Read a manifest, checking its hashes name algorithms we have
+pub fn load(path: &Path, default: HashAlgorithm) -> io::Result<Manifest> {
+    let text = fsio::read_to_string(path)?;
+    let manifest: Manifest = serde_json::from_str(&text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            tr!("manifest-bad", path = path.display(), error = e),
+        )
+    })?;
+    for file in &manifest.files {
+        if let Some(hash) = &file.hash {
+            if parse_hash(hash, default).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    tr!(
+                        "manifest-unknown-hash",
+                        path = path.display(),
+                        name = &file.name,
+                        hash = hash
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+// Everything about the written files that doesn't match the manifest
+pub fn compare(
+    manifest: &Manifest,
+    written: &[(u8, PathBuf)],
+    default: HashAlgorithm,
+) -> io::Result<Vec<Divergence>> {
+    let name_of = |path: &PathBuf| {
+        path.file_name()
+            .map(OsStr::to_string_lossy)
+            .unwrap_or_default()
+            .into_owned()
+    };
+    let mut divergences = Vec::new();
+    for expected in &manifest.files {
+        let Some((_, path)) = written
+            .iter()
+            .find(|(_, path)| name_of(path) == expected.name)
+        else {
+            divergences.push(Divergence::Missing(expected.name.clone()));
+            continue;
+        };
+        let data = fsio::read(path)?;
+        if let Some(size) = expected.size.filter(|&size| size != data.len() as u64) {
+            divergences.push(Divergence::WrongSize {
+                name: expected.name.clone(),
+                expected: size,
+                actual: data.len() as u64,
+            });
+            continue; // The hash can't match either
+        }
+        if let Some((algorithm, digest)) = expected
+            .hash
+            .as_deref()
+            .and_then(|hash| parse_hash(hash, default))
+        {
+            let actual = algorithm.digest(&data);
+            if actual != digest {
+                divergences.push(Divergence::WrongHash {
+                    name: expected.name.clone(),
+                    expected: format!("{}:{digest}", algorithm.name()),
+                    actual: format!("{}:{actual}", algorithm.name()),
+                });
+            }
+        }
+    }
+    for (_, path) in written {
+        let name = name_of(path);
+        if !manifest.files.iter().any(|expected| expected.name == name) {
+            divergences.push(Divergence::Extra(name));
+        }
+    }
+    Ok(divergences)
+}
+
+// Print how the output compares, returning how many problems there were
+pub fn report(divergences: &[Divergence], files: usize) -> usize {
+    if divergences.is_empty() {
+        println!("{}", tr!("manifest-ok", count = files));
+    }
+    for divergence in divergences {
+        log!(Error, "{}", divergence.describe());
+    }
+    divergences.len()
+}
+
+// --write-manifest: the files written into `dir`, in the order they were,
+// as a manifest at `path`
+pub fn write(
+    path: &Path,
+    dir: &Path,
+    written: &[(u8, PathBuf)],
+    files: &FileManager,
+    report: &SessionReport,
+) -> io::Result<()> {
+    let mut entries = Vec::new();
+    for (file_id, written) in written {
+        let data = fsio::read(written)?;
+        let times = report.file_times(*file_id);
+        entries.push(json!({
+            "name": written.file_name().map(OsStr::to_string_lossy),
+            "path": written.strip_prefix(dir).unwrap_or(written).to_string_lossy(),
+            "file_id": file_id,
+            "size": data.len(),
+            "packets": files.file_status(*file_id).map(|(_, packets, _)| packets),
+            "hash": HashAlgorithm::Sha256.tagged(&data),
+            "first_packet_at": times.map(|(first, _)| units::format_timestamp(first)),
+            "last_packet_at": times.map(|(_, latest)| units::format_timestamp(latest)),
+        }));
+    }
+    let manifest = json!({ "files": entries });
+    let mut text = serde_json::to_string_pretty(&manifest).map_err(io::Error::other)?;
+    text.push('\n');
+    fsio::write(path, text.as_bytes())
+}
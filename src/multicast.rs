@@ -0,0 +1,187 @@
+// --multicast: a pushed transfer (--listen-only) sent to a multicast group,
+// so one server's packets reach many clients at once. The socket joins the
+// group on --interface and is read with recv_from; every sender gets a
+// session of its own, the way --concurrent gives every server one, so two
+// servers sending to the same group don't end up in each other's files.
+// --multicast-source narrows it to the senders named; with exactly one named
+// its files go in --output-dir, and otherwise each sender's go in a
+// directory of its own there, named for it.
+//
+// The run ends once every session that started is done, or with --daemon
+// keeps going, a sender coming back starting a new session. Session
+// replies (capabilities, echoes) go back to their sender alone.
+
+use crate::{
+    cli::Cli,
+    client,
+    error::ClientError,
+    events::{Progress, Terminal},
+    fsio,
+    i18n::tr,
+    interrupt,
+    log::log,
+    manifest::Manifest,
+    session,
+    transport::{self, MemoryServer, MemoryTransport, SystemClock},
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// How long a read waits before looking for sessions that are done
+const POLL: Duration = Duration::from_millis(100);
+
+struct Running {
+    to_session: Sender<Vec<u8>>,
+    session: JoinHandle<Result<(), ClientError>>,
+}
+
+// A socket on the group's port, in the group on `interface` (any, if
+// unspecified). Other programs on this machine can be in it too.
+pub fn join(group: SocketAddrV4, interface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let joined = (|| {
+        let sock = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        sock.set_reuse_address(true)?;
+        sock.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, group.port())).into())?;
+        sock.join_multicast_v4(group.ip(), &interface)?;
+        Ok::<_, io::Error>(sock.into())
+    })();
+    joined.map_err(|e| {
+        let message = tr!(
+            "multicast-join-failed",
+            group = group,
+            interface = interface,
+            error = e
+        );
+        io::Error::new(e.kind(), message)
+    })
+}
+
+// Whether `from` is a sender to listen to
+fn wanted(cli: &Cli, from: SocketAddr) -> bool {
+    match from {
+        SocketAddr::V4(from) => {
+            cli.multicast_source.is_empty() || cli.multicast_source.contains(from.ip())
+        }
+        SocketAddr::V6(_) => false,
+    }
+}
+
+fn start(
+    sock: &UdpSocket,
+    cli: &Cli,
+    from: SocketAddr,
+    manifest: &Arc<Option<Manifest>>,
+    finishing: &Arc<Mutex<()>>,
+    deadline: Option<Instant>,
+) -> Result<Running, ClientError> {
+    let several = cli.multicast_source.len() != 1;
+    let cli = client::for_server(cli, &from.to_string(), several);
+    fsio::create_dir_all(cli.output_dir())?;
+    let (
+        mut transport,
+        MemoryServer {
+            requests,
+            to_client,
+        },
+    ) = MemoryTransport::pair();
+    let out = sock.try_clone()?;
+    thread::spawn(move || {
+        for datagram in requests {
+            let _ = out.send_to(&datagram, from);
+        }
+    });
+    let (manifest, finishing) = (manifest.clone(), finishing.clone());
+    let session = thread::spawn(move || {
+        let mut progress = Progress::default();
+        if !cli.quiet {
+            progress.add(Terminal::default());
+        }
+        let received = session::receive_files_over(
+            &mut transport,
+            &SystemClock,
+            &cli,
+            &mut progress,
+            deadline,
+        );
+        drop(transport); // Lets its sender finish
+        let (file_manager, report) = received?;
+        let _turn = finishing
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        client::finish_session(&cli, (*manifest).as_ref(), file_manager, report, deadline)
+    });
+    Ok(Running {
+        to_session: to_client,
+        session,
+    })
+}
+
+pub fn run(sock: &UdpSocket, cli: &Cli, manifest: Option<Manifest>) -> Result<(), ClientError> {
+    let manifest = Arc::new(manifest);
+    // Reports and --expect results come out one session at a time
+    let finishing = Arc::new(Mutex::new(()));
+    // A daemon's sessions each have the whole of --deadline
+    let deadline = (cli.deadline.filter(|_| !cli.daemon)).map(|timeout| Instant::now() + timeout);
+    let mut running: HashMap<SocketAddr, Running> = HashMap::new();
+    let mut started = false;
+
+    sock.set_read_timeout(Some(POLL))?;
+    let mut buf = vec![0; cli.receive_buffer_len()];
+    let mut first_error = None;
+    loop {
+        match sock.recv_from(&mut buf) {
+            Ok((len, from)) if wanted(cli, from) => {
+                let session = match running.entry(from) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let deadline = match cli.daemon {
+                            true => cli.deadline.map(|timeout| Instant::now() + timeout),
+                            false => deadline,
+                        };
+                        started = true;
+                        entry.insert(start(sock, cli, from, &manifest, &finishing, deadline)?)
+                    }
+                };
+                let _ = session.to_session.send(buf[..len].to_vec());
+            }
+            // Someone else sending to the group
+            Ok(_) => {}
+            Err(e) if transport::is_timeout(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let done: Vec<SocketAddr> = (running.iter())
+            .filter(|(_, running)| running.session.is_finished())
+            .map(|(&addr, _)| addr)
+            .collect();
+        for from in done {
+            let Running { session, .. } = running.remove(&from).expect("listed as done");
+            let result = session.join().map_err(|_| {
+                io::Error::other(tr!("concurrent-panicked", server = from.to_string()))
+            })?;
+            if let Err(e) = result {
+                log!(
+                    Error,
+                    "{}",
+                    tr!("server-session-failed", server = from, error = e)
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+        if running.is_empty() {
+            if interrupt::requested() || (started && !cli.daemon) {
+                break;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(ClientError::DeadlinePassed);
+            }
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
@@ -0,0 +1,104 @@
+// Retransmission requests (--nack-after): once the server has gone quiet with
+// gaps left in some files, the client lists the packets it's missing so a
+// server that understands can send just those. Unlike a resume token, which
+// describes what we have, a NACK names what we don't, and goes out without
+// asking for the transfer again. The course server ignores it; --nack-after
+// is off unless asked for.
+//
+// Layout, big-endian, not padded:
+//
+//   "SFSN"  version  session ID (8)  pair count (2)
+//   per pair: file ID (1), packet number (2, or 4 in version 2)
+//
+// Version 2's pairs reach the packets past 65535 that wide data packets
+// carry. It's only sent to a server that agreed to capability::WIDE_NACK
+// under protocol v2; anyone else gets version 1, and gaps past 65535 wait
+// for the server to go round again. A long list is split over several
+// datagrams, each a complete NACK.
+
+use crate::wire;
+
+pub const MAGIC: &[u8; 4] = b"SFSN";
+pub const VERSION: u8 = 1;
+pub const WIDE_VERSION: u8 = 2;
+pub const HEADER_LEN: usize = 4 + 1 + 8 + 2;
+pub const PAIR_LEN: usize = 3;
+pub const WIDE_PAIR_LEN: usize = 5;
+// As many pairs as fit in the largest packet
+pub const MAX_PAIRS: usize = (wire::MAX_PACKET - HEADER_LEN) / PAIR_LEN;
+pub const MAX_WIDE_PAIRS: usize = (wire::MAX_PACKET - HEADER_LEN) / WIDE_PAIR_LEN;
+// Datagrams sent at a time; whatever's left waits for the next round
+pub const MAX_DATAGRAMS: usize = 8;
+
+// The NACKs for `missing`, in the order given, at most MAX_PAIRS apiece
+pub fn encode(session_id: u64, missing: &[(u8, u16)]) -> Vec<Vec<u8>> {
+    split(
+        session_id,
+        VERSION,
+        missing,
+        MAX_PAIRS,
+        |out, packet_number| out.extend_from_slice(&packet_number.to_be_bytes()),
+    )
+}
+
+// The same as version 2, at most MAX_WIDE_PAIRS apiece
+pub fn encode_wide(session_id: u64, missing: &[(u8, u32)]) -> Vec<Vec<u8>> {
+    split(
+        session_id,
+        WIDE_VERSION,
+        missing,
+        MAX_WIDE_PAIRS,
+        |out, packet_number| out.extend_from_slice(&packet_number.to_be_bytes()),
+    )
+}
+
+fn split<N: Copy>(
+    session_id: u64,
+    version: u8,
+    missing: &[(u8, N)],
+    most: usize,
+    number: impl Fn(&mut Vec<u8>, N),
+) -> Vec<Vec<u8>> {
+    missing
+        .chunks(most)
+        .map(|pairs| {
+            let mut out = Vec::with_capacity(wire::MAX_PACKET);
+            out.extend_from_slice(MAGIC);
+            out.push(version);
+            out.extend_from_slice(&session_id.to_be_bytes());
+            out.extend_from_slice(&(pairs.len() as u16).to_be_bytes());
+            for &(file_id, packet_number) in pairs {
+                out.push(file_id);
+                number(&mut out, packet_number);
+            }
+            out
+        })
+        .collect()
+}
+
+// The session ID and pairs in a NACK of either version, for servers and
+// tests
+pub fn decode(bytes: &[u8]) -> Option<(u64, Vec<(u8, u32)>)> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return None;
+    }
+    let pair_len = match bytes[4] {
+        VERSION => PAIR_LEN,
+        WIDE_VERSION => WIDE_PAIR_LEN,
+        _ => return None,
+    };
+    let session_id = u64::from_be_bytes(bytes[5..13].try_into().ok()?);
+    let count = u16::from_be_bytes([bytes[13], bytes[14]]) as usize;
+    let body = &bytes[HEADER_LEN..];
+    if body.len() != count * pair_len {
+        return None;
+    }
+    let pairs = body
+        .chunks(pair_len)
+        .map(|pair| {
+            let number = (pair[1..].iter()).fold(0, |n, &byte| n << 8 | u32::from(byte));
+            (pair[0], number)
+        })
+        .collect();
+    Some((session_id, pairs))
+}
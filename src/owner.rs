@@ -0,0 +1,66 @@
+// --chown and --umask, for running as root on someone else's behalf (say, a
+// systemd service receiving for a student): the files a session writes, and
+// the directories `grade` makes for them, are handed to that account. Names
+// are looked up while the options are parsed, so a typo stops the run before
+// anything is received.
+
+use crate::{fsio, i18n};
+use std::{io, path::Path};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+// A name or a number
+fn user_id(user: &str) -> Result<u32, String> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    #[cfg(target_os = "linux")]
+    return crate::linux::lookup_user(user)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| i18n::tr!("chown-no-user", name = user));
+    #[cfg(not(target_os = "linux"))]
+    Err(i18n::text("chown-names-linux-only").to_string())
+}
+
+fn group_id(group: &str) -> Result<u32, String> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    #[cfg(target_os = "linux")]
+    return crate::linux::lookup_group(group)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| i18n::tr!("chown-no-group", name = group));
+    #[cfg(not(target_os = "linux"))]
+    Err(i18n::text("chown-names-linux-only").to_string())
+}
+
+// `user:group`, `user`, or `:group`, by name or number; a part left out
+// isn't changed
+pub fn parse(spec: &str) -> Result<Owner, String> {
+    let (user, group) = spec.split_once(':').unwrap_or((spec, ""));
+    if user.is_empty() && group.is_empty() {
+        return Err(i18n::text("chown-empty").to_string());
+    }
+    Ok(Owner {
+        uid: (!user.is_empty()).then(|| user_id(user)).transpose()?,
+        gid: (!group.is_empty()).then(|| group_id(group)).transpose()?,
+    })
+}
+
+// Octal, as umask(1) takes it
+pub fn parse_umask(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 8)
+        .ok()
+        .filter(|&mask| mask <= 0o777)
+        .ok_or_else(|| i18n::text("umask-not-octal").to_string())
+}
+
+impl Owner {
+    pub fn apply(self, path: &Path) -> io::Result<()> {
+        fsio::chown(path, self.uid, self.gid)
+    }
+}
@@ -0,0 +1,108 @@
+// `pack` and `unpack`: files and their packets, with no network between
+// them. `pack` cuts files into the packets the companion server would send,
+// IDs counting up from 0, and writes each datagram's bytes to a file of its
+// own, named so they sort in the order they'd go out:
+//
+//   00000-header-0.bin  00001-data-0-0.bin  00002-data-0-1.bin  ...
+//
+// `unpack` reads a directory of them back through a FileManager and writes
+// out the files, the way a session would have. Like datagrams, they can be
+// in any order, and any that are missing leave their file unwritten. For
+// seeing the format a byte at a time, and for fixtures other servers and
+// clients can be tested against.
+
+use crate::{
+    cli::WriteOrder,
+    error::ClientError,
+    file_manager::FileManager,
+    fsio::{self, Action},
+    i18n::tr,
+    log::log,
+    packet::Packet,
+    server, units,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// What to call the `index`th packet
+pub fn packet_file_name(index: usize, packet: &Packet) -> String {
+    match packet {
+        Packet::Header(header) => format!("{index:05}-header-{}.bin", header.file_id),
+        Packet::Data(data) => format!(
+            "{index:05}-data-{}-{}.bin",
+            data.file_id, data.packet_number
+        ),
+        Packet::Checksum(checksum) => format!("{index:05}-checksum-{}.bin", checksum.file_id),
+    }
+}
+
+// Write `files`' packets into `into`, and say how many there were
+pub fn pack(files: &[PathBuf], into: &Path, with_metadata: bool) -> io::Result<usize> {
+    let packets = server::load(files, with_metadata)?;
+    fsio::create_dir_all(into)?;
+    for (index, packet) in packets.iter().enumerate() {
+        fsio::write(
+            &into.join(packet_file_name(index, packet)),
+            &packet.to_bytes(),
+        )?;
+    }
+    Ok(packets.len())
+}
+
+// The packets in `dir`, in name order, with the files they came from
+pub fn read(dir: &Path) -> io::Result<Vec<(PathBuf, Packet)>> {
+    let mut paths = fsio::at(Action::Read, dir, || {
+        fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    paths.retain(|path| path.is_file());
+    paths.sort();
+    let mut packets = Vec::new();
+    for path in paths {
+        let bytes = fsio::read(&path)?;
+        let packet = Packet::try_from(&bytes[..]).map_err(|e| {
+            let message = tr!("unpack-bad-packet", path = path.display(), error = e);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })?;
+        packets.push((path, packet));
+    }
+    Ok(packets)
+}
+
+// Put the files `dir`'s packets make up in `output_dir`, and say where each
+// went
+pub fn unpack(dir: &Path, output_dir: &Path) -> Result<Vec<(u8, PathBuf)>, ClientError> {
+    let mut file_manager = FileManager::default();
+    for (_, packet) in read(dir)? {
+        file_manager.process_packet(packet);
+    }
+    for file_id in file_manager.file_ids() {
+        if !file_manager.is_complete(file_id) {
+            let missing = file_manager.missing_packets(file_id).unwrap_or_default();
+            log!(
+                Warn,
+                "{}",
+                tr!(
+                    "unpack-incomplete",
+                    file_id = file_id,
+                    missing = units::count(&missing)
+                )
+            );
+        }
+    }
+    fsio::create_dir_all(output_dir)?;
+    let finalized = file_manager.write_all_files(output_dir, WriteOrder::Id, None);
+    for (file_id, e) in &finalized.failed {
+        log!(
+            Error,
+            "{}",
+            tr!("write-failed", file_id = file_id, error = e)
+        );
+    }
+    let written = finalized.into_result()?;
+    file_manager.clean_up()?;
+    Ok(written)
+}
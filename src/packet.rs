@@ -0,0 +1,487 @@
+// Packets as they come off the wire: a header naming a file (and maybe saying
+// more about it), a piece of one, or a checksum of a whole one. `PacketView` picks a datagram apart in place; `Packet` owns its bytes.
+
+use crate::{
+    compression::Compression,
+    i18n::tr,
+    intern,
+    protocol::{self, Version},
+    wire,
+};
+use smallvec::SmallVec;
+use std::{
+    convert::TryFrom,
+    error::Error,
+    ffi::OsStr,
+    fmt,
+    str::Utf8Error,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Most payloads are a full 1 KB and live on the heap either way, but short
+// last packets fit inline without an allocation of their own
+pub const INLINE_PAYLOAD: usize = 64;
+pub type Payload = SmallVec<[u8; INLINE_PAYLOAD]>;
+
+pub enum Packet {
+    // Define the packet structure here
+    Header(Header), // header packet with file name
+    Data(Data),     // data packet with file content
+    Checksum(Checksum),
+}
+
+impl Packet {
+    pub fn file_id(&self) -> u8 {
+        match self {
+            Packet::Header(header) => header.file_id,
+            Packet::Data(data) => data.file_id,
+            Packet::Checksum(checksum) => checksum.file_id,
+        }
+    }
+
+    // Append the packet to `buf` as the server sends it, without a CRC. A
+    // piece numbered past what two bytes hold goes in a wide packet.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Packet::Header(header) if !header.metadata.has_fields() => {
+                let status = wire::HEADER_STATUS | header.metadata.compression_bits();
+                buf.extend_from_slice(&[status, header.file_id]);
+                buf.extend_from_slice(header.file_name.as_encoded_bytes());
+            }
+            Packet::Header(header) => {
+                let status =
+                    wire::HEADER_STATUS | wire::EXTENDED_BIT | header.metadata.compression_bits();
+                buf.extend_from_slice(&[status, header.file_id]);
+                header.metadata.encode(buf);
+                buf.extend_from_slice(header.file_name.as_encoded_bytes());
+            }
+            Packet::Data(data) => {
+                let (file_id, is_last) = (data.file_id, data.is_last_packet);
+                match u16::try_from(data.packet_number) {
+                    Ok(number) => {
+                        buf.extend_from_slice(&wire::data_prefix(file_id, number, is_last))
+                    }
+                    Err(_) => buf.extend_from_slice(&wire::wide_data_prefix(
+                        file_id,
+                        data.packet_number,
+                        is_last,
+                    )),
+                }
+                buf.extend_from_slice(&data.data);
+            }
+            Packet::Checksum(checksum) => {
+                buf.extend_from_slice(&[wire::CHECKSUM_STATUS, checksum.file_id]);
+                buf.extend_from_slice(&checksum.digest);
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.encoded_len());
+        self.encode(&mut out);
+        out
+    }
+
+    // How many bytes `encode` appends
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Packet::Header(header) if !header.metadata.has_fields() => {
+                wire::FILE_NAME + header.file_name.len()
+            }
+            Packet::Header(header) => wire::EXTENDED_FILE_NAME + header.file_name.len(),
+            Packet::Data(data) if data.packet_number > u16::MAX as u32 => {
+                wire::WIDE_PAYLOAD + data.data.len()
+            }
+            Packet::Data(data) => wire::PAYLOAD + data.data.len(),
+            Packet::Checksum(_) => wire::CHECKSUM_PACKET_LEN,
+        }
+    }
+
+    // Look at a packet we already own the way we'd look at a fresh datagram
+    pub fn view(&self) -> PacketView<'_> {
+        match self {
+            Packet::Header(header) => PacketView::Header {
+                file_id: header.file_id,
+                file_name: &header.file_name,
+                metadata: header.metadata,
+            },
+            Packet::Data(data) => PacketView::Data {
+                file_id: data.file_id,
+                packet_number: data.packet_number,
+                is_last_packet: data.is_last_packet,
+                payload: &data.data,
+            },
+            Packet::Checksum(checksum) => PacketView::Checksum {
+                file_id: checksum.file_id,
+                digest: checksum.digest,
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Header {
+    pub file_id: u8,
+    pub file_name: Arc<OsStr>, // shared between repeats of the same name
+    pub metadata: Metadata,
+}
+
+// What a header says about its file besides its name: the parts an extended
+// header carries, each only if the server said it, and whether the data is
+// compressed, which any header can say
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub size: Option<u64>,
+    // Seconds since 1970, before it if negative
+    pub modified: Option<i64>,
+    // Unix permission bits
+    pub mode: Option<u32>,
+    pub compression: Compression,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Metadata::default()
+    }
+
+    // Whether it takes an extended header to say
+    pub fn has_fields(&self) -> bool {
+        self.size.is_some() || self.modified.is_some() || self.mode.is_some()
+    }
+
+    fn compression_bits(&self) -> u8 {
+        match self.compression {
+            Compression::None => 0,
+            Compression::Gzip => wire::GZIP_BITS,
+            Compression::Zstd => wire::ZSTD_BITS,
+        }
+    }
+
+    pub fn modified_time(&self) -> Option<SystemTime> {
+        let seconds = self.modified?;
+        let offset = Duration::from_secs(seconds.unsigned_abs());
+        match seconds < 0 {
+            true => UNIX_EPOCH.checked_sub(offset),
+            false => UNIX_EPOCH.checked_add(offset),
+        }
+    }
+
+    // From the bytes between an extended header's file ID and its name
+    fn parse(bytes: &[u8]) -> Self {
+        let fields = bytes[0];
+        let size = u64::from_be_bytes(bytes[1..9].try_into().unwrap());
+        let modified = i64::from_be_bytes(bytes[9..17].try_into().unwrap());
+        let mode = u32::from_be_bytes(bytes[17..21].try_into().unwrap());
+        Metadata {
+            size: (fields & wire::SIZE_FIELD != 0).then_some(size),
+            modified: (fields & wire::MODIFIED_FIELD != 0).then_some(modified),
+            mode: (fields & wire::MODE_FIELD != 0).then_some(mode),
+            compression: Compression::None,
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let fields = [
+            (self.size.is_some(), wire::SIZE_FIELD),
+            (self.modified.is_some(), wire::MODIFIED_FIELD),
+            (self.mode.is_some(), wire::MODE_FIELD),
+        ];
+        buf.push((fields.iter().filter(|(given, _)| *given)).fold(0, |bits, (_, bit)| bits | bit));
+        buf.extend_from_slice(&self.size.unwrap_or(0).to_be_bytes());
+        buf.extend_from_slice(&self.modified.unwrap_or(0).to_be_bytes());
+        buf.extend_from_slice(&self.mode.unwrap_or(0).to_be_bytes());
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Data {
+    pub file_id: u8,
+    pub packet_number: u32,
+    pub is_last_packet: bool,
+    pub data: Payload, // file content
+}
+
+// The SHA-256 a file should have once it's put together
+#[derive(Debug, PartialEq, Eq)]
+pub struct Checksum {
+    pub file_id: u8,
+    pub digest: [u8; wire::DIGEST_LEN],
+}
+
+// Ways a datagram can fail to be a usable packet, counted per kind in the
+// session report
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Malformed {
+    TooShort,
+    BadUtf8, // only where names have to be UTF-8
+    DataTooShort,
+    TooLong,
+    BadChecksum, // only with --crc
+    ChecksumLength,
+    UnknownCompression,
+    Forged, // only with --psk
+}
+
+// Why a datagram isn't a packet, with what's known about it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PacketParseError {
+    // Shorter than a status byte and a file ID
+    PacketTooShort { len: usize },
+    InvalidUtf8Filename { file_id: u8, source: Utf8Error },
+    // A data packet with no room for its packet number
+    DataPacketTooShort { file_id: u8, len: usize },
+    // Longer than the biggest packet the server sends
+    DatagramTooLong { len: usize, limit: usize },
+    // Filled the receive buffer, so its end was cut off and its length
+    // isn't known
+    DatagramCutShort { buffer: usize },
+    // A checksum packet without room for its digest, or with more than it
+    ChecksumWrongLength { file_id: u8, len: usize },
+    // An extended header without room for its fields
+    ExtendedHeaderTooShort { file_id: u8, len: usize },
+    // A header with both compression bits set
+    UnknownCompression { file_id: u8 },
+}
+
+impl PacketParseError {
+    // Which count in the session report it goes under
+    pub fn kind(&self) -> Malformed {
+        match self {
+            PacketParseError::PacketTooShort { .. } => Malformed::TooShort,
+            PacketParseError::InvalidUtf8Filename { .. } => Malformed::BadUtf8,
+            PacketParseError::DataPacketTooShort { .. } => Malformed::DataTooShort,
+            PacketParseError::DatagramTooLong { .. }
+            | PacketParseError::DatagramCutShort { .. } => Malformed::TooLong,
+            PacketParseError::ChecksumWrongLength { .. } => Malformed::ChecksumLength,
+            PacketParseError::ExtendedHeaderTooShort { .. } => Malformed::TooShort,
+            PacketParseError::UnknownCompression { .. } => Malformed::UnknownCompression,
+        }
+    }
+}
+
+impl fmt::Display for PacketParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&match self {
+            PacketParseError::PacketTooShort { len } => tr!("packet-too-short", len = len),
+            PacketParseError::InvalidUtf8Filename { file_id, .. } => {
+                tr!("invalid-utf8", file_id = file_id)
+            }
+            PacketParseError::DataPacketTooShort { file_id, len } => {
+                tr!("data-packet-too-short", file_id = file_id, len = len)
+            }
+            PacketParseError::DatagramTooLong { len, limit } => {
+                tr!("datagram-too-long", len = len, limit = limit)
+            }
+            PacketParseError::DatagramCutShort { buffer } => {
+                tr!("datagram-cut-short", buffer = buffer)
+            }
+            PacketParseError::ChecksumWrongLength { file_id, len } => tr!(
+                "checksum-wrong-length",
+                file_id = file_id,
+                len = len,
+                expected = wire::CHECKSUM_PACKET_LEN
+            ),
+            PacketParseError::ExtendedHeaderTooShort { file_id, len } => tr!(
+                "extended-header-too-short",
+                file_id = file_id,
+                len = len,
+                expected = wire::MIN_EXTENDED_LEN
+            ),
+            PacketParseError::UnknownCompression { file_id } => {
+                tr!("unknown-compression", file_id = file_id)
+            }
+        })
+    }
+}
+
+impl Error for PacketParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PacketParseError::InvalidUtf8Filename { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// A datagram checked and picked apart in place. Nothing is copied out of the
+// receive buffer until `claim`, so a packet that gets dropped (a duplicate,
+// noise before a pushed session starts, something the limits refuse) never
+// costs an allocation.
+pub enum PacketView<'a> {
+    Header {
+        file_id: u8,
+        file_name: &'a OsStr,
+        metadata: Metadata,
+    },
+    Data {
+        file_id: u8,
+        packet_number: u32,
+        is_last_packet: bool,
+        payload: &'a [u8],
+    },
+    Checksum {
+        file_id: u8,
+        digest: [u8; wire::DIGEST_LEN],
+    },
+}
+
+impl<'a> PacketView<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, PacketParseError> {
+        Self::parse_as(bytes, protocol::LATEST)
+    }
+
+    // As `version` reads it: under v1 the status bits v2 added are ignored
+    pub fn parse_as(bytes: &'a [u8], version: Version) -> Result<Self, PacketParseError> {
+        Self::parse_with(bytes, version, os_name)
+    }
+
+    // Where names have to be UTF-8, a header whose name isn't, with the name
+    // put in `lossy` with U+FFFD for the bytes that don't fit
+    pub fn parse_lossy(
+        bytes: &'a [u8],
+        version: Version,
+        lossy: &'a mut String,
+    ) -> Result<Self, PacketParseError> {
+        Self::parse_with(bytes, version, |name| {
+            *lossy = String::from_utf8_lossy(name).into_owned();
+            let lossy: &'a String = lossy;
+            Ok(OsStr::new(lossy))
+        })
+    }
+
+    fn parse_with(
+        bytes: &'a [u8],
+        version: Version,
+        name: impl FnOnce(&'a [u8]) -> Result<&'a OsStr, Utf8Error>,
+    ) -> Result<Self, PacketParseError> {
+        if bytes.len() < wire::MIN_PACKET_LEN {
+            return Err(PacketParseError::PacketTooShort { len: bytes.len() });
+        }
+
+        let status = version.status(bytes[wire::STATUS]); // First byte is status byte
+        let file_id = bytes[wire::FILE_ID]; // Second byte is file ID
+
+        if wire::is_checksum(status) {
+            let digest = bytes[wire::FILE_NAME..].try_into().map_err(|_| {
+                PacketParseError::ChecksumWrongLength {
+                    file_id,
+                    len: bytes.len(),
+                }
+            })?;
+            Ok(PacketView::Checksum { file_id, digest })
+        } else if !wire::is_data(status) {
+            // Header packet case
+            let compression = match status & wire::COMPRESSION_BITS {
+                0 => Compression::None,
+                wire::GZIP_BITS => Compression::Gzip,
+                wire::ZSTD_BITS => Compression::Zstd,
+                _ => return Err(PacketParseError::UnknownCompression { file_id }),
+            };
+            let (metadata, offset) = match wire::is_extended(status) {
+                true if bytes.len() < wire::MIN_EXTENDED_LEN => {
+                    return Err(PacketParseError::ExtendedHeaderTooShort {
+                        file_id,
+                        len: bytes.len(),
+                    })
+                }
+                true => (
+                    Metadata::parse(&bytes[wire::METADATA..wire::EXTENDED_FILE_NAME]),
+                    wire::EXTENDED_FILE_NAME,
+                ),
+                false => (Metadata::default(), wire::FILE_NAME),
+            };
+            let metadata = Metadata {
+                compression,
+                ..metadata
+            };
+            let file_name = name(&bytes[offset..])
+                .map_err(|source| PacketParseError::InvalidUtf8Filename { file_id, source })?;
+            Ok(PacketView::Header {
+                file_id,
+                file_name,
+                metadata,
+            })
+        } else {
+            // Data packet case
+            let payload = wire::payload_offset(status);
+            if bytes.len() < payload {
+                return Err(PacketParseError::DataPacketTooShort {
+                    file_id,
+                    len: bytes.len(),
+                });
+            }
+
+            Ok(PacketView::Data {
+                file_id,
+                packet_number: wire::packet_number_as(bytes, status), // Parse 2 (or 4) byte big endian packet num
+                is_last_packet: wire::is_last(status), // check last packet if status % 4 = = 3
+                payload: &bytes[payload..],            // data content
+            })
+        }
+    }
+
+    pub fn file_id(&self) -> u8 {
+        match self {
+            PacketView::Header { file_id, .. }
+            | PacketView::Data { file_id, .. }
+            | PacketView::Checksum { file_id, .. } => *file_id,
+        }
+    }
+
+    // Copy the packet out of the buffer to keep it
+    pub fn claim(&self) -> Packet {
+        match *self {
+            PacketView::Header {
+                file_id,
+                file_name,
+                metadata,
+            } => Packet::Header(Header {
+                file_id,
+                file_name: intern::file_name(file_name),
+                metadata,
+            }),
+            PacketView::Data {
+                file_id,
+                packet_number,
+                is_last_packet,
+                payload,
+            } => Packet::Data(Data {
+                file_id,
+                packet_number,
+                is_last_packet,
+                data: Payload::from_slice(payload),
+            }),
+            PacketView::Checksum { file_id, digest } => {
+                Packet::Checksum(Checksum { file_id, digest })
+            }
+        }
+    }
+}
+
+// A name as the bytes the server sent. Any bytes will do on Unix, so a name
+// in some legacy encoding is written as it came; elsewhere names have to be
+// UTF-8.
+#[cfg(unix)]
+fn os_name(bytes: &[u8]) -> Result<&OsStr, Utf8Error> {
+    Ok(std::os::unix::ffi::OsStrExt::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn os_name(bytes: &[u8]) -> Result<&OsStr, Utf8Error> {
+    std::str::from_utf8(bytes).map(OsStr::new)
+}
+
+impl From<&Packet> for Vec<u8> {
+    fn from(packet: &Packet) -> Self {
+        packet.to_bytes()
+    }
+}
+
+impl TryFrom<&[u8]> for Packet {
+    type Error = PacketParseError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        PacketView::parse(bytes).map(|view| view.claim())
+    }
+}
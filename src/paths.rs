@@ -0,0 +1,52 @@
+// Long output paths on Windows. Deep output directories plus long
+// server-provided names easily pass MAX_PATH (260 characters), after which
+// plain Win32 file calls fail. Prefixing an absolute path with `\\?\` lifts
+// the limit, so the writer passes every path through `long` just before
+// touching the filesystem. Everywhere else `long` changes nothing.
+
+use std::path::{Path, PathBuf};
+
+// Past this many characters Windows starts refusing paths. Directories are
+// stricter than files (they need room for an 8.3 name inside), hence 248
+// rather than 260.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub const MAX_SHORT_PATH: usize = 248;
+
+const VERBATIM: &str = r"\\?\";
+
+// The extended-length form of an absolute Windows path: `C:\x` becomes
+// `\\?\C:\x` and `\\server\share\x` becomes `\\?\UNC\server\share\x`.
+// Verbatim paths don't get `/` translated for them, so that's done here too.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn extended_length(path: &str) -> String {
+    if path.starts_with(VERBATIM) {
+        return path.to_string();
+    }
+    let path = path.replace('/', r"\");
+    match path.strip_prefix(r"\\") {
+        Some(unc) => format!(r"{VERBATIM}UNC\{unc}"),
+        None => format!("{VERBATIM}{path}"),
+    }
+}
+
+// `path` in a form the OS will accept however long it is
+#[cfg(windows)]
+pub fn long(path: &Path) -> PathBuf {
+    // The limit applies to the full path, and verbatim paths skip
+    // normalization, so `..` and relative parts have to be resolved first
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    if absolute.as_os_str().len() < MAX_SHORT_PATH {
+        return path.to_path_buf();
+    }
+    match absolute.to_str() {
+        Some(absolute) => PathBuf::from(extended_length(absolute)),
+        None => absolute,
+    }
+}
+
+#[cfg(not(windows))]
+pub fn long(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
@@ -0,0 +1,74 @@
+// The server a session is talking to: where its packets come from, the
+// servers there are to fall back on (--failover), and when it was last heard
+// from. The first datagram that counts fixes the source. With --listen-only,
+// where nobody was asked, datagrams from anywhere else after that are from
+// some other transfer and are left out; a server that was asked can answer
+// from another port, so nothing is held against it there. Failing over
+// connects the transport to the next server and starts the wait over, as if
+// that one had been the first asked.
+
+use crate::{cli::Cli, transport::Transport};
+use std::{collections::VecDeque, io, net::SocketAddr, time::Instant};
+
+pub struct Peer {
+    source: Option<SocketAddr>,
+    // Once there's a source, only it is listened to
+    pinned: bool,
+    // Servers still to try, in order
+    fallbacks: VecDeque<String>,
+    // Anything at all, usable or not
+    last_heard: Instant,
+}
+
+impl Peer {
+    pub fn new(cli: &Cli, now: Instant) -> Self {
+        let fallbacks = match cli.failover && !cli.listen_only {
+            true => cli.server.iter().skip(1).cloned().collect(),
+            false => VecDeque::new(),
+        };
+        Peer {
+            source: None,
+            pinned: cli.listen_only,
+            fallbacks,
+            last_heard: now,
+        }
+    }
+
+    pub fn source(&self) -> Option<SocketAddr> {
+        self.source
+    }
+
+    pub fn last_heard(&self) -> Instant {
+        self.last_heard
+    }
+
+    // A datagram came from `from` at `now`; false if it's from somewhere
+    // this session doesn't listen to
+    pub fn hear(&mut self, from: SocketAddr, now: Instant) -> bool {
+        self.last_heard = now;
+        !self.pinned || self.source.is_none_or(|source| source == from)
+    }
+
+    // A packet from `from` counted. True if it's the first, and so where the
+    // session's packets come from.
+    pub fn settle(&mut self, from: SocketAddr) -> bool {
+        let first = self.source.is_none();
+        self.source.get_or_insert(from);
+        first
+    }
+
+    // Connect `transport` to the next server to try, and say which it is;
+    // None if there are none left
+    pub fn fail_over(
+        &mut self,
+        transport: &mut dyn Transport,
+        now: Instant,
+    ) -> io::Result<Option<String>> {
+        let Some(server) = self.fallbacks.front() else {
+            return Ok(None);
+        };
+        transport.connect(server)?;
+        self.last_heard = now;
+        Ok(self.fallbacks.pop_front())
+    }
+}
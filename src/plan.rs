@@ -0,0 +1,89 @@
+// --plan: the transfer as usual, but at the end, instead of writing the
+// files, what writing them would do. First the directories that would be
+// made, then a line for each complete file, in --write-order: where it
+// would go under --on-conflict and what would become of anything already
+// there. Sizes are as received, before unpacking or --normalize-text.
+
+use crate::{
+    cli::WriteOrder,
+    file_manager::{FileManager, Placement},
+    fsio,
+    i18n::{self, tr},
+    units,
+};
+use std::path::{Path, PathBuf};
+
+pub fn plan(files: &mut FileManager, dir: &Path, order: WriteOrder) -> Vec<String> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let mut lines = Vec::new();
+    let mut count = 0;
+    for file_id in files.ordered_ids(order) {
+        let Some(size) = files.received_len(file_id) else {
+            continue;
+        };
+        let Some(placement) = files.place(dir, file_id) else {
+            continue;
+        };
+        count += 1;
+        let size = units::format_size(size);
+        let (written, line) = match placement {
+            Placement::New(path) => {
+                let line = tr!("plan-new", path = path.display(), size = size);
+                (Some(path), line)
+            }
+            Placement::Overwrite(path) => {
+                let line = tr!("plan-overwrite", path = path.display(), size = size);
+                (Some(path), line)
+            }
+            Placement::Kept(path) => {
+                let line = tr!("plan-kept", path = path.display(), file_id = file_id);
+                (None, line)
+            }
+            Placement::Beside {
+                path,
+                existing,
+                compare,
+            } => {
+                let key = if compare {
+                    "plan-beside-compare"
+                } else {
+                    "plan-beside"
+                };
+                let line = i18n::format(
+                    key,
+                    &[
+                        ("path", &path.display()),
+                        ("size", &size),
+                        ("existing", &existing.display()),
+                    ],
+                );
+                (Some(path), line)
+            }
+            Placement::Refused(path) => {
+                let line = tr!("plan-refused", path = path.display(), file_id = file_id);
+                (None, line)
+            }
+        };
+        // Every directory on the way there that isn't yet, outermost first
+        if let Some(parent) = written.as_deref().and_then(Path::parent) {
+            let missing: Vec<_> = (parent.ancestors())
+                .take_while(|dir| !dir.as_os_str().is_empty() && !fsio::exists(dir))
+                .collect();
+            for dir in missing.into_iter().rev() {
+                if !dirs.iter().any(|made| made == dir) {
+                    dirs.push(dir.to_path_buf());
+                }
+            }
+        }
+        lines.push(line);
+    }
+
+    let mut plan = vec![tr!("plan-heading", count = count, dir = dir.display())];
+    let made = dirs.iter().map(|dir| tr!("plan-dir", path = dir.display()));
+    plan.extend(made);
+    if lines.is_empty() {
+        lines.push(i18n::text("plan-nothing").to_string());
+    }
+    plan.extend(lines);
+    plan
+}
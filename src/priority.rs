@@ -0,0 +1,52 @@
+// The order a read's datagrams are handled in. A batched (--recv-batch) or
+// GRO (--gro) read can bring in dozens at once, and control packets among
+// them (headers, echo replies, capability advertisements) go ahead of the
+// bulk data: a file's name shouldn't wait behind a flood of its payloads, and
+// an echo reply timed after them would make the round trip look longer than
+// it was. Telling them apart
+// takes a look at the first bytes only; the full parse comes later. Control
+// packets keep their order among themselves, and so does data.
+
+use crate::{capability, echo, wire};
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    Control,
+    Data,
+}
+
+pub fn classify(datagram: &[u8]) -> Priority {
+    match datagram.first() {
+        // "S" is odd, so this has to come before the status byte is read
+        _ if datagram.starts_with(echo::MAGIC) => Priority::Control,
+        _ if datagram.starts_with(capability::MAGIC) => Priority::Control,
+        Some(&status) if wire::is_data(status) => Priority::Data,
+        // Headers, and whatever's too short to be a packet at all
+        _ => Priority::Control,
+    }
+}
+
+// Where each queued datagram is: which one of the read, and the bytes of it
+// (a GRO read is several datagrams glued together)
+pub type Slot = (usize, Range<usize>);
+
+#[derive(Default)]
+pub struct Queue {
+    control: Vec<Slot>,
+    data: Vec<Slot>,
+}
+
+impl Queue {
+    pub fn push(&mut self, index: usize, range: Range<usize>, datagram: &[u8]) {
+        match classify(datagram) {
+            Priority::Control => self.control.push((index, range)),
+            Priority::Data => self.data.push((index, range)),
+        }
+    }
+
+    // Everything queued, control first, leaving the queue empty
+    pub fn drain(&mut self) -> impl Iterator<Item = Slot> + '_ {
+        self.control.drain(..).chain(self.data.drain(..))
+    }
+}
@@ -0,0 +1,197 @@
+// Which version of the protocol a session speaks, and the hello that tells
+// the server what the client can do.
+//
+//   v1: the course protocol. Headers and data packets with 16-bit numbers;
+//       only bits 0 and 1 of a status byte mean anything.
+//   v2: wide data packets, extended headers, checksum packets and the
+//       compression bits on top (see wire.rs).
+//
+// Parsing reads a status byte through the session's version, so under v1 the
+// bits v2 gave meaning to are ignored and a packet is taken as the course
+// server meant it. --legacy speaks v1 from the start and stays there.
+// Otherwise the server says its version in byte 5 of its capability
+// advertisement (see capability.rs) and the session speaks the lower of the
+// two; one that doesn't say gets v2, which is what a server that never sets
+// those bits gets anyway.
+//
+// The hello, big-endian:
+//
+//   v1: "SFSH" 1
+//   v2: "SFSH" 2  protocol version  features (2)  rate (u64, 0 for none)
+//       [salt (16)]  [file count (1)  file IDs]
+//
+// The features are capability bits (the ones this run will use), and the
+// rate is --limit-rate's, in bytes a second. The salt is there with the
+// encryption bit, for the server to make the session's key with (see
+// crypto.rs). The file IDs are there when
+// only some files are wanted (--file-id, or `fetch`), so a server that
+// understands can send just those. Any datagram will do for the course
+// server, so it's none the worse for any of it.
+
+use crate::{
+    capability,
+    cli::Cli,
+    crypto::{Salt, SALT_LEN},
+    wire,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Version {
+    V1 = 1,
+    V2 = 2,
+}
+
+// The newest this build speaks
+pub const LATEST: Version = Version::V2;
+
+impl Version {
+    // The version to speak with a server that says it speaks `theirs`: the
+    // highest both do. None if it didn't say (0).
+    pub fn negotiate(theirs: u8) -> Option<Version> {
+        match theirs {
+            0 => None,
+            1 => Some(Version::V1),
+            _ => Some(LATEST),
+        }
+    }
+
+    // A status byte with only the bits that mean something in this version
+    pub fn status(self, status: u8) -> u8 {
+        match self {
+            Version::V1 => status & (wire::DATA_BIT | wire::LAST_BIT),
+            Version::V2 => status,
+        }
+    }
+}
+
+pub const HELLO: [u8; 5] = *b"SFSH\x01";
+pub const HELLO_LEN: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hello {
+    pub version: Version,
+    pub features: u16,
+    pub rate: Option<u64>,
+    // With --psk
+    pub salt: Option<Salt>,
+    // Empty for every file
+    pub files: Vec<u8>,
+}
+
+impl Hello {
+    pub fn for_cli(cli: &Cli) -> Self {
+        let features = wanted(cli) & capability::BUILT_IN;
+        Hello {
+            version: LATEST,
+            features,
+            rate: cli.limit_rate,
+            salt: (features & capability::ENCRYPTION != 0).then_some(cli.salt),
+            files: cli.selection().file_ids,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = b"SFSH\x02".to_vec();
+        out.push(self.version as u8);
+        out.extend_from_slice(&self.features.to_be_bytes());
+        out.extend_from_slice(&self.rate.unwrap_or(0).to_be_bytes());
+        if let Some(salt) = self.salt {
+            out.extend_from_slice(&salt.0);
+        }
+        if !self.files.is_empty() {
+            out.push(self.files.len() as u8);
+            out.extend_from_slice(&self.files);
+        }
+        out
+    }
+
+    // A v1 hello is from a client that spoke v2 already, and asked for
+    // nothing in particular
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes == HELLO {
+            return Some(Hello {
+                version: LATEST,
+                features: 0,
+                rate: None,
+                salt: None,
+                files: Vec::new(),
+            });
+        }
+        if bytes.len() < HELLO_LEN || !bytes.starts_with(b"SFSH\x02") {
+            return None;
+        }
+        let features = u16::from_be_bytes([bytes[6], bytes[7]]);
+        let (salt, rest) = match features & capability::ENCRYPTION {
+            0 => (None, &bytes[HELLO_LEN..]),
+            _ => {
+                let salt = bytes.get(HELLO_LEN..HELLO_LEN + SALT_LEN)?;
+                (
+                    Some(Salt(salt.try_into().ok()?)),
+                    &bytes[HELLO_LEN + SALT_LEN..],
+                )
+            }
+        };
+        let files = match *rest {
+            [] => Vec::new(),
+            [count, ref files @ ..] if files.len() == count as usize => files.to_vec(),
+            _ => return None,
+        };
+        let rate = u64::from_be_bytes(bytes[8..HELLO_LEN].try_into().ok()?);
+        Some(Hello {
+            version: Version::negotiate(bytes[5])?,
+            features,
+            rate: (rate > 0).then_some(rate),
+            salt,
+            files,
+        })
+    }
+}
+
+// The hello for `cli`; with --legacy, the v1 one whatever else was asked
+pub fn hello(cli: &Cli) -> Vec<u8> {
+    match cli.legacy {
+        true => HELLO.to_vec(),
+        false => Hello::for_cli(cli).encode(),
+    }
+}
+
+// The version a session starts out speaking, before the server says
+pub fn starting_version(cli: &Cli) -> Version {
+    match cli.legacy {
+        true => Version::V1,
+        false => LATEST,
+    }
+}
+
+// The rate a hello asks for, if it asks for one
+pub fn requested_rate(datagram: &[u8]) -> Option<u64> {
+    Hello::parse(datagram)?.rate
+}
+
+// The files a hello asks for, if it names any
+pub fn requested_files(datagram: &[u8]) -> Vec<u8> {
+    Hello::parse(datagram).map_or(Vec::new(), |hello| hello.files)
+}
+
+// The extensions `cli` turns on, as capability bits. Nobody is told
+// anything, or probed, when the transfer was pushed, and --legacy turns
+// them all off.
+pub fn wanted(cli: &Cli) -> u16 {
+    if cli.legacy {
+        return 0;
+    }
+    let asked = !cli.listen_only;
+    [
+        (cli.crc, capability::CRC),
+        (cli.compression, capability::COMPRESSION),
+        (cli.compression, capability::ZSTD),
+        (cli.psk.is_some(), capability::ENCRYPTION),
+        (asked && cli.echo_interval.is_some(), capability::ECHO),
+        (asked && cli.ack_interval.is_some(), capability::ACK),
+        (asked && cli.sends_nacks(), capability::WIDE_NACK),
+        (!cli.no_resume_token, capability::RESUME),
+    ]
+    .into_iter()
+    .filter(|&(on, _)| on)
+    .fold(0, |bits, (_, bit)| bits | bit)
+}
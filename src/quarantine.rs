@@ -0,0 +1,96 @@
+// Files that came out wrong, by their checksum packet's SHA-256 or by the
+// size or hash --expect gave, don't stay in the output directory looking
+// like the ones that came out right. Each is moved into quarantine/ under
+// it, with NAME.failure.json beside it saying what was wrong:
+//
+//   { "file_id": 2, "name": "b.txt", "reason": "hash",
+//     "expected": "sha256:...", "actual": "sha256:..." }
+//
+// The exit status still says which check it failed (11 or 7).
+
+use crate::{file_manager::Finalized, fsio, manifest::Divergence};
+use serde::Serialize;
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+pub const DIR: &str = "quarantine";
+
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub file_id: u8,
+    pub name: String,
+    // `checksum` (the checksum packet's), or `size` or `hash` (--expect's)
+    pub reason: &'static str,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+// Move `path` into quarantine/ under `dir`, replacing anything an earlier
+// run put there under the same name, and return where it went
+pub fn quarantine(dir: &Path, path: &Path, failure: &Failure) -> io::Result<PathBuf> {
+    let quarantine = dir.join(DIR);
+    fsio::create_dir_all(&quarantine)?;
+    let name = path.file_name().unwrap_or(OsStr::new(&failure.name));
+    let to = quarantine.join(name);
+    fsio::rename(path, &to)?;
+    let mut sidecar = name.to_os_string();
+    sidecar.push(".failure.json");
+    let mut text = serde_json::to_string_pretty(failure).map_err(io::Error::other)?;
+    text.push('\n');
+    fsio::write(&quarantine.join(sidecar), text.as_bytes())?;
+    Ok(to)
+}
+
+// The failure a manifest divergence is about, if it's about a file that was
+// written
+pub fn failure(divergence: &Divergence, written: &[(u8, PathBuf)]) -> Option<Failure> {
+    let (name, reason, expected, actual) = match divergence {
+        Divergence::WrongSize {
+            name,
+            expected,
+            actual,
+        } => (name, "size", expected.to_string(), actual.to_string()),
+        Divergence::WrongHash {
+            name,
+            expected,
+            actual,
+        } => (name, "hash", expected.clone(), actual.clone()),
+        Divergence::Missing(_) | Divergence::Extra(_) => return None,
+    };
+    let &(file_id, _) = (written.iter()).find(|(_, path)| {
+        path.file_name()
+            .is_some_and(|file| file.to_string_lossy() == **name)
+    })?;
+    Some(Failure {
+        file_id,
+        name: name.clone(),
+        reason,
+        expected: Some(expected),
+        actual: Some(actual),
+    })
+}
+
+// Move each of `failures` out of the way, keeping `finalized`'s paths to
+// where it went
+pub fn apply(dir: &Path, finalized: &mut Finalized, failures: &[Failure]) -> io::Result<()> {
+    for failure in failures {
+        let Some(path) = (finalized.written.iter())
+            .find(|(file_id, _)| *file_id == failure.file_id)
+            .map(|(_, path)| path.clone())
+        else {
+            continue;
+        };
+        if !path.starts_with(dir.join(DIR)) {
+            let to = quarantine(dir, &path, failure)?;
+            for (_, moved) in (finalized.written.iter_mut()).chain(&mut finalized.mismatched) {
+                if *moved == path {
+                    *moved = to.clone();
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+// --limit-rate on the way out: ACKs and NACKs take from a token bucket that
+// fills at the rate, so the client's own datagrams keep to it as well as
+// the server's. What doesn't fit waits: an ACK for the next interval, the
+// rest of a NACK for the next round, which asks again for whatever is
+// still missing.
+
+use std::time::{Duration, Instant};
+
+pub struct Bucket {
+    rate: u64,
+    tokens: f64,
+    // Enough for a tenth of a second at the rate, and always for one
+    // datagram, however low the rate
+    capacity: f64,
+    filled_at: Instant,
+}
+
+impl Bucket {
+    pub fn new(rate: u64, now: Instant) -> Self {
+        let capacity = (rate as f64 / 10.0).max(crate::wire::MAX_PACKET as f64);
+        Bucket {
+            rate,
+            tokens: capacity,
+            capacity,
+            filled_at: now,
+        }
+    }
+
+    // Whether a datagram of `len` bytes can go now, taking its share if so
+    pub fn take(&mut self, len: usize, now: Instant) -> bool {
+        let since = now.saturating_duration_since(self.filled_at);
+        self.tokens = (self.tokens + since.as_secs_f64() * self.rate as f64).min(self.capacity);
+        self.filled_at = now;
+        if self.tokens < len as f64 {
+            return false;
+        }
+        self.tokens -= len as f64;
+        true
+    }
+}
+
+// How long `len` bytes take at `rate`
+pub fn time_for(rate: u64, len: usize) -> Duration {
+    Duration::from_secs_f64(len as f64 / rate.max(1) as f64)
+}
@@ -0,0 +1,219 @@
+// An interactive prompt for driving a session by hand, one step at a time.
+// Packets are received on a background thread while commands inspect (and
+// write out) whatever has arrived so far.
+
+use crate::{
+    cli::{self, OnConflict},
+    client::{self, IpVersion},
+    file_manager::{FileManager, Written},
+    log::log,
+    packet::Packet,
+    session::send_request,
+    units, wire,
+};
+use std::{
+    io::{self, BufRead, Write},
+    net::UdpSocket,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const HELP: &str = "\
+commands:
+  connect <addr>   bind and point the client at a server (e.g. 127.0.0.1:6014)
+  request          send the request and start receiving in the background
+  status           show every file seen so far
+  missing <id>     list the packet numbers still missing for a file
+  write <id>       write a file to disk as it stands
+  abort            stop receiving and forget the session
+  help             show this message
+  quit             leave the REPL";
+
+// State shared with the receiving thread
+#[derive(Default)]
+struct Shared {
+    files: Mutex<FileManager>,
+    stop: AtomicBool,
+    packets: AtomicU64,
+    malformed: AtomicU64,
+}
+
+struct Session {
+    sock: UdpSocket,
+    shared: Arc<Shared>,
+    receiver: Option<JoinHandle<()>>,
+}
+
+impl Session {
+    fn connect(addr: &str, port: u16) -> io::Result<Self> {
+        let sock = client::bind(port, IpVersion::for_servers([addr]))?;
+        client::connect(&sock, addr)?;
+        Ok(Session {
+            sock,
+            shared: Arc::default(),
+            receiver: None,
+        })
+    }
+
+    fn start_receiving(&mut self) -> io::Result<()> {
+        if self.receiver.is_some() {
+            return Ok(());
+        }
+
+        let sock = self.sock.try_clone()?;
+        sock.set_read_timeout(Some(Duration::from_millis(200)))?;
+        let shared = Arc::clone(&self.shared);
+        self.receiver = Some(thread::spawn(move || {
+            let mut buf = [0; wire::receive_buffer_len(false)];
+            while !shared.stop.load(Ordering::Relaxed) {
+                let Ok(len) = sock.recv(&mut buf) else {
+                    continue; // Timed out; check whether we've been stopped
+                };
+                shared.packets.fetch_add(1, Ordering::Relaxed);
+                match Packet::try_from(&buf[..len]) {
+                    Ok(packet) if len <= wire::max_len(buf[wire::STATUS], false) => {
+                        shared.files.lock().unwrap().process_packet(packet)
+                    }
+                    _ => {
+                        shared.malformed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }));
+        Ok(())
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(receiver) = self.receiver.take() {
+            let _ = receiver.join();
+        }
+    }
+}
+
+fn parse_file_id(arg: Option<&str>) -> Result<u8, String> {
+    arg.ok_or("expected a file ID")?
+        .parse()
+        .map_err(|_| "file IDs are numbers from 0 to 255".to_string())
+}
+
+fn status(session: &Session) {
+    let files = session.shared.files.lock().unwrap();
+    println!(
+        "{} packets received, {} malformed",
+        session.shared.packets.load(Ordering::Relaxed),
+        session.shared.malformed.load(Ordering::Relaxed)
+    );
+    for file_id in files.file_ids() {
+        let Some((name, received, expected)) = files.file_status(file_id) else {
+            continue;
+        };
+        let name = name.map_or("<no header yet>".into(), |n| n.to_string_lossy());
+        let expected = expected.map_or("?".to_string(), |n| n.to_string());
+        println!("  {file_id:>3}  {name}  {received}/{expected}");
+    }
+}
+
+// Run one command, returning false when it's time to leave
+// `port` is the local one to bind, from --bind
+fn run_command(line: &str, session: &mut Option<Session>, port: u16) -> Result<bool, String> {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return Ok(true);
+    };
+
+    match (command, session.as_mut()) {
+        ("help", _) => println!("{HELP}"),
+        ("quit" | "exit", _) => return Ok(false),
+        ("connect", _) => {
+            let addr = words
+                .next()
+                .ok_or("expected an address like 127.0.0.1:6014")?;
+            cli::parse_server(addr)?;
+            *session = None; // Release the port before binding again
+            *session = Some(Session::connect(addr, port).map_err(|e| e.to_string())?);
+            println!("connected to {addr}");
+        }
+        ("abort", Some(_)) => {
+            *session = None;
+            println!("session aborted");
+        }
+        ("request" | "status" | "missing" | "write" | "abort", None) => {
+            return Err("not connected; use `connect <addr>` first".to_string())
+        }
+        ("request", Some(session)) => {
+            send_request(&session.sock);
+            session.start_receiving().map_err(|e| e.to_string())?;
+            println!("request sent");
+        }
+        ("status", Some(session)) => status(session),
+        ("missing", Some(session)) => {
+            let file_id = parse_file_id(words.next())?;
+            let files = session.shared.files.lock().unwrap();
+            let missing = files
+                .missing_packets(file_id)
+                .ok_or(format!("no packets for file {file_id} yet"))?;
+            let (_, _, expected) = files.file_status(file_id).unwrap_or_default();
+            if missing.is_empty() && expected.is_some() {
+                println!("file {file_id} is complete");
+            } else {
+                println!("missing: {}", units::format_spans(&missing, usize::MAX));
+                if expected.is_none() {
+                    println!("(the last packet hasn't arrived, so there may be more)");
+                }
+            }
+        }
+        ("write", Some(session)) => {
+            let file_id = parse_file_id(words.next())?;
+            let mut files = session.shared.files.lock().unwrap();
+            match files.file_status(file_id) {
+                None => return Err(format!("no packets for file {file_id} yet")),
+                Some((None, _, _)) => return Err(format!("no header for file {file_id} yet")),
+                Some((Some(_), _, _)) => {
+                    // Writing it again is how you see more of it
+                    files.on_conflict = OnConflict::Overwrite;
+                    let written = files
+                        .write_file(Path::new("."), file_id)
+                        .map_err(|e| e.to_string())?;
+                    match written {
+                        Some(Written::To(path)) => println!("wrote {}", path.display()),
+                        Some(Written::Mismatched(path)) => {
+                            println!("wrote {} (checksum mismatch)", path.display())
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        (other, _) => return Err(format!("unknown command `{other}`; try `help`")),
+    }
+    Ok(true)
+}
+
+pub fn run(port: u16) -> io::Result<()> {
+    println!("Segmented file system client REPL; type `help` for commands.");
+    let mut session = None;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            break; // End of input
+        };
+        match run_command(&line?, &mut session, port) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(message) => log!(Error, "error: {message}"),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,793 @@
+use crate::{
+    capability::{self, Negotiation},
+    diagnose::Diagnosis,
+    echo,
+    i18n::{self, tr},
+    limits::Ignored,
+    packet::Malformed,
+    protocol::Version,
+    simulate,
+    transport::PipelineStats,
+    units,
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// A watchdog firing during the session
+pub struct WatchdogActivation {
+    pub at: Duration,       // time since the session started
+    pub rate: u64,          // bytes per second over the slow window
+    pub re_requested: bool, // whether we sent the request again
+}
+
+// Something that went wrong without ending the run, against the file it
+// happened to when it was one file's. A run only exits one way, however many
+// things went wrong, so this is where the rest are: --json's `finished` line
+// lists every one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileError {
+    pub file_id: Option<u8>,
+    pub code: &'static str,
+    pub message: String,
+}
+
+// What happened during one session, printed once it ends. Durations and
+// rates all come from the monotonic clock; the wall-clock start is only there
+// to say when it happened, since NTP can move it by any amount mid-transfer.
+pub struct SessionReport {
+    started: Instant,
+    started_wall: SystemTime,
+    packets: u64,
+    bytes: u64,
+    watchdog_activations: Vec<WatchdogActivation>,
+    kernel_drops: Option<u32>,
+    pipeline: Option<PipelineStats>,
+    crc: Option<CrcStats>,
+    ignored: BTreeMap<Ignored, u64>,
+    malformed: BTreeMap<Malformed, u64>,
+    // Servers switched to, and the error that made us give up on the last
+    failovers: Vec<(String, &'static str)>,
+    // Files --fix-extensions gave another name: ID, as sent, as written
+    renamed: Vec<(u8, String, String)>,
+    // What writing the files out went wrong with
+    errors: Vec<FileError>,
+    // Repeat requests that carried a resume token
+    resume_tokens: u32,
+    // Packets read back from an earlier run's --resume log
+    resumed: u64,
+    // Retransmission requests sent (--nack-after), and the packets they
+    // asked for between them
+    nacks: u32,
+    nacked_packets: u64,
+    // Times --idle-after went off
+    idle: u32,
+    // Acknowledgement datagrams sent (--ack-interval)
+    acks: u32,
+    // --limit-rate, and the ACK and NACK datagrams that had to wait for it
+    rate_limit: Option<u64>,
+    held_back: u32,
+    // Empty data packets not marked last
+    keepalives: u64,
+    // Data packets past --stdout-buffer, dropped to be asked for again
+    past_buffer: u64,
+    // Data packets we already had, those of them whose payload didn't match
+    // the copy we kept (by file), and headers repeating a name we had
+    duplicates: u64,
+    conflicting_duplicates: BTreeMap<u8, u64>,
+    duplicate_headers: u64,
+    // Data packets kept for each file, and those numbered below one kept
+    // before them
+    files: BTreeMap<u8, FileStats>,
+    out_of_order: u64,
+    // Files left out by --only, --exclude, or --file-id, and their packets
+    skipped_files: u64,
+    skipped_packets: u64,
+    // Datagrams thrown away on purpose with --drop-every
+    simulated_drops: u64,
+    // What --simulate-loss and the rest did
+    simulation: Option<simulate::Stats>,
+    // The --until rule that ended the session, and the files it ended
+    // without (ID, packets received, packets expected)
+    ended_by: Option<String>,
+    // Ctrl-C ended it instead
+    interrupted: bool,
+    ended: Option<Instant>,
+    stragglers: Vec<(u8, usize, Option<u32>)>,
+    // Payload bytes received in each second of the session, for --history
+    throughput: Vec<u64>,
+    // When each file's first and latest packets were accepted, since the
+    // session started
+    file_times: BTreeMap<u8, (Duration, Duration)>,
+    // Pacing details, with --diagnose
+    diagnosis: Option<Diagnosis>,
+    // Round trips, with --echo-interval
+    echo: Option<echo::Stats>,
+    // The server's latest capability advertisement, if it sent one
+    capabilities: Option<Negotiation>,
+    // The protocol version agreed from it, if it said one
+    protocol: Option<Version>,
+}
+
+#[derive(Default)]
+struct FileStats {
+    packets: u64,
+    bytes: u64,
+    highest: u32,
+}
+
+// Per-packet CRC checking, when it's on
+#[derive(Default)]
+struct CrcStats {
+    checked: u64,
+    corrupt: u64,
+    total_latency: Duration,
+    max_latency: Duration,
+}
+
+impl SessionReport {
+    pub fn new(started: Instant) -> Self {
+        SessionReport {
+            started,
+            started_wall: SystemTime::now() - started.elapsed(),
+            packets: 0,
+            bytes: 0,
+            watchdog_activations: Vec::new(),
+            kernel_drops: None,
+            pipeline: None,
+            crc: None,
+            ignored: BTreeMap::new(),
+            malformed: BTreeMap::new(),
+            failovers: Vec::new(),
+            renamed: Vec::new(),
+            errors: Vec::new(),
+            resume_tokens: 0,
+            resumed: 0,
+            nacks: 0,
+            nacked_packets: 0,
+            idle: 0,
+            acks: 0,
+            rate_limit: None,
+            held_back: 0,
+            simulated_drops: 0,
+            simulation: None,
+            keepalives: 0,
+            past_buffer: 0,
+            duplicates: 0,
+            files: BTreeMap::new(),
+            out_of_order: 0,
+            conflicting_duplicates: BTreeMap::new(),
+            duplicate_headers: 0,
+            skipped_files: 0,
+            skipped_packets: 0,
+            ended_by: None,
+            interrupted: false,
+            ended: None,
+            stragglers: Vec::new(),
+            throughput: Vec::new(),
+            file_times: BTreeMap::new(),
+            diagnosis: None,
+            echo: None,
+            capabilities: None,
+            protocol: None,
+        }
+    }
+
+    // Keep what --diagnose needs from here on
+    pub fn diagnose(&mut self) {
+        self.diagnosis = Some(Diagnosis::default());
+    }
+
+    // `payload` is the payload length of a data packet
+    pub fn record_datagram(&mut self, now: Instant, payload: Option<usize>) {
+        let second = now.saturating_duration_since(self.started).as_secs() as usize;
+        if self.throughput.len() <= second {
+            self.throughput.resize(second + 1, 0);
+        }
+        self.throughput[second] += payload.unwrap_or(0) as u64;
+        if let Some(diagnosis) = self.diagnosis.as_mut() {
+            diagnosis.record_datagram(now, payload);
+        }
+    }
+
+    // A repeat request went out
+    pub fn record_request(&mut self, now: Instant) {
+        if let Some(diagnosis) = self.diagnosis.as_mut() {
+            diagnosis.record_request(now);
+        }
+    }
+
+    // A data packet we already had arrived
+    pub fn record_duplicate(&mut self, now: Instant) {
+        self.duplicates += 1;
+        if let Some(diagnosis) = self.diagnosis.as_mut() {
+            diagnosis.record_duplicate(now);
+        }
+    }
+
+    // A data packet we didn't have was kept
+    pub fn record_data(&mut self, file_id: u8, packet_number: u32, payload: usize) {
+        let file = self.files.entry(file_id).or_default();
+        if file.packets > 0 && packet_number < file.highest {
+            self.out_of_order += 1;
+        }
+        file.packets += 1;
+        file.bytes += payload as u64;
+        file.highest = file.highest.max(packet_number);
+    }
+
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    pub fn out_of_order(&self) -> u64 {
+        self.out_of_order
+    }
+
+    // Returns true the first time this kind of packet is ignored
+    pub fn record_ignored(&mut self, reason: Ignored) -> bool {
+        let count = self.ignored.entry(reason).or_default();
+        *count += 1;
+        *count == 1
+    }
+
+    pub fn record_failover(&mut self, server: &str, reason: &'static str) {
+        self.failovers.push((server.to_string(), reason));
+    }
+
+    pub fn record_renamed(&mut self, file_id: u8, from: &str, to: &str) {
+        self.renamed
+            .push((file_id, from.to_string(), to.to_string()));
+    }
+
+    pub fn record_error(&mut self, file_id: Option<u8>, code: &'static str, message: String) {
+        self.errors.push(FileError {
+            file_id,
+            code,
+            message,
+        });
+    }
+
+    // Everything that went wrong, by file, and then the datagrams that
+    // didn't parse, a kind at a time
+    pub fn errors(&self) -> Vec<FileError> {
+        let malformed = self.malformed.iter().map(|(kind, count)| FileError {
+            file_id: None,
+            code: "malformed-packet",
+            message: tr!(
+                "report-malformed",
+                count = count,
+                what = i18n::malformed(*kind)
+            )
+            .trim()
+            .to_string(),
+        });
+        (self.errors.iter().cloned()).chain(malformed).collect()
+    }
+
+    // Returns true the first time it happens to this file
+    pub fn record_conflicting_duplicate(&mut self, file_id: u8) -> bool {
+        let count = self.conflicting_duplicates.entry(file_id).or_default();
+        *count += 1;
+        *count == 1
+    }
+
+    pub fn record_duplicate_header(&mut self) {
+        self.duplicate_headers += 1;
+    }
+
+    pub fn record_skipped_file(&mut self) {
+        self.skipped_files += 1;
+    }
+
+    pub fn record_skipped_packet(&mut self) {
+        self.skipped_packets += 1;
+    }
+
+    pub fn record_keepalive(&mut self) {
+        self.keepalives += 1;
+    }
+
+    pub fn record_past_buffer(&mut self) {
+        self.past_buffer += 1;
+    }
+
+    pub fn past_buffer(&self) -> u64 {
+        self.past_buffer
+    }
+
+    pub fn record_simulated_drop(&mut self) {
+        self.simulated_drops += 1;
+    }
+
+    pub fn record_resume_token(&mut self) {
+        self.resume_tokens += 1;
+    }
+
+    pub fn record_resumed(&mut self, packets: u64) {
+        self.resumed = packets;
+    }
+
+    pub fn record_acks(&mut self, datagrams: u32) {
+        self.acks += datagrams;
+    }
+
+    pub fn set_rate_limit(&mut self, rate: u64) {
+        self.rate_limit = Some(rate);
+    }
+
+    pub fn record_held_back(&mut self) {
+        self.held_back += 1;
+    }
+
+    pub fn record_idle(&mut self) {
+        self.idle += 1;
+    }
+
+    pub fn record_nack(&mut self, packets: u64) {
+        self.nacks += 1;
+        self.nacked_packets += packets;
+    }
+
+    // Whether it's the first of its kind
+    pub fn record_malformed(&mut self, kind: Malformed) -> bool {
+        let count = self.malformed.entry(kind).or_default();
+        *count += 1;
+        *count == 1
+    }
+
+    // `latency` is how long the packet waited for its check
+    pub fn record_crc(&mut self, ok: bool, latency: Duration) {
+        let crc = self.crc.get_or_insert_with(CrcStats::default);
+        crc.checked += 1;
+        crc.corrupt += u64::from(!ok);
+        crc.total_latency += latency;
+        crc.max_latency = crc.max_latency.max(latency);
+    }
+
+    pub fn record_file_packet(&mut self, file_id: u8, now: Instant) {
+        let at = now.saturating_duration_since(self.started);
+        self.file_times
+            .entry(file_id)
+            .and_modify(|(_, latest)| *latest = at)
+            .or_insert((at, at));
+    }
+
+    // From a file's first packet to its latest
+    pub fn file_duration(&self, file_id: u8) -> Option<Duration> {
+        self.file_times
+            .get(&file_id)
+            .map(|(first, latest)| *latest - *first)
+    }
+
+    // When a file's first and latest packets came, by the wall clock
+    pub fn file_times(&self, file_id: u8) -> Option<(SystemTime, SystemTime)> {
+        (self.file_times.get(&file_id))
+            .map(|(first, latest)| (self.started_wall + *first, self.started_wall + *latest))
+    }
+
+    // When a file's first and latest packets came, since the session started
+    pub fn file_span(&self, file_id: u8) -> Option<(Duration, Duration)> {
+        self.file_times.get(&file_id).copied()
+    }
+
+    pub fn throughput(&self) -> &[u64] {
+        &self.throughput
+    }
+
+    pub fn started_wall(&self) -> SystemTime {
+        self.started_wall
+    }
+
+    pub fn nacked_packets(&self) -> u64 {
+        self.nacked_packets
+    }
+
+    pub fn record_packet(&mut self, len: usize) {
+        self.packets += 1;
+        self.bytes += len as u64;
+    }
+
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    // How long the session took, or has taken so far
+    pub fn elapsed(&self) -> Duration {
+        self.ended.unwrap_or_else(Instant::now) - self.started
+    }
+
+    pub fn record_watchdog(&mut self, now: Instant, rate: u64, re_requested: bool) {
+        self.watchdog_activations.push(WatchdogActivation {
+            at: now.duration_since(self.started),
+            rate,
+            re_requested,
+        });
+    }
+
+    pub fn record_end(
+        &mut self,
+        now: Instant,
+        criterion: String,
+        stragglers: Vec<(u8, usize, Option<u32>)>,
+    ) {
+        self.ended = Some(now);
+        self.ended_by = Some(criterion);
+        self.stragglers = stragglers;
+    }
+
+    pub fn record_interrupted(&mut self) {
+        self.interrupted = true;
+    }
+
+    pub fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    pub fn set_capabilities(&mut self, negotiation: Negotiation) {
+        self.capabilities = Some(negotiation);
+    }
+
+    pub fn set_protocol(&mut self, version: Version) {
+        self.protocol = Some(version);
+    }
+
+    pub fn set_echo(&mut self, stats: echo::Stats) {
+        self.echo = Some(stats);
+    }
+
+    // None when the platform can't count them
+    pub fn set_kernel_drops(&mut self, drops: Option<u32>) {
+        self.kernel_drops = drops;
+    }
+
+    pub fn set_pipeline(&mut self, stats: PipelineStats) {
+        self.pipeline = Some(stats);
+    }
+
+    pub fn set_simulation(&mut self, stats: simulate::Stats) {
+        self.simulation = Some(stats);
+    }
+
+    pub fn print(&self) {
+        let _ = self.write(&mut io::stdout().lock());
+    }
+
+    // The main figures, for --json's `finished` line
+    pub fn summary(&self) -> serde_json::Value {
+        let elapsed = self.elapsed();
+        let started = self.started_wall.duration_since(UNIX_EPOCH);
+        serde_json::json!({
+            "started_ms": started.map_or(0, |d| d.as_millis() as u64),
+            "elapsed_ms": elapsed.as_millis() as u64,
+            "packets": self.packets,
+            "bytes": self.bytes,
+            "rate": (self.bytes as f64 / elapsed.as_secs_f64()) as u64,
+            "rate_limit": self.rate_limit,
+            "protocol": self.protocol.map(|version| version as u8),
+            "duplicates": self.duplicates,
+            "out_of_order": self.out_of_order,
+            "nacks": self.nacks,
+            "idle": self.idle,
+            "failovers": self.failovers.len(),
+            "renamed": (self.renamed.iter())
+                .map(|(file_id, from, to)| serde_json::json!({
+                    "file_id": file_id, "from": from, "to": to,
+                }))
+                .collect::<Vec<_>>(),
+            "ended_by": self.ended_by,
+            "errors": (self.errors().iter())
+                .map(|e| serde_json::json!({
+                    "file_id": e.file_id, "code": e.code, "message": e.message,
+                }))
+                .collect::<Vec<_>>(),
+            "interrupted": self.interrupted,
+        })
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        let elapsed = self.elapsed();
+        writeln!(
+            out,
+            "{}",
+            tr!(
+                "report-received",
+                packets = self.packets,
+                bytes = units::format_size(self.bytes),
+                elapsed = units::format_duration(elapsed),
+                rate = units::format_rate((self.bytes as f64 / elapsed.as_secs_f64()) as u64)
+            )
+        )?;
+        if let Some(limit) = self.rate_limit {
+            let rate = self.bytes as f64 / elapsed.as_secs_f64();
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-rate-limit",
+                    limit = units::format_rate(limit),
+                    percent = format!("{:.0}", 100.0 * rate / limit as f64)
+                )
+            )?;
+            if self.held_back > 0 {
+                writeln!(out, "{}", tr!("report-held-back", count = self.held_back))?;
+            }
+        }
+        writeln!(
+            out,
+            "{}",
+            tr!(
+                "report-started",
+                time = units::format_timestamp(self.started_wall)
+            )
+        )?;
+        if self.interrupted {
+            writeln!(out, "{}", i18n::text("report-interrupted"))?;
+        } else if let Some(criterion) = &self.ended_by {
+            writeln!(out, "{}", tr!("report-ended-by", criterion = criterion))?;
+        }
+        for (file_id, file) in &self.files {
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-file",
+                    file_id = file_id,
+                    bytes = units::format_size(file.bytes),
+                    packets = file.packets
+                )
+            )?;
+        }
+        // Kept as .partial files rather than dropped, and listed as they are
+        let stragglers = if self.interrupted {
+            &[][..]
+        } else {
+            &self.stragglers
+        };
+        for (file_id, received, expected) in stragglers {
+            let expected = expected.map_or("?".to_string(), |count| count.to_string());
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-straggler",
+                    file_id = file_id,
+                    received = received,
+                    expected = expected
+                )
+            )?;
+        }
+        for (server, reason) in &self.failovers {
+            writeln!(
+                out,
+                "{}",
+                tr!("report-failover", server = server, reason = reason)
+            )?;
+        }
+        for (file_id, from, to) in &self.renamed {
+            writeln!(
+                out,
+                "{}",
+                tr!("report-renamed", file_id = file_id, from = from, to = to)
+            )?;
+        }
+        if self.resumed > 0 {
+            writeln!(out, "{}", tr!("report-resumed", packets = self.resumed))?;
+        }
+        if self.resume_tokens > 0 {
+            writeln!(
+                out,
+                "{}",
+                tr!("report-resume-tokens", requests = self.resume_tokens)
+            )?;
+        }
+        if self.acks > 0 {
+            writeln!(out, "{}", tr!("report-acks", acks = self.acks))?;
+        }
+        if self.nacks > 0 {
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-nacks",
+                    nacks = self.nacks,
+                    packets = self.nacked_packets
+                )
+            )?;
+        }
+        if self.idle > 0 {
+            writeln!(out, "{}", tr!("report-idle", count = self.idle))?;
+        }
+        for activation in &self.watchdog_activations {
+            writeln!(
+                out,
+                "{}{}",
+                tr!(
+                    "report-watchdog",
+                    at = units::format_duration(activation.at),
+                    rate = units::format_rate(activation.rate)
+                ),
+                if activation.re_requested {
+                    i18n::text("report-watchdog-resent")
+                } else {
+                    ""
+                }
+            )?;
+        }
+        if self.simulated_drops > 0 {
+            writeln!(
+                out,
+                "{}",
+                tr!("report-simulated-drops", count = self.simulated_drops)
+            )?;
+        }
+        if let Some(stats) = self.simulation {
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-simulation",
+                    dropped = stats.dropped,
+                    duplicated = stats.duplicated,
+                    reordered = stats.reordered,
+                    seed = stats.seed
+                )
+            )?;
+        }
+        match self.kernel_drops {
+            Some(0) => writeln!(out, "{}", i18n::text("report-no-drops"))?,
+            Some(drops) => writeln!(out, "{}", tr!("report-drops", drops = drops))?,
+            None => {}
+        }
+        if let Some(stats) = self.pipeline {
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-pipeline",
+                    queued = stats.queued,
+                    high_water = stats.high_water,
+                    capacity = stats.capacity,
+                    full = stats.full
+                )
+            )?;
+        }
+        if self.skipped_files > 0 || self.skipped_packets > 0 {
+            let (files, packets) = (self.skipped_files, self.skipped_packets);
+            writeln!(
+                out,
+                "{}",
+                tr!("report-skipped", files = files, packets = packets)
+            )?;
+        }
+        if self.keepalives > 0 {
+            writeln!(out, "{}", tr!("report-keepalives", count = self.keepalives))?;
+        }
+        if self.duplicates > 0 {
+            writeln!(out, "{}", tr!("report-duplicates", count = self.duplicates))?;
+        }
+        if self.past_buffer > 0 {
+            let count = self.past_buffer;
+            writeln!(out, "{}", tr!("report-past-buffer", count = count))?;
+        }
+        if self.out_of_order > 0 {
+            writeln!(
+                out,
+                "{}",
+                tr!("report-out-of-order", count = self.out_of_order)
+            )?;
+        }
+        for (file_id, count) in &self.conflicting_duplicates {
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-conflicting-duplicates",
+                    count = count,
+                    file_id = file_id
+                )
+            )?;
+        }
+        if self.duplicate_headers > 0 {
+            writeln!(
+                out,
+                "{}",
+                tr!("report-duplicate-headers", count = self.duplicate_headers)
+            )?;
+        }
+        for (reason, count) in &self.ignored {
+            let what = i18n::ignored(*reason);
+            writeln!(out, "{}", tr!("report-ignored", count = count, what = what))?;
+        }
+        for (kind, count) in &self.malformed {
+            let what = i18n::malformed(*kind);
+            writeln!(
+                out,
+                "{}",
+                tr!("report-malformed", count = count, what = what)
+            )?;
+        }
+        if let Some(crc) = &self.crc {
+            let (average, max) = (
+                crc.total_latency / crc.checked.max(1) as u32,
+                crc.max_latency,
+            );
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-crc",
+                    corrupt = crc.corrupt,
+                    checked = crc.checked,
+                    average = format!("{average:?}"),
+                    max = format!("{max:?}")
+                )
+            )?;
+        }
+        if let Some(negotiation) = self.capabilities {
+            let list = |bits| match capability::names(bits) {
+                names if names.is_empty() => i18n::text("capabilities-none").to_string(),
+                names => names.join(", "),
+            };
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "report-capabilities",
+                    offered = list(negotiation.offered),
+                    agreed = list(negotiation.agreed())
+                )
+            )?;
+            if negotiation.missing() != 0 {
+                let missing = list(negotiation.missing());
+                writeln!(out, "{}", tr!("capabilities-missing", features = missing))?;
+            }
+            if negotiation.declined() != 0 {
+                let declined = list(negotiation.declined());
+                writeln!(out, "{}", tr!("capabilities-declined", features = declined))?;
+            }
+            if negotiation.unoffered() != 0 {
+                let unoffered = list(negotiation.unoffered());
+                writeln!(
+                    out,
+                    "{}",
+                    tr!("capabilities-unoffered", features = unoffered)
+                )?;
+            }
+        }
+        if let Some(version) = self.protocol {
+            let version = version as u8;
+            writeln!(out, "{}", tr!("report-protocol", version = version))?;
+        }
+        if let Some(echo) = &self.echo {
+            let show = |d: Duration| format!("{:?}", Duration::from_micros(d.as_micros() as u64));
+            writeln!(
+                out,
+                "{}",
+                if echo.answered == 0 {
+                    tr!("report-echo-unanswered", probes = echo.probes)
+                } else {
+                    tr!(
+                        "report-echo",
+                        answered = echo.answered,
+                        probes = echo.probes,
+                        min = show(echo.min),
+                        median = show(echo.median),
+                        max = show(echo.max),
+                        jitter = show(echo.jitter)
+                    )
+                }
+            )?;
+        }
+        if let Some(diagnosis) = &self.diagnosis {
+            diagnosis.write(out)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+// Per-file results for --report-csv: one row per file the session heard
+// about, for pasting into a spreadsheet
+
+use crate::{
+    cli::Cli,
+    file_manager::{FileManager, Finalized},
+    fsio,
+    report::SessionReport,
+};
+use std::{
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+pub struct FileRow {
+    pub name: String,
+    pub file_id: u8,
+    // Only known for files that were written
+    pub bytes: Option<u64>,
+    pub packets: usize,
+    pub duplicates: u64,
+    pub hash: Option<String>,
+    // `complete`, `incomplete`, or `failed` (complete but not written)
+    pub status: &'static str,
+    pub duration: Option<Duration>,
+}
+
+// Quoted as RFC 4180 has it, if it needs to be
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// A row for every file in --write-order, reading the written ones back to
+// hash them
+pub fn file_rows(
+    files: &FileManager,
+    report: &SessionReport,
+    finalized: &Finalized,
+    cli: &Cli,
+) -> io::Result<Vec<FileRow>> {
+    let mut rows = Vec::new();
+    for file_id in files.ordered_ids(cli.write_order) {
+        let Some((name, packets, _)) = files.file_status(file_id) else {
+            continue;
+        };
+        let path = finalized
+            .written
+            .iter()
+            .find(|(id, _)| *id == file_id)
+            .map(|(_, path)| path);
+        let data = path.map(|path| fsio::read(path)).transpose()?;
+        rows.push(FileRow {
+            name: name.map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+            file_id,
+            bytes: data.as_ref().map(|data| data.len() as u64),
+            packets,
+            duplicates: files.duplicates(file_id),
+            hash: data.as_ref().map(|data| cli.hash.tagged(data)),
+            status: status(finalized, file_id),
+            duration: report.file_duration(file_id),
+        });
+    }
+    Ok(rows)
+}
+
+// What became of a file once the session was over
+pub fn status(finalized: &Finalized, file_id: u8) -> &'static str {
+    if finalized.mismatched.iter().any(|(id, _)| *id == file_id) {
+        "checksum-mismatch"
+    } else if finalized.written.iter().any(|(id, _)| *id == file_id) {
+        "complete"
+    } else if finalized.skipped.iter().any(|(id, _)| *id == file_id) {
+        "skipped"
+    } else if finalized.failed.iter().any(|(id, _)| *id == file_id) {
+        "failed"
+    } else {
+        "incomplete"
+    }
+}
+
+pub fn write_csv(path: &Path, rows: &[FileRow]) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fsio::create(path)?);
+    writeln!(
+        out,
+        "name,id,bytes,packets,duplicates,hash,status,duration_ms"
+    )?;
+    for row in rows {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            csv_field(&row.name),
+            row.file_id,
+            row.bytes.map_or(String::new(), |bytes| bytes.to_string()),
+            row.packets,
+            row.duplicates,
+            row.hash.as_deref().unwrap_or(""),
+            row.status,
+            row.duration
+                .map_or(String::new(), |duration| duration.as_millis().to_string())
+        )?;
+    }
+    out.flush()
+}
@@ -0,0 +1,101 @@
+// Resume tokens: a repeat request that says what we already have, so a server
+// that understands it only sends what's missing. The course server ignores
+// everything in a request, so it just sends the whole transfer again, which
+// is what it did before tokens existed; nothing depends on a server
+// honouring one.
+//
+// Layout, big-endian, padded with zeros to the size of a full data packet:
+//
+//   "SFSR"  version  session ID (8)  file count (1)
+//   per file: ID (1), flags (1), expected count + 1 or 0 if unknown
+//             (varint), run count (varint), runs (varints)
+//
+// Flag bit 0 is set once we have the file's header.
+//
+// A file's received packets are a bitmap stored as alternating run lengths,
+// received first, so a file with one gap costs a few bytes rather than a bit
+// per packet. Varints are LEB128: seven bits a byte, low bits first.
+
+use crate::wire;
+
+pub const MAGIC: &[u8; 4] = b"SFSR";
+pub const VERSION: u8 = 1;
+// A token bigger than a full data packet isn't sent; we ask without one
+pub const MAX_LEN: usize = wire::MAX_PACKET;
+
+pub const HAVE_HEADER: u8 = 1;
+
+pub struct FileProgress {
+    pub file_id: u8,
+    pub have_header: bool,
+    pub expected: Option<u32>,
+    // Packet numbers received, sorted
+    pub received: Vec<u32>,
+}
+
+pub struct Token {
+    pub session_id: u64,
+    pub files: Vec<FileProgress>,
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// Alternating received/missing run lengths, starting with received (so the
+// first run is 0 if packet 0 hasn't arrived). Trailing missing packets are
+// left out; the expected count says how many there are.
+pub fn runs(received: &[u32]) -> Vec<u32> {
+    let mut runs = Vec::new();
+    let mut next = 0u32; // first packet number not covered yet
+    let mut iter = received.iter().copied().peekable();
+    while let Some(start) = iter.next() {
+        let mut end = start + 1;
+        while iter.next_if(|&n| n == end).is_some() {
+            end += 1;
+        }
+        if runs.is_empty() {
+            if start > 0 {
+                runs.extend([0, start]);
+            }
+        } else {
+            runs.push(start - next);
+        }
+        runs.push(end - start);
+        next = end;
+    }
+    runs
+}
+
+impl Token {
+    // The token as it goes in a request, or None if it won't fit
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity(MAX_LEN);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.session_id.to_be_bytes());
+        out.push(u8::try_from(self.files.len()).ok()?);
+        for file in &self.files {
+            out.push(file.file_id);
+            out.push(if file.have_header { HAVE_HEADER } else { 0 });
+            push_varint(
+                &mut out,
+                file.expected.map_or(0, |count| u64::from(count) + 1),
+            );
+            let runs = runs(&file.received);
+            push_varint(&mut out, runs.len() as u64);
+            for run in runs {
+                push_varint(&mut out, u64::from(run));
+            }
+        }
+        if out.len() > MAX_LEN {
+            return None;
+        }
+        out.resize(MAX_LEN, 0);
+        Some(out)
+    }
+}
@@ -0,0 +1,239 @@
+// Scenarios: a transfer written out step by step as JSON, for tests and for
+// reproducing a bug somewhere else. The files are given whole and cut into
+// packets here; the steps say which of those packets go out, in what order,
+// and what happens in between. Anything not listed is never sent.
+//
+//   {
+//     "files": [{"id": 1, "name": "a.txt", "content": "one, then more",
+//                "packet_size": 5}],
+//     "steps": [
+//       {"header": 1},
+//       {"data": {"file": 1, "packets": [2, 0]}},
+//       {"delay": "200ms"},
+//       {"data": {"file": 1, "packets": [0]}},
+//       {"corrupt": {"file": 1, "packet": 1}},
+//       {"data": {"file": 1, "except": [0, 1, 2]}},
+//       {"checksum": 1},
+//       {"raw": "0301"},
+//       "await_request"
+//     ]
+//   }
+//
+// `data` without `packets` sends every packet of the file in order, less any
+// in `except`. `corrupt` sends a packet with one payload byte (`byte`,
+// default 0) flipped. `raw` is any datagram at all, in hex. `await_request`
+// waits until the client sends something. Steps become the same `Step`s a
+// `ScriptedTransport` plays, so a scenario runs the same in a test as it does
+// from `serve`, which plays one to the first client that asks.
+
+use crate::{fsio, i18n::tr, transport::Step, units, wire};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, UdpSocket},
+    path::Path,
+    thread,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub files: Vec<File>,
+    pub steps: Vec<Action>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct File {
+    pub id: u8,
+    pub name: String,
+    pub content: String,
+    #[serde(default = "full_packet")]
+    pub packet_size: usize,
+}
+
+fn full_packet() -> usize {
+    wire::MAX_PAYLOAD
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Action {
+    Header(u8),
+    Data {
+        file: u8,
+        packets: Option<Vec<u16>>,
+        #[serde(default)]
+        except: Vec<u16>,
+    },
+    Corrupt {
+        file: u8,
+        packet: u16,
+        #[serde(default)]
+        byte: usize,
+    },
+    Checksum(u8),
+    Delay(String),
+    Raw(String),
+    AwaitRequest,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl File {
+    fn payloads(&self) -> Vec<&[u8]> {
+        let content = self.content.as_bytes();
+        if content.is_empty() {
+            // Still one packet, so the file has a last one
+            return vec![content];
+        }
+        content.chunks(self.packet_size).collect()
+    }
+}
+
+impl Scenario {
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let scenario: Scenario =
+            serde_json::from_str(text).map_err(|e| invalid(tr!("scenario-bad", error = e)))?;
+        for file in &scenario.files {
+            if file.packet_size == 0 || file.packet_size > wire::MAX_PAYLOAD {
+                let message = tr!(
+                    "scenario-packet-size",
+                    file = file.id,
+                    size = file.packet_size,
+                    max = wire::MAX_PAYLOAD
+                );
+                return Err(invalid(message));
+            }
+        }
+        Ok(scenario)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fsio::read_to_string(path)?)
+            .map_err(|e| invalid(format!("{}: {e}", path.display())))
+    }
+
+    // The datagrams and pauses, in order
+    pub fn steps(&self) -> io::Result<Vec<Step>> {
+        let files: HashMap<u8, &File> = self.files.iter().map(|file| (file.id, file)).collect();
+        let file = |id: u8| {
+            (files.get(&id).copied()).ok_or_else(|| invalid(tr!("scenario-no-file", file = id)))
+        };
+        let data = |file: &File, number: u16| {
+            let payloads = file.payloads();
+            let payload = payloads.get(number as usize).ok_or_else(|| {
+                let count = payloads.len();
+                invalid(tr!(
+                    "scenario-no-packet",
+                    file = file.id,
+                    packet = number,
+                    count = count
+                ))
+            })?;
+            let is_last = number as usize == payloads.len() - 1;
+            let mut datagram = wire::data_prefix(file.id, number, is_last).to_vec();
+            datagram.extend_from_slice(payload);
+            Ok::<_, io::Error>(datagram)
+        };
+
+        let mut steps = Vec::new();
+        for action in &self.steps {
+            match action {
+                Action::Header(id) => {
+                    let mut datagram = vec![wire::HEADER_STATUS, *id];
+                    datagram.extend_from_slice(file(*id)?.name.as_bytes());
+                    steps.push(Step::Datagram(datagram));
+                }
+                Action::Data {
+                    file: id,
+                    packets,
+                    except,
+                } => {
+                    let file = file(*id)?;
+                    let numbers = match packets {
+                        Some(packets) => packets.clone(),
+                        None => (0..file.payloads().len() as u16).collect(),
+                    };
+                    for number in numbers.into_iter().filter(|n| !except.contains(n)) {
+                        steps.push(Step::Datagram(data(file, number)?));
+                    }
+                }
+                Action::Corrupt {
+                    file: id,
+                    packet,
+                    byte,
+                } => {
+                    let mut datagram = data(file(*id)?, *packet)?;
+                    let byte = datagram.get_mut(wire::PAYLOAD + byte).ok_or_else(|| {
+                        invalid(tr!(
+                            "scenario-no-byte",
+                            file = id,
+                            packet = packet,
+                            byte = byte
+                        ))
+                    })?;
+                    *byte ^= 0xff;
+                    steps.push(Step::Datagram(datagram));
+                }
+                Action::Checksum(id) => {
+                    let mut datagram = vec![wire::CHECKSUM_STATUS, *id];
+                    datagram.extend(Sha256::digest(file(*id)?.content.as_bytes()));
+                    steps.push(Step::Datagram(datagram));
+                }
+                Action::Delay(delay) => {
+                    let delay = units::parse_duration(delay).map_err(|e| {
+                        invalid(tr!("scenario-bad-delay", delay = delay, error = e))
+                    })?;
+                    steps.push(Step::Silence(delay));
+                }
+                Action::Raw(hex) => {
+                    let datagram =
+                        unhex(hex).ok_or_else(|| invalid(tr!("scenario-bad-raw", raw = hex)))?;
+                    steps.push(Step::Datagram(datagram));
+                }
+                Action::AwaitRequest => steps.push(Step::AwaitSend),
+            }
+        }
+        Ok(steps)
+    }
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// `serve`: wait for a request on `port`, play the scenario to whoever sent
+// it, and stop
+pub fn serve(path: &Path, port: u16) -> io::Result<()> {
+    let steps = Scenario::load(path)?.steps()?;
+    let sock = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    println!("{}", tr!("scenario-serving", addr = sock.local_addr()?));
+    let mut buf = [0; wire::MAX_PACKET];
+    let (_, client) = sock.recv_from(&mut buf)?;
+    for step in steps {
+        match step {
+            Step::Datagram(datagram) => {
+                sock.send_to(&datagram, client)?;
+            }
+            Step::Silence(delay) => thread::sleep(delay),
+            Step::AwaitSend => loop {
+                let (_, from) = sock.recv_from(&mut buf)?;
+                if from == client {
+                    break;
+                }
+            },
+        }
+    }
+    Ok(())
+}
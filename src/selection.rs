@@ -0,0 +1,94 @@
+// Selective download (--only, --exclude, --file-id): which of the server's
+// files to keep. Data for a file ruled out by ID is dropped as it arrives;
+// one ruled out by name can only be told apart once its header comes, and
+// whatever it had stored by then is thrown away with it. When the selection
+// says exactly which files it wants (IDs, or --only names with no wildcards),
+// the session ends once those are in, whatever --until says.
+
+use crate::completion::{Completion, State};
+
+// `*` is any run of characters, `?` any one; everything else is itself
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it has taken so far
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the `*` take one more character and try again
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn is_literal(pattern: &str) -> bool {
+    !pattern.contains(['*', '?'])
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub only: Vec<String>,
+    pub exclude: Vec<String>,
+    pub file_ids: Vec<u8>,
+}
+
+impl Selection {
+    pub fn is_everything(&self) -> bool {
+        self.only.is_empty() && self.exclude.is_empty() && self.file_ids.is_empty()
+    }
+
+    // Whether a file with this ID can be wanted at all, before its name
+    // is known
+    pub fn wants_id(&self, file_id: u8) -> bool {
+        self.file_ids.is_empty() || self.file_ids.contains(&file_id)
+    }
+
+    pub fn wants(&self, file_id: u8, name: &str) -> bool {
+        self.wants_id(file_id)
+            && (self.only.is_empty() || self.only.iter().any(|glob| glob_matches(glob, name)))
+            && !self.exclude.iter().any(|glob| glob_matches(glob, name))
+    }
+
+    // The rule that ends the session when the selection names its files
+    // exactly. `skipped` files were ruled out by name, so they count as done.
+    pub fn completion(&self) -> Option<Completion> {
+        if !self.file_ids.is_empty() {
+            let ids = self.file_ids.clone();
+            let name = format!("--file-id {}", join(&ids));
+            return Some(Completion::custom(name, move |state: &State| {
+                (ids.iter()).all(|&id| state.files.is_complete(id) || state.skipped.contains(&id))
+            }));
+        }
+        if !self.only.is_empty() && self.only.iter().all(|glob| is_literal(glob)) {
+            let names = self.only.clone();
+            let name = format!("--only {}", join(&names));
+            let catalog = Completion::catalog(names);
+            return Some(Completion::custom(name, move |state: &State| {
+                catalog.is_done(state)
+            }));
+        }
+        None
+    }
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    let items: Vec<String> = items.iter().map(T::to_string).collect();
+    items.join(",")
+}
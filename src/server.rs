@@ -0,0 +1,201 @@
+// The companion server (`cargo run --bin server -- FILE...`): the course
+// server's side of the protocol, for testing and class use without it. Each
+// file gets an ID in the order given and is cut into 1 KB pieces, and to
+// every datagram that comes in the lot goes back to its sender, each file's
+// header and then its pieces in order. With `--metadata` the headers are
+// extended ones, carrying each file's size, modification time and
+// permissions. `serve` in the client plays a scenario
+// once; this sends real files to as many clients as ask.
+
+use crate::{
+    completion, discover, fsio,
+    i18n::tr,
+    intern,
+    log::log,
+    packet::{Data, Header, Metadata, Packet, Payload},
+    protocol, ratelimit, tcp, wire,
+};
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    net::{TcpListener, UdpSocket},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// `name`'s packets as file `file_id`
+pub fn packets(file_id: u8, name: &str, content: &[u8], metadata: Metadata) -> Vec<Packet> {
+    let mut packets = vec![Packet::Header(Header {
+        file_id,
+        file_name: intern::file_name(OsStr::new(name)),
+        metadata,
+    })];
+    // An empty file is still one packet, so it has a last one
+    let pieces: Vec<&[u8]> = match content {
+        [] => vec![content],
+        _ => content.chunks(wire::MAX_PAYLOAD).collect(),
+    };
+    let count = pieces.len();
+    for (number, piece) in pieces.into_iter().enumerate() {
+        packets.push(Packet::Data(Data {
+            file_id,
+            packet_number: number as u32,
+            is_last_packet: number == count - 1,
+            data: Payload::from_slice(piece),
+        }));
+    }
+    packets
+}
+
+// What an extended header says about a file here
+pub fn metadata(content: &[u8], file: &fs::Metadata) -> Metadata {
+    let modified = (file.modified().ok())
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs() as i64);
+    #[cfg(unix)]
+    let mode = Some(std::os::unix::fs::PermissionsExt::mode(&file.permissions()) & 0o777);
+    #[cfg(not(unix))]
+    let mode = None;
+    Metadata {
+        size: Some(content.len() as u64),
+        modified,
+        mode,
+        ..Metadata::default()
+    }
+}
+
+// Every file's packets, IDs counting up from 0, with extended headers if
+// `with_metadata`
+pub fn load(paths: &[PathBuf], with_metadata: bool) -> io::Result<Vec<Packet>> {
+    if paths.len() > usize::from(u8::MAX) + 1 {
+        return Err(invalid(tr!("serve-files-too-many", count = paths.len())));
+    }
+    let mut all = Vec::new();
+    for (file_id, path) in paths.iter().enumerate() {
+        let name = file_name(path)?;
+        let content = fsio::read(path)?;
+        let metadata = match with_metadata {
+            true => metadata(&content, &fsio::metadata(path)?),
+            false => Metadata::default(),
+        };
+        all.extend(packets(file_id as u8, name, &content, metadata));
+    }
+    Ok(all)
+}
+
+// The name the client is told, without the directories it's in here
+fn file_name(path: &Path) -> io::Result<&str> {
+    (path.file_name().and_then(|name| name.to_str()))
+        .ok_or_else(|| invalid(tr!("serve-files-bad-name", path = path.display())))
+}
+
+// How long to wait between packets by default. Sent back to back, a few
+// hundred packets fill a client's receive buffer on loopback faster than it
+// reads it, and the rest are lost.
+pub const PACE: Duration = Duration::from_micros(100);
+
+pub struct Options {
+    // Stop after this many clients have been served
+    pub clients: Option<usize>,
+    // End each transfer with the end-of-session datagram `--until end` waits
+    // for
+    pub end_marker: bool,
+    pub pace: Duration,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            clients: None,
+            end_marker: false,
+            pace: PACE,
+        }
+    }
+}
+
+// Answer requests on `sock` until it fails, or enough clients have been
+// served. A --discover request gets the file names back instead, and
+// doesn't count as a client; a hello with a --limit-rate in it gets the
+// files no faster than that, and one that names files gets only those.
+pub fn serve(sock: &UdpSocket, packets: &[Packet], options: &Options) -> io::Result<()> {
+    let mut datagrams: Vec<(Option<u8>, Vec<u8>)> = (packets.iter())
+        .map(|packet| (Some(packet.file_id()), packet.to_bytes()))
+        .collect();
+    if options.end_marker {
+        datagrams.push((None, completion::end_of_session().to_vec()));
+    }
+    let names: Vec<&str> = (packets.iter())
+        .filter_map(|packet| match packet {
+            Packet::Header(header) => header.file_name.to_str(),
+            _ => None,
+        })
+        .collect();
+    let mut buf = [0; wire::MAX_WIDE_PACKET];
+    let mut served = 0;
+    while options.clients != Some(served) {
+        let (len, client) = match sock.recv_from(&mut buf) {
+            Ok(received) => received,
+            // Windows's word that an earlier client has gone
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => return Err(e),
+        };
+        // Someone looking for servers, not asking for the files
+        if discover::is_request(&buf[..len]) {
+            sock.send_to(&discover::reply(&names), client)?;
+            continue;
+        }
+        // No faster than the client's hello asked, if it did
+        let rate = protocol::requested_rate(&buf[..len]);
+        let files = protocol::requested_files(&buf[..len]);
+        let wanted = (datagrams.iter())
+            .filter(|(file_id, _)| files.is_empty() || file_id.is_none_or(|id| files.contains(&id)))
+            .map(|(_, datagram)| datagram);
+        for datagram in wanted {
+            sock.send_to(datagram, client)?;
+            let pace = rate.map_or(options.pace, |rate| {
+                options.pace.max(ratelimit::time_for(rate, datagram.len()))
+            });
+            if !pace.is_zero() {
+                thread::sleep(pace);
+            }
+        }
+        served += 1;
+    }
+    Ok(())
+}
+
+// The same over TCP (`--transport tcp`), one client at a time: a client's
+// first frame is its request, and it gets every packet as a frame before
+// the connection is closed. There's no pacing; TCP does that itself.
+pub fn serve_tcp(listener: &TcpListener, packets: &[Packet], options: &Options) -> io::Result<()> {
+    let mut datagrams: Vec<Vec<u8>> = packets.iter().map(Packet::to_bytes).collect();
+    if options.end_marker {
+        datagrams.push(completion::end_of_session().to_vec());
+    }
+    let mut served = 0;
+    while options.clients != Some(served) {
+        let (mut stream, _) = listener.accept()?;
+        // One that goes away partway is its own problem, not the server's
+        let sent = (|| {
+            if tcp::read_frame(&mut stream)?.is_none() {
+                return Ok(());
+            }
+            let mut out = io::BufWriter::new(&stream);
+            for datagram in &datagrams {
+                tcp::write_frame(&mut out, datagram)?;
+            }
+            out.flush()
+        })();
+        if let Err(e) = sent {
+            log!(Warn, "{}", tr!("serve-client-failed", error = e));
+        }
+        served += 1;
+    }
+    Ok(())
+}
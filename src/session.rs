@@ -0,0 +1,1449 @@
+// The receive loop: everything between sending the request and having every
+// file in hand
+
+use crate::{
+    ack,
+    capability::{self, Negotiation},
+    capture::Recorder,
+    cli::{Cli, Keepalives},
+    completion::{self, Completion},
+    echo::Echo,
+    error::ClientError,
+    events::{json_line, unix_millis, Event, Progress},
+    file_manager::FileManager,
+    i18n::{self, tr},
+    idle::{self, OnIdle},
+    interrupt,
+    journal::{self, Journal},
+    limits::{self, Screen},
+    log::{self, log},
+    nack,
+    packet::{Malformed, Packet, PacketParseError, PacketView},
+    peer::Peer,
+    protocol::{self, Version},
+    ratelimit::Bucket,
+    report::SessionReport,
+    selection::Selection,
+    simulate::SimulatedTransport,
+    snapshot::{self, Snapshot},
+    space::Preflight,
+    state::StateLog,
+    stream::{Out, Stream},
+    trace::{self, Tracer},
+    transport::{Clock, PipelineTransport, SystemClock, Transport, UdpTransport},
+    units,
+    verify::Verifier,
+    watchdog::Watchdog,
+    wire,
+};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// Describe a received packet for the progress sinks
+fn packet_event<'a>(packet: &PacketView<'a>, len: usize) -> Event<'a> {
+    match *packet {
+        PacketView::Header {
+            file_id,
+            file_name,
+            metadata,
+        } => Event::Header {
+            file_id,
+            file_name,
+            size: metadata.size,
+        },
+        PacketView::Data {
+            file_id,
+            packet_number,
+            is_last_packet,
+            ..
+        } => Event::Data {
+            file_id,
+            packet_number,
+            len,
+            is_last_packet,
+        },
+        PacketView::Checksum { file_id, .. } => Event::Checksum { file_id },
+    }
+}
+
+// "Hello, send me stuff": see protocol.rs
+pub use crate::protocol::{hello, requested_rate, HELLO};
+
+// The longest the hello waits between resends, however many it's been
+pub const MAX_HELLO_BACKOFF: Duration = Duration::from_secs(8);
+
+pub fn send_request(sock: &UdpSocket) {
+    let _ = sock.send(&HELLO);
+}
+
+// The same, saying what `cli` has to
+pub fn send_hello(sock: &UdpSocket, cli: &Cli) {
+    let _ = sock.send(&hello(cli));
+}
+
+// When the first hello goes again, if it can. Nothing's asked for when
+// listening, so there's nothing to ask again.
+fn first_hello(cli: &Cli, now: Instant) -> Option<Instant> {
+    let retries = !cli.listen_only && cli.connect_retries != Some(0);
+    retries.then(|| now + cli.hello_interval)
+}
+
+// How long after hello number `sent` (from 1) to send the next one
+fn hello_backoff(cli: &Cli, sent: u32) -> Duration {
+    let doubled = cli.hello_interval.saturating_mul(1 << (sent - 1).min(16));
+    doubled.min(MAX_HELLO_BACKOFF)
+}
+
+// Good enough to tell one run's sessions from another's
+fn new_session_id() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
+
+// Everything a session keeps track of while packets arrive
+struct Session<'a> {
+    cli: &'a Cli,
+    clock: &'a dyn Clock,
+    progress: &'a mut Progress,
+    file_manager: FileManager,
+    // Packets from the current read, stored together once it's handled
+    pending: Vec<Packet>,
+    // Set with --crc; packets go through it before being stored
+    verifier: Option<Verifier>,
+    screen: Screen,
+    report: SessionReport,
+    peer: Peer,
+    watchdog: Option<Watchdog>,
+    // When --session-timeout runs out
+    timeout_at: Option<Instant>,
+    // The caller's deadline for the whole run, files written and all
+    deadline: Option<Instant>,
+    started: Instant,
+    // Requests sent, counting the caller's first one
+    requests: u32,
+    // When to send the hello again if the server still hasn't answered
+    next_hello: Option<Instant>,
+    // Every datagram that arrived, usable or not
+    datagrams: u64,
+    // Datagrams skipped because they didn't parse
+    parse_errors: u64,
+    // Datagrams seen by --drop-every, which counts across failovers
+    arrivals: u64,
+    // When each file last had a packet accepted
+    last_progress: HashMap<u8, Instant>,
+    // When the last retransmission request went out, with --nack-after
+    last_nack: Option<Instant>,
+    // When --idle-after last went off
+    last_idle: Option<Instant>,
+    // When the next --ack-interval acknowledgement is due
+    next_ack: Option<Instant>,
+    // What --limit-rate leaves for ACKs and NACKs
+    outgoing: Option<Bucket>,
+    // What the server's advertisement said to speak, or the latest
+    version: Version,
+    // The server agreed to capability::WIDE_NACK under protocol v2
+    wide_nacks: bool,
+    completion: Completion,
+    // Sent in resume tokens so a server can tell our requests apart
+    session_id: u64,
+    journal: Option<Journal>,
+    capture: Option<Recorder>,
+    // Every datagram as hex, with --trace-packets
+    tracer: Option<Tracer>,
+    // Whether what the headers say will fit, unless --no-space-check
+    preflight: Option<Preflight>,
+    // Where kept packets are logged, with --resume
+    state: Option<StateLog>,
+    // Files with all their data but no header, when we noticed, and
+    // whether we've asked again
+    headerless: HashMap<u8, (Instant, bool)>,
+    // Round-trip probes, with --echo-interval
+    echo: Option<Echo>,
+    // Files whose header names had directories taken off, so the warning
+    // comes once
+    stripped: HashSet<u8>,
+    // Files whose sealed names didn't open, likewise
+    #[cfg(feature = "crypto")]
+    unsealed: HashSet<u8>,
+    // Files -v has said are complete
+    completed: HashSet<u8>,
+    // When progress.json is next due, with --snapshot-interval
+    next_snapshot: Option<Instant>,
+    // Our answer to a capability advertisement, until it's sent
+    capability_reply: Option<[u8; capability::LEN]>,
+    // Whether the server has sent its end-of-session datagram
+    ended: bool,
+    selection: Selection,
+    // The file going to standard output, with --stdout
+    stream: Option<Stream<Out>>,
+    // Files whose headers --only or --exclude ruled out
+    skipped: HashSet<u8>,
+    // What --psk's packets are opened with, from the key and our salt
+    #[cfg(feature = "crypto")]
+    key: Option<crate::crypto::Key>,
+}
+
+impl Session<'_> {
+    // Everything up to the end of the session: what was kept from last time,
+    // then packets until it's done. Returns whether --replay ran out first.
+    fn receive(&mut self, transport: &mut dyn Transport) -> Result<bool, ClientError> {
+        let (cli, clock, deadline) = (self.cli, self.clock, self.deadline);
+        let poll = poll_interval(cli);
+        if let Some(dir) = &cli.resume {
+            // Whoever the first request went to; failing over is the same transfer
+            let server = if cli.listen_only { "" } else { &cli.server[0] };
+            let (log, packets) = StateLog::open(dir, server, cli.force)?;
+            self.state = Some(log);
+            if !packets.is_empty() {
+                self.report.record_resumed(packets.len() as u64);
+                self.file_manager.process_packets(packets.into_iter());
+                // Tell the server what we have, in case it can skip it
+                if !cli.listen_only {
+                    self.re_request(transport);
+                }
+            }
+        }
+        if cli.diagnose {
+            self.report.diagnose();
+        }
+
+        while !self.is_done() {
+            if interrupt::requested() {
+                self.report.record_interrupted();
+                break;
+            }
+            if let Err(e) = self.check_timers(transport) {
+                self.fail_over(transport, e)?;
+            }
+            // Wake up at the deadline, not at the first poll after it
+            if let Some(left) = deadline.map(|at| at.saturating_duration_since(clock.now())) {
+                if poll.is_none_or(|poll| left < poll) {
+                    transport.set_poll_interval(Some(left.max(Duration::from_millis(1))))?;
+                }
+            }
+            let received =
+                match transport.receive(&mut |bytes, from| self.handle_datagram(bytes, from)) {
+                    Ok(received) => received,
+                    Err(e) => {
+                        self.fail_over(transport, e)?;
+                        false
+                    }
+                };
+            // A quiet socket is a good time to wait out outstanding CRC checks
+            self.release_verified(!received)?;
+            self.store_pending()?;
+            if transport.exhausted() {
+                // Whatever's still being checked is all there will be
+                self.release_verified(true)?;
+                self.store_pending()?;
+                return Ok(!self.is_done());
+            }
+        }
+
+        // All of it, if it was all resumed
+        self.store_pending()?;
+        Ok(false)
+    }
+
+    fn check_timers(&mut self, transport: &mut dyn Transport) -> Result<(), ClientError> {
+        if let Some(reply) = self.capability_reply.take() {
+            transport.send(&reply);
+        }
+        let now = self.clock.now();
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(ClientError::DeadlinePassed);
+        }
+        self.check_headers(transport, now);
+        self.check_gaps(transport, now)?;
+        self.check_idle(transport, now)?;
+        self.check_acks(transport, now);
+        self.check_snapshot(now);
+        if let Some(probe) = self.echo.as_mut().and_then(|echo| echo.due(now)) {
+            transport.send(&probe);
+        }
+        let elapsed = now - self.started;
+        if self.datagrams > 0 {
+            self.next_hello = None;
+        }
+        if self.next_hello.is_some_and(|at| now >= at) {
+            self.re_request(transport);
+            let retried = self.requests - 1;
+            let more = self
+                .cli
+                .connect_retries
+                .is_none_or(|retries| retried < retries);
+            self.next_hello = more.then(|| now + hello_backoff(self.cli, self.requests));
+        }
+        if let Some(timeout) = (self.cli.connect_timeout)
+            .filter(|&timeout| !self.cli.listen_only && self.datagrams == 0 && elapsed >= timeout)
+        {
+            return Err(ClientError::ServerUnreachable {
+                timeout,
+                requests: self.requests,
+            });
+        }
+
+        if let Some(timeout) = self
+            .cli
+            .first_packet_timeout
+            .filter(|&timeout| self.last_progress.is_empty() && elapsed >= timeout)
+        {
+            return Err(ClientError::FirstPacketTimeout {
+                timeout,
+                datagrams: self.datagrams,
+            });
+        }
+
+        if let Some(timeout) = self.cli.stall_timeout {
+            // Never shorter than the path can answer in
+            let rto = self.echo.as_ref().and_then(Echo::rto);
+            let timeout = timeout.max(rto.unwrap_or_default());
+            for (&file_id, &at) in &self.last_progress {
+                let stalled_for = now - at;
+                if stalled_for >= timeout && !self.file_manager.is_complete(file_id) {
+                    let (received, expected) = self
+                        .file_manager
+                        .file_status(file_id)
+                        .map_or((0, None), |(_, received, expected)| (received, expected));
+                    return Err(ClientError::FileStalled {
+                        file_id,
+                        stalled_for,
+                        received,
+                        expected,
+                    });
+                }
+            }
+        }
+
+        if self.timeout_at.is_some_and(|at| now >= at) {
+            return Err(ClientError::SessionTimeout {
+                timeout: self.screen.limits().max_session.unwrap_or_default(),
+                packets: self.report.packets(),
+            });
+        }
+
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            if let Some(rate) = watchdog.check(now) {
+                // There's nobody to ask again when the transfer was pushed
+                let re_request = !self.cli.listen_only;
+                if re_request {
+                    self.re_request(transport);
+                }
+                self.report.record_watchdog(now, rate, re_request);
+                self.progress.emit(Event::Watchdog {
+                    rate,
+                    re_requested: re_request,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    // A file with every data packet but no header gets --header-grace for
+    // the header to turn up, then a name of our own. Usually the header was
+    // only overtaken, so the request goes out again halfway through rather
+    // than straight away: some servers start over from scratch when asked.
+    fn check_headers(&mut self, transport: &mut dyn Transport, now: Instant) {
+        for file_id in self.file_manager.headerless() {
+            let (since, asked) = self.headerless.entry(file_id).or_insert((now, false));
+            let waited = now - *since;
+            if !*asked && waited >= self.cli.header_grace / 2 {
+                *asked = true;
+                if !self.cli.listen_only {
+                    self.re_request(transport);
+                }
+            }
+            if waited >= self.cli.header_grace {
+                let name = self.cli.headerless_name(file_id);
+                log!(
+                    Warn,
+                    "{}",
+                    tr!("header-never-came", file_id = file_id, name = &name)
+                );
+                self.file_manager.name_file(file_id, &name);
+                self.headerless.remove(&file_id);
+            }
+        }
+    }
+
+    // Write progress.json if it's due. Not being able to is worth a warning,
+    // not the transfer, so snapshots just stop.
+    fn check_snapshot(&mut self, now: Instant) {
+        let (Some(due), Some(interval)) = (self.next_snapshot, self.cli.snapshot_interval) else {
+            return;
+        };
+        if now < due {
+            return;
+        }
+        let snapshot = Snapshot::new(
+            &self.file_manager,
+            &self.report,
+            self.peer.source(),
+            unix_millis(),
+        );
+        match snapshot.write(self.cli.output_dir()) {
+            Ok(()) => self.next_snapshot = Some(now + interval),
+            Err(e) => {
+                log!(Warn, "{}", tr!("snapshot-failed", error = e));
+                self.next_snapshot = None;
+            }
+        }
+    }
+
+    // --nack-after: once it's been quiet that long since the last datagram
+    // (or the last NACK, if that was later), list what's missing
+    fn check_gaps(
+        &mut self,
+        transport: &mut dyn Transport,
+        now: Instant,
+    ) -> Result<(), ClientError> {
+        let Some(after) = self.cli.nack_after.filter(|_| !self.cli.listen_only) else {
+            return Ok(());
+        };
+        let heard = self.peer.last_heard();
+        let since = (self.last_nack).map_or(heard, |at| at.max(heard));
+        if now - since < after {
+            return Ok(());
+        }
+        self.send_nack(transport, now)
+    }
+
+    // A retransmission request for every gap known, if there are any
+    fn send_nack(
+        &mut self,
+        transport: &mut dyn Transport,
+        now: Instant,
+    ) -> Result<(), ClientError> {
+        let (end, pairs, pair_len) = match self.wide_nacks {
+            true => (u32::MAX, nack::MAX_WIDE_PAIRS, nack::WIDE_PAIR_LEN),
+            false => (1 << 16, nack::MAX_PAIRS, nack::PAIR_LEN),
+        };
+        let mut gaps: Vec<(u8, u32)> = (self.file_manager.gaps(end))
+            .take(nack::MAX_DATAGRAMS * pairs)
+            .collect();
+        // With --stdout-buffer, its file's are only those there's room for
+        let buffered = self.stream.as_ref();
+        if let Some((file_id, missing)) = buffered.and_then(|s| s.missing(&self.file_manager)) {
+            gaps.retain(|&(id, _)| id != file_id);
+            gaps.extend(
+                (missing.into_iter())
+                    .filter(|&n| n < end)
+                    .map(|n| (file_id, n)),
+            );
+        }
+        if gaps.is_empty() {
+            return Ok(());
+        }
+        self.last_nack = Some(now);
+        let datagrams = match self.wide_nacks {
+            true => nack::encode_wide(self.session_id, &gaps),
+            false => {
+                let narrow: Vec<(u8, u16)> = (gaps.iter())
+                    .map(|&(file_id, n)| (file_id, n as u16))
+                    .collect();
+                nack::encode(self.session_id, &narrow)
+            }
+        };
+        let mut packets = 0;
+        for datagram in datagrams.iter().take(nack::MAX_DATAGRAMS) {
+            // The rest are asked for again next time, if still missing
+            if !self.may_send(datagram, now) {
+                break;
+            }
+            transport.send(datagram);
+            packets += (datagram.len() - nack::HEADER_LEN) / pair_len;
+        }
+        self.report.record_nack(packets as u64);
+        self.progress.emit(Event::Nack { packets })?;
+        Ok(())
+    }
+
+    // What the files had come to when the session failed, on standard error
+    // (or as a --json line), since the error means none of them get written
+    fn report_failure(&self) {
+        let files = &self.file_manager;
+        let complete: Vec<u8> = (files.file_ids().into_iter())
+            .filter(|&file_id| files.is_complete(file_id))
+            .collect();
+        let partial = idle::incomplete(files);
+        if complete.is_empty() && partial.is_empty() {
+            return;
+        }
+        if self.cli.json {
+            let complete = complete.iter().filter_map(|&file_id| {
+                let (name, received, _) = files.file_status(file_id)?;
+                Some(json!({
+                    "file_id": file_id,
+                    "name": name.map(|name| name.to_string_lossy()),
+                    "status": "complete",
+                    "received": received,
+                }))
+            });
+            let partial = partial.iter().map(|file| {
+                json!({
+                    "file_id": file.file_id,
+                    "name": file.name,
+                    "status": "partial",
+                    "received": file.received,
+                    "expected": file.expected,
+                    "missing": units::count(&file.missing),
+                })
+            });
+            let files: Vec<_> = complete.chain(partial).collect();
+            return json_line(json!({ "event": "failure-report", "files": files }));
+        }
+        let mut lines = vec![tr!(
+            "failure-report",
+            complete = complete.len(),
+            partial = partial.len()
+        )];
+        for &file_id in &complete {
+            if let Some((name, received, _)) = files.file_status(file_id) {
+                let name = name.map_or(String::new(), |name| name.to_string_lossy().into_owned());
+                let line = tr!(
+                    "failure-complete",
+                    file_id = file_id,
+                    name = name,
+                    received = received
+                );
+                lines.push(line);
+            }
+        }
+        lines.extend(partial.iter().map(idle::file_line));
+        log!(Error, "{}", lines.join("\n"));
+    }
+
+    // --idle-after: once nothing at all has arrived for that long (since the
+    // last datagram, or since it last went off) with a file incomplete, say
+    // what's missing and do what --on-idle says
+    fn check_idle(
+        &mut self,
+        transport: &mut dyn Transport,
+        now: Instant,
+    ) -> Result<(), ClientError> {
+        // Nothing can stall before it's started
+        let Some(after) = self
+            .cli
+            .idle_after
+            .filter(|_| !self.last_progress.is_empty())
+        else {
+            return Ok(());
+        };
+        let heard = self.peer.last_heard();
+        let since = (self.last_idle).map_or(heard, |at| at.max(heard));
+        if now - since < after {
+            return Ok(());
+        }
+        let files = idle::incomplete(&self.file_manager);
+        if files.is_empty() {
+            return Ok(());
+        }
+        self.last_idle = Some(now);
+        let idle_for = now - self.peer.last_heard();
+        let last = SystemTime::now()
+            .checked_sub(idle_for)
+            .unwrap_or(UNIX_EPOCH);
+        // A pushed transfer has nobody to ask again
+        let action = match self.cli.on_idle {
+            OnIdle::Resend | OnIdle::Nack if self.cli.listen_only => OnIdle::Report,
+            policy => policy,
+        };
+        log!(Warn, "{}", idle::report(&files, idle_for, last));
+        self.report.record_idle();
+        self.progress.emit(Event::Idle {
+            idle_for,
+            files: &files,
+            action: action.name(),
+        })?;
+        match action {
+            OnIdle::Resend => self.re_request(transport),
+            OnIdle::Nack => self.send_nack(transport, now)?,
+            OnIdle::Report => {}
+            OnIdle::Abort => {
+                return Err(ClientError::TransferIdle {
+                    idle_for,
+                    incomplete: files.len(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    // --ack-interval: how far each file has got, once there's anything
+    fn check_acks(&mut self, transport: &mut dyn Transport, now: Instant) {
+        let (Some(due), Some(interval)) = (self.next_ack, self.cli.ack_interval) else {
+            return;
+        };
+        if now < due {
+            return;
+        }
+        self.next_ack = Some(now + interval);
+        let entries = ack::summarize(&self.file_manager);
+        let datagrams = ack::encode(self.session_id, &entries);
+        let mut sent = 0;
+        for datagram in &datagrams {
+            if !self.may_send(datagram, now) {
+                break;
+            }
+            transport.send(datagram);
+            sent += 1;
+        }
+        self.report.record_acks(sent);
+    }
+
+    // Whether --limit-rate leaves room for `datagram` now; one that has to
+    // wait is counted in the report
+    fn may_send(&mut self, datagram: &[u8], now: Instant) -> bool {
+        let Some(outgoing) = self.outgoing.as_mut() else {
+            return true;
+        };
+        let room = outgoing.take(datagram.len(), now);
+        if !room {
+            self.report.record_held_back();
+        }
+        room
+    }
+
+    // Send the request again, with a resume token once there's anything to
+    // resume, unless that's turned off or the token is too big to send
+    fn re_request(&mut self, transport: &mut dyn Transport) {
+        self.requests += 1;
+        self.report.record_request(self.clock.now());
+        let token = Some(self.file_manager.resume_token(self.session_id))
+            .filter(|token| !token.files.is_empty())
+            .filter(|_| !self.cli.no_resume_token && !self.cli.legacy)
+            .and_then(|token| token.encode());
+        match token {
+            Some(token) => {
+                transport.send(&token);
+                self.report.record_resume_token();
+            }
+            None => transport.send(&hello(self.cli)),
+        }
+    }
+
+    // Move the request on to the next --failover server if `error` is the
+    // current one going quiet (or its port being closed), keeping everything
+    // received so far. Any other error is passed back.
+    fn fail_over(
+        &mut self,
+        transport: &mut dyn Transport,
+        error: ClientError,
+    ) -> Result<(), ClientError> {
+        let reason = match &error {
+            ClientError::ServerUnreachable { .. }
+            | ClientError::FirstPacketTimeout { .. }
+            | ClientError::FileStalled { .. }
+            | ClientError::TransferIdle { .. } => Some(error.code()),
+            ClientError::IoError(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                Some("connection-refused")
+            }
+            // Only a connection (--transport tcp) can be closed
+            ClientError::IoError(e) if e.kind() == io::ErrorKind::ConnectionAborted => {
+                Some("connection-closed")
+            }
+            _ => None,
+        };
+        let now = self.clock.now();
+        let Some(reason) = reason else {
+            return Err(error);
+        };
+        let Some(server) = self.peer.fail_over(transport, now)? else {
+            return Err(error);
+        };
+        self.re_request(transport);
+
+        // The new server gets the same grace as the first one did
+        self.started = now;
+        self.requests = 1;
+        self.next_hello = first_hello(self.cli, now);
+        self.datagrams = 0;
+        self.ended = false;
+        for at in self.last_progress.values_mut() {
+            *at = now;
+        }
+        self.report.record_failover(&server, reason);
+        self.progress.emit(Event::Failover {
+            server: &server,
+            reason,
+        })?;
+        Ok(())
+    }
+
+    // --artificial-delay and --drop-every: act like a client that can't keep
+    // up. Returns true if this datagram should be thrown away.
+    fn misbehave(&mut self) -> bool {
+        if let Some(delay) = self.cli.artificial_delay {
+            self.clock.sleep(delay);
+        }
+        self.arrivals += 1;
+        let drop = self
+            .cli
+            .drop_every
+            .is_some_and(|n| self.arrivals.is_multiple_of(n));
+        if drop {
+            self.report.record_simulated_drop();
+        }
+        drop
+    }
+
+    fn handle_datagram(&mut self, bytes: &[u8], from: SocketAddr) -> Result<(), ClientError> {
+        // Before anything can drop it, so a replay starts from the same place
+        if let Some(capture) = self.capture.as_mut() {
+            capture.record(self.clock.now(), bytes)?;
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(self.clock.now(), from, bytes, self.version)?;
+        }
+        if self.misbehave() {
+            return Ok(());
+        }
+        let len = bytes.len();
+        self.datagrams += 1;
+        if !self.peer.hear(from, self.clock.now()) {
+            return Ok(()); // Not part of this session
+        }
+        let now = self.clock.now();
+        if self
+            .echo
+            .as_mut()
+            .is_some_and(|echo| echo.answer(bytes, now))
+        {
+            return Ok(());
+        }
+        if completion::is_end_of_session(bytes) {
+            self.ended = true;
+            return Ok(());
+        }
+        // The course server never offers anything, so with --legacy an offer
+        // is left unanswered
+        if let Some(offered) = capability::parse(bytes) {
+            if self.cli.legacy {
+                return Ok(());
+            }
+            let negotiation = Negotiation {
+                offered,
+                wanted: protocol::wanted(self.cli),
+            };
+            self.capability_reply = Some(capability::encode(negotiation.agreed()));
+            self.report.set_capabilities(negotiation);
+            if let Some(version) = Version::negotiate(capability::protocol_version(bytes)) {
+                self.version = version;
+                self.report.set_protocol(version);
+            }
+            // Wide NACKs name packets only wide data packets carry
+            self.wide_nacks =
+                negotiation.agreed() & capability::WIDE_NACK != 0 && self.version >= Version::V2;
+            return Ok(());
+        }
+        let status = bytes.first().map(|&status| self.version.status(status));
+        let limit = status.map_or(0, |status| {
+            wire::max_len(status, self.cli.crc) + self.cli.tag_len()
+        });
+        let parsed = if len >= self.cli.receive_buffer_len() {
+            let buffer = self.cli.max_packet_size();
+            Err(PacketParseError::DatagramCutShort { buffer })
+        } else if len > limit {
+            Err(PacketParseError::DatagramTooLong { len, limit })
+        } else {
+            PacketView::parse_as(bytes, self.version)
+        };
+        // Where names have to be UTF-8, one that isn't is written under its
+        // lossy conversion rather than the file being lost
+        #[cfg(not(unix))]
+        let mut lossy = String::new();
+        #[cfg(not(unix))]
+        let parsed = match parsed {
+            Err(PacketParseError::InvalidUtf8Filename { file_id, .. }) => {
+                let parsed = PacketView::parse_lossy(bytes, self.version, &mut lossy);
+                let named = (self.file_manager.file_status(file_id))
+                    .is_some_and(|(name, ..)| name.is_some());
+                if let (Ok(PacketView::Header { file_name, .. }), false) = (&parsed, named) {
+                    let name = file_name.to_string_lossy();
+                    log!(
+                        Warn,
+                        "{}",
+                        tr!("header-name-lossy", file_id = file_id, name = name)
+                    );
+                }
+                parsed
+            }
+            parsed => parsed,
+        };
+        let first_of_kind = match &parsed {
+            Err(e) => self.report.record_malformed(e.kind()),
+            Ok(_) => false,
+        };
+        let packet = if self.peer.source().is_some() || !self.cli.listen_only {
+            match parsed {
+                Ok(packet) => packet,
+                // One bad datagram isn't worth the whole transfer, up to
+                // --max-parse-errors of them
+                Err(e) => {
+                    self.parse_errors += 1;
+                    let limit = self.cli.max_parse_errors;
+                    if limit.is_some_and(|limit| self.parse_errors > limit) {
+                        return Err(e.into());
+                    }
+                    if first_of_kind {
+                        log!(
+                            Warn,
+                            "{}",
+                            tr!("skipping-malformed", from = from, message = e)
+                        );
+                    }
+                    return Ok(());
+                }
+            }
+        } else {
+            // Waiting for a session to start, so anything that isn't a
+            // well-formed header is just noise
+            match parsed {
+                Ok(packet @ PacketView::Header { .. }) => packet,
+                Ok(PacketView::Data { .. } | PacketView::Checksum { .. }) => return Ok(()),
+                Err(e) => {
+                    log!(Warn, "{}", tr!("ignoring-packet", from = from, message = e));
+                    return Ok(());
+                }
+            }
+        };
+
+        // With --psk a header's name is opened here, before anything looks
+        // at it
+        #[cfg(feature = "crypto")]
+        let opened_name;
+        #[cfg(feature = "crypto")]
+        let packet = match (packet, self.key.as_ref()) {
+            (
+                PacketView::Header {
+                    file_id,
+                    file_name,
+                    metadata,
+                },
+                Some(key),
+            ) => {
+                let field = file_name.as_encoded_bytes();
+                let prefix = bytes.len().checked_sub(field.len()).map(|at| &bytes[..at]);
+                let opened =
+                    prefix.and_then(|prefix| crate::crypto::open_name(key, file_id, prefix, field));
+                opened_name = opened.unwrap_or_else(|| {
+                    let fallback = self.cli.headerless_name(file_id);
+                    if self.unsealed.insert(file_id) {
+                        log!(
+                            Warn,
+                            "{}",
+                            tr!("sealed-name-unopened", file_id = file_id, name = &fallback)
+                        );
+                    }
+                    fallback
+                });
+                PacketView::Header {
+                    file_id,
+                    file_name: std::ffi::OsStr::new(&opened_name),
+                    metadata,
+                }
+            }
+            (packet, _) => packet,
+        };
+
+        // With --tree the directories stay, and the screen checks them
+        let packet = match packet {
+            PacketView::Header {
+                file_id,
+                file_name,
+                metadata,
+            } if !self.cli.tree => {
+                let base = limits::base_os_name(file_name);
+                if base != file_name && self.stripped.insert(file_id) {
+                    log!(
+                        Warn,
+                        "{}",
+                        tr!(
+                            "header-path-stripped",
+                            file_id = file_id,
+                            name = format!("{file_name:?}"),
+                            base = format!("{base:?}")
+                        )
+                    );
+                }
+                PacketView::Header {
+                    file_id,
+                    file_name: base,
+                    metadata,
+                }
+            }
+            other => other,
+        };
+        log!(
+            Debug,
+            "{}",
+            tr!("log-packet", from = from, packet = trace::describe(&packet))
+        );
+
+        // With --psk a data packet's payload is opened here, and from here on
+        // it's as if it had come in the clear
+        #[cfg(feature = "crypto")]
+        let opened;
+        #[cfg(feature = "crypto")]
+        let packet = match (packet, self.key.as_ref()) {
+            (
+                PacketView::Data {
+                    file_id,
+                    packet_number,
+                    is_last_packet,
+                    payload,
+                },
+                Some(key),
+            ) => {
+                let prefix = &bytes[..bytes.len() - payload.len()];
+                let nonce = crate::crypto::packet_nonce(file_id, packet_number);
+                opened = match crate::crypto::open(key, &nonce, prefix, payload) {
+                    Some(opened) => opened,
+                    None => {
+                        self.report.record_malformed(Malformed::Forged);
+                        return Ok(());
+                    }
+                };
+                PacketView::Data {
+                    file_id,
+                    packet_number,
+                    is_last_packet,
+                    payload: &opened,
+                }
+            }
+            (packet, _) => packet,
+        };
+
+        if let Some(journal) = self.journal.as_mut() {
+            let (file_id, flags, packet_number) = match packet {
+                PacketView::Header { file_id, .. } => (file_id, 0, 0),
+                PacketView::Data {
+                    file_id,
+                    packet_number,
+                    is_last_packet,
+                    ..
+                } => {
+                    let last = if is_last_packet { journal::LAST } else { 0 };
+                    (file_id, journal::DATA | last, packet_number)
+                }
+                PacketView::Checksum { file_id, .. } => (file_id, journal::CHECKSUM, 0),
+            };
+            journal.record(self.clock.now(), file_id, flags, packet_number, len)?;
+        }
+        let payload = match packet {
+            PacketView::Data { payload, .. } => Some(payload.len()),
+            PacketView::Header { .. } | PacketView::Checksum { .. } => None,
+        };
+        self.report.record_datagram(self.clock.now(), payload);
+
+        if self.peer.settle(from) {
+            if self.timeout_at.is_none() {
+                self.timeout_at =
+                    (self.screen.limits().max_session).map(|timeout| self.clock.now() + timeout);
+            }
+            self.progress.emit(Event::SessionStarted { peer: from })?;
+        }
+
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.record(len);
+        }
+        self.report.record_packet(len);
+        if let Some(limit) =
+            (self.screen.limits().max_total_bytes).filter(|&limit| self.report.bytes() > limit)
+        {
+            return Err(ClientError::TooMuchData { limit });
+        }
+
+        match self.verifier.as_mut() {
+            // The check runs on a copy, maybe on another thread
+            Some(verifier) => verifier.submit(packet.claim(), len),
+            None => {
+                if self.admit(&packet, len)? {
+                    self.pending.push(packet.claim());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Run a packet past the limits and filters, returning whether it should
+    // be stored. A duplicate still counts as activity on its file, but
+    // there's no point copying it.
+    fn admit(&mut self, packet: &PacketView, len: usize) -> Result<bool, ClientError> {
+        let file_id = packet.file_id();
+        if !self.selection.wants_id(file_id) || self.skipped.contains(&file_id) {
+            self.report.record_skipped_packet();
+            return Ok(false);
+        }
+        let mut duplicate = false;
+        match *packet {
+            PacketView::Header {
+                file_id,
+                file_name,
+                metadata,
+            } => {
+                // Globs and the screen go by the name as text
+                let lossy = file_name.to_string_lossy();
+                if !self.selection.wants(file_id, &lossy) {
+                    self.skip(file_id);
+                    return Ok(false);
+                }
+                let known = (self.file_manager.file_status(file_id)).and_then(|(name, ..)| name);
+                duplicate = known == Some(file_name);
+                if duplicate {
+                    self.report.record_duplicate_header();
+                }
+                if let Some(renamed) = self.screen.header(file_id, &lossy)? {
+                    let earlier = &renamed.earlier;
+                    let warning = if renamed.kept_earlier {
+                        tr!(
+                            "header-renamed-kept",
+                            file_id = file_id,
+                            earlier = earlier,
+                            name = lossy
+                        )
+                    } else {
+                        tr!(
+                            "header-renamed",
+                            file_id = file_id,
+                            earlier = earlier,
+                            name = lossy
+                        )
+                    };
+                    log!(Warn, "{warning}");
+                    if renamed.kept_earlier {
+                        return Ok(false);
+                    }
+                }
+                if let Some(size) = metadata.size {
+                    self.screen.size(file_id, size)?;
+                }
+                if let (Some(preflight), Some(size)) = (self.preflight.as_mut(), metadata.size) {
+                    preflight.header(file_id, size, &self.file_manager)?;
+                }
+            }
+            PacketView::Data {
+                file_id,
+                packet_number,
+                is_last_packet,
+                payload,
+            } => {
+                let status = wire::data_status(is_last_packet);
+                if wire::is_keepalive(status, payload.len()) {
+                    self.report.record_keepalive();
+                    if self.cli.keepalives == Keepalives::Ignore {
+                        return Ok(false);
+                    }
+                }
+                let earlier_end = (self.file_manager.file_status(file_id))
+                    .and_then(|(_, _, expected)| expected)
+                    .filter(|&count| is_last_packet && count != packet_number + 1);
+                let screened = self.screen.data(file_id, packet_number, is_last_packet)?;
+                if let Some(reason) = screened {
+                    if self.report.record_ignored(reason) {
+                        let what = i18n::ignored(reason);
+                        log!(
+                            Warn,
+                            "{}",
+                            tr!("ignoring-first", what = what, file_id = file_id)
+                        );
+                    }
+                    return Ok(false);
+                }
+                if let Some(count) = earlier_end {
+                    let warning = tr!(
+                        "last-packet-moved",
+                        file_id = file_id,
+                        earlier = count - 1,
+                        packet = packet_number
+                    );
+                    log!(Warn, "{warning}");
+                }
+                let past_buffer = (self.stream.as_mut())
+                    .is_some_and(|stream| !stream.admit(file_id, packet_number));
+                if past_buffer {
+                    self.report.record_past_buffer();
+                    return Ok(false);
+                }
+                duplicate = self.file_manager.has_packet(file_id, packet_number);
+                if !duplicate {
+                    self.screen.payload(file_id, payload.len())?;
+                    (self.report).record_data(file_id, packet_number, payload.len());
+                }
+                if duplicate {
+                    log!(
+                        Warn,
+                        "{}",
+                        tr!("log-duplicate", file_id = file_id, packet = packet_number)
+                    );
+                    self.file_manager.record_duplicate(file_id);
+                    self.report.record_duplicate(self.clock.now());
+                    let kept = self.file_manager.payload(file_id, packet_number);
+                    if kept.is_some_and(|kept| kept != payload)
+                        && self.report.record_conflicting_duplicate(file_id)
+                    {
+                        let warning = tr!(
+                            "duplicate-conflicts",
+                            packet = packet_number,
+                            file_id = file_id
+                        );
+                        log!(Warn, "{warning}");
+                    }
+                }
+            }
+            PacketView::Checksum { .. } => {}
+        }
+        let now = self.clock.now();
+        self.last_progress.insert(packet.file_id(), now);
+        self.report.record_file_packet(packet.file_id(), now);
+        self.progress.emit(packet_event(packet, len))?;
+        Ok(!duplicate)
+    }
+
+    // Leave a file out, along with whatever came for it before its header
+    fn skip(&mut self, file_id: u8) {
+        self.skipped.insert(file_id);
+        self.report.record_skipped_file();
+        self.report.record_skipped_packet();
+        self.pending.retain(|packet| packet.file_id() != file_id);
+        self.file_manager.forget(file_id);
+        self.last_progress.remove(&file_id);
+        self.headerless.remove(&file_id);
+    }
+
+    // Accept whatever has passed its CRC check, waiting for every
+    // outstanding check if `wait` is set
+    fn release_verified(&mut self, wait: bool) -> Result<(), ClientError> {
+        let Some(verifier) = self.verifier.as_mut() else {
+            return Ok(());
+        };
+        for verified in verifier.release(wait) {
+            self.report
+                .record_crc(verified.packet.is_some(), verified.latency);
+            match verified.packet {
+                Some(packet) => {
+                    if self.admit(&packet.view(), verified.len)? {
+                        self.pending.push(packet);
+                    }
+                }
+                None => {
+                    self.report.record_malformed(Malformed::BadChecksum);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn store_pending(&mut self) -> Result<(), ClientError> {
+        if let Some(state) = self.state.as_mut() {
+            for packet in &self.pending {
+                state.append(packet)?;
+            }
+            state.flush_if_due(self.clock.now())?;
+        }
+        let stored: HashSet<u8> = match log::enabled(log::Level::Info) {
+            true => self.pending.iter().map(|packet| packet.file_id()).collect(),
+            false => HashSet::new(),
+        };
+        self.file_manager.process_packets(self.pending.drain(..));
+        for file_id in stored {
+            if self.file_manager.is_complete(file_id) && self.completed.insert(file_id) {
+                let Some((name, packets, _)) = self.file_manager.file_status(file_id) else {
+                    continue;
+                };
+                let name = name.unwrap_or_default().to_string_lossy();
+                let message = tr!(
+                    "log-file-complete",
+                    file_id = file_id,
+                    name = name,
+                    packets = packets
+                );
+                log!(Info, "{message}");
+            }
+        }
+        if let Some(stream) = self.stream.as_mut() {
+            stream.advance(&self.file_manager)?;
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        let now = self.clock.now();
+        let min_runtime = self.cli.min_runtime.unwrap_or_default();
+        now - self.started >= min_runtime
+            && self.completion.is_done(&completion::State {
+                files: &self.file_manager,
+                quiet_for: now - self.peer.last_heard(),
+                ended: self.ended,
+                skipped: &self.skipped,
+            })
+    }
+
+    // Files the session ended without
+    fn stragglers(&self) -> Vec<(u8, usize, Option<u32>)> {
+        self.file_manager
+            .ordered_ids(self.cli.write_order)
+            .into_iter()
+            .filter(|&id| !self.file_manager.is_complete(id))
+            .filter_map(|id| {
+                let (_, received, expected) = self.file_manager.file_status(id)?;
+                Some((id, received, expected))
+            })
+            .collect()
+    }
+}
+
+// How long a read may block before the session needs to check its timers,
+// or None to block until something arrives
+fn poll_interval(cli: &Cli) -> Option<Duration> {
+    let watchdog = cli
+        .watchdog_min_rate
+        .map(|min_rate| Watchdog::new(min_rate, cli.watchdog_window(), Instant::now()));
+    [
+        watchdog.as_ref().map(Watchdog::poll_interval),
+        // Waiting out --header-grace needs the clock too
+        Some((cli.header_grace / 2).clamp(Duration::from_millis(10), Duration::from_secs(1))),
+        Some(Duration::from_millis(250)).filter(|_| {
+            cli.completion().needs_clock()
+                || cli.min_runtime.is_some()
+                || [
+                    cli.session_timeout,
+                    cli.deadline,
+                    cli.connect_timeout,
+                    cli.first_packet_timeout,
+                    cli.stall_timeout,
+                ]
+                .iter()
+                .any(Option::is_some)
+        }),
+        // Don't sit in recv while the last few packets are being checked
+        Some(Duration::from_millis(5)).filter(|_| cli.crc && cli.crc_workers > 0),
+        cli.echo_interval.filter(|_| !cli.listen_only),
+        cli.snapshot_interval,
+        Some(interrupt::POLL).filter(|_| interrupt::installed()),
+        (cli.nack_after)
+            .filter(|_| !cli.listen_only)
+            .map(|after| (after / 4).max(Duration::from_millis(1))),
+        (cli.idle_after).map(|after| (after / 4).max(Duration::from_millis(1))),
+        // Close enough to on time for the first resend of the hello, and
+        // the later ones, further apart
+        Some(cli.hello_interval)
+            .filter(|_| cli.connect_retries != Some(0) && !cli.listen_only)
+            .map(|interval| (interval / 4).max(Duration::from_millis(1))),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+// Receive packets until every file is complete. In listen-only mode nothing
+// was requested, so the session starts with the first header that arrives and
+// packets from any other sender are dropped from then on.
+//
+// Past `deadline`, if there is one, the session gives up however far it got.
+pub fn receive_files(
+    sock: &UdpSocket,
+    cli: &Cli,
+    progress: &mut Progress,
+    deadline: Option<Instant>,
+) -> Result<(FileManager, SessionReport), ClientError> {
+    match cli.pipeline {
+        Some(capacity) => {
+            let mut transport = PipelineTransport::new(sock, cli, capacity as usize)?;
+            receive_files_over(&mut transport, &SystemClock, cli, progress, deadline)
+        }
+        None => {
+            let mut transport = UdpTransport::new(sock, cli, poll_interval(cli))?;
+            receive_files_over(&mut transport, &SystemClock, cli, progress, deadline)
+        }
+    }
+}
+
+// receive_files over any transport and clock, setting the transport's poll
+// interval to suit `cli`
+pub fn receive_files_over(
+    transport: &mut dyn Transport,
+    clock: &dyn Clock,
+    cli: &Cli,
+    progress: &mut Progress,
+    deadline: Option<Instant>,
+) -> Result<(FileManager, SessionReport), ClientError> {
+    let spill_dir = cli.spill_dir().unwrap_or(Path::new("."));
+    let mut file_manager = FileManager::with_backend(cli.assembly_backend(), spill_dir);
+    if let Some(dir) = &cli.temp_dir {
+        file_manager = file_manager.with_temp_dir(dir);
+    }
+    file_manager = file_manager.with_limits(&cli.limits(), spill_dir);
+    if let Some(threads) = cli.write_threads {
+        file_manager = file_manager.with_writers(threads.into(), cli.output_dir());
+    }
+    file_manager.owner = cli.chown;
+    file_manager.newlines = cli.normalize_text;
+    file_manager.on_conflict = cli.on_conflict;
+    transport.set_poll_interval(poll_interval(cli))?;
+    let result = match cli.simulation() {
+        Some(options) => {
+            let mut simulated = SimulatedTransport::new(transport, options);
+            run_session(&mut simulated, clock, file_manager, progress, cli, deadline)
+        }
+        None => run_session(transport, clock, file_manager, progress, cli, deadline),
+    };
+    // Gone however the session ended; a stale one would say it's still going
+    if cli.snapshot_interval.is_some() {
+        snapshot::remove(cli.output_dir())?;
+    }
+    result
+}
+
+// The loop itself, with everything it touches passed in. The caller has
+// already sent the first request (unless listening) and set the transport's
+// poll interval.
+pub fn run_session(
+    transport: &mut dyn Transport,
+    clock: &dyn Clock,
+    file_manager: FileManager,
+    progress: &mut Progress,
+    cli: &Cli,
+    deadline: Option<Instant>,
+) -> Result<(FileManager, SessionReport), ClientError> {
+    let now = clock.now();
+    let watchdog = cli
+        .watchdog_min_rate
+        .map(|min_rate| Watchdog::new(min_rate, cli.watchdog_window(), now));
+    let session_id = new_session_id();
+    let limits = cli.limits();
+    let stream = match &cli.stdout {
+        Some(target) => {
+            let stream = Stream::new(target.clone(), Out::open(cli.pipe_to.as_deref())?);
+            Some(match cli.stdout_buffer {
+                Some(bytes) => stream.with_buffer(bytes),
+                None => stream,
+            })
+        }
+        None => None,
+    };
+    let mut session = Session {
+        cli,
+        clock,
+        progress,
+        file_manager,
+        pending: Vec::new(),
+        verifier: cli.crc.then(|| Verifier::new(cli.crc_workers)),
+        screen: Screen::new(limits),
+        report: SessionReport::new(now),
+        peer: Peer::new(cli, now),
+        watchdog,
+        // A pushed session's clock only starts once the server shows up
+        timeout_at: (limits.max_session)
+            .filter(|_| !cli.listen_only)
+            .map(|timeout| now + timeout),
+        deadline,
+        started: now,
+        requests: u32::from(!cli.listen_only),
+        next_hello: first_hello(cli, now),
+        datagrams: 0,
+        parse_errors: 0,
+        arrivals: 0,
+        last_progress: HashMap::new(),
+        last_nack: None,
+        last_idle: None,
+        // Nobody to tell when the transfer was pushed
+        next_ack: (cli.ack_interval)
+            .filter(|_| !cli.listen_only)
+            .map(|interval| now + interval),
+        outgoing: cli.limit_rate.map(|rate| Bucket::new(rate, now)),
+        version: protocol::starting_version(cli),
+        wide_nacks: false,
+        completion: cli.completion(),
+        session_id,
+        headerless: HashMap::new(),
+        // A pushed session has nobody we asked, so nobody to probe
+        echo: cli
+            .echo_interval
+            .filter(|_| !cli.listen_only)
+            .map(|interval| Echo::new(session_id, interval, now)),
+        stripped: HashSet::new(),
+        #[cfg(feature = "crypto")]
+        unsealed: HashSet::new(),
+        completed: HashSet::new(),
+        next_snapshot: cli.snapshot_interval.map(|_| now),
+        capability_reply: None,
+        ended: false,
+        selection: cli.selection(),
+        stream,
+        skipped: HashSet::new(),
+        #[cfg(feature = "crypto")]
+        key: (cli.psk.as_ref()).map(|psk| crate::crypto::session_key(psk, &cli.salt)),
+        journal: cli
+            .journal
+            .as_deref()
+            .map(|path| Journal::create(path, now))
+            .transpose()?,
+        capture: (cli.record.as_deref())
+            .map(|path| Recorder::create(path, now))
+            .transpose()?,
+        preflight: (!cli.no_space_check).then(|| Preflight::for_cli(cli)),
+        tracer: (cli.trace_packets.as_deref())
+            .map(|path| Tracer::create(path, now))
+            .transpose()?,
+        state: None,
+    };
+    if let Some(rate) = cli.limit_rate {
+        session.report.set_rate_limit(rate);
+    }
+    let exhausted = match session.receive(transport) {
+        Ok(exhausted) => exhausted,
+        Err(e) => {
+            session.report_failure();
+            return Err(e);
+        }
+    };
+    if let Some(journal) = session.journal.take() {
+        journal.finish()?;
+    }
+    if let Some(stream) = session.stream.take() {
+        stream.into_inner().finish()?;
+    }
+    if let Some(capture) = session.capture.take() {
+        capture.finish()?;
+    }
+    if let Some(tracer) = session.tracer.take() {
+        tracer.finish()?;
+    }
+    if let Some(state) = session.state.as_mut() {
+        state.flush()?;
+    }
+    session.report.set_kernel_drops(transport.kernel_drops());
+    if let Some(stats) = transport.pipeline_stats() {
+        session.report.set_pipeline(stats);
+    }
+    if let Some(stats) = transport.simulation_stats() {
+        session.report.set_simulation(stats);
+    }
+    if let Some(echo) = &session.echo {
+        session.report.set_echo(echo.stats());
+    }
+    let stragglers = session.stragglers();
+    let criterion = match exhausted {
+        true => "--replay".to_string(),
+        false => session.completion.name().to_string(),
+    };
+    session
+        .report
+        .record_end(clock.now(), criterion, stragglers);
+
+    session.progress.emit(Event::SessionFinished {
+        packets: session.report.packets(),
+        bytes: session.report.bytes(),
+        elapsed: session.report.elapsed(),
+    })?;
+    Ok((session.file_manager, session.report))
+}
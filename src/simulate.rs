@@ -0,0 +1,200 @@
+// A bad network on purpose (--simulate-loss, --simulate-duplicate,
+// --simulate-reorder): datagrams dropped, delivered twice, or held back and
+// delivered late, at random, between whatever transport the session has and
+// the session itself. Unlike a real bad network it can be run again: the
+// choices all come from one seed, and the report says which.
+
+use crate::{
+    error::ClientError,
+    transport::{Handler, PipelineStats, Transport},
+};
+use std::{
+    io,
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// How often a datagram is held back with --simulate-reorder, and how many
+// later ones can overtake it
+const REORDER_CHANCE: f64 = 0.25;
+const MAX_REORDER_DEPTH: u32 = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Options {
+    // Fractions of datagrams, each from 0 to 1
+    pub loss: f64,
+    pub duplicate: f64,
+    pub reorder: bool,
+    // Picked from the clock if not given
+    pub seed: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub seed: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+}
+
+// xorshift64*, so a seed means the same choices on every machine
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // True this fraction of the time
+    fn chance(&mut self, fraction: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < fraction
+    }
+
+    fn below(&mut self, n: u32) -> u32 {
+        (self.next() % u64::from(n)) as u32
+    }
+}
+
+// A datagram held back, and how many more have to go past it first
+struct Held {
+    overtakers: u32,
+    datagram: Vec<u8>,
+    from: SocketAddr,
+}
+
+pub struct SimulatedTransport<'a> {
+    inner: &'a mut dyn Transport,
+    options: Options,
+    rng: Rng,
+    held: Vec<Held>,
+    stats: Stats,
+}
+
+impl<'a> SimulatedTransport<'a> {
+    pub fn new(inner: &'a mut dyn Transport, options: Options) -> Self {
+        let seed = options.seed.unwrap_or_else(|| {
+            let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH);
+            since_epoch.map_or(0, |d| d.as_nanos() as u64)
+        });
+        SimulatedTransport {
+            inner,
+            options,
+            rng: Rng::new(seed),
+            held: Vec::new(),
+            stats: Stats {
+                seed,
+                ..Stats::default()
+            },
+        }
+    }
+}
+
+// One datagram through the simulated network, and whatever it lets past
+fn pass(
+    options: &Options,
+    rng: &mut Rng,
+    held: &mut Vec<Held>,
+    stats: &mut Stats,
+    handle: &mut Handler,
+    datagram: &[u8],
+    from: SocketAddr,
+) -> Result<(), ClientError> {
+    if rng.chance(options.loss) {
+        stats.dropped += 1;
+        return Ok(());
+    }
+    let copies = match rng.chance(options.duplicate) {
+        true => {
+            stats.duplicated += 1;
+            2
+        }
+        false => 1,
+    };
+    for _ in 0..copies {
+        if options.reorder && rng.chance(REORDER_CHANCE) {
+            stats.reordered += 1;
+            held.push(Held {
+                overtakers: 1 + rng.below(MAX_REORDER_DEPTH),
+                datagram: datagram.to_vec(),
+                from,
+            });
+            continue;
+        }
+        handle(datagram, from)?;
+        for waiting in held.iter_mut() {
+            waiting.overtakers -= 1;
+        }
+        release(held, handle, |waiting| waiting.overtakers == 0)?;
+    }
+    Ok(())
+}
+
+fn release(
+    held: &mut Vec<Held>,
+    handle: &mut Handler,
+    ready: impl Fn(&Held) -> bool,
+) -> Result<(), ClientError> {
+    let (ready, waiting): (Vec<Held>, Vec<Held>) = held.drain(..).partition(ready);
+    *held = waiting;
+    for held in ready {
+        handle(&held.datagram, held.from)?;
+    }
+    Ok(())
+}
+
+impl Transport for SimulatedTransport<'_> {
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError> {
+        let SimulatedTransport {
+            inner,
+            options,
+            rng,
+            held,
+            stats,
+        } = self;
+        let received = inner.receive(&mut |datagram, from| {
+            pass(options, rng, held, stats, handle, datagram, from)
+        })?;
+        // A network holds a datagram back, not forever: a quiet moment lets
+        // everything out
+        if !received && !held.is_empty() {
+            release(held, handle, |_| true)?;
+            return Ok(true);
+        }
+        Ok(received)
+    }
+
+    fn send(&mut self, datagram: &[u8]) {
+        self.inner.send(datagram);
+    }
+
+    fn connect(&mut self, server: &str) -> io::Result<()> {
+        self.inner.connect(server)
+    }
+
+    fn set_poll_interval(&mut self, interval: Option<Duration>) -> io::Result<()> {
+        self.inner.set_poll_interval(interval)
+    }
+
+    fn kernel_drops(&self) -> Option<u32> {
+        self.inner.kernel_drops()
+    }
+
+    fn pipeline_stats(&self) -> Option<PipelineStats> {
+        self.inner.pipeline_stats()
+    }
+
+    fn exhausted(&self) -> bool {
+        self.inner.exhausted() && self.held.is_empty()
+    }
+
+    fn simulation_stats(&self) -> Option<Stats> {
+        Some(self.stats)
+    }
+}
@@ -0,0 +1,128 @@
+// Where complete files go when they're written through `FileManager::
+// write_all_to`: a directory, a map in memory (for tests, or a caller that
+// wants the bytes), or nowhere at all (for benchmarks that time everything
+// but the disk). A sink hands out a writer for a file and is told when the
+// file is done; `write_all_files` keeps the full treatment (--on-conflict,
+// --chown, spilled files renamed into place) for the real output directory.
+
+use crate::fsio;
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+pub trait FileSink {
+    // Somewhere to write the file called `name`, from the start
+    fn create(&mut self, name: &OsStr) -> io::Result<Box<dyn Write + '_>>;
+
+    // Everything's been written; say where it ended up
+    fn finalize(&mut self, name: &OsStr) -> io::Result<PathBuf>;
+}
+
+// Files in a directory, each written beside its real name as `.name.partial`
+// and renamed once it's all there, replacing whatever had that name
+pub struct DirSink {
+    dir: PathBuf,
+}
+
+impl DirSink {
+    pub fn new(dir: &Path) -> Self {
+        DirSink {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    // In the same directory as the file, for a --tree name
+    fn partial(&self, name: &OsStr) -> PathBuf {
+        let path = self.dir.join(name);
+        let mut partial = OsString::from(".");
+        partial.push(path.file_name().unwrap_or(name));
+        partial.push(".partial");
+        path.with_file_name(partial)
+    }
+}
+
+// Writes that say which file failed
+struct Named {
+    file: File,
+    path: PathBuf,
+}
+
+impl Write for Named {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        fsio::write_all(&mut self.file, &self.path, buf)?;
+        Ok(buf.len())
+    }
+
+    // On the disk, before finalize renames it
+    fn flush(&mut self) -> io::Result<()> {
+        fsio::sync(&self.file, &self.path)
+    }
+}
+
+impl FileSink for DirSink {
+    fn create(&mut self, name: &OsStr) -> io::Result<Box<dyn Write + '_>> {
+        let path = self.partial(name);
+        fsio::create_parent_dirs(&path)?;
+        let file = fsio::create(&path)?;
+        Ok(Box::new(Named { file, path }))
+    }
+
+    fn finalize(&mut self, name: &OsStr) -> io::Result<PathBuf> {
+        let path = self.dir.join(name);
+        fsio::rename(&self.partial(name), &path)?;
+        Ok(path)
+    }
+}
+
+// Files kept as bytes, by name. A file written again replaces the first.
+#[derive(Default)]
+pub struct MemorySink {
+    pub files: BTreeMap<OsString, Vec<u8>>,
+}
+
+impl FileSink for MemorySink {
+    fn create(&mut self, name: &OsStr) -> io::Result<Box<dyn Write + '_>> {
+        let data = self.files.entry(name.to_os_string()).or_default();
+        data.clear();
+        Ok(Box::new(data))
+    }
+
+    fn finalize(&mut self, name: &OsStr) -> io::Result<PathBuf> {
+        Ok(PathBuf::from(name))
+    }
+}
+
+// Throws everything away, counting the bytes
+#[derive(Default)]
+pub struct NullSink {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+struct Counting<'a>(&'a mut u64);
+
+impl Write for Counting<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        *self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FileSink for NullSink {
+    fn create(&mut self, _: &OsStr) -> io::Result<Box<dyn Write + '_>> {
+        Ok(Box::new(Counting(&mut self.bytes)))
+    }
+
+    fn finalize(&mut self, name: &OsStr) -> io::Result<PathBuf> {
+        self.files += 1;
+        Ok(PathBuf::from(name))
+    }
+}
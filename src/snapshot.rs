@@ -0,0 +1,82 @@
+// --snapshot-interval: a small progress.json in the output directory, kept
+// up to date while a session runs, for anyone who wants to know how it's
+// going without attaching to the process or reading logs. Each one is written
+// beside the old and renamed over it, so a reader never sees half of one.
+// It's removed when the session ends.
+
+use crate::{file_manager::FileManager, fsio, report::SessionReport};
+use serde::Serialize;
+use std::{io, net::SocketAddr, path::Path};
+
+pub const FILE_NAME: &str = "progress.json";
+const PARTIAL_NAME: &str = "progress.json.partial";
+
+#[derive(Serialize)]
+pub struct Snapshot {
+    pid: u32,
+    // Where packets are coming from, once we know
+    peer: Option<String>,
+    // Wall-clock time of this snapshot, in ms since the Unix epoch
+    updated_unix_ms: u128,
+    elapsed_ms: u128,
+    packets: u64,
+    bytes: u64,
+    files: Vec<FileSnapshot>,
+}
+
+#[derive(Serialize)]
+struct FileSnapshot {
+    id: u8,
+    name: Option<String>,
+    received: usize,
+    // Unknown until the last packet arrives
+    expected: Option<u32>,
+    complete: bool,
+}
+
+impl Snapshot {
+    pub fn new(
+        file_manager: &FileManager,
+        report: &SessionReport,
+        peer: Option<SocketAddr>,
+        updated_unix_ms: u128,
+    ) -> Self {
+        let files = (file_manager.file_ids().into_iter())
+            .filter_map(|id| {
+                let (name, received, expected) = file_manager.file_status(id)?;
+                Some(FileSnapshot {
+                    id,
+                    name: name.map(|name| name.to_string_lossy().into_owned()),
+                    received,
+                    expected,
+                    complete: file_manager.is_complete(id),
+                })
+            })
+            .collect();
+        Snapshot {
+            pid: std::process::id(),
+            peer: peer.map(|peer| peer.to_string()),
+            updated_unix_ms,
+            elapsed_ms: report.elapsed().as_millis(),
+            packets: report.packets(),
+            bytes: report.bytes(),
+            files,
+        }
+    }
+
+    // Replace `dir`'s progress.json with this one
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        let partial = dir.join(PARTIAL_NAME);
+        fsio::write(&partial, format!("{json}\n").as_bytes())?;
+        fsio::rename(&partial, &dir.join(FILE_NAME))
+    }
+}
+
+// Take `dir`'s progress.json away, if there is one
+pub fn remove(dir: &Path) -> io::Result<()> {
+    match fsio::remove_file(&dir.join(FILE_NAME)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
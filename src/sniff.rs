@@ -0,0 +1,136 @@
+// --fix-extensions: tell what a complete file is from its first bytes, and
+// give it the extension that says so when its name doesn't. Some servers
+// name files by bare ID, and "3" opens in nothing. A name with no extension,
+// or one we don't know, gets the right one added ("3" becomes "3.png"); one
+// that belongs to some other kind is swapped ("logo.txt" becomes "logo.png").
+// Text keeps an extension we don't know, since there are too many kinds of
+// text to tell apart. Original names go in the report.
+
+use crate::file_manager::FileManager;
+use std::path::Path;
+
+// Enough to tell text from binary without reading the whole file
+pub const HEAD_LEN: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Png,
+    Pdf,
+    Zip,
+    Elf,
+    Text,
+}
+
+const KINDS: [Kind; 5] = [Kind::Png, Kind::Pdf, Kind::Zip, Kind::Elf, Kind::Text];
+
+impl Kind {
+    // The first is the one that's added
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Kind::Png => &["png"],
+            Kind::Pdf => &["pdf"],
+            // Plenty of formats are ZIPs underneath
+            Kind::Zip => &[
+                "zip", "jar", "apk", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "whl",
+            ],
+            Kind::Elf => &["elf", "so", "o", "ko", "bin", "out"],
+            Kind::Text => &["txt"],
+        }
+    }
+}
+
+// What `head`, the start of a file, says the file is
+pub fn sniff(head: &[u8]) -> Option<Kind> {
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(Kind::Png);
+    }
+    if head.starts_with(b"%PDF-") {
+        return Some(Kind::Pdf);
+    }
+    // Empty archives start with the end-of-directory record instead
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        return Some(Kind::Zip);
+    }
+    if head.starts_with(b"\x7fELF") {
+        return Some(Kind::Elf);
+    }
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // Cut off partway through a character is still text
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    let control = |c: char| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b');
+    (!text.is_empty() && !text.chars().any(control)).then_some(Kind::Text)
+}
+
+// The name a file of `kind` called `name` should have, if it isn't that
+pub fn fixed_name(name: &str, kind: Kind) -> Option<String> {
+    let path = Path::new(name);
+    let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+        return Some(format!("{name}.{}", kind.extensions()[0]));
+    };
+    let extension = extension.to_ascii_lowercase();
+    if kind.extensions().contains(&extension.as_str()) {
+        None
+    } else if belongs_elsewhere(&extension, kind) {
+        swapped(path, kind)
+    } else if kind == Kind::Text {
+        None
+    } else {
+        Some(format!("{name}.{}", kind.extensions()[0]))
+    }
+}
+
+fn belongs_elsewhere(extension: &str, kind: Kind) -> bool {
+    (KINDS.iter())
+        .filter(|&&other| other != kind)
+        .any(|other| other.extensions().contains(&extension))
+}
+
+fn swapped(path: &Path, kind: Kind) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    Some(format!("{stem}.{}", kind.extensions()[0]))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Renamed {
+    pub file_id: u8,
+    pub from: String,
+    pub to: String,
+}
+
+// Rename every complete file whose name doesn't fit what's in it, unless
+// another file already has the name it would get. Files whose names aren't
+// UTF-8 are left as they are.
+pub fn fix_extensions(file_manager: &mut FileManager) -> Vec<Renamed> {
+    let mut renamed = Vec::new();
+    for file_id in file_manager.file_ids() {
+        let Some((Some(name), ..)) = file_manager.file_status(file_id) else {
+            continue;
+        };
+        let Some(name) = name.to_str().map(str::to_string) else {
+            continue;
+        };
+        let Some(to) = (file_manager.head(file_id, HEAD_LEN))
+            .and_then(|head| sniff(&head))
+            .and_then(|kind| fixed_name(&name, kind))
+        else {
+            continue;
+        };
+        let taken = (file_manager.file_ids().into_iter()).any(|other| {
+            file_manager
+                .file_status(other)
+                .is_some_and(|(other, ..)| other.is_some_and(|other| other == to.as_str()))
+        });
+        if !taken {
+            file_manager.name_file(file_id, &to);
+            renamed.push(Renamed {
+                file_id,
+                from: name,
+                to,
+            });
+        }
+    }
+    renamed
+}
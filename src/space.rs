@@ -0,0 +1,93 @@
+// Checking for room before the files need it. An extended header says how
+// big its file is, and as each one comes the sizes said so far (less the
+// files already handed to --write-threads) are held up against the free
+// space in every directory the files pass through: the output directory,
+// and --temp-dir and --spill-dir if they're elsewhere. If it won't fit, the
+// session ends there with InsufficientSpace instead of with ENOSPC halfway
+// through writing. Only Linux says how much room there is; elsewhere
+// nothing is checked, and --no-space-check turns it off.
+
+use crate::{assembly::Backend, cli::Cli, error::ClientError, file_manager::FileManager};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+pub struct Preflight {
+    dirs: Vec<PathBuf>,
+    // Each file's size, as its header said
+    sizes: HashMap<u8, u64>,
+}
+
+impl Preflight {
+    pub fn new(dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut unique: Vec<PathBuf> = Vec::new();
+        for dir in dirs {
+            if !unique.contains(&dir) {
+                unique.push(dir);
+            }
+        }
+        Preflight {
+            dirs: unique,
+            sizes: HashMap::new(),
+        }
+    }
+
+    // The directories `cli` has files pass through
+    pub fn for_cli(cli: &Cli) -> Self {
+        let spilling = cli.assembly_backend() == Backend::Spill || cli.max_memory.is_some();
+        let spill = spilling.then(|| cli.spill_dir().unwrap_or(Path::new(".")));
+        let dirs = [Some(cli.output_dir()), cli.temp_dir.as_deref(), spill];
+        Self::new(dirs.into_iter().flatten().map(Path::to_path_buf))
+    }
+
+    // A header said file `file_id` is `size` bytes
+    pub fn header(
+        &mut self,
+        file_id: u8,
+        size: u64,
+        files: &FileManager,
+    ) -> Result<(), ClientError> {
+        if self.sizes.insert(file_id, size) == Some(size) {
+            return Ok(());
+        }
+        let needed = (self.sizes.iter())
+            .filter(|&(&file_id, _)| !files.is_handed_off(file_id))
+            .fold(0u64, |needed, (_, &size)| needed.saturating_add(size));
+        for dir in &self.dirs {
+            match available(dir) {
+                Some(available) if needed > available => {
+                    return Err(ClientError::InsufficientSpace {
+                        dir: dir.clone(),
+                        needed,
+                        available,
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// The free space where `dir` is, or will be once it's made; None where
+// that can't be told
+pub fn available(dir: &Path) -> Option<u64> {
+    let existing = (dir.ancestors())
+        .map(|dir| match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        })
+        .find(|dir| dir.is_dir())?;
+    free_space(existing)
+}
+
+#[cfg(target_os = "linux")]
+fn free_space(dir: &Path) -> Option<u64> {
+    crate::linux::available_space(dir).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,379 @@
+// Spill-to-disk storage (--spill-dir). Payloads are copied straight into a
+// memory-mapped file at `packet_number * GRID` as they arrive, so while every
+// packet but the last is a full 1 KiB the spill file *is* the finished file
+// and finishing it is a rename. The first packet that doesn't fit the grid
+// moves that file back into memory. Spill files live in the session's
+// scratch directory inside --spill-dir. When an extended header says how
+// big the file is, its spill file is made that long (and on Linux, its
+// blocks set aside) up front instead of growing as packets come.
+//
+// With --max-memory alone, files only come here once they're handed over:
+// the file holding the most in memory is moved onto disk whenever payloads
+// in memory pass the cap, and carries on there.
+
+use crate::{
+    fsio::{self, Action},
+    i18n::tr,
+    limits,
+    log::log,
+    packet::Payload,
+    tempdir::{self, TempDirs},
+    wire,
+};
+use memmap2::MmapMut;
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+// Every payload but the last is this long in a well-behaved transfer
+const GRID: usize = wire::MAX_PAYLOAD;
+
+// Spill files grow a power of two at a time from here, which keeps their
+// length (and so every remap) a whole number of pages
+const MIN_CAPACITY: u64 = 64 * 1024;
+
+struct SpillFile {
+    path: PathBuf,
+    file: File,
+    map: MmapMut,
+    // Highest packet number written so far
+    highest: u32,
+    // Number and length of the last packet, once it's here
+    last: Option<(u32, usize)>,
+}
+
+impl SpillFile {
+    fn create(dir: &Path, file_id: u8) -> io::Result<Self> {
+        let path = dir.join(format!("{file_id}.spill"));
+        let file = fsio::open(
+            &path,
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true),
+        )?;
+        fsio::set_len(&file, &path, MIN_CAPACITY)?;
+        // SAFETY: the file was just created for us alone; nothing else is
+        // expected to touch it while it's mapped
+        let map = fsio::at(Action::Map, &path, || unsafe { MmapMut::map_mut(&file) })?;
+        Ok(SpillFile {
+            path,
+            file,
+            map,
+            highest: 0,
+            last: None,
+        })
+    }
+
+    // Whether a payload can go on the grid without breaking the "spill file
+    // is the final file" layout
+    fn fits(&self, packet_number: u32, len: usize, is_last: bool) -> bool {
+        match self.last {
+            _ if len > GRID => false,
+            // Past where Screen stops a session's files, for the paths that
+            // don't go through one (unpack, replay)
+            _ if packet_number >= limits::MAX_PACKETS => false,
+            Some((last, last_len)) if packet_number == last => is_last && len == last_len,
+            Some((last, _)) => packet_number < last && !is_last && len == GRID,
+            None if is_last => packet_number >= self.highest,
+            None => len == GRID,
+        }
+    }
+
+    fn write(&mut self, packet_number: u32, data: &[u8], is_last: bool) -> io::Result<()> {
+        let offset = packet_number as usize * GRID;
+        let needed = (offset + data.len()) as u64;
+        if needed > self.map.len() as u64 {
+            self.resize(needed.next_power_of_two().max(MIN_CAPACITY))?;
+        }
+        self.map[offset..offset + data.len()].copy_from_slice(data);
+        self.highest = self.highest.max(packet_number);
+        if is_last {
+            self.last = Some((packet_number, data.len()));
+        }
+        Ok(())
+    }
+
+    // Make the file `len` bytes long, and map all of it
+    fn resize(&mut self, len: u64) -> io::Result<()> {
+        fsio::set_len(&self.file, &self.path, len)?;
+        // SAFETY: as in create
+        self.map = fsio::at(Action::Map, &self.path, || unsafe {
+            MmapMut::map_mut(&self.file)
+        })?;
+        Ok(())
+    }
+
+    // Room for the whole of a file `len` bytes long, all at once
+    fn reserve(&mut self, len: u64) -> io::Result<()> {
+        if len <= self.map.len() as u64 {
+            return Ok(());
+        }
+        self.resize(len)?;
+        #[cfg(target_os = "linux")]
+        crate::linux::allocate(&self.file, len)?;
+        Ok(())
+    }
+
+    // The payload stored for a packet
+    fn read(&self, packet_number: u32) -> &[u8] {
+        let offset = packet_number as usize * GRID;
+        let len = match self.last {
+            Some((last, len)) if last == packet_number => len,
+            _ => GRID,
+        };
+        &self.map[offset..offset + len]
+    }
+
+    // How long the file is, going by its last packet
+    fn len(&self) -> u64 {
+        self.last
+            .map_or(0, |(last, len)| last as u64 * GRID as u64 + len as u64)
+    }
+
+    // Trim the file to its real length and move it to `target`
+    fn finish(self, target: &Path) -> io::Result<()> {
+        let len = self.len();
+        fsio::at(Action::Flush, &self.path, || self.map.flush())?;
+        drop(self.map);
+        fsio::set_len(&self.file, &self.path, len)?;
+        fsio::sync(&self.file, &self.path)?;
+        drop(self.file);
+        tempdir::move_into_place(&self.path, target)
+    }
+
+    // Throw the spill file away
+    fn discard(self) {
+        let SpillFile {
+            path, file, map, ..
+        } = self;
+        drop(map);
+        drop(file);
+        let _ = fsio::remove_file(&path);
+    }
+}
+
+// A spill file for `file_id` in the session's scratch directory, or None
+// (and a warning) if there can't be one
+fn create(dir: &Path, temp: &mut TempDirs, file_id: u8) -> Option<SpillFile> {
+    (temp.get(dir))
+        .and_then(|dir| SpillFile::create(&dir, file_id))
+        .map_err(|e| {
+            log!(
+                Warn,
+                "{}",
+                tr!("spill-fallback", file_id = file_id, error = e)
+            )
+        })
+        .ok()
+}
+
+// What happened to a payload handed to Spill::store
+pub enum Stored {
+    OnDisk,
+    // Keep it in memory like any other payload
+    InMemory,
+    // The file just stopped fitting the grid: these earlier payloads need to
+    // go back into memory along with this one
+    FellBack(Vec<(u32, Payload)>),
+}
+
+pub struct Spill {
+    dir: PathBuf,
+    // None once a file has fallen back to memory
+    files: HashMap<u8, Option<SpillFile>>,
+    // Files already renamed into place
+    finished: HashMap<u8, PathBuf>,
+    // Whether a file's first payload starts a spill file, or files only
+    // come here through `adopt`
+    every_file: bool,
+}
+
+impl Spill {
+    pub fn new(dir: PathBuf) -> Self {
+        Spill {
+            dir,
+            files: HashMap::new(),
+            finished: HashMap::new(),
+            every_file: true,
+        }
+    }
+
+    // Spilling only the files handed to `adopt` (--max-memory)
+    pub fn on_demand(dir: PathBuf) -> Self {
+        Spill {
+            every_file: false,
+            ..Spill::new(dir)
+        }
+    }
+
+    pub fn store(
+        &mut self,
+        temp: &mut TempDirs,
+        file_id: u8,
+        packet_number: u32,
+        data: &[u8],
+        is_last: bool,
+        stored: impl Iterator<Item = u32>,
+    ) -> Stored {
+        if !self.every_file && !self.files.contains_key(&file_id) {
+            return Stored::InMemory;
+        }
+        let slot = (self.files.entry(file_id)).or_insert_with(|| create(&self.dir, temp, file_id));
+        let Some(file) = slot else {
+            return Stored::InMemory;
+        };
+
+        if file.fits(packet_number, data.len(), is_last) {
+            match file.write(packet_number, data, is_last) {
+                Ok(()) => return Stored::OnDisk,
+                Err(e) => log!(
+                    Warn,
+                    "{}",
+                    tr!("spill-fallback", file_id = file_id, error = e)
+                ),
+            }
+        }
+
+        let earlier = stored
+            .filter(|&number| number != packet_number)
+            .map(|number| (number, Payload::from_slice(file.read(number))))
+            .collect();
+        if let Some(file) = slot.take() {
+            file.discard();
+        }
+        Stored::FellBack(earlier)
+    }
+
+    // A header said the file is `len` bytes long. Nothing's lost if the room
+    // can't be had now: the file grows as packets come, as it would have.
+    pub fn reserve(&mut self, temp: &mut TempDirs, file_id: u8, len: u64) {
+        if !self.every_file && !self.files.contains_key(&file_id) {
+            return;
+        }
+        let slot = (self.files.entry(file_id)).or_insert_with(|| create(&self.dir, temp, file_id));
+        if let Some(file) = slot {
+            let _ = file.reserve(len);
+        }
+    }
+
+    // Move a file's payloads from memory onto disk, lowest packet number
+    // first. Returns false, and leaves the file in memory for good, if they
+    // can't all go on the grid. `last` is the number of its last packet, if
+    // that's here.
+    pub fn adopt<'a>(
+        &mut self,
+        temp: &mut TempDirs,
+        file_id: u8,
+        payloads: impl Iterator<Item = (u32, &'a [u8])>,
+        last: Option<u32>,
+    ) -> bool {
+        let mut file = create(&self.dir, temp, file_id);
+        for (packet_number, data) in payloads {
+            let Some(spill) = file.as_mut() else {
+                break;
+            };
+            let is_last = last == Some(packet_number);
+            let written = spill.fits(packet_number, data.len(), is_last)
+                && (spill.write(packet_number, data, is_last))
+                    .map_err(|e| {
+                        log!(
+                            Warn,
+                            "{}",
+                            tr!("spill-fallback", file_id = file_id, error = e)
+                        )
+                    })
+                    .is_ok();
+            if !written {
+                if let Some(spill) = file.take() {
+                    spill.discard();
+                }
+            }
+        }
+        let adopted = file.is_some();
+        self.files.insert(file_id, file);
+        adopted
+    }
+
+    // Whether a file stays in memory however full it gets, having tried the
+    // disk and not fit
+    pub fn refused(&self, file_id: u8) -> bool {
+        matches!(self.files.get(&file_id), Some(None))
+    }
+
+    // Whether a file's payloads are on disk rather than in memory
+    pub fn holds(&self, file_id: u8) -> bool {
+        matches!(self.files.get(&file_id), Some(Some(_))) || self.finished.contains_key(&file_id)
+    }
+
+    // A payload on disk, while its file is still being spilled
+    pub fn read(&self, file_id: u8, packet_number: u32) -> Option<&[u8]> {
+        let Some(Some(file)) = self.files.get(&file_id) else {
+            return None;
+        };
+        Some(file.read(packet_number))
+    }
+
+    // Move a complete spilled file to `target`. Returns false if the file
+    // isn't (or is no longer) on disk and has to be written from memory.
+    pub fn finish(&mut self, file_id: u8, target: &Path) -> io::Result<bool> {
+        if let Some(path) = self.finished.get(&file_id) {
+            if path != target {
+                fsio::copy(path, target)?;
+            }
+            return Ok(true);
+        }
+        let Some(Some(file)) = self.files.remove(&file_id) else {
+            return Ok(false);
+        };
+        file.finish(target)?;
+        self.finished.insert(file_id, target.to_path_buf());
+        Ok(true)
+    }
+
+    // How long a file still on disk will be
+    pub fn len(&self, file_id: u8) -> Option<u64> {
+        self.files.get(&file_id)?.as_ref().map(SpillFile::len)
+    }
+
+    // A file's spill file, moved into a Spill of its own to be finished
+    // somewhere else
+    pub fn detach(&mut self, file_id: u8) -> Spill {
+        let mut alone = Spill::on_demand(self.dir.clone());
+        if let Some(file) = self.files.remove(&file_id) {
+            alone.files.insert(file_id, file);
+        }
+        alone
+    }
+
+    // Throw a file's spill file away, if it has one
+    pub fn discard(&mut self, file_id: u8) {
+        if let Some(Some(file)) = self.files.remove(&file_id) {
+            file.discard();
+        }
+    }
+
+    // Bring a spilled file back into memory, e.g. to write it out before
+    // it's complete
+    pub fn unspill(
+        &mut self,
+        file_id: u8,
+        stored: impl Iterator<Item = u32>,
+    ) -> Vec<(u32, Payload)> {
+        let Some(slot) = self.files.get_mut(&file_id) else {
+            return Vec::new();
+        };
+        let Some(file) = slot.take() else {
+            return Vec::new();
+        };
+        let packets = stored
+            .map(|number| (number, Payload::from_slice(file.read(number))))
+            .collect();
+        file.discard();
+        packets
+    }
+}
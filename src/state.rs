@@ -0,0 +1,221 @@
+// --resume DIR: every packet the session keeps is also appended to a log in
+// DIR, flushed every FLUSH_EVERY, so a client that crashes or is killed
+// halfway through can be started again with the same DIR and pick up where
+// it left off: the log is read back into the file manager before the first
+// datagram, the repeat request carries a resume token saying what's already
+// here, and only what's missing is waited for. Once the files are written the
+// log is removed.
+//
+// Layout, big-endian:
+//
+//   header: "SFSS"  version  server length (1)  server
+//   record: packet length (2)  packet, as on the wire without a CRC  CRC-32
+//
+// The CRC covers the length and the packet. A log cut off mid-record (the
+// client was killed while writing it) keeps everything before it, with a
+// warning saying how much went and why. A log from another server, or a file
+// that isn't a log at all, is left alone unless --force says to start over.
+
+use crate::{checksum, fsio, i18n::tr, log::log, packet::Packet};
+use std::{
+    convert::TryFrom,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+pub const MAGIC: &[u8; 4] = b"SFSS";
+pub const VERSION: u8 = 1;
+pub const FILE_NAME: &str = "packets.log";
+// How much a crash can lose
+pub const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+fn header(server: &str) -> Vec<u8> {
+    let server = &server.as_bytes()[..server.len().min(u8::MAX as usize)];
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.push(server.len() as u8);
+    out.extend_from_slice(server);
+    out
+}
+
+pub fn record(packet: &Packet) -> Vec<u8> {
+    let bytes = packet.to_bytes();
+    let mut out = (bytes.len() as u16).to_be_bytes().to_vec();
+    out.extend_from_slice(&bytes);
+    let crc = checksum::crc32(&out);
+    out.extend_from_slice(&crc.to_be_bytes());
+    out
+}
+
+// A log for our server: its packets, how many bytes of it are whole, and
+// why the rest isn't, if there's any rest
+pub struct Decoded {
+    pub packets: Vec<Packet>,
+    pub whole: usize,
+    pub cut: Option<Cut>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cut {
+    // The client was killed partway through writing it
+    Short,
+    Crc,
+    // Whole and checked, but not a packet this build reads
+    NotAPacket,
+}
+
+impl Cut {
+    fn describe(self) -> String {
+        match self {
+            Cut::Short => tr!("state-cut-short"),
+            Cut::Crc => tr!("state-cut-crc"),
+            Cut::NotAPacket => tr!("state-cut-not-a-packet"),
+        }
+    }
+}
+
+// A log that isn't ours to carry on from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Foreign {
+    NotALog,
+    // The server it's for
+    Server(String),
+}
+
+pub fn decode(log: &[u8], server: &str) -> Result<Decoded, Foreign> {
+    let header = header(server);
+    if !log.starts_with(&header) {
+        let mut ours = MAGIC.to_vec();
+        ours.push(VERSION);
+        let theirs = (log.get(5).filter(|_| log.starts_with(&ours)))
+            .and_then(|&len| log.get(6..6 + len as usize));
+        return Err(match theirs {
+            Some(theirs) => Foreign::Server(String::from_utf8_lossy(theirs).into_owned()),
+            None => Foreign::NotALog,
+        });
+    }
+    let mut packets = Vec::new();
+    let mut at = header.len();
+    let cut = loop {
+        if at == log.len() {
+            break None;
+        }
+        let Some(len) = log.get(at..at + 2) else {
+            break Some(Cut::Short);
+        };
+        let end = at + 2 + u16::from_be_bytes([len[0], len[1]]) as usize;
+        let Some(crc) = log.get(end..end + 4) else {
+            break Some(Cut::Short);
+        };
+        if crc != checksum::crc32(&log[at..end]).to_be_bytes() {
+            break Some(Cut::Crc);
+        }
+        let Ok(packet) = Packet::try_from(&log[at + 2..end]) else {
+            break Some(Cut::NotAPacket);
+        };
+        packets.push(packet);
+        at = end + 4;
+    };
+    Ok(Decoded {
+        packets,
+        whole: at,
+        cut,
+    })
+}
+
+// The open log, appended to as packets are kept
+pub struct StateLog {
+    out: BufWriter<File>,
+    path: PathBuf,
+    flushed: Instant,
+}
+
+impl StateLog {
+    // Open `dir`'s log for `server`, returning it and the packets it already
+    // had. A damaged tail is cut off so new records follow whole ones. With
+    // `force`, a log that isn't ours is started over rather than refused.
+    pub fn open(dir: &Path, server: &str, force: bool) -> io::Result<(Self, Vec<Packet>)> {
+        fsio::create_dir_all(dir)?;
+        let path = dir.join(FILE_NAME);
+        let existing = match fsio::read(&path) {
+            Ok(log) => Some((decode(&log, server), log.len())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let (file, packets) = match existing {
+            Some((Ok(decoded), len)) => {
+                if let Some(cut) = decoded.cut {
+                    log!(
+                        Warn,
+                        "{}",
+                        tr!(
+                            "state-log-cut",
+                            path = path.display(),
+                            bytes = len - decoded.whole,
+                            why = cut.describe(),
+                            count = decoded.packets.len()
+                        )
+                    );
+                }
+                // Appending, so new records go after the cut
+                let file = fsio::open(&path, OpenOptions::new().append(true))?;
+                fsio::set_len(&file, &path, decoded.whole as u64)?;
+                (file, decoded.packets)
+            }
+            Some((Err(foreign), _)) => {
+                let what = match foreign {
+                    Foreign::NotALog => tr!("state-not-a-log", path = path.display()),
+                    Foreign::Server(theirs) => {
+                        tr!("state-other-server", path = path.display(), server = theirs)
+                    }
+                };
+                if !force {
+                    let message = tr!("state-log-refused", what = what);
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, message));
+                }
+                log!(Warn, "{}", tr!("state-log-replaced", what = what));
+                (Self::create(&path, server)?, Vec::new())
+            }
+            None => (Self::create(&path, server)?, Vec::new()),
+        };
+        let log = StateLog {
+            out: BufWriter::new(file),
+            path,
+            flushed: Instant::now(),
+        };
+        Ok((log, packets))
+    }
+
+    fn create(path: &Path, server: &str) -> io::Result<File> {
+        let mut file = fsio::create(path)?;
+        fsio::write_all(&mut file, path, &header(server))?;
+        Ok(file)
+    }
+
+    pub fn append(&mut self, packet: &Packet) -> io::Result<()> {
+        fsio::write_all(&mut self.out, &self.path, &record(packet))
+    }
+
+    // Flush if it's been FLUSH_EVERY since the last time
+    pub fn flush_if_due(&mut self, now: Instant) -> io::Result<()> {
+        if now - self.flushed < FLUSH_EVERY {
+            return Ok(());
+        }
+        self.flushed = now;
+        self.flush()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        fsio::at(fsio::Action::Flush, &self.path, || self.out.flush())
+    }
+}
+
+// Remove `dir`'s log once its files are written
+pub fn clear(dir: &Path) -> io::Result<()> {
+    match fsio::remove_file(&dir.join(FILE_NAME)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
@@ -0,0 +1,209 @@
+// --stdout NAME|ID: one file's bytes written to standard output rather than
+// to disk, in order, each run of packets as soon as everything before it is
+// in, so `client --stdout data.tar | tar x` starts unpacking while the rest
+// is still arriving. Only that file is kept (see `Cli::selection`), and the
+// report and progress go to standard error to leave standard output to it.
+// With --pipe-to it goes to a command's standard input instead.
+//
+// Either only takes the file as fast as whatever reads it, and the
+// packets after the first gap wait in memory for it. --stdout-buffer bounds
+// how far past what's gone out they're kept: further on, they're dropped as
+// they come, and --nack-after asks again only for those within it, so the
+// server is paced by the reader.
+
+use crate::{file_manager::FileManager, i18n::tr, wire};
+use std::{
+    fmt,
+    io::{self, Write},
+    process::{self, Child, Stdio},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Id(u8),
+    Name(String),
+}
+
+// A file ID if it's a number that can be one, a name otherwise
+pub fn parse_target(s: &str) -> Result<Target, String> {
+    if s.is_empty() {
+        return Err("expected a file name or ID".to_string());
+    }
+    Ok(s.parse()
+        .map_or_else(|_| Target::Name(s.to_string()), Target::Id))
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Id(id) => write!(f, "{id}"),
+            Target::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+// Where the file goes
+pub enum Out {
+    Stdout(io::Stdout),
+    // --pipe-to's command, run by the shell
+    Pipe { command: String, child: Child },
+}
+
+impl Out {
+    pub fn open(pipe_to: Option<&str>) -> io::Result<Out> {
+        let Some(command) = pipe_to else {
+            return Ok(Out::Stdout(io::stdout()));
+        };
+        #[cfg(windows)]
+        let mut shell = {
+            let mut shell = process::Command::new("cmd");
+            shell.arg("/C");
+            shell
+        };
+        #[cfg(not(windows))]
+        let mut shell = {
+            let mut shell = process::Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        let child = shell.arg(command).stdin(Stdio::piped()).spawn()?;
+        Ok(Out::Pipe {
+            command: command.to_string(),
+            child,
+        })
+    }
+
+    // Tell the command the file has ended, and wait for it to finish with it
+    pub fn finish(self) -> io::Result<()> {
+        let Out::Pipe { command, mut child } = self else {
+            return Ok(());
+        };
+        drop(child.stdin.take());
+        let status = child.wait()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(io::Error::other(tr!(
+                "pipe-to-failed",
+                command = command,
+                status = status
+            ))),
+        }
+    }
+}
+
+impl Write for Out {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Out::Stdout(out) => out.write(buf),
+            Out::Pipe { child, .. } => child.stdin.as_mut().expect("stdin is piped").write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Out::Stdout(out) => out.flush(),
+            Out::Pipe { child, .. } => child.stdin.as_mut().expect("stdin is piped").flush(),
+        }
+    }
+}
+
+pub struct Stream<W: Write> {
+    target: Target,
+    // Once it's known which file the target is
+    file_id: Option<u8>,
+    // The first packet not written yet
+    next: u32,
+    // How many packets from `next` on are kept (--stdout-buffer), and the
+    // furthest one heard of, kept or not
+    ahead: Option<u32>,
+    furthest: Option<u32>,
+    out: W,
+}
+
+impl<W: Write> Stream<W> {
+    pub fn new(target: Target, out: W) -> Self {
+        let file_id = match target {
+            Target::Id(id) => Some(id),
+            Target::Name(_) => None,
+        };
+        Stream {
+            target,
+            file_id,
+            next: 0,
+            ahead: None,
+            furthest: None,
+            out,
+        }
+    }
+
+    // Keep no more than `bytes` of the file past what's gone out
+    pub fn with_buffer(mut self, bytes: u64) -> Self {
+        let packets = bytes / wire::MAX_PAYLOAD as u64;
+        self.ahead = Some(packets.clamp(1, u32::MAX.into()) as u32);
+        self
+    }
+
+    // Whether packet `packet_number` of `file_id` is to be kept; one past
+    // the buffer isn't, and is asked for again once there's room
+    pub fn admit(&mut self, file_id: u8, packet_number: u32) -> bool {
+        let Some(ahead) = self.ahead.filter(|_| self.file_id == Some(file_id)) else {
+            return true;
+        };
+        self.furthest = self.furthest.max(Some(packet_number));
+        packet_number - self.next.min(packet_number) < ahead
+    }
+
+    // The file's packets to ask for again: those missing from what fits in
+    // the buffer, up to the furthest heard of. None without a buffer, or
+    // before the file is known.
+    pub fn missing(&self, files: &FileManager) -> Option<(u8, Vec<u32>)> {
+        let (file_id, ahead) = (self.file_id?, self.ahead?);
+        let heard = files.file_status(file_id).and_then(|(_, _, count)| count);
+        let end = (heard.map(|count| count.saturating_sub(1)))
+            .max(self.furthest)
+            .map_or(self.next, |last| last.saturating_add(1))
+            .min(self.next.saturating_add(ahead));
+        let missing = (self.next..end)
+            .filter(|&packet_number| !files.has_packet(file_id, packet_number))
+            .collect();
+        Some((file_id, missing))
+    }
+
+    fn find(&self, files: &FileManager) -> Option<u8> {
+        let Target::Name(name) = &self.target else {
+            return self.file_id;
+        };
+        (files.file_ids().into_iter()).find(|&id| {
+            let known = files.file_status(id).and_then(|(known, ..)| known);
+            known.is_some_and(|known| known == name.as_str())
+        })
+    }
+
+    // Write whatever has become contiguous since the last call
+    pub fn advance(&mut self, files: &FileManager) -> io::Result<()> {
+        if self.file_id.is_none() {
+            self.file_id = self.find(files);
+        }
+        let Some(file_id) = self.file_id else {
+            return Ok(());
+        };
+        let before = self.next;
+        while let Some(payload) = files.payload(file_id, self.next) {
+            self.out.write_all(payload)?;
+            self.next += 1;
+        }
+        if self.next != before {
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+
+    // Packets written so far
+    pub fn written(&self) -> u32 {
+        self.next
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
@@ -0,0 +1,188 @@
+// --transport tcp: the same packets over a TCP connection, for networks
+// where UDP is blocked or loses too much to be worth it. Each datagram, both
+// ways, goes as a frame: its length (u16, big-endian) and then its bytes, so
+// what the session sees is exactly what it would over UDP and nothing past
+// the transport knows the difference. The companion server speaks it with
+// `--tcp`; the course server doesn't.
+//
+// A connection that can't be made, or that the server closes before the
+// session is done, is an error from `receive` rather than from `connect`,
+// so --failover can move on to the next server the way it does when one
+// stops answering over UDP.
+
+use crate::{
+    error::ClientError,
+    i18n::tr,
+    transport::{self, Handler, Transport},
+};
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+
+// How long a frame's length is
+pub const LEN_PREFIX: usize = 2;
+
+pub fn write_frame(out: &mut impl Write, datagram: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(datagram.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            tr!("tcp-frame-too-long", len = datagram.len()),
+        )
+    })?;
+    let mut frame = Vec::with_capacity(LEN_PREFIX + datagram.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(datagram);
+    out.write_all(&frame)
+}
+
+// The next whole frame, or None if the connection ends cleanly before one
+// starts
+pub fn read_frame(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; LEN_PREFIX];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut datagram = vec![0; usize::from(u16::from_be_bytes(len))];
+    input.read_exact(&mut datagram)?;
+    Ok(Some(datagram))
+}
+
+pub struct TcpTransport {
+    stream: Option<(TcpStream, SocketAddr)>,
+    server: String,
+    connect_timeout: Option<Duration>,
+    poll_interval: Option<Duration>,
+    // Why the last connection couldn't be made or ended, for `receive`
+    failed: Option<io::Error>,
+    // Bytes read but not yet a whole frame
+    pending: Vec<u8>,
+    buf: Box<[u8; 16 * 1024]>,
+}
+
+impl TcpTransport {
+    pub fn new(connect_timeout: Option<Duration>) -> Self {
+        TcpTransport {
+            stream: None,
+            server: String::new(),
+            connect_timeout,
+            poll_interval: None,
+            failed: None,
+            pending: Vec::new(),
+            buf: Box::new([0; 16 * 1024]),
+        }
+    }
+
+    fn open(&self, server: &str) -> io::Result<(TcpStream, SocketAddr)> {
+        let mut last = None;
+        for addr in server.to_socket_addrs()? {
+            let stream = match self.connect_timeout {
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+            match stream {
+                Ok(stream) => {
+                    // Packets are small and each one matters on its own
+                    stream.set_nodelay(true)?;
+                    stream.set_read_timeout(self.poll_interval)?;
+                    return Ok((stream, addr));
+                }
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                tr!("server-wrong-family", server = server),
+            )
+        }))
+    }
+
+    // Hand on every whole frame read so far
+    fn frames(&mut self, from: SocketAddr, handle: &mut Handler) -> Result<bool, ClientError> {
+        let mut start = 0;
+        let mut handled = false;
+        while let Some(len) = (self.pending.get(start..start + LEN_PREFIX))
+            .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+        {
+            let end = start + LEN_PREFIX + len;
+            if self.pending.len() < end {
+                break;
+            }
+            let result = handle(&self.pending[start + LEN_PREFIX..end], from);
+            start = end;
+            handled = true;
+            if let Err(e) = result {
+                self.pending.drain(..start);
+                return Err(e);
+            }
+        }
+        self.pending.drain(..start);
+        Ok(handled)
+    }
+}
+
+impl Transport for TcpTransport {
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError> {
+        if let Some(e) = self.failed.take() {
+            return Err(e.into());
+        }
+        let Some((stream, from)) = self.stream.as_mut() else {
+            // Nowhere to read from until the next `connect`
+            thread::sleep(self.poll_interval.unwrap_or(Duration::from_millis(100)));
+            return Ok(false);
+        };
+        let from = *from;
+        match stream.read(&mut self.buf[..]) {
+            Ok(0) => {
+                self.stream = None;
+                self.pending.clear();
+                let message = tr!("tcp-closed", server = self.server);
+                Err(io::Error::new(io::ErrorKind::ConnectionAborted, message).into())
+            }
+            Ok(n) => {
+                self.pending.extend_from_slice(&self.buf[..n]);
+                self.frames(from, handle)
+            }
+            Err(e) if transport::is_timeout(&e) => Ok(false),
+            Err(e) => {
+                self.stream = None;
+                Err(e.into())
+            }
+        }
+    }
+
+    fn send(&mut self, datagram: &[u8]) {
+        if let Some((stream, _)) = self.stream.as_mut() {
+            let _ = write_frame(stream, datagram);
+        }
+    }
+
+    fn connect(&mut self, server: &str) -> io::Result<()> {
+        self.server = server.to_string();
+        self.pending.clear();
+        match self.open(server) {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.failed = None;
+            }
+            Err(e) => {
+                self.stream = None;
+                self.failed = Some(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_poll_interval(&mut self, interval: Option<Duration>) -> io::Result<()> {
+        self.poll_interval = interval;
+        match &self.stream {
+            Some((stream, _)) => stream.set_read_timeout(interval),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Per-session scratch directories. Everything that isn't a finished output
+// file (spill files, files still being written) lives under a
+// `.segfs-tmp-<session>/` directory next to where it's headed, so a crash
+// never leaves half-written files that look like real ones. The directories
+// are removed once the session's files are safely written, and kept (with a
+// note saying where) if the session fails.
+
+use crate::{fsio, i18n::tr, log::log, paths};
+use std::{
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+// Sessions started by this process, so daemon and grade runs don't collide
+static SESSIONS: AtomicU64 = AtomicU64::new(0);
+
+pub struct TempDirs {
+    session: String,
+    dirs: Vec<PathBuf>,
+    cleaned: bool,
+}
+
+impl Default for TempDirs {
+    fn default() -> Self {
+        let n = SESSIONS.fetch_add(1, Ordering::Relaxed);
+        TempDirs {
+            session: format!("{}-{n}", std::process::id()),
+            dirs: Vec::new(),
+            cleaned: false,
+        }
+    }
+}
+
+impl TempDirs {
+    // This session's scratch directory inside `parent`, created on first use
+    pub fn get(&mut self, parent: &Path) -> io::Result<PathBuf> {
+        let dir = parent.join(format!(".segfs-tmp-{}", self.session));
+        if !self.dirs.contains(&dir) {
+            fsio::create_dir_all(&dir)?;
+            self.dirs.push(dir.clone());
+        }
+        Ok(dir)
+    }
+
+    // The session worked: nothing in here is needed any more
+    pub fn clean_up(mut self) -> io::Result<()> {
+        self.cleaned = true;
+        for dir in &self.dirs {
+            fsio::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempDirs {
+    fn drop(&mut self) {
+        if self.cleaned {
+            return;
+        }
+        for dir in &self.dirs {
+            // Nothing lost by removing an empty one
+            if fs::remove_dir(paths::long(dir)).is_err() && paths::long(dir).exists() {
+                log!(Warn, "{}", tr!("kept-partial-files", dir = dir.display()));
+            }
+        }
+    }
+}
+
+// Rename `from` to `to`. Across filesystems it's copied beside `to` as
+// `.name.partial` first, and that renamed, so `to` is never half there.
+pub fn move_into_place(from: &Path, to: &Path) -> io::Result<()> {
+    if fsio::rename(from, to).is_err() {
+        let mut partial = OsString::from(".");
+        partial.push(to.file_name().unwrap_or_default());
+        partial.push(".partial");
+        let partial = to.with_file_name(partial);
+        fsio::copy(from, &partial)?;
+        fsio::sync(
+            &fsio::open(&partial, OpenOptions::new().write(true))?,
+            &partial,
+        )?;
+        fsio::rename(&partial, to)?;
+        fsio::remove_file(from)?;
+    }
+    Ok(())
+}
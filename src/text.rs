@@ -0,0 +1,46 @@
+// --normalize-text: text files get the line endings asked for and lose any
+// UTF-8 byte order mark, for source files moving between a Windows server and
+// a Unix client. A file counts as text if it's valid UTF-8 without NUL bytes;
+// anything else is left exactly as it came.
+
+use clap::ValueEnum;
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Newlines {
+    // Unix: \n
+    Lf,
+    // Windows: \r\n
+    Crlf,
+}
+
+pub fn is_text(bytes: &[u8]) -> bool {
+    !bytes.contains(&0) && std::str::from_utf8(bytes).is_ok()
+}
+
+// The file normalized, or None if it's binary. CRLF, lone CR, and LF all
+// count as a line ending.
+pub fn normalize(bytes: &[u8], newlines: Newlines) -> Option<Vec<u8>> {
+    let bytes = bytes.strip_prefix(BOM).unwrap_or(bytes);
+    if !is_text(bytes) {
+        return None;
+    }
+    let ending: &[u8] = match newlines {
+        Newlines::Lf => b"\n",
+        Newlines::Crlf => b"\r\n",
+    };
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut rest = bytes.iter().copied().peekable();
+    while let Some(byte) = rest.next() {
+        match byte {
+            b'\r' => {
+                rest.next_if_eq(&b'\n');
+                out.extend_from_slice(ending);
+            }
+            b'\n' => out.extend_from_slice(ending),
+            _ => out.push(byte),
+        }
+    }
+    Some(out)
+}
@@ -0,0 +1,160 @@
+// --trace-packets: every datagram the session was handed, as text, for when
+// the server and the client don't agree on the wire format. Each one gets a
+// line saying when it came, from where, how long it was and what its status
+// byte means, then what the parser made of it, then a hex dump:
+//
+//   +0.012345s 127.0.0.1:6014 14 bytes, status 0x03 (data, last)
+//     data: file 1, packet 0, last, 10 bytes of payload
+//     0000  03 01 00 00 6f 6e 65 2c  20 74 68 65 6e 20     |....one, then |
+//
+// The parse is the session's, under the protocol version it's speaking, but
+// isn't held to the size limits: a datagram turned away for its length
+// still shows what it would have been. Like --record, it's written before
+// anything (--drop-every, say) can drop a datagram.
+
+use crate::{capability, completion, echo, fsio, packet::PacketView, protocol::Version, wire};
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Result},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+// Bytes to a dump line
+const WIDTH: usize = 16;
+
+pub struct Tracer {
+    out: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+}
+
+impl Tracer {
+    // Start a new trace at `path`, replacing any old one
+    pub fn create(path: &Path, started: Instant) -> Result<Self> {
+        Ok(Tracer {
+            out: BufWriter::new(fsio::create(path)?),
+            path: path.to_path_buf(),
+            started,
+        })
+    }
+
+    pub fn trace(
+        &mut self,
+        now: Instant,
+        from: SocketAddr,
+        datagram: &[u8],
+        version: Version,
+    ) -> Result<()> {
+        let at = now.saturating_duration_since(self.started);
+        let mut text = format!("+{:.6}s {from} ", at.as_secs_f64());
+        text.push_str(&annotate(datagram, version));
+        fsio::write_all(&mut self.out, &self.path, text.as_bytes())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        fsio::at(fsio::Action::Flush, &self.path, || {
+            std::io::Write::flush(&mut self.out)
+        })
+    }
+}
+
+// What a datagram's status byte says, by bit
+pub fn status_bits(status: u8) -> Vec<&'static str> {
+    let mut bits = Vec::new();
+    if wire::is_data(status) {
+        bits.push("data");
+        if wire::is_last(status) {
+            bits.push("last");
+        }
+        if wire::is_wide(status) {
+            bits.push("wide");
+        }
+    } else if wire::is_checksum(status) {
+        bits.push("checksum");
+    } else {
+        bits.push("header");
+        if wire::is_extended(status) {
+            bits.push("extended");
+        }
+        match status & wire::COMPRESSION_BITS {
+            0 => {}
+            wire::GZIP_BITS => bits.push("gzip"),
+            wire::ZSTD_BITS => bits.push("zstd"),
+            _ => bits.push("unknown compression"),
+        }
+    }
+    bits
+}
+
+// Everything after the time and address, lines and all
+pub fn annotate(datagram: &[u8], version: Version) -> String {
+    let mut text = format!("{} bytes", datagram.len());
+    if let Some(&status) = datagram.first() {
+        let read = version.status(status);
+        let bits = status_bits(read).join(", ");
+        let _ = write!(text, ", status {status:#04x} ({bits})");
+    }
+    let _ = writeln!(text, "\n  {}", decode(datagram, version));
+    for (n, line) in datagram.chunks(WIDTH).enumerate() {
+        let _ = writeln!(text, "  {}", dump_line(n * WIDTH, line));
+    }
+    text
+}
+
+fn decode(datagram: &[u8], version: Version) -> String {
+    if let Some(offered) = capability::parse(datagram) {
+        let features = capability::names(offered).join(", ");
+        return format!("capability advertisement: {features}");
+    }
+    if completion::is_end_of_session(datagram) {
+        return "end of session".to_string();
+    }
+    if datagram.starts_with(echo::MAGIC) {
+        return "echo reply".to_string();
+    }
+    match PacketView::parse_as(datagram, version) {
+        Ok(packet) => describe(&packet),
+        Err(e) => format!("unparsed: {e}"),
+    }
+}
+
+// What a parsed packet says, on one line
+pub fn describe(packet: &PacketView) -> String {
+    match *packet {
+        PacketView::Header {
+            file_id, file_name, ..
+        } => format!("header: file {file_id}, name {file_name:?}"),
+        PacketView::Data {
+            file_id,
+            packet_number,
+            is_last_packet,
+            payload,
+        } => {
+            let last = if is_last_packet { ", last" } else { "" };
+            let len = payload.len();
+            format!("data: file {file_id}, packet {packet_number}{last}, {len} bytes of payload")
+        }
+        PacketView::Checksum { file_id, .. } => format!("checksum: file {file_id}"),
+    }
+}
+
+// Offset, the bytes in two groups of eight, and the ones that print
+fn dump_line(offset: usize, bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    for (n, byte) in bytes.iter().enumerate() {
+        if n == WIDTH / 2 {
+            hex.push(' ');
+        }
+        let _ = write!(hex, "{byte:02x} ");
+    }
+    let shown: String = (bytes.iter())
+        .map(|&byte| match byte {
+            0x20..=0x7e => byte as char,
+            _ => '.',
+        })
+        .collect();
+    format!("{offset:04x}  {hex:<w$} |{shown}|", w = WIDTH * 3 + 1)
+}
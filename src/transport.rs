@@ -0,0 +1,679 @@
+// Where the receive loop gets its datagrams and the time from. The session
+// only talks to these traits, so it can be driven by something other than a
+// socket and the system clock: `MemoryTransport` is a channel to a server
+// played by another thread, and `ScriptedTransport` plays back a fixed
+// sequence of datagrams and silences against a `ManualClock`, so a test can
+// run the whole receive loop without a network or a wait.
+
+use crate::{
+    cli::{Cli, PollStrategy},
+    client,
+    error::ClientError,
+    priority::Queue,
+    simulate,
+};
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver as Inbox, RecvTimeoutError, Sender, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// What's done with each datagram as it comes in
+pub type Handler<'a> = dyn FnMut(&[u8], SocketAddr) -> Result<(), ClientError> + 'a;
+
+pub trait Transport {
+    // Wait for the next datagram(s) and pass each protocol packet to
+    // `handle`. Returns false if nothing arrived before the poll interval
+    // was up.
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError>;
+    fn send(&mut self, datagram: &[u8]);
+    // Send to a different server from now on
+    fn connect(&mut self, server: &str) -> io::Result<()>;
+    // Give up waiting in `receive` after this long instead; a transport
+    // that never blocks can ignore it
+    fn set_poll_interval(&mut self, _interval: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+    // Datagrams lost before we could read them, if the transport can tell
+    fn kernel_drops(&self) -> Option<u32> {
+        None
+    }
+    // How the queue between reading and handling datagrams fared, if
+    // there is one
+    fn pipeline_stats(&self) -> Option<PipelineStats> {
+        None
+    }
+    // What a simulated bad network did, if there is one
+    fn simulation_stats(&self) -> Option<simulate::Stats> {
+        None
+    }
+    // Nothing more will ever arrive, so there's no point waiting for it;
+    // only something played back from a file can know
+    fn exhausted(&self) -> bool {
+        false
+    }
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// A clock that only moves when told to (or slept on). Clones share the time,
+// so a transport can move it along with the script it plays.
+#[derive(Clone)]
+pub struct ManualClock(Rc<Cell<Instant>>);
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock(Rc::new(Cell::new(Instant::now())))
+    }
+}
+
+impl ManualClock {
+    pub fn advance(&self, by: Duration) {
+        self.0.set(self.0.get() + by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.0.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+// Where datagrams that aren't from a real socket say they came from
+pub const FAKE_SERVER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 6014);
+
+fn hung_up() -> ClientError {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "the other end is gone").into()
+}
+
+// The client's end of an in-memory link; `MemoryServer` is the other
+pub struct MemoryTransport {
+    inbox: Inbox<Vec<u8>>,
+    outbox: Sender<Vec<u8>>,
+    poll_interval: Option<Duration>,
+    // Every server `connect` has pointed it at
+    pub connected: Vec<String>,
+}
+
+// The server's end: whatever the client sends, and a way to answer
+pub struct MemoryServer {
+    pub requests: Inbox<Vec<u8>>,
+    pub to_client: Sender<Vec<u8>>,
+}
+
+impl MemoryServer {
+    // False once the client has gone
+    pub fn send(&self, datagram: &[u8]) -> bool {
+        self.to_client.send(datagram.to_vec()).is_ok()
+    }
+}
+
+impl MemoryTransport {
+    pub fn pair() -> (MemoryTransport, MemoryServer) {
+        let (to_client, inbox) = mpsc::channel();
+        let (outbox, requests) = mpsc::channel();
+        let transport = MemoryTransport {
+            inbox,
+            outbox,
+            poll_interval: None,
+            connected: Vec::new(),
+        };
+        (
+            transport,
+            MemoryServer {
+                requests,
+                to_client,
+            },
+        )
+    }
+}
+
+impl Transport for MemoryTransport {
+    // One datagram at a time, so there's nothing to put in priority order.
+    // Once the server hangs up and everything it sent is read, that's an
+    // error rather than waiting forever.
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError> {
+        let datagram = match self.poll_interval {
+            Some(interval) => match self.inbox.recv_timeout(interval) {
+                Ok(datagram) => datagram,
+                Err(RecvTimeoutError::Timeout) => return Ok(false),
+                Err(RecvTimeoutError::Disconnected) => return Err(hung_up()),
+            },
+            None => self.inbox.recv().map_err(|_| hung_up())?,
+        };
+        handle(&datagram, FAKE_SERVER).map(|()| true)
+    }
+
+    fn send(&mut self, datagram: &[u8]) {
+        let _ = self.outbox.send(datagram.to_vec());
+    }
+
+    fn connect(&mut self, server: &str) -> io::Result<()> {
+        self.connected.push(server.to_string());
+        Ok(())
+    }
+
+    fn set_poll_interval(&mut self, interval: Option<Duration>) -> io::Result<()> {
+        self.poll_interval = interval;
+        Ok(())
+    }
+}
+
+// One thing that happens in a scripted session
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    Datagram(Vec<u8>),
+    // Nothing arrives, and this much time goes by
+    Silence(Duration),
+    // Nothing more arrives until the client sends something
+    AwaitSend,
+}
+
+// Plays its steps back one per `receive`, moving its clock through the
+// silences. Running out of script is an error, so a session that would wait
+// forever fails instead.
+pub struct ScriptedTransport {
+    steps: VecDeque<Step>,
+    clock: ManualClock,
+    poll_interval: Option<Duration>,
+    // Sent since the last AwaitSend let the script go on
+    unanswered: usize,
+    // Every datagram the client sent, in order
+    pub sent: Vec<Vec<u8>>,
+    pub connected: Vec<String>,
+}
+
+impl ScriptedTransport {
+    pub fn new(steps: impl IntoIterator<Item = Step>, clock: &ManualClock) -> Self {
+        ScriptedTransport {
+            steps: steps.into_iter().collect(),
+            clock: clock.clone(),
+            poll_interval: None,
+            unanswered: 0,
+            sent: Vec::new(),
+            connected: Vec::new(),
+        }
+    }
+
+    // Steps not played yet
+    pub fn remaining(&self) -> usize {
+        self.steps.len()
+    }
+}
+
+impl Transport for ScriptedTransport {
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError> {
+        match self.steps.pop_front() {
+            Some(Step::Datagram(datagram)) => handle(&datagram, FAKE_SERVER).map(|()| true),
+            Some(Step::Silence(duration)) => {
+                self.clock.advance(duration);
+                Ok(false)
+            }
+            Some(Step::AwaitSend) if self.unanswered > 0 => {
+                self.unanswered = 0;
+                self.receive(handle)
+            }
+            // A poll interval's worth of waiting for it
+            Some(Step::AwaitSend) => {
+                self.steps.push_front(Step::AwaitSend);
+                let interval = self.poll_interval.ok_or_else(hung_up)?;
+                self.clock.advance(interval);
+                Ok(false)
+            }
+            None => Err(hung_up()),
+        }
+    }
+
+    fn send(&mut self, datagram: &[u8]) {
+        self.sent.push(datagram.to_vec());
+        self.unanswered += 1;
+    }
+
+    fn connect(&mut self, server: &str) -> io::Result<()> {
+        self.connected.push(server.to_string());
+        Ok(())
+    }
+
+    fn set_poll_interval(&mut self, interval: Option<Duration>) -> io::Result<()> {
+        self.poll_interval = interval;
+        Ok(())
+    }
+}
+
+// The real thing: a UDP socket already connected to (or listening for) the
+// server
+pub struct UdpTransport<'a> {
+    sock: &'a UdpSocket,
+    receiver: Receiver,
+}
+
+impl<'a> UdpTransport<'a> {
+    // Reads give up after `poll_interval` so the session can check its timers
+    pub fn new(
+        sock: &'a UdpSocket,
+        cli: &Cli,
+        poll_interval: Option<Duration>,
+    ) -> io::Result<Self> {
+        sock.set_read_timeout(poll_interval)?;
+        let receiver = Receiver::new(sock, cli)?;
+        Ok(UdpTransport { sock, receiver })
+    }
+}
+
+impl Transport for UdpTransport<'_> {
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError> {
+        self.receiver.receive(self.sock, handle)
+    }
+
+    fn send(&mut self, datagram: &[u8]) {
+        let _ = self.sock.send(datagram);
+    }
+
+    fn connect(&mut self, server: &str) -> io::Result<()> {
+        client::connect(self.sock, server)
+    }
+
+    fn set_poll_interval(&mut self, interval: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(interval)
+    }
+
+    fn kernel_drops(&self) -> Option<u32> {
+        self.receiver.kernel_drops()
+    }
+}
+
+// --pipeline: a thread that only reads the socket, queueing each datagram
+// for the session, which takes them off the queue as its `receive`. A burst
+// that outpaces parsing and storing waits in the queue instead of in (and
+// then out of) the kernel's buffer. Once the queue is full the reader waits
+// for room, and from there on it's the kernel's buffer that fills.
+pub struct PipelineTransport<'a> {
+    sock: &'a UdpSocket,
+    queue: Inbox<Queued>,
+    poll_interval: Option<Duration>,
+    shared: Arc<Pipeline>,
+    reader: Option<JoinHandle<()>>,
+}
+
+// A datagram and its sender, or why the read failed
+type Queued = Result<(Vec<u8>, SocketAddr), ClientError>;
+
+// What the two ends share. Counts go up before a datagram is handed over,
+// so they're never behind what the session has seen.
+#[derive(Default)]
+struct Pipeline {
+    stop: AtomicBool,
+    capacity: usize,
+    // Datagrams in the queue right now, or waiting for room in it
+    depth: AtomicUsize,
+    high_water: AtomicUsize,
+    queued: AtomicU64,
+    full: AtomicU64,
+    kernel_drops: Mutex<Option<u32>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    pub capacity: usize,
+    pub queued: u64,
+    // The most datagrams waiting at once
+    pub high_water: usize,
+    // Times the reader found the queue full and had to wait
+    pub full: u64,
+}
+
+// How long the reader waits on the socket before checking whether it
+// should stop
+const READER_POLL: Duration = Duration::from_millis(50);
+
+// The most queued datagrams handled per `receive`, so the session still
+// checks its timers during a long burst
+const MAX_PER_RECEIVE: usize = 256;
+
+impl<'a> PipelineTransport<'a> {
+    pub fn new(sock: &'a UdpSocket, cli: &Cli, capacity: usize) -> io::Result<Self> {
+        let reader_sock = sock.try_clone()?;
+        reader_sock.set_read_timeout(Some(READER_POLL))?;
+        let (sender, queue) = mpsc::sync_channel(capacity);
+        let shared = Arc::new(Pipeline {
+            capacity,
+            ..Pipeline::default()
+        });
+        let reader = {
+            let shared = shared.clone();
+            let cli = cli.clone();
+            // The receiver is built on the thread it's used on; a batch of
+            // recvmmsg(2) buffers can't be sent between threads
+            thread::Builder::new().name("receive".to_string()).spawn(
+                move || match Receiver::new(&reader_sock, &cli) {
+                    Ok(receiver) => read_into(receiver, &reader_sock, &sender, &shared),
+                    Err(e) => {
+                        let _ = sender.send(Err(e.into()));
+                    }
+                },
+            )?
+        };
+        Ok(PipelineTransport {
+            sock,
+            queue,
+            poll_interval: None,
+            shared,
+            reader: Some(reader),
+        })
+    }
+
+    fn take(&self, handle: &mut Handler, item: Queued) -> Result<(), ClientError> {
+        self.shared.depth.fetch_sub(1, Ordering::Relaxed);
+        let (datagram, from) = item?;
+        handle(&datagram, from)
+    }
+}
+
+// The reader thread's loop: read, queue, and again, until told to stop or
+// the session has gone
+fn read_into(
+    mut receiver: Receiver,
+    sock: &UdpSocket,
+    sender: &SyncSender<Queued>,
+    shared: &Pipeline,
+) {
+    // False once nobody is taking them
+    let enqueue = |item| {
+        let depth = shared.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        shared.high_water.fetch_max(depth, Ordering::Relaxed);
+        shared.queued.fetch_add(1, Ordering::Relaxed);
+        match sender.try_send(item) {
+            Ok(()) => true,
+            Err(TrySendError::Full(item)) => {
+                shared.full.fetch_add(1, Ordering::Relaxed);
+                sender.send(item).is_ok()
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    };
+    while !shared.stop.load(Ordering::Relaxed) {
+        let read = receiver.receive(sock, &mut |datagram, from| {
+            enqueue(Ok((datagram.to_vec(), from)));
+            Ok(())
+        });
+        *shared.kernel_drops.lock().unwrap() = receiver.kernel_drops();
+        // Anything but a closed queue is the session's to deal with
+        if let Err(e) = read {
+            if !enqueue(Err(e)) {
+                break;
+            }
+        }
+    }
+}
+
+impl Transport for PipelineTransport<'_> {
+    fn receive(&mut self, handle: &mut Handler) -> Result<bool, ClientError> {
+        let first = match self.poll_interval {
+            Some(interval) => match self.queue.recv_timeout(interval) {
+                Ok(item) => item,
+                Err(RecvTimeoutError::Timeout) => return Ok(false),
+                Err(RecvTimeoutError::Disconnected) => return Err(hung_up()),
+            },
+            None => self.queue.recv().map_err(|_| hung_up())?,
+        };
+        self.take(handle, first)?;
+        for item in self.queue.try_iter().take(MAX_PER_RECEIVE - 1) {
+            self.take(handle, item)?;
+        }
+        Ok(true)
+    }
+
+    fn send(&mut self, datagram: &[u8]) {
+        let _ = self.sock.send(datagram);
+    }
+
+    fn connect(&mut self, server: &str) -> io::Result<()> {
+        client::connect(self.sock, server)
+    }
+
+    fn set_poll_interval(&mut self, interval: Option<Duration>) -> io::Result<()> {
+        self.poll_interval = interval;
+        Ok(())
+    }
+
+    fn kernel_drops(&self) -> Option<u32> {
+        *self.shared.kernel_drops.lock().unwrap()
+    }
+
+    fn pipeline_stats(&self) -> Option<PipelineStats> {
+        let shared = &self.shared;
+        Some(PipelineStats {
+            capacity: shared.capacity,
+            queued: shared.queued.load(Ordering::Relaxed),
+            // Not counting a datagram the reader had waiting for room
+            high_water: shared
+                .high_water
+                .load(Ordering::Relaxed)
+                .min(shared.capacity),
+            full: shared.full.load(Ordering::Relaxed),
+        })
+    }
+}
+
+impl Drop for PipelineTransport<'_> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        // A reader stuck waiting for room gives up once the queue is gone
+        let (_, closed) = mpsc::sync_channel(0);
+        drop(std::mem::replace(&mut self.queue, closed));
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+// Nothing arrived in time. A signal (Ctrl-C) cutting the read short counts
+// too; the session checks for it next time round.
+pub fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+// How datagrams come off the socket
+struct Receiver {
+    buf: Vec<u8>,
+    // Length, sender, and GRO segment size of what's in `buf`
+    last: Option<(usize, SocketAddr, Option<usize>)>,
+    // Read several datagrams per syscall with recvmmsg(2) instead of `buf`
+    #[cfg(target_os = "linux")]
+    batch: Option<crate::linux::BatchReceiver>,
+    // The kernel hands us several datagrams glued together, plus the size
+    // they were before it did
+    #[cfg(target_os = "linux")]
+    gro: bool,
+    // First and latest values of the kernel's drop counter this session
+    #[cfg(target_os = "linux")]
+    kernel_drops: Option<(u32, u32)>,
+    strategy: PollStrategy,
+    spin_budget: Duration,
+    nonblocking: bool,
+    last_packet: Instant,
+    // The last read's datagrams, control packets first
+    queue: Queue,
+}
+
+impl Receiver {
+    fn new(sock: &UdpSocket, cli: &Cli) -> io::Result<Self> {
+        let mut receiver = Receiver {
+            buf: vec![0; cli.receive_buffer_len()],
+            last: None,
+            #[cfg(target_os = "linux")]
+            batch: None,
+            #[cfg(target_os = "linux")]
+            gro: false,
+            #[cfg(target_os = "linux")]
+            kernel_drops: None,
+            strategy: cli.poll_strategy,
+            spin_budget: cli.spin_budget,
+            nonblocking: false,
+            last_packet: Instant::now(),
+            queue: Queue::default(),
+        };
+
+        #[cfg(target_os = "linux")]
+        {
+            if cli.gro {
+                crate::linux::enable_gro(sock)?;
+                receiver.buf = vec![0; crate::linux::GRO_BUFFER_SIZE];
+                receiver.gro = true;
+            }
+            if cli.recv_batch > 1 {
+                let batch =
+                    crate::linux::BatchReceiver::new(cli.recv_batch as usize, receiver.buf.len());
+                receiver.batch = Some(batch);
+            }
+            // Only used for the report, so carry on without it
+            if let Err(e) = crate::linux::enable_drop_counter(sock) {
+                crate::log::log!(Warn, "{}", crate::i18n::tr!("no-drop-counter", error = e));
+            }
+        }
+
+        // Adaptive polling starts out idle, i.e. blocking
+        receiver.set_nonblocking(sock, cli.poll_strategy == PollStrategy::Busy)?;
+        Ok(receiver)
+    }
+
+    fn set_nonblocking(&mut self, sock: &UdpSocket, nonblocking: bool) -> io::Result<()> {
+        if self.nonblocking != nonblocking {
+            sock.set_nonblocking(nonblocking)?;
+            self.nonblocking = nonblocking;
+        }
+        Ok(())
+    }
+
+    // Datagrams the kernel threw away because we weren't reading fast enough,
+    // if the platform can tell us
+    fn kernel_drops(&self) -> Option<u32> {
+        #[cfg(target_os = "linux")]
+        return Some(self.kernel_drops.map_or(0, |(first, last)| last - first));
+        #[cfg(not(target_os = "linux"))]
+        None
+    }
+
+    #[cfg(target_os = "linux")]
+    fn record_kernel_drops(&mut self, drops: Option<u32>) {
+        if let Some(drops) = drops {
+            let first = self.kernel_drops.map_or(drops, |(first, _)| first);
+            self.kernel_drops = Some((first, drops));
+        }
+    }
+
+    // Read one datagram (or GRO batch), or several when batching, returning
+    // how many there are
+    fn read(&mut self, sock: &UdpSocket) -> io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(batch) = self.batch.as_mut() {
+                let count = batch.recv(sock)?;
+                let drops = (0..count).filter_map(|i| batch.get(i).0.kernel_drops).max();
+                self.record_kernel_drops(drops);
+                return Ok(count);
+            }
+            // recvmsg(2) so we can see the drop counter (and GRO sizes)
+            let received = crate::linux::recv(sock, &mut self.buf)?;
+            self.record_kernel_drops(received.kernel_drops);
+            self.last = Some((received.len, received.from, received.segment_size));
+            Ok(1)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let (len, from) = sock.recv_from(&mut self.buf)?;
+            self.last = Some((len, from, None));
+            Ok(1)
+        }
+    }
+
+    // The `i`th datagram from the last read, with its sender and GRO
+    // segment size
+    fn datagram(&self, i: usize) -> (&[u8], SocketAddr, Option<usize>) {
+        #[cfg(target_os = "linux")]
+        if let Some(batch) = &self.batch {
+            let (received, data) = batch.get(i);
+            return (data, received.from, received.segment_size);
+        }
+        let (len, from, segment_size) = self.last.expect("datagram before read");
+        debug_assert_eq!(i, 0);
+        (&self.buf[..len], from, segment_size)
+    }
+
+    // Returns false if nothing arrived before the read timed out (or, when
+    // polling, right away)
+    fn receive(&mut self, sock: &UdpSocket, handle: &mut Handler) -> Result<bool, ClientError> {
+        // Once packets stop flowing for a while, stop spinning and sleep in
+        // the kernel instead
+        if self.strategy == PollStrategy::Adaptive
+            && self.nonblocking
+            && self.last_packet.elapsed() > self.spin_budget
+        {
+            self.set_nonblocking(sock, false)?;
+        }
+
+        let count = match self.read(sock) {
+            Ok(count) => count,
+            Err(e) if is_timeout(&e) => {
+                if self.nonblocking {
+                    std::hint::spin_loop();
+                }
+                return Ok(false);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        self.last_packet = Instant::now();
+        if self.strategy == PollStrategy::Adaptive {
+            self.set_nonblocking(sock, true)?;
+        }
+
+        let mut queue = std::mem::take(&mut self.queue);
+        for i in 0..count {
+            let (data, _, segment_size) = self.datagram(i);
+            let size = segment_size.unwrap_or(data.len()).max(1);
+            // An empty datagram is still one, if not much of one
+            for start in (0..data.len().max(1)).step_by(size) {
+                let end = data.len().min(start + size);
+                queue.push(i, start..end, &data[start..end]);
+            }
+        }
+        let handled = queue.drain().try_for_each(|(i, range)| {
+            let (data, from, _) = self.datagram(i);
+            handle(&data[range], from)
+        });
+        self.queue = queue;
+        handled.map(|()| true)
+    }
+}
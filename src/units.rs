@@ -0,0 +1,244 @@
+// Human-friendly durations, sizes, and rates for command-line options, e.g.
+// `2m30s`, `1.5GiB`, and `10Mbps`. Each parser has a matching formatter whose
+// output parses back to the same value, so reports can echo what was asked for.
+
+use std::{
+    ops::Range,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Split "1.5GiB" into (1.5, "GiB")
+fn split_number(s: &str) -> Result<(f64, &str), String> {
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(end);
+    let value = number
+        .parse::<f64>()
+        .map_err(|_| format!("expected a number in {s:?}"))?;
+    Ok((value, unit.trim_start()))
+}
+
+// A duration made of one or more `<number><unit>` parts (`90s`, `2m30s`,
+// `1.5h`, `250ms`); a bare number is seconds
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    if let Ok(secs) = s.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).map_err(|e| e.to_string());
+    }
+
+    let mut total = 0.0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let (value, after) = split_number(rest)?;
+        let unit_len = after
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(after.len());
+        let (unit, next) = after.split_at(unit_len);
+        let scale = match unit.trim() {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            "" => return Err(format!("missing unit after {value} in {s:?}")),
+            other => {
+                return Err(format!(
+                    "unknown duration unit {other:?} (use ms, s, m, h, d)"
+                ))
+            }
+        };
+        total += value * scale;
+        rest = next;
+    }
+    Duration::try_from_secs_f64(total).map_err(|e| e.to_string())
+}
+
+// A fraction from 0 to 1, as a number (`0.05`) or a percentage (`5%`)
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let (value, scale) = match s.strip_suffix('%') {
+        Some(percent) => (percent.trim_end(), 100.0),
+        None => (s, 1.0),
+    };
+    let fraction = value
+        .parse::<f64>()
+        .map_err(|_| format!("expected a number in {s:?}"))?
+        / scale;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(format!("{s:?} isn't between 0 and 1 (or 0% and 100%)"));
+    }
+    Ok(fraction)
+}
+
+pub fn format_duration(d: Duration) -> String {
+    let millis = d.as_millis();
+    if millis == 0 {
+        return "0s".to_string();
+    }
+    let mut out = String::new();
+    let mut rest = millis;
+    for (unit, size) in [("h", 3_600_000), ("m", 60_000), ("s", 1000), ("ms", 1)] {
+        if rest >= size {
+            out += &format!("{}{unit}", rest / size);
+            rest %= size;
+        }
+    }
+    out
+}
+
+fn size_multiplier(unit: &str) -> Option<f64> {
+    Some(match unit {
+        "" | "B" => 1.0,
+        "k" | "K" | "KB" | "kB" => 1e3,
+        "M" | "MB" => 1e6,
+        "G" | "GB" => 1e9,
+        "T" | "TB" => 1e12,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    })
+}
+
+// A byte count with optional decimal (`KB`, `MB`) or binary (`KiB`, `MiB`)
+// suffix; a bare number is bytes
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let (value, unit) = split_number(s.trim())?;
+    let multiplier = size_multiplier(unit)
+        .ok_or_else(|| format!("unknown size unit {unit:?} (use B, KB, MB, GB, KiB, MiB, GiB)"))?;
+    Ok((value * multiplier).round() as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    for (unit, size) in [("GiB", 1u64 << 30), ("MiB", 1 << 20), ("KiB", 1 << 10)] {
+        // Only use a bigger unit when it needs at most three decimals
+        if bytes >= size && (bytes as u128 * 1000).is_multiple_of(size as u128) {
+            return format!("{}{unit}", bytes as f64 / size as f64);
+        }
+    }
+    format!("{bytes}B")
+}
+
+// For a number that changes as it's watched, like progress: the largest unit
+// that fits, always to one decimal, so the line doesn't go from `1.5KiB` to
+// `1537B` and back. Doesn't parse back exactly, and isn't meant to.
+pub fn format_size_rounded(bytes: u64) -> String {
+    for (unit, size) in [("GiB", 1u64 << 30), ("MiB", 1 << 20), ("KiB", 1 << 10)] {
+        if bytes >= size {
+            return format!("{:.1}{unit}", bytes as f64 / size as f64);
+        }
+    }
+    format!("{bytes}B")
+}
+
+// A transfer rate in bytes per second. Sizes per second (`500KB/s`, `2MiB/s`)
+// are bytes; `bps`, `kbps`, `Mbps`, `Gbps` are bits. A bare number is bytes
+// per second.
+pub fn parse_rate(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (value, unit) = split_number(s)?;
+    let bits = match unit {
+        "bps" => Some(1.0),
+        "kbps" | "Kbps" => Some(1e3),
+        "Mbps" => Some(1e6),
+        "Gbps" => Some(1e9),
+        _ => None,
+    };
+    if let Some(multiplier) = bits {
+        return Ok((value * multiplier / 8.0).round() as u64);
+    }
+    let size_unit = unit.strip_suffix("/s").unwrap_or(unit);
+    parse_size(&format!("{value}{size_unit}"))
+        .map_err(|_| format!("unknown rate unit {unit:?} (use B/s, KB/s, MiB/s, kbps, Mbps, ...)"))
+}
+
+pub fn format_rate(bytes_per_sec: u64) -> String {
+    format!("{}/s", format_size(bytes_per_sec))
+}
+
+// Compact "0-4, 7, 9-12" form of a sorted list of packet numbers, of the
+// first `most` ranges and then "..." if there are more
+pub fn format_ranges(numbers: &[u32], most: usize) -> String {
+    let mut ranges: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < numbers.len() {
+        if ranges.len() == most {
+            ranges.push("...".to_string());
+            break;
+        }
+        let start = numbers[i];
+        let mut end = start;
+        while i + 1 < numbers.len() && numbers[i + 1] == end + 1 {
+            end += 1;
+            i += 1;
+        }
+        ranges.push(if start == end {
+            start.to_string()
+        } else {
+            format!("{start}-{end}")
+        });
+        i += 1;
+    }
+    ranges.join(", ")
+}
+
+// The same for numbers already in ranges, as FileManager::missing_packets
+// gives them
+pub fn format_spans(ranges: &[Range<u32>], most: usize) -> String {
+    let mut spans: Vec<String> = (ranges.iter().take(most))
+        .map(|range| match range.end - 1 {
+            last if last == range.start => last.to_string(),
+            last => format!("{}-{last}", range.start),
+        })
+        .collect();
+    if ranges.len() > most {
+        spans.push("...".to_string());
+    }
+    spans.join(", ")
+}
+
+// How many numbers `ranges` take in
+pub fn count(ranges: &[Range<u32>]) -> u64 {
+    ranges
+        .iter()
+        .map(|range| u64::from(range.end - range.start))
+        .sum()
+}
+
+// A wall-clock time as UTC RFC 3339, e.g. `2025-03-01T14:05:09.250Z`. Output
+// only: nothing should be timed with these, since the clock can jump.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Days since the epoch to a proleptic Gregorian date, counting in 400
+    // year eras that start on March 1st so leap days come last
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
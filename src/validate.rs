@@ -0,0 +1,464 @@
+use crate::{
+    assembly::Backend,
+    cli::{Cli, Command, PollStrategy, TransportKind},
+    i18n::{self, tr},
+    limits, units, wire,
+};
+use std::fmt;
+
+// Problems with the options found before anything touches the network, each
+// pointing at the options involved and, where we can, how to fix it
+pub struct Diagnostic {
+    severity: Severity,
+    options: &'static [&'static str],
+    message: String,
+    hint: Option<String>,
+}
+
+#[derive(PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+impl Diagnostic {
+    fn error(options: &'static [&'static str], message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            options,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(options: &'static [&'static str], message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(options, message)
+        }
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Error => i18n::text("label-error"),
+            Severity::Warning => i18n::text("label-warning"),
+        };
+        write!(f, "{label}: {}", self.message)?;
+        write!(f, "\n  --> {}", self.options.join(", "))?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n  {}: {hint}", i18n::text("label-help"))?;
+        }
+        Ok(())
+    }
+}
+
+// Check the options for values that can't work or combinations that conflict
+pub fn validate(cli: &Cli) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if cli.watchdog_window.is_some_and(|window| window.is_zero()) {
+        diagnostics.push(
+            Diagnostic::error(&["--watchdog-window"], i18n::text("watchdog-window-empty"))
+                .hint(i18n::text("watchdog-window-hint")),
+        );
+    }
+
+    if cli.session_timeout.is_some_and(|timeout| timeout.is_zero()) {
+        diagnostics.push(Diagnostic::error(
+            &["--session-timeout"],
+            i18n::text("session-timeout-zero"),
+        ));
+    }
+
+    if cli.deadline.is_some_and(|deadline| deadline.is_zero()) {
+        diagnostics.push(Diagnostic::error(
+            &["--deadline"],
+            i18n::text("deadline-zero"),
+        ));
+    }
+
+    if let Some((min_runtime, timeout)) = cli
+        .min_runtime
+        .zip(cli.session_timeout)
+        .filter(|(min_runtime, timeout)| min_runtime >= timeout)
+    {
+        diagnostics.push(Diagnostic::error(
+            &["--min-runtime", "--session-timeout"],
+            tr!(
+                "min-runtime-past-timeout",
+                min_runtime = units::format_duration(min_runtime),
+                timeout = units::format_duration(timeout)
+            ),
+        ));
+    }
+
+    for (option, timeout) in [
+        (&["--connect-timeout"], cli.connect_timeout),
+        (&["--first-packet-timeout"], cli.first_packet_timeout),
+        (&["--stall-timeout"], cli.stall_timeout),
+        (&["--idle-after"], cli.idle_after),
+    ] {
+        if timeout.is_some_and(|timeout| timeout.is_zero()) {
+            diagnostics.push(Diagnostic::error(
+                option,
+                tr!("timeout-zero", option = option[0]),
+            ));
+        }
+    }
+
+    if cli.listen_only && (cli.failover || cli.server.len() > 1) {
+        diagnostics.push(Diagnostic::warning(
+            &["--server", "--listen-only"],
+            i18n::text("servers-listen-only"),
+        ));
+    } else if cli.failover && cli.server.len() < 2 {
+        diagnostics.push(Diagnostic::warning(
+            &["--failover", "--server"],
+            i18n::text("failover-one-server"),
+        ));
+    } else if !cli.failover && !cli.run_async && !cli.concurrent && cli.server.len() > 1 {
+        diagnostics.push(
+            Diagnostic::warning(&["--server"], i18n::text("servers-without-failover"))
+                .hint(i18n::text("servers-without-failover-hint")),
+        );
+    }
+
+    if cli.connect_timeout.is_some() && cli.listen_only {
+        diagnostics.push(
+            Diagnostic::warning(
+                &["--connect-timeout", "--listen-only"],
+                i18n::text("connect-timeout-listen-only"),
+            )
+            .hint(i18n::text("connect-timeout-listen-only-hint")),
+        );
+    }
+
+    if cli
+        .snapshot_interval
+        .is_some_and(|interval| interval.is_zero())
+    {
+        diagnostics.push(Diagnostic::error(
+            &["--snapshot-interval"],
+            i18n::text("snapshot-interval-zero"),
+        ));
+    }
+
+    if cli.nack_after.is_some_and(|after| after.is_zero()) {
+        diagnostics.push(Diagnostic::error(
+            &["--nack-after"],
+            i18n::text("nack-after-zero"),
+        ));
+    } else if cli.nack_after.is_some() && cli.listen_only {
+        diagnostics.push(Diagnostic::warning(
+            &["--nack-after", "--listen-only"],
+            i18n::text("nack-listen-only"),
+        ));
+    }
+
+    if cli.hello_interval.is_zero() {
+        diagnostics.push(Diagnostic::error(
+            &["--hello-interval"],
+            i18n::text("hello-interval-zero"),
+        ));
+    }
+
+    if cli.ack_interval.is_some_and(|interval| interval.is_zero()) {
+        diagnostics.push(Diagnostic::error(
+            &["--ack-interval"],
+            i18n::text("ack-interval-zero"),
+        ));
+    } else if cli.ack_interval.is_some() && cli.listen_only {
+        diagnostics.push(Diagnostic::warning(
+            &["--ack-interval", "--listen-only"],
+            i18n::text("ack-listen-only"),
+        ));
+    }
+
+    let packet = wire::MAX_PACKET as u64;
+    if let Some(max) = cli.max_total_bytes.filter(|&max| max < packet) {
+        diagnostics.push(
+            Diagnostic::error(
+                &["--max-total-bytes"],
+                tr!("max-total-bytes-small", size = units::format_size(max)),
+            )
+            .hint(tr!(
+                "max-total-bytes-hint",
+                size = units::format_size(packet)
+            )),
+        );
+    }
+
+    let longest = wire::max_datagram(cli.crc);
+    if let Some(max) = (cli.max_packet_size).filter(|&max| usize::from(max) < longest) {
+        diagnostics.push(
+            Diagnostic::error(
+                &["--max-packet-size"],
+                tr!("max-packet-size-small", size = max),
+            )
+            .hint(tr!("max-packet-size-hint", size = longest)),
+        );
+    }
+
+    match cli.watchdog_min_rate {
+        Some(0) => diagnostics.push(
+            Diagnostic::warning(&["--watchdog-min-rate"], i18n::text("min-rate-zero"))
+                .hint(i18n::text("min-rate-zero-hint")),
+        ),
+        Some(_) if cli.listen_only => diagnostics.push(Diagnostic::warning(
+            &["--watchdog-min-rate", "--listen-only"],
+            i18n::text("min-rate-listen-only"),
+        )),
+        Some(_) => {}
+        None if cli.watchdog_window.is_some() => diagnostics.push(
+            Diagnostic::warning(
+                &["--watchdog-window", "--watchdog-min-rate"],
+                i18n::text("window-without-rate"),
+            )
+            .hint(i18n::text("window-without-rate-hint")),
+        ),
+        None => {}
+    }
+
+    if cli.run_async && !cfg!(feature = "tokio") {
+        diagnostics.push(
+            Diagnostic::error(&["--async"], i18n::text("async-needs-tokio"))
+                .hint(i18n::text("async-needs-tokio-hint")),
+        );
+    }
+    if cli.tui && !cfg!(feature = "tui") {
+        diagnostics.push(
+            Diagnostic::error(&["--tui"], i18n::text("tui-not-built"))
+                .hint(i18n::text("tui-not-built-hint")),
+        );
+    }
+    let grade = matches!(cli.command, Some(Command::Grade { .. }));
+    for (on, options) in [
+        (cli.run_async && cli.failover, &["--async", "--failover"]),
+        (
+            cli.run_async && cli.listen_only,
+            &["--async", "--listen-only"],
+        ),
+        (cli.run_async && grade, &["--async", "grade"]),
+        (
+            cli.concurrent && cli.failover,
+            &["--concurrent", "--failover"],
+        ),
+        (
+            cli.concurrent && cli.listen_only,
+            &["--concurrent", "--listen-only"],
+        ),
+        (cli.concurrent && grade, &["--concurrent", "grade"]),
+        (
+            cli.concurrent && cli.run_async,
+            &["--concurrent", "--async"],
+        ),
+    ] {
+        if on {
+            let message = tr!(
+                "all-at-once-conflicts",
+                flag = options[0],
+                option = options[1]
+            );
+            diagnostics.push(Diagnostic::error(options, message));
+        }
+    }
+
+    if cli.transport == TransportKind::Tcp {
+        for (on, options) in [
+            (cli.listen_only, &["--transport", "--listen-only"]),
+            (cli.concurrent, &["--transport", "--concurrent"]),
+            (cli.run_async, &["--transport", "--async"]),
+            (cli.pipeline.is_some(), &["--transport", "--pipeline"]),
+            (cli.recv_batch > 1, &["--transport", "--recv-batch"]),
+            (cli.gro, &["--transport", "--gro"]),
+        ] {
+            if on {
+                let message = tr!("tcp-conflicts", option = options[1]);
+                diagnostics.push(Diagnostic::error(options, message));
+            }
+        }
+    }
+
+    if cli.concurrent && cli.pipeline.is_some() {
+        diagnostics.push(Diagnostic::warning(
+            &["--concurrent", "--pipeline"],
+            i18n::text("concurrent-pipeline"),
+        ));
+    }
+    if cli.run_async && cli.pipeline.is_some() {
+        diagnostics.push(Diagnostic::warning(
+            &["--async", "--pipeline"],
+            i18n::text("async-pipeline"),
+        ));
+    }
+
+    if cli.gro && !cfg!(target_os = "linux") {
+        diagnostics.push(
+            Diagnostic::error(&["--gro"], i18n::text("gro-linux-only"))
+                .hint(i18n::text("gro-linux-only-hint")),
+        );
+    }
+
+    if (cli.chown.is_some() || cli.umask.is_some()) && !cfg!(target_os = "linux") {
+        diagnostics.push(Diagnostic::error(
+            &["--chown", "--umask"],
+            i18n::text("ownership-linux-only"),
+        ));
+    }
+
+    // Only root can give a file to someone else
+    #[cfg(target_os = "linux")]
+    if let Some(uid) = cli.chown.and_then(|owner| owner.uid) {
+        if crate::linux::euid() != 0 && crate::linux::euid() != uid {
+            diagnostics.push(
+                Diagnostic::warning(&["--chown"], i18n::text("chown-not-root"))
+                    .hint(i18n::text("chown-not-root-hint")),
+            );
+        }
+    }
+
+    if cli.recv_batch > 1 && !cfg!(target_os = "linux") {
+        diagnostics.push(
+            Diagnostic::error(&["--recv-batch"], i18n::text("recv-batch-linux-only"))
+                .hint(i18n::text("recv-batch-linux-only-hint")),
+        );
+    }
+
+    if (cli.cpu.is_some() || cli.priority.is_some()) && !cfg!(target_os = "linux") {
+        diagnostics.push(Diagnostic::warning(
+            &["--cpu", "--priority"],
+            i18n::text("tuning-linux-only"),
+        ));
+    }
+
+    if cli.psk.is_some() && !cfg!(feature = "crypto") {
+        diagnostics.push(Diagnostic::error(&["--psk"], i18n::text("psk-not-built")));
+    }
+
+    if cli.poll_strategy == PollStrategy::Busy && cli.cpu.is_none() {
+        diagnostics.push(
+            Diagnostic::warning(&["--poll-strategy"], i18n::text("busy-polling"))
+                .hint(i18n::text("busy-polling-hint")),
+        );
+    }
+
+    for (flag, path) in [
+        (&["--progress-file"], &cli.progress_file),
+        (&["--journal"], &cli.journal),
+        (&["--record"], &cli.record),
+        (&["--trace-packets"], &cli.trace_packets),
+        (&["--report-csv"], &cli.report_csv),
+    ] {
+        let Some(path) = path else { continue };
+        if path.is_dir() {
+            diagnostics.push(Diagnostic::error(
+                flag,
+                tr!("is-a-directory", path = path.display()),
+            ));
+        } else if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            if !parent.is_dir() {
+                diagnostics.push(
+                    Diagnostic::error(flag, tr!("does-not-exist", path = parent.display()))
+                        .hint(i18n::text("create-directory-hint")),
+                );
+            }
+        }
+    }
+
+    if let Some(dir) = cli
+        .resume
+        .as_deref()
+        .filter(|dir| dir.exists() && !dir.is_dir())
+    {
+        diagnostics.push(Diagnostic::error(
+            &["--resume"],
+            tr!("not-a-directory", path = dir.display()),
+        ));
+    }
+    if cli.output_dir.is_some() && matches!(cli.command, Some(Command::Grade { .. })) {
+        diagnostics.push(Diagnostic::warning(
+            &["--output-dir"],
+            i18n::text("output-dir-grade"),
+        ));
+    }
+    if let Some(dir) = cli
+        .output_dir
+        .as_deref()
+        .filter(|dir| dir.exists() && !dir.is_dir())
+    {
+        diagnostics.push(Diagnostic::error(
+            &["--output-dir"],
+            tr!("not-a-directory", path = dir.display()),
+        ));
+    }
+    if cli.resume.is_some() && matches!(cli.command, Some(Command::Grade { .. })) {
+        diagnostics.push(Diagnostic::error(&["--resume"], i18n::text("resume-grade")));
+    }
+    if cli.limit_rate == Some(0) {
+        diagnostics.push(Diagnostic::error(
+            &["--limit-rate"],
+            i18n::text("limit-rate-zero"),
+        ));
+    }
+    // Held to what a header's name would be, with the longest ID in it
+    if limits::check_name(&cli.headerless_name(u8::MAX), cli.max_name_len).is_err() {
+        diagnostics.push(Diagnostic::error(
+            &["--headerless-name"],
+            tr!(
+                "headerless-name-bad",
+                name = format!("{:?}", cli.headerless_name)
+            ),
+        ));
+    }
+    if cli.write_threads.is_some() && matches!(cli.command, Some(Command::Grade { .. })) {
+        diagnostics.push(Diagnostic::error(
+            &["--write-threads"],
+            i18n::text("write-threads-grade"),
+        ));
+    }
+
+    if cli.crc_workers > 0 && !cli.crc {
+        diagnostics.push(
+            Diagnostic::warning(
+                &["--crc-workers", "--crc"],
+                i18n::text("crc-workers-without-crc"),
+            )
+            .hint(i18n::text("crc-workers-without-crc-hint")),
+        );
+    }
+
+    if cli.spill_dir.is_some() && cli.assembly_backend() != Backend::Spill {
+        diagnostics.push(
+            Diagnostic::warning(
+                &["--spill-dir", "--assembly-backend"],
+                i18n::text("spill-dir-unused"),
+            )
+            .hint(i18n::text("spill-dir-unused-hint")),
+        );
+    }
+
+    for (flag, dir) in [
+        (&["--spill-dir"], &cli.spill_dir),
+        (&["--temp-dir"], &cli.temp_dir),
+    ] {
+        if let Some(dir) = dir.as_ref().filter(|dir| !dir.is_dir()) {
+            diagnostics.push(
+                Diagnostic::error(flag, tr!("not-a-directory", path = dir.display()))
+                    .hint(i18n::text("create-directory-hint")),
+            );
+        }
+    }
+
+    diagnostics
+}
@@ -0,0 +1,163 @@
+// Per-packet CRC checks (--crc). With the extension on, the last 4 bytes of
+// every data packet are a big-endian CRC-32 of the payload before them.
+// Checking can happen on the receive thread or on a pool of workers
+// (--crc-workers); either way packets come back out in the order they went
+// in, so the rest of the session can't tell the difference.
+
+use crate::{checksum::crc32, packet::Packet, wire};
+use std::{
+    collections::BTreeMap,
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// How many packets may be waiting on the workers before the receive thread
+// waits for them to catch up
+const MAX_IN_FLIGHT: u64 = 256;
+
+// Strip and check a data packet's CRC, or None if it doesn't match
+fn check(packet: Packet) -> Option<Packet> {
+    let Packet::Data(mut data) = packet else {
+        return Some(packet); // Headers don't carry one
+    };
+    let split = data.data.len().checked_sub(wire::CRC_LEN)?;
+    let trailer = u32::from_be_bytes(data.data[split..].try_into().expect("CRC_LEN bytes"));
+    if crc32(&data.data[..split]) != trailer {
+        return None;
+    }
+    data.data.truncate(split);
+    Some(Packet::Data(data))
+}
+
+struct Job {
+    seq: u64,
+    packet: Packet,
+    len: usize,
+    submitted: Instant,
+}
+
+// A packet that has been through the check
+pub struct Verified {
+    pub packet: Option<Packet>, // None if the CRC was wrong
+    pub len: usize,             // datagram length, for events and stats
+    pub latency: Duration,      // from submission to the result coming back
+}
+
+fn run_job(job: Job) -> (u64, Verified) {
+    let packet = check(job.packet);
+    (
+        job.seq,
+        Verified {
+            packet,
+            len: job.len,
+            latency: job.submitted.elapsed(),
+        },
+    )
+}
+
+pub struct Verifier {
+    jobs: Option<mpsc::Sender<Job>>, // None when checking inline
+    results: mpsc::Receiver<(u64, Verified)>,
+    workers: Vec<JoinHandle<()>>,
+    next_seq: u64,
+    // Results that came back ahead of an earlier packet
+    reorder: BTreeMap<u64, Verified>,
+    next_release: u64,
+}
+
+impl Verifier {
+    // Zero workers checks each packet on the calling thread as it's submitted
+    pub fn new(workers: usize) -> Self {
+        let (results_tx, results) = mpsc::channel();
+        let mut verifier = Verifier {
+            jobs: None,
+            results,
+            workers: Vec::new(),
+            next_seq: 0,
+            reorder: BTreeMap::new(),
+            next_release: 0,
+        };
+        if workers > 0 {
+            let (jobs_tx, jobs) = mpsc::channel::<Job>();
+            let jobs = Arc::new(Mutex::new(jobs));
+            for _ in 0..workers {
+                let jobs = Arc::clone(&jobs);
+                let results = results_tx.clone();
+                verifier.workers.push(thread::spawn(move || loop {
+                    let job = jobs.lock().unwrap().recv();
+                    let Ok(job) = job else {
+                        break; // The verifier is gone
+                    };
+                    if results.send(run_job(job)).is_err() {
+                        break;
+                    }
+                }));
+            }
+            verifier.jobs = Some(jobs_tx);
+        }
+        verifier
+    }
+
+    fn in_flight(&self) -> u64 {
+        self.next_seq - self.next_release - self.reorder.len() as u64
+    }
+
+    // Collect finished results, waiting for one if `wait` is set
+    fn collect(&mut self, wait: bool) {
+        if wait {
+            if let Ok((seq, verified)) = self.results.recv() {
+                self.reorder.insert(seq, verified);
+            }
+        }
+        while let Ok((seq, verified)) = self.results.try_recv() {
+            self.reorder.insert(seq, verified);
+        }
+    }
+
+    pub fn submit(&mut self, packet: Packet, len: usize) {
+        let job = Job {
+            seq: self.next_seq,
+            packet,
+            len,
+            submitted: Instant::now(),
+        };
+        self.next_seq += 1;
+        match &self.jobs {
+            Some(jobs) => {
+                let _ = jobs.send(job);
+                while self.in_flight() > MAX_IN_FLIGHT {
+                    self.collect(true);
+                }
+            }
+            None => {
+                let (seq, verified) = run_job(job);
+                self.reorder.insert(seq, verified);
+            }
+        }
+    }
+
+    // Checked packets in submission order. With `wait`, every packet
+    // submitted so far is waited for; otherwise only what's ready comes back.
+    pub fn release(&mut self, wait: bool) -> Vec<Verified> {
+        self.collect(false);
+        while wait && self.in_flight() > 0 {
+            self.collect(true);
+        }
+        let mut released = Vec::new();
+        while let Some(verified) = self.reorder.remove(&self.next_release) {
+            released.push(verified);
+            self.next_release += 1;
+        }
+        released
+    }
+}
+
+impl Drop for Verifier {
+    fn drop(&mut self) {
+        self.jobs = None; // Hang up so the workers stop
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
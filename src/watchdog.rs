@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+// Watches the receive rate over fixed windows and fires when a whole window
+// comes in below the configured floor. A server that has quietly lost track
+// of us usually trickles (or stops) rather than failing loudly, so this catches
+// slow-downs as well as complete stalls.
+pub struct Watchdog {
+    min_rate: u64, // bytes per second
+    window: Duration,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl Watchdog {
+    pub fn new(min_rate: u64, window: Duration, now: Instant) -> Self {
+        Watchdog {
+            min_rate,
+            window,
+            window_start: now,
+            window_bytes: 0,
+        }
+    }
+
+    // Count bytes received in the current window
+    pub fn record(&mut self, bytes: usize) {
+        self.window_bytes += bytes as u64;
+    }
+
+    // How long the receive loop may block before it should check in again
+    pub fn poll_interval(&self) -> Duration {
+        (self.window / 4).max(Duration::from_millis(50))
+    }
+
+    // Once a window has elapsed, returns the rate seen over it if that rate
+    // was below the floor. Either way a new window starts.
+    pub fn check(&mut self, now: Instant) -> Option<u64> {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < self.window {
+            return None;
+        }
+
+        let rate = (self.window_bytes as f64 / elapsed.as_secs_f64()) as u64;
+        self.window_start = now;
+        self.window_bytes = 0;
+        (rate < self.min_rate).then_some(rate)
+    }
+}
@@ -0,0 +1,179 @@
+// The wire format, in one place. Every datagram starts with a status byte and
+// a file ID:
+//
+//   header:   status (even) | file ID | file name (UTF-8, the rest)
+//   extended: status (even, bit 3 set) | file ID | fields | size (u64) |
+//             modified (i64, seconds since 1970) | mode (u32) | file name
+//   data:     status (odd)  | file ID | packet number (u16, big-endian) | payload
+//   wide:     status (odd, bit 3 set) | file ID | packet number (u32) | payload
+//   checksum: status (even, bit 2 set) | file ID | SHA-256 of the whole file
+//
+// A data packet with bit 1 of its status set too is the last one of its file.
+// Checksum packets are an extension the course server doesn't send; a file
+// that gets one is checked against it once written. Wide data packets
+// (protocol v2) are another, for files past the 65,536 packets (64 MiB) a
+// 16-bit number can count; a server can mix them with plain ones, and one
+// that never sets bit 3 gets what it always did. Extended headers, also v2,
+// say up front how big the file is, when it was last changed, and its Unix
+// permissions; a bit of `fields` says which of them are given, and the
+// others are zero. An empty file is one last data packet with no payload,
+// or an extended header saying its size is 0 and no data packets at all.
+// Bits 4 and 5 of a header's status say its file's data is one compressed
+// stream (01 gzip, 10 zstd; see compression.rs), which a server only sends a
+// client that took up the compression capability. Higher status bits mean
+// nothing (yet) and are ignored.
+
+// Offsets into a datagram
+pub const STATUS: usize = 0;
+pub const FILE_ID: usize = 1;
+pub const FILE_NAME: usize = 2;
+pub const PACKET_NUMBER: usize = 2;
+pub const PAYLOAD: usize = 4;
+// Where a wide data packet's payload starts instead
+pub const WIDE_PAYLOAD: usize = 6;
+
+// Anything shorter can't be a packet at all, or a data packet
+pub const MIN_PACKET_LEN: usize = FILE_NAME;
+pub const MIN_DATA_LEN: usize = PAYLOAD;
+pub const MIN_WIDE_DATA_LEN: usize = WIDE_PAYLOAD;
+
+// Where an extended header's fields start, and its file name
+pub const METADATA: usize = 2;
+pub const EXTENDED_FILE_NAME: usize = METADATA + 1 + 8 + 8 + 4;
+pub const MIN_EXTENDED_LEN: usize = EXTENDED_FILE_NAME;
+
+// A checksum packet is exactly this long
+pub const DIGEST_LEN: usize = 32;
+pub const CHECKSUM_PACKET_LEN: usize = FILE_NAME + DIGEST_LEN;
+
+// Every data packet but a file's last carries exactly this much
+pub const MAX_PAYLOAD: usize = 1024;
+
+// The longest packet there is, a full data packet, and so also the longest
+// datagram the client sends
+pub const MAX_PACKET: usize = PAYLOAD + MAX_PAYLOAD;
+const _: () = assert!(PAYLOAD == PACKET_NUMBER + 2 && MAX_PACKET == 1028);
+pub const MAX_WIDE_PACKET: usize = WIDE_PAYLOAD + MAX_PAYLOAD;
+const _: () = assert!(WIDE_PAYLOAD == PACKET_NUMBER + 4);
+
+// Status bits
+pub const DATA_BIT: u8 = 0b01;
+pub const LAST_BIT: u8 = 0b10;
+pub const CHECKSUM_BIT: u8 = 0b100;
+pub const WIDE_BIT: u8 = 0b1000;
+// The same bit on a header
+pub const EXTENDED_BIT: u8 = WIDE_BIT;
+
+// How a header's file is compressed, if it is
+pub const COMPRESSION_BITS: u8 = 0b11_0000;
+pub const GZIP_BITS: u8 = 0b01_0000;
+pub const ZSTD_BITS: u8 = 0b10_0000;
+
+// An extended header's fields
+pub const SIZE_FIELD: u8 = 0b001;
+pub const MODIFIED_FIELD: u8 = 0b010;
+pub const MODE_FIELD: u8 = 0b100;
+
+// Status bytes we send (the hello) or build for benchmarks
+pub const HEADER_STATUS: u8 = 0;
+pub const DATA_STATUS: u8 = DATA_BIT;
+pub const LAST_DATA_STATUS: u8 = DATA_BIT | LAST_BIT;
+pub const CHECKSUM_STATUS: u8 = CHECKSUM_BIT;
+
+// With the CRC extension (--crc), data packets end in a big-endian CRC-32 of
+// their payload
+pub const CRC_LEN: usize = 4;
+
+// The longest datagram a session takes, a full wide data packet. Receive
+// buffers are a byte longer, so a longer one still shows up as too long
+// instead of being cut down to something that passes for a packet.
+pub const fn max_datagram(crc: bool) -> usize {
+    if crc {
+        MAX_WIDE_PACKET + CRC_LEN
+    } else {
+        MAX_WIDE_PACKET
+    }
+}
+
+// The longest a datagram starting with `status` can be: anything but a wide
+// data packet has two bytes less room
+pub fn max_len(status: u8, crc: bool) -> usize {
+    if is_wide(status) {
+        max_datagram(crc)
+    } else {
+        max_datagram(crc) - (MAX_WIDE_PACKET - MAX_PACKET)
+    }
+}
+
+pub const fn receive_buffer_len(crc: bool) -> usize {
+    max_datagram(crc) + 1
+}
+
+pub fn is_data(status: u8) -> bool {
+    status & DATA_BIT != 0
+}
+
+pub fn is_last(status: u8) -> bool {
+    is_data(status) && status & LAST_BIT != 0
+}
+
+pub fn is_wide(status: u8) -> bool {
+    is_data(status) && status & WIDE_BIT != 0
+}
+
+// Where the payload starts, and so how long a data packet has to be at least
+pub fn payload_offset(status: u8) -> usize {
+    if is_wide(status) {
+        WIDE_PAYLOAD
+    } else {
+        PAYLOAD
+    }
+}
+
+pub fn is_checksum(status: u8) -> bool {
+    !is_data(status) && status & CHECKSUM_BIT != 0
+}
+
+pub fn is_extended(status: u8) -> bool {
+    !is_data(status) && !is_checksum(status) && status & EXTENDED_BIT != 0
+}
+
+pub fn data_status(is_last: bool) -> u8 {
+    if is_last {
+        LAST_DATA_STATUS
+    } else {
+        DATA_STATUS
+    }
+}
+
+// Some servers send empty data packets, not marked last, just to show they're
+// still there. Those can't be part of a file: every packet but the last is
+// full.
+pub fn is_keepalive(status: u8, payload_len: usize) -> bool {
+    is_data(status) && !is_last(status) && payload_len == 0
+}
+
+// The packet number of a data packet at least `payload_offset` long
+pub fn packet_number(bytes: &[u8]) -> u32 {
+    packet_number_as(bytes, bytes[STATUS])
+}
+
+// The same, reading the packet as if its status byte were `status`
+pub fn packet_number_as(bytes: &[u8], status: u8) -> u32 {
+    let number = &bytes[PACKET_NUMBER..payload_offset(status)];
+    number
+        .iter()
+        .fold(0, |number, &byte| number << 8 | u32::from(byte))
+}
+
+// The first PAYLOAD bytes of a data packet
+pub fn data_prefix(file_id: u8, packet_number: u16, is_last: bool) -> [u8; PAYLOAD] {
+    let [high, low] = packet_number.to_be_bytes();
+    [data_status(is_last), file_id, high, low]
+}
+
+// The first WIDE_PAYLOAD bytes of a wide data packet
+pub fn wide_data_prefix(file_id: u8, packet_number: u32, is_last: bool) -> [u8; WIDE_PAYLOAD] {
+    let [a, b, c, d] = packet_number.to_be_bytes();
+    [data_status(is_last) | WIDE_BIT, file_id, a, b, c, d]
+}
@@ -0,0 +1,100 @@
+// --write-threads: files written as soon as they're complete, rather than
+// all of them once the session is over. The file manager hands each one
+// over, payloads and all, as a file manager of its own, and a few threads
+// write them into the output directory the way write_all_files would
+// (--on-conflict, --chown and checksums included). What became of them is
+// picked up when the session's files are written.
+
+use crate::{
+    file_manager::{FileManager, Written, WRITE_ATTEMPTS},
+    fsio,
+};
+use std::{
+    io,
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+// One file to write, alone in its file manager
+struct Job {
+    file_id: u8,
+    files: FileManager,
+}
+
+type Outcome = (u8, io::Result<Option<Written>>);
+
+pub struct Writers {
+    // None once the threads have been told there's nothing more
+    jobs: Option<Sender<Job>>,
+    done: Receiver<Outcome>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Writers {
+    // `threads` threads writing into `dir`
+    pub fn new(threads: usize, dir: &Path) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (finished, done) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(queue));
+        let threads = (0..threads.max(1))
+            .map(|n| {
+                let (queue, finished, dir) = (Arc::clone(&queue), finished.clone(), dir.to_owned());
+                let work = move || loop {
+                    // The lock is let go of before the file is written
+                    let next = queue.lock().expect("writer queue poisoned").recv();
+                    let Ok(Job { file_id, mut files }) = next else {
+                        return;
+                    };
+                    let written =
+                        fsio::retry_transient(WRITE_ATTEMPTS, || files.write_file(&dir, file_id));
+                    // One that failed keeps its scratch directory, and says so
+                    if written.is_ok() {
+                        let _ = files.clean_up();
+                    }
+                    let _ = finished.send((file_id, written));
+                };
+                (thread::Builder::new()
+                    .name(format!("write-{n}"))
+                    .spawn(work))
+                .expect("can't start a writer thread")
+            })
+            .collect();
+        Writers {
+            jobs: Some(jobs),
+            done,
+            threads,
+        }
+    }
+
+    pub fn write(&self, file_id: u8, files: FileManager) {
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(Job { file_id, files });
+        }
+    }
+
+    // Wait for every file handed over, and say what became of each, in the
+    // order they were done
+    pub fn finish(mut self) -> Vec<Outcome> {
+        self.join();
+        self.done.try_iter().collect()
+    }
+
+    fn join(&mut self) {
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+// A session that ends in an error still has its files finish writing, so
+// nothing is left behind half-written
+impl Drop for Writers {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
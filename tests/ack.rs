@@ -0,0 +1,167 @@
+// Acknowledgements: the bytes on the wire, what they say about a file with
+// holes in it, and a session sending them on its timer, within --limit-rate.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    ack::{self, Entry},
+    cli::Cli,
+    events::Progress,
+    file_manager::FileManager,
+    packet::Packet,
+    ratelimit::Bucket,
+    session::run_session,
+    transport::{ManualClock, ScriptedTransport, Step, Transport},
+    wire,
+};
+use std::{
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from(["segmented-file-system-client"].iter().chain(args)).unwrap()
+}
+
+#[test]
+fn layout() {
+    let entry = Entry {
+        file_id: 7,
+        in_a_row: 0x0203,
+        bitmap: 0b101,
+    };
+    assert_eq!(
+        ack::encode(0x0102030405060708, &[entry]),
+        [
+            b"SFSA\x01\x01\x02\x03\x04\x05\x06\x07\x08\x00\x01\x07\x00\x00\x02\x03\x00\x00\x00\x05"
+                .to_vec()
+        ]
+    );
+}
+
+#[test]
+fn long_lists_are_split_and_round_trip() {
+    let entries: Vec<Entry> = (0..=255)
+        .map(|file_id| Entry {
+            file_id,
+            in_a_row: u32::from(file_id) * 1000,
+            bitmap: u32::MAX >> (file_id % 32),
+        })
+        .collect();
+    let datagrams = ack::encode(9, &entries);
+    assert_eq!(datagrams.len(), 256usize.div_ceil(ack::MAX_ENTRIES));
+    assert!(datagrams.iter().all(|d| d.len() <= wire::MAX_PACKET));
+    let decoded: Vec<Entry> = (datagrams.iter())
+        .flat_map(|d| {
+            let (session_id, entries) = ack::decode(d).unwrap();
+            assert_eq!(session_id, 9);
+            entries
+        })
+        .collect();
+    assert_eq!(decoded, entries);
+    assert_eq!(ack::decode(&datagrams[0][..20]), None);
+}
+
+#[test]
+fn a_file_with_holes_says_where_they_are() {
+    let mut files = FileManager::default();
+    for bytes in [
+        &b"\x01\x01\x00\x00a"[..],
+        b"\x01\x01\x00\x01b",
+        b"\x01\x01\x00\x03d",
+        b"\x01\x01\x00\x06g",
+        b"\x00\x02b.txt",
+        b"\x03\x02\x00\x00two",
+    ] {
+        files.process_packet(Packet::try_from(bytes).unwrap());
+    }
+    assert_eq!(
+        ack::summarize(&files),
+        [
+            // 2 is the first missing; 3 and 6 came past it
+            Entry {
+                file_id: 1,
+                in_a_row: 2,
+                bitmap: 0b1001,
+            },
+            Entry {
+                file_id: 2,
+                in_a_row: 1,
+                bitmap: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn a_session_sends_them_on_its_timer() {
+    let clock = ManualClock::default();
+    let steps = vec![
+        Step::Datagram(b"\x00\x01a.txt".to_vec()),
+        Step::Datagram(b"\x01\x01\x00\x00one, ".to_vec()),
+        Step::Silence(Duration::from_millis(300)),
+        Step::Datagram(b"\x03\x01\x00\x01then more".to_vec()),
+    ];
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    transport
+        .set_poll_interval(Some(Duration::from_millis(50)))
+        .unwrap();
+    let cli = cli(&["--until", "files=1", "--ack-interval", "200ms"]);
+    run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli,
+        None,
+    )
+    .unwrap();
+    let acks: Vec<_> = (transport.sent.iter())
+        .filter_map(|d| ack::decode(d))
+        .collect();
+    assert_eq!(acks.len(), 1);
+    let (_, entries) = &acks[0];
+    assert_eq!(
+        entries,
+        &[Entry {
+            file_id: 1,
+            in_a_row: 1,
+            bitmap: 0,
+        }]
+    );
+
+    // Nothing goes out unless asked for
+    let mut transport = ScriptedTransport::new(
+        vec![
+            Step::Datagram(b"\x00\x01a.txt".to_vec()),
+            Step::Silence(Duration::from_secs(1)),
+            Step::Datagram(b"\x03\x01\x00\x00one".to_vec()),
+        ],
+        &clock,
+    );
+    run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &self::cli(&["--until", "files=1"]),
+        None,
+    )
+    .unwrap();
+    assert!(transport.sent.is_empty());
+}
+
+#[test]
+fn the_rate_limit_holds_them_back_until_theres_room() {
+    let now = Instant::now();
+    // A tenth of a second's worth, but never less than one full datagram
+    let mut bucket = Bucket::new(10_000, now);
+    assert!(bucket.take(wire::MAX_PACKET, now));
+    assert!(!bucket.take(1, now));
+    let later = now + Duration::from_millis(100);
+    assert!(bucket.take(1000, later));
+    assert!(!bucket.take(1, later));
+    // It doesn't save up past its capacity
+    let much_later = later + Duration::from_secs(60);
+    assert!(bucket.take(wire::MAX_PACKET, much_later));
+    assert!(!bucket.take(1, much_later));
+}
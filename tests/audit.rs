@@ -0,0 +1,132 @@
+// The audit log's hash chain: what it's made of, and every way of tampering
+// with it that verify-audit should catch.
+
+use segmented_file_system_client::{
+    audit::{self, Broken, Chain, Problem},
+    hash,
+};
+
+fn log(events: &[&str]) -> Vec<String> {
+    let mut chain = Chain::default();
+    (events.iter().enumerate())
+        .map(|(i, event)| chain.entry(1_700_000_000_000 + i as u128, event))
+        .collect()
+}
+
+fn joined(lines: &[String]) -> String {
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+fn broken_at(lines: &[String]) -> Broken {
+    audit::verify(&joined(lines)).unwrap_err()
+}
+
+const EVENTS: [&str; 4] = [
+    "session-started peer=127.0.0.1:6014",
+    "header file_id=0 name=\"a.txt\"",
+    "data file_id=0 packet=0 len=10 last=true",
+    "session-finished packets=2 bytes=10 elapsed_ms=3 rate=3333",
+];
+
+#[test]
+fn first_entry_chains_to_zeros() {
+    let entry = &log(&EVENTS[..1])[0];
+    let body = format!(
+        "0 1700000000000 session-started peer=127.0.0.1:6014 prev={}",
+        audit::GENESIS
+    );
+    let hash = hash::HashAlgorithm::Sha256.digest(body.as_bytes());
+    assert_eq!(*entry, format!("{body} hash={hash}"));
+}
+
+#[test]
+fn an_untouched_log_is_intact() {
+    let lines = log(&EVENTS);
+    let intact = audit::verify(&joined(&lines)).unwrap();
+    assert_eq!(intact.entries, 4);
+    assert!(lines[3].ends_with(&format!(" hash={}", intact.last_hash)));
+
+    let empty = audit::verify("").unwrap();
+    assert_eq!(empty.entries, 0);
+    assert_eq!(empty.last_hash, audit::GENESIS);
+}
+
+#[test]
+fn appending_carries_on_the_chain() {
+    let mut lines = log(&EVENTS[..2]);
+    let mut chain = Chain::after(&lines[1]).unwrap();
+    lines.extend(EVENTS[2..].iter().map(|event| chain.entry(5, event)));
+    assert_eq!(audit::verify(&joined(&lines)).unwrap().entries, 4);
+    assert_eq!(Chain::after("not an entry"), None);
+}
+
+#[test]
+fn an_edited_entry_is_caught() {
+    let mut lines = log(&EVENTS);
+    lines[2] = lines[2].replace("len=10", "len=11");
+    assert_eq!(
+        broken_at(&lines),
+        Broken {
+            line: 3,
+            problem: Problem::WrongHash
+        }
+    );
+}
+
+#[test]
+fn a_rehashed_edit_breaks_the_next_link() {
+    let mut lines = log(&EVENTS);
+    let (body, _) = lines[1].rsplit_once(" hash=").unwrap();
+    let body = body.replace("a.txt", "b.txt");
+    let hash = hash::HashAlgorithm::Sha256.digest(body.as_bytes());
+    lines[1] = format!("{body} hash={hash}");
+    assert_eq!(
+        broken_at(&lines),
+        Broken {
+            line: 3,
+            problem: Problem::WrongPrev
+        }
+    );
+}
+
+#[test]
+fn dropped_and_reordered_entries_are_caught() {
+    let mut dropped = log(&EVENTS);
+    dropped.remove(1);
+    assert_eq!(
+        broken_at(&dropped),
+        Broken {
+            line: 2,
+            problem: Problem::OutOfSequence {
+                expected: 1,
+                found: 2
+            }
+        }
+    );
+
+    let mut swapped = log(&EVENTS);
+    swapped.swap(1, 2);
+    assert_eq!(broken_at(&swapped).line, 2);
+}
+
+#[test]
+fn a_stray_line_is_malformed() {
+    let mut lines = log(&EVENTS);
+    lines.insert(
+        2,
+        "1700000000005 data file_id=0 packet=1 len=10".to_string(),
+    );
+    assert_eq!(
+        broken_at(&lines),
+        Broken {
+            line: 3,
+            problem: Problem::Malformed
+        }
+    );
+}
+
+#[test]
+fn names_that_look_like_fields_dont_confuse_it() {
+    let lines = log(&["header file_id=0 name=\"x prev=0 hash=0\""]);
+    assert_eq!(audit::verify(&joined(&lines)).unwrap().entries, 1);
+}
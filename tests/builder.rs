@@ -0,0 +1,194 @@
+// Client::builder(): a client set up in code, checked like the command line,
+// and run against the companion server.
+
+use segmented_file_system_client::{
+    client::{self, Client},
+    error::ClientError,
+    hooks::{Hook, HookError, Hooks},
+    packet::Metadata,
+    server,
+};
+use std::{
+    env, fs,
+    net::{SocketAddr, UdpSocket},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+#[test]
+fn the_builder_sets_the_options() {
+    let client = Client::builder()
+        .server("127.0.0.1:7000")
+        .server("localhost:7001")
+        .bind_port(0)
+        .timeout(Duration::from_secs(30))
+        .output_dir("out")
+        .args(["--until", "files=2"])
+        .build()
+        .unwrap();
+    let cli = client.cli();
+    assert_eq!(cli.server, ["127.0.0.1:7000", "localhost:7001"]);
+    assert_eq!(cli.bind, 0);
+    assert_eq!(cli.deadline, Some(Duration::from_secs(30)));
+    assert_eq!(cli.output_dir, Some(PathBuf::from("out")));
+
+    // Left alone, it's what the command line would do
+    let cli = Client::builder().build().unwrap().cli().clone();
+    assert_eq!(cli.server, [client::SERVER_ADDR]);
+    assert_eq!(cli.bind, client::BIND_PORT);
+}
+
+#[test]
+fn options_that_cant_work_are_an_error() {
+    let bad = |e| match e {
+        Err(ClientError::BadOptions { problems }) => problems,
+        _ => panic!("the options should have been turned down"),
+    };
+    let problems = bad(Client::builder().server("nowhere").build());
+    assert!(problems[0].contains("nowhere"), "{problems:?}");
+    let problems = bad(Client::builder().args(["--no-such-option"]).build());
+    assert!(problems[0].contains("--no-such-option"), "{problems:?}");
+    let problems = bad(Client::builder()
+        .args(["--headerless-name", "../{id}"])
+        .build());
+    assert!(problems[0].contains("--headerless-name"), "{problems:?}");
+    let e = Client::builder().args(["--stall-timeout", "0s"]).build();
+    let Err(e) = e else {
+        panic!("a zero timeout should have been turned down");
+    };
+    assert_eq!((e.code(), e.exit_code()), ("bad-options", 2));
+}
+
+#[test]
+fn a_built_client_runs_the_transfer() {
+    let dir = env::temp_dir().join(format!("segfs-builder-{}", std::process::id()));
+    let packets = server::packets(0, "a.txt", b"built", Metadata::default());
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap().to_string();
+    let options = server::Options {
+        clients: Some(1),
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+
+    let client = Client::builder()
+        .server(addr)
+        .bind_port(0)
+        .timeout(Duration::from_secs(10))
+        .output_dir(&dir)
+        .args(["--until", "files=1"])
+        .build()
+        .unwrap();
+    assert_eq!(client.run().unwrap(), [(0, dir.join("a.txt"))]);
+    assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "built");
+    serving.join().unwrap().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Says where it was called, sends file 0 to `renamed.txt`, and turns down
+// file 1
+struct Steering(Sender<String>);
+
+impl Hooks for Steering {
+    fn after_bind(&mut self, local: SocketAddr) -> Result<(), HookError> {
+        self.0.send(format!("bound {}", local.ip())).unwrap();
+        Ok(())
+    }
+
+    fn after_handshake(&mut self, peer: SocketAddr) -> Result<(), HookError> {
+        self.0.send(format!("answered by {peer}")).unwrap();
+        Ok(())
+    }
+
+    fn before_write(&mut self, file_id: u8, path: &Path) -> Result<Option<PathBuf>, HookError> {
+        let name = path.file_name().unwrap().to_string_lossy();
+        self.0.send(format!("writing {file_id} to {name}")).unwrap();
+        match file_id {
+            0 => Ok(Some(path.with_file_name("renamed.txt"))),
+            _ => Err("not that one".into()),
+        }
+    }
+}
+
+#[test]
+fn hooks_can_move_a_file_or_turn_one_down() {
+    let dir = env::temp_dir().join(format!("segfs-builder-hooks-{}", std::process::id()));
+    let mut packets = server::packets(0, "a.txt", b"moved", Metadata::default());
+    packets.extend(server::packets(1, "b.txt", b"refused", Metadata::default()));
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap();
+    let options = server::Options {
+        clients: Some(1),
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+    let (sender, calls) = mpsc::channel();
+    let client = Client::builder()
+        .server(addr.to_string())
+        .bind_port(0)
+        .timeout(Duration::from_secs(10))
+        .output_dir(&dir)
+        .args(["--until", "files=2"])
+        .hooks(Steering(sender))
+        .build()
+        .unwrap();
+    let e = client.run().err().unwrap();
+    serving.join().unwrap().unwrap();
+    let mut calls: Vec<_> = calls.try_iter().collect();
+    assert_eq!(calls.remove(1), format!("answered by {addr}"));
+    assert_eq!(
+        calls,
+        ["bound 0.0.0.0", "writing 0 to a.txt", "writing 1 to b.txt"]
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("renamed.txt")).unwrap(),
+        "moved"
+    );
+    assert!(!dir.join("a.txt").exists() && !dir.join("b.txt").exists());
+    let ClientError::Hook { hook, error } = &e else {
+        panic!("{e:?}");
+    };
+    assert_eq!(
+        (*hook, error.to_string().as_str()),
+        (Hook::BeforeWrite, "not that one")
+    );
+    assert_eq!(e.code(), "hook");
+    assert_eq!(
+        e.to_string(),
+        "the before_write hook stopped the transfer: not that one"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_hook_that_fails_at_bind_stops_everything() {
+    struct Refuse;
+    impl Hooks for Refuse {
+        fn after_bind(&mut self, _local: SocketAddr) -> Result<(), HookError> {
+            Err("port not allowed".into())
+        }
+    }
+    // Nothing would answer anyway
+    let quiet = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let client = Client::builder()
+        .server(quiet.local_addr().unwrap().to_string())
+        .bind_port(0)
+        .hooks(Refuse)
+        .build()
+        .unwrap();
+    let e = client.run().err().unwrap();
+    assert!(
+        matches!(
+            e,
+            ClientError::Hook {
+                hook: Hook::AfterBind,
+                ..
+            }
+        ),
+        "{e:?}"
+    );
+    quiet.set_nonblocking(true).unwrap();
+    assert!(quiet.recv(&mut [0; 16]).is_err(), "the hello went out");
+}
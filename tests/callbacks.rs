@@ -0,0 +1,158 @@
+// ClientEvents: the hooks a program embedding the client gets, from a
+// session's events and from a whole transfer against the companion server.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    callbacks::{Callbacks, ClientEvents},
+    cli::Cli,
+    client,
+    error::ClientError,
+    events::{Event, Progress},
+    packet::Metadata,
+    server,
+};
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    net::UdpSocket,
+    path::Path,
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+// Each hook as a line down a channel, the way a GUI would pass them on
+struct Forward(Sender<String>);
+
+impl ClientEvents for Forward {
+    fn on_header(&mut self, file_id: u8, name: &OsStr) {
+        let _ = self.0.send(format!("header {file_id} {name:?}"));
+    }
+
+    fn on_progress(&mut self, file_id: u8, received: usize, expected: Option<u32>) {
+        let _ = (self.0).send(format!("progress {file_id} {received}/{expected:?}"));
+    }
+
+    fn on_file_complete(&mut self, path: &Path) {
+        let name = path.file_name().unwrap();
+        let _ = self.0.send(format!("complete {name:?}"));
+    }
+
+    fn on_error(&mut self, error: &ClientError) {
+        let _ = self.0.send(format!("error {}", error.code()));
+    }
+}
+
+#[test]
+fn the_hooks_follow_the_session() {
+    let (sender, hooks) = mpsc::channel();
+    let mut progress = Progress::default();
+    progress.add(Callbacks::new(Forward(sender)));
+    let data = |packet_number, is_last_packet| Event::Data {
+        file_id: 2,
+        packet_number,
+        len: 10,
+        is_last_packet,
+    };
+    let header = Event::Header {
+        file_id: 2,
+        file_name: OsStr::new("a.txt"),
+        size: None,
+    };
+    progress.emit(data(1, true)).unwrap();
+    progress.emit(header).unwrap();
+    // Neither a header nor a packet again says anything new
+    for event in [
+        data(1, true),
+        Event::Header {
+            file_id: 2,
+            file_name: OsStr::new("a.txt"),
+            size: None,
+        },
+        data(0, false),
+    ] {
+        progress.emit(event).unwrap();
+    }
+    // A header with a size knows how many packets are to come
+    progress
+        .emit(Event::Header {
+            file_id: 5,
+            file_name: OsStr::new("b.bin"),
+            size: Some(2500),
+        })
+        .unwrap();
+    drop(progress);
+    assert_eq!(
+        hooks.iter().collect::<Vec<_>>(),
+        [
+            "progress 2 1/Some(2)",
+            "header 2 \"a.txt\"",
+            "progress 2 1/Some(2)",
+            "progress 2 2/Some(2)",
+            "header 5 \"b.bin\"",
+            "progress 5 0/Some(3)",
+        ]
+    );
+}
+
+#[test]
+fn a_transfer_calls_back_until_its_files_are_written() {
+    let dir = env::temp_dir().join(format!("segfs-callbacks-{}", std::process::id()));
+    let packets = server::packets(1, "a.txt", &[b'a'; 1500], Metadata::default());
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap().to_string();
+    let options = server::Options {
+        clients: Some(1),
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+
+    let cli = Cli::try_parse_from([
+        "client",
+        "--server",
+        &addr,
+        "--bind",
+        "0",
+        "--until",
+        "files=1",
+        "--output-dir",
+        dir.to_str().unwrap(),
+    ])
+    .unwrap();
+    let (sender, hooks) = mpsc::channel();
+    let written = client::transfer(&cli, Forward(sender)).unwrap();
+    assert_eq!(written, [(1, dir.join("a.txt"))]);
+    let hooks: Vec<_> = hooks.iter().collect();
+    assert_eq!(hooks[0], "header 1 \"a.txt\"");
+    assert!(
+        hooks.contains(&"progress 1 2/Some(2)".to_string()),
+        "{hooks:?}"
+    );
+    assert_eq!(hooks.last().unwrap(), "complete \"a.txt\"");
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), [b'a'; 1500]);
+    serving.join().unwrap().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_transfer_that_fails_says_why() {
+    // Nobody answers here
+    let quiet = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = quiet.local_addr().unwrap().to_string();
+    let args = [
+        "client",
+        "--server",
+        &addr,
+        "--bind",
+        "0",
+        "--deadline",
+        "200ms",
+    ];
+    let cli = Cli::try_parse_from(args).unwrap();
+    let (sender, hooks) = mpsc::channel();
+    let e = client::transfer(&cli, Forward(sender)).err().unwrap();
+    assert_eq!(
+        hooks.iter().collect::<Vec<_>>(),
+        [format!("error {}", e.code())]
+    );
+}
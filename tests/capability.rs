@@ -0,0 +1,86 @@
+// Capability advertisements: the bytes on the wire, and what gets agreed to,
+// turned down, and reported from what the server offers.
+
+use segmented_file_system_client::capability::{
+    self, Negotiation, COMPRESSION, CRC, ECHO, ENCRYPTION, RESUME, ZSTD,
+};
+
+#[test]
+fn layout() {
+    assert_eq!(capability::encode(CRC | RESUME), *b"SFSC\x01\x00\x00\x05");
+    assert_eq!(
+        capability::parse(b"SFSC\x01\x00\x00\x18"),
+        Some(COMPRESSION | ENCRYPTION)
+    );
+    let bits = CRC | ECHO | 1 << 15;
+    assert_eq!(capability::parse(&capability::encode(bits)), Some(bits));
+}
+
+#[test]
+fn anything_else_is_not_an_advertisement() {
+    // Short, long, another version, another magic (an echo reply)
+    assert_eq!(capability::parse(b"SFSC\x01\x00\x00"), None);
+    assert_eq!(capability::parse(b"SFSC\x01\x00\x00\x01\x00"), None);
+    assert_eq!(capability::parse(b"SFSC\x02\x00\x00\x01"), None);
+    assert_eq!(capability::parse(b"SFSE\x01\x00\x00\x01"), None);
+    assert_eq!(capability::parse(b""), None);
+}
+
+#[test]
+fn names_go_lowest_bit_first() {
+    assert_eq!(capability::names(ENCRYPTION | CRC), ["crc", "encryption"]);
+    assert_eq!(capability::names(ECHO | 1 << 9), ["echo", "bit 9"]);
+    assert!(capability::names(0).is_empty());
+}
+
+#[test]
+fn features_missing_from_the_build_are_turned_down() {
+    let negotiation = Negotiation {
+        offered: CRC | ZSTD | ENCRYPTION,
+        wanted: CRC | ZSTD,
+    };
+    // Only when the build hasn't got them; otherwise zstd is agreed and
+    // encryption just not wanted
+    let without = |feature, bit| if feature { 0 } else { bit };
+    let zstd = without(cfg!(feature = "zstd"), ZSTD);
+    let encryption = without(cfg!(feature = "crypto"), ENCRYPTION);
+    assert_eq!(negotiation.agreed(), CRC | ZSTD & !zstd);
+    assert_eq!(negotiation.missing(), zstd | encryption);
+    assert_eq!(negotiation.declined(), ENCRYPTION & !encryption);
+    assert_eq!(negotiation.unoffered(), 0);
+}
+
+#[test]
+fn gzip_is_there_with_its_feature() {
+    let negotiation = Negotiation {
+        offered: COMPRESSION,
+        wanted: COMPRESSION,
+    };
+    if cfg!(feature = "gzip") {
+        assert_eq!(negotiation.agreed(), COMPRESSION);
+    } else {
+        assert_eq!(negotiation.missing(), COMPRESSION);
+    }
+}
+
+#[test]
+fn built_in_features_left_off_are_declined() {
+    let negotiation = Negotiation {
+        offered: CRC | ECHO,
+        wanted: ECHO | RESUME,
+    };
+    assert_eq!(negotiation.agreed(), ECHO);
+    assert_eq!(negotiation.missing(), 0);
+    assert_eq!(negotiation.declined(), CRC);
+    assert_eq!(negotiation.unoffered(), RESUME);
+}
+
+#[test]
+fn nothing_offered_means_nothing_agreed() {
+    let negotiation = Negotiation {
+        offered: 0,
+        wanted: CRC | ECHO | RESUME,
+    };
+    assert_eq!(negotiation.agreed(), 0);
+    assert_eq!(negotiation.unoffered(), CRC | ECHO | RESUME);
+}
@@ -0,0 +1,134 @@
+// --record and --replay: a session's datagrams saved as they came, and the
+// same session run again from them with no server.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    capture::{self, Datagram, ReplayTransport},
+    cli::Cli,
+    events::Progress,
+    file_manager::FileManager,
+    session::run_session,
+    transport::{ManualClock, ScriptedTransport, Step, Transport},
+};
+use std::{env, fs, io, path::PathBuf, time::Duration};
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from(["segmented-file-system-client"].iter().chain(args)).unwrap()
+}
+
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("segfs-capture-{name}-{}", std::process::id()))
+}
+
+const TWO_FILES: [&[u8]; 6] = [
+    b"\x01\x01\x00\x00one, ",
+    b"\x00\x01a.txt",
+    // Not a packet at all, but it came, so it's kept
+    b"\x01",
+    b"\x03\x02\x00\x00two",
+    b"\x03\x01\x00\x01then more",
+    b"\x00\x02b.txt",
+];
+
+#[test]
+fn a_recorded_session_replays_to_the_same_files() {
+    let path = scratch("same");
+    let clock = ManualClock::default();
+    let mut steps: Vec<Step> = TWO_FILES
+        .iter()
+        .map(|b| Step::Datagram(b.to_vec()))
+        .collect();
+    steps.insert(3, Step::Silence(Duration::from_millis(300)));
+    let mut scripted = ScriptedTransport::new(steps, &clock);
+    let record = cli(&["--until", "files=2", "--record", path.to_str().unwrap()]);
+    let (recorded, _) = run_session(
+        &mut scripted,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &record,
+        None,
+    )
+    .unwrap();
+
+    let datagrams = capture::read(&path).unwrap();
+    let bytes: Vec<&[u8]> = datagrams.iter().map(|d| &d.bytes[..]).collect();
+    assert_eq!(bytes, TWO_FILES);
+    assert_eq!(datagrams[2].at, Duration::ZERO);
+    assert_eq!(datagrams[3].at, Duration::from_millis(300));
+
+    let clock = ManualClock::default();
+    let mut replay = ReplayTransport::new(datagrams, &clock);
+    replay
+        .set_poll_interval(Some(Duration::from_millis(50)))
+        .unwrap();
+    let (replayed, report) = run_session(
+        &mut replay,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli(&["--until", "files=2"]),
+        None,
+    )
+    .unwrap();
+    for id in [1, 2] {
+        assert_eq!(replayed.contents(id), recorded.contents(id));
+    }
+    assert_eq!(report.packets(), 5);
+    assert_eq!(replay.remaining(), 0);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn the_replay_ends_with_the_capture() {
+    let datagrams = TWO_FILES[..2]
+        .iter()
+        .map(|bytes| Datagram {
+            at: Duration::from_secs(1),
+            bytes: bytes.to_vec(),
+        })
+        .collect();
+    let clock = ManualClock::default();
+    let mut replay = ReplayTransport::new(datagrams, &clock);
+    let (files, _) = run_session(
+        &mut replay,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli(&[]),
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        files.file_status(1).map(|(_, received, _)| received),
+        Some(1)
+    );
+    assert!(!files.is_complete(1));
+}
+
+#[test]
+fn a_capture_cut_short_keeps_its_whole_datagrams() {
+    let path = scratch("cut");
+    let clock = ManualClock::default();
+    let steps = TWO_FILES.iter().map(|b| Step::Datagram(b.to_vec()));
+    let mut scripted = ScriptedTransport::new(steps, &clock);
+    let record = cli(&["--until", "files=2", "--record", path.to_str().unwrap()]);
+    let mut progress = Progress::default();
+    run_session(
+        &mut scripted,
+        &clock,
+        FileManager::default(),
+        &mut progress,
+        &record,
+        None,
+    )
+    .unwrap();
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+    assert_eq!(capture::read(&path).unwrap().len(), TWO_FILES.len() - 1);
+
+    fs::write(&path, b"SFSJ\x02\0\0\0\0\0\0\0\0\0\0\0").unwrap();
+    let e = capture::read(&path).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    fs::remove_file(&path).unwrap();
+}
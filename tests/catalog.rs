@@ -0,0 +1,48 @@
+// --daemon's manifest.json: batches adding up, rewrites replacing their
+// entries, and --expect reading it back.
+
+use segmented_file_system_client::{
+    catalog::{self, Catalog},
+    hash::HashAlgorithm,
+    manifest,
+};
+use std::{env, fs};
+
+#[test]
+fn batches_add_up_and_rewrites_replace_their_entries() {
+    let dir = env::temp_dir().join(format!("segfs-catalog-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    assert!(Catalog::load(&dir).unwrap().files.is_empty());
+
+    fs::write(dir.join("a.txt"), "one").unwrap();
+    fs::write(dir.join("b.txt"), "two").unwrap();
+    let first = [(1, dir.join("a.txt")), (2, dir.join("b.txt"))];
+    catalog::record(&dir, &first, HashAlgorithm::Sha256, 100).unwrap();
+
+    fs::write(dir.join("a.txt"), "three").unwrap();
+    catalog::record(&dir, &[(1, dir.join("a.txt"))], HashAlgorithm::Sha256, 200).unwrap();
+
+    let files = Catalog::load(&dir).unwrap().files;
+    let names: Vec<_> = files.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["b.txt", "a.txt"]);
+    assert_eq!((files[0].size, files[0].batch_unix_ms), (3, 100));
+    assert_eq!((files[1].size, files[1].batch_unix_ms), (5, 200));
+    assert_eq!(files[1].hash, HashAlgorithm::Sha256.tagged(b"three"));
+    // Only ever renamed into place
+    assert!(!dir.join("manifest.json.partial").exists());
+
+    // It's an --expect manifest too
+    let expected = manifest::load(&dir.join(catalog::FILE_NAME), HashAlgorithm::Blake3).unwrap();
+    assert_eq!(expected.files.len(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_garbled_manifest_is_an_error_not_a_fresh_start() {
+    let dir = env::temp_dir().join(format!("segfs-catalog-bad-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(catalog::FILE_NAME), "{\"files\": [").unwrap();
+    let e = Catalog::load(&dir).err().unwrap();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    fs::remove_dir_all(&dir).unwrap();
+}
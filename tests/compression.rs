@@ -0,0 +1,138 @@
+// Compressed files: gzip and zstd unpacked, streams that don't check out, and
+// a file marked compressed in its header written down unpacked.
+
+use segmented_file_system_client::{
+    cli::WriteOrder,
+    compression::{self, Compression},
+    file_manager::FileManager,
+    packet::{Checksum, Metadata, Packet},
+    server,
+};
+use sha2::{Digest, Sha256};
+use std::{convert::TryFrom, env, fs, io};
+
+// `gzip -9` of the play, which names the file in its header and runs to
+// dynamic blocks past the end of the decoder's window
+const PLAY: &[u8] = include_bytes!("compressed/AsYouLikeIt.txt.gz");
+// `zstd -19` of it
+const PLAY_ZSTD: &[u8] = include_bytes!("compressed/AsYouLikeIt.txt.zst");
+
+// One stored block, and one with the fixed code
+const STORED: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x04\x03\x01\x10\x00\xef\xff\
+    stored as it is\n\x3c\x42\x55\x0f\x10\x00\x00\x00";
+const FIXED: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\xd7\
+    \x51\xc8\x40\xa2\x14\xca\xf3\x8b\x72\x52\xb8\x00\x87\x5d\x46\x2b\x1a\x00\x00\x00";
+
+fn unpack(compression: Compression, bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    compression::decode(compression, bytes, &mut |data| {
+        out.extend_from_slice(data);
+        Ok(())
+    })?;
+    Ok(out)
+}
+
+#[test]
+#[cfg_attr(not(feature = "gzip"), ignore)]
+fn gzip_unpacks_to_what_was_packed() {
+    let play = fs::read("tests/target-files/AsYouLikeIt.txt").unwrap();
+    assert_eq!(unpack(Compression::Gzip, PLAY).unwrap(), play);
+    assert_eq!(
+        unpack(Compression::Gzip, STORED).unwrap(),
+        b"stored as it is\n"
+    );
+    assert_eq!(
+        unpack(Compression::Gzip, FIXED).unwrap(),
+        b"hello, hello, hello world\n"
+    );
+    // Members one after another make one file
+    assert_eq!(
+        unpack(Compression::Gzip, &[STORED, FIXED].concat()).unwrap(),
+        b"stored as it is\nhello, hello, hello world\n"
+    );
+    assert!(unpack(Compression::Gzip, b"").unwrap().is_empty());
+}
+
+#[test]
+#[cfg_attr(not(feature = "gzip"), ignore)]
+fn a_stream_that_doesnt_check_out_is_an_error() {
+    let mut bad_crc = FIXED.to_vec();
+    bad_crc[FIXED.len() - 8] ^= 1;
+    let mut bad_code = FIXED.to_vec();
+    // Block type 3, which there isn't
+    bad_code[10] |= 0b110;
+    for (bytes, expected) in [
+        (&bad_crc[..], "corrupt or cut short"),
+        (&FIXED[..FIXED.len() - 3], "corrupt or cut short"),
+        (&PLAY[..PLAY.len() / 2], "corrupt or cut short"),
+        (&bad_code[..], "corrupt or cut short"),
+        (b"not gzip at all", "isn't gzip"),
+    ] {
+        let e = unpack(Compression::Gzip, bytes).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains(expected), "{e}");
+    }
+}
+
+#[test]
+#[cfg_attr(not(feature = "zstd"), ignore)]
+fn zstd_unpacks_to_what_was_packed() {
+    let play = fs::read("tests/target-files/AsYouLikeIt.txt").unwrap();
+    assert_eq!(unpack(Compression::Zstd, PLAY_ZSTD).unwrap(), play);
+    // Frames one after another make one file, like gzip's members
+    let twice = unpack(Compression::Zstd, &[PLAY_ZSTD, PLAY_ZSTD].concat()).unwrap();
+    assert_eq!(twice, [&play[..], &play].concat());
+    assert!(unpack(Compression::Zstd, b"").unwrap().is_empty());
+
+    for bytes in [&PLAY_ZSTD[..PLAY_ZSTD.len() / 2], PLAY, b"not zstd at all"] {
+        let e = unpack(Compression::Zstd, bytes).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("isn't zstd"), "{e}");
+    }
+}
+
+#[test]
+fn whatever_isnt_built_in_is_unsupported() {
+    assert_eq!(Compression::Gzip.is_built_in(), cfg!(feature = "gzip"));
+    assert_eq!(Compression::Zstd.is_built_in(), cfg!(feature = "zstd"));
+    for compression in [Compression::Gzip, Compression::Zstd] {
+        if !compression.is_built_in() {
+            let e = unpack(compression, b"\x28\xb5\x2f\xfd").unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+        }
+    }
+    assert_eq!(unpack(Compression::None, b"as is").unwrap(), b"as is");
+}
+
+#[test]
+#[cfg_attr(not(feature = "gzip"), ignore)]
+fn a_compressed_file_is_written_unpacked() {
+    let dir = env::temp_dir().join(format!("segfs-compressed-{}", std::process::id()));
+    let play = fs::read("tests/target-files/AsYouLikeIt.txt").unwrap();
+    let gzip = Metadata {
+        compression: Compression::Gzip,
+        ..Metadata::default()
+    };
+    let mut file_manager = FileManager::default();
+    for packet in server::packets(1, "play.txt", PLAY, gzip) {
+        // Through the bytes, so the header's status bits say it
+        let packet = Packet::try_from(&packet.to_bytes()[..]).unwrap();
+        file_manager.process_packet(packet);
+    }
+    // Of the file unpacked
+    let digest = Sha256::digest(&play).into();
+    file_manager.process_packet(Packet::Checksum(Checksum { file_id: 1, digest }));
+    for packet in server::packets(2, "broken.txt", &FIXED[..20], gzip) {
+        file_manager.process_packet(packet);
+    }
+    assert_eq!(file_manager.metadata(1), Some(gzip));
+
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    assert_eq!(finalized.written.len(), 1);
+    assert!(finalized.mismatched.is_empty());
+    assert_eq!(finalized.failed.len(), 1);
+    assert_eq!(fs::read(dir.join("play.txt")).unwrap(), play);
+    assert!(!dir.join("broken.txt").exists());
+    file_manager.clean_up().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
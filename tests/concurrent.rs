@@ -0,0 +1,53 @@
+// --concurrent over loopback: two servers on the one client socket, each
+// session's files kept apart in a directory of its own.
+
+use clap::Parser;
+use segmented_file_system_client::{cli::Cli, concurrent, grade};
+use std::{env, fs, net::UdpSocket, thread};
+
+// Answers one request with a one-packet file
+fn server(name: &'static str, content: &'static [u8]) -> (String, thread::JoinHandle<()>) {
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap().to_string();
+    let serving = thread::spawn(move || {
+        let mut buf = [0; 2048];
+        let (_, client) = sock.recv_from(&mut buf).unwrap();
+        let mut data = b"\x03\x01\x00\x00".to_vec();
+        data.extend_from_slice(content);
+        let mut header = b"\x00\x01".to_vec();
+        header.extend_from_slice(name.as_bytes());
+        sock.send_to(&data, client).unwrap();
+        sock.send_to(&header, client).unwrap();
+    });
+    (addr, serving)
+}
+
+#[test]
+fn each_server_gets_a_session_and_a_directory() {
+    let dir = env::temp_dir().join(format!("segfs-concurrent-{}", std::process::id()));
+    let (first, first_serving) = server("a.txt", b"from the first");
+    let (second, second_serving) = server("a.txt", b"from the second");
+    let cli = Cli::try_parse_from([
+        "segmented-file-system-client",
+        "--concurrent",
+        "--quiet",
+        "--server",
+        &first,
+        "--server",
+        &second,
+        "--until",
+        "files=1",
+        "--output-dir",
+        dir.to_str().unwrap(),
+    ])
+    .unwrap();
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    concurrent::run(&sock, &cli, None).unwrap();
+    first_serving.join().unwrap();
+    second_serving.join().unwrap();
+
+    let read = |server: &str| fs::read(dir.join(grade::directory_name(server)).join("a.txt"));
+    assert_eq!(read(&first).unwrap(), b"from the first");
+    assert_eq!(read(&second).unwrap(), b"from the second");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,126 @@
+// The configuration file: what it can say, what it can't, and the command
+// line winning over it.
+
+use clap::{CommandFactory, Parser};
+use segmented_file_system_client::{
+    cli::Cli,
+    config::{self, Config, Value},
+};
+use std::{env, ffi::OsString, fs, path::Path, time::Duration};
+
+const FILE: &str = r#"
+# Defaults for the lab machines
+server = "10.0.0.5:6014"
+output-dir = 'C:\downloads'
+connect_timeout = "5s"   # durations as the flags take them
+max-files = 1_000
+crc = true
+failover = false
+only = ["*.txt", "a \"quoted\" name"]
+"#;
+
+// The command line, with the file's defaults under it
+fn parse(file: &str, command_line: &[&str]) -> Result<Cli, String> {
+    let config = Config::parse(Path::new("config.toml"), file).map_err(|e| e.to_string())?;
+    let args: Vec<&str> = ["client"].iter().chain(command_line).copied().collect();
+    let matches = Cli::command().try_get_matches_from(&args).unwrap();
+    let mut all = vec![OsString::from("client")];
+    all.extend(config.to_args(&matches).map_err(|e| e.to_string())?);
+    all.extend(command_line.iter().map(OsString::from));
+    Ok(Cli::try_parse_from(all).unwrap())
+}
+
+#[test]
+fn every_kind_of_value_is_read() {
+    let config = Config::parse(Path::new("config.toml"), FILE).unwrap();
+    let values: Vec<(&str, &Value)> = (config.entries.iter())
+        .map(|entry| (entry.key.as_str(), &entry.value))
+        .collect();
+    assert_eq!(
+        values[1],
+        ("output-dir", &Value::String(r"C:\downloads".into()))
+    );
+    assert_eq!(values[3], ("max-files", &Value::Number("1000".into())));
+    assert_eq!(values[4], ("crc", &Value::Bool(true)));
+    assert_eq!(
+        values[6].1,
+        &Value::Array(vec![
+            Value::String("*.txt".into()),
+            Value::String(r#"a "quoted" name"#.into())
+        ])
+    );
+    assert_eq!(config.entries[0].line, 3);
+}
+
+#[test]
+fn the_file_fills_in_what_the_command_line_leaves_out() {
+    let cli = parse(FILE, &[]).unwrap();
+    assert_eq!(cli.server, ["10.0.0.5:6014"]);
+    assert_eq!(cli.connect_timeout, Some(Duration::from_secs(5)));
+    assert_eq!(cli.max_files, Some(1000));
+    assert!(cli.crc && !cli.failover);
+    assert_eq!(cli.only, ["*.txt", r#"a "quoted" name"#]);
+
+    let cli = parse(
+        FILE,
+        &[
+            "--server",
+            "127.0.0.1:7000",
+            "--only",
+            "*.jpg",
+            "--connect-timeout",
+            "1s",
+        ],
+    )
+    .unwrap();
+    assert_eq!(cli.server, ["127.0.0.1:7000"]);
+    assert_eq!(cli.only, ["*.jpg"]);
+    assert_eq!(cli.connect_timeout, Some(Duration::from_secs(1)));
+    assert!(cli.crc);
+}
+
+#[test]
+fn mistakes_say_where_they_are() {
+    for (file, expected) in [
+        (
+            "crc = true\nserver = 10.0.0.5\n",
+            "config.toml:2: expected a string",
+        ),
+        ("[client]\n", "config.toml:1: tables aren't needed"),
+        ("only = [\"a\" \"b\"]", "config.toml:1: expected `,` or `]`"),
+        ("server = \"x", "config.toml:1: the string is never closed"),
+        ("crc", "config.toml:1: expected `key = value`"),
+        (
+            "crc = true false",
+            "config.toml:1: there's more on the line",
+        ),
+        (
+            "colour = \"red\"",
+            "config.toml:1: there's no --colour option",
+        ),
+        ("crc = \"yes\"", "config.toml:1: --crc is a switch"),
+        ("bind = [1, 2]", "config.toml:1: --bind takes one value"),
+    ] {
+        let e = parse(file, &[]).unwrap_err();
+        assert!(e.starts_with(expected), "{file:?}: {e}");
+    }
+}
+
+#[test]
+fn a_config_option_points_at_the_file() {
+    let path = env::temp_dir().join(format!("segfs-config-{}.toml", std::process::id()));
+    fs::write(&path, "bind = 9000\nquiet = true\n").unwrap();
+    let args = config::args(
+        ["client", "--config", path.to_str().unwrap()]
+            .map(OsString::from)
+            .to_vec(),
+    )
+    .unwrap();
+    let cli = Cli::try_parse_from(args).unwrap();
+    assert_eq!(cli.bind, 9000);
+    assert!(cli.quiet);
+    fs::remove_file(&path).unwrap();
+
+    let missing = ["client", "--config", path.to_str().unwrap()].map(OsString::from);
+    assert!(config::args(missing.to_vec()).is_err());
+}
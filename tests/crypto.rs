@@ -0,0 +1,214 @@
+// --psk: the cipher against RFC 8439's own examples, and a session that
+// opens sealed packets and names, dropping packets that were tampered with,
+// under a key salted afresh for each session.
+#![cfg(feature = "crypto")]
+
+use clap::Parser;
+use segmented_file_system_client::{
+    capability,
+    cli::Cli,
+    crypto::{self, Key},
+    events::Progress,
+    file_manager::FileManager,
+    protocol::{self, Hello},
+    session::run_session,
+    transport::{ManualClock, ScriptedTransport, Step},
+};
+
+fn hex(s: &str) -> Vec<u8> {
+    let s: String = s.split_whitespace().collect();
+    (0..s.len() / 2)
+        .map(|n| u8::from_str_radix(&s[n * 2..n * 2 + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn a_sealed_draft_from_the_rfc_opens() {
+    // RFC 8439 A.5
+    let key = hex("1c9240a5eb55d38af333888604f6b5f0473917c1402b80099dca5cbc207075c0");
+    let key = Key::from(<[u8; 32]>::try_from(key).unwrap());
+    let nonce = hex("000000000102030405060708").try_into().unwrap();
+    let aad = hex("f33388860000000000004e91");
+    let sealed = hex(
+        "64a0861575861af460f062c79be643bd5e805cfd345cf389f108670ac76c8cb2
+         4c6cfc18755d43eea09ee94e382d26b0bdb7b73c321b0100d4f03b7f355894cf
+         332f830e710b97ce98c8a84abd0b948114ad176e008d33bd60f982b1ff37c855
+         9797a06ef4f0ef61c186324e2b3506383606907b6a7c02b0f9f6157b53c867e4
+         b9166c767b804d46a59b5216cde7a4e99040c5a40433225ee282a1b0a06c523e
+         af4534d7f83fa1155b0047718cbc546a0d072b04b3564eea1b422273f548271a
+         0bb2316053fa76991955ebd63159434ecebb4e466dae5a1073a6727627097a10
+         49e617d91d361094fa68f0ff77987130305beaba2eda04df997b714d6c6f2c29
+         a6ad5cb4022b02709b
+         eead9d67890cbb22392336fea1851f38",
+    );
+    let opened = crypto::open(&key, &nonce, &aad, &sealed).unwrap();
+    let opened = String::from_utf8(opened).unwrap();
+    assert!(
+        opened.starts_with("Internet-Drafts are draft documents valid for a maximum of six months")
+    );
+    assert!(opened.ends_with("as /\u{201c}work in progress./\u{201d}"));
+    assert_eq!(crypto::seal(&key, &nonce, &aad, opened.as_bytes()), sealed);
+}
+
+#[test]
+fn the_aead_matches_the_rfc() {
+    let key: [u8; 32] = std::array::from_fn(|n| 0x80 + n as u8);
+    let key = Key::from(key);
+    let nonce = hex("070000004041424344454647").try_into().unwrap();
+    let aad = hex("50515253c0c1c2c3c4c5c6c7");
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+                      tip for the future, sunscreen would be it.";
+    let sealed = crypto::seal(&key, &nonce, &aad, plaintext);
+    assert_eq!(sealed.len(), plaintext.len() + crypto::TAG_LEN);
+    assert_eq!(sealed[..16], hex("d31a8d34648e60db7b86afbc53ef7ec2"));
+    assert_eq!(
+        sealed[plaintext.len()..],
+        hex("1ae10b594f09e26a7e902ecbd0600691")
+    );
+    assert_eq!(
+        crypto::open(&key, &nonce, &aad, &sealed).unwrap(),
+        plaintext
+    );
+
+    // Any bit changed anywhere, or other additional data, and it won't open
+    for at in [0, 50, sealed.len() - 1] {
+        let mut tampered = sealed.clone();
+        tampered[at] ^= 0x10;
+        assert_eq!(crypto::open(&key, &nonce, &aad, &tampered), None);
+    }
+    assert_eq!(crypto::open(&key, &nonce, b"other", &sealed), None);
+    assert_eq!(crypto::open(&key, &nonce, &aad, &sealed[..10]), None);
+}
+
+#[test]
+fn keys_are_64_hex_digits_and_never_shown() {
+    let key = crypto::parse_key(&"0f".repeat(32)).unwrap();
+    assert_eq!(key, Key::from([0x0f; 32]));
+    assert_eq!(format!("{key:?}"), "Key(..)");
+    assert!(crypto::parse_key("0f0f").is_err());
+    assert!(crypto::parse_key(&"zz".repeat(32)).is_err());
+}
+
+#[test]
+fn tampered_packets_are_dropped_and_the_rest_decrypted() {
+    let psk = "42".repeat(32);
+    let cli = Cli::try_parse_from(["client", "--psk", &psk, "--until", "files=1"]).unwrap();
+    let key = crypto::session_key(&crypto::parse_key(&psk).unwrap(), &cli.salt);
+    let full = vec![b'x'; 1024];
+    let first = crypto::seal_packet(&key, b"\x01\x01\x00\x00", 1, 0, &full);
+    let last = crypto::seal_packet(&key, b"\x03\x01\x00\x01", 1, 1, b"end");
+    let mut tampered = last.clone();
+    tampered[5] ^= 1;
+    // The same payload claiming to be another packet doesn't open either
+    let mut renumbered = first.clone();
+    renumbered[3] = 5;
+
+    let clock = ManualClock::default();
+    let mut header = b"\x00\x01".to_vec();
+    header.extend(crypto::seal_name(&key, 1, b"\x00\x01", "a.txt").bytes());
+    let steps = [&header[..], &tampered, &renumbered, &first, &last];
+    let steps = steps.iter().map(|b| Step::Datagram(b.to_vec()));
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let (files, report) = run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli,
+        None,
+    )
+    .unwrap();
+    let mut expected = full;
+    expected.extend_from_slice(b"end");
+    assert_eq!(files.contents(1).unwrap(), expected);
+    assert_eq!(files.file_status(1).unwrap().0.unwrap(), "a.txt");
+    assert_eq!(report.packets(), 3);
+    let mut text = Vec::new();
+    report.write(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(
+        text.contains("rejected 2 packets that didn't authenticate"),
+        "{text}"
+    );
+}
+
+#[test]
+fn each_session_sends_a_salt_of_its_own() {
+    let psk = "42".repeat(32);
+    let cli = Cli::try_parse_from(["client", "--psk", &psk]).unwrap();
+    let hello = Hello::parse(&protocol::hello(&cli)).unwrap();
+    assert_ne!(hello.features & capability::ENCRYPTION, 0);
+    assert_eq!(hello.salt, Some(cli.salt));
+    assert_eq!(
+        protocol::hello(&cli).len(),
+        protocol::HELLO_LEN + crypto::SALT_LEN
+    );
+    // Leaving the salt off makes it no hello at all
+    assert_eq!(
+        Hello::parse(&protocol::hello(&cli)[..protocol::HELLO_LEN]),
+        None
+    );
+
+    // Another session, another salt, and another key
+    let again = cli.resalted();
+    assert_ne!(again.salt, cli.salt);
+    let psk = crypto::parse_key(&psk).unwrap();
+    assert_ne!(
+        crypto::session_key(&psk, &again.salt),
+        crypto::session_key(&psk, &cli.salt)
+    );
+    assert_eq!(
+        crypto::session_key(&psk, &cli.salt),
+        crypto::session_key(&psk, &cli.salt)
+    );
+
+    // Nothing pushed or replayed has a hello to carry one
+    let psk = "42".repeat(32);
+    for other in ["--listen-only", "--replay=capture.bin"] {
+        assert!(Cli::try_parse_from(["client", "--psk", &psk, other]).is_err());
+    }
+}
+
+#[test]
+fn names_are_sealed_and_fall_back_when_they_dont_open() {
+    let key = Key::from([7; 32]);
+    let sealed = crypto::seal_name(&key, 2, b"\x00\x02", "secret plans.txt");
+    assert!(!sealed.contains("secret"));
+    assert!(sealed.bytes().all(|b| b.is_ascii_hexdigit()));
+    let open = |file_id, prefix: &[u8], field: &str| {
+        crypto::open_name(&key, file_id, prefix, field.as_bytes())
+    };
+    assert_eq!(
+        open(2, b"\x00\x02", &sealed).as_deref(),
+        Some("secret plans.txt")
+    );
+    // Not for another file, another header, or in the clear
+    assert_eq!(open(3, b"\x00\x02", &sealed), None);
+    assert_eq!(open(2, b"\x04\x02", &sealed), None);
+    assert_eq!(open(2, b"\x00\x02", "a.txt"), None);
+    // Nor can it be told from a packet's nonce
+    assert!((0..=u32::MAX)
+        .step_by(1 << 24)
+        .all(|n| crypto::packet_nonce(2, n) != crypto::name_nonce(2)));
+
+    let psk = "07".repeat(32);
+    let cli = Cli::try_parse_from(["client", "--psk", &psk, "--until", "files=1"]).unwrap();
+    let key = crypto::session_key(&key, &cli.salt);
+    let clock = ManualClock::default();
+    // A header from a server that doesn't seal names
+    let last = crypto::seal_packet(&key, b"\x03\x02\x00\x00", 2, 0, b"clear");
+    let steps = [&b"\x00\x02a.txt"[..], &last].map(|b| Step::Datagram(b.to_vec()));
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let (files, _) = run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli,
+        None,
+    )
+    .unwrap();
+    let name = files.file_status(2).unwrap().0.unwrap();
+    assert_eq!(name.to_str().unwrap(), cli.headerless_name(2));
+    assert_eq!(files.contents(2).unwrap(), b"clear");
+}
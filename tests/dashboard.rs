@@ -0,0 +1,116 @@
+// --tui: the table a dashboard draws from a session's events.
+#![cfg(feature = "tui")]
+
+use crossterm::event::KeyCode;
+use segmented_file_system_client::{dashboard::Dashboard, events::Event};
+use std::{
+    ffi::OsStr,
+    time::{Duration, Instant},
+};
+
+fn data(file_id: u8, packet_number: u32, is_last_packet: bool) -> Event<'static> {
+    Event::Data {
+        file_id,
+        packet_number,
+        len: 1024,
+        is_last_packet,
+    }
+}
+
+#[test]
+fn each_file_gets_a_row_of_packets_rate_duplicates_and_gaps() {
+    let started = Instant::now();
+    let mut dashboard = Dashboard::default();
+    let peer = "127.0.0.1:6014".parse().unwrap();
+    dashboard.record(&Event::SessionStarted { peer }, started);
+    let name = OsStr::new("lecture.mp4");
+    let header = Event::Header {
+        file_id: 1,
+        file_name: name,
+        size: None,
+    };
+    dashboard.record(&header, started);
+    for (n, packet_number) in [0, 1, 1, 4, 5, 7, 9].into_iter().enumerate() {
+        let at = started + Duration::from_secs(n as u64);
+        dashboard.record(&data(1, packet_number, packet_number == 9), at);
+    }
+    // A file whose header hasn't come, still counting
+    dashboard.record(&data(2, 3, false), started + Duration::from_secs(6));
+
+    let lines = dashboard.render(started + Duration::from_secs(8), 200, 50);
+    assert_eq!(lines.len(), 4, "{lines:?}");
+    assert!(lines[0].contains("packets") && lines[0].ends_with("missing"));
+    let row: Vec<&str> = lines[1].split_whitespace().collect();
+    // Six packets over six seconds, only the first copy of 1 counted
+    assert_eq!(row[..5], ["1", "lecture.mp4", "6/10", "1KiB/s", "1"]);
+    assert!(lines[1].ends_with("  2-3, 6, 8"), "{}", lines[1]);
+    assert!(lines[2].contains("file 2") && lines[2].contains("1/?"));
+    assert!(lines[2].ends_with("  0-2"), "{}", lines[2]);
+    assert_eq!(
+        lines[3],
+        "from 127.0.0.1:6014, 8s: 8 packets, 1 duplicates, 1KiB/s"
+    );
+    // Nothing goes past the width it's given
+    let narrow = dashboard.render(started, 30, 50);
+    assert!(narrow.iter().all(|line| line.chars().count() <= 30));
+    assert!(narrow[1].ends_with('…'));
+}
+
+#[test]
+fn a_long_gap_list_is_cut_short_and_a_new_session_starts_over() {
+    let started = Instant::now();
+    let mut dashboard = Dashboard::default();
+    let peer = "127.0.0.1:6014".parse().unwrap();
+    dashboard.record(&Event::SessionStarted { peer }, started);
+    for packet_number in (0..20).step_by(2) {
+        dashboard.record(&data(1, packet_number, packet_number == 18), started);
+    }
+    dashboard.record(&data(1, 19, true), started);
+    let lines = dashboard.render(started, 200, 50);
+    assert!(lines[1].ends_with("  1, 3, 5, 7, ..."), "{}", lines[1]);
+
+    dashboard.record(&Event::SessionStarted { peer }, started);
+    let lines = dashboard.render(started, 200, 50);
+    assert_eq!(lines.len(), 2, "{lines:?}");
+}
+
+#[test]
+fn more_files_than_rows_scroll_and_a_paused_table_says_so() {
+    let started = Instant::now();
+    let mut dashboard = Dashboard::default();
+    let peer = "127.0.0.1:6014".parse().unwrap();
+    dashboard.record(&Event::SessionStarted { peer }, started);
+    for file_id in 1..=10 {
+        dashboard.record(&data(file_id, 0, false), started);
+    }
+    let file_ids = |lines: &[String]| -> Vec<String> {
+        (lines[1..lines.len() - 2].iter())
+            .map(|line| line.split_whitespace().next().unwrap().to_string())
+            .collect()
+    };
+    // Room for three files under the heading
+    let lines = dashboard.render(started, 200, 6);
+    assert_eq!(lines.len(), 6, "{lines:?}");
+    assert_eq!(file_ids(&lines), ["1", "2", "3"]);
+    assert_eq!(lines[5], "files 1-3 of 10, arrow keys to scroll");
+
+    dashboard.key(KeyCode::Down);
+    dashboard.key(KeyCode::Char('j'));
+    let lines = dashboard.render(started, 200, 6);
+    assert_eq!(file_ids(&lines), ["3", "4", "5"]);
+    // Never past the last file
+    dashboard.key(KeyCode::End);
+    let lines = dashboard.render(started, 200, 6);
+    assert_eq!(file_ids(&lines), ["8", "9", "10"]);
+    dashboard.key(KeyCode::Home);
+    dashboard.key(KeyCode::Up);
+    let lines = dashboard.render(started, 200, 6);
+    assert_eq!(file_ids(&lines), ["1", "2", "3"]);
+
+    dashboard.key(KeyCode::Char(' '));
+    let lines = dashboard.render(started, 200, 50);
+    assert_eq!(lines.len(), 13, "{lines:?}");
+    assert_eq!(lines[12], "paused, space to go on");
+    dashboard.key(KeyCode::Char('p'));
+    assert_eq!(dashboard.render(started, 200, 50).len(), 12);
+}
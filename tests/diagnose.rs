@@ -0,0 +1,121 @@
+// --diagnose: the server's pacing worked out from when datagrams came, and
+// what a run with it says when there's no server to pace anything or
+// nowhere to put the files.
+
+use segmented_file_system_client::diagnose::Diagnosis;
+use std::{
+    env, fs,
+    net::UdpSocket,
+    process::{Command, Output},
+    time::{Duration, Instant},
+};
+
+fn report(diagnosis: &Diagnosis) -> String {
+    let mut out = Vec::new();
+    diagnosis.write(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn bursts_gaps_resends_and_sizes_are_reported() {
+    let start = Instant::now();
+    let ms = Duration::from_millis;
+    let mut diagnosis = Diagnosis::default();
+    // Two bursts of three, then one more after a repeat request
+    for at in [ms(0), ms(0), ms(1), ms(10), ms(10), ms(10)] {
+        diagnosis.record_datagram(start + at, Some(1024));
+    }
+    diagnosis.record_request(start + ms(20));
+    diagnosis.record_duplicate(start + ms(25));
+    diagnosis.record_datagram(start + ms(25), Some(10));
+    let text = report(&diagnosis);
+    for line in [
+        "Server pacing",
+        "  3 bursts (datagrams under 1ms apart), 3 datagrams in the median one, 3 in the largest",
+        "  gaps between bursts: 15ms median, 15ms longest",
+        "  resent after 1 of 1 repeat requests: 5ms median, 5ms slowest",
+        "        1-255        1  14.3%",
+        "         1024        6  85.7%",
+    ] {
+        assert!(text.lines().any(|l| l == line), "{line:?} in\n{text}");
+    }
+}
+
+fn client(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--quiet", "--bind", "0", "--diagnose"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn a_server_that_never_answers_is_unreachable() {
+    // Nobody answers here
+    let quiet = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = quiet.local_addr().unwrap().to_string();
+    let dir = env::temp_dir().join(format!("segfs-diagnose-quiet-{}", std::process::id()));
+    let output = client(&[
+        "--server",
+        &addr,
+        "--connect-timeout",
+        "300ms",
+        "--output-dir",
+        dir.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    assert!(stderr.contains("didn't answer within 300ms"), "{stderr}");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+// Root writes wherever it likes, so this only means something without it
+#[cfg(unix)]
+#[test]
+fn an_output_dir_that_cant_be_written_is_reported() {
+    use segmented_file_system_client::{packet::Metadata, server};
+    use std::{os::unix::fs::PermissionsExt, thread};
+    let dir = env::temp_dir().join(format!("segfs-diagnose-denied-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+    if fs::write(dir.join("probe"), "").is_ok() {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        return;
+    }
+
+    let packets = server::packets(0, "a.txt", b"nowhere to go", Metadata::default());
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap().to_string();
+    let options = server::Options {
+        clients: Some(1),
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+    let output = client(&[
+        "--server",
+        &addr,
+        "--until",
+        "files=1",
+        "--session-timeout",
+        "10s",
+        "--output-dir",
+        dir.to_str().unwrap(),
+    ]);
+    serving.join().unwrap().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    // The pacing is all there, since the transfer itself went fine
+    assert!(stdout.contains("Server pacing"), "{stdout}");
+    assert!(stdout.contains("  1 bursts"), "{stdout}");
+    assert!(stdout.contains("no repeat requests were sent"), "{stdout}");
+    assert!(
+        stderr.contains(&format!("{}", dir.display())) && stderr.contains("Permission denied"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("1 couldn't be written"), "{stderr}");
+    assert_eq!(output.status.code(), Some(8), "{output:?}");
+}
@@ -0,0 +1,206 @@
+// Differential tests: random but well-formed packet streams are pushed at
+// the real client (in --listen-only mode) and fed to the reference model in
+// tests/reference, and both have to end up with the same files. Streams are
+// shuffled, padded with duplicates and empty keepalive packets, and sometimes
+// led by stray data packets from before the session began. Each seed is its
+// own case, so a failure names the seed that reproduces it.
+
+mod reference;
+
+use reference::Reference;
+use segmented_file_system_client::wire;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{BufRead, BufReader},
+    net::UdpSocket,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+const CLIENT_ADDR: &str = "127.0.0.1:7077";
+
+// xorshift64*, so streams are the same on every machine
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+fn header(file_id: u8, name: &str) -> Vec<u8> {
+    let mut packet = vec![wire::HEADER_STATUS, file_id];
+    packet.extend_from_slice(name.as_bytes());
+    packet
+}
+
+fn data(file_id: u8, packet_number: u16, is_last: bool, payload: &[u8]) -> Vec<u8> {
+    let mut packet = wire::data_prefix(file_id, packet_number, is_last).to_vec();
+    packet.extend_from_slice(payload);
+    packet
+}
+
+// Returns the stream and how many files are in it
+fn stream(seed: u64) -> (Vec<Vec<u8>>, usize) {
+    let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1);
+    let file_count = 1 + rng.below(4);
+    let mut headers = Vec::new();
+    let mut packets = Vec::new();
+    let mut keepalives = Vec::new();
+    for i in 0..file_count {
+        // Spread the IDs out rather than always using 0, 1, 2
+        let file_id = (i * 61 + rng.below(61)) as u8;
+        headers.push(header(file_id, &format!("seed{seed}-file{i}.bin")));
+        let count = 1 + rng.below(40);
+        for _ in 0..rng.below(3) {
+            keepalives.push(data(file_id, rng.below(count) as u16, false, &[]));
+        }
+        for n in 0..count {
+            let is_last = n + 1 == count;
+            // Mostly full packets, as the course server sends, with the odd
+            // short one to knock the spill grid out of line
+            let len = if is_last || rng.below(20) == 0 {
+                1 + rng.below(wire::MAX_PAYLOAD)
+            } else {
+                wire::MAX_PAYLOAD
+            };
+            let payload: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            packets.push(data(file_id, n as u16, is_last, &payload));
+        }
+    }
+
+    let duplicates = rng.below(packets.len() / 2 + 1);
+    for _ in 0..duplicates {
+        let packet = packets[rng.below(packets.len())].clone();
+        packets.push(packet);
+    }
+    for _ in 0..rng.below(3) {
+        let header = headers[rng.below(headers.len())].clone();
+        headers.push(header);
+    }
+
+    let mut stream = headers.split_off(1);
+    stream.extend(packets.iter().cloned());
+    stream.extend(keepalives);
+    rng.shuffle(&mut stream);
+    stream.insert(0, headers.remove(0));
+    // Data that shows up before the session starts is ignored, but its
+    // originals are still in the stream
+    for _ in 0..rng.below(3) {
+        let stray = packets[rng.below(packets.len())].clone();
+        stream.insert(0, stray);
+    }
+    (stream, file_count)
+}
+
+fn client_output(
+    dir: &Path,
+    stream: &[Vec<u8>],
+    file_count: usize,
+    extra: &[&str],
+) -> BTreeMap<String, Vec<u8>> {
+    let mut client = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--listen-only", "--session-timeout", "20s"])
+        .arg(format!("--until=files={file_count}"))
+        .args(extra)
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Wait until it's listening before sending anything
+    let mut stdout = BufReader::new(client.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert!(
+        line.starts_with("Listening on"),
+        "unexpected output {line:?}"
+    );
+
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for (i, packet) in stream.iter().enumerate() {
+        if sock.send_to(packet, CLIENT_ADDR).is_err() {
+            break; // The client has everything and is gone
+        }
+        // Loopback drops datagrams too if the receive buffer fills
+        if i % 32 == 31 {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    let status = client.wait().unwrap();
+    assert!(status.success(), "client exited with {status}");
+
+    fs::read_dir(dir)
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.file_type().unwrap().is_file())
+        .map(|entry| {
+            let name = entry.file_name().into_string().unwrap();
+            (name, fs::read(entry.path()).unwrap())
+        })
+        .collect()
+}
+
+fn check(seed: u64, extra: &[&str]) {
+    let (stream, file_count) = stream(seed);
+    let mut reference = Reference::default();
+    for packet in &stream {
+        reference.push(packet);
+    }
+    assert_eq!(reference.complete_files(), file_count, "seed {seed}");
+
+    let dir = env::temp_dir().join(format!("segfs-differential-{}-{seed}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let output = client_output(&dir, &stream, file_count, extra);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let expected = reference.output();
+    assert_eq!(
+        output.keys().collect::<Vec<_>>(),
+        expected.keys().collect::<Vec<_>>(),
+        "seed {seed}"
+    );
+    for (name, contents) in &expected {
+        assert!(output[name] == *contents, "seed {seed}: {name} differs");
+    }
+}
+
+// One test, since the client always listens on the same port
+#[test]
+fn client_matches_the_reference() {
+    for seed in 0..24 {
+        check(seed, &[]);
+    }
+    for seed in 100..112 {
+        check(seed, &["--spill-dir", "."]);
+    }
+    for seed in 200..212 {
+        check(seed, &["--assembly-backend", "vec"]);
+    }
+    for seed in 300..312 {
+        check(seed, &["--assembly-backend", "mmap"]);
+    }
+    for seed in 400..412 {
+        check(seed, &["--max-memory", "4KiB"]);
+    }
+    for seed in 500..512 {
+        check(seed, &["--write-threads", "3"]);
+    }
+}
@@ -0,0 +1,94 @@
+// --discover: the request and reply datagrams, and finding the companion
+// server over loopback rather than being told where it is.
+
+use segmented_file_system_client::{
+    discover::{self, Found},
+    packet::Metadata,
+    server, wire,
+};
+use std::{
+    env, fs,
+    net::UdpSocket,
+    process::{Command, Output},
+    thread,
+};
+
+fn client(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--bind", "0", "--quiet"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn a_reply_names_as_many_files_as_fit() {
+    assert!(discover::is_request(&discover::request()));
+    let from = "127.0.0.1:6014".parse().unwrap();
+    let reply = discover::reply(&["a.txt", "b.txt"]);
+    assert!(!discover::is_request(&reply));
+    assert_eq!(
+        discover::parse_reply(&reply, from),
+        Some(Found {
+            addr: from,
+            files: 2,
+            names: vec!["a.txt".into(), "b.txt".into()],
+        })
+    );
+
+    let names: Vec<String> = (0..200).map(|n| format!("file-{n:04}.txt")).collect();
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    let reply = discover::reply(&names);
+    assert!(reply.len() <= wire::MAX_PACKET);
+    let found = discover::parse_reply(&reply, from).unwrap();
+    assert_eq!(found.files, 200);
+    assert!(found.names.len() < 200 && found.names[..] == names[..found.names.len()]);
+    // Neither a request nor a data packet is a reply
+    assert_eq!(discover::parse_reply(&discover::request(), from), None);
+    assert_eq!(discover::parse_reply(b"\x03\x00\x00\x00x", from), None);
+}
+
+#[test]
+fn the_server_found_is_the_one_used() {
+    let dir = env::temp_dir().join(format!("segfs-discover-{}", std::process::id()));
+    let packets = server::packets(0, "found.txt", b"discovered", Metadata::default());
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap().to_string();
+    // Being asked who's there doesn't count as a client
+    let options = server::Options {
+        clients: Some(1),
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+
+    let output = client(&[
+        "--discover",
+        &addr,
+        "--until",
+        "files=1",
+        "--output-dir",
+        dir.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let listed = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        listed.contains(&format!("1. {addr}: 1 files (found.txt)")),
+        "{listed}"
+    );
+    assert_eq!(fs::read(dir.join("found.txt")).unwrap(), b"discovered");
+    serving.join().unwrap().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn nobody_answering_is_like_an_unreachable_server() {
+    let quiet = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = quiet.local_addr().unwrap().to_string();
+    let output = client(&["--discover", &addr, "--discover-wait", "200ms", "--json"]);
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"nothing-discovered\""), "{stdout}");
+    // --first only means something with --discover
+    let output = client(&["--first"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
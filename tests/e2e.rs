@@ -0,0 +1,78 @@
+// The client binary end to end against a server on a thread, over loopback:
+// the files go out shuffled, some of them twice, and have to come out the
+// same. The shuffle is seeded, so a failure happens the same way every run.
+
+use segmented_file_system_client::{packet::Metadata, server};
+use std::{env, fs, net::UdpSocket, path::PathBuf, process::Command, thread, time::Duration};
+
+// xorshift64, so the order is the same on every machine
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+fn files() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("small.txt", b"just the one packet".to_vec()),
+        (
+            "big.bin",
+            (0..10_000).map(|i| (i * 7 % 251) as u8).collect(),
+        ),
+        ("empty", Vec::new()),
+    ]
+}
+
+// Wait for a hello, then send every packet in a shuffled order, every third
+// one twice
+fn serve(sock: UdpSocket, seed: u64) {
+    let mut datagrams: Vec<Vec<u8>> = (files().iter().enumerate())
+        .flat_map(|(id, (name, content))| {
+            server::packets(id as u8, name, content, Metadata::default())
+        })
+        .map(|packet| packet.to_bytes())
+        .collect();
+    let mut rng = Rng(seed);
+    for i in (1..datagrams.len()).rev() {
+        datagrams.swap(i, rng.below(i + 1));
+    }
+    sock.set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut buf = [0; 64];
+    let (_, client) = sock.recv_from(&mut buf).unwrap();
+    for (n, datagram) in datagrams.iter().enumerate() {
+        let times = if n % 3 == 0 { 2 } else { 1 };
+        for _ in 0..times {
+            sock.send_to(datagram, client).unwrap();
+        }
+        thread::sleep(Duration::from_micros(200));
+    }
+}
+
+#[test]
+fn shuffled_and_duplicated_packets_make_the_same_files() {
+    for seed in [1, 0x5EED, 0xDEAD_BEEF] {
+        let dir: PathBuf = env::temp_dir().join(format!("segfs-e2e-{seed}-{}", std::process::id()));
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = sock.local_addr().unwrap().to_string();
+        let serving = thread::spawn(move || serve(sock, seed));
+
+        let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+            .args(["--lang", "en", "--quiet", "--bind", "0", "--server", &addr])
+            .args(["--until", "files=3", "--session-timeout", "10s"])
+            .args(["--output-dir", dir.to_str().unwrap()])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "seed {seed}: {output:?}");
+        serving.join().unwrap();
+        for (name, content) in files() {
+            assert_eq!(fs::read(dir.join(name)).unwrap(), content, "seed {seed}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
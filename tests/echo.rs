@@ -0,0 +1,92 @@
+// Echo probes byte for byte, since servers have to reflect them without our
+// code, and the round-trip arithmetic they feed.
+
+use segmented_file_system_client::echo::{self, Echo, Estimator};
+use std::time::{Duration, Instant};
+
+const MS: Duration = Duration::from_millis(1);
+
+#[test]
+fn probe_layout() {
+    let expected = [
+        b'S', b'F', b'S', b'E', 1, 0, 0, 0, // magic, version, padding
+        1, 2, 3, 4, 5, 6, 7, 8, // session ID
+        0, 0, 1, 2, // sequence number
+    ];
+    assert_eq!(echo::probe(0x0102_0304_0506_0708, 258), expected);
+}
+
+#[test]
+fn only_our_own_probes_are_replies() {
+    let probe = echo::probe(7, 42);
+    assert_eq!(echo::reply(&probe, 7), Some(42));
+    assert_eq!(echo::reply(&probe, 8), None);
+    assert_eq!(echo::reply(&probe[..19], 7), None);
+    let mut longer = probe.to_vec();
+    longer.push(0);
+    assert_eq!(echo::reply(&longer, 7), None);
+    let mut other = probe;
+    other[3] = b'R';
+    assert_eq!(echo::reply(&other, 7), None);
+    let mut newer = probe;
+    newer[4] = 2;
+    assert_eq!(echo::reply(&newer, 7), None);
+}
+
+#[test]
+fn estimator_follows_rfc_6298() {
+    let mut estimator = Estimator::default();
+    assert_eq!(estimator.rto(), None);
+    estimator.sample(100 * MS);
+    assert_eq!(estimator.srtt, Some(100 * MS));
+    assert_eq!(estimator.rttvar, 50 * MS);
+    assert_eq!(estimator.rto(), Some(300 * MS));
+    estimator.sample(20 * MS);
+    // rttvar = 3/4 * 50 + 1/4 * 80, srtt = 7/8 * 100 + 1/8 * 20
+    assert_eq!(estimator.rttvar, 57_500 * Duration::from_micros(1));
+    assert_eq!(estimator.srtt, Some(90 * MS));
+    assert_eq!(estimator.rto(), Some(320 * MS));
+}
+
+#[test]
+fn rto_is_never_under_the_clock_granularity() {
+    let mut estimator = Estimator::default();
+    estimator.sample(Duration::ZERO);
+    assert_eq!(estimator.rto(), Some(MS));
+}
+
+#[test]
+fn probes_are_timed_when_answered() {
+    let start = Instant::now();
+    let mut echo = Echo::new(5, 10 * MS, start);
+    let first = echo.due(start).unwrap();
+    assert_eq!(echo.due(start + 5 * MS), None);
+    let second = echo.due(start + 10 * MS).unwrap();
+    assert_eq!(echo::reply(&second, 5), Some(1));
+
+    assert!(echo.answer(&first, start + 4 * MS));
+    assert!(echo.answer(&second, start + 18 * MS));
+    // A repeated reply is still ours, just not timed again
+    assert!(echo.answer(&second, start + 30 * MS));
+    assert!(!echo.answer(&echo::probe(6, 0), start + 30 * MS));
+    assert!(echo.rto().is_some());
+
+    let stats = echo.stats();
+    assert_eq!(stats.probes, 2);
+    assert_eq!(stats.answered, 2);
+    assert_eq!(stats.min, 4 * MS);
+    assert_eq!(stats.max, 8 * MS);
+    assert_eq!(stats.median, 8 * MS);
+    assert_eq!(stats.jitter, 4 * MS);
+}
+
+#[test]
+fn no_answers_no_numbers() {
+    let start = Instant::now();
+    let mut echo = Echo::new(5, 10 * MS, start);
+    echo.due(start);
+    let stats = echo.stats();
+    assert_eq!((stats.probes, stats.answered), (1, 0));
+    assert_eq!(stats.jitter, Duration::ZERO);
+    assert_eq!(echo.rto(), None);
+}
@@ -0,0 +1,117 @@
+// --progress-file: every event of a session appended to a file as a line of
+// its own, `<unix ms> <event> key=value ... mono_ms=<ms>`, beside whatever
+// else is watching.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::Cli,
+    events::{self, Event, EventsFile, Progress, ProgressSink},
+    file_manager::FileManager,
+    session::run_session,
+    transport::{ManualClock, ScriptedTransport, Step, Transport},
+};
+use std::{collections::BTreeMap, env, fs, path::Path, time::Duration};
+
+// One line: when, what, and the rest of its fields
+fn parse(line: &str) -> (u128, String, BTreeMap<String, String>) {
+    let mut words = line.split(' ');
+    let wall = words.next().unwrap().parse().unwrap();
+    let event = words.next().unwrap().to_string();
+    let fields = words
+        .map(|word| {
+            let (key, value) = word.split_once('=').unwrap();
+            (key.to_string(), value.to_string())
+        })
+        .collect();
+    (wall, event, fields)
+}
+
+// A session with file 1's two packets, written to `path`
+fn session(path: &Path) {
+    let cli = Cli::try_parse_from(["client", "--until", "files=1"]).unwrap();
+    let steps = vec![
+        Step::Datagram(b"\x00\x01a.txt".to_vec()),
+        Step::Datagram(b"\x01\x01\x00\x00one, ".to_vec()),
+        Step::Silence(Duration::from_millis(500)),
+        Step::Datagram(b"\x03\x01\x00\x01two".to_vec()),
+    ];
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    transport
+        .set_poll_interval(Some(Duration::from_millis(50)))
+        .unwrap();
+    let mut progress = Progress::default();
+    progress.add(EventsFile::open(path).unwrap());
+    run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut progress,
+        &cli,
+        None,
+    )
+    .unwrap();
+}
+
+#[test]
+fn each_event_is_a_line_of_fields() {
+    let path = env::temp_dir().join(format!("segfs-events-{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let before = events::unix_millis();
+    session(&path);
+    // Appended to, not started over
+    session(&path);
+    let text = fs::read_to_string(&path).unwrap();
+    let lines: Vec<_> = text.lines().map(parse).collect();
+    let names: Vec<&str> = (lines.iter()).map(|(_, event, _)| event.as_str()).collect();
+    let one = [
+        "session-started",
+        "header",
+        "data",
+        "data",
+        "session-finished",
+    ];
+    assert_eq!(names, [one, one].concat());
+    let mut last_wall = before;
+    for (wall, _, fields) in &lines {
+        assert!(*wall >= last_wall, "{text}");
+        last_wall = *wall;
+        fields["mono_ms"].parse::<u64>().unwrap();
+    }
+    let field = |line: usize, key: &str| lines[line].2[key].as_str();
+    assert_eq!(field(1, "file_id"), "1");
+    assert_eq!(field(1, "name"), "\"a.txt\"");
+    assert_eq!(
+        [field(3, "packet"), field(3, "len"), field(3, "last")],
+        ["1", "7", "true"]
+    );
+    // 23 bytes in half a second
+    assert_eq!(
+        [
+            field(4, "packets"),
+            field(4, "bytes"),
+            field(4, "elapsed_ms"),
+            field(4, "rate"),
+        ],
+        ["3", "23", "500", "46"]
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_session_over_in_no_time_has_no_rate() {
+    let path = env::temp_dir().join(format!("segfs-events-instant-{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut file = EventsFile::open(&path).unwrap();
+    file.event(&Event::SessionFinished {
+        packets: 1,
+        bytes: 10,
+        elapsed: Duration::ZERO,
+    })
+    .unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    let (_, event, fields) = parse(text.trim_end());
+    assert_eq!(event, "session-finished");
+    assert_eq!(fields["rate"], "0");
+    fs::remove_file(&path).unwrap();
+}
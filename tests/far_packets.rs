@@ -0,0 +1,163 @@
+// Packets numbered near the top of 32 bits, as only a wide packet can be and
+// a hostile server might send: what the client keeps and reports of such a
+// file has to cost about what was received, not a slot for every packet
+// number below it. An allocator that notes the largest single request keeps
+// that honest, since one for every number in between would be gigabytes.
+
+// What's missing is a list of ranges, even when there's only the one
+#![allow(clippy::single_range_in_vec_init)]
+// That feature puts in an allocator of its own
+#![cfg(not(feature = "count-allocs"))]
+
+use segmented_file_system_client::{
+    assembly::Backend,
+    file_manager::FileManager,
+    idle,
+    limits::{self, Ignored, Limits, Rejected, Screen},
+    packet::Packet,
+    wire,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    convert::TryFrom,
+    env, fs,
+    io::{Read, Seek, SeekFrom},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+struct Largest;
+
+// SAFETY: defers to the system allocator and only adds bookkeeping
+unsafe impl GlobalAlloc for Largest {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST.fetch_max(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST.fetch_max(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Largest = Largest;
+
+// Far more than any of this should take, and far less than a list of the
+// numbers would
+const BOUND: usize = 1 << 20;
+
+// File 7's header and one wide packet numbered 4,000,000,000
+const FAR: [&[u8]; 2] = [b"\x00\x07far.bin", b"\x09\x07\xee\x6b\x28\x00z"];
+
+#[test]
+fn the_gap_below_a_far_off_packet_is_one_range() {
+    let mut file_manager = FileManager::default();
+    for bytes in FAR {
+        file_manager.process_packet(Packet::try_from(bytes).unwrap());
+    }
+    assert_eq!(
+        file_manager.missing_packets(7),
+        Some(vec![0..4_000_000_000])
+    );
+    let files = idle::incomplete(&file_manager);
+    assert_eq!(files[0].missing, [0..4_000_000_000]);
+    let line = idle::file_line(&files[0]);
+    assert!(line.ends_with("4000000000 missing: 0-3999999999"), "{line}");
+    let gaps: Vec<_> = file_manager.gaps(u32::MAX).take(3).collect();
+    assert_eq!(gaps, [(7, 0), (7, 1), (7, 2)]);
+    let largest = LARGEST.load(Ordering::Relaxed);
+    assert!(largest < BOUND, "{largest} bytes at once");
+}
+
+fn wide(file_id: u8, packet_number: u32, payload: &[u8]) -> Packet {
+    let bytes = [
+        &wire::wide_data_prefix(file_id, packet_number, false)[..],
+        payload,
+    ]
+    .concat();
+    Packet::try_from(&bytes[..]).unwrap()
+}
+
+#[test]
+fn the_screen_stops_a_file_at_its_limit_and_at_a_tib() {
+    let mut limited = Screen::new(Limits {
+        max_file_size: Some(1 << 20),
+        ..Limits::default()
+    });
+    // Starting right at the limit is as far as a packet can
+    assert_eq!(limited.data(1, 1024, false), Ok(None));
+    assert_eq!(
+        limited.data(1, 1025, false),
+        Err(Rejected::FileTooLarge {
+            file_id: 1,
+            limit: 1 << 20
+        })
+    );
+    let mut unlimited = Screen::new(Limits::default());
+    let top = limits::MAX_PACKETS;
+    assert_eq!(unlimited.data(1, top - 1, false), Ok(None));
+    assert_eq!(unlimited.data(1, top, false), Ok(Some(Ignored::TooHigh)));
+    assert_eq!(
+        unlimited.data(1, 4_000_000_000, true),
+        Ok(Some(Ignored::TooHigh))
+    );
+}
+
+#[test]
+fn a_partial_file_leaves_holes_for_its_gaps() {
+    let dir = env::temp_dir().join(format!("segfs-far-partial-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut file_manager = FileManager::default();
+    file_manager.process_packet(Packet::try_from(&b"\x00\x07far.bin"[..]).unwrap());
+    file_manager.process_packet(wide(7, 0, b"a"));
+    // A GiB in
+    file_manager.process_packet(wide(7, 1 << 20, b"z"));
+    let partial = file_manager.write_partial_files(&dir).unwrap();
+    assert_eq!(partial[0].missing, [1..1 << 20]);
+
+    let path = dir.join("far.bin.partial");
+    let mut file = fs::File::open(&path).unwrap();
+    assert_eq!(file.metadata().unwrap().len(), (1 << 30) + 1);
+    let mut byte = [0; 1];
+    file.read_exact(&mut byte).unwrap();
+    assert_eq!(byte, *b"a");
+    file.seek(SeekFrom::Start(1 << 29)).unwrap();
+    file.read_exact(&mut byte).unwrap();
+    assert_eq!(byte, [0]);
+    file.seek(SeekFrom::Start(1 << 30)).unwrap();
+    file.read_exact(&mut byte).unwrap();
+    assert_eq!(byte, *b"z");
+    let largest = LARGEST.load(Ordering::Relaxed);
+    assert!(largest < BOUND, "{largest} bytes at once");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_packet_past_a_tib_takes_its_file_off_the_spill_grid() {
+    let dir = env::temp_dir().join(format!("segfs-far-spill-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let full = [b'x'; wire::MAX_PAYLOAD];
+    let mut file_manager = FileManager::with_backend(Backend::Spill, &dir);
+    file_manager.process_packet(wide(7, 0, &full));
+    let scratch = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    assert!(scratch.join("7.spill").exists());
+
+    file_manager.process_packet(wide(7, limits::MAX_PACKETS, &full));
+    assert!(!scratch.join("7.spill").exists());
+    assert_eq!(file_manager.payload(7, 0), Some(&full[..]));
+    assert_eq!(
+        file_manager.payload(7, limits::MAX_PACKETS),
+        Some(&full[..])
+    );
+    file_manager.clean_up().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
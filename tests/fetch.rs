@@ -0,0 +1,18 @@
+// `fetch`: the hello that names the files wanted, and the rule that ends
+// the session once they're in.
+
+use clap::Parser;
+use segmented_file_system_client::{cli::Cli, protocol, session};
+
+#[test]
+fn the_hello_names_the_files() {
+    let cli = Cli::try_parse_from(["client", "fetch", "--file-id", "2", "--file-id", "5"]).unwrap();
+    assert_eq!(protocol::requested_files(&session::hello(&cli)), [2, 5]);
+    assert_eq!(cli.completion().name(), "--file-id 2,5");
+
+    // Without `fetch`, it's every file and --until
+    let cli = Cli::try_parse_from(["client", "--until", "files=2"]).unwrap();
+    assert!(protocol::requested_files(&session::hello(&cli)).is_empty());
+    assert_eq!(cli.completion().name(), "--until files=2");
+    assert!(Cli::try_parse_from(["client", "fetch"]).is_err());
+}
@@ -0,0 +1,804 @@
+// Reassembly through the library: packets in any order, duplicates, gaps,
+// and files written out once they're whole.
+
+// What's missing is a list of ranges, even when there's only the one
+#![allow(clippy::single_range_in_vec_init)]
+
+use clap::ValueEnum;
+use segmented_file_system_client::{
+    assembly::Backend,
+    cli::{OnConflict, WriteOrder},
+    error::ClientError,
+    file_manager::{self, FileManager, RangeRead},
+    limits::Limits,
+    packet::{Metadata, Packet},
+    server,
+    sink::{DirSink, MemorySink, NullSink},
+    text::Newlines,
+};
+use std::{
+    convert::TryFrom,
+    env,
+    ffi::OsStr,
+    fs,
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+
+fn feed(file_manager: &mut FileManager, datagrams: &[&[u8]]) {
+    for bytes in datagrams {
+        file_manager.process_packet(Packet::try_from(*bytes).unwrap());
+    }
+}
+
+#[test]
+fn a_file_is_complete_with_its_header_and_every_packet() {
+    let mut file_manager = FileManager::default();
+    feed(
+        &mut file_manager,
+        &[b"\x03\x00\x00\x02c", b"\x01\x00\x00\x00a"],
+    );
+    assert!(!file_manager.is_complete(0));
+    assert_eq!(file_manager.missing_packets(0), Some(vec![1..2]));
+
+    feed(&mut file_manager, &[b"\x01\x00\x00\x01b"]);
+    assert!(!file_manager.is_complete(0));
+    assert_eq!(file_manager.headerless(), [0]);
+
+    feed(&mut file_manager, &[b"\x00\x00a.txt"]);
+    assert!(file_manager.is_complete(0));
+    assert_eq!(
+        file_manager.file_status(0),
+        Some((Some(OsStr::new("a.txt")), 3, Some(3)))
+    );
+}
+
+#[test]
+fn gaps_are_known_only_below_the_highest_packet_until_the_last() {
+    let mut file_manager = FileManager::default();
+    feed(
+        &mut file_manager,
+        &[b"\x01\x05\x00\x03d", b"\x01\x05\x00\x01b"],
+    );
+    assert_eq!(file_manager.missing_packets(5), Some(vec![0..1, 2..3]));
+    assert_eq!(file_manager.missing_packets(6), None);
+}
+
+#[test]
+fn gaps_list_every_file_in_order() {
+    let mut file_manager = FileManager::default();
+    feed(
+        &mut file_manager,
+        &[
+            b"\x03\x09\x00\x02c",
+            b"\x01\x02\x00\x02c",
+            b"\x01\x02\x00\x00a",
+            // Nothing missing below packet 0
+            b"\x01\x04\x00\x00a",
+        ],
+    );
+    let gaps: Vec<_> = file_manager.gaps(u32::MAX).collect();
+    assert_eq!(gaps, [(2, 1), (9, 0), (9, 1)]);
+}
+
+#[test]
+fn duplicates_are_counted_not_stored() {
+    let mut file_manager = FileManager::default();
+    let packets = [
+        b"\x01\x01\x00\x00a",
+        b"\x01\x01\x00\x00a",
+        b"\x01\x01\x00\x00a",
+    ];
+    file_manager.process_packets(
+        packets
+            .iter()
+            .map(|bytes| Packet::try_from(&bytes[..]).unwrap()),
+    );
+    assert_eq!(file_manager.duplicates(1), 2);
+    assert!(file_manager.has_packet(1, 0));
+    assert!(!file_manager.has_packet(1, 1));
+}
+
+#[test]
+fn a_duplicate_leaves_the_first_copy_alone() {
+    let mut file_manager = FileManager::default();
+    feed(
+        &mut file_manager,
+        &[b"\x01\x01\x00\x00a", b"\x01\x01\x00\x00b"],
+    );
+    assert_eq!(file_manager.duplicates(1), 1);
+    assert_eq!(file_manager.payload(1, 0), Some(&b"a"[..]));
+    assert_eq!(file_manager.payload(1, 1), None);
+}
+
+#[test]
+fn complete_files_are_written_in_order() {
+    let dir = env::temp_dir().join(format!("segfs-file-manager-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    for backend in [Backend::Hashmap, Backend::Vec] {
+        let mut file_manager = FileManager::with_backend(backend, &dir);
+        file_manager.on_conflict = OnConflict::Overwrite;
+        feed(
+            &mut file_manager,
+            &[
+                b"\x00\x02b.txt",
+                b"\x03\x02\x00\x00two",
+                b"\x01\x01\x00\x00one, ",
+                b"\x03\x01\x00\x01then more",
+                b"\x00\x01a.txt",
+                // Never finished, so never written
+                b"\x00\x03c.txt",
+            ],
+        );
+        let finalized = file_manager.write_all_files(&dir, WriteOrder::Name, None);
+        let written: Vec<u8> = finalized.written.iter().map(|(id, _)| *id).collect();
+        assert_eq!(written, [1, 2]);
+        assert!(finalized.failed.is_empty());
+        assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"one, then more");
+        assert_eq!(fs::read(dir.join("b.txt")).unwrap(), b"two");
+        assert!(!dir.join("c.txt").exists());
+        file_manager.clean_up().unwrap();
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+const TWO_FILES: &[&[u8]] = &[
+    b"\x00\x02b.txt",
+    b"\x03\x02\x00\x00two",
+    b"\x01\x01\x00\x00one, ",
+    b"\x03\x01\x00\x01then more",
+    b"\x00\x01a.txt",
+    b"\x00\x03c.txt",
+];
+
+#[test]
+fn sinks_get_the_complete_files_in_order() {
+    let mut file_manager = FileManager::default();
+    feed(&mut file_manager, TWO_FILES);
+    let mut sink = MemorySink::default();
+    let finalized = file_manager.write_all_to(&mut sink, WriteOrder::Name, None);
+    assert_eq!(
+        finalized.written,
+        [(1, PathBuf::from("a.txt")), (2, PathBuf::from("b.txt"))]
+    );
+    let names: Vec<&OsStr> = sink.files.keys().map(|name| name.as_os_str()).collect();
+    assert_eq!(names, ["a.txt", "b.txt"]);
+    assert_eq!(sink.files[OsStr::new("a.txt")], b"one, then more");
+
+    let mut null = NullSink::default();
+    file_manager.write_all_to(&mut null, WriteOrder::Id, None);
+    assert_eq!((null.files, null.bytes), (2, 17));
+}
+
+#[test]
+fn a_directory_sink_leaves_only_finished_files() {
+    let dir = env::temp_dir().join(format!("segfs-dir-sink-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut file_manager = FileManager::default();
+    feed(&mut file_manager, TWO_FILES);
+    let finalized = file_manager.write_all_to(&mut DirSink::new(&dir), WriteOrder::Id, None);
+    assert_eq!(finalized.written.len(), 2);
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"one, then more");
+    assert_eq!(fs::read(dir.join("b.txt")).unwrap(), b"two");
+    let mut names: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["a.txt", "b.txt"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_can_be_staged_somewhere_else() {
+    let dir = env::temp_dir().join(format!("segfs-staged-{}", std::process::id()));
+    let staging = dir.join("staging");
+    fs::create_dir_all(&staging).unwrap();
+    let mut file_manager = FileManager::default().with_temp_dir(&staging);
+    feed(&mut file_manager, TWO_FILES);
+    let out = dir.join("out");
+    fs::create_dir_all(&out).unwrap();
+    let finalized = file_manager.write_all_files(&out, WriteOrder::Id, None);
+    assert_eq!(finalized.written.len(), 2);
+    assert_eq!(fs::read(out.join("a.txt")).unwrap(), b"one, then more");
+    // The scratch directory was made there, not beside the files
+    assert_eq!(fs::read_dir(&out).unwrap().count(), 2);
+    assert_eq!(fs::read_dir(&staging).unwrap().count(), 1);
+    file_manager.clean_up().unwrap();
+    assert_eq!(fs::read_dir(&staging).unwrap().count(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn normalizing_rewrites_text_and_leaves_binary_alone() {
+    let dir = env::temp_dir().join(format!("segfs-normalize-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut file_manager = FileManager::default();
+    file_manager.newlines = Some(Newlines::Lf);
+    feed(
+        &mut file_manager,
+        &[
+            b"\x00\x01a.txt",
+            b"\x01\x01\x00\x00one\r",
+            b"\x03\x01\x00\x01\ntwo\r\n",
+            b"\x00\x02b.bin",
+            b"\x03\x02\x00\x00\x00\r\n",
+        ],
+    );
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    assert_eq!(finalized.written.len(), 2);
+    // The CRLF split across packets is still one line ending
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"one\ntwo\n");
+    assert_eq!(fs::read(dir.join("b.bin")).unwrap(), b"\x00\r\n");
+    file_manager.clean_up().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn files_already_there_go_by_the_policy() {
+    let dir = env::temp_dir().join(format!("segfs-conflict-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let write = |policy| {
+        let mut file_manager = FileManager::default();
+        file_manager.on_conflict = policy;
+        feed(
+            &mut file_manager,
+            &[b"\x00\x01a.txt", b"\x03\x01\x00\x00new"],
+        );
+        let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+        file_manager.clean_up().unwrap();
+        finalized
+    };
+    fs::write(dir.join("a.txt"), "old").unwrap();
+
+    let skipped = write(OnConflict::Skip);
+    assert_eq!(skipped.skipped, [(1, dir.join("a.txt"))]);
+    assert!(skipped.written.is_empty());
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"old");
+
+    let refused = write(OnConflict::Error);
+    assert_eq!(
+        refused.failed[0].1.kind(),
+        std::io::ErrorKind::AlreadyExists
+    );
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"old");
+
+    assert_eq!(
+        write(OnConflict::Rename).written,
+        [(1, dir.join("a.1.txt"))]
+    );
+    assert_eq!(
+        write(OnConflict::Rename).written,
+        [(1, dir.join("a.2.txt"))]
+    );
+    assert_eq!(fs::read(dir.join("a.2.txt")).unwrap(), b"new");
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"old");
+
+    assert_eq!(
+        write(OnConflict::Overwrite).written,
+        [(1, dir.join("a.txt"))]
+    );
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"new");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn identical_files_are_skipped_and_different_ones_renamed() {
+    let dir = env::temp_dir().join(format!("segfs-identical-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "new").unwrap();
+    fs::write(dir.join("b.txt"), "old").unwrap();
+    for backend in [Backend::Hashmap, Backend::Spill] {
+        let mut file_manager = FileManager::with_backend(backend, &dir);
+        file_manager.on_conflict = OnConflict::SkipIdentical;
+        feed(
+            &mut file_manager,
+            &[
+                b"\x00\x01a.txt",
+                b"\x03\x01\x00\x00new",
+                b"\x00\x02b.txt",
+                b"\x03\x02\x00\x00new",
+            ],
+        );
+        let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+        file_manager.clean_up().unwrap();
+        assert_eq!(finalized.skipped, [(1, dir.join("a.txt"))]);
+        assert_eq!(finalized.written.len(), 1);
+        assert_eq!(fs::read(&finalized.written[0].1).unwrap(), b"new");
+        fs::remove_file(&finalized.written[0].1).unwrap();
+    }
+    // Nothing left behind from the comparing
+    assert!(!dir.join("a.1.txt").exists());
+    assert_eq!(fs::read(dir.join("b.txt")).unwrap(), b"old");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn versions_go_after_the_whole_name() {
+    let dir = env::temp_dir().join(format!("segfs-version-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "old").unwrap();
+    let write = || {
+        let mut file_manager = FileManager::default();
+        file_manager.on_conflict = OnConflict::VersionSuffix;
+        feed(
+            &mut file_manager,
+            &[b"\x00\x01a.txt", b"\x03\x01\x00\x00new"],
+        );
+        let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+        file_manager.clean_up().unwrap();
+        finalized.written
+    };
+    assert_eq!(write(), [(1, dir.join("a.txt.v2"))]);
+    assert_eq!(write(), [(1, dir.join("a.txt.v3"))]);
+    assert_eq!(fs::read(dir.join("a.txt.v3")).unwrap(), b"new");
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"old");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_sessions_collisions_share_one_timestamped_directory() {
+    let dir = env::temp_dir().join(format!("segfs-timestamp-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "old").unwrap();
+    fs::write(dir.join("b.txt"), "old").unwrap();
+    let mut file_manager = FileManager::default();
+    file_manager.on_conflict = OnConflict::TimestampSubdir;
+    feed(
+        &mut file_manager,
+        &[
+            b"\x00\x01a.txt",
+            b"\x03\x01\x00\x00new a",
+            b"\x00\x02b.txt",
+            b"\x03\x02\x00\x00new b",
+            b"\x00\x03c.txt",
+            b"\x03\x03\x00\x00new c",
+        ],
+    );
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    file_manager.clean_up().unwrap();
+    let [(1, a), (2, b), (3, c)] = &finalized.written[..] else {
+        panic!("not all written");
+    };
+    let subdir = a.parent().unwrap();
+    assert_eq!(b.parent(), Some(subdir));
+    assert_eq!(subdir.parent(), Some(&*dir));
+    // Something like 2025-03-01T14-05-09.250Z, with nothing Windows refuses
+    let stamp = subdir.file_name().unwrap().to_str().unwrap();
+    assert!(stamp.ends_with('Z') && !stamp.contains(':'));
+    assert_eq!(fs::read(b).unwrap(), b"new b");
+    // No collision, so it goes where it always would
+    assert_eq!(*c, dir.join("c.txt"));
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"old");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn free_names_keep_the_extension_last() {
+    let dir = env::temp_dir().join("segfs-free-name-that-isnt-there");
+    let free = |name: &str| file_manager::free_name(&dir.join(name));
+    assert_eq!(free("a.tar.gz"), dir.join("a.tar.1.gz"));
+    assert_eq!(free("README"), dir.join("README.1"));
+    assert_eq!(free(".profile"), dir.join(".profile.1"));
+}
+
+// A Latin-1 name is written under the bytes it came as
+#[cfg(unix)]
+#[test]
+fn names_that_arent_utf8_are_written_as_sent() {
+    use std::os::unix::ffi::OsStrExt;
+    let dir = env::temp_dir().join(format!("segfs-latin1-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut file_manager = FileManager::default();
+    feed(
+        &mut file_manager,
+        &[b"\x00\x01caf\xe9.txt", b"\x03\x01\x00\x00menu"],
+    );
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    let path = dir.join(OsStr::from_bytes(b"caf\xe9.txt"));
+    assert_eq!(finalized.written, [(1, path.clone())]);
+    assert_eq!(fs::read(&path).unwrap(), b"menu");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn two_last_packets_need_everything_up_to_the_furthest() {
+    let mut file_manager = FileManager::default();
+    feed(
+        &mut file_manager,
+        &[
+            b"\x00\x01a.txt",
+            b"\x03\x01\x00\x04four",
+            b"\x01\x01\x00\x00zero",
+            b"\x03\x01\x00\x01one",
+            b"\x01\x01\x00\x02two",
+        ],
+    );
+    // Four packets, as the packet saying 1 was the last would have it, but
+    // not the five the one saying 4 needs
+    assert_eq!(file_manager.file_status(1).unwrap().2, Some(5));
+    assert!(!file_manager.is_complete(1));
+    feed(&mut file_manager, &[b"\x01\x01\x00\x03three"]);
+    assert!(file_manager.is_complete(1));
+}
+
+#[test]
+fn files_are_checked_against_their_checksum_packets() {
+    let dir = env::temp_dir().join(format!("segfs-checksum-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // SHA-256 of "abc"
+    let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let checksum = |file_id: u8| {
+        let mut bytes = vec![0x04, file_id];
+        bytes.extend((0..32).map(|i| u8::from_str_radix(&digest[i * 2..i * 2 + 2], 16).unwrap()));
+        bytes
+    };
+    for backend in [Backend::Hashmap, Backend::Spill] {
+        let mut file_manager = FileManager::with_backend(backend, &dir);
+        file_manager.on_conflict = OnConflict::Overwrite;
+        feed(
+            &mut file_manager,
+            &[
+                &checksum(1),
+                b"\x00\x01a.txt",
+                b"\x01\x01\x00\x00ab",
+                b"\x03\x01\x00\x01c",
+                b"\x00\x02b.txt",
+                b"\x03\x02\x00\x00abd",
+                &checksum(2),
+            ],
+        );
+        let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+        assert_eq!(finalized.written.len(), 2);
+        assert_eq!(finalized.mismatched, [(2, dir.join("b.txt"))]);
+        assert_eq!(fs::read(dir.join("b.txt")).unwrap(), b"abd");
+        // What quarantine's failure.json gives as expected
+        assert_eq!(file_manager.checksum(2).as_deref(), Some(digest));
+        assert!(matches!(
+            finalized.into_result(),
+            Err(ClientError::ChecksumMismatch { files: 1 })
+        ));
+        file_manager.clean_up().unwrap();
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_file_past_max_file_size_is_not_written() {
+    let dir = env::temp_dir().join(format!("segfs-max-file-size-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let limits = Limits {
+        max_file_size: Some(4),
+        ..Limits::default()
+    };
+    let mut file_manager = FileManager::default().with_limits(&limits, &dir);
+    // Straight in, the way --resume's log is read back, past the screen
+    feed(
+        &mut file_manager,
+        &[
+            b"\x00\x01big.txt",
+            b"\x03\x01\x00\x00bigger",
+            b"\x00\x02ok.txt",
+            b"\x03\x02\x00\x00fine",
+        ],
+    );
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    assert_eq!(finalized.written, [(2, dir.join("ok.txt"))]);
+    assert_eq!(finalized.failed.len(), 1);
+    let (file_id, e) = &finalized.failed[0];
+    assert_eq!(*file_id, 1);
+    assert!(e.to_string().contains("more than 4B"), "{e}");
+    assert!(!dir.join("big.txt").exists());
+    file_manager.clean_up().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_spill_file_is_as_long_as_its_header_says_from_the_start() {
+    let dir = env::temp_dir().join(format!("segfs-reserve-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let content: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+    let sized = |size| Metadata {
+        size: Some(size),
+        ..Metadata::default()
+    };
+    // The header says too little for file 1, and wasn't wrong about file 0
+    let mut packets = server::packets(0, "a.bin", &content, sized(100_000));
+    packets.extend(server::packets(1, "b.bin", &content, sized(10)));
+    let mut file_manager = FileManager::with_backend(Backend::Spill, &dir);
+    file_manager.process_packet(packets.remove(0));
+    let scratch = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let spilled = || fs::metadata(scratch.join("0.spill")).unwrap().len();
+    assert_eq!(spilled(), 100_000);
+    // Last first
+    file_manager.process_packets(packets.into_iter().rev());
+    assert_eq!(spilled(), 100_000);
+
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    assert_eq!(finalized.written.len(), 2);
+    assert_eq!(fs::read(dir.join("a.bin")).unwrap(), content);
+    assert_eq!(fs::read(dir.join("b.bin")).unwrap(), content);
+    file_manager.clean_up().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn partial_files_keep_their_packets_in_place() {
+    let dir = env::temp_dir().join(format!("segfs-partial-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut file_manager = FileManager::default();
+    let mut second = vec![0x01, 0x01, 0x00, 0x02];
+    second.extend_from_slice(b"xy");
+    feed(
+        &mut file_manager,
+        &[
+            b"\x00\x01a.txt",
+            b"\x01\x01\x00\x00ab",
+            &second,
+            // Complete, so written the usual way and not kept as partial
+            b"\x00\x02b.txt",
+            b"\x03\x02\x00\x00done",
+            // No header
+            b"\x03\x03\x00\x01z",
+        ],
+    );
+    let partial = file_manager.write_partial_files(&dir).unwrap();
+    let kept: Vec<_> = partial.iter().map(|file| file.partial.as_str()).collect();
+    assert_eq!(kept, ["a.txt.partial", "unnamed-3.bin.partial"]);
+    assert_eq!(partial[0].missing, [1..2]);
+    assert_eq!(partial[0].expected, None);
+    assert_eq!(partial[1].missing, [0..1]);
+    assert_eq!(partial[1].expected, Some(2));
+
+    let a = fs::read(dir.join("a.txt.partial")).unwrap();
+    assert_eq!(a.len(), 2 * 1024 + 2);
+    assert_eq!(&a[..2], b"ab");
+    assert!(a[2..2048].iter().all(|&byte| byte == 0));
+    assert_eq!(&a[2048..], b"xy");
+
+    let json: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.join(file_manager::PARTIAL_MANIFEST)).unwrap())
+            .unwrap();
+    assert_eq!(json["files"][0]["name"], "a.txt");
+    assert_eq!(json["files"][1]["missing"], serde_json::json!(["0"]));
+    assert!(!dir.join("b.txt.partial").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn past_max_memory_the_biggest_file_goes_to_disk() {
+    let dir = env::temp_dir().join(format!("segfs-max-memory-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let big: Vec<u8> = (0..6000).map(|i| (i % 251) as u8).collect();
+    let mut file_manager = FileManager::default().with_max_memory(4096, &dir);
+    // Last packet first, so it's all out of order
+    let mut packets = server::packets(1, "big.bin", &big, Metadata::default());
+    packets.reverse();
+    packets.extend(server::packets(
+        2,
+        "small.txt",
+        b"small",
+        Metadata::default(),
+    ));
+    for packet in packets {
+        file_manager.process_packet(packet);
+    }
+    let scratch = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    assert!(scratch.join("1.spill").exists());
+    assert!(!scratch.join("2.spill").exists());
+    assert_eq!(file_manager.payload(1, 0), Some(&big[..1024]));
+
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    assert_eq!(finalized.written.len(), 2);
+    assert_eq!(fs::read(dir.join("big.bin")).unwrap(), big);
+    assert_eq!(fs::read(dir.join("small.txt")).unwrap(), b"small");
+    file_manager.clean_up().unwrap();
+
+    // A short packet before the last keeps a file off the grid, so in memory
+    let mut file_manager = FileManager::default().with_max_memory(0, &dir);
+    feed(
+        &mut file_manager,
+        &[
+            b"\x00\x03odd.txt",
+            b"\x01\x03\x00\x00short",
+            b"\x03\x03\x00\x01 one",
+        ],
+    );
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    assert_eq!(finalized.written.len(), 1);
+    assert_eq!(fs::read(dir.join("odd.txt")).unwrap(), b"short one");
+    file_manager.clean_up().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn with_writers_a_file_is_written_once_its_complete() {
+    let dir = env::temp_dir().join(format!("segfs-writers-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let big: Vec<u8> = (0..3000).map(|i| (i % 251) as u8).collect();
+    // Spilled on the way, so it's finished from disk by a writer
+    let mut file_manager = (FileManager::default())
+        .with_max_memory(0, &dir)
+        .with_writers(2, &dir);
+    for packet in server::packets(1, "big.bin", &big, Metadata::default()) {
+        file_manager.process_packet(packet);
+    }
+    // Its header last
+    feed(
+        &mut file_manager,
+        &[
+            b"\x03\x02\x00\x00early",
+            b"\x00\x02a.txt",
+            b"\x01\x03\x00\x00not yet",
+        ],
+    );
+    let written = dir.join("a.txt");
+    for _ in 0..500 {
+        if written.exists() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(fs::read(&written).unwrap(), b"early");
+    assert!(file_manager.is_complete(2));
+    assert_eq!(file_manager.payload(2, 0), None, "handed over");
+
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    let mut ids: Vec<_> = finalized.written.iter().map(|(id, _)| *id).collect();
+    ids.sort();
+    assert_eq!(ids, [1, 2]);
+    assert_eq!(fs::read(dir.join("big.bin")).unwrap(), big);
+    file_manager.clean_up().unwrap();
+    let names: Vec<_> = (fs::read_dir(&dir).unwrap())
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names.len(), 2, "{names:?}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn the_head_of_a_file_can_be_read_before_the_tail_arrives() {
+    let mut file_manager = FileManager::default();
+    let mut first = b"\x01\x04\x00\x00".to_vec();
+    first.extend([b'a'; 1024]);
+    feed(&mut file_manager, &[&first, b"\x03\x04\x00\x02cc"]);
+    assert_eq!(
+        file_manager.read_range(4, 1020..1030),
+        RangeRead::NotYetAvailable { missing: vec![1] }
+    );
+    assert_eq!(
+        file_manager.read_range(4, 1020..1024),
+        RangeRead::Bytes(b"aaaa".to_vec())
+    );
+
+    let mut second = b"\x01\x04\x00\x01".to_vec();
+    second.extend([b'b'; 1024]);
+    feed(&mut file_manager, &[&second]);
+    assert_eq!(
+        file_manager.read_range(4, 2047..4000),
+        RangeRead::Bytes(b"bcc".to_vec())
+    );
+    assert_eq!(
+        file_manager.read_range(4, 2050..2060),
+        RangeRead::PastEnd { size: 2050 }
+    );
+}
+
+#[test]
+fn plain_and_wide_packets_make_one_file() {
+    for backend in [Backend::Hashmap, Backend::Vec, Backend::Mmap] {
+        let mut file_manager = FileManager::with_backend(backend, &env::temp_dir());
+        feed(
+            &mut file_manager,
+            &[
+                b"\x00\x03big.bin",
+                b"\x01\x03\x00\x00a",
+                b"\x0b\x03\x00\x01\x00\x00z",
+            ],
+        );
+        assert_eq!(file_manager.file_status(3).unwrap().2, Some(65537));
+        assert_eq!(file_manager.payload(3, 65536), Some(&b"z"[..]));
+        assert_eq!(file_manager.missing_packets(3), Some(vec![1..65536]));
+        // As far as a retransmission request can ask for them
+        assert_eq!(file_manager.gaps(1 << 16).count(), 65535);
+        assert_eq!(file_manager.gaps(100).last(), Some((3, 99)));
+    }
+}
+
+#[test]
+fn a_written_file_gets_what_its_extended_header_said() {
+    let dir = env::temp_dir().join(format!("segfs-metadata-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let metadata = Metadata {
+        size: Some(3000),
+        modified: Some(1_000_000_000),
+        mode: Some(0o4640),
+        ..Metadata::default()
+    };
+    let mut file_manager = FileManager::default();
+    for packet in server::packets(1, "a.bin", &[7; 3000], metadata) {
+        file_manager.process_packet(packet);
+    }
+    assert_eq!(file_manager.metadata(1), Some(metadata));
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Name, None);
+    assert_eq!(finalized.written.len(), 1);
+
+    let written = fs::metadata(dir.join("a.bin")).unwrap();
+    assert_eq!(written.len(), 3000);
+    assert_eq!(
+        written.modified().unwrap(),
+        UNIX_EPOCH + Duration::from_secs(1_000_000_000)
+    );
+    // Without the set-user-ID bit
+    #[cfg(unix)]
+    assert_eq!(
+        std::os::unix::fs::PermissionsExt::mode(&written.permissions()) & 0o7777,
+        0o640
+    );
+    file_manager.clean_up().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_name_with_directories_is_written_down_in_them() {
+    let dir = env::temp_dir().join(format!("segfs-tree-{}", std::process::id()));
+    let datagrams: &[&[u8]] = &[
+        b"\x00\x01src/lib/util.rs",
+        b"\x03\x01\x00\x00fn x() {}",
+        b"\x00\x02src/main.rs",
+        b"\x03\x02\x00\x00fn main() {}",
+    ];
+    let mut file_manager = FileManager::default();
+    feed(&mut file_manager, datagrams);
+    let finalized = file_manager.write_all_files(&dir, WriteOrder::Id, None);
+    assert!(finalized.failed.is_empty(), "{:?}", finalized.failed);
+    assert_eq!(fs::read(dir.join("src/lib/util.rs")).unwrap(), b"fn x() {}");
+    assert_eq!(fs::read(dir.join("src/main.rs")).unwrap(), b"fn main() {}");
+    file_manager.clean_up().unwrap();
+
+    let sunk = dir.join("sunk");
+    let mut file_manager = FileManager::default();
+    feed(&mut file_manager, datagrams);
+    file_manager.write_all_to(&mut DirSink::new(&sunk), WriteOrder::Id, None);
+    let names: Vec<_> = (fs::read_dir(sunk.join("src/lib")).unwrap())
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names, ["util.rs"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn empty_files_are_complete_and_written_empty() {
+    let dir = env::temp_dir().join(format!("segfs-empty-{}", std::process::id()));
+    let sized = Metadata {
+        size: Some(0),
+        ..Metadata::default()
+    };
+    for &backend in Backend::value_variants() {
+        let mut file_manager = FileManager::with_backend(backend, &dir);
+        // The header, then one last packet with nothing in it
+        for packet in server::packets(0, "plain.txt", b"", Metadata::default()) {
+            file_manager.process_packet(packet);
+        }
+        // A header that says it's empty, and no data packets at all
+        let mut header_only = server::packets(1, "header.txt", b"", sized);
+        header_only.truncate(1);
+        file_manager.process_packets(header_only.into_iter());
+        assert!(file_manager.is_complete(1), "{backend:?}");
+        assert_eq!(file_manager.missing_packets(1), Some(vec![]));
+        // An empty last packet that comes after all is a packet, not a gap
+        feed(&mut file_manager, &[b"\x03\x01\x00\x00"]);
+        // A plain header says nothing of the size, so that file waits
+        feed(&mut file_manager, &[b"\x00\x02unsized.txt"]);
+        assert!(!file_manager.is_complete(2));
+
+        let finalized = file_manager.write_all_files(&dir, WriteOrder::Name, None);
+        assert!(finalized.failed.is_empty(), "{:?}", finalized.failed);
+        for name in ["plain.txt", "header.txt"] {
+            assert_eq!(fs::read(dir.join(name)).unwrap(), b"", "{backend:?}");
+        }
+        file_manager.clean_up().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+// The filesystem wrappers' retries: a signal's Interrupted is tried again
+// for as long as it takes, a transient error a few times, and anything else
+// is handed straight back with what was being done to which file.
+
+use segmented_file_system_client::fsio::{self, Action};
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+// An op that fails with `kind` the first `failures` times it's called,
+// counting every call
+fn failing(
+    kind: io::ErrorKind,
+    failures: u32,
+    calls: &mut u32,
+) -> impl FnMut() -> io::Result<u32> + '_ {
+    move || {
+        *calls += 1;
+        match *calls <= failures {
+            true => Err(kind.into()),
+            false => Ok(*calls),
+        }
+    }
+}
+
+#[test]
+fn an_interrupted_call_is_made_again() {
+    let mut calls = 0;
+    let result = fsio::retry(failing(io::ErrorKind::Interrupted, 5, &mut calls));
+    assert_eq!(result.unwrap(), 6);
+
+    let mut calls = 0;
+    let e = fsio::retry(failing(io::ErrorKind::NotFound, 5, &mut calls)).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn a_transient_error_is_tried_again_up_to_the_limit() {
+    for kind in [
+        io::ErrorKind::WouldBlock,
+        io::ErrorKind::TimedOut,
+        io::ErrorKind::ResourceBusy,
+    ] {
+        assert!(fsio::is_transient(&kind.into()), "{kind:?}");
+        let mut calls = 0;
+        let result = fsio::retry_transient(3, failing(kind, 2, &mut calls));
+        assert_eq!(result.unwrap(), 3, "{kind:?}");
+
+        // The last attempt's error is the one that comes back
+        let mut calls = 0;
+        let e = fsio::retry_transient(3, failing(kind, 3, &mut calls)).unwrap_err();
+        assert_eq!((e.kind(), calls), (kind, 3));
+    }
+}
+
+#[test]
+fn other_errors_arent_tried_again() {
+    for kind in [
+        io::ErrorKind::NotFound,
+        io::ErrorKind::PermissionDenied,
+        io::ErrorKind::StorageFull,
+    ] {
+        assert!(!fsio::is_transient(&kind.into()), "{kind:?}");
+        let mut calls = 0;
+        let e = fsio::retry_transient(3, failing(kind, 1, &mut calls)).unwrap_err();
+        assert_eq!((e.kind(), calls), (kind, 1));
+    }
+    // One attempt is just the call
+    let mut calls = 0;
+    let e = fsio::retry_transient(1, failing(io::ErrorKind::TimedOut, 1, &mut calls));
+    assert_eq!((e.unwrap_err().kind(), calls), (io::ErrorKind::TimedOut, 1));
+}
+
+#[test]
+fn a_failure_says_what_and_where_and_keeps_its_kind() {
+    let path = Path::new("out/a.txt");
+    let mut calls = 0;
+    let result = fsio::at(
+        Action::Read,
+        path,
+        failing(io::ErrorKind::Interrupted, 2, &mut calls),
+    );
+    assert_eq!(result.unwrap(), 3);
+
+    let mut calls = 0;
+    let e = fsio::at(
+        Action::Read,
+        path,
+        failing(io::ErrorKind::PermissionDenied, 2, &mut calls),
+    )
+    .unwrap_err();
+    assert_eq!((e.kind(), calls), (io::ErrorKind::PermissionDenied, 1));
+    assert!(e.to_string().contains("out/a.txt"), "{e}");
+}
+
+// Takes at most three bytes a call, and is interrupted before every other
+// one; or takes nothing at all
+struct Awkward {
+    written: Vec<u8>,
+    calls: u32,
+    stuck: bool,
+}
+
+impl Write for Awkward {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.calls += 1;
+        if self.stuck {
+            return Ok(0);
+        }
+        if self.calls % 2 == 1 {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        let len = bytes.len().min(3);
+        self.written.extend(&bytes[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn write_all_gets_everything_out_or_says_it_couldnt() {
+    let path = Path::new("a.txt");
+    let mut out = Awkward {
+        written: Vec::new(),
+        calls: 0,
+        stuck: false,
+    };
+    fsio::write_all(&mut out, path, b"ten bytes!").unwrap();
+    assert_eq!(out.written, b"ten bytes!");
+
+    let mut stuck = Awkward {
+        written: Vec::new(),
+        calls: 0,
+        stuck: true,
+    };
+    let e = fsio::write_all(&mut stuck, path, b"x").unwrap_err();
+    assert_eq!((e.kind(), stuck.calls), (io::ErrorKind::WriteZero, 1));
+}
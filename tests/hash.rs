@@ -0,0 +1,78 @@
+// Every --hash algorithm against its published test vectors, and feeding a
+// hasher in pieces against hashing in one go.
+
+use segmented_file_system_client::hash::HashAlgorithm;
+
+const ALGORITHMS: [HashAlgorithm; 3] = [
+    HashAlgorithm::Blake3,
+    HashAlgorithm::Sha256,
+    HashAlgorithm::Crc32,
+];
+
+#[test]
+fn blake3_vectors() {
+    assert_eq!(
+        HashAlgorithm::Blake3.digest(b""),
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+    );
+    assert_eq!(
+        HashAlgorithm::Blake3.digest(b"abc"),
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+}
+
+#[test]
+fn sha256_vectors() {
+    assert_eq!(
+        HashAlgorithm::Sha256.digest(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        HashAlgorithm::Sha256.digest(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        HashAlgorithm::Sha256.digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+}
+
+#[test]
+fn crc32_vectors() {
+    assert_eq!(HashAlgorithm::Crc32.digest(b""), "00000000");
+    assert_eq!(HashAlgorithm::Crc32.digest(b"123456789"), "cbf43926");
+    assert_eq!(
+        HashAlgorithm::Crc32.digest(b"The quick brown fox jumps over the lazy dog"),
+        "414fa339"
+    );
+}
+
+#[test]
+fn pieces_hash_the_same_as_the_whole() {
+    let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 + i / 13) as u8).collect();
+    for algorithm in ALGORITHMS {
+        let whole = algorithm.digest(&data);
+        for piece in [1, 63, 1024, 4999] {
+            let mut hasher = algorithm.hasher();
+            for chunk in data.chunks(piece) {
+                hasher.update(chunk);
+            }
+            assert_eq!(
+                hasher.finish(),
+                whole,
+                "{algorithm:?} in {piece}-byte pieces"
+            );
+        }
+    }
+}
+
+#[test]
+fn tagged_digests_name_the_algorithm() {
+    assert_eq!(HashAlgorithm::Crc32.tagged(b"123456789"), "crc32:cbf43926");
+    assert!(HashAlgorithm::Sha256
+        .tagged(b"")
+        .starts_with("sha256:e3b0c442"));
+    assert!(HashAlgorithm::Blake3
+        .tagged(b"")
+        .starts_with("blake3:af1349b9"));
+}
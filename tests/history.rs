@@ -0,0 +1,107 @@
+// --history and `report`: sessions added up in the history file, and the
+// page drawn from it, which must stand on its own.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::{Cli, WriteOrder},
+    events::Progress,
+    file_manager::FileManager,
+    history,
+    session::run_session,
+    transport::{ManualClock, ScriptedTransport, Step},
+};
+use std::{env, fs, path::PathBuf, process::Command, time::Duration};
+
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("segfs-history-{name}-{}", std::process::id()))
+}
+
+#[test]
+fn sessions_add_up_into_a_page() {
+    let dir = scratch("out");
+    let history = scratch("history.jsonl");
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_file(&history);
+    fs::create_dir_all(&dir).unwrap();
+    let cli = Cli::try_parse_from(["client", "--quiet", "--until", "quiet=1s"]).unwrap();
+    for _ in 1..=2 {
+        let clock = ManualClock::default();
+        let steps = [
+            &b"\x00\x02lost.bin"[..],
+            b"\x03\x02\x00\x02end",
+            b"\x00\x01<b>.txt",
+            b"\x01\x01\x00\x00one",
+            b"\x03\x01\x00\x01two",
+        ];
+        let steps = steps.iter().map(|bytes| Step::Datagram(bytes.to_vec()));
+        let steps = steps.chain([Step::Silence(Duration::from_secs(2))]);
+        let mut transport = ScriptedTransport::new(steps, &clock);
+        let (mut files, report) = run_session(
+            &mut transport,
+            &clock,
+            FileManager::default(),
+            &mut Progress::default(),
+            &cli,
+            None,
+        )
+        .unwrap();
+        let finalized = files.write_all_files(&dir, WriteOrder::Id, None);
+        let entry = history::Entry::new(&report, &files, &finalized, WriteOrder::Id);
+        history::append(&history, &entry).unwrap();
+        fs::remove_file(dir.join("<b>.txt")).unwrap();
+    }
+    let entries = history::load(&history).unwrap();
+    assert_eq!(entries.len(), 2);
+    let latest = &entries[1];
+    assert_eq!((latest.packets, latest.bytes > 0), (5, true));
+    // File 2 never got packets 0 and 1
+    assert_eq!(latest.missing, 2);
+    assert_eq!(latest.loss(), 2.0 / 7.0);
+    assert_eq!(latest.throughput.iter().sum::<u64>(), 9);
+    let statuses: Vec<_> = (latest.files.iter())
+        .map(|file| (file.file_id, file.name.as_str(), file.status.as_str()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [(1, "<b>.txt", "complete"), (2, "lost.bin", "incomplete")]
+    );
+    assert!(latest.files.iter().all(|file| file.first_ms.is_some()));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--history", history.to_str().unwrap()])
+        .args(["--output-dir", dir.to_str().unwrap(), "report"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let page_path = dir.join(history::PAGE_NAME);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("wrote {}\n", page_path.display())
+    );
+    let page = fs::read_to_string(&page_path).unwrap();
+    assert!(page.starts_with("<!DOCTYPE html>"), "{page}");
+    assert!(page.contains("<h2>Latest session, started "), "{page}");
+    assert!(
+        page.contains("&lt;b&gt;.txt") && !page.contains("<b>"),
+        "{page}"
+    );
+    assert_eq!(page.matches("<tr><td>").count(), 2, "{page}");
+    // Nothing to fetch from anywhere
+    assert!(!page.contains("http") && !page.contains("src="), "{page}");
+
+    // Without --history there's nothing to draw
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "report"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_file(&history).unwrap();
+}
+
+#[test]
+fn an_empty_history_says_so() {
+    let page = history::render(&[]);
+    assert!(page.contains("No sessions in the history yet."), "{page}");
+    assert!(page.ends_with("</html>\n"));
+}
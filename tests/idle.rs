@@ -0,0 +1,157 @@
+// --idle-after: a transfer that goes quiet partway, what the diagnostics
+// say is missing, and each --on-idle policy, on a scripted clock.
+
+// What's missing is a list of ranges, even when there's only the one
+#![allow(clippy::single_range_in_vec_init)]
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::Cli,
+    error::ClientError,
+    events::Progress,
+    file_manager::FileManager,
+    idle::{self, Incomplete},
+    nack,
+    packet::Packet,
+    report::SessionReport,
+    session::{self, run_session},
+    transport::{ManualClock, ScriptedTransport, Step},
+    units,
+};
+use std::{
+    convert::TryFrom,
+    time::{Duration, UNIX_EPOCH},
+};
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from(["client", "--until", "files=1"].iter().chain(args)).unwrap()
+}
+
+// Packet 1 missing, and the last not here yet
+const STARTED: [&[u8]; 3] = [
+    b"\x00\x01a.txt",
+    b"\x01\x01\x00\x00one, ",
+    b"\x01\x01\x00\x02three, ",
+];
+const REST: [&[u8]; 2] = [b"\x01\x01\x00\x01two, ", b"\x03\x01\x00\x03four"];
+
+// The start, a silence, and then the rest
+fn script(silence: Duration) -> Vec<Step> {
+    let mut steps: Vec<Step> = (STARTED.iter())
+        .map(|b| Step::Datagram(b.to_vec()))
+        .collect();
+    steps.push(Step::Silence(silence));
+    steps.extend(REST.iter().map(|b| Step::Datagram(b.to_vec())));
+    steps
+}
+
+type Outcome = Result<(FileManager, SessionReport), ClientError>;
+
+// The session's outcome and what it sent
+fn run(steps: Vec<Step>, cli: &Cli) -> (Outcome, Vec<Vec<u8>>) {
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let result = run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        cli,
+        None,
+    );
+    (result, transport.sent)
+}
+
+#[test]
+fn the_diagnostics_list_what_each_file_is_missing() {
+    let mut file_manager = FileManager::default();
+    for bytes in STARTED.iter().chain([&&b"\x03\x02\x00\x05x"[..]]) {
+        file_manager.process_packet(Packet::try_from(*bytes).unwrap());
+    }
+    let files = idle::incomplete(&file_manager);
+    assert_eq!(
+        files[0],
+        Incomplete {
+            file_id: 1,
+            name: Some("a.txt".to_string()),
+            received: 2,
+            expected: None,
+            missing: vec![1..2],
+        }
+    );
+    assert_eq!(files[1].missing, [0..5]);
+
+    let report = idle::report(&files, Duration::from_secs(5), UNIX_EPOCH);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "no packets for 5s, the last at 1970-01-01T00:00:00.000Z; 2 files still incomplete:",
+            "  file 1 (a.txt): 2 of ? packets, 1 missing: 1",
+            "  file 2 (no header yet): 1 of 6 packets, 5 missing: 0-4",
+        ]
+    );
+    assert_eq!(units::format_ranges(&[0, 1, 2, 5, 7, 8], 2), "0-2, 5, ...");
+    assert_eq!(units::format_spans(&[0..3, 5..6, 7..9], 2), "0-2, 5, ...");
+}
+
+#[test]
+fn a_quiet_spell_sends_the_request_again_and_the_transfer_carries_on() {
+    let (result, sent) = run(
+        script(Duration::from_secs(3)),
+        &cli(&["--idle-after", "2s"]),
+    );
+    let (files, report) = result.unwrap();
+    assert!(files.is_complete(1));
+    assert_eq!(report.summary()["idle"], 1);
+    assert_eq!(sent.len(), 1);
+    assert_ne!(sent[0], session::HELLO, "goes with a resume token");
+
+    // Too short a spell doesn't count
+    let (result, sent) = run(
+        script(Duration::from_secs(1)),
+        &cli(&["--idle-after", "2s"]),
+    );
+    assert_eq!(result.unwrap().1.summary()["idle"], 0);
+    assert!(sent.is_empty());
+}
+
+#[test]
+fn each_policy_does_what_it_says() {
+    let nack = cli(&["--idle-after", "2s", "--on-idle", "nack"]);
+    let (result, sent) = run(script(Duration::from_secs(3)), &nack);
+    assert!(result.is_ok());
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].len(), nack::HEADER_LEN + nack::PAIR_LEN);
+
+    let report = cli(&["--idle-after", "2s", "--on-idle", "report"]);
+    let (result, sent) = run(script(Duration::from_secs(3)), &report);
+    assert_eq!(result.unwrap().1.summary()["idle"], 1);
+    assert!(sent.is_empty());
+
+    let abort = cli(&["--idle-after", "2s", "--on-idle", "abort"]);
+    let (result, _) = run(script(Duration::from_secs(3)), &abort);
+    let Err(e @ ClientError::TransferIdle { incomplete: 1, .. }) = result else {
+        panic!("the session should have given up");
+    };
+    assert_eq!(e.exit_code(), 12);
+    assert_eq!(e.code(), "transfer-idle");
+}
+
+#[test]
+fn it_needs_a_transfer_to_have_started() {
+    // Quiet before anything arrives is --first-packet-timeout's business
+    let mut steps = vec![Step::Silence(Duration::from_secs(3))];
+    steps.extend(script(Duration::ZERO));
+    let abort = cli(&[
+        "--idle-after",
+        "2s",
+        "--on-idle",
+        "abort",
+        "--hello-interval",
+        "1h",
+    ]);
+    let (result, _) = run(steps, &abort);
+    assert!(result.is_ok());
+    assert!(Cli::try_parse_from(["client", "--on-idle", "abort"]).is_err());
+}
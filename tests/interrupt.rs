@@ -0,0 +1,93 @@
+// Ctrl-C partway through a transfer: the session stops where it stands, the
+// whole files are written, the rest kept as .partial files, and the run
+// ends with the status a shell gives one killed by SIGINT. The flag is the
+// whole process's, so this has a test binary of its own.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    callbacks::{Callbacks, ClientEvents},
+    cli::Cli,
+    client,
+    error::ClientError,
+    events::Progress,
+    file_manager::{FileManager, PARTIAL_MANIFEST},
+    interrupt,
+    session::run_session,
+    transport::{ManualClock, ScriptedTransport, Step, Transport},
+};
+use std::{env, fs, time::Duration};
+
+// Ctrl-C as soon as file 2's first packet is in
+struct CtrlC;
+
+impl ClientEvents for CtrlC {
+    fn on_progress(&mut self, file_id: u8, received: usize, _expected: Option<u32>) {
+        if file_id == 2 && received > 0 {
+            interrupt::request();
+        }
+    }
+}
+
+#[test]
+fn ctrl_c_keeps_what_came_and_exits_130() {
+    let dir = env::temp_dir().join(format!("segfs-interrupt-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let cli = Cli::try_parse_from([
+        "client",
+        "--until",
+        "files=2",
+        "--output-dir",
+        dir.to_str().unwrap(),
+    ])
+    .unwrap();
+    let steps: Vec<Step> = [
+        &b"\x00\x01a.txt"[..],
+        b"\x03\x01\x00\x00whole",
+        b"\x00\x02b.txt",
+        b"\x01\x02\x00\x00first ",
+        // Never looked at
+        b"\x03\x02\x00\x02last",
+    ]
+    .iter()
+    .map(|bytes| Step::Datagram(bytes.to_vec()))
+    .collect();
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    transport
+        .set_poll_interval(Some(Duration::from_millis(50)))
+        .unwrap();
+    let mut progress = Progress::default();
+    progress.add(Callbacks::new(CtrlC));
+    let (file_manager, report) = run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut progress,
+        &cli,
+        None,
+    )
+    .unwrap();
+    assert!(interrupt::requested());
+    assert!(report.interrupted());
+    assert_eq!(transport.remaining(), 1);
+
+    let e = client::finish_session(&cli, None, file_manager, report, None).unwrap_err();
+    assert!(
+        matches!(
+            e,
+            ClientError::Interrupted {
+                written: 1,
+                partial: 1
+            }
+        ),
+        "{e}"
+    );
+    assert_eq!(e.exit_code(), interrupt::EXIT_CODE);
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"whole");
+    assert_eq!(fs::read(dir.join("b.txt.partial")).unwrap(), b"first ");
+    assert!(!dir.join("b.txt").exists());
+    let listed = fs::read_to_string(dir.join(PARTIAL_MANIFEST)).unwrap();
+    assert!(listed.contains("b.txt.partial"), "{listed}");
+    fs::remove_dir_all(&dir).unwrap();
+}
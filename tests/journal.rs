@@ -0,0 +1,175 @@
+// --journal files: records written and read back, a damaged record or a cut
+// off tail left out without losing the rest, and a version 1 journal (no
+// CRCs) still read.
+
+use segmented_file_system_client::journal::{self, Journal, Record};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const HEADER_LEN: usize = 20;
+const RECORD_LEN: usize = 20;
+
+fn temp(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("segfs-journal-{name}-{}", std::process::id()))
+}
+
+// A header and then packets 0 to 4 of file 1, a millisecond apart, the last
+// one last
+fn write_five(path: &Path) {
+    let started = Instant::now();
+    let mut journal = Journal::create(path, started).unwrap();
+    for n in 0..5u32 {
+        let flags = journal::DATA | if n == 4 { journal::LAST } else { 0 };
+        let at = started + Duration::from_millis(u64::from(n));
+        journal.record(at, 1, flags, n, 1000 + n as usize).unwrap();
+    }
+    journal.finish().unwrap();
+}
+
+fn numbers(records: &[Record]) -> Vec<u32> {
+    records.iter().map(|record| record.packet_number).collect()
+}
+
+#[test]
+fn records_come_back_as_written() {
+    let path = temp("round-trip");
+    write_five(&path);
+    assert_eq!(fs::metadata(&path).unwrap().len(), 20 + 5 * 20);
+    let records = journal::read(&path).unwrap();
+    assert_eq!(numbers(&records), [0, 1, 2, 3, 4]);
+    let last = &records[4];
+    assert_eq!(
+        (last.at, last.file_id, last.flags, last.size),
+        (
+            Duration::from_millis(4),
+            1,
+            journal::DATA | journal::LAST,
+            1004
+        )
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_damaged_record_and_a_cut_off_tail_are_left_out() {
+    let path = temp("damaged");
+    write_five(&path);
+    let mut bytes = fs::read(&path).unwrap();
+    // A bit of packet 2's size flipped, and packet 4 only half there
+    bytes[HEADER_LEN + 2 * RECORD_LEN + 12] ^= 0x40;
+    bytes.truncate(HEADER_LEN + 4 * RECORD_LEN + RECORD_LEN / 2);
+    fs::write(&path, &bytes).unwrap();
+    let records = journal::read(&path).unwrap();
+    assert_eq!(numbers(&records), [0, 1, 3]);
+    assert_eq!(records[2].size, 1003);
+
+    // Cut inside the header, it isn't a journal at all; damaged there, it's
+    // one that can't be trusted
+    fs::write(&path, &bytes[..10]).unwrap();
+    let e = journal::read(&path).err().unwrap();
+    assert!(e.to_string().contains("isn't a --journal file"), "{e}");
+    bytes[9] ^= 1;
+    fs::write(&path, &bytes).unwrap();
+    let e = journal::read(&path).err().unwrap();
+    assert!(e.to_string().contains("header is damaged"), "{e}");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_version_1_journal_is_still_read() {
+    let path = temp("v1");
+    // 16-byte header and records, the packet number's high half not there
+    let mut bytes = b"SFSJ\x01\x00\x00\x00".to_vec();
+    bytes.extend(1_700_000_000_000_000u64.to_be_bytes());
+    for (micros, flags, n) in [(0u64, journal::DATA, 0u16), (2500, 3, 1)] {
+        bytes.extend(micros.to_be_bytes());
+        bytes.extend([7, flags]);
+        bytes.extend(n.to_be_bytes());
+        bytes.extend(512u16.to_be_bytes());
+        bytes.extend([0, 0]);
+    }
+    // Cut off partway through a third
+    bytes.extend([0; 9]);
+    fs::write(&path, &bytes).unwrap();
+    let records = journal::read(&path).unwrap();
+    assert_eq!(numbers(&records), [0, 1]);
+    assert_eq!(records[1].at, Duration::from_micros(2500));
+    assert_eq!((records[1].file_id, records[1].flags), (7, 3));
+    assert_eq!(records[1].size, 512);
+
+    bytes[4] = journal::VERSION + 1;
+    fs::write(&path, &bytes).unwrap();
+    let e = journal::read(&path).err().unwrap();
+    assert!(e.to_string().contains("can't read"), "{e}");
+    fs::remove_file(&path).unwrap();
+}
+
+// File 1 packets 1, 0 and 3 (the last), with 2 never coming; file 2 packets
+// 0 and 2, without a last one. Three in the first tenth of a second, two in
+// the next.
+fn write_two_files(path: &Path) {
+    let started = Instant::now();
+    let mut journal = Journal::create(path, started).unwrap();
+    for (ms, file_id, last, n) in [
+        (0, 1, false, 1),
+        (10, 1, false, 0),
+        (50, 2, false, 0),
+        (150, 1, true, 3),
+        (160, 2, false, 2),
+    ] {
+        let flags = journal::DATA | if last { journal::LAST } else { 0 };
+        let at = started + Duration::from_millis(ms);
+        journal.record(at, file_id, flags, n, 1000).unwrap();
+    }
+    journal.finish().unwrap();
+}
+
+fn analyze(path: &Path, csv: bool) -> String {
+    let mut out = Vec::new();
+    journal::run(path, Duration::from_millis(100), csv, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn analyze_counts_loss_and_throughput_per_interval() {
+    let path = temp("analyze");
+    write_two_files(&path);
+    assert_eq!(
+        analyze(&path, true),
+        "file_id,datagrams,distinct,expected,missing,bursts,longest_burst,reorder_depth\n\
+         1,3,3,4,1,1,1,1\n\
+         2,2,2,,1,1,1,0\n\
+         \n\
+         file_id,first,last,length\n\
+         1,2,2,1\n\
+         2,1,1,1\n\
+         \n\
+         start_ms,packets,bytes,bytes_per_second\n\
+         0,3,3000,30000\n\
+         100,2,2000,20000\n"
+    );
+    let text: Vec<String> = analyze(&path, false).lines().map(String::from).collect();
+    assert_eq!(
+        text,
+        [
+            "Files",
+            "file datagrams distinct expected  missing  bursts longest reorder",
+            "   1         3        3        4        1       1       1       1",
+            "   2         2        2        ?        1       1       1       0",
+            "",
+            "Packets that never arrived",
+            "file  first   last length",
+            "   1      2      2      1",
+            "   2      1      1      1",
+            "",
+            "Received per 100ms",
+            "        at  packets      bytes         rate",
+            "        0s        3       3000     30000B/s",
+            "     100ms        2       2000     20000B/s",
+        ]
+    );
+    fs::remove_file(&path).unwrap();
+}
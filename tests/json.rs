@@ -0,0 +1,217 @@
+// --json: the client's output as a program reading it would see it, from a
+// replayed capture so there's no server to start.
+
+use segmented_file_system_client::capture::Recorder;
+use serde_json::Value;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    time::Instant,
+};
+
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("segfs-json-{name}-{}", std::process::id()))
+}
+
+fn client(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--json"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn lines(output: &Output) -> Vec<Value> {
+    (String::from_utf8(output.stdout.clone()).unwrap().lines())
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+fn capture(path: &Path, datagrams: &[&[u8]]) {
+    let started = Instant::now();
+    let mut recorder = Recorder::create(path, started).unwrap();
+    for datagram in datagrams {
+        recorder.record(started, datagram).unwrap();
+    }
+    recorder.finish().unwrap();
+}
+
+#[test]
+fn every_line_is_an_object_saying_what_happened() {
+    let dir = scratch("files");
+    let path = scratch("files.capture");
+    capture(
+        &path,
+        &[
+            b"\x00\x01a.txt",
+            b"\x01\x01\x00\x00one, ",
+            b"\x03\x01\x00\x01then more",
+            b"\x00\x02b.txt",
+            b"\x01\x02\x00\x00never finished",
+        ],
+    );
+    let output = client(&[
+        "--replay",
+        path.to_str().unwrap(),
+        "--output-dir",
+        dir.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let lines = lines(&output);
+    let events: Vec<&str> = (lines.iter())
+        .map(|line| line["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "session-started",
+            "file-started",
+            "file-started",
+            "file-completed",
+            "file-incomplete",
+            "finished"
+        ]
+    );
+    assert!(lines.iter().all(|line| line["time_ms"].is_u64()));
+
+    let a = &lines[3];
+    assert_eq!(a["name"], "a.txt");
+    assert_eq!(a["bytes"], 14);
+    assert_eq!(a["status"], "complete");
+    assert_eq!(
+        fs::read(a["path"].as_str().unwrap()).unwrap(),
+        b"one, then more"
+    );
+    assert!(a["hash"].as_str().unwrap().contains(':'));
+    assert_eq!(lines[4]["name"], "b.txt");
+    assert_eq!(lines[4]["path"], Value::Null);
+
+    let finished = &lines[5];
+    assert_eq!(finished["written"], 1);
+    assert_eq!(finished["packets"], 5);
+    assert_eq!(finished["interrupted"], false);
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn an_error_is_a_line_too() {
+    let output = client(&["--replay", scratch("missing").to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(14));
+    let lines = lines(&output);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["event"], "error");
+    assert_eq!(lines[0]["code"], "io");
+    assert_eq!(lines[0]["exit_code"], 14);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn options_that_cant_work_are_an_error_line() {
+    let output = client(&["--watchdog-window", "0ms"]);
+    assert_eq!(output.status.code(), Some(2));
+    let lines = lines(&output);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["event"], "error");
+    assert_eq!(lines[0]["code"], "bad-options");
+    assert_eq!(lines[0]["exit_code"], 2);
+    let message = lines[0]["message"].as_str().unwrap();
+    assert!(message.contains("--> --watchdog-window"), "{message}");
+}
+
+#[test]
+fn a_failed_session_says_what_it_had() {
+    let path = scratch("failed.capture");
+    capture(
+        &path,
+        &[
+            b"\x00\x01a.txt",
+            b"\x03\x01\x00\x00done",
+            b"\x01\x02\x00\x01partway",
+            b"\x01",
+        ],
+    );
+    let args = [
+        "--replay",
+        path.to_str().unwrap(),
+        "--max-parse-errors",
+        "0",
+    ];
+    let output = client(&args);
+    assert_eq!(output.status.code(), Some(13));
+    let lines = lines(&output);
+    let report = lines.iter().find(|line| line["event"] == "failure-report");
+    let files = report.unwrap()["files"].as_array().unwrap();
+    assert_eq!(files[0]["status"], "complete");
+    assert_eq!(files[0]["name"], "a.txt");
+    assert_eq!(files[1]["status"], "partial");
+    assert_eq!(files[1]["missing"], 1);
+    assert_eq!(lines.last().unwrap()["code"], "malformed-packet");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--quiet"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(13));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("1 files were complete (none written) and 1 partial:\n  file 1 (a.txt): complete, 1 packets\n  file 2 (no header yet): 1 of ? packets, 1 missing: 0"),
+        "{stderr}"
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn every_problem_is_listed_against_its_file() {
+    let dir = scratch("errors");
+    let path = scratch("errors.capture");
+    let _ = fs::remove_dir_all(&dir);
+    // Something c.txt can't be written over
+    fs::create_dir_all(dir.join("c.txt/inside")).unwrap();
+    let mut checksum = vec![0x04, 1];
+    checksum.extend([0; 32]);
+    capture(
+        &path,
+        &[
+            b"\x00\x01a.txt",
+            b"\x03\x01\x00\x00not what the checksum says",
+            &checksum,
+            b"\x00\x02c.txt",
+            b"\x03\x02\x00\x00blocked",
+            b"\x01",
+            b"\x02",
+        ],
+    );
+    let output = client(&[
+        "--replay",
+        path.to_str().unwrap(),
+        "--output-dir",
+        dir.to_str().unwrap(),
+        "--on-conflict",
+        "overwrite",
+    ]);
+    // The exit status can only say one of them
+    assert_eq!(output.status.code(), Some(8), "{output:?}");
+    let lines = lines(&output);
+    let finished = lines.iter().find(|line| line["event"] == "finished");
+    let errors = finished.unwrap()["errors"].as_array().unwrap();
+    let codes: Vec<_> = (errors.iter())
+        .map(|e| (e["file_id"].clone(), e["code"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            (Value::from(2), "write-failed"),
+            (Value::from(1), "checksum-mismatch"),
+            (Value::Null, "malformed-packet"),
+        ]
+    );
+    assert!(errors[2]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("rejected 2 "));
+    fs::remove_dir_all(&dir).unwrap();
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,89 @@
+// --legacy: the client as the course server knows it. Only the v1 request
+// goes out, capability offers go unanswered, status bytes mean what they
+// did in v1, and a server that answers any datagram the way the course one
+// does gets its files across.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    capability,
+    cli::Cli,
+    events::Progress,
+    file_manager::FileManager,
+    protocol::{self, Version},
+    session::{self, receive_files, run_session, send_hello},
+    transport::{ManualClock, ScriptedTransport, Step},
+};
+use std::{net::UdpSocket, thread};
+
+fn cli(args: &[&str]) -> Cli {
+    let args = ["client", "--legacy", "--quiet"].iter().chain(args);
+    Cli::try_parse_from(args).unwrap()
+}
+
+#[test]
+fn only_the_plain_request_goes_out() {
+    let cli = cli(&["--file-id", "2", "--limit-rate", "1M"]);
+    assert_eq!(session::hello(&cli), session::HELLO);
+    assert_eq!(protocol::wanted(&cli), 0);
+    assert_eq!(protocol::starting_version(&cli), Version::V1);
+    for extension in [
+        "--crc",
+        "--compression",
+        "--nack-after=1s",
+        "--ack-interval=1s",
+    ] {
+        let args = ["client", "--legacy", extension];
+        assert!(Cli::try_parse_from(args).is_err(), "{extension}");
+    }
+}
+
+#[test]
+fn status_bytes_are_read_as_v1_and_offers_go_unanswered() {
+    let clock = ManualClock::default();
+    let steps = [
+        &capability::encode(capability::BUILT_IN)[..],
+        b"\x00\x01a.txt",
+        // The wide bit means nothing in v1, so this is packet 0 of 1, not
+        // a wide packet numbered 0x00006f6e
+        b"\x0b\x01\x00\x00one",
+    ];
+    let steps = steps.iter().map(|bytes| Step::Datagram(bytes.to_vec()));
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let (files, _) = run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli(&["--until", "files=1"]),
+        None,
+    )
+    .unwrap();
+    assert_eq!(files.contents(1).unwrap(), b"one");
+    assert!(transport.sent.is_empty(), "{:?}", transport.sent);
+}
+
+#[test]
+fn a_course_server_gets_its_files_across() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    // Answers the first datagram with the files, as the course server does,
+    // and hands that datagram back
+    let serving = thread::spawn(move || {
+        let mut buf = [0; 1500];
+        let (len, from) = server.recv_from(&mut buf).unwrap();
+        let mut full = b"\x01\x00\x00\x00".to_vec();
+        full.extend_from_slice(&[b'x'; 1024]);
+        for datagram in [&b"\x00\x00big.bin"[..], &full, b"\x03\x00\x00\x01end"] {
+            server.send_to(datagram, from).unwrap();
+        }
+        buf[..len].to_vec()
+    });
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(addr).unwrap();
+    let cli = cli(&["--until", "files=1"]);
+    send_hello(&client, &cli);
+    let (files, _) = receive_files(&client, &cli, &mut Progress::default(), None).unwrap();
+    assert_eq!(files.contents(0).unwrap().len(), 1027);
+    assert_eq!(serving.join().unwrap(), session::HELLO);
+}
@@ -0,0 +1,314 @@
+// Hostile and broken servers: floods of file IDs, oversized and malformed
+// names, and packet numbers that contradict each other.
+
+use segmented_file_system_client::limits::{
+    self, Ignored, Limits, NameProblem, Rejected, Renamed, Renames, Screen,
+};
+
+fn screen(max_files: Option<usize>, max_name_len: usize) -> Screen {
+    Screen::new(Limits {
+        max_files,
+        max_name_len,
+        ..Limits::default()
+    })
+}
+
+fn renaming(policy: Renames) -> Screen {
+    Screen::new(Limits {
+        renamed_headers: policy,
+        ..Limits::default()
+    })
+}
+
+#[test]
+fn every_file_id_is_fine_without_a_limit() {
+    let mut screen = screen(None, 255);
+    for i in 0..5000u32 {
+        let file_id = (i % 256) as u8;
+        assert_eq!(screen.data(file_id, i / 256, false), Ok(None));
+    }
+}
+
+#[test]
+fn file_id_flood_hits_the_limit() {
+    let mut screen = screen(Some(3), 255);
+    for file_id in 0..3 {
+        assert_eq!(screen.header(file_id, "a.txt"), Ok(None));
+    }
+    // Files we already know about keep working
+    assert_eq!(screen.data(2, 0, false), Ok(None));
+    for file_id in 3..=255 {
+        assert_eq!(
+            screen.data(file_id, 0, false),
+            Err(Rejected::TooManyFiles { limit: 3 })
+        );
+        assert_eq!(
+            screen.header(file_id, "b.txt"),
+            Err(Rejected::TooManyFiles { limit: 3 })
+        );
+    }
+}
+
+#[test]
+fn names_up_to_the_limit_are_accepted() {
+    let mut screen = screen(None, 255);
+    assert_eq!(screen.header(0, &"x".repeat(255)), Ok(None));
+    assert_eq!(
+        screen.header(1, &"x".repeat(256)),
+        Err(Rejected::BadName {
+            file_id: 1,
+            problem: NameProblem::TooLong {
+                len: 256,
+                limit: 255
+            }
+        })
+    );
+}
+
+#[test]
+fn name_length_counts_bytes() {
+    // 128 two-byte characters
+    let name = "é".repeat(128);
+    assert_eq!(
+        limits::check_name(&name, 255),
+        Err(NameProblem::TooLong {
+            len: 256,
+            limit: 255
+        })
+    );
+}
+
+#[test]
+fn embedded_nul_and_empty_names_are_rejected() {
+    let mut screen = screen(None, 255);
+    assert_eq!(
+        screen.header(4, "evil\0.txt"),
+        Err(Rejected::BadName {
+            file_id: 4,
+            problem: NameProblem::ContainsNul
+        })
+    );
+    assert_eq!(
+        screen.header(5, ""),
+        Err(Rejected::BadName {
+            file_id: 5,
+            problem: NameProblem::Empty
+        })
+    );
+}
+
+#[test]
+fn packets_past_the_end_are_ignored() {
+    let mut screen = screen(None, 255);
+    assert_eq!(screen.data(0, 9, true), Ok(None));
+    assert_eq!(screen.data(0, 3, false), Ok(None));
+    for packet_number in 10..=u32::from(u16::MAX) {
+        assert_eq!(
+            screen.data(0, packet_number, false),
+            Ok(Some(Ignored::PastEnd))
+        );
+    }
+}
+
+#[test]
+fn conflicting_last_packets_go_the_furthest() {
+    let mut screen = screen(None, 255);
+    assert_eq!(screen.data(0, 9, true), Ok(None));
+    // A repeat of the same last packet is fine
+    assert_eq!(screen.data(0, 9, true), Ok(None));
+    for packet_number in [0, 5, 8] {
+        assert_eq!(
+            screen.data(0, packet_number, true),
+            Ok(Some(Ignored::ConflictingLast))
+        );
+    }
+    assert_eq!(screen.data(0, 9, false), Ok(None));
+    assert_eq!(screen.data(0, 10, false), Ok(Some(Ignored::PastEnd)));
+    // One further on moves the end, and the first can't move it back
+    assert_eq!(screen.data(0, 20, true), Ok(None));
+    assert_eq!(screen.data(0, 10, false), Ok(None));
+    assert_eq!(screen.data(0, 9, true), Ok(Some(Ignored::ConflictingLast)));
+    assert_eq!(screen.data(0, 21, false), Ok(Some(Ignored::PastEnd)));
+}
+
+#[test]
+fn last_packet_before_stored_data_is_ignored() {
+    let mut screen = screen(None, 255);
+    assert_eq!(screen.data(1, 500, false), Ok(None));
+    assert_eq!(screen.data(1, 20, true), Ok(Some(Ignored::ConflictingLast)));
+    assert_eq!(screen.data(1, 500, true), Ok(None));
+}
+
+#[test]
+fn the_highest_packet_number_is_ignored() {
+    let mut screen = screen(None, 255);
+    assert_eq!(screen.data(0, u32::MAX, true), Ok(Some(Ignored::TooHigh)));
+    assert_eq!(screen.data(0, u32::MAX, false), Ok(Some(Ignored::TooHigh)));
+    // And doesn't count as having set the end
+    assert_eq!(screen.data(0, 60000, false), Ok(None));
+    // Which 65535, a wide packet's business now, can
+    assert_eq!(screen.data(0, u32::from(u16::MAX), true), Ok(None));
+}
+
+#[test]
+fn packet_number_churn_without_a_last_packet_is_accepted() {
+    let mut screen = screen(None, 255);
+    for round in 0..3 {
+        for packet_number in (0..=u32::from(u16::MAX)).rev().step_by(7 + round) {
+            assert_eq!(screen.data(2, packet_number, false), Ok(None));
+        }
+    }
+}
+
+#[test]
+fn repeated_headers_with_the_same_name_are_not_renames() {
+    for policy in [Renames::FirstWins, Renames::LastWins, Renames::Error] {
+        let mut screen = renaming(policy);
+        for _ in 0..3 {
+            assert_eq!(screen.header(0, "a.txt"), Ok(None));
+        }
+    }
+}
+
+#[test]
+fn first_wins_keeps_the_first_name() {
+    let mut screen = renaming(Renames::FirstWins);
+    assert_eq!(screen.header(0, "a.txt"), Ok(None));
+    let kept = Ok(Some(Renamed {
+        earlier: "a.txt".to_string(),
+        kept_earlier: true,
+    }));
+    assert_eq!(screen.header(0, "b.txt"), kept);
+    assert_eq!(screen.header(0, "c.txt"), kept);
+    assert_eq!(screen.header(0, "a.txt"), Ok(None));
+    // Other files are on their own
+    assert_eq!(screen.header(1, "b.txt"), Ok(None));
+}
+
+#[test]
+fn last_wins_takes_each_new_name() {
+    let mut screen = renaming(Renames::LastWins);
+    assert_eq!(screen.header(0, "a.txt"), Ok(None));
+    assert_eq!(
+        screen.header(0, "b.txt"),
+        Ok(Some(Renamed {
+            earlier: "a.txt".to_string(),
+            kept_earlier: false,
+        }))
+    );
+    assert_eq!(screen.header(0, "b.txt"), Ok(None));
+    assert_eq!(
+        screen.header(0, "a.txt"),
+        Ok(Some(Renamed {
+            earlier: "b.txt".to_string(),
+            kept_earlier: false,
+        }))
+    );
+}
+
+#[test]
+fn error_refuses_a_rename() {
+    let mut screen = renaming(Renames::Error);
+    assert_eq!(screen.header(7, "a.txt"), Ok(None));
+    assert_eq!(
+        screen.header(7, "b.txt"),
+        Err(Rejected::Renamed {
+            file_id: 7,
+            first: "a.txt".to_string(),
+            second: "b.txt".to_string(),
+        })
+    );
+}
+
+#[test]
+fn names_lose_any_directories() {
+    assert_eq!(limits::base_name("../../etc/cron.d/evil"), "evil");
+    assert_eq!(limits::base_name("/tmp/x"), "x");
+    assert_eq!(limits::base_name(r"..\..\Windows\evil.dll"), "evil.dll");
+    assert_eq!(limits::base_name("plain.txt"), "plain.txt");
+    assert_eq!(limits::base_name("dir/"), "");
+    assert_eq!(limits::base_name("a/.."), "..");
+}
+
+#[cfg(unix)]
+#[test]
+fn names_that_arent_utf8_lose_their_directories_too() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    let name = OsStr::from_bytes(b"../caf\xe9/men\xfa.txt");
+    assert_eq!(limits::base_os_name(name).as_bytes(), b"men\xfa.txt");
+    assert_eq!(limits::base_os_name(OsStr::new("a/b.txt")), "b.txt");
+}
+
+#[test]
+fn names_that_arent_one_file_are_rejected() {
+    for (name, problem) in [
+        ("../evil", NameProblem::HasSeparator),
+        (r"a\b", NameProblem::HasSeparator),
+        (".", NameProblem::NotAFile),
+        ("..", NameProblem::NotAFile),
+    ] {
+        assert_eq!(limits::check_name(name, 255), Err(problem), "{name}");
+    }
+    assert_eq!(limits::check_name("...", 255), Ok(()));
+    assert_eq!(limits::check_name(".hidden", 255), Ok(()));
+}
+
+#[test]
+fn a_file_can_only_be_so_big() {
+    let mut limited = Screen::new(Limits {
+        max_file_size: Some(2048),
+        ..Limits::default()
+    });
+    let too_large = |file_id| {
+        Err(Rejected::FileTooLarge {
+            file_id,
+            limit: 2048,
+        })
+    };
+    assert_eq!(limited.size(0, 2048), Ok(()));
+    assert_eq!(limited.size(0, 2049), too_large(0));
+    assert_eq!(limited.payload(1, 1024), Ok(()));
+    assert_eq!(limited.payload(1, 1024), Ok(()));
+    // Each file counts for itself
+    assert_eq!(limited.payload(2, 1024), Ok(()));
+    assert_eq!(limited.payload(1, 1), too_large(1));
+
+    let mut unlimited = screen(None, 255);
+    assert_eq!(unlimited.size(0, u64::MAX), Ok(()));
+    assert_eq!(unlimited.payload(0, 1 << 40), Ok(()));
+}
+
+#[test]
+fn a_tree_name_can_only_lead_down() {
+    for (name, problem) in [
+        ("/etc/passwd", NameProblem::Absolute),
+        ("src/../../evil", NameProblem::ParentDir),
+        ("..", NameProblem::ParentDir),
+        (r"src\lib.rs", NameProblem::HasSeparator),
+        ("src//lib.rs", NameProblem::Empty),
+        ("src/", NameProblem::Empty),
+        ("./lib.rs", NameProblem::NotAFile),
+    ] {
+        assert_eq!(limits::check_path(name, 255), Err(problem), "{name}");
+    }
+    assert_eq!(limits::check_path("src/lib/util.rs", 255), Ok(()));
+    assert_eq!(limits::check_path("plain.txt", 255), Ok(()));
+    // Each part has its own limit
+    let deep = ["abcd"; 10].join("/");
+    assert_eq!(limits::check_path(&deep, 4), Ok(()));
+
+    let mut tree = Screen::new(Limits {
+        tree: true,
+        ..Limits::default()
+    });
+    assert_eq!(tree.header(0, "src/lib/util.rs"), Ok(None));
+    assert_eq!(
+        tree.header(1, "../up"),
+        Err(Rejected::BadName {
+            file_id: 1,
+            problem: NameProblem::ParentDir
+        })
+    );
+    // Without --tree it's never seen with its directories, but still
+    assert!(screen(None, 255).header(2, "src/lib/util.rs").is_err());
+}
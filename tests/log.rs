@@ -0,0 +1,68 @@
+// -v and -vv: how much the session says on standard error as it goes.
+
+use segmented_file_system_client::log::{self, Level};
+use std::{env, fs, net::UdpSocket, path::PathBuf, process::Command, thread};
+
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("segfs-log-{name}-{}", std::process::id()))
+}
+
+#[test]
+fn each_v_says_more() {
+    assert_eq!(Level::from_verbosity(0), Level::Warn);
+    assert_eq!(Level::from_verbosity(1), Level::Info);
+    assert_eq!(Level::from_verbosity(5), Level::Debug);
+    log::set(Level::Info);
+    assert!(log::enabled(Level::Error) && log::enabled(Level::Info));
+    assert!(!log::enabled(Level::Debug));
+
+    let dir = scratch("out");
+    let stderr = |verbosity: &[&str]| {
+        let _ = fs::remove_dir_all(&dir);
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        // Answers the request with one file, a packet of it twice
+        let serving = thread::spawn(move || {
+            let mut buf = [0; 1500];
+            let (_, from) = server.recv_from(&mut buf).unwrap();
+            for datagram in [
+                &b"\x00\x01a.txt"[..],
+                b"\x01\x01\x00\x00one",
+                b"\x01\x01\x00\x00one",
+                b"\x03\x01\x00\x01two",
+            ] {
+                server.send_to(datagram, from).unwrap();
+            }
+        });
+        let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+            .args(["--lang", "en", "--quiet", "--until", "files=1"])
+            .args(verbosity)
+            .args(["--server", &addr.to_string()])
+            .args(["--output-dir", dir.to_str().unwrap()])
+            .output()
+            .unwrap();
+        serving.join().unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    // The duplicate is a warning, so it's said whatever the level
+    let warn = stderr(&[]);
+    assert_eq!(warn, "warning: packet 0 of file 1 came again\n");
+    let info = stderr(&["-v"]);
+    assert_eq!(info, warn + "file 1 (a.txt) is complete: 2 packets\n");
+    let debug = stderr(&["-vv"]);
+    assert!(
+        debug.contains(": data: file 1, packet 1, last, 3 bytes of payload\n"),
+        "{debug}"
+    );
+    assert!(
+        debug.contains(": header: file 1, name \"a.txt\"\n"),
+        "{debug}"
+    );
+    assert!(
+        debug.ends_with(&info[info.find("file 1 (").unwrap()..]),
+        "{debug}"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
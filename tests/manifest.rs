@@ -0,0 +1,110 @@
+// --write-manifest: what a finished transfer delivered, and --expect
+// checking a later one against it. From a replayed capture, so there's no
+// server to start.
+
+use segmented_file_system_client::{capture::Recorder, hash::HashAlgorithm};
+use serde_json::Value;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    time::Instant,
+};
+
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("segfs-manifest-{name}-{}", std::process::id()))
+}
+
+fn client(capture: &Path, dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--quiet", "--until", "files=2"])
+        .args(["--replay", capture.to_str().unwrap()])
+        .args(["--output-dir", dir.to_str().unwrap()])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn capture(path: &Path) {
+    let started = Instant::now();
+    let mut recorder = Recorder::create(path, started).unwrap();
+    for datagram in [
+        &b"\x00\x01a.txt"[..],
+        b"\x01\x01\x00\x00one, ",
+        b"\x03\x01\x00\x01then more",
+        b"\x00\x02b.txt",
+        b"\x03\x02\x00\x00bee",
+    ] {
+        recorder.record(started, datagram).unwrap();
+    }
+    recorder.finish().unwrap();
+}
+
+#[test]
+fn the_manifest_lists_what_was_written() {
+    let (dir, path) = (scratch("files"), scratch("files.capture"));
+    capture(&path);
+    let output = client(&path, &dir, &["--write-manifest"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let text = fs::read_to_string(dir.join("manifest.json")).unwrap();
+    let manifest: Value = serde_json::from_str(&text).unwrap();
+    let files = manifest["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    let a = &files[0];
+    assert_eq!(a["name"], "a.txt");
+    assert_eq!(a["path"], "a.txt");
+    assert_eq!(a["file_id"], 1);
+    assert_eq!(a["size"], 14);
+    assert_eq!(a["packets"], 2);
+    assert_eq!(a["hash"], HashAlgorithm::Sha256.tagged(b"one, then more"));
+    assert!(a["first_packet_at"].as_str().unwrap().ends_with('Z'));
+    assert!(a["first_packet_at"].as_str() <= a["last_packet_at"].as_str());
+    assert_eq!(files[1]["name"], "b.txt");
+
+    // A run that comes out the same passes --expect with it
+    let again = scratch("again");
+    let manifest = dir.join("manifest.json");
+    let output = client(&path, &again, &["--expect", manifest.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    // And one that doesn't, doesn't
+    fs::write(&manifest, text.replace("\"size\": 14", "\"size\": 15")).unwrap();
+    let output = client(
+        &path,
+        &scratch("wrong"),
+        &["--expect", manifest.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(7), "{output:?}");
+    // The file that came out wrong is moved aside, with why beside it
+    let wrong = scratch("wrong");
+    assert!(!wrong.join("a.txt").exists() && wrong.join("b.txt").exists());
+    assert_eq!(
+        fs::read(wrong.join("quarantine/a.txt")).unwrap(),
+        b"one, then more"
+    );
+    let failure = fs::read_to_string(wrong.join("quarantine/a.txt.failure.json")).unwrap();
+    let failure: Value = serde_json::from_str(&failure).unwrap();
+    assert_eq!(failure["file_id"], 1);
+    assert_eq!(failure["reason"], "size");
+    assert_eq!(
+        (&failure["expected"], &failure["actual"]),
+        (&"15".into(), &"14".into())
+    );
+    for dir in [dir, again, scratch("wrong")] {
+        fs::remove_dir_all(dir).unwrap();
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn somewhere_else_in_the_output_directory() {
+    let (dir, path) = (scratch("named"), scratch("named.capture"));
+    capture(&path);
+    let output = client(&path, &dir, &["--write-manifest", "delivered.json"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.join("delivered.json").exists());
+    assert!(!dir.join("manifest.json").exists());
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_file(path).unwrap();
+}
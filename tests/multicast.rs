@@ -0,0 +1,114 @@
+// --multicast: the client in a group on the loopback interface, with
+// senders pushing files to the group and each one's kept apart.
+
+use segmented_file_system_client::grade;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader},
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(239, 1, 2, 3);
+
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("segfs-multicast-{name}-{}", std::process::id()))
+}
+
+// A port nobody has, for the group
+fn free_port() -> u16 {
+    let sock = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    sock.local_addr().unwrap().port()
+}
+
+// The client, once it's in the group
+fn client(port: u16, dir: &Path, args: &[&str]) -> Child {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--listen-only", "--interface", "127.0.0.1"])
+        .args(["--multicast", &format!("{GROUP}:{port}")])
+        .args(["--output-dir", dir.to_str().unwrap(), "--deadline", "10s"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.as_mut().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert!(line.contains("multicast group"), "{line}");
+    child
+}
+
+fn sender() -> UdpSocket {
+    let sock = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    sock.set_multicast_if_v4(&Ipv4Addr::LOCALHOST).unwrap();
+    sock.bind(&SocketAddr::from((Ipv4Addr::LOCALHOST, 0)).into())
+        .unwrap();
+    sock.into()
+}
+
+fn send(sock: &UdpSocket, port: u16, datagrams: &[&[u8]]) {
+    for datagram in datagrams {
+        sock.send_to(datagram, (GROUP, port)).unwrap();
+    }
+}
+
+#[test]
+fn every_sender_gets_a_session_of_its_own() {
+    let (port, dir) = (free_port(), scratch("senders"));
+    let mut child = client(port, &dir, &["--quiet", "--until", "files=1"]);
+    let (one, two) = (sender(), sender());
+    // Both started before either can finish
+    send(&one, port, &[b"\x00\x01a.txt"]);
+    send(&two, port, &[b"\x00\x01a.txt"]);
+    send(&one, port, &[b"\x03\x01\x00\x00from one"]);
+    send(&two, port, &[b"\x03\x01\x00\x00from two"]);
+    assert!(child.wait().unwrap().success());
+
+    for (sock, expected) in [(one, "from one"), (two, "from two")] {
+        let name = grade::directory_name(&sock.local_addr().unwrap().to_string());
+        let written = fs::read_to_string(dir.join(name).join("a.txt")).unwrap();
+        assert_eq!(written, expected);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_named_source_writes_into_the_output_directory() {
+    let (port, dir) = (free_port(), scratch("source"));
+    let args = [
+        "--quiet",
+        "--until",
+        "files=1",
+        "--multicast-source",
+        "127.0.0.1",
+    ];
+    let mut child = client(port, &dir, &args);
+    send(
+        &sender(),
+        port,
+        &[b"\x00\x07b.txt", b"\x03\x07\x00\x00pushed"],
+    );
+    assert!(child.wait().unwrap().success());
+    assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "pushed");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn only_a_group_can_be_joined() {
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args([
+            "--lang",
+            "en",
+            "--listen-only",
+            "--multicast",
+            "10.0.0.1:6014",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("isn't a multicast address"), "{stderr}");
+}
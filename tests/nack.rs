@@ -0,0 +1,81 @@
+// Retransmission requests: the bytes on the wire, narrow and wide, and long
+// lists split over several datagrams.
+
+use segmented_file_system_client::{nack, wire};
+
+#[test]
+fn layout() {
+    let datagrams = nack::encode(0x0102030405060708, &[(0, 1), (7, 0x0203)]);
+    assert_eq!(
+        datagrams,
+        [b"SFSN\x01\x01\x02\x03\x04\x05\x06\x07\x08\x00\x02\x00\x00\x01\x07\x02\x03".to_vec()]
+    );
+}
+
+#[test]
+fn round_trip() {
+    let pairs = [(3, 0), (3, 9), (200, u16::MAX)];
+    let datagrams = nack::encode(42, &pairs);
+    let widened = pairs.map(|(file_id, n)| (file_id, u32::from(n)));
+    assert_eq!(nack::decode(&datagrams[0]), Some((42, widened.to_vec())));
+
+    let wide = [(3, 0), (3, 65536), (200, u32::MAX)];
+    let datagrams = nack::encode_wide(42, &wide);
+    assert_eq!(nack::decode(&datagrams[0]), Some((42, wide.to_vec())));
+}
+
+#[test]
+fn wide_layout() {
+    let datagrams = nack::encode_wide(0x0102030405060708, &[(7, 0x01020304)]);
+    assert_eq!(
+        datagrams,
+        [b"SFSN\x02\x01\x02\x03\x04\x05\x06\x07\x08\x00\x01\x07\x01\x02\x03\x04".to_vec()]
+    );
+}
+
+#[test]
+fn long_lists_are_split_and_every_piece_fits() {
+    let pairs: Vec<(u8, u16)> = (0..1000).map(|n| ((n % 3) as u8, n)).collect();
+    let datagrams = nack::encode(1, &pairs);
+    assert_eq!(datagrams.len(), 1000usize.div_ceil(nack::MAX_PAIRS));
+    assert!(datagrams.iter().all(|d| d.len() <= wire::MAX_PACKET));
+    assert_eq!(
+        nack::decode(&datagrams[0]).unwrap().1.len(),
+        nack::MAX_PAIRS
+    );
+    assert!(datagrams[0].len() + nack::PAIR_LEN > wire::MAX_PACKET);
+
+    let decoded: Vec<(u8, u32)> = (datagrams.iter())
+        .flat_map(|d| nack::decode(d).unwrap().1)
+        .collect();
+    let widened: Vec<(u8, u32)> = (pairs.iter())
+        .map(|&(file_id, n)| (file_id, u32::from(n)))
+        .collect();
+    assert_eq!(decoded, widened);
+
+    let wide: Vec<(u8, u32)> = (0..1000).map(|n| (1, n << 16)).collect();
+    let datagrams = nack::encode_wide(1, &wide);
+    assert_eq!(datagrams.len(), 1000usize.div_ceil(nack::MAX_WIDE_PAIRS));
+    assert!(datagrams.iter().all(|d| d.len() <= wire::MAX_PACKET));
+    assert!(datagrams[0].len() + nack::WIDE_PAIR_LEN > wire::MAX_PACKET);
+}
+
+#[test]
+fn nothing_missing_is_no_datagrams() {
+    assert!(nack::encode(1, &[]).is_empty());
+}
+
+#[test]
+fn anything_else_is_not_a_nack() {
+    let good = nack::encode(1, &[(0, 1)]).remove(0);
+    assert_eq!(nack::decode(&good[..good.len() - 1]), None);
+    assert_eq!(nack::decode(&[&good[..], b"x"].concat()), None);
+    let mut other_version = good.clone();
+    other_version[4] = 3;
+    assert_eq!(nack::decode(&other_version), None);
+    // A narrow pair's length doesn't make a wide one
+    let mut miscounted = good.clone();
+    miscounted[4] = nack::WIDE_VERSION;
+    assert_eq!(nack::decode(&miscounted), None);
+    assert_eq!(nack::decode(b"SFSR\x01"), None);
+}
@@ -0,0 +1,104 @@
+// --umask and --chown: what the files written end up with. Giving them away
+// takes root, but giving them to ourselves doesn't, and goes through the
+// same chown. Both are Linux only.
+#![cfg(target_os = "linux")]
+
+use segmented_file_system_client::{
+    capture::Recorder,
+    owner::{self, Owner},
+};
+use std::{
+    env, fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    time::Instant,
+};
+
+#[test]
+fn masks_and_owners_parse() {
+    assert_eq!(owner::parse_umask("077"), Ok(0o077));
+    assert_eq!(owner::parse_umask("0"), Ok(0));
+    for bad in ["8", "1000", "rwx", ""] {
+        assert!(owner::parse_umask(bad).is_err(), "{bad}");
+    }
+    assert_eq!(
+        owner::parse("1000:100"),
+        Ok(Owner {
+            uid: Some(1000),
+            gid: Some(100)
+        })
+    );
+    assert_eq!(
+        owner::parse(":100"),
+        Ok(Owner {
+            uid: None,
+            gid: Some(100)
+        })
+    );
+    assert!(owner::parse(":").is_err());
+    assert!(owner::parse("no-such-user-here").is_err());
+}
+
+// A capture of one small file, to replay without a server
+fn capture(scratch: &Path) -> PathBuf {
+    let capture = scratch.join("capture");
+    let started = Instant::now();
+    let mut recorder = Recorder::create(&capture, started).unwrap();
+    for datagram in [&b"\x00\x01a.txt"[..], b"\x03\x01\x00\x00abc"] {
+        recorder.record(started, datagram).unwrap();
+    }
+    recorder.finish().unwrap();
+    capture
+}
+
+fn client(capture: &Path, dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--quiet", "--until", "files=1"])
+        .args(["--replay", capture.to_str().unwrap()])
+        .args(["--output-dir", dir.to_str().unwrap()])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn the_umask_sets_the_files_mode() {
+    let scratch = env::temp_dir().join(format!("segfs-umask-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&scratch);
+    fs::create_dir_all(&scratch).unwrap();
+    let capture = capture(&scratch);
+    for (mask, mode) in [("077", 0o600), ("027", 0o640), ("002", 0o664)] {
+        let dir = scratch.join(mask);
+        let output = client(&capture, &dir, &["--umask", mask]);
+        assert!(output.status.success(), "{output:?}");
+        let file = dir.join("a.txt");
+        assert_eq!(fs::read(&file).unwrap(), b"abc");
+        let written = fs::metadata(&file).unwrap().permissions().mode() & 0o777;
+        assert_eq!(written, mode, "--umask {mask} gave {written:o}");
+    }
+    fs::remove_dir_all(&scratch).unwrap();
+}
+
+#[test]
+fn files_can_be_given_to_ourselves() {
+    let scratch = env::temp_dir().join(format!("segfs-chown-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&scratch);
+    fs::create_dir_all(&scratch).unwrap();
+    let capture = capture(&scratch);
+    // Whoever made the capture is who we are
+    let us = fs::metadata(&capture).unwrap();
+    let spec = format!("{}:{}", us.uid(), us.gid());
+    let dir = scratch.join("out");
+    let output = client(&capture, &dir, &["--chown", &spec]);
+    assert!(output.status.success(), "{output:?}");
+    let written = fs::metadata(dir.join("a.txt")).unwrap();
+    assert_eq!((written.uid(), written.gid()), (us.uid(), us.gid()));
+
+    // Only the group, by itself
+    let dir = scratch.join("group");
+    let output = client(&capture, &dir, &["--chown", &format!(":{}", us.gid())]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::metadata(dir.join("a.txt")).unwrap().gid(), us.gid());
+    fs::remove_dir_all(&scratch).unwrap();
+}
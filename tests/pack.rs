@@ -0,0 +1,85 @@
+// `pack` and `unpack`: files cut into packets on disk and put back together,
+// with one of the packets gone.
+
+use segmented_file_system_client::pack;
+use std::{env, fs, path::PathBuf, process::Command};
+
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("segfs-pack-{name}-{}", std::process::id()))
+}
+
+#[test]
+fn packed_files_unpack_to_the_same_bytes() {
+    let (root, packets, out) = (scratch("in"), scratch("packets"), scratch("out"));
+    for dir in [&root, &packets, &out] {
+        let _ = fs::remove_dir_all(dir);
+    }
+    fs::create_dir_all(&root).unwrap();
+    let big: Vec<u8> = (0..2500u32).map(|n| n as u8).collect();
+    fs::write(root.join("big.bin"), &big).unwrap();
+    fs::write(root.join("small.txt"), "small").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "pack", "--into", packets.to_str().unwrap()])
+        .args([root.join("big.bin"), root.join("small.txt")])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("wrote 6 packets into "), "{stdout}");
+    let mut names: Vec<_> = (fs::read_dir(&packets).unwrap())
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "00000-header-0.bin",
+            "00001-data-0-0.bin",
+            "00002-data-0-1.bin",
+            "00003-data-0-2.bin",
+            "00004-header-1.bin",
+            "00005-data-1-0.bin",
+        ]
+    );
+    assert_eq!(
+        fs::read(packets.join("00005-data-1-0.bin")).unwrap(),
+        b"\x03\x01\x00\x00small"
+    );
+
+    let written = pack::unpack(&packets, &out).unwrap();
+    assert_eq!(
+        written,
+        [(0, out.join("big.bin")), (1, out.join("small.txt"))]
+    );
+    assert_eq!(fs::read(out.join("big.bin")).unwrap(), big);
+    assert_eq!(fs::read(out.join("small.txt")).unwrap(), b"small");
+
+    // Without one of its packets, only the other file comes out
+    fs::remove_dir_all(&out).unwrap();
+    fs::remove_file(packets.join("00002-data-0-1.bin")).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--output-dir", out.to_str().unwrap()])
+        .args(["unpack", packets.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        format!("file 1: {}\n", out.join("small.txt").display())
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("file 0 is missing its header or packets (1 known missing)"),
+        "{stderr}"
+    );
+    assert!(!out.join("big.bin").exists());
+
+    // Anything in there that isn't a packet is an error
+    fs::write(packets.join("00009-junk.bin"), b"\x01").unwrap();
+    assert!(pack::unpack(&packets, &out).is_err());
+    for dir in [&root, &packets, &out] {
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+// Packet parsing through the library, the way another tool would use it.
+
+use segmented_file_system_client::{
+    compression::Compression,
+    packet::{Checksum, Data, Header, Malformed, Metadata, Packet, PacketParseError, PacketView},
+    protocol,
+};
+use std::{
+    convert::TryFrom,
+    ffi::OsStr,
+    time::{Duration, UNIX_EPOCH},
+};
+
+#[test]
+fn a_header_names_its_file() {
+    let Ok(Packet::Header(Header {
+        file_id,
+        file_name,
+        metadata,
+    })) = Packet::try_from(&b"\x00\x07a.txt"[..])
+    else {
+        panic!("not a header");
+    };
+    assert_eq!(file_id, 7);
+    assert_eq!(&*file_name, OsStr::new("a.txt"));
+    assert!(metadata.is_empty());
+}
+
+#[test]
+fn an_extended_header_says_more_about_its_file() {
+    let mut bytes = b"\x08\x07\x05".to_vec();
+    bytes.extend_from_slice(&1500u64.to_be_bytes());
+    bytes.extend_from_slice(&[0xff; 8]); // not given, so not read
+    bytes.extend_from_slice(&0o640u32.to_be_bytes());
+    bytes.extend_from_slice(b"a.txt");
+    let Ok(Packet::Header(header)) = Packet::try_from(&bytes[..]) else {
+        panic!("not a header");
+    };
+    assert_eq!(&*header.file_name, OsStr::new("a.txt"));
+    assert_eq!(
+        header.metadata,
+        Metadata {
+            size: Some(1500),
+            modified: None,
+            mode: Some(0o640),
+            compression: Compression::None,
+        }
+    );
+    assert_eq!(Packet::Header(header).to_bytes()[..11], bytes[..11]);
+
+    let modified = Metadata {
+        modified: Some(-86_400),
+        ..Metadata::default()
+    };
+    assert_eq!(
+        modified.modified_time(),
+        UNIX_EPOCH.checked_sub(Duration::from_secs(86_400))
+    );
+
+    // The fields cut short
+    assert!(matches!(
+        Packet::try_from(&b"\x08\x07\x05\x00\x00"[..]),
+        Err(PacketParseError::ExtendedHeaderTooShort { file_id: 7, len: 5 })
+    ));
+}
+
+#[test]
+fn any_header_can_say_its_file_is_compressed() {
+    for (status, compression) in [(0x10, Compression::Gzip), (0x28, Compression::Zstd)] {
+        let mut bytes = vec![status, 3];
+        if status & 0x08 != 0 {
+            bytes.extend_from_slice(&[0; 21]);
+        }
+        bytes.extend_from_slice(b"a.gz");
+        let Ok(Packet::Header(header)) = Packet::try_from(&bytes[..]) else {
+            panic!("not a header");
+        };
+        assert_eq!(header.metadata.compression, compression);
+        assert!(!header.metadata.has_fields());
+        // Said again without the fields nobody gave
+        assert_eq!(
+            Packet::Header(header).to_bytes(),
+            [&[status & !0x08, 3], &b"a.gz"[..]].concat()
+        );
+    }
+    let Err(e) = Packet::try_from(&b"\x30\x03a.gz"[..]) else {
+        panic!("both bits set");
+    };
+    assert_eq!(e, PacketParseError::UnknownCompression { file_id: 3 });
+    assert_eq!(e.kind(), Malformed::UnknownCompression);
+}
+
+#[test]
+fn data_carries_its_number_and_whether_its_last() {
+    let Ok(Packet::Data(data)) = Packet::try_from(&b"\x03\x02\x01\x00abc"[..]) else {
+        panic!("not data");
+    };
+    let Data {
+        file_id,
+        packet_number,
+        is_last_packet,
+        ..
+    } = data;
+    assert_eq!((file_id, packet_number, is_last_packet), (2, 256, true));
+    assert_eq!(&data.data[..], b"abc");
+
+    let Ok(Packet::Data(data)) = Packet::try_from(&b"\x01\x02\x00\x05"[..]) else {
+        panic!("not data");
+    };
+    assert!(!data.is_last_packet);
+    assert!(data.data.is_empty());
+}
+
+#[test]
+fn a_wide_packet_carries_a_32_bit_number() {
+    let Ok(Packet::Data(data)) = Packet::try_from(&b"\x0b\x02\x00\x01\x00\x00abc"[..]) else {
+        panic!("not data");
+    };
+    assert_eq!((data.packet_number, data.is_last_packet), (65536, true));
+    assert_eq!(&data.data[..], b"abc");
+
+    // Two bytes of number short
+    assert!(matches!(
+        Packet::try_from(&b"\x09\x02\x00\x01"[..]),
+        Err(PacketParseError::DataPacketTooShort { file_id: 2, len: 4 })
+    ));
+}
+
+#[test]
+fn a_view_borrows_what_claim_would_copy() {
+    let bytes = b"\x01\x04\x00\x09payload";
+    let view = PacketView::parse(bytes).unwrap();
+    assert_eq!(view.file_id(), 4);
+    let PacketView::Data { payload, .. } = view else {
+        panic!("not data");
+    };
+    assert_eq!(payload.as_ptr(), bytes[4..].as_ptr());
+
+    let claimed = view.claim();
+    assert_eq!(claimed.file_id(), 4);
+    assert!(matches!(
+        claimed.view(),
+        PacketView::Data {
+            packet_number: 9,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn a_checksum_packet_carries_a_whole_digest() {
+    let mut bytes = vec![0x04, 0x05];
+    bytes.extend(0..32);
+    let Ok(Packet::Checksum(Checksum { file_id, digest })) = Packet::try_from(&bytes[..]) else {
+        panic!("not a checksum");
+    };
+    assert_eq!(file_id, 5);
+    assert_eq!(digest[31], 31);
+
+    bytes.pop();
+    assert_eq!(
+        Packet::try_from(&bytes[..]).err(),
+        Some(PacketParseError::ChecksumWrongLength {
+            file_id: 5,
+            len: 33
+        })
+    );
+    assert_eq!(
+        PacketView::parse(b"\x04\x05").err().map(|e| e.kind()),
+        Some(Malformed::ChecksumLength)
+    );
+}
+
+#[test]
+fn malformed_datagrams_say_how() {
+    let kind = |bytes: &[u8]| Packet::try_from(bytes).err().map(|e| e.kind());
+    assert_eq!(kind(b""), Some(Malformed::TooShort));
+    assert_eq!(kind(b"\x00"), Some(Malformed::TooShort));
+    #[cfg(not(unix))]
+    assert_eq!(kind(b"\x00\x00\xff\xfe"), Some(Malformed::BadUtf8));
+    assert_eq!(kind(b"\x01\x00\x00"), Some(Malformed::DataTooShort));
+    assert_eq!(kind(b"\x00\x00"), None);
+}
+
+#[test]
+fn parse_errors_carry_what_was_wrong() {
+    let error = |bytes: &[u8]| Packet::try_from(bytes).err().unwrap();
+    assert_eq!(error(b"\x00"), PacketParseError::PacketTooShort { len: 1 });
+    assert_eq!(
+        error(b"\x03\x04\x00"),
+        PacketParseError::DataPacketTooShort { file_id: 4, len: 3 }
+    );
+    assert_eq!(error(b"\x00").to_string(), "packet too short: 1 bytes");
+
+    #[cfg(not(unix))]
+    let bad_name = error(b"\x00\x09\xff");
+    #[cfg(not(unix))]
+    {
+        use std::error::Error;
+        assert!(matches!(
+            bad_name,
+            PacketParseError::InvalidUtf8Filename { file_id: 9, .. }
+        ));
+        assert!(bad_name.source().is_some());
+    }
+}
+
+// Unix takes any bytes as a name, so one in a legacy encoding (Latin-1
+// here) comes through as it was sent
+#[cfg(unix)]
+#[test]
+fn names_that_arent_utf8_keep_their_bytes() {
+    use std::os::unix::ffi::OsStrExt;
+    let bytes = b"\x00\x09caf\xe9.txt";
+    let packet = Packet::try_from(&bytes[..]).unwrap();
+    let Packet::Header(header) = &packet else {
+        panic!("not a header");
+    };
+    assert_eq!(header.file_name.as_bytes(), b"caf\xe9.txt");
+    assert_eq!(packet.to_bytes(), bytes);
+}
+
+#[test]
+fn a_lossy_parse_replaces_what_isnt_utf8() {
+    let mut lossy = String::new();
+    let view = PacketView::parse_lossy(b"\x00\x09caf\xe9.txt", protocol::LATEST, &mut lossy);
+    let Ok(PacketView::Header {
+        file_id, file_name, ..
+    }) = view
+    else {
+        panic!("not a header");
+    };
+    assert_eq!(file_id, 9);
+    assert_eq!(file_name, "caf\u{fffd}.txt");
+}
+
+#[cfg(not(unix))]
+#[test]
+fn errors_compose_with_the_question_mark() {
+    use segmented_file_system_client::error::ClientError;
+    use std::error::Error;
+    fn parse(bytes: &[u8]) -> Result<Packet, Box<dyn Error>> {
+        Ok(Packet::try_from(bytes).map_err(ClientError::from)?)
+    }
+    let e = parse(b"\x00\x00\xff").err().unwrap();
+    assert!(e.to_string().starts_with("malformed packet: "));
+    let source = e.source().unwrap();
+    assert!(source.downcast_ref::<PacketParseError>().is_some());
+    assert!(source.source().is_some());
+}
+
+#[test]
+fn every_kind_of_packet_goes_back_to_the_bytes_it_came_from() {
+    let mut wide = b"\x0b\x05\x00\x01\x00\x02".to_vec();
+    wide.extend_from_slice(&[9; 1024]);
+    let mut checksum = b"\x04\x01".to_vec();
+    checksum.extend_from_slice(&[0xab; 32]);
+    let datagrams: [&[u8]; 7] = [
+        b"\x00\x07a.txt",
+        b"\x00\x00",
+        b"\x01\x02\x00\x00one",
+        b"\x03\x02\xff\xfftwo",
+        b"\x03\x02\x00\x01",
+        &wide,
+        &checksum,
+    ];
+    let mut buf = b"before".to_vec();
+    for datagram in datagrams {
+        let packet = Packet::try_from(datagram).unwrap();
+        assert_eq!(Vec::from(&packet), datagram);
+        assert_eq!(packet.encoded_len(), datagram.len());
+        packet.encode(&mut buf);
+    }
+    assert_eq!(buf, [&b"before"[..], &datagrams.concat()].concat());
+}
@@ -0,0 +1,62 @@
+// Paths past Windows' MAX_PATH: the `\\?\` rewriting itself, and writing a
+// file more than 260 characters deep through `paths::long` on whatever
+// platform the tests run on.
+
+use segmented_file_system_client::paths;
+use std::{env, fs, path::PathBuf};
+
+#[test]
+fn drive_paths_get_the_verbatim_prefix() {
+    assert_eq!(
+        paths::extended_length(r"C:\Users\student\out\a.txt"),
+        r"\\?\C:\Users\student\out\a.txt"
+    );
+}
+
+#[test]
+fn unc_paths_use_the_unc_form() {
+    assert_eq!(
+        paths::extended_length(r"\\labserver\share\out\a.txt"),
+        r"\\?\UNC\labserver\share\out\a.txt"
+    );
+}
+
+#[test]
+fn already_verbatim_paths_are_left_alone() {
+    let path = r"\\?\C:\out\a.txt";
+    assert_eq!(paths::extended_length(path), path);
+}
+
+#[test]
+fn forward_slashes_are_translated() {
+    assert_eq!(
+        paths::extended_length("C:/out/sub/a.txt"),
+        r"\\?\C:\out\sub\a.txt"
+    );
+}
+
+#[test]
+fn long_paths_keep_every_component() {
+    let deep = (0..30)
+        .map(|i| format!(r"\directory-{i:02}"))
+        .collect::<String>();
+    let path = format!(r"C:{deep}\{}.txt", "n".repeat(100));
+    assert!(path.len() > 260);
+    assert_eq!(paths::extended_length(&path), format!(r"\\?\{path}"));
+}
+
+#[test]
+fn files_deeper_than_max_path_can_be_written() {
+    let mut dir: PathBuf = env::temp_dir().join(format!("segfs-long-{}", std::process::id()));
+    let root = dir.clone();
+    while dir.as_os_str().len() <= 300 {
+        dir.push("a-fairly-long-directory-name");
+    }
+    let file = dir.join(format!("{}.txt", "n".repeat(120)));
+    assert!(file.as_os_str().len() > 260);
+
+    fs::create_dir_all(paths::long(&dir)).unwrap();
+    fs::write(paths::long(&file), b"hello").unwrap();
+    assert_eq!(fs::read(paths::long(&file)).unwrap(), b"hello");
+    fs::remove_dir_all(paths::long(&root)).unwrap();
+}
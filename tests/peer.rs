@@ -0,0 +1,92 @@
+// The session's peer: whose datagrams count, and which server comes next
+// when one goes quiet.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::Cli,
+    error::ClientError,
+    peer::Peer,
+    transport::{Handler, Transport},
+};
+use std::{
+    io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from(["client"].iter().chain(args)).unwrap()
+}
+
+// Where it was told to connect, and nothing else
+#[derive(Default)]
+struct Connections {
+    connected: Vec<String>,
+}
+
+impl Transport for Connections {
+    fn receive(&mut self, _handle: &mut Handler) -> Result<bool, ClientError> {
+        Ok(false)
+    }
+
+    fn send(&mut self, _datagram: &[u8]) {}
+
+    fn connect(&mut self, server: &str) -> io::Result<()> {
+        self.connected.push(server.to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn a_pushed_session_listens_only_to_its_first_sender() {
+    let (first, other): (SocketAddr, SocketAddr) = (
+        "10.0.0.1:6014".parse().unwrap(),
+        "10.0.0.2:6014".parse().unwrap(),
+    );
+    let now = Instant::now();
+    let later = now + Duration::from_secs(1);
+    let mut peer = Peer::new(&cli(&["--listen-only"]), now);
+    assert!(peer.hear(other, now));
+    assert!(peer.settle(first) && !peer.settle(other));
+    assert_eq!(peer.source(), Some(first));
+    assert!(!peer.hear(other, later) && peer.hear(first, later));
+    // Heard from is heard from, whether or not it counted
+    assert_eq!(peer.last_heard(), later);
+
+    // A server that was asked can answer from wherever it likes
+    let mut peer = Peer::new(&cli(&[]), now);
+    peer.settle(first);
+    assert!(peer.hear(other, now));
+}
+
+#[test]
+fn failing_over_goes_down_the_list_once() {
+    let args = [
+        "--failover",
+        "--server",
+        "a:1",
+        "--server",
+        "b:2",
+        "--server",
+        "c:3",
+    ];
+    let now = Instant::now();
+    let mut peer = Peer::new(&cli(&args), now);
+    let mut transport = Connections::default();
+    let later = now + Duration::from_secs(5);
+    assert_eq!(
+        peer.fail_over(&mut transport, later).unwrap().as_deref(),
+        Some("b:2")
+    );
+    assert_eq!(peer.last_heard(), later);
+    assert_eq!(
+        peer.fail_over(&mut transport, later).unwrap().as_deref(),
+        Some("c:3")
+    );
+    assert_eq!(peer.fail_over(&mut transport, later).unwrap(), None);
+    assert_eq!(transport.connected, ["b:2", "c:3"]);
+
+    // Without --failover the other servers aren't fallbacks
+    let mut peer = Peer::new(&cli(&["--server", "a:1", "--server", "b:2"]), now);
+    assert_eq!(peer.fail_over(&mut transport, later).unwrap(), None);
+}
@@ -0,0 +1,72 @@
+// --plan: what writing the files would do, with nothing written.
+
+use segmented_file_system_client::{
+    cli::{OnConflict, WriteOrder},
+    file_manager::FileManager,
+    packet::Packet,
+    plan,
+};
+use std::{convert::TryFrom, env, fs};
+
+#[test]
+fn the_plan_says_where_files_would_go_and_writes_nothing() {
+    let dir = env::temp_dir().join(format!("segfs-plan-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut files = FileManager::default();
+    for datagram in [
+        &b"\x00\x01a.txt"[..],
+        b"\x03\x01\x00\x00one, then more",
+        b"\x00\x02b.txt",
+        b"\x03\x02\x00\x00bee",
+        // Not complete, so not in the plan
+        b"\x01\x03\x00\x00c",
+    ] {
+        files.process_packet(Packet::try_from(datagram).unwrap());
+    }
+    fs::write(dir.join("a.txt"), "already here").unwrap();
+
+    let (a, a1, b) = (dir.join("a.txt"), dir.join("a.1.txt"), dir.join("b.txt"));
+    let expected = [
+        format!("--plan: writing 2 files into {} would", dir.display()),
+        format!(
+            "  write {} (14B), leaving {} as it is",
+            a1.display(),
+            a.display()
+        ),
+        format!("  write {} (3B)", b.display()),
+    ];
+    assert_eq!(plan::plan(&mut files, &dir, WriteOrder::Id), expected);
+    assert_eq!(fs::read_to_string(&a).unwrap(), "already here");
+    assert!(!a1.exists() && !b.exists());
+
+    files.on_conflict = OnConflict::Error;
+    let text = plan::plan(&mut files, &dir, WriteOrder::Id);
+    assert_eq!(
+        text[1],
+        format!("  fail on file 1: {} is already there", a.display())
+    );
+    files.on_conflict = OnConflict::SkipIdentical;
+    let text = plan::plan(&mut files, &dir, WriteOrder::Id);
+    assert_eq!(
+        text[1],
+        format!(
+            "  write {} (14B), unless it's the same as {}",
+            a1.display(),
+            a.display()
+        )
+    );
+    files.on_conflict = OnConflict::default();
+
+    // A directory that isn't there yet would be made, and still isn't
+    let deeper = dir.join("new/deeper");
+    let text = plan::plan(&mut files, &deeper, WriteOrder::Id);
+    assert_eq!(
+        text[1..3],
+        [
+            format!("  make directory {}", dir.join("new").display()),
+            format!("  make directory {}", deeper.display()),
+        ]
+    );
+    assert!(!dir.join("new").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
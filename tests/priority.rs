@@ -0,0 +1,63 @@
+// Which datagrams count as control packets, and the order a read's datagrams
+// come out of the queue in.
+
+use segmented_file_system_client::{
+    capability, echo,
+    priority::{self, Priority, Queue},
+};
+
+#[test]
+fn headers_and_echo_replies_are_control() {
+    assert_eq!(priority::classify(b"\x00\x07a.txt"), Priority::Control);
+    assert_eq!(priority::classify(b"\x04\x07a.txt"), Priority::Control);
+    assert_eq!(priority::classify(&echo::probe(1, 2)), Priority::Control);
+    let advertisement = capability::encode(capability::CRC);
+    assert_eq!(priority::classify(&advertisement), Priority::Control);
+    assert_eq!(priority::classify(b""), Priority::Control);
+}
+
+#[test]
+fn data_is_data() {
+    assert_eq!(priority::classify(b"\x01\x07\x00\x00abc"), Priority::Data);
+    assert_eq!(priority::classify(b"\x03\x07\x00\x01"), Priority::Data);
+    // Starting like an echo reply isn't enough without the rest of the magic
+    assert_eq!(priority::classify(b"SFSX rest of it"), Priority::Data);
+}
+
+#[test]
+fn control_goes_first_and_each_keeps_its_order() {
+    let datagrams: [&[u8]; 6] = [
+        b"\x01\x00\x00\x00x",
+        b"\x00\x00a",
+        b"\x01\x00\x00\x01y",
+        b"\x00\x01b",
+        &echo::probe(1, 0),
+        b"\x03\x00\x00\x02z",
+    ];
+    let mut queue = Queue::default();
+    for (i, datagram) in datagrams.iter().enumerate() {
+        queue.push(i, 0..datagram.len(), datagram);
+    }
+    let order: Vec<usize> = queue.drain().map(|(i, _)| i).collect();
+    assert_eq!(order, [1, 3, 4, 0, 2, 5]);
+    assert_eq!(queue.drain().count(), 0);
+}
+
+#[test]
+fn segments_of_one_datagram_are_queued_apart() {
+    let glued = [b"\x01\x00\x00\x00x".as_slice(), b"\x00\x00a"].concat();
+    let mut queue = Queue::default();
+    queue.push(0, 0..5, &glued[0..5]);
+    queue.push(0, 5..8, &glued[5..8]);
+    let slots: Vec<_> = queue.drain().collect();
+    assert_eq!(slots, [(0, 5..8), (0, 0..5)]);
+}
+
+#[test]
+fn an_abandoned_drain_still_empties_the_queue() {
+    let mut queue = Queue::default();
+    queue.push(0, 0..3, b"\x00\x00a");
+    queue.push(1, 0..5, b"\x01\x00\x00\x00x");
+    assert_eq!(queue.drain().next(), Some((0, 0..3)));
+    assert_eq!(queue.drain().count(), 0);
+}
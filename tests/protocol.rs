@@ -0,0 +1,104 @@
+// Protocol versions: the hello on the wire, what gets agreed from the
+// server's advertisement, and a v1 session reading packets the way the
+// course server meant them.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    capability,
+    cli::Cli,
+    events::Progress,
+    file_manager::FileManager,
+    packet::PacketView,
+    protocol::{self, Hello, Version},
+    session::run_session,
+    transport::{ManualClock, ScriptedTransport, Step},
+};
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from(["segmented-file-system-client"].iter().chain(args)).unwrap()
+}
+
+#[test]
+fn the_hello_says_the_version_features_and_rate() {
+    let hello = protocol::hello(&cli(&["--crc", "--limit-rate", "1KiB"]));
+    assert_eq!(hello.len(), protocol::HELLO_LEN);
+    assert_eq!(&hello[..6], b"SFSH\x02\x02");
+    assert_eq!(
+        Hello::parse(&hello),
+        Some(Hello {
+            version: Version::V2,
+            features: capability::CRC | capability::RESUME,
+            rate: Some(1024),
+            salt: None,
+            files: Vec::new(),
+        })
+    );
+    assert_eq!(protocol::requested_rate(&hello), Some(1024));
+
+    // Without a rate, none is asked for
+    let hello = protocol::hello(&cli(&["--no-resume-token"]));
+    assert_eq!(Hello::parse(&hello).map(|hello| hello.features), Some(0));
+    assert_eq!(protocol::requested_rate(&hello), None);
+    // The old hello still parses; something else doesn't
+    assert_eq!(Hello::parse(&protocol::HELLO).unwrap().rate, None);
+    assert_eq!(Hello::parse(b"SFSH\x02\x02"), None);
+    assert_eq!(Hello::parse(b"\x00\x01a.txt"), None);
+
+    // Only some files wanted, and the hello says which
+    let hello = protocol::hello(&cli(&["fetch", "--file-id", "2", "--file-id", "5"]));
+    assert_eq!(hello[protocol::HELLO_LEN..], [2, 2, 5]);
+    assert_eq!(
+        hello,
+        protocol::hello(&cli(&["--file-id", "2", "--file-id", "5"]))
+    );
+    assert_eq!(protocol::requested_files(&hello), [2, 5]);
+    assert_eq!(Hello::parse(&hello[..hello.len() - 1]), None);
+}
+
+#[test]
+fn the_lower_version_is_spoken() {
+    assert_eq!(Version::negotiate(0), None);
+    assert_eq!(Version::negotiate(1), Some(Version::V1));
+    assert_eq!(Version::negotiate(2), Some(Version::V2));
+    assert_eq!(Version::negotiate(9), Some(protocol::LATEST));
+
+    // A wide data packet under v2 is just a last one under v1
+    let packet = b"\x0b\x01\x00\x00abc";
+    assert!(matches!(
+        PacketView::parse(packet),
+        Ok(PacketView::Data { payload: b"c", .. })
+    ));
+    assert!(matches!(
+        PacketView::parse_as(packet, Version::V1),
+        Ok(PacketView::Data {
+            packet_number: 0,
+            is_last_packet: true,
+            payload: b"abc",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn a_v1_server_gets_read_as_v1() {
+    let clock = ManualClock::default();
+    let steps = [
+        &b"SFSC\x01\x01\x00\x00"[..],
+        b"\x00\x01a.txt",
+        b"\x0b\x01\x00\x00abc",
+    ];
+    let steps = steps.iter().map(|b| Step::Datagram(b.to_vec()));
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&["--until", "files=1"]);
+    let (files, report) = run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli,
+        None,
+    )
+    .unwrap();
+    assert_eq!(files.contents(1).unwrap(), b"abc");
+    assert_eq!(report.summary()["protocol"], 1);
+}
@@ -0,0 +1,51 @@
+// Files --expect finds wrong, moved into quarantine/ with why beside them.
+
+use segmented_file_system_client::{file_manager::Finalized, manifest::Divergence, quarantine};
+use serde_json::Value;
+use std::{env, fs};
+
+#[test]
+fn a_wrong_file_is_moved_aside_with_why() {
+    let dir = env::temp_dir().join(format!("segfs-quarantine-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["a.txt", "b.txt"] {
+        fs::write(dir.join(name), name).unwrap();
+    }
+    let mut finalized = Finalized {
+        written: vec![(1, dir.join("a.txt")), (2, dir.join("b.txt"))],
+        ..Finalized::default()
+    };
+    let divergences = [
+        Divergence::WrongSize {
+            name: "a.txt".into(),
+            expected: 15,
+            actual: 5,
+        },
+        // Nothing written to move
+        Divergence::Missing("c.txt".into()),
+    ];
+    let failures: Vec<_> = (divergences.iter())
+        .filter_map(|d| quarantine::failure(d, &finalized.written))
+        .collect();
+    assert_eq!(failures.len(), 1);
+    quarantine::apply(&dir, &mut finalized, &failures).unwrap();
+
+    let moved = dir.join("quarantine/a.txt");
+    assert_eq!(finalized.written[0], (1, moved.clone()));
+    assert!(!dir.join("a.txt").exists() && dir.join("b.txt").exists());
+    assert_eq!(fs::read(&moved).unwrap(), b"a.txt");
+    let failure = fs::read_to_string(dir.join("quarantine/a.txt.failure.json")).unwrap();
+    let failure: Value = serde_json::from_str(&failure).unwrap();
+    assert_eq!(failure["file_id"], 1);
+    assert_eq!(failure["reason"], "size");
+    assert_eq!(
+        (&failure["expected"], &failure["actual"]),
+        (&"15".into(), &"5".into())
+    );
+
+    // Once it's there, it stays put
+    quarantine::apply(&dir, &mut finalized, &failures).unwrap();
+    assert_eq!(finalized.written[0], (1, moved));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,103 @@
+// The ways the receive loop can read its socket: each is a different path
+// to the same place, so a transfer of tests/target-files over loopback has
+// to come out byte for byte the same whichever one it takes.
+
+use segmented_file_system_client::server;
+use std::{
+    env, fs,
+    net::UdpSocket,
+    path::PathBuf,
+    process::{Command, Output},
+    thread,
+};
+
+fn target_files() -> Vec<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/target-files");
+    ["small.txt", "AsYouLikeIt.txt", "binary.jpg"]
+        .iter()
+        .map(|name| dir.join(name))
+        .collect()
+}
+
+// The target files from the companion server into a directory of their own,
+// with `args` picking the path; the files are checked before it's cleaned up
+fn transfer(name: &str, args: &[&str]) -> Output {
+    let dir = env::temp_dir().join(format!("segfs-receive-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let packets = server::load(&target_files(), false).unwrap();
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap().to_string();
+    let options = server::Options {
+        clients: Some(1),
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--quiet", "--bind", "0", "--server", &addr])
+        .args(["--until", "files=3", "--session-timeout", "20s"])
+        .args(["--output-dir", dir.to_str().unwrap()])
+        .args(args)
+        .output()
+        .unwrap();
+    // Before waiting on the server, which hears nothing from a client that fails early
+    assert!(output.status.success(), "{name}: {output:?}");
+    serving.join().unwrap().unwrap();
+    for path in target_files() {
+        let written = dir.join(path.file_name().unwrap());
+        assert!(
+            fs::read(&written).unwrap() == fs::read(&path).unwrap(),
+            "{name}: {} isn't the same",
+            written.display()
+        );
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn every_poll_strategy_delivers_the_same_files() {
+    for strategy in ["blocking", "busy", "adaptive"] {
+        transfer(strategy, &["--poll-strategy", strategy]);
+    }
+    // Adaptive without any spinning, so it blocks between packets too
+    transfer(
+        "adaptive-short",
+        &["--poll-strategy", "adaptive", "--spin-budget", "0ms"],
+    );
+}
+
+// SO_RXQ_OVFL's count comes back whichever way the socket is read, and a
+// transfer that kept up says the kernel dropped nothing
+#[cfg(target_os = "linux")]
+#[test]
+fn the_kernel_drop_count_comes_back_on_every_path() {
+    for (name, args) in [
+        ("drops", &[][..]),
+        ("drops-busy", &["--poll-strategy", "busy"]),
+        ("drops-pipeline", &["--pipeline", "1024"]),
+    ] {
+        let output = transfer(name, args);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("no packets dropped by the kernel"),
+            "{name}: {stdout}"
+        );
+    }
+}
+
+// recvmmsg(2) batches of a few sizes, from one datagram a call to more than
+// the server sends between two of the client's reads
+#[cfg(target_os = "linux")]
+#[test]
+fn every_recv_batch_size_delivers_the_same_files() {
+    for batch in ["1", "2", "16", "256"] {
+        transfer(&format!("batch-{batch}"), &["--recv-batch", batch]);
+    }
+    // Batches with the drops counted too
+    let output = transfer("batch-drops", &["--recv-batch", "32"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("no packets dropped by the kernel"),
+        "{stdout}"
+    );
+}
@@ -0,0 +1,69 @@
+// The slowest, plainest reassembler we could write: parse each datagram by
+// hand, keep every payload in a BTreeMap, and glue them together in order at
+// the end. It's the yardstick the real `FileManager` is measured against, so
+// it mustn't share any code with it.
+
+use std::collections::BTreeMap;
+
+#[derive(Default)]
+struct File {
+    name: Option<String>,
+    packets: BTreeMap<u16, Vec<u8>>,
+    last: Option<u16>,
+}
+
+#[derive(Default)]
+pub struct Reference {
+    files: BTreeMap<u8, File>,
+    started: bool,
+}
+
+impl Reference {
+    // A pushed session starts with its first header; anything before that
+    // is dropped. So are keepalives: empty data packets not marked last.
+    pub fn push(&mut self, datagram: &[u8]) {
+        let status = datagram[0];
+        if status & 1 == 1 && !self.started {
+            return;
+        }
+        if status & 3 == 1 && datagram.len() == 4 {
+            return;
+        }
+        let file = self.files.entry(datagram[1]).or_default();
+        if status & 1 == 0 {
+            file.name = Some(String::from_utf8(datagram[2..].to_vec()).unwrap());
+            self.started = true;
+        } else {
+            let number = u16::from_be_bytes([datagram[2], datagram[3]]);
+            file.packets.insert(number, datagram[4..].to_vec());
+            if status & 3 == 3 {
+                file.last = Some(number);
+            }
+        }
+    }
+
+    fn is_complete(file: &File) -> bool {
+        match file.last {
+            Some(last) => file.name.is_some() && file.packets.len() == last as usize + 1,
+            None => false,
+        }
+    }
+
+    pub fn complete_files(&self) -> usize {
+        self.files.values().filter(|f| Self::is_complete(f)).count()
+    }
+
+    // Name and contents of every complete file
+    pub fn output(&self) -> BTreeMap<String, Vec<u8>> {
+        self.files
+            .values()
+            .filter(|f| Self::is_complete(f))
+            .map(|f| {
+                (
+                    f.name.clone().unwrap(),
+                    f.packets.values().flatten().copied().collect(),
+                )
+            })
+            .collect()
+    }
+}
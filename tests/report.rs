@@ -0,0 +1,38 @@
+// Everything that went wrong in a session, against the file it was.
+
+use segmented_file_system_client::{
+    i18n::{self, Lang},
+    packet::Malformed,
+    report::{FileError, SessionReport},
+};
+use std::time::Instant;
+
+#[test]
+fn every_problem_is_listed_against_its_file() {
+    i18n::set(Lang::En);
+    let mut report = SessionReport::new(Instant::now());
+    report.record_malformed(Malformed::TooShort);
+    report.record_malformed(Malformed::TooShort);
+    report.record_error(Some(2), "write-failed", "couldn't write file 2".into());
+    report.record_error(None, "manifest-mismatch", "c.txt never came".into());
+
+    let errors = report.errors();
+    let codes: Vec<_> = (errors.iter()).map(|e| (e.file_id, e.code)).collect();
+    assert_eq!(
+        codes,
+        [
+            (Some(2), "write-failed"),
+            (None, "manifest-mismatch"),
+            (None, "malformed-packet"),
+        ]
+    );
+    assert_eq!(
+        errors[0],
+        FileError {
+            file_id: Some(2),
+            code: "write-failed",
+            message: "couldn't write file 2".into(),
+        }
+    );
+    assert!(errors[2].message.starts_with("rejected 2 "));
+}
@@ -0,0 +1,83 @@
+// --report-csv: a row per file, with names that have commas, quotes or line
+// breaks in them quoted the way RFC 4180 says.
+
+use segmented_file_system_client::results;
+
+#[test]
+fn fields_are_quoted_only_when_they_need_it() {
+    for (field, quoted) in [
+        ("plain.txt", "plain.txt"),
+        ("", ""),
+        ("a,b.txt", "\"a,b.txt\""),
+        ("say \"hi\".txt", "\"say \"\"hi\"\".txt\""),
+        ("\"", "\"\"\"\""),
+        ("two\nlines", "\"two\nlines\""),
+        ("carriage\rreturn", "\"carriage\rreturn\""),
+        ("it's fine; really", "it's fine; really"),
+    ] {
+        assert_eq!(results::csv_field(field), quoted, "{field:?}");
+    }
+}
+
+// Windows won't have a quote in a file name
+#[cfg(unix)]
+#[test]
+fn the_report_quotes_the_names_that_need_it() {
+    use segmented_file_system_client::{packet::Metadata, server};
+    use std::{env, fs, net::UdpSocket, process::Command, thread};
+    let dir = env::temp_dir().join(format!("segfs-results-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut packets = Vec::new();
+    for (file_id, name) in ["a,b.txt", "say \"hi\".txt", "plain.txt"]
+        .iter()
+        .enumerate()
+    {
+        packets.extend(server::packets(
+            file_id as u8,
+            name,
+            name.as_bytes(),
+            Metadata::default(),
+        ));
+    }
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap().to_string();
+    let options = server::Options {
+        clients: Some(1),
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+
+    let report = dir.join("report.csv");
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--quiet", "--bind", "0", "--server", &addr])
+        .args(["--until", "files=3", "--session-timeout", "10s"])
+        .args(["--output-dir", dir.to_str().unwrap()])
+        .args(["--report-csv", report.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    serving.join().unwrap().unwrap();
+
+    let csv = fs::read_to_string(&report).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "name,id,bytes,packets,duplicates,hash,status,duration_ms"
+    );
+    assert_eq!(lines.len(), 4, "{csv}");
+    for (line, (name, id)) in lines[1..].iter().zip([
+        ("\"a,b.txt\"", 0),
+        ("\"say \"\"hi\"\".txt\"", 1),
+        ("plain.txt", 2),
+    ]) {
+        let prefix = format!("{name},{id},");
+        assert!(line.starts_with(&prefix), "{line}");
+        // Past the name, nothing else has a comma or a quote of its own
+        let rest: Vec<&str> = line[prefix.len()..].split(',').collect();
+        assert_eq!(rest.len(), 6, "{line}");
+        assert_eq!(rest[4], "complete", "{line}");
+        assert!(!line[prefix.len()..].contains('"'), "{line}");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,62 @@
+// The resume token layout, byte for byte, since servers have to parse it
+// without our code.
+
+use segmented_file_system_client::resume::{self, FileProgress, Token};
+
+#[test]
+fn runs_alternate_starting_with_received() {
+    assert_eq!(resume::runs(&[]), Vec::<u32>::new());
+    assert_eq!(resume::runs(&[0, 1, 2]), [3]);
+    assert_eq!(resume::runs(&[0, 1, 4, 5, 6, 9]), [2, 2, 3, 2, 1]);
+    assert_eq!(resume::runs(&[3, 4]), [0, 3, 2]);
+    assert_eq!(resume::runs(&[65535]), [0, 65535, 1]);
+}
+
+#[test]
+fn token_layout() {
+    let token = Token {
+        session_id: 0x0102_0304_0506_0708,
+        files: vec![
+            FileProgress {
+                file_id: 7,
+                have_header: true,
+                expected: Some(300),
+                received: vec![0, 1, 2, 200],
+            },
+            FileProgress {
+                file_id: 9,
+                have_header: false,
+                expected: None,
+                received: vec![],
+            },
+        ],
+    };
+    let encoded = token.encode().unwrap();
+    assert_eq!(encoded.len(), resume::MAX_LEN);
+    let expected = [
+        b'S', b'F', b'S', b'R', 1, // magic, version
+        1, 2, 3, 4, 5, 6, 7, 8, // session ID
+        2, // files
+        7, 1, 0xad, 0x02, // ID, header, expected 300 + 1 as a varint
+        3, 3, 0xc5, 0x01, 1, // runs: 3 received, 197 missing, 1 received
+        9, 0, 0, 0, // ID, no header, expected unknown, no runs
+    ];
+    assert_eq!(encoded[..expected.len()], expected);
+    assert!(encoded[expected.len()..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn oversized_tokens_are_not_sent() {
+    // Every other packet missing costs a byte per packet
+    let received = (0..4000).step_by(2).collect();
+    let token = Token {
+        session_id: 1,
+        files: vec![FileProgress {
+            file_id: 0,
+            have_header: true,
+            expected: None,
+            received,
+        }],
+    };
+    assert!(token.encode().is_none());
+}
@@ -0,0 +1,153 @@
+// Property tests for the packet parser: packets made up at random go to
+// bytes and back unchanged, and random bytes never make it panic. Each seed
+// is its own case, so a failure names the seed that reproduces it.
+
+use segmented_file_system_client::{
+    compression::Compression,
+    packet::{Checksum, Data, Header, Metadata, Packet, PacketParseError, PacketView, Payload},
+};
+use std::{convert::TryFrom, ffi::OsStr, sync::Arc};
+
+const SEEDS: u64 = 2000;
+
+// xorshift64*, so cases are the same on every machine
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.byte()).collect()
+    }
+}
+
+// Names mix ASCII with characters of two, three and four bytes
+fn name(rng: &mut Rng) -> String {
+    const CHARS: [char; 8] = ['a', 'Z', '.', '_', ' ', 'ñ', '€', '🎵'];
+    (0..rng.below(40))
+        .map(|_| CHARS[rng.below(CHARS.len())])
+        .collect()
+}
+
+fn packet(rng: &mut Rng) -> Packet {
+    let file_id = rng.byte();
+    match rng.below(3) {
+        0 => Packet::Header(Header {
+            file_id,
+            file_name: Arc::from(OsStr::new(&name(rng))),
+            // A plain header as often as an extended one
+            metadata: Metadata {
+                size: (rng.below(4) == 0).then(|| rng.next()),
+                modified: (rng.below(4) == 0).then(|| rng.next() as i64),
+                mode: (rng.below(4) == 0).then(|| rng.next() as u32),
+                compression: [Compression::None, Compression::Gzip, Compression::Zstd]
+                    [rng.below(3)],
+            },
+        }),
+        1 => {
+            let packet_number = match rng.below(3) {
+                0 => rng.below(4) as u32,
+                1 => rng.next() as u16 as u32,
+                _ => rng.next() as u32,
+            };
+            let len = [0, 1, 64, 65, 1024][rng.below(5)];
+            Packet::Data(Data {
+                file_id,
+                packet_number,
+                is_last_packet: rng.below(2) == 0,
+                data: Payload::from_vec(rng.bytes(len)),
+            })
+        }
+        _ => Packet::Checksum(Checksum {
+            file_id,
+            digest: rng.bytes(32).try_into().unwrap(),
+        }),
+    }
+}
+
+#[test]
+fn made_up_packets_survive_the_trip_through_bytes() {
+    for seed in 0..SEEDS {
+        let mut rng = Rng::new(seed);
+        let packet = packet(&mut rng);
+        let bytes = packet.to_bytes();
+        let parsed = Packet::try_from(&bytes[..]).unwrap_or_else(|e| panic!("seed {seed}: {e}"));
+        let same = match (&packet, &parsed) {
+            (Packet::Header(a), Packet::Header(b)) => a == b,
+            (Packet::Data(a), Packet::Data(b)) => a == b,
+            (Packet::Checksum(a), Packet::Checksum(b)) => a == b,
+            _ => false,
+        };
+        assert!(same, "seed {seed}: {bytes:02x?}");
+    }
+}
+
+// Whatever parses goes back to bytes that parse to the same thing
+fn check(bytes: &[u8], case: &str) {
+    let Ok(packet) = Packet::try_from(bytes) else {
+        return;
+    };
+    let again = packet.to_bytes();
+    let reparsed = Packet::try_from(&again[..]).unwrap_or_else(|e| panic!("{case}: {e}"));
+    assert_eq!(reparsed.to_bytes(), again, "{case}: {bytes:02x?}");
+    assert_eq!(packet.file_id(), bytes[1], "{case}");
+}
+
+#[test]
+fn random_bytes_never_make_the_parser_panic() {
+    for seed in 0..SEEDS {
+        let mut rng = Rng::new(seed);
+        // Short datagrams are where the edges are, so most are
+        let len = match rng.below(4) {
+            0 => rng.below(8),
+            1 => rng.below(40),
+            _ => rng.below(1100),
+        };
+        let mut bytes = rng.bytes(len);
+        // Keep the status byte to the bits that mean something, mostly
+        if let Some(status) = bytes.first_mut() {
+            if rng.below(4) != 0 {
+                *status &= 0b1111;
+            }
+        }
+        check(&bytes, &format!("seed {seed}"));
+    }
+}
+
+#[test]
+fn every_short_datagram_is_an_error_or_a_packet() {
+    assert_eq!(
+        PacketView::parse(&[]).err(),
+        Some(PacketParseError::PacketTooShort { len: 0 })
+    );
+    for status in 0..=u8::MAX {
+        for len in 1..8 {
+            let mut bytes = vec![0x61; len];
+            bytes[0] = status;
+            check(&bytes, &format!("status {status:#04x}, {len} bytes"));
+        }
+    }
+    for bytes in [&b"\x01\x00"[..], b"\x03\x00\x00", b"\x09\x00\x00\x00\x00"] {
+        assert!(matches!(
+            PacketView::parse(bytes),
+            Err(PacketParseError::DataPacketTooShort { .. })
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+// Scenario files: what they turn into, and a whole session played from one
+// in tests/scenarios.
+
+// What's missing is a list of ranges, even when there's only the one
+#![allow(clippy::single_range_in_vec_init)]
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::{Cli, WriteOrder},
+    events::Progress,
+    file_manager::FileManager,
+    scenario::Scenario,
+    session::run_session,
+    transport::{Clock, ManualClock, ScriptedTransport, Step, Transport},
+};
+use std::{env, fs, path::Path, time::Duration};
+
+#[test]
+fn steps_cut_files_into_packets() {
+    let scenario = Scenario::parse(
+        r#"{
+            "files": [{"id": 3, "name": "a.txt", "content": "abcde", "packet_size": 2}],
+            "steps": [
+                {"header": 3},
+                {"data": {"file": 3, "except": [1]}},
+                {"delay": "150ms"},
+                {"corrupt": {"file": 3, "packet": 1}},
+                {"raw": "00ff"},
+                "await_request"
+            ]
+        }"#,
+    )
+    .unwrap();
+    let steps = scenario.steps().unwrap();
+    let datagram = |step: &Step| match step {
+        Step::Datagram(datagram) => datagram.clone(),
+        _ => panic!("not a datagram"),
+    };
+    assert_eq!(datagram(&steps[0]), b"\x00\x03a.txt");
+    assert_eq!(datagram(&steps[1]), b"\x01\x03\x00\x00ab");
+    assert_eq!(datagram(&steps[2]), b"\x03\x03\x00\x02e");
+    assert!(matches!(steps[3], Step::Silence(d) if d == Duration::from_millis(150)));
+    assert_eq!(datagram(&steps[4]), b"\x01\x03\x00\x01\x9cd");
+    assert_eq!(datagram(&steps[5]), b"\x00\xff");
+    assert!(matches!(steps[6], Step::AwaitSend));
+}
+
+#[test]
+fn mistakes_in_a_scenario_are_caught_before_it_plays() {
+    let steps = |steps: &str| {
+        let text = format!(
+            r#"{{"files": [{{"id": 1, "name": "a", "content": "abc"}}], "steps": [{steps}]}}"#
+        );
+        Scenario::parse(&text).and_then(|scenario| scenario.steps())
+    };
+    assert!(steps(r#"{"header": 1}"#).is_ok());
+    assert!(steps(r#"{"header": 2}"#).is_err());
+    assert!(steps(r#"{"data": {"file": 1, "packets": [1]}}"#).is_err());
+    assert!(steps(r#"{"corrupt": {"file": 1, "packet": 0, "byte": 3}}"#).is_err());
+    assert!(steps(r#"{"delay": "soon"}"#).is_err());
+    assert!(steps(r#"{"raw": "0g"}"#).is_err());
+    assert!(steps(r#"{"shout": 1}"#).is_err());
+    let oversized = r#"{"files": [{"id": 1, "name": "a", "content": "", "packet_size": 2000}],
+                        "steps": []}"#;
+    assert!(Scenario::parse(oversized).is_err());
+}
+
+#[test]
+fn a_scenario_file_plays_a_whole_session() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios/out_of_order.json");
+    let steps = Scenario::load(&path).unwrap().steps().unwrap();
+    let clock = ManualClock::default();
+    let started = clock.now();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    transport
+        .set_poll_interval(Some(Duration::from_millis(50)))
+        .unwrap();
+    let cli =
+        Cli::try_parse_from(["segmented-file-system-client", "--until", "names=a.txt"]).unwrap();
+    let mut progress = Progress::default();
+    let (mut files, report) = run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut progress,
+        &cli,
+        None,
+    )
+    .unwrap();
+    assert_eq!(transport.remaining(), 0);
+    assert_eq!(clock.now() - started, Duration::from_secs(2));
+    assert_eq!(files.duplicates(1), 1);
+    assert_eq!(files.missing_packets(2), Some(vec![1..2]));
+    assert_eq!(report.packets(), 9);
+
+    // The corrupted packet made it in, and the checksum caught it
+    let dir = env::temp_dir().join(format!("segfs-scenario-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let finalized = files.write_all_files(&dir, WriteOrder::Id, None);
+    assert_eq!(finalized.mismatched, [(1, dir.join("a.txt"))]);
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"one, t\x97en more");
+    files.clean_up().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
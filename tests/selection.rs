@@ -0,0 +1,55 @@
+// --only, --exclude, and --file-id: which names a pattern takes in, and when
+// a selection says exactly what it's waiting for.
+
+use segmented_file_system_client::selection::{glob_matches, Selection};
+
+#[test]
+fn stars_and_question_marks_stand_for_characters() {
+    for (pattern, name) in [
+        ("*.txt", "small.txt"),
+        ("*", ""),
+        ("a?c", "abc"),
+        ("*Like*", "AsYouLikeIt.txt"),
+        ("b*.j*g", "binary.jpeg"),
+        ("ñ?", "ñé"),
+    ] {
+        assert!(glob_matches(pattern, name), "{pattern} {name}");
+    }
+    for (pattern, name) in [
+        ("*.txt", "binary.jpg"),
+        ("a?c", "ac"),
+        ("small", "small.txt"),
+        ("*.txt", "a.txt.gz"),
+    ] {
+        assert!(!glob_matches(pattern, name), "{pattern} {name}");
+    }
+}
+
+#[test]
+fn a_file_has_to_pass_every_part_of_the_selection() {
+    let selection = Selection {
+        only: vec!["*.txt".to_string()],
+        exclude: vec!["secret*".to_string()],
+        file_ids: vec![1, 2],
+    };
+    assert!(selection.wants(1, "a.txt"));
+    assert!(!selection.wants(1, "a.jpg"));
+    assert!(!selection.wants(2, "secret.txt"));
+    assert!(!selection.wants(3, "a.txt") && !selection.wants_id(3));
+    assert!(Selection::default().is_everything());
+}
+
+#[test]
+fn only_exact_selections_decide_when_the_session_ends() {
+    let names = |only: &[&str]| Selection {
+        only: only.iter().map(|name| name.to_string()).collect(),
+        ..Selection::default()
+    };
+    assert!(names(&["a.txt", "b.txt"]).completion().is_some());
+    assert!(names(&["*.txt"]).completion().is_none());
+    let ids = Selection {
+        file_ids: vec![4],
+        ..Selection::default()
+    };
+    assert_eq!(ids.completion().unwrap().name(), "--file-id 4");
+}
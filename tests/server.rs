@@ -0,0 +1,152 @@
+// The companion server: files cut into packets the client puts back
+// together, served over loopback.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::Cli,
+    client,
+    events::Progress,
+    packet::{Metadata, Packet},
+    server,
+    session::{self, receive_files, send_hello, send_request},
+};
+use std::{
+    ffi::OsStr,
+    net::UdpSocket,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn a_file_is_cut_at_every_kilobyte() {
+    let content = vec![7; 2500];
+    let packets = server::packets(4, "b.bin", &content, Metadata::default());
+    let sizes: Vec<_> = (packets.iter().map(Packet::to_bytes))
+        .map(|bytes| (bytes[0], bytes.len()))
+        .collect();
+    assert_eq!(sizes, [(0, 7), (1, 1028), (1, 1028), (3, 456)]);
+    assert_eq!(
+        server::packets(0, "empty", b"", Metadata::default()).len(),
+        2
+    );
+}
+
+#[test]
+fn the_client_receives_what_the_server_serves() {
+    let mut packets = server::packets(0, "a.txt", b"one, then more", Metadata::default());
+    packets.extend(server::packets(
+        1,
+        "b.txt",
+        &[b'x'; 3000],
+        Metadata::default(),
+    ));
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap();
+    let serving = thread::spawn(move || {
+        server::serve(
+            &sock,
+            &packets,
+            &server::Options {
+                clients: Some(1),
+                end_marker: true,
+                ..server::Options::default()
+            },
+        )
+    });
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(addr).unwrap();
+    send_request(&client);
+    let cli = Cli::try_parse_from(["client", "--until", "end", "--quiet"]).unwrap();
+    let (files, _) = receive_files(&client, &cli, &mut Progress::default(), None).unwrap();
+    assert!(files.is_complete(0) && files.is_complete(1));
+    assert_eq!(files.file_status(1).unwrap().1, 3);
+    serving.join().unwrap().unwrap();
+}
+
+#[test]
+fn receive_all_hands_the_files_back_in_memory() {
+    let content: Vec<u8> = (0..2500).map(|i| i as u8).collect();
+    let mut packets = server::packets(3, "b.bin", &content, Metadata::default());
+    packets.extend(server::packets(1, "a.txt", b"first", Metadata::default()));
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap().to_string();
+    let options = server::Options {
+        clients: Some(1),
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+
+    let args = [
+        "client", "--server", &addr, "--bind", "0", "--until", "files=2",
+    ];
+    let cli = Cli::try_parse_from(args).unwrap();
+    let files = client::receive_all(&cli).unwrap();
+    let got: Vec<_> = (files.iter())
+        .map(|file| (file.id, file.name.as_os_str(), file.data.len()))
+        .collect();
+    assert_eq!(
+        got,
+        [(1, OsStr::new("a.txt"), 5), (3, OsStr::new("b.bin"), 2500)]
+    );
+    assert_eq!(files[1].data, content);
+    serving.join().unwrap().unwrap();
+}
+
+#[test]
+fn a_limit_rate_in_the_hello_slows_the_server_down() {
+    let cli = Cli::try_parse_from(["client", "--until", "files=1", "--limit-rate", "40KB/s"]);
+    let cli = cli.unwrap();
+    let hello = session::hello(&cli);
+    assert_eq!(session::requested_rate(&hello), Some(40_000));
+    assert_eq!(session::requested_rate(&session::HELLO), None);
+
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap();
+    let packets = server::packets(0, "slow.bin", &[1; 8000], Metadata::default());
+    let options = server::Options {
+        clients: Some(1),
+        pace: Duration::ZERO,
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(addr).unwrap();
+    let started = Instant::now();
+    send_hello(&client, &cli);
+    let (files, report) = receive_files(&client, &cli, &mut Progress::default(), None).unwrap();
+    assert!(files.is_complete(0));
+    // Eight kilobytes at 40 KB/s is a fifth of a second
+    assert!(started.elapsed() >= Duration::from_millis(180));
+    let mut written = Vec::new();
+    report.write(&mut written).unwrap();
+    let written = String::from_utf8(written).unwrap();
+    assert!(written.contains("of the --limit-rate of"), "{written}");
+    serving.join().unwrap().unwrap();
+}
+
+#[test]
+fn a_hello_naming_files_gets_only_those() {
+    let mut packets = server::packets(0, "a.txt", b"not this", Metadata::default());
+    packets.extend(server::packets(1, "b.txt", b"this", Metadata::default()));
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap();
+    let options = server::Options {
+        clients: Some(1),
+        end_marker: true,
+        ..server::Options::default()
+    };
+    let serving = thread::spawn(move || server::serve(&sock, &packets, &options));
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(addr).unwrap();
+    let args = ["client", "--quiet", "--until", "end", "--file-id", "1"];
+    let cli = Cli::try_parse_from(args).unwrap();
+    send_hello(&client, &cli);
+    let (files, report) = receive_files(&client, &cli, &mut Progress::default(), None).unwrap();
+    assert!(files.is_complete(1) && files.file_status(0).is_none());
+    // Nothing of the other file was sent, so nothing was dropped
+    assert_eq!(report.packets(), 2);
+    serving.join().unwrap().unwrap();
+}
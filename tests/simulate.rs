@@ -0,0 +1,127 @@
+// The simulated bad network (--simulate-loss and the rest) wrapped around a
+// scripted transport: what it lets through, and that one seed always makes
+// the same choices.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::Cli,
+    events::Progress,
+    file_manager::FileManager,
+    session::run_session,
+    simulate::{Options, SimulatedTransport, Stats},
+    transport::{ManualClock, ScriptedTransport, Step, Transport},
+};
+use std::time::Duration;
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from(["segmented-file-system-client"].iter().chain(args)).unwrap()
+}
+
+// A hundred datagrams, each its own number, then a pause to let out
+// whatever's held back
+fn script() -> Vec<Step> {
+    let mut steps: Vec<Step> = (0..100u8).map(|i| Step::Datagram(vec![i])).collect();
+    steps.push(Step::Silence(Duration::from_millis(50)));
+    steps
+}
+
+// Everything the session would have been handed
+fn delivered(options: Options) -> (Vec<u8>, Stats) {
+    let clock = ManualClock::default();
+    let mut scripted = ScriptedTransport::new(script(), &clock);
+    let mut simulated = SimulatedTransport::new(&mut scripted, options);
+    let mut delivered = Vec::new();
+    while simulated
+        .receive(&mut |datagram, _| {
+            delivered.push(datagram[0]);
+            Ok(())
+        })
+        .is_ok()
+    {}
+    let stats = simulated.simulation_stats().unwrap();
+    (delivered, stats)
+}
+
+#[test]
+fn lost_datagrams_are_left_out_and_the_rest_kept_in_order() {
+    let options = Options {
+        loss: 0.3,
+        seed: Some(7),
+        ..Options::default()
+    };
+    let (delivered, stats) = delivered(options);
+    assert_eq!(stats.seed, 7);
+    assert!(stats.dropped > 10 && stats.dropped < 50, "{stats:?}");
+    assert_eq!(delivered.len() as u64 + stats.dropped, 100);
+    assert!(delivered.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(self::delivered(options), (delivered, stats));
+}
+
+#[test]
+fn reordered_and_duplicated_datagrams_all_arrive() {
+    let options = Options {
+        duplicate: 0.2,
+        reorder: true,
+        seed: Some(11),
+        ..Options::default()
+    };
+    let (delivered, stats) = delivered(options);
+    assert_eq!(delivered.len() as u64, 100 + stats.duplicated);
+    assert!(stats.duplicated > 0 && stats.reordered > 0, "{stats:?}");
+    assert!(delivered.windows(2).any(|pair| pair[0] > pair[1]));
+    let mut distinct = delivered.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct, (0..100).collect::<Vec<u8>>());
+}
+
+#[test]
+fn a_session_reassembles_through_a_shuffling_network() {
+    let mut steps: Vec<Step> = [
+        &b"\x00\x01a.txt"[..],
+        b"\x01\x01\x00\x00one, ",
+        b"\x01\x01\x00\x01then ",
+        b"\x03\x01\x00\x02more",
+        b"\x00\x02b.txt",
+        b"\x03\x02\x00\x00two",
+    ]
+    .iter()
+    .map(|bytes| Step::Datagram(bytes.to_vec()))
+    .collect();
+    steps.push(Step::Silence(Duration::from_millis(50)));
+    let clock = ManualClock::default();
+    let mut scripted = ScriptedTransport::new(steps, &clock);
+    let options = Options {
+        duplicate: 0.5,
+        reorder: true,
+        seed: Some(3),
+        ..Options::default()
+    };
+    let mut simulated = SimulatedTransport::new(&mut scripted, options);
+    let (files, _) = run_session(
+        &mut simulated,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli(&["--until", "files=2"]),
+        None,
+    )
+    .unwrap();
+    assert_eq!(files.contents(1).unwrap(), b"one, then more");
+    assert_eq!(files.contents(2).unwrap(), b"two");
+}
+
+#[test]
+fn the_options_turn_it_on() {
+    assert_eq!(cli(&[]).simulation(), None);
+    let options = cli(&["--simulate-loss", "5%", "--simulate-seed", "9"]).simulation();
+    assert_eq!(
+        options,
+        Some(Options {
+            loss: 0.05,
+            seed: Some(9),
+            ..Options::default()
+        })
+    );
+    assert!(Cli::try_parse_from(["client", "--simulate-loss", "1.5"]).is_err());
+}
@@ -0,0 +1,53 @@
+// progress.json: what a snapshot says, and replacing and removing it.
+
+use segmented_file_system_client::{
+    file_manager::FileManager,
+    packet::Packet,
+    report::SessionReport,
+    snapshot::{self, Snapshot},
+};
+use std::{convert::TryFrom, env, fs, time::Instant};
+
+#[test]
+fn snapshots_replace_each_other_and_go_away() {
+    let dir = env::temp_dir().join(format!("segfs-snapshot-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut file_manager = FileManager::default();
+    let report = SessionReport::new(Instant::now());
+
+    Snapshot::new(&file_manager, &report, None, 1)
+        .write(&dir)
+        .unwrap();
+    for bytes in [
+        &b"\x00\x03a.txt"[..],
+        b"\x01\x03\x00\x00a",
+        b"\x03\x05\x00\x01b",
+    ] {
+        file_manager.process_packet(Packet::try_from(bytes).unwrap());
+    }
+    let peer = "127.0.0.1:6014".parse().unwrap();
+    Snapshot::new(&file_manager, &report, Some(peer), 2)
+        .write(&dir)
+        .unwrap();
+
+    let json: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.join(snapshot::FILE_NAME)).unwrap()).unwrap();
+    assert_eq!(json["updated_unix_ms"], 2);
+    assert_eq!(json["peer"], "127.0.0.1:6014");
+    assert_eq!(json["pid"], std::process::id());
+    let files = json["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["name"], "a.txt");
+    assert_eq!(files[0]["received"], 1);
+    assert!(files[0]["expected"].is_null());
+    assert_eq!(files[1]["id"], 5);
+    assert!(files[1]["name"].is_null());
+    assert_eq!(files[1]["expected"], 2);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    snapshot::remove(&dir).unwrap();
+    assert!(!dir.join(snapshot::FILE_NAME).exists());
+    // Nothing to remove is fine too
+    snapshot::remove(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
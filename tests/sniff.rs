@@ -0,0 +1,81 @@
+// --fix-extensions: what the first bytes say a file is, the names that
+// follow from it, and files written under them.
+
+use segmented_file_system_client::{
+    cli::WriteOrder,
+    file_manager::FileManager,
+    packet::Packet,
+    sniff::{self, Kind, Renamed},
+};
+use std::{convert::TryFrom, env, fs};
+
+#[test]
+fn magic_bytes_say_what_a_file_is() {
+    assert_eq!(sniff::sniff(b"\x89PNG\r\n\x1a\n\0\0"), Some(Kind::Png));
+    assert_eq!(sniff::sniff(b"%PDF-1.7\n"), Some(Kind::Pdf));
+    assert_eq!(sniff::sniff(b"PK\x03\x04\x14\0"), Some(Kind::Zip));
+    assert_eq!(sniff::sniff(b"\x7fELF\x02\x01"), Some(Kind::Elf));
+    assert_eq!(
+        sniff::sniff("héllo\r\n\tthere".as_bytes()),
+        Some(Kind::Text)
+    );
+    // Cut off in the middle of a character, it's still text
+    assert_eq!(sniff::sniff(&"né".as_bytes()[..2]), Some(Kind::Text));
+    assert_eq!(sniff::sniff(b"bin\0ary"), None);
+    assert_eq!(sniff::sniff(b"\xff\xfe"), None);
+    assert_eq!(sniff::sniff(b""), None);
+}
+
+#[test]
+fn extensions_are_added_or_swapped() {
+    let fixed = |name, kind| sniff::fixed_name(name, kind);
+    assert_eq!(fixed("3", Kind::Png).as_deref(), Some("3.png"));
+    assert_eq!(fixed("logo.txt", Kind::Png).as_deref(), Some("logo.png"));
+    assert_eq!(fixed("LOGO.PNG", Kind::Png), None);
+    assert_eq!(
+        fixed("report.v2", Kind::Pdf).as_deref(),
+        Some("report.v2.pdf")
+    );
+    assert_eq!(fixed("notes.docx", Kind::Zip), None);
+    assert_eq!(
+        fixed("unnamed-4.bin", Kind::Zip).as_deref(),
+        Some("unnamed-4.zip")
+    );
+    assert_eq!(fixed("tool", Kind::Elf).as_deref(), Some("tool.elf"));
+    assert_eq!(fixed("README", Kind::Text).as_deref(), Some("README.txt"));
+    assert_eq!(fixed("main.rs", Kind::Text), None);
+    assert_eq!(fixed("data.pdf", Kind::Text).as_deref(), Some("data.txt"));
+}
+
+#[test]
+fn files_are_written_under_the_names_their_contents_give() {
+    let dir = env::temp_dir().join(format!("segfs-sniff-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut files = FileManager::default();
+    for datagram in [
+        &b"\x00\x017"[..],
+        b"\x03\x01\x00\x00\x89PNG\r\n\x1a\npixels",
+        b"\x00\x02b.txt",
+        b"\x03\x02\x00\x00bee",
+        // Would become 7.png too, so it's left alone
+        b"\x00\x037.pdf",
+        b"\x03\x03\x00\x00\x89PNG\r\n\x1a\nmore",
+    ] {
+        files.process_packet(Packet::try_from(datagram).unwrap());
+    }
+
+    let renamed = sniff::fix_extensions(&mut files);
+    assert_eq!(
+        renamed,
+        [Renamed {
+            file_id: 1,
+            from: "7".into(),
+            to: "7.png".into(),
+        }]
+    );
+    files.write_all_files(&dir, WriteOrder::Id, None);
+    assert!(dir.join("7.png").exists() && !dir.join("7").exists());
+    assert!(dir.join("b.txt").exists() && dir.join("7.pdf").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,98 @@
+// Room for the files: what the headers say held up against the free space,
+// before any of it is written. Only Linux says how much there is.
+#![cfg(target_os = "linux")]
+
+use segmented_file_system_client::{
+    capture::Recorder,
+    error::ClientError,
+    file_manager::FileManager,
+    packet::{Header, Metadata, Packet},
+    space::{self, Preflight},
+};
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    path::Path,
+    process::{Command, Output},
+    sync::Arc,
+    time::Instant,
+};
+
+fn huge_header() -> Vec<u8> {
+    Packet::Header(Header {
+        file_id: 1,
+        file_name: Arc::from(OsStr::new("a.txt")),
+        metadata: Metadata {
+            size: Some(1 << 62),
+            ..Metadata::default()
+        },
+    })
+    .to_bytes()
+}
+
+fn client(capture: &Path, dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--quiet", "--json", "--until", "files=1"])
+        .args(["--replay", capture.to_str().unwrap()])
+        .args(["--output-dir", dir.to_str().unwrap()])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn sizes_that_fit_pass() {
+    let files = FileManager::default();
+    let mut preflight = Preflight::new([env::temp_dir()]);
+    preflight.header(0, 10, &files).unwrap();
+    // The same header again is nothing new
+    preflight.header(0, 10, &files).unwrap();
+    // A directory yet to be made has its parent's room
+    let unmade = env::temp_dir().join("segfs-space-not-yet/deeper");
+    assert_eq!(
+        space::available(&unmade),
+        space::available(&env::temp_dir())
+    );
+}
+
+#[test]
+fn sizes_that_dont_fit_end_the_session() {
+    let files = FileManager::default();
+    let mut preflight = Preflight::new([env::temp_dir()]);
+    preflight.header(0, 1 << 20, &files).unwrap();
+    let error = preflight.header(1, u64::MAX, &files).unwrap_err();
+    assert!(matches!(
+        error,
+        ClientError::InsufficientSpace {
+            needed: u64::MAX,
+            ..
+        }
+    ));
+    assert_eq!(
+        (error.code(), error.exit_code()),
+        ("insufficient-space", 14)
+    );
+
+    let scratch = env::temp_dir().join(format!("segfs-space-{}", std::process::id()));
+    fs::create_dir_all(&scratch).unwrap();
+    let capture = scratch.join("capture");
+    let started = Instant::now();
+    let mut recorder = Recorder::create(&capture, started).unwrap();
+    for datagram in [&huge_header()[..], b"\x03\x01\x00\x00abc"] {
+        recorder.record(started, datagram).unwrap();
+    }
+    recorder.finish().unwrap();
+    let dir = scratch.join("out");
+    let output = client(&capture, &dir, &[]);
+    assert_eq!(output.status.code(), Some(14), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"insufficient-space\""), "{stdout}");
+    assert!(!dir.join("a.txt").exists());
+
+    // Unless it's not asked
+    let output = client(&capture, &dir, &["--no-space-check"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"abc");
+    fs::remove_dir_all(scratch).unwrap();
+}
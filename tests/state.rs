@@ -0,0 +1,135 @@
+// The --resume log: packets written to it come back on the next run, a cut
+// off or damaged tail is dropped, and a log for another server is left alone
+// unless --force says to start over.
+
+use segmented_file_system_client::{
+    packet::Packet,
+    state::{self, Cut, Foreign, StateLog},
+};
+use std::{convert::TryFrom, env, fs, io, path::PathBuf, time::Instant};
+
+const SERVER: &str = "127.0.0.1:6014";
+
+fn packets() -> Vec<Packet> {
+    [
+        &b"\x00\x02a.txt"[..],
+        b"\x01\x02\x00\x00one",
+        b"\x03\x02\x00\x01two",
+    ]
+    .iter()
+    .map(|bytes| Packet::try_from(*bytes).unwrap())
+    .collect()
+}
+
+fn wire(packets: &[Packet]) -> Vec<Vec<u8>> {
+    packets.iter().map(Packet::to_bytes).collect()
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("segfs-state-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn packets_go_back_on_the_wire_as_they_came() {
+    assert_eq!(
+        wire(&packets()),
+        [
+            &b"\x00\x02a.txt"[..],
+            b"\x01\x02\x00\x00one",
+            b"\x03\x02\x00\x01two"
+        ]
+    );
+}
+
+#[test]
+fn a_log_comes_back_whole_and_loses_only_a_damaged_tail() {
+    let dir = scratch("reopen");
+    let (mut log, loaded) = StateLog::open(&dir, SERVER, false).unwrap();
+    assert!(loaded.is_empty());
+    for packet in &packets() {
+        log.append(packet).unwrap();
+    }
+    log.flush().unwrap();
+    drop(log);
+
+    let path = dir.join(state::FILE_NAME);
+    let whole = fs::read(&path).unwrap();
+    let decoded = state::decode(&whole, SERVER).unwrap();
+    assert_eq!(wire(&decoded.packets), wire(&packets()));
+    assert_eq!((decoded.whole, decoded.cut), (whole.len(), None));
+    let cut = state::decode(&whole[..whole.len() - 3], SERVER).unwrap();
+    assert_eq!(cut.cut, Some(Cut::Short));
+
+    // Killed partway through the last record
+    fs::write(&path, &whole[..whole.len() - 3]).unwrap();
+    let (mut log, loaded) = StateLog::open(&dir, SERVER, false).unwrap();
+    assert_eq!(wire(&loaded), wire(&packets()[..2]));
+    log.append(&packets()[2]).unwrap();
+    log.flush_if_due(Instant::now()).unwrap();
+    log.flush().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), whole);
+
+    state::clear(&dir).unwrap();
+    assert!(!path.exists());
+    state::clear(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_flipped_bit_ends_the_log_there() {
+    let dir = scratch("damaged");
+    let (mut log, _) = StateLog::open(&dir, SERVER, false).unwrap();
+    for packet in &packets() {
+        log.append(packet).unwrap();
+    }
+    log.flush().unwrap();
+    let mut bytes = fs::read(dir.join(state::FILE_NAME)).unwrap();
+    let last = bytes.len() - 6;
+    bytes[last] ^= 1;
+    let decoded = state::decode(&bytes, SERVER).unwrap();
+    assert_eq!(decoded.packets.len(), 2);
+    assert_eq!(decoded.cut, Some(Cut::Crc));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn another_servers_log_starts_over_only_with_force() {
+    let dir = scratch("server");
+    let (mut log, _) = StateLog::open(&dir, SERVER, false).unwrap();
+    log.append(&packets()[0]).unwrap();
+    log.flush().unwrap();
+    drop(log);
+
+    let path = dir.join(state::FILE_NAME);
+    let bytes = fs::read(&path).unwrap();
+    let other = "10.0.0.1:6014";
+    assert_eq!(
+        state::decode(&bytes, other).err(),
+        Some(Foreign::Server(SERVER.into()))
+    );
+    assert_eq!(
+        state::decode(b"not a log", SERVER).err(),
+        Some(Foreign::NotALog)
+    );
+
+    // Stopped, with the log as it was
+    let e = StateLog::open(&dir, other, false).err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    assert!(
+        e.to_string().contains(SERVER) && e.to_string().contains("--force"),
+        "{e}"
+    );
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+    let (_, loaded) = StateLog::open(&dir, other, true).unwrap();
+    assert!(loaded.is_empty());
+    let e = StateLog::open(&dir, SERVER, false).err().unwrap();
+    assert!(e.to_string().contains(other), "{e}");
+
+    fs::write(&path, "not a log").unwrap();
+    assert!(StateLog::open(&dir, SERVER, false).is_err());
+    let (_, loaded) = StateLog::open(&dir, SERVER, true).unwrap();
+    assert!(loaded.is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
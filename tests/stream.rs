@@ -0,0 +1,92 @@
+// --stdout: a file's bytes go out in order, each run as soon as nothing
+// before it is missing.
+
+use segmented_file_system_client::{
+    file_manager::FileManager,
+    packet::Packet,
+    stream::{parse_target, Stream, Target},
+};
+use std::convert::TryFrom;
+
+fn store(files: &mut FileManager, bytes: &[u8]) {
+    files.process_packet(Packet::try_from(bytes).unwrap());
+}
+
+#[test]
+fn runs_go_out_once_the_gap_before_them_is_filled() {
+    let mut files = FileManager::default();
+    let mut stream = Stream::new(parse_target("a.txt").unwrap(), Vec::new());
+    store(&mut files, b"\x01\x01\x00\x01then ");
+    store(&mut files, b"\x01\x01\x00\x00one, ");
+    stream.advance(&files).unwrap();
+    // Nothing's known to be a.txt until its header comes
+    assert_eq!(stream.written(), 0);
+
+    store(&mut files, b"\x00\x01a.txt");
+    stream.advance(&files).unwrap();
+    assert_eq!(stream.written(), 2);
+    store(&mut files, b"\x03\x01\x00\x03!");
+    stream.advance(&files).unwrap();
+    assert_eq!(stream.written(), 2);
+    store(&mut files, b"\x01\x01\x00\x02more");
+    stream.advance(&files).unwrap();
+    assert_eq!(stream.into_inner(), b"one, then more!");
+}
+
+#[test]
+fn a_number_is_a_file_id() {
+    assert_eq!(parse_target("7"), Ok(Target::Id(7)));
+    assert_eq!(parse_target("300"), Ok(Target::Name("300".to_string())));
+    assert!(parse_target("").is_err());
+}
+
+#[test]
+fn a_buffer_keeps_only_so_much_past_what_went_out() {
+    let mut files = FileManager::default();
+    let mut stream = Stream::new(Target::Id(1), Vec::new()).with_buffer(2048);
+    store(&mut files, b"\x00\x01a.txt");
+    // Room for packets 0 and 1, as nothing has gone out
+    assert!(stream.admit(1, 1));
+    store(&mut files, b"\x01\x01\x00\x01then ");
+    assert!(!stream.admit(1, 2) && !stream.admit(1, 3));
+    // Other files are kept as they come
+    assert!(stream.admit(2, 9));
+    assert_eq!(stream.missing(&files), Some((1, vec![0])));
+
+    store(&mut files, b"\x01\x01\x00\x00one, ");
+    stream.advance(&files).unwrap();
+    // Both dropped ones now fit, so they're asked for
+    assert_eq!(stream.missing(&files), Some((1, vec![2, 3])));
+    assert!(stream.admit(1, 2) && stream.admit(1, 3));
+    store(&mut files, b"\x01\x01\x00\x02more");
+    store(&mut files, b"\x03\x01\x00\x03!");
+    stream.advance(&files).unwrap();
+    assert_eq!(stream.missing(&files), Some((1, vec![])));
+    assert_eq!(stream.into_inner(), b"one, then more!");
+
+    // Without a buffer everything's kept, and the usual NACKs go out
+    let mut unbounded = Stream::new(Target::Id(1), Vec::new());
+    assert!(unbounded.admit(1, 1000));
+    assert_eq!(unbounded.missing(&files), None);
+}
+
+// --pipe-to: the file on a command's standard input, and its failure ours
+#[cfg(unix)]
+#[test]
+fn a_piped_file_goes_to_the_command() {
+    use segmented_file_system_client::stream::Out;
+    use std::{env, fs};
+    let path = env::temp_dir().join(format!("segfs-pipe-to-{}", std::process::id()));
+    let command = format!("tr a-z A-Z > '{}'", path.display());
+    let mut files = FileManager::default();
+    store(&mut files, b"\x00\x01a.txt");
+    store(&mut files, b"\x03\x01\x00\x00shout");
+    let mut stream = Stream::new(Target::Id(1), Out::open(Some(&command)).unwrap());
+    stream.advance(&files).unwrap();
+    stream.into_inner().finish().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"SHOUT");
+    fs::remove_file(&path).unwrap();
+
+    let failed = Out::open(Some("cat > /dev/null; exit 3")).unwrap().finish();
+    assert!(failed.unwrap_err().to_string().contains("exit status: 3"));
+}
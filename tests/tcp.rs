@@ -0,0 +1,140 @@
+// --transport tcp: frames on the connection, a whole session over one from
+// the companion server, and a server that hangs up partway.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::Cli,
+    events::Progress,
+    packet::Metadata,
+    server,
+    session::{self, HELLO},
+    tcp::{self, TcpTransport},
+    transport::{SystemClock, Transport},
+};
+use std::{io::Cursor, net::TcpListener, thread};
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from(["client", "--quiet"].iter().chain(args)).unwrap()
+}
+
+#[test]
+fn frames_are_a_length_and_then_the_datagram() {
+    let mut out = Vec::new();
+    tcp::write_frame(&mut out, b"\x00\x01a.txt").unwrap();
+    tcp::write_frame(&mut out, b"").unwrap();
+    assert_eq!(out, b"\x00\x07\x00\x01a.txt\x00\x00");
+
+    let mut input = Cursor::new(out);
+    assert_eq!(
+        tcp::read_frame(&mut input).unwrap().unwrap(),
+        b"\x00\x01a.txt"
+    );
+    assert_eq!(tcp::read_frame(&mut input).unwrap().unwrap(), b"");
+    assert_eq!(tcp::read_frame(&mut input).unwrap(), None);
+    // Cut off inside one
+    let mut input = Cursor::new(b"\x00\x07\x00\x01a".to_vec());
+    assert!(tcp::read_frame(&mut input).is_err());
+    assert!(tcp::write_frame(&mut Vec::new(), &[0; 70_000]).is_err());
+}
+
+#[test]
+fn a_session_runs_over_a_connection() {
+    let mut packets = server::packets(0, "a.txt", b"one, then more", Metadata::default());
+    packets.extend(server::packets(
+        1,
+        "b.txt",
+        &[b'x'; 3000],
+        Metadata::default(),
+    ));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let serving = thread::spawn(move || {
+        let options = server::Options {
+            clients: Some(1),
+            ..server::Options::default()
+        };
+        server::serve_tcp(&listener, &packets, &options)
+    });
+
+    let mut transport = TcpTransport::new(None);
+    transport.connect(&addr.to_string()).unwrap();
+    transport.send(&HELLO);
+    let cli = cli(&["--until", "files=2"]);
+    let (files, _) = session::receive_files_over(
+        &mut transport,
+        &SystemClock,
+        &cli,
+        &mut Progress::default(),
+        None,
+    )
+    .unwrap();
+    assert!(files.is_complete(0) && files.is_complete(1));
+    assert_eq!(files.payload(0, 0), Some(&b"one, then more"[..]));
+    assert_eq!(files.file_status(1).unwrap().1, 3);
+    serving.join().unwrap().unwrap();
+}
+
+#[test]
+fn a_server_that_hangs_up_is_failed_over() {
+    // Sends the header and a piece, then closes
+    let quitter = TcpListener::bind("127.0.0.1:0").unwrap();
+    let quitter_addr = quitter.local_addr().unwrap();
+    let quitting = thread::spawn(move || {
+        let (mut stream, _) = quitter.accept().unwrap();
+        tcp::read_frame(&mut stream).unwrap();
+        let packets = server::packets(0, "a.txt", &[b'q'; 2000], Metadata::default());
+        for packet in &packets[..2] {
+            tcp::write_frame(&mut stream, &packet.to_bytes()).unwrap();
+        }
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let serving = thread::spawn(move || {
+        let packets = server::packets(0, "a.txt", &[b'q'; 2000], Metadata::default());
+        let options = server::Options {
+            clients: Some(1),
+            ..server::Options::default()
+        };
+        server::serve_tcp(&listener, &packets, &options)
+    });
+
+    let (first, second) = (quitter_addr.to_string(), addr.to_string());
+    let failing_over = cli(&[
+        "--server",
+        &first,
+        "--server",
+        &second,
+        "--failover",
+        "--until",
+        "files=1",
+    ]);
+    let mut transport = TcpTransport::new(None);
+    transport.connect(&first).unwrap();
+    transport.send(&HELLO);
+    let (files, report) = session::receive_files_over(
+        &mut transport,
+        &SystemClock,
+        &failing_over,
+        &mut Progress::default(),
+        None,
+    )
+    .unwrap();
+    assert!(files.is_complete(0));
+    assert_eq!(report.summary()["failovers"], 1);
+    quitting.join().unwrap();
+    serving.join().unwrap().unwrap();
+
+    // With nowhere to go, it's the error
+    let mut transport = TcpTransport::new(None);
+    transport.connect(&first).unwrap();
+    let e = session::receive_files_over(
+        &mut transport,
+        &SystemClock,
+        &cli(&["--server", &first]),
+        &mut Progress::default(),
+        None,
+    )
+    .err()
+    .unwrap();
+    assert!(e.to_string().contains("refused"), "{e}");
+}
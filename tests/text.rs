@@ -0,0 +1,50 @@
+// --normalize-text: line endings either way, byte order marks, and binary
+// files left alone.
+
+use segmented_file_system_client::text::{self, Newlines};
+
+#[test]
+fn every_line_ending_becomes_the_one_asked_for() {
+    let mixed = b"one\r\ntwo\nthree\rfour\r\n\r\n";
+    assert_eq!(
+        text::normalize(mixed, Newlines::Lf).unwrap(),
+        b"one\ntwo\nthree\nfour\n\n"
+    );
+    assert_eq!(
+        text::normalize(mixed, Newlines::Crlf).unwrap(),
+        b"one\r\ntwo\r\nthree\r\nfour\r\n\r\n"
+    );
+}
+
+#[test]
+fn normalizing_twice_changes_nothing_more() {
+    for newlines in [Newlines::Lf, Newlines::Crlf] {
+        let once = text::normalize(b"a\r\nb\rc\n", newlines).unwrap();
+        assert_eq!(text::normalize(&once, newlines).unwrap(), once);
+    }
+}
+
+#[test]
+fn byte_order_marks_are_dropped() {
+    assert_eq!(
+        text::normalize(b"\xEF\xBB\xBFfn main() {}\r\n", Newlines::Lf).unwrap(),
+        b"fn main() {}\n"
+    );
+    // Only at the start
+    assert_eq!(
+        text::normalize("a\u{FEFF}b".as_bytes(), Newlines::Lf).unwrap(),
+        "a\u{FEFF}b".as_bytes()
+    );
+}
+
+#[test]
+fn binary_files_are_left_alone() {
+    assert_eq!(
+        text::normalize(b"\x89PNG\r\n\x1a\n\0\0", Newlines::Lf),
+        None
+    );
+    assert_eq!(text::normalize(b"\xFF\xD8\xFF\xE0\r\n", Newlines::Lf), None);
+    assert!(!text::is_text(b"text\0with a NUL"));
+    assert!(text::is_text("¿qué?\r\n".as_bytes()));
+    assert_eq!(text::normalize(b"", Newlines::Crlf).unwrap(), b"");
+}
@@ -0,0 +1,59 @@
+// --trace-packets: what a datagram's dump says about it, and a replayed
+// capture traced to a file.
+
+use segmented_file_system_client::{capture::Recorder, protocol::Version, trace};
+use std::{env, fs, process::Command, time::Instant};
+
+#[test]
+fn each_datagram_is_annotated_and_dumped() {
+    let text = trace::annotate(b"\x03\x01\x00\x00one, then more", Version::V2);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "18 bytes, status 0x03 (data, last)");
+    assert_eq!(
+        lines[1],
+        "  data: file 1, packet 0, last, 14 bytes of payload"
+    );
+    assert_eq!(
+        lines[2],
+        "  0000  03 01 00 00 6f 6e 65 2c  20 74 68 65 6e 20 6d 6f  |....one, then mo|"
+    );
+    assert!(lines[3].starts_with("  0010  72 65 ") && lines[3].ends_with(" |re|"));
+
+    // What the parser turned down, and what it never sees
+    let text = trace::annotate(b"\x01\x01", Version::V2);
+    assert!(text.contains("(data)\n  unparsed: "), "{text}");
+    assert!(trace::annotate(b"SFSC\x01\x00\x00\x01", Version::V2)
+        .contains("capability advertisement: crc"));
+    // Under v1 the bits v2 added are left out
+    assert_eq!(trace::status_bits(0x1b), ["data", "last", "wide"]);
+    let text = trace::annotate(b"\x0b\x01\x00\x00abc", Version::V1);
+    assert!(text.contains("status 0x0b (data, last)\n  data: file 1, packet 0, last, 3 bytes"));
+    assert_eq!(trace::status_bits(0x18), ["header", "extended", "gzip"]);
+}
+
+#[test]
+fn a_session_traces_every_datagram() {
+    let scratch = env::temp_dir().join(format!("segfs-trace-{}", std::process::id()));
+    fs::create_dir_all(&scratch).unwrap();
+    let (capture, trace) = (scratch.join("capture"), scratch.join("trace.txt"));
+    let started = Instant::now();
+    let mut recorder = Recorder::create(&capture, started).unwrap();
+    for datagram in [&b"\x00\x01a.txt"[..], b"\x03\x01\x00\x00one"] {
+        recorder.record(started, datagram).unwrap();
+    }
+    recorder.finish().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_segmented-file-system-client"))
+        .args(["--lang", "en", "--quiet", "--until", "files=1"])
+        .args(["--replay", capture.to_str().unwrap()])
+        .args(["--output-dir", scratch.to_str().unwrap()])
+        .args(["--trace-packets", trace.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let text = fs::read_to_string(&trace).unwrap();
+    assert!(text.starts_with("+0.000000s "), "{text}");
+    assert!(text.contains("7 bytes, status 0x00 (header)\n  header: file 1, name \"a.txt\"\n"));
+    assert!(text.contains("  data: file 1, packet 0, last, 3 bytes of payload\n"));
+    fs::remove_dir_all(scratch).unwrap();
+}
@@ -0,0 +1,635 @@
+// The whole receive loop run against transports that aren't a socket: a
+// scripted one on a clock that only moves when the script says, and an
+// in-memory link to a server on another thread, and `bench pipeline`'s
+// generator. And --pipeline, over loopback.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    bench::Synthetic,
+    capability, checksum,
+    cli::Cli,
+    client::{self, IpVersion},
+    completion,
+    error::ClientError,
+    events::Progress,
+    file_manager::FileManager,
+    nack,
+    report::SessionReport,
+    session::{self, receive_files, run_session, send_request},
+    transport::{
+        Clock, ManualClock, MemoryTransport, PipelineTransport, ScriptedTransport, Step,
+        SystemClock, Transport,
+    },
+    wire,
+};
+use socket2::SockRef;
+use std::{ffi::OsStr, net::UdpSocket, thread, time::Duration};
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from(["segmented-file-system-client"].iter().chain(args)).unwrap()
+}
+
+fn datagrams(bytes: &[&[u8]]) -> Vec<Step> {
+    bytes.iter().map(|b| Step::Datagram(b.to_vec())).collect()
+}
+
+fn run(
+    transport: &mut dyn Transport,
+    clock: &dyn Clock,
+    cli: &Cli,
+) -> Result<(FileManager, SessionReport), ClientError> {
+    transport
+        .set_poll_interval(Some(Duration::from_millis(50)))
+        .unwrap();
+    let mut progress = Progress::default();
+    run_session(
+        transport,
+        clock,
+        FileManager::default(),
+        &mut progress,
+        cli,
+        None,
+    )
+}
+
+const TWO_FILES: [&[u8]; 5] = [
+    b"\x01\x01\x00\x00one, ",
+    b"\x00\x01a.txt",
+    b"\x03\x02\x00\x00two",
+    b"\x03\x01\x00\x01then more",
+    b"\x00\x02b.txt",
+];
+
+#[test]
+fn a_scripted_session_ends_once_its_files_are_whole() {
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(datagrams(&TWO_FILES), &clock);
+    let (files, report) = run(&mut transport, &clock, &cli(&["--until", "files=2"])).unwrap();
+    assert!(files.is_complete(1) && files.is_complete(2));
+    assert_eq!(
+        files.file_status(1),
+        Some((Some(OsStr::new("a.txt")), 2, Some(2)))
+    );
+    assert_eq!(report.packets(), 5);
+    assert_eq!(transport.remaining(), 0);
+}
+
+#[test]
+fn running_out_of_script_is_an_error_not_a_hang() {
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(datagrams(&TWO_FILES[..2]), &clock);
+    let Err(ClientError::IoError(e)) = run(&mut transport, &clock, &cli(&[])) else {
+        panic!("the session should have failed");
+    };
+    assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn timers_run_on_the_scripts_clock() {
+    let clock = ManualClock::default();
+    let started = clock.now();
+    let steps = vec![Step::Silence(Duration::from_secs(2)); 3];
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&["--session-timeout", "5s"]);
+    let result = run(&mut transport, &clock, &cli);
+    assert!(matches!(result, Err(ClientError::SessionTimeout { .. })));
+    assert_eq!(clock.now() - started, Duration::from_secs(6));
+}
+
+#[test]
+fn an_unanswered_hello_goes_out_again_less_and_less_often() {
+    let clock = ManualClock::default();
+    let started = clock.now();
+    let mut steps = vec![Step::AwaitSend; 3];
+    steps.extend(datagrams(&TWO_FILES));
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&["--until", "files=2", "--hello-interval", "100ms"]);
+    run(&mut transport, &clock, &cli).unwrap();
+    // At 100ms, 300ms and 700ms
+    assert_eq!(transport.sent, vec![session::hello(&cli); 3]);
+    let waited = clock.now() - started;
+    assert!(waited >= Duration::from_millis(700) && waited < Duration::from_millis(800));
+}
+
+#[test]
+fn a_server_that_never_answers_is_unreachable() {
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(vec![Step::AwaitSend; 10], &clock);
+    let cli = cli(&["--hello-interval", "100ms", "--connect-timeout", "1s"]);
+    let Err(ClientError::ServerUnreachable { timeout, requests }) =
+        run(&mut transport, &clock, &cli)
+    else {
+        panic!("the session should have given up");
+    };
+    assert_eq!(timeout, Duration::from_secs(1));
+    // The first, and then at 100ms, 300ms and 700ms
+    assert_eq!(requests, 4);
+
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(vec![Step::AwaitSend; 10], &clock);
+    let cli = self::cli(&["--connect-retries", "0", "--connect-timeout", "1s"]);
+    assert!(run(&mut transport, &clock, &cli).is_err());
+    assert!(transport.sent.is_empty());
+}
+
+#[test]
+fn a_server_thread_over_memory() {
+    let (mut transport, server) = MemoryTransport::pair();
+    let server = thread::spawn(move || {
+        let request = server.requests.recv().unwrap();
+        for datagram in TWO_FILES {
+            assert!(server.send(datagram));
+        }
+        request
+    });
+    transport.send(&session::HELLO);
+    let (files, _) = run(&mut transport, &SystemClock, &cli(&["--until", "files=2"])).unwrap();
+    assert!(files.is_complete(1) && files.is_complete(2));
+    assert_eq!(server.join().unwrap(), session::HELLO);
+}
+
+#[test]
+fn a_pipeline_hands_over_every_datagram_and_counts_them() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sock.connect(server.local_addr().unwrap()).unwrap();
+    let cli = cli(&["--until", "files=2", "--pipeline", "2"]);
+    let mut transport = PipelineTransport::new(&sock, &cli, 2).unwrap();
+    for datagram in TWO_FILES {
+        server
+            .send_to(datagram, sock.local_addr().unwrap())
+            .unwrap();
+    }
+    let (files, _) = run(&mut transport, &SystemClock, &cli).unwrap();
+    assert!(files.is_complete(1) && files.is_complete(2));
+    let stats = transport.pipeline_stats().unwrap();
+    assert_eq!((stats.queued, stats.capacity), (5, 2));
+    assert!(stats.high_water >= 1 && stats.high_water <= 2);
+}
+
+#[test]
+fn a_second_last_packet_further_on_moves_the_end() {
+    let steps = datagrams(&[
+        b"\x00\x01a.txt",
+        b"\x03\x01\x00\x01one",
+        // Says it's the last too, before the file could be done by the first
+        b"\x03\x01\x00\x03three",
+        b"\x01\x01\x00\x00zero",
+        b"\x01\x01\x00\x02two",
+    ]);
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let (files, _) = run(&mut transport, &clock, &cli(&["--until", "files=1"])).unwrap();
+    assert_eq!(files.file_status(1).unwrap().2, Some(4));
+    assert_eq!(files.head(1, 64).unwrap(), b"zeroonetwothree");
+}
+
+#[test]
+fn every_limit_comes_from_the_options() {
+    let limits = cli(&[
+        "--max-files=3",
+        "--max-file-size=1MiB",
+        "--max-name-len=40",
+        "--max-memory=2MiB",
+        "--max-total-bytes=1GiB",
+        "--session-timeout=1m",
+    ])
+    .limits();
+    assert_eq!(limits.max_files, Some(3));
+    assert_eq!(limits.max_file_size, Some(1 << 20));
+    assert_eq!(limits.max_name_len, 40);
+    assert_eq!(limits.max_buffered, Some(2 << 20));
+    assert_eq!(limits.max_total_bytes, Some(1 << 30));
+    assert_eq!(limits.max_session, Some(Duration::from_secs(60)));
+}
+
+#[test]
+fn a_file_past_max_file_size_ends_the_session() {
+    let steps = datagrams(&[
+        b"\x00\x01a.txt",
+        b"\x01\x01\x00\x00abc",
+        // A repeat isn't kept, so it doesn't count
+        b"\x01\x01\x00\x00abc",
+        b"\x01\x01\x00\x01d",
+        b"\x03\x01\x00\x02e",
+    ]);
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&["--quiet", "--max-file-size", "4"]);
+    match run(&mut transport, &clock, &cli) {
+        Err(ClientError::FileTooLarge { file_id, limit }) => assert_eq!((file_id, limit), (1, 4)),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+// --crc: a packet whose CRC is wrong is counted, and stays missing until
+// --nack-after asks for it again
+#[test]
+fn a_corrupt_packet_is_asked_for_again() {
+    let with_crc = |prefix: &[u8], payload: &[u8]| {
+        let mut bytes = [prefix, payload].concat();
+        bytes.extend(checksum::crc32(payload).to_be_bytes());
+        bytes
+    };
+    let mut corrupt = with_crc(b"\x01\x01\x00\x00", b"zero");
+    corrupt[4] ^= 1;
+    let last = with_crc(b"\x03\x01\x00\x01", b"one");
+    let mut steps = datagrams(&[b"\x00\x01a.txt", &corrupt, &last]);
+    steps.push(Step::AwaitSend);
+    steps.push(Step::Datagram(with_crc(b"\x01\x01\x00\x00", b"zero")));
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&[
+        "--quiet",
+        "--crc",
+        "--nack-after",
+        "100ms",
+        "--until",
+        "files=1",
+    ]);
+    let (files, report) = run(&mut transport, &clock, &cli).unwrap();
+    assert_eq!(files.head(1, 64).unwrap(), b"zeroone");
+    let asked: Vec<_> = (transport.sent.iter())
+        .filter_map(|datagram| nack::decode(datagram))
+        .map(|(_, missing)| missing)
+        .collect();
+    assert_eq!(asked, [vec![(1, 0)]]);
+    let malformed: Vec<_> = (report.errors().into_iter())
+        .filter(|e| e.code == "malformed-packet")
+        .collect();
+    assert_eq!(malformed.len(), 1, "{malformed:?}");
+}
+
+// File 1 with every packet up to 65535 and the last, 65537, but not 65536,
+// from a server offering `features` under protocol v2. `then` comes before
+// the missing packet does.
+fn wide_gap(features: u16, then: Step) -> (FileManager, Vec<Vec<u8>>) {
+    let mut advertisement = capability::encode(features);
+    advertisement[5] = 2;
+    let mut steps = datagrams(&[&advertisement, b"\x00\x01big.bin"]);
+    steps.extend(
+        (0..=u16::MAX).map(|n| Step::Datagram([&[1, 1], &n.to_be_bytes()[..], b"x"].concat())),
+    );
+    let wide = |n, is_last| [&wire::wide_data_prefix(1, n, is_last)[..], b"x"].concat();
+    steps.push(Step::Datagram(wide(65537, true)));
+    // The answer to the advertisement
+    steps.push(Step::AwaitSend);
+    steps.push(then);
+    steps.push(Step::Datagram(wide(65536, false)));
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&["--quiet", "--nack-after", "100ms", "--until", "files=1"]);
+    let (files, _) = run(&mut transport, &clock, &cli).unwrap();
+    (files, transport.sent)
+}
+
+#[test]
+fn a_gap_past_65535_is_asked_for_in_a_wide_nack() {
+    let (files, sent) = wide_gap(capability::WIDE_NACK, Step::AwaitSend);
+    assert!(files.is_complete(1));
+    let nacks: Vec<_> = (sent.iter())
+        .filter(|d| d.starts_with(nack::MAGIC))
+        .collect();
+    assert_eq!(nacks.len(), 1);
+    assert_eq!(nacks[0][4], nack::WIDE_VERSION);
+    assert_eq!(nack::decode(nacks[0]).unwrap().1, [(1, 65536)]);
+
+    // A server that doesn't say it reads them gets none, and with nothing
+    // missing below 65536 there's no narrow one either
+    let (files, sent) = wide_gap(0, Step::Silence(Duration::from_secs(1)));
+    assert!(files.is_complete(1));
+    assert!(!sent.iter().any(|d| d.starts_with(nack::MAGIC)));
+}
+
+#[test]
+fn the_report_gives_each_file_and_what_came_out_of_order() {
+    let steps = datagrams(&[
+        b"\x00\x01a.txt",
+        b"\x01\x01\x00\x02cc",
+        b"\x01\x01\x00\x00a",
+        // Already kept, so neither counted again nor out of order
+        b"\x01\x01\x00\x00a",
+        b"\x03\x01\x00\x03ddd",
+        b"\x01\x01\x00\x01b",
+        b"\x00\x02b.txt",
+        b"\x03\x02\x00\x00only",
+    ]);
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&["--quiet", "--lang", "en", "--until", "files=2"]);
+    let (_, report) = run(&mut transport, &clock, &cli).unwrap();
+    assert_eq!(report.out_of_order(), 2);
+    assert_eq!(report.duplicates(), 1);
+    assert_eq!(report.summary()["out_of_order"], 2);
+    let mut written = Vec::new();
+    report.write(&mut written).unwrap();
+    let text = String::from_utf8(written).unwrap();
+    assert!(text.contains("  file 1: 7B in 4 packets\n"), "{text}");
+    assert!(text.contains("  file 2: 4B in 1 packets\n"), "{text}");
+    assert!(
+        text.contains("  2 data packets came after one numbered higher\n"),
+        "{text}"
+    );
+    assert!(
+        text.contains("  1 duplicate data packets dropped\n"),
+        "{text}"
+    );
+}
+
+#[test]
+fn a_malformed_datagram_is_skipped_up_to_the_limit() {
+    let mut bytes = TWO_FILES.to_vec();
+    bytes.insert(2, b"\x01");
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(datagrams(&bytes), &clock);
+    let (files, _) = run(&mut transport, &clock, &cli(&["--until", "files=2"])).unwrap();
+    assert!(files.is_complete(1) && files.is_complete(2));
+
+    let mut transport = ScriptedTransport::new(datagrams(&bytes), &clock);
+    let cli = cli(&["--until", "files=2", "--max-parse-errors", "0"]);
+    let result = run(&mut transport, &clock, &cli);
+    assert!(matches!(result, Err(ClientError::PacketParseError(_))));
+}
+
+#[test]
+fn repeated_packets_keep_what_came_first() {
+    let mut bytes = TWO_FILES.to_vec();
+    bytes.insert(1, b"\x01\x01\x00\x00ONE, ");
+    bytes.insert(2, b"\x00\x01a.txt");
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(datagrams(&bytes), &clock);
+    let (files, _) = run(&mut transport, &clock, &cli(&["--until", "files=2"])).unwrap();
+    assert_eq!(files.payload(1, 0), Some(&b"one, "[..]));
+    assert_eq!(files.duplicates(1), 1);
+}
+
+#[test]
+fn a_dual_stack_socket_reaches_an_ipv4_server() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap().to_string();
+    assert_eq!(
+        IpVersion::for_servers([&*addr, "[::1]:6014"]),
+        IpVersion::Dual
+    );
+    let sock = client::bind(0, IpVersion::Dual).unwrap();
+    client::connect(&sock, &addr).unwrap();
+    send_request(&sock);
+    let mut buf = [0; 2048];
+    let (_, from) = server.recv_from(&mut buf).unwrap();
+    for datagram in TWO_FILES {
+        server.send_to(datagram, from).unwrap();
+    }
+    let cli = cli(&["--until", "files=2", "--quiet"]);
+    let (files, _) = receive_files(&sock, &cli, &mut Progress::default(), None).unwrap();
+    assert!(files.is_complete(1) && files.is_complete(2));
+
+    let v4 = client::bind(0, IpVersion::V4).unwrap();
+    assert!(client::connect(&v4, "[::1]:6014").is_err());
+}
+
+#[test]
+fn a_datagram_bigger_than_the_buffer_is_cut_short() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sock = client::bind(0, IpVersion::V4).unwrap();
+    client::connect(&sock, &server.local_addr().unwrap().to_string()).unwrap();
+    let port = sock.local_addr().unwrap().port();
+    let cli = cli(&[
+        "--max-packet-size",
+        "2000",
+        "--max-parse-errors",
+        "0",
+        "--quiet",
+    ]);
+    assert_eq!(cli.receive_buffer_len(), 2001);
+    for (len, expected) in [
+        // Read whole, so its length is known
+        (
+            1500,
+            "datagram of 1500 bytes is longer than any packet (1028)",
+        ),
+        (3000, "didn't fit in the 2000-byte receive buffer"),
+    ] {
+        server.send_to(&vec![1; len], ("127.0.0.1", port)).unwrap();
+        let e = receive_files(&sock, &cli, &mut Progress::default(), None)
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains(expected), "{e}");
+    }
+}
+
+#[test]
+fn the_socket_receive_buffer_can_be_raised() {
+    let sock = client::bind(0, IpVersion::V4).unwrap();
+    client::set_recv_buffer(&sock, &cli(&["--recv-buffer", "64KiB"]));
+    assert!(SockRef::from(&sock).recv_buffer_size().unwrap() >= 64 * 1024);
+}
+
+#[test]
+fn an_end_marker_ends_the_session_once_the_files_catch_up() {
+    let mut steps = datagrams(&TWO_FILES);
+    steps.insert(2, Step::Datagram(completion::end_of_session().to_vec()));
+    steps.push(Step::Silence(Duration::from_secs(60)));
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let (files, _) = run(&mut transport, &clock, &cli(&["--until", "end"])).unwrap();
+    assert!(files.is_complete(1) && files.is_complete(2));
+    assert_eq!(transport.remaining(), 1);
+}
+
+#[test]
+fn idle_waits_for_every_file_heard_of() {
+    let mut steps = datagrams(&TWO_FILES[..2]);
+    steps.push(Step::Silence(Duration::from_secs(3)));
+    steps.extend(datagrams(&TWO_FILES[2..]));
+    steps.push(Step::Silence(Duration::from_secs(3)));
+    let clock = ManualClock::default();
+    let started = clock.now();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let (files, _) = run(&mut transport, &clock, &cli(&["--until", "idle=2s"])).unwrap();
+    assert!(files.is_complete(1) && files.is_complete(2));
+    assert!(clock.now() - started >= Duration::from_secs(5));
+}
+
+#[test]
+fn any_closure_is_a_completion_policy() {
+    let policy = |state: &completion::State| state.files.file_ids().len() == 1;
+    let completion = completion::Completion::new("one file", policy);
+    let files = FileManager::default();
+    let state = completion::State {
+        files: &files,
+        quiet_for: Duration::ZERO,
+        ended: false,
+        skipped: &Default::default(),
+    };
+    assert!(!completion.is_done(&state) && !completion.needs_clock());
+}
+
+#[test]
+fn files_left_out_are_never_kept() {
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(datagrams(&TWO_FILES), &clock);
+    let (files, report) = run(&mut transport, &clock, &cli(&["--file-id", "2"])).unwrap();
+    assert_eq!(files.file_ids(), [2]);
+    assert_eq!(report.packets(), 5);
+
+    let cli = cli(&["--exclude", "a*", "--until", "files=1"]);
+    let mut transport = ScriptedTransport::new(datagrams(&TWO_FILES), &clock);
+    let (files, _) = run(&mut transport, &clock, &cli).unwrap();
+    assert_eq!(files.file_ids(), [2]);
+    assert!(files.is_complete(2));
+}
+
+#[test]
+fn a_tree_keeps_the_directories_in_names() {
+    let clock = ManualClock::default();
+    let script = [
+        &b"\x00\x01src/lib/util.rs"[..],
+        b"\x03\x01\x00\x00fn x() {}",
+    ];
+    let name = |cli: &Cli| {
+        let mut transport = ScriptedTransport::new(datagrams(&script), &clock);
+        let (files, _) = run(&mut transport, &clock, cli).unwrap();
+        let (name, ..) = files.file_status(1).unwrap();
+        name.unwrap().to_os_string()
+    };
+    assert_eq!(name(&cli(&["--until", "files=1"])), "util.rs");
+    assert_eq!(
+        name(&cli(&["--until", "files=1", "--tree"])),
+        "src/lib/util.rs"
+    );
+
+    // Climbing out still ends the session
+    let mut transport = ScriptedTransport::new(datagrams(&[b"\x00\x01../../evil"]), &clock);
+    let result = run(&mut transport, &clock, &cli(&["--tree"]));
+    assert!(
+        matches!(result, Err(ClientError::BadFileName { .. })),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn generated_packets_make_whole_files() {
+    // Just over a file's worth, so the second one is short
+    let mut synthetic = Synthetic::new(usize::from(u16::MAX) + 10);
+    assert_eq!(synthetic.files(), 2);
+    let clock = ManualClock::default();
+    let (files, report) = run_session(
+        &mut synthetic,
+        &clock,
+        FileManager::default(),
+        &mut Progress::default(),
+        &cli(&["--until", "files=2"]),
+        None,
+    )
+    .unwrap();
+    assert!(synthetic.exhausted());
+    assert!(files.is_complete(0) && files.is_complete(1));
+    assert_eq!(files.contents(1).unwrap(), vec![b'x'; 10 * 128]);
+    assert_eq!(report.packets(), u64::from(u16::MAX) + 12);
+}
+
+#[test]
+fn a_file_whose_header_never_comes_gets_the_headerless_name() {
+    let mut steps = datagrams(&[b"\x03\x07\x00\x00lost"]);
+    steps.extend(vec![Step::Silence(Duration::from_millis(100)); 4]);
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&[
+        "--until",
+        "files=1",
+        "--header-grace",
+        "300ms",
+        "--headerless-name",
+        "recovered-{id}.dat",
+    ]);
+    let (files, _) = run(&mut transport, &clock, &cli).unwrap();
+    assert!(files.is_complete(7));
+    let (name, _, _) = files.file_status(7).unwrap();
+    assert_eq!(name, Some(OsStr::new("recovered-7.dat")));
+}
+
+// --min-runtime: --until can't end the session any sooner, so a file that
+// comes after a pause longer than quiet= does is still received
+#[test]
+fn until_waits_out_the_minimum_runtime() {
+    let mut steps = datagrams(&[b"\x00\x01a.txt", b"\x03\x01\x00\x00one"]);
+    steps.push(Step::Silence(Duration::from_millis(600)));
+    steps.extend(datagrams(&[b"\x00\x02b.txt", b"\x03\x02\x00\x00two"]));
+    steps.extend(vec![Step::Silence(Duration::from_millis(100)); 30]);
+    let session = |args: &[&str]| {
+        let clock = ManualClock::default();
+        let started = clock.now();
+        let mut transport = ScriptedTransport::new(steps.clone(), &clock);
+        let (files, report) = run(&mut transport, &clock, &cli(args)).unwrap();
+        let ended_by = report.summary()["ended_by"].clone();
+        (files.is_complete(2), clock.now() - started, ended_by)
+    };
+    let (second, took, ended_by) = session(&["--until", "quiet=300ms"]);
+    assert!(!second);
+    assert_eq!(took, Duration::from_millis(600));
+    assert_eq!(ended_by, "--until quiet=300ms");
+
+    // Once it's run long enough, quiet= ends it as before
+    for (min_runtime, second, ended) in [("2s", true, 2000), ("400ms", false, 600)] {
+        let args = ["--until", "quiet=300ms", "--min-runtime", min_runtime];
+        let (got_second, took, ended_by) = session(&args);
+        assert_eq!(got_second, second, "{min_runtime}");
+        assert_eq!(took, Duration::from_millis(ended), "{min_runtime}");
+        assert_eq!(ended_by, "--until quiet=300ms");
+    }
+}
+
+// --artificial-delay sleeps on the session's clock after every datagram
+#[test]
+fn each_datagram_holds_the_session_up_for_the_artificial_delay() {
+    let clock = ManualClock::default();
+    let started = clock.now();
+    let mut transport = ScriptedTransport::new(datagrams(&TWO_FILES), &clock);
+    let cli = cli(&["--until", "files=2", "--artificial-delay", "50ms"]);
+    let (files, _) = run(&mut transport, &clock, &cli).unwrap();
+    assert!(files.is_complete(1) && files.is_complete(2));
+    assert_eq!(clock.now() - started, Duration::from_millis(250));
+}
+
+// --drop-every 3 counts the header too, so packets 1 and 4 go the first time
+// and 4 again when it's sent once more; NACKs get both back in the end
+#[test]
+fn every_nth_datagram_is_dropped_and_asked_for_again() {
+    let packet = |n: u8| {
+        let status = if n == 5 { 3 } else { 1 };
+        vec![status, 1, 0, n, b'0' + n]
+    };
+    let mut steps = vec![Step::Datagram(b"\x00\x01a.txt".to_vec())];
+    steps.extend((0..6).map(|n| Step::Datagram(packet(n))));
+    steps.push(Step::AwaitSend);
+    steps.extend([Step::Datagram(packet(1)), Step::Datagram(packet(4))]);
+    steps.push(Step::AwaitSend);
+    steps.push(Step::Datagram(packet(4)));
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    let cli = cli(&[
+        "--lang",
+        "en",
+        "--drop-every",
+        "3",
+        "--nack-after",
+        "100ms",
+        "--until",
+        "files=1",
+    ]);
+    let (files, report) = run(&mut transport, &clock, &cli).unwrap();
+    assert_eq!(files.head(1, 64).unwrap(), b"012345");
+    let asked: Vec<_> = (transport.sent.iter())
+        .filter_map(|datagram| nack::decode(datagram))
+        .map(|(_, missing)| missing)
+        .collect();
+    assert_eq!(asked, [vec![(1, 1), (1, 4)], vec![(1, 4)]]);
+    let mut written = Vec::new();
+    report.write(&mut written).unwrap();
+    let text = String::from_utf8(written).unwrap();
+    assert!(
+        text.contains("  threw away 3 datagrams for --drop-every\n"),
+        "{text}"
+    );
+}
@@ -0,0 +1,140 @@
+// Units on the command line: each parser against what it's meant to take
+// and turn down, and each formatter's output parsing back to what it was
+// given.
+
+use segmented_file_system_client::units;
+use std::time::Duration;
+
+#[test]
+fn durations_take_parts_and_bare_seconds() {
+    let secs = Duration::from_secs;
+    assert_eq!(units::parse_duration("90"), Ok(secs(90)));
+    assert_eq!(units::parse_duration(" 2m30s "), Ok(secs(150)));
+    assert_eq!(units::parse_duration("1.5h"), Ok(secs(5400)));
+    assert_eq!(units::parse_duration("1d"), Ok(secs(86_400)));
+    assert_eq!(
+        units::parse_duration("250ms"),
+        Ok(Duration::from_millis(250))
+    );
+    assert_eq!(units::parse_duration("0.5"), Ok(Duration::from_millis(500)));
+
+    for bad in ["", "  ", "5x", "2m30", "m", "-5s", "-1", "1.2.3s", "1e400"] {
+        assert!(units::parse_duration(bad).is_err(), "{bad:?}");
+    }
+    let e = units::parse_duration("3w").unwrap_err();
+    assert!(e.contains("\"w\"") && e.contains("ms, s, m, h, d"), "{e}");
+}
+
+#[test]
+fn a_formatted_duration_parses_back() {
+    assert_eq!(units::format_duration(Duration::ZERO), "0s");
+    assert_eq!(units::format_duration(Duration::from_secs(150)), "2m30s");
+    // Days come out as hours, and less than a millisecond not at all
+    assert_eq!(units::format_duration(Duration::from_secs(90_000)), "25h");
+    assert_eq!(units::format_duration(Duration::from_micros(1500)), "1ms");
+    for millis in [1, 999, 1000, 61_001, 3_600_000, 3_723_004, 86_400_000] {
+        let d = Duration::from_millis(millis);
+        let text = units::format_duration(d);
+        assert_eq!(units::parse_duration(&text), Ok(d), "{text}");
+    }
+}
+
+#[test]
+fn sizes_take_decimal_and_binary_units() {
+    assert_eq!(units::parse_size("512"), Ok(512));
+    assert_eq!(units::parse_size("512B"), Ok(512));
+    assert_eq!(units::parse_size("2KB"), Ok(2000));
+    assert_eq!(units::parse_size("2k"), Ok(2000));
+    assert_eq!(units::parse_size("2 KiB"), Ok(2048));
+    assert_eq!(units::parse_size("1.5GiB"), Ok(3 << 29));
+    assert_eq!(units::parse_size("1TB"), Ok(1_000_000_000_000));
+    // To the nearest byte
+    assert_eq!(units::parse_size("1.0004KiB"), Ok(1024));
+
+    for bad in ["", "KiB", "1.5XB", "1kib", "-1MB", "ten"] {
+        assert!(units::parse_size(bad).is_err(), "{bad:?}");
+    }
+    let e = units::parse_size("4PB").unwrap_err();
+    assert!(e.contains("\"PB\""), "{e}");
+}
+
+#[test]
+fn a_formatted_size_parses_back() {
+    assert_eq!(units::format_size(0), "0B");
+    assert_eq!(units::format_size(1023), "1023B");
+    assert_eq!(units::format_size(1024), "1KiB");
+    assert_eq!(units::format_size(3 << 29), "1.5GiB");
+    // A bigger unit only when three decimals say it exactly: 1152 bytes is
+    // 1.125KiB, since 1152 * 1000 is a multiple of 1024, but 1025 isn't
+    assert_eq!(units::format_size(1152), "1.125KiB");
+    assert_eq!(units::format_size(1025), "1025B");
+    assert_eq!(units::format_size(1088), "1088B");
+    assert_eq!(units::format_size((1 << 20) + 128), "1024.125KiB");
+    // Past where bytes * 1000 fits in a u64
+    assert_eq!(units::format_size(1 << 62), "4294967296GiB");
+    assert_eq!(units::format_size(u64::MAX), format!("{}B", u64::MAX));
+
+    for bytes in [
+        0,
+        1,
+        1023,
+        1024,
+        1025,
+        1152,
+        1 << 20,
+        (1 << 20) + 128,
+        3 << 29,
+    ] {
+        let text = units::format_size(bytes);
+        assert_eq!(units::parse_size(&text), Ok(bytes), "{text}");
+    }
+}
+
+#[test]
+fn a_rounded_size_keeps_to_the_largest_unit() {
+    assert_eq!(units::format_size_rounded(0), "0B");
+    assert_eq!(units::format_size_rounded(1023), "1023B");
+    assert_eq!(units::format_size_rounded(1024), "1.0KiB");
+    // Where format_size would go back to bytes
+    assert_eq!(units::format_size_rounded(1537), "1.5KiB");
+    assert_eq!(units::format_size_rounded(1536), "1.5KiB");
+    assert_eq!(units::format_size_rounded(10 << 20), "10.0MiB");
+    assert_eq!(units::format_size_rounded((3 << 29) + 1), "1.5GiB");
+    assert_eq!(units::format_size_rounded(u64::MAX), "17179869184.0GiB");
+}
+
+#[test]
+fn rates_take_sizes_a_second_and_bits() {
+    assert_eq!(units::parse_rate("1000"), Ok(1000));
+    assert_eq!(units::parse_rate("500KB/s"), Ok(500_000));
+    assert_eq!(units::parse_rate("500KB"), Ok(500_000));
+    assert_eq!(units::parse_rate("2MiB/s"), Ok(2 << 20));
+    assert_eq!(units::parse_rate("10Mbps"), Ok(1_250_000));
+    assert_eq!(units::parse_rate("8kbps"), Ok(1000));
+    assert_eq!(units::parse_rate("12bps"), Ok(2));
+
+    for bad in ["", "fast", "10Mb/s/s", "10 furlongs"] {
+        assert!(units::parse_rate(bad).is_err(), "{bad:?}");
+    }
+
+    for rate in [1, 1000, 1024, 1536, 2 << 20, 1_250_000] {
+        let text = units::format_rate(rate);
+        assert!(text.ends_with("/s"), "{text}");
+        assert_eq!(units::parse_rate(&text), Ok(rate), "{text}");
+    }
+}
+
+#[test]
+fn ranges_run_together_up_to_the_most_asked_for() {
+    let numbers = [0, 1, 2, 3, 4, 7, 9, 10, 11, 12];
+    assert_eq!(units::format_ranges(&numbers, 10), "0-4, 7, 9-12");
+    assert_eq!(units::format_ranges(&numbers, 3), "0-4, 7, 9-12");
+    assert_eq!(units::format_ranges(&numbers, 2), "0-4, 7, ...");
+    assert_eq!(units::format_ranges(&numbers, 0), "...");
+    assert_eq!(units::format_ranges(&[], 5), "");
+    assert_eq!(units::format_ranges(&[6], 5), "6");
+    assert_eq!(
+        units::format_ranges(&[u32::MAX - 1, u32::MAX], 5),
+        "4294967294-4294967295"
+    );
+}
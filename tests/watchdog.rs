@@ -0,0 +1,105 @@
+// --watchdog-min-rate: a window that comes in below the floor gets the
+// request sent again, on a clock that only moves when the test says; and a
+// file that stays stuck is still ended by --stall-timeout.
+
+use clap::Parser;
+use segmented_file_system_client::{
+    cli::Cli,
+    error::ClientError,
+    events::{Event, Progress, ProgressSink},
+    file_manager::FileManager,
+    session::run_session,
+    transport::{ManualClock, ScriptedTransport, Step, Transport},
+    watchdog::Watchdog,
+};
+use std::{
+    cell::RefCell,
+    io,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn a_window_below_the_floor_fires_once_it_is_over() {
+    let start = Instant::now();
+    let second = Duration::from_secs(1);
+    let mut watchdog = Watchdog::new(1000, second, start);
+    watchdog.record(500);
+    assert_eq!(watchdog.check(start + second / 2), None);
+    assert_eq!(watchdog.check(start + second), Some(500));
+    // Fast enough, and then nothing at all
+    watchdog.record(2000);
+    assert_eq!(watchdog.check(start + 2 * second), None);
+    assert_eq!(watchdog.check(start + 3 * second), Some(0));
+    assert_eq!(watchdog.poll_interval(), Duration::from_millis(250));
+    let short = Watchdog::new(1000, Duration::from_millis(100), start);
+    assert_eq!(short.poll_interval(), Duration::from_millis(50));
+}
+
+// The watchdog's events, as the report would record them
+struct Fired(Rc<RefCell<Vec<(u64, bool)>>>);
+
+impl ProgressSink for Fired {
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        if let Event::Watchdog { rate, re_requested } = *event {
+            self.0.borrow_mut().push((rate, re_requested));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn a_trickle_is_asked_for_again_and_a_stall_ends_with_status_5() {
+    let cli = Cli::try_parse_from([
+        "client",
+        "--watchdog-min-rate",
+        "1KB/s",
+        "--watchdog-window",
+        "1s",
+        "--stall-timeout",
+        "2500ms",
+        "--until",
+        "files=1",
+    ])
+    .unwrap();
+    // A header and a packet of file 1, then nothing more
+    let mut steps = vec![
+        Step::Datagram(b"\x00\x01a.txt".to_vec()),
+        Step::Datagram([&b"\x01\x01\x00\x00"[..], &[b'a'; 100]].concat()),
+    ];
+    steps.extend(vec![Step::Silence(Duration::from_millis(250)); 20]);
+    let clock = ManualClock::default();
+    let mut transport = ScriptedTransport::new(steps, &clock);
+    transport
+        .set_poll_interval(Some(Duration::from_millis(250)))
+        .unwrap();
+    let fired = Rc::new(RefCell::new(Vec::new()));
+    let mut progress = Progress::default();
+    progress.add(Fired(Rc::clone(&fired)));
+    let e = run_session(
+        &mut transport,
+        &clock,
+        FileManager::default(),
+        &mut progress,
+        &cli,
+        None,
+    )
+    .err()
+    .unwrap();
+
+    // The first second had the two datagrams' 111 bytes, the next none
+    assert_eq!(*fired.borrow(), [(111, true), (0, true)]);
+    assert_eq!(transport.sent.len(), 2);
+    assert!(
+        matches!(
+            e,
+            ClientError::FileStalled {
+                file_id: 1,
+                received: 1,
+                ..
+            }
+        ),
+        "{e}"
+    );
+    assert_eq!((e.code(), e.exit_code()), ("file-stalled", 5));
+}
@@ -0,0 +1,115 @@
+// Byte-exact checks of the wire format, so a refactor can't quietly change
+// what goes over the network.
+
+use segmented_file_system_client::wire;
+
+#[test]
+fn header_layout() {
+    let packet = [0x00, 0x07, b'a', b'.', b't', b'x', b't'];
+    assert!(!wire::is_data(packet[wire::STATUS]));
+    assert_eq!(packet[wire::FILE_ID], 7);
+    assert_eq!(&packet[wire::FILE_NAME..], b"a.txt");
+    assert_eq!(wire::HEADER_STATUS, 0x00);
+}
+
+#[test]
+fn data_layout() {
+    let packet = [0x01, 0x02, 0x01, 0x02, 0xAA, 0xBB];
+    assert!(wire::is_data(packet[wire::STATUS]));
+    assert!(!wire::is_last(packet[wire::STATUS]));
+    assert_eq!(packet[wire::FILE_ID], 2);
+    assert_eq!(wire::packet_number(&packet), 0x0102);
+    assert_eq!(&packet[wire::PAYLOAD..], [0xAA, 0xBB]);
+}
+
+#[test]
+fn last_data_layout() {
+    let packet = [0x03, 0x00, 0xFF, 0xFE];
+    assert!(wire::is_data(packet[wire::STATUS]));
+    assert!(wire::is_last(packet[wire::STATUS]));
+    assert_eq!(wire::packet_number(&packet), 65534);
+    assert!(packet[wire::PAYLOAD..].is_empty());
+}
+
+#[test]
+fn wide_data_layout() {
+    let packet = [0x0B, 0x02, 0x00, 0x01, 0x02, 0x03, 0xAA];
+    assert!(wire::is_wide(packet[wire::STATUS]));
+    assert!(wire::is_last(packet[wire::STATUS]));
+    assert_eq!(
+        wire::payload_offset(packet[wire::STATUS]),
+        wire::WIDE_PAYLOAD
+    );
+    assert_eq!(wire::packet_number(&packet), 0x010203);
+    assert_eq!(&packet[wire::WIDE_PAYLOAD..], [0xAA]);
+    assert_eq!(
+        wire::wide_data_prefix(2, 0x010203, true),
+        [0x0B, 0x02, 0x00, 0x01, 0x02, 0x03]
+    );
+    // Bit 3 means nothing to a header
+    assert!(!wire::is_wide(wire::HEADER_STATUS | wire::WIDE_BIT));
+}
+
+#[test]
+fn encoded_prefixes() {
+    assert_eq!(
+        wire::data_prefix(5, 0x1234, false),
+        [0x01, 0x05, 0x12, 0x34]
+    );
+    assert_eq!(wire::data_prefix(5, 0x1234, true), [0x03, 0x05, 0x12, 0x34]);
+    assert_eq!(wire::data_prefix(0, 0, false), [0x01, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn status_bits() {
+    // Only the low two bits mean anything
+    for status in 0..=u8::MAX {
+        assert_eq!(wire::is_data(status), status % 2 == 1);
+        assert_eq!(wire::is_last(status), status % 4 == 3);
+    }
+    assert_eq!(wire::data_status(false), 0x01);
+    assert_eq!(wire::data_status(true), 0x03);
+}
+
+#[test]
+fn sizes() {
+    assert_eq!(wire::MIN_PACKET_LEN, 2);
+    assert_eq!(wire::MIN_DATA_LEN, 4);
+    assert_eq!(wire::MAX_PAYLOAD, 1024);
+    assert_eq!(wire::MAX_PACKET, 1028);
+    assert_eq!(wire::CRC_LEN, 4);
+}
+
+#[test]
+fn receive_buffers_have_room_to_spot_a_long_datagram() {
+    assert_eq!(wire::max_datagram(false), wire::MAX_WIDE_PACKET);
+    assert_eq!(
+        wire::max_datagram(true),
+        wire::MAX_WIDE_PACKET + wire::CRC_LEN
+    );
+    assert_eq!(wire::max_len(wire::DATA_STATUS, false), wire::MAX_PACKET);
+    assert_eq!(
+        wire::max_len(wire::HEADER_STATUS, true),
+        wire::MAX_PACKET + 4
+    );
+    assert_eq!(
+        wire::max_len(wire::DATA_STATUS | wire::WIDE_BIT, false),
+        1030
+    );
+    for crc in [false, true] {
+        assert!(wire::receive_buffer_len(crc) > wire::max_datagram(crc));
+    }
+}
+
+#[test]
+fn keepalives_are_empty_data_packets_not_marked_last() {
+    assert!(wire::is_keepalive(wire::DATA_STATUS, 0));
+    assert!(!wire::is_keepalive(wire::DATA_STATUS, 1));
+    assert!(!wire::is_keepalive(wire::DATA_STATUS, wire::MAX_PAYLOAD));
+    // An empty last packet ends a file whose length is a multiple of 1024
+    assert!(!wire::is_keepalive(wire::LAST_DATA_STATUS, 0));
+    // Headers with no name are malformed, not keepalives
+    assert!(!wire::is_keepalive(wire::HEADER_STATUS, 0));
+    // Unused high bits don't change anything
+    assert!(wire::is_keepalive(0xF0 | wire::DATA_STATUS, 0));
+}